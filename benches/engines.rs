@@ -0,0 +1,18 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use lox::bench;
+
+fn engines(criterion: &mut Criterion) {
+    for workload in bench::WORKLOADS {
+        let mut group = criterion.benchmark_group(workload.name);
+        for &engine in bench::ENGINES {
+            group.bench_function(engine.name(), |bencher| {
+                bencher.iter(|| bench::run(workload, engine).expect("workload failed"))
+            });
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, engines);
+criterion_main!(benches);
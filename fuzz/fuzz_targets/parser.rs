@@ -0,0 +1,17 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use lox::parser::Parser;
+use lox::scanner::Scanner;
+use lox::syntax;
+
+// Parsing never panics, whether for a program, a lone expression or the
+// lossless tree, and the lossless tree always gives back the source.
+fuzz_target!(|source: &str| {
+    if let Ok(tokens) = Scanner::new(source.to_string()).scan_tokens() {
+        let _ = Parser::new(tokens.clone()).parse();
+        let _ = Parser::new(tokens).parse_expression();
+    }
+
+    assert_eq!(syntax::parse(source).syntax().text(), source);
+});
@@ -0,0 +1,40 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use lox::scanner::Scanner;
+
+// Scanning never panics, and every token, comments included, lies within the
+// source: in order, on a line that exists, with the end of file last.
+fuzz_target!(|data: &[u8]| {
+    let source = String::from_utf8_lossy(data).into_owned();
+    let length = source.chars().count();
+    let lines = source.matches('\n').count() + 1;
+
+    for trivia in [false, true] {
+        let mut scanner = Scanner::new(source.clone());
+        scanner.set_trivia(trivia);
+        let Ok(tokens) = scanner.scan_tokens() else {
+            continue;
+        };
+
+        let mut previous = 0;
+        for token in &tokens {
+            let position = token.position;
+            assert!(previous <= position.start, "{:?} overlaps", token);
+            assert!(
+                position.start <= position.current,
+                "{:?} is reversed",
+                token
+            );
+            assert!(position.current <= length, "{:?} runs past the end", token);
+            assert!(
+                (1..=lines).contains(&position.line),
+                "{:?} is on no line",
+                token
+            );
+            previous = position.current;
+        }
+        let end = tokens.last().expect("no end of file").position;
+        assert_eq!((end.start, end.current), (length, length));
+    }
+});
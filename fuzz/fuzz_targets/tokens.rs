@@ -0,0 +1,60 @@
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use lox::bench::WORKLOADS;
+use lox::parser::Parser;
+use lox::scanner::Scanner;
+
+#[derive(Debug, Arbitrary)]
+enum Mutation {
+    Delete(u16),
+    Duplicate(u16),
+    Swap(u16, u16),
+    // Copies one token to another place in the stream.
+    Copy(u16, u16),
+}
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    seed: u8,
+    mutations: Vec<Mutation>,
+}
+
+// Starts from the tokens of a valid program and shuffles them around, so the
+// parser sees streams that are nearly right rather than mostly noise. Every
+// stream still ends with the end of file, as the scanner's do.
+fuzz_target!(|input: Input| {
+    let workload = &WORKLOADS[usize::from(input.seed) % WORKLOADS.len()];
+    let mut tokens = Scanner::new(workload.source.to_string())
+        .scan_tokens()
+        .expect("workloads scan");
+    let end = tokens.pop().expect("no end of file");
+
+    for mutation in input.mutations {
+        if tokens.is_empty() {
+            break;
+        }
+        let index = |at: u16| usize::from(at) % tokens.len();
+        match mutation {
+            Mutation::Delete(at) => {
+                tokens.remove(index(at));
+            }
+            Mutation::Duplicate(at) => {
+                let at = index(at);
+                tokens.insert(at, tokens[at].clone());
+            }
+            Mutation::Swap(from, to) => {
+                let (from, to) = (index(from), index(to));
+                tokens.swap(from, to);
+            }
+            Mutation::Copy(from, to) => {
+                let (from, to) = (index(from), index(to));
+                tokens.insert(to, tokens[from].clone());
+            }
+        }
+    }
+
+    tokens.push(end);
+    let _ = Parser::new(tokens).parse();
+});
@@ -0,0 +1,68 @@
+#ifndef LOX_H
+#define LOX_H
+
+#include <stdbool.h>
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define LOX_OK 0
+#define LOX_INVALID_ARGUMENT 64
+#define LOX_COMPILE_ERROR 65
+#define LOX_RUNTIME_ERROR 70
+
+#define LOX_TYPE_NIL 0
+#define LOX_TYPE_BOOL 1
+#define LOX_TYPE_NUMBER 2
+#define LOX_TYPE_STRING 3
+#define LOX_TYPE_OBJECT 4
+
+typedef struct LoxVm LoxVm;
+/* Values returned by lox_eval, lox_get_global, lox_call and the lox_value_*
+ * constructors are owned by the caller and released with lox_value_free. */
+typedef struct LoxValue LoxValue;
+
+/* A native callback borrows its arguments for the duration of the call and
+ * must not free them. It returns a new value, which the interpreter takes
+ * ownership of, one of its arguments, which is copied, or NULL for nil. */
+typedef LoxValue *(*LoxCallback)(void *user_data, const LoxValue *const *arguments, size_t count);
+
+LoxVm *lox_new(void);
+void lox_free(LoxVm *vm);
+
+/* Functions that use the interpreter refuse to run from inside one of its
+ * callbacks, with LOX_INVALID_ARGUMENT or NULL and an error message. */
+int lox_run(LoxVm *vm, const char *source);
+LoxValue *lox_eval(LoxVm *vm, const char *source);
+const char *lox_last_error(const LoxVm *vm);
+
+LoxValue *lox_get_global(LoxVm *vm, const char *name);
+/* Calls `function` with `count` arguments, none of which may be NULL. On
+ * LOX_OK `*result` holds a new value; otherwise it is NULL and the status is
+ * LOX_INVALID_ARGUMENT or LOX_RUNTIME_ERROR. */
+int lox_call(LoxVm *vm, const LoxValue *function, const LoxValue *const *arguments, size_t count,
+             LoxValue **result);
+int lox_register_native(LoxVm *vm, const char *name, size_t arity, LoxCallback callback, void *user_data);
+
+LoxValue *lox_value_nil(void);
+LoxValue *lox_value_bool(bool boolean);
+LoxValue *lox_value_number(double number);
+LoxValue *lox_value_string(const char *string);
+LoxValue *lox_value_error(const char *message);
+void lox_value_free(LoxValue *value);
+
+int lox_value_type(const LoxValue *value);
+double lox_value_as_number(const LoxValue *value);
+bool lox_value_as_bool(const LoxValue *value);
+/* The text stays valid until the next lox_value_to_string on the same value
+ * or until the value is freed. */
+const char *lox_value_to_string(LoxValue *value);
+bool lox_value_is_callable(const LoxValue *value);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,417 @@
+use std::cell::{RefCell, RefMut};
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::ptr;
+
+use lox::error::LoxError;
+use lox::value::Value;
+use lox::Lox;
+
+pub const LOX_OK: i32 = 0;
+pub const LOX_COMPILE_ERROR: i32 = 65;
+pub const LOX_RUNTIME_ERROR: i32 = 70;
+pub const LOX_INVALID_ARGUMENT: i32 = 64;
+
+pub const LOX_TYPE_NIL: i32 = 0;
+pub const LOX_TYPE_BOOL: i32 = 1;
+pub const LOX_TYPE_NUMBER: i32 = 2;
+pub const LOX_TYPE_STRING: i32 = 3;
+pub const LOX_TYPE_OBJECT: i32 = 4;
+
+pub type LoxCallback = extern "C" fn(
+    user_data: *mut c_void,
+    arguments: *const *const LoxValue,
+    count: usize,
+) -> *mut LoxValue;
+
+// The interpreter is borrowed for each call into it, so a callback that
+// calls back in is refused rather than given a second mutable reference.
+pub struct LoxVm {
+    lox: RefCell<Lox>,
+    last_error: RefCell<Option<CString>>,
+}
+
+pub struct LoxValue {
+    value: Value,
+    error: Option<String>,
+    text: Option<CString>,
+}
+
+impl LoxValue {
+    fn boxed(value: Value) -> *mut LoxValue {
+        Box::into_raw(Box::new(LoxValue {
+            value,
+            error: None,
+            text: None,
+        }))
+    }
+}
+
+impl LoxVm {
+    fn fail(&self, message: String) {
+        *self.last_error.borrow_mut() = CString::new(message.replace('\0', "")).ok();
+    }
+
+    fn lox(&self) -> Option<RefMut<'_, Lox>> {
+        let lox = self.lox.try_borrow_mut().ok();
+        if lox.is_none() {
+            self.fail("Can't use the interpreter from inside one of its callbacks.".to_string());
+        }
+        lox
+    }
+}
+
+unsafe fn string<'a>(pointer: *const c_char) -> Option<&'a str> {
+    if pointer.is_null() {
+        return None;
+    }
+
+    CStr::from_ptr(pointer).to_str().ok()
+}
+
+#[no_mangle]
+pub extern "C" fn lox_new() -> *mut LoxVm {
+    Box::into_raw(Box::new(LoxVm {
+        lox: RefCell::new(Lox::new()),
+        last_error: RefCell::new(None),
+    }))
+}
+
+/// # Safety
+///
+/// `vm` must be null or a pointer returned by `lox_new` that has not been freed.
+#[no_mangle]
+pub unsafe extern "C" fn lox_free(vm: *mut LoxVm) {
+    if !vm.is_null() {
+        drop(Box::from_raw(vm));
+    }
+}
+
+/// # Safety
+///
+/// `vm` must be a live pointer from `lox_new` and `source` a NUL-terminated UTF-8 string.
+/// Running code from inside one of the interpreter's callbacks is refused with
+/// `LOX_INVALID_ARGUMENT`.
+#[no_mangle]
+pub unsafe extern "C" fn lox_run(vm: *mut LoxVm, source: *const c_char) -> i32 {
+    let Some(vm) = vm.as_ref() else {
+        return LOX_INVALID_ARGUMENT;
+    };
+    let Some(source) = string(source) else {
+        vm.fail("Source must be a valid UTF-8 string.".to_string());
+        return LOX_INVALID_ARGUMENT;
+    };
+
+    let Some(mut lox) = vm.lox() else {
+        return LOX_INVALID_ARGUMENT;
+    };
+
+    match lox.run(source) {
+        Ok(()) => LOX_OK,
+        Err(error) => {
+            let code = match error {
+                LoxError::Compile(_) => LOX_COMPILE_ERROR,
+                LoxError::Runtime(_) => LOX_RUNTIME_ERROR,
+            };
+            vm.fail(error.to_string());
+            code
+        }
+    }
+}
+
+/// # Safety
+///
+/// `vm` must be a live pointer from `lox_new` and `source` a NUL-terminated UTF-8 string.
+/// A non-null result must be released with `lox_value_free`.
+#[no_mangle]
+pub unsafe extern "C" fn lox_eval(vm: *mut LoxVm, source: *const c_char) -> *mut LoxValue {
+    let Some(vm) = vm.as_ref() else {
+        return ptr::null_mut();
+    };
+    let Some(source) = string(source) else {
+        vm.fail("Source must be a valid UTF-8 string.".to_string());
+        return ptr::null_mut();
+    };
+
+    let Some(mut lox) = vm.lox() else {
+        return ptr::null_mut();
+    };
+
+    match lox.eval(source) {
+        Ok(value) => LoxValue::boxed(value),
+        Err(error) => {
+            vm.fail(error.to_string());
+            ptr::null_mut()
+        }
+    }
+}
+
+/// # Safety
+///
+/// `vm` must be a live pointer from `lox_new`. The returned string is owned by the
+/// interpreter and stays valid until the next call that fails.
+#[no_mangle]
+pub unsafe extern "C" fn lox_last_error(vm: *const LoxVm) -> *const c_char {
+    match vm
+        .as_ref()
+        .and_then(|vm| vm.last_error.borrow().as_ref().map(|error| error.as_ptr()))
+    {
+        Some(error) => error,
+        None => ptr::null(),
+    }
+}
+
+/// # Safety
+///
+/// `vm` must be a live pointer from `lox_new` and `name` a NUL-terminated UTF-8 string.
+/// A non-null result must be released with `lox_value_free`.
+#[no_mangle]
+pub unsafe extern "C" fn lox_get_global(vm: *mut LoxVm, name: *const c_char) -> *mut LoxValue {
+    let (Some(vm), Some(name)) = (vm.as_ref(), string(name)) else {
+        return ptr::null_mut();
+    };
+
+    let Some(lox) = vm.lox() else {
+        return ptr::null_mut();
+    };
+
+    match lox.get_global(name) {
+        Some(value) => LoxValue::boxed(value),
+        None => ptr::null_mut(),
+    }
+}
+
+/// # Safety
+///
+/// `vm` must be a live pointer from `lox_new`, `function` a live value, `arguments` point
+/// to `count` live values and `result` be writable. On `LOX_OK` the call's value is stored
+/// in `result` and must be released with `lox_value_free`; otherwise `result` is set to null.
+#[no_mangle]
+pub unsafe extern "C" fn lox_call(
+    vm: *mut LoxVm,
+    function: *const LoxValue,
+    arguments: *const *const LoxValue,
+    count: usize,
+    result: *mut *mut LoxValue,
+) -> i32 {
+    let Some(vm) = vm.as_ref() else {
+        return LOX_INVALID_ARGUMENT;
+    };
+    let Some(result) = result.as_mut() else {
+        vm.fail("The result must be a valid pointer.".to_string());
+        return LOX_INVALID_ARGUMENT;
+    };
+    *result = ptr::null_mut();
+    let Some(function) = function.as_ref() else {
+        vm.fail("The function must be a value.".to_string());
+        return LOX_INVALID_ARGUMENT;
+    };
+    let Some(arguments) = values(arguments, count) else {
+        vm.fail("Arguments must be values.".to_string());
+        return LOX_INVALID_ARGUMENT;
+    };
+
+    let Some(mut lox) = vm.lox() else {
+        return LOX_INVALID_ARGUMENT;
+    };
+
+    match lox.call(&function.value, &arguments) {
+        Ok(value) => {
+            *result = LoxValue::boxed(value);
+            LOX_OK
+        }
+        Err(error) => {
+            vm.fail(error.to_string());
+            LOX_RUNTIME_ERROR
+        }
+    }
+}
+
+/// # Safety
+///
+/// `vm` must be a live pointer from `lox_new` and `name` a NUL-terminated UTF-8 string.
+/// `callback` is invoked on the calling thread with `user_data`, which must stay valid for
+/// as long as the interpreter lives. Its arguments are only borrowed for the call, and the
+/// library takes ownership of a returned value unless it is one of those arguments.
+#[no_mangle]
+pub unsafe extern "C" fn lox_register_native(
+    vm: *mut LoxVm,
+    name: *const c_char,
+    arity: usize,
+    callback: LoxCallback,
+    user_data: *mut c_void,
+) -> i32 {
+    let (Some(vm), Some(name)) = (vm.as_ref(), string(name)) else {
+        return LOX_INVALID_ARGUMENT;
+    };
+
+    let Some(mut lox) = vm.lox() else {
+        return LOX_INVALID_ARGUMENT;
+    };
+
+    lox.register_native(name, arity, move |interpreter, arguments| {
+        let boxed: Vec<LoxValue> = arguments
+            .iter()
+            .map(|argument| LoxValue {
+                value: argument.clone(),
+                error: None,
+                text: None,
+            })
+            .collect();
+        let pointers: Vec<*const LoxValue> =
+            boxed.iter().map(|value| value as *const LoxValue).collect();
+
+        let result = callback(user_data, pointers.as_ptr(), pointers.len());
+        if result.is_null() {
+            return Ok(Value::Nil);
+        }
+
+        // Handing back an argument returns a copy of it: the argument
+        // belongs to this call, not to the callback.
+        if let Some(index) = pointers.iter().position(|&pointer| pointer == result) {
+            return Ok(boxed[index].value.clone());
+        }
+
+        let result = Box::from_raw(result);
+        match result.error {
+            Some(message) => Err(interpreter.runtime_error(message)),
+            None => Ok(result.value),
+        }
+    });
+
+    LOX_OK
+}
+
+// The values behind `count` argument pointers, or `None` if any of them is
+// null. A null array is only allowed for no arguments.
+unsafe fn values(arguments: *const *const LoxValue, count: usize) -> Option<Vec<Value>> {
+    if count == 0 {
+        return Some(Vec::new());
+    }
+    if arguments.is_null() {
+        return None;
+    }
+
+    (0..count)
+        .map(|index| {
+            (*arguments.add(index))
+                .as_ref()
+                .map(|argument| argument.value.clone())
+        })
+        .collect()
+}
+
+#[no_mangle]
+pub extern "C" fn lox_value_nil() -> *mut LoxValue {
+    LoxValue::boxed(Value::Nil)
+}
+
+#[no_mangle]
+pub extern "C" fn lox_value_bool(boolean: bool) -> *mut LoxValue {
+    LoxValue::boxed(Value::Boolean(boolean))
+}
+
+#[no_mangle]
+pub extern "C" fn lox_value_number(number: f64) -> *mut LoxValue {
+    LoxValue::boxed(Value::Number(number))
+}
+
+/// # Safety
+///
+/// `string` must be a NUL-terminated UTF-8 string.
+#[no_mangle]
+pub unsafe extern "C" fn lox_value_string(string: *const c_char) -> *mut LoxValue {
+    match self::string(string) {
+        Some(string) => LoxValue::boxed(Value::String(string.into())),
+        None => ptr::null_mut(),
+    }
+}
+
+/// # Safety
+///
+/// `message` must be a NUL-terminated UTF-8 string. Returning the result from a callback
+/// raises a runtime error with that message.
+#[no_mangle]
+pub unsafe extern "C" fn lox_value_error(message: *const c_char) -> *mut LoxValue {
+    let message = string(message).unwrap_or("Native callback failed.");
+
+    Box::into_raw(Box::new(LoxValue {
+        value: Value::Nil,
+        error: Some(message.to_string()),
+        text: None,
+    }))
+}
+
+/// # Safety
+///
+/// `value` must be null or a value returned by this library that has not been freed.
+#[no_mangle]
+pub unsafe extern "C" fn lox_value_free(value: *mut LoxValue) {
+    if !value.is_null() {
+        drop(Box::from_raw(value));
+    }
+}
+
+/// # Safety
+///
+/// `value` must be a live value.
+#[no_mangle]
+pub unsafe extern "C" fn lox_value_type(value: *const LoxValue) -> i32 {
+    match value.as_ref().map(|value| &value.value) {
+        None | Some(Value::Nil) => LOX_TYPE_NIL,
+        Some(Value::Boolean(_)) => LOX_TYPE_BOOL,
+        Some(Value::Number(_) | Value::Int(_)) => LOX_TYPE_NUMBER,
+        Some(Value::String(_)) => LOX_TYPE_STRING,
+        Some(_) => LOX_TYPE_OBJECT,
+    }
+}
+
+/// # Safety
+///
+/// `value` must be a live value.
+#[no_mangle]
+pub unsafe extern "C" fn lox_value_as_number(value: *const LoxValue) -> f64 {
+    match value.as_ref().map(|value| &value.value) {
+        Some(Value::Number(number)) => *number,
+        Some(Value::Int(integer)) => *integer as f64,
+        _ => 0.0,
+    }
+}
+
+/// # Safety
+///
+/// `value` must be a live value.
+#[no_mangle]
+pub unsafe extern "C" fn lox_value_as_bool(value: *const LoxValue) -> bool {
+    value.as_ref().is_some_and(|value| value.value.is_truthy())
+}
+
+/// # Safety
+///
+/// `value` must be a live value. The returned string is owned by `value` and stays valid
+/// until the next call to `lox_value_to_string` on it or until it is freed.
+#[no_mangle]
+pub unsafe extern "C" fn lox_value_to_string(value: *mut LoxValue) -> *const c_char {
+    let Some(value) = value.as_mut() else {
+        return ptr::null();
+    };
+
+    let text = CString::new(value.value.to_string().replace('\0', "")).unwrap_or_default();
+    value.text.insert(text).as_ptr()
+}
+
+/// # Safety
+///
+/// `value` must be a live value.
+#[no_mangle]
+pub unsafe extern "C" fn lox_value_is_callable(value: *const LoxValue) -> bool {
+    matches!(
+        value.as_ref().map(|value| &value.value),
+        Some(
+            Value::Function(_)
+                | Value::Closure(_)
+                | Value::BoundMethod(_)
+                | Value::Native(_)
+                | Value::Class(_)
+        )
+    )
+}
@@ -0,0 +1,165 @@
+use std::ffi::{c_void, CStr, CString};
+use std::ptr;
+
+use lox_ffi::*;
+
+extern "C" fn first(_: *mut c_void, arguments: *const *const LoxValue, _: usize) -> *mut LoxValue {
+    unsafe { *arguments as *mut LoxValue }
+}
+
+extern "C" fn sum(
+    user_data: *mut c_void,
+    arguments: *const *const LoxValue,
+    count: usize,
+) -> *mut LoxValue {
+    let calls = unsafe { &mut *(user_data as *mut usize) };
+    *calls += 1;
+
+    let total = (0..count)
+        .map(|index| unsafe { lox_value_as_number(*arguments.add(index)) })
+        .sum();
+    lox_value_number(total)
+}
+
+extern "C" fn refuse(_: *mut c_void, _: *const *const LoxValue, _: usize) -> *mut LoxValue {
+    unsafe { lox_value_error(c"Refused.".as_ptr()) }
+}
+
+unsafe fn source(vm: *mut LoxVm, code: &str) -> i32 {
+    let code = CString::new(code).unwrap();
+    lox_run(vm, code.as_ptr())
+}
+
+unsafe fn last_error(vm: *const LoxVm) -> String {
+    CStr::from_ptr(lox_last_error(vm))
+        .to_string_lossy()
+        .into_owned()
+}
+
+unsafe fn text(value: *mut LoxValue) -> String {
+    CStr::from_ptr(lox_value_to_string(value))
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[test]
+fn callbacks_can_hand_back_their_arguments() {
+    unsafe {
+        let vm = lox_new();
+        let mut calls = 0usize;
+        let user_data = &mut calls as *mut usize as *mut c_void;
+        assert_eq!(
+            lox_register_native(vm, c"first".as_ptr(), 1, first, ptr::null_mut()),
+            LOX_OK
+        );
+        assert_eq!(
+            lox_register_native(vm, c"sum".as_ptr(), 2, sum, user_data),
+            LOX_OK
+        );
+        assert_eq!(
+            lox_register_native(vm, c"refuse".as_ptr(), 0, refuse, ptr::null_mut()),
+            LOX_OK
+        );
+
+        let value = lox_eval(vm, c"first(\"kept\") + first(\"!\")".as_ptr());
+        assert_eq!(lox_value_type(value), LOX_TYPE_STRING);
+        assert_eq!(text(value), "kept!");
+        lox_value_free(value);
+
+        let value = lox_eval(vm, c"sum(1, 2)".as_ptr());
+        assert_eq!(lox_value_as_number(value), 3.0);
+        lox_value_free(value);
+        assert_eq!(calls, 1);
+
+        assert_eq!(source(vm, "refuse();"), LOX_RUNTIME_ERROR);
+        assert!(last_error(vm).contains("Refused."));
+        assert_eq!(source(vm, "var x = ;"), LOX_COMPILE_ERROR);
+
+        lox_free(vm);
+    }
+}
+
+#[test]
+fn call_rejects_null_arguments() {
+    unsafe {
+        let vm = lox_new();
+        assert_eq!(source(vm, "fun add(a, b) { return a + b; }"), LOX_OK);
+        let add = lox_get_global(vm, c"add".as_ptr());
+        assert!(lox_value_is_callable(add));
+
+        let one = lox_value_number(1.0);
+        let two = lox_value_number(2.0);
+        let mut result = ptr::null_mut();
+
+        let arguments = [one as *const LoxValue, two];
+        assert_eq!(
+            lox_call(vm, add, arguments.as_ptr(), 2, &mut result),
+            LOX_OK
+        );
+        assert_eq!(lox_value_as_number(result), 3.0);
+        lox_value_free(result);
+
+        let arguments = [one as *const LoxValue, ptr::null()];
+        assert_eq!(
+            lox_call(vm, add, arguments.as_ptr(), 2, &mut result),
+            LOX_INVALID_ARGUMENT
+        );
+        assert!(result.is_null());
+        assert_eq!(last_error(vm), "Arguments must be values.");
+        assert_eq!(
+            lox_call(vm, add, ptr::null(), 2, &mut result),
+            LOX_INVALID_ARGUMENT
+        );
+
+        let arguments = [one as *const LoxValue];
+        assert_eq!(
+            lox_call(vm, add, arguments.as_ptr(), 1, &mut result),
+            LOX_RUNTIME_ERROR
+        );
+        assert!(result.is_null());
+        assert!(last_error(vm).contains("Expected 2 arguments but got 1."));
+
+        lox_value_free(one);
+        lox_value_free(two);
+        lox_value_free(add);
+        lox_free(vm);
+    }
+}
+
+struct Reentry {
+    vm: *mut LoxVm,
+    status: i32,
+}
+
+extern "C" fn reenter(
+    user_data: *mut c_void,
+    _: *const *const LoxValue,
+    _: usize,
+) -> *mut LoxValue {
+    let reentry = unsafe { &mut *(user_data as *mut Reentry) };
+    reentry.status = unsafe { source(reentry.vm, "print 1;") };
+    ptr::null_mut()
+}
+
+#[test]
+fn callbacks_cannot_reenter_the_interpreter() {
+    unsafe {
+        let vm = lox_new();
+        let mut reentry = Reentry { vm, status: LOX_OK };
+        let user_data = &mut reentry as *mut Reentry as *mut c_void;
+        assert_eq!(
+            lox_register_native(vm, c"reenter".as_ptr(), 0, reenter, user_data),
+            LOX_OK
+        );
+
+        assert_eq!(source(vm, "reenter();"), LOX_OK);
+        assert_eq!(reentry.status, LOX_INVALID_ARGUMENT);
+        assert_eq!(
+            last_error(vm),
+            "Can't use the interpreter from inside one of its callbacks."
+        );
+        assert_eq!(source(vm, "var after = 1;"), LOX_OK);
+
+        lox_free(vm);
+    }
+}
@@ -0,0 +1 @@
+pub use lox::wasm::{run, RunResult};
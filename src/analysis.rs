@@ -0,0 +1,712 @@
+use alloc::collections::BTreeMap;
+use core::slice;
+
+use crate::arity::Arity;
+use crate::completion::{self, Completion};
+use crate::error::Error;
+use crate::lint::{self, Config, Warning};
+use crate::parser::Parser;
+use crate::prelude::*;
+use crate::resolver::{Resolver, Symbols};
+pub use crate::resolver::{Symbol, SymbolKind};
+use crate::scanner::{self, Scanner};
+use crate::statement::Statement;
+use crate::syntax::{self, Parse, SyntaxNode};
+use crate::token::{Keyword, Kind, Position, Token};
+
+// Semantic token types, as LSP names them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenType {
+    Keyword,
+    Class,
+    Function,
+    Method,
+    Parameter,
+    Variable,
+    Property,
+    String,
+    Number,
+    Comment,
+}
+
+impl TokenType {
+    // The legend a language server advertises; a token's type is sent as
+    // its index here.
+    pub const ALL: [TokenType; 10] = [
+        TokenType::Keyword,
+        TokenType::Class,
+        TokenType::Function,
+        TokenType::Method,
+        TokenType::Parameter,
+        TokenType::Variable,
+        TokenType::Property,
+        TokenType::String,
+        TokenType::Number,
+        TokenType::Comment,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TokenType::Keyword => "keyword",
+            TokenType::Class => "class",
+            TokenType::Function => "function",
+            TokenType::Method => "method",
+            TokenType::Parameter => "parameter",
+            TokenType::Variable => "variable",
+            TokenType::Property => "property",
+            TokenType::String => "string",
+            TokenType::Number => "number",
+            TokenType::Comment => "comment",
+        }
+    }
+
+    fn of(kind: SymbolKind) -> TokenType {
+        match kind {
+            SymbolKind::Variable => TokenType::Variable,
+            SymbolKind::Parameter => TokenType::Parameter,
+            SymbolKind::Function => TokenType::Function,
+            SymbolKind::Method => TokenType::Method,
+            SymbolKind::Class => TokenType::Class,
+        }
+    }
+}
+
+// A classified span of the source, counted in characters. Strings may span
+// several lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SemanticToken {
+    pub token_type: TokenType,
+    pub start: usize,
+    pub length: usize,
+}
+
+// Replaces the characters spanned by `position` with `text`.
+#[derive(Debug, Clone)]
+pub struct TextEdit {
+    pub position: Position,
+    pub text: String,
+}
+
+// Edits that resolve a diagnostic or a warning and are safe to apply
+// without review.
+#[derive(Debug, Clone)]
+pub struct Fix {
+    pub message: String,
+    pub edits: Vec<TextEdit>,
+}
+
+// A class, method, function or global variable, with the span of its name
+// and of its whole declaration.
+#[derive(Debug, Clone)]
+pub struct OutlineItem {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub detail: String,
+    pub position: Position,
+    pub span: Position,
+    pub children: Vec<OutlineItem>,
+}
+
+pub fn semantic_tokens(source: &str) -> Vec<SemanticToken> {
+    Analysis::new(source).semantic_tokens()
+}
+
+// What can be known about a source file without running it: its compile
+// errors, the symbols it declares and the classification of its tokens.
+// Scan and parse errors stop the analysis; the resolver's errors are only
+// looked for in a program that parses.
+pub struct Analysis {
+    source: String,
+    diagnostics: Vec<Error>,
+    statements: Vec<Rc<Statement>>,
+    symbols: Symbols,
+    tokens: Vec<Token>,
+    // Indexed like the diagnostics they resolve.
+    fixes: Vec<(usize, Fix)>,
+    // Where each expression and statement is, whether or not the source
+    // parses.
+    syntax: Parse,
+}
+
+impl Analysis {
+    pub fn new(source: &str) -> Analysis {
+        let mut scanner = Scanner::new(source.to_string());
+        scanner.set_trivia(true);
+        let tokens = match scanner.scan_tokens() {
+            Ok(tokens) => tokens,
+            Err(error) => {
+                return Analysis {
+                    source: source.to_string(),
+                    diagnostics: vec![error],
+                    statements: Vec::new(),
+                    symbols: Symbols::default(),
+                    tokens: Vec::new(),
+                    fixes: Vec::new(),
+                    syntax: syntax::parse(source),
+                }
+            }
+        };
+        Analysis::from_tokens(source.to_string(), tokens, syntax::parse(source))
+    }
+
+    // The analysis of the source after `edit`. Only the tokens around the
+    // edit are scanned again; those after it are moved along.
+    pub fn edit(&self, edit: &TextEdit) -> Analysis {
+        let source = apply(&self.source, slice::from_ref(edit));
+        match relex(&self.tokens, &self.source, &source, edit) {
+            Some(tokens) => Analysis::from_tokens(source, tokens, self.syntax.reparse(edit)),
+            None => Analysis::new(&source),
+        }
+    }
+
+    fn from_tokens(source: String, tokens: Vec<Token>, syntax: Parse) -> Analysis {
+        let program = tokens
+            .iter()
+            .filter(|token| !matches!(token.kind, Kind::Comment(_)))
+            .cloned()
+            .collect();
+        let mut parser = Parser::new(program);
+        let (statements, symbols, diagnostics) = match parser.parse() {
+            Ok(statements) => {
+                let (symbols, diagnostics) = Resolver::analyze(&statements);
+                (statements, symbols, diagnostics)
+            }
+            Err(diagnostics) => (Vec::new(), Symbols::default(), diagnostics),
+        };
+        let fixes = parser
+            .missing_semicolons()
+            .iter()
+            .map(|&(index, after)| {
+                let position = Position {
+                    start: after.current,
+                    current: after.current,
+                    line: after.line,
+                };
+                let fix = Fix {
+                    message: "Insert the missing ';'.".to_string(),
+                    edits: vec![TextEdit {
+                        position,
+                        text: ";".to_string(),
+                    }],
+                };
+                (index, fix)
+            })
+            .collect();
+
+        Analysis {
+            source,
+            diagnostics,
+            statements,
+            symbols,
+            tokens,
+            fixes,
+            syntax,
+        }
+    }
+
+    pub fn diagnostics(&self) -> &[Error] {
+        &self.diagnostics
+    }
+
+    // The fix for the diagnostic at `index`, if it has one: only a missing
+    // semicolon can be repaired without guessing what was meant.
+    pub fn fix_for(&self, index: usize) -> Option<&Fix> {
+        self.fixes
+            .iter()
+            .find(|(diagnostic, _)| *diagnostic == index)
+            .map(|(_, fix)| fix)
+    }
+
+    // The enabled rules' warnings, in source order; none for a program that
+    // doesn't parse.
+    pub fn lint(&self, config: &Config) -> Vec<Warning> {
+        let tokens: Vec<Token> = self
+            .tokens
+            .iter()
+            .filter(|token| !matches!(token.kind, Kind::Comment(_)))
+            .cloned()
+            .collect();
+        lint::check(
+            &self.statements,
+            &self.symbols,
+            &tokens,
+            &self.source,
+            config,
+        )
+    }
+
+    // In declaration order; containers come before what they contain.
+    pub fn symbols(&self) -> &[Symbol] {
+        &self.symbols.declarations
+    }
+
+    // The symbol declared or referenced by the name at `offset`, counted in
+    // characters from the start of the source.
+    pub fn symbol_at(&self, offset: usize) -> Option<&Symbol> {
+        self.symbol_index_at(offset)
+            .map(|symbol| &self.symbols.declarations[symbol])
+    }
+
+    // The innermost expression or statement covering the character at
+    // `offset`, then the nodes around it out to the program; empty between
+    // top-level declarations.
+    pub fn node_at(&self, offset: usize) -> Vec<SyntaxNode> {
+        let mut innermost = None;
+        let mut node = self.syntax.syntax();
+        while let Some(child) = node
+            .children()
+            .into_iter()
+            .find(|child| child.range().contains(&offset))
+        {
+            if child.kind().is_expression() || child.kind().is_statement() {
+                innermost = Some(child.clone());
+            }
+            node = child;
+        }
+
+        let Some(node) = innermost else {
+            return Vec::new();
+        };
+        let mut nodes = vec![node.clone()];
+        nodes.extend(node.ancestors());
+        nodes
+    }
+
+    // Names and keywords that can be typed at `offset`, matching what was
+    // typed of the one the cursor is at the end of.
+    pub fn completions(&self, offset: usize) -> Vec<Completion> {
+        completion::complete(&self.syntax.syntax(), offset)
+    }
+
+    // Where the name at `offset` is declared.
+    pub fn definition_at(&self, offset: usize) -> Option<Position> {
+        self.symbol_at(offset).map(|symbol| symbol.position)
+    }
+
+    // Top-level declarations in source order, with the methods of each class
+    // and the functions and classes declared inside each function nested
+    // under it. Parameters and local variables are left out.
+    pub fn outline(&self) -> Vec<OutlineItem> {
+        self.outline_within(None)
+    }
+
+    fn outline_within(&self, container: Option<usize>) -> Vec<OutlineItem> {
+        let declarations = &self.symbols.declarations;
+        declarations
+            .iter()
+            .enumerate()
+            .filter(|(_, symbol)| symbol.container == container)
+            .filter(|(index, symbol)| match symbol.kind {
+                SymbolKind::Class | SymbolKind::Method | SymbolKind::Function => true,
+                SymbolKind::Variable => self.symbols.scopes[*index] == 0,
+                SymbolKind::Parameter => false,
+            })
+            .map(|(index, symbol)| OutlineItem {
+                name: symbol.name.clone(),
+                kind: symbol.kind,
+                detail: symbol.detail.clone(),
+                position: symbol.position,
+                span: self.declaration_span(symbol),
+                children: self.outline_within(Some(index)),
+            })
+            .collect()
+    }
+
+    // From the "var", "fun" or "class" keyword, or a method's name, to the
+    // closing ";" or "}". Only called for programs that parse, so brackets
+    // balance.
+    fn declaration_span(&self, symbol: &Symbol) -> Position {
+        let code: Vec<&Token> = self
+            .tokens
+            .iter()
+            .filter(|token| !matches!(token.kind, Kind::Comment(_)))
+            .collect();
+        let Ok(name) =
+            code.binary_search_by_key(&symbol.position.start, |token| token.position.start)
+        else {
+            return symbol.position;
+        };
+
+        let start = match name.checked_sub(1).map(|index| &code[index].kind) {
+            Some(Kind::Keyword(Keyword::Var | Keyword::Fun | Keyword::Class)) => {
+                code[name - 1].position
+            }
+            _ => symbol.position,
+        };
+
+        let mut depth = 0;
+        let mut end = symbol.position.current;
+        for token in &code[name..] {
+            match token.kind {
+                Kind::OpenParenthesis | Kind::OpenSquareBracket | Kind::OpenCurlyBracket => {
+                    depth += 1
+                }
+                Kind::CloseParenthesis | Kind::CloseSquareBracket => depth -= 1,
+                Kind::CloseCurlyBracket => {
+                    depth -= 1;
+                    if depth == 0 && symbol.kind != SymbolKind::Variable {
+                        end = token.position.current;
+                        break;
+                    }
+                }
+                Kind::Semicolon if depth == 0 && symbol.kind == SymbolKind::Variable => {
+                    end = token.position.current;
+                    break;
+                }
+                _ => {}
+            }
+        }
+
+        Position {
+            start: start.start,
+            current: end,
+            line: start.line,
+        }
+    }
+
+    // Every use of the symbol named at `offset`, in source order, optionally
+    // with its declaration.
+    pub fn references_at(&self, offset: usize, include_declaration: bool) -> Vec<Position> {
+        match self.symbol_index_at(offset) {
+            Some(symbol) => self.occurrences(symbol, include_declaration),
+            None => Vec::new(),
+        }
+    }
+
+    // Edits renaming the variable, function or class named at `offset`
+    // along with every reference to it. Refused when the new name is
+    // already declared in the same scope, or when renaming would make any
+    // name in the program resolve differently: a use captured by an inner
+    // declaration, or another name shadowed by this one.
+    pub fn rename(&self, offset: usize, new_name: &str) -> Result<Vec<TextEdit>, String> {
+        let Some(symbol) = self.symbol_index_at(offset) else {
+            return Err("There is no variable, function or class to rename here.".to_string());
+        };
+        let declaration = &self.symbols.declarations[symbol];
+        if declaration.kind == SymbolKind::Method {
+            return Err(
+                "Methods can't be renamed: calls to them are resolved at runtime.".to_string(),
+            );
+        }
+        if !scanner::is_identifier(new_name) {
+            return Err(format!("'{}' is not a valid name.", new_name));
+        }
+
+        let scope = self.symbols.scopes[symbol];
+        let collides = self
+            .symbols
+            .declarations
+            .iter()
+            .enumerate()
+            .any(|(index, other)| {
+                index != symbol
+                    && other.name == new_name
+                    && other.kind != SymbolKind::Method
+                    && self.symbols.scopes[index] == scope
+            });
+        if collides {
+            return Err(format!("'{}' is already declared in this scope.", new_name));
+        }
+
+        let edits: Vec<TextEdit> = self
+            .occurrences(symbol, true)
+            .into_iter()
+            .map(|position| TextEdit {
+                position,
+                text: new_name.to_string(),
+            })
+            .collect();
+
+        // Renaming touches only names, so the renamed program has the same
+        // references in the same order.
+        let renamed = Analysis::new(&apply(&self.source, &edits));
+        let unchanged = renamed.symbols.references.len() == self.symbols.references.len()
+            && renamed
+                .symbols
+                .references
+                .iter()
+                .zip(&self.symbols.references)
+                .all(|(after, before)| after.symbol == before.symbol);
+        if !unchanged {
+            return Err(format!(
+                "Renaming to '{}' would change what other names refer to.",
+                new_name
+            ));
+        }
+
+        Ok(edits)
+    }
+
+    fn occurrences(&self, symbol: usize, include_declaration: bool) -> Vec<Position> {
+        let mut positions: Vec<Position> = self.symbols.uses[symbol]
+            .iter()
+            .map(|&reference| self.symbols.references[reference].position)
+            .collect();
+        if include_declaration {
+            positions.push(self.symbols.declarations[symbol].position);
+        }
+        positions.sort_by_key(|position| position.start);
+        positions
+    }
+
+    // Markdown for the name at `offset`: how it is declared, how many
+    // arguments it takes if it can be called, and the `///` comment above
+    // its declaration.
+    pub fn hover(&self, offset: usize) -> Option<String> {
+        let index = self.symbol_index_at(offset)?;
+        let symbol = &self.symbols.declarations[index];
+        let mut text = format!("```lox\n{}\n```", symbol.detail);
+        if symbol.kind == SymbolKind::Parameter {
+            if let Some(container) = symbol.container {
+                let function = &self.symbols.declarations[container];
+                text.push_str(&format!("\n\nParameter of `{}`.", function.name));
+            }
+        }
+        if let Some(arity) = self.arity(index) {
+            text.push_str(&match arity {
+                Arity {
+                    required: 0,
+                    optional: 0,
+                    variadic: false,
+                } => "\n\nTakes no arguments.".to_string(),
+                Arity {
+                    required: 1,
+                    optional: 0,
+                    variadic: false,
+                } => "\n\nTakes 1 argument.".to_string(),
+                _ => format!("\n\nTakes {} arguments.", arity),
+            });
+        }
+        if let Some(documentation) = self.documentation(index) {
+            text.push_str("\n\n");
+            text.push_str(&documentation);
+        }
+        Some(text)
+    }
+
+    // The arguments a call to the function, method or class declared at
+    // `index` takes; a class takes its initializer's. None for a subclass
+    // without an initializer of its own, which inherits one.
+    fn arity(&self, index: usize) -> Option<Arity> {
+        let declarations = &self.symbols.declarations;
+        let parameters = |function: usize| {
+            let mut arity = Arity::exactly(0);
+            for symbol in declarations.iter().filter(|symbol| {
+                symbol.kind == SymbolKind::Parameter && symbol.container == Some(function)
+            }) {
+                if symbol.detail.starts_with("parameter ...") {
+                    arity.variadic = true;
+                } else if symbol.detail.ends_with("(optional)") {
+                    arity.optional += 1;
+                } else {
+                    arity.required += 1;
+                }
+            }
+            arity
+        };
+        match declarations[index].kind {
+            SymbolKind::Function | SymbolKind::Method => Some(parameters(index)),
+            SymbolKind::Class => {
+                let initializer = declarations.iter().position(|symbol| {
+                    symbol.kind == SymbolKind::Method
+                        && symbol.name == "init"
+                        && symbol.container == Some(index)
+                });
+                match initializer {
+                    Some(initializer) => Some(parameters(initializer)),
+                    None => {
+                        let name = self.token_index(&declarations[index].position)?;
+                        let subclass = self
+                            .tokens
+                            .get(name + 1)
+                            .is_some_and(|token| token.kind == Kind::Less);
+                        (!subclass).then_some(Arity::exactly(0))
+                    }
+                }
+            }
+            SymbolKind::Variable | SymbolKind::Parameter => None,
+        }
+    }
+
+    // The lines of the `///` comments right above the declaration at
+    // `index`, without their slashes.
+    fn documentation(&self, index: usize) -> Option<String> {
+        let mut first = self.token_index(&self.symbols.declarations[index].position)?;
+        let keyword = first
+            .checked_sub(1)
+            .map(|previous| &self.tokens[previous].kind);
+        if matches!(
+            keyword,
+            Some(Kind::Keyword(Keyword::Fun | Keyword::Class | Keyword::Var))
+        ) {
+            first -= 1;
+        }
+
+        let mut lines = Vec::new();
+        let mut line = self.tokens[first].position.line;
+        while let Some(comment) = first.checked_sub(1).map(|previous| &self.tokens[previous]) {
+            let Kind::Comment(text) = &comment.kind else {
+                break;
+            };
+            let Some(text) = text.strip_prefix('/').filter(|text| !text.starts_with('/')) else {
+                break;
+            };
+            if comment.position.line + 1 != line {
+                break;
+            }
+            lines.push(text.strip_prefix(' ').unwrap_or(text));
+            line = comment.position.line;
+            first -= 1;
+        }
+
+        if lines.is_empty() {
+            return None;
+        }
+        lines.reverse();
+        Some(lines.join("\n"))
+    }
+
+    fn token_index(&self, position: &Position) -> Option<usize> {
+        self.tokens
+            .iter()
+            .position(|token| token.position.start == position.start)
+    }
+
+    fn symbol_index_at(&self, offset: usize) -> Option<usize> {
+        let contains = |position: &Position| position.start <= offset && offset < position.current;
+
+        self.symbols
+            .declarations
+            .iter()
+            .position(|symbol| contains(&symbol.position))
+            .or_else(|| {
+                self.symbols
+                    .references
+                    .iter()
+                    .find(|reference| contains(&reference.position))
+                    .and_then(|reference| reference.symbol)
+            })
+    }
+
+    // Names take their type from the declaration they resolve to. Without
+    // one (properties, natives, or a program that doesn't parse) the
+    // neighbouring tokens decide.
+    pub fn semantic_tokens(&self) -> Vec<SemanticToken> {
+        let mut resolved: BTreeMap<usize, SymbolKind> = BTreeMap::new();
+        for symbol in &self.symbols.declarations {
+            resolved.insert(symbol.position.start, symbol.kind);
+        }
+        for reference in &self.symbols.references {
+            if let Some(symbol) = reference.symbol {
+                let kind = self.symbols.declarations[symbol].kind;
+                resolved.insert(reference.position.start, kind);
+            }
+        }
+
+        let mut semantic = Vec::new();
+        for (index, token) in self.tokens.iter().enumerate() {
+            let token_type = match &token.kind {
+                Kind::Keyword(_) => TokenType::Keyword,
+                Kind::String(_) => TokenType::String,
+                Kind::Number(_) | Kind::Integer(_) => TokenType::Number,
+                Kind::Comment(_) => TokenType::Comment,
+                Kind::Identifier(_) => {
+                    let after_dot = index > 0 && self.tokens[index - 1].kind == Kind::Dot;
+                    let called = self
+                        .tokens
+                        .get(index + 1)
+                        .is_some_and(|next| next.kind == Kind::OpenParenthesis);
+                    match resolved.get(&token.position.start) {
+                        Some(&kind) => TokenType::of(kind),
+                        None if after_dot && called => TokenType::Method,
+                        None if after_dot => TokenType::Property,
+                        None if called => TokenType::Function,
+                        None => TokenType::Variable,
+                    }
+                }
+                _ => continue,
+            };
+
+            semantic.push(SemanticToken {
+                token_type,
+                start: token.position.start,
+                length: token.position.current - token.position.start,
+            });
+        }
+        semantic
+    }
+}
+
+// Scans from the end of the last token before `edit` until a token lines up
+// with an old one after it; from there on the old tokens only move. None
+// when the old source didn't scan, or the new one doesn't.
+fn relex(old: &[Token], old_source: &str, source: &str, edit: &TextEdit) -> Option<Vec<Token>> {
+    let (start, end) = (edit.position.start, edit.position.current);
+    let removed: String = old_source.chars().skip(start).take(end - start).collect();
+    let shift = edit.text.chars().count() as isize - (end - start) as isize;
+    let lines = edit.text.matches('\n').count() as isize - removed.matches('\n').count() as isize;
+
+    // A token ending where the edit starts may run on into it, and a number
+    // before a '.' may take the '.' and a digit after it.
+    let kept = old
+        .iter()
+        .position(|token| token.position.current >= start)?
+        .saturating_sub(1);
+    let mut tokens = old[..kept].to_vec();
+    let mut scanner = Scanner::new(source.to_string());
+    scanner.set_trivia(true);
+    if let Some(last) = tokens.last() {
+        scanner.seek(last.position.current, last.position.line);
+    }
+
+    let edited_end = start + edit.text.chars().count();
+    let mut next = kept;
+    loop {
+        let token = scanner.next_token().ok()?;
+        if token.position.start >= edited_end {
+            let old_start = (token.position.start as isize - shift) as usize;
+            while old
+                .get(next)
+                .is_some_and(|old| old.position.start < old_start)
+            {
+                next += 1;
+            }
+            let lines_up = old.get(next).is_some_and(|old| {
+                old.position.start == old_start
+                    && old.kind == token.kind
+                    && old.position.line as isize + lines == token.position.line as isize
+            });
+            if lines_up {
+                tokens.extend(old[next..].iter().map(|old| Token {
+                    kind: old.kind.clone(),
+                    position: Position {
+                        start: (old.position.start as isize + shift) as usize,
+                        current: (old.position.current as isize + shift) as usize,
+                        line: (old.position.line as isize + lines) as usize,
+                    },
+                }));
+                return Some(tokens);
+            }
+        }
+
+        let finished = token.kind == Kind::EndOfFile;
+        tokens.push(token);
+        if finished {
+            return Some(tokens);
+        }
+    }
+}
+
+// Expects the edits sorted by position and not overlapping.
+pub(crate) fn apply(source: &str, edits: &[TextEdit]) -> String {
+    let characters: Vec<char> = source.chars().collect();
+    let mut result = String::new();
+    let mut offset = 0;
+    for edit in edits {
+        result.extend(&characters[offset..edit.position.start]);
+        result.push_str(&edit.text);
+        offset = edit.position.current;
+    }
+    result.extend(&characters[offset..]);
+    result
+}
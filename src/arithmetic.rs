@@ -0,0 +1,248 @@
+use core::cmp::Ordering;
+use core::mem;
+
+use crate::error::ErrorCategory;
+use crate::interpreter::DivisionByZero;
+use crate::prelude::*;
+use crate::value::Value;
+
+// The numeric operators, with the rules every engine shares. Integers only
+// come from dialects that have them: two integers give an integer, or an
+// error where the result would not fit, and an integer meeting a float is
+// promoted to one. `/` always divides exactly; `div` rounds down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Operator {
+    Subtract,
+    Multiply,
+    Divide,
+    FloorDivide,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+}
+
+// Why an operator failed, with the category its error is raised with.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Failure {
+    pub(crate) category: ErrorCategory,
+    pub(crate) message: &'static str,
+}
+
+impl Failure {
+    const fn new(category: ErrorCategory, message: &'static str) -> Failure {
+        Failure { category, message }
+    }
+}
+
+pub(crate) const NOT_NUMBERS: Failure =
+    Failure::new(ErrorCategory::Type, "Operands must be numbers.");
+pub(crate) const NOT_COMPARABLE: Failure = Failure::new(
+    ErrorCategory::Type,
+    "Operands must be two numbers or two strings.",
+);
+const FRACTIONAL_COUNT: Failure = Failure::new(
+    ErrorCategory::General,
+    "Repetition count must be an integer.",
+);
+const NEGATIVE_COUNT: Failure = Failure::new(
+    ErrorCategory::General,
+    "Repetition count must not be negative.",
+);
+const TOO_LARGE: Failure = Failure::new(ErrorCategory::General, "Repetition result is too large.");
+// Past this a repetition fails rather than leaving an allocation the system
+// may grant lazily and then be unable to fill.
+const MAX_REPETITION_BYTES: usize = 1 << 30;
+pub(crate) const OVERFLOW: Failure = Failure::new(ErrorCategory::General, "Integer overflow.");
+pub(crate) const DIVISION_BY_ZERO: Failure =
+    Failure::new(ErrorCategory::General, "Division by zero.");
+
+// The settings of the running interpreter that change what operators do.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Rules {
+    // Infinity or NaN as IEEE 754 has it, or an error. Integer `div` has no
+    // infinity to give, so it fails either way.
+    pub(crate) division: DivisionByZero,
+    pub(crate) string_ordering: bool,
+    pub(crate) repetition: bool,
+}
+
+pub(crate) fn binary(
+    operator: Operator,
+    left: &Value,
+    right: &Value,
+    rules: Rules,
+) -> Result<Value, Failure> {
+    if rules.string_ordering {
+        if let Some(result) = compare_strings(operator, left, right) {
+            return result;
+        }
+    }
+    if rules.repetition && operator == Operator::Multiply {
+        match (left, right) {
+            (Value::String(_) | Value::List(_), count) => return repeat(left, count),
+            (count, Value::String(_) | Value::List(_)) => return repeat(right, count),
+            _ => {}
+        }
+    }
+
+    let division = rules.division;
+    let dividing = matches!(operator, Operator::Divide | Operator::FloorDivide);
+    if let (Value::Int(left), Value::Int(right)) = (left, right) {
+        if dividing && *right == 0 && division == DivisionByZero::Error {
+            return Err(DIVISION_BY_ZERO);
+        }
+        return integer(operator, *left, *right);
+    }
+    match (number(left), number(right)) {
+        (Some(_), Some(right)) if dividing && right == 0.0 && division == DivisionByZero::Error => {
+            Err(DIVISION_BY_ZERO)
+        }
+        (Some(left), Some(right)) => Ok(float(operator, left, right)),
+        _ => Err(NOT_NUMBERS),
+    }
+}
+
+// Numbers only; adding strings is up to the caller.
+pub(crate) fn add(left: &Value, right: &Value) -> Option<Result<Value, Failure>> {
+    match (left, right) {
+        (Value::Number(left), Value::Number(right)) => Some(Ok(Value::Number(left + right))),
+        (Value::Int(left), Value::Int(right)) => {
+            Some(left.checked_add(*right).map(Value::Int).ok_or(OVERFLOW))
+        }
+        _ => Some(Ok(Value::Number(number(left)? + number(right)?))),
+    }
+}
+
+// Orders two strings lexicographically for the comparison operators. None
+// leaves the operands to the numeric rules.
+fn compare_strings(
+    operator: Operator,
+    left: &Value,
+    right: &Value,
+) -> Option<Result<Value, Failure>> {
+    let accepts: fn(Ordering) -> bool = match operator {
+        Operator::Greater => Ordering::is_gt,
+        Operator::GreaterEqual => Ordering::is_ge,
+        Operator::Less => Ordering::is_lt,
+        Operator::LessEqual => Ordering::is_le,
+        _ => return None,
+    };
+    let result = match (left, right) {
+        (Value::String(left), Value::String(right)) => accepts(left.cmp(right)),
+        (Value::String(_), _) | (_, Value::String(_)) => return Some(Err(NOT_COMPARABLE)),
+        _ => return None,
+    };
+    Some(Ok(Value::Boolean(result)))
+}
+
+// `sequence` repeated `count` times, a list's elements shared rather than
+// copied.
+fn repeat(sequence: &Value, count: &Value) -> Result<Value, Failure> {
+    let count = match count {
+        Value::Int(count) => *count as f64,
+        Value::Number(count) => *count,
+        _ => return Err(NOT_NUMBERS),
+    };
+    if count % 1.0 != 0.0 {
+        return Err(FRACTIONAL_COUNT);
+    }
+    if count < 0.0 {
+        return Err(NEGATIVE_COUNT);
+    }
+    let count = count as usize;
+
+    match sequence {
+        Value::String(string) => {
+            let length = repeated_length(string.len(), count, 1)?;
+            let mut repeated = String::new();
+            repeated.try_reserve_exact(length).map_err(|_| TOO_LARGE)?;
+            if !string.is_empty() {
+                for _ in 0..count {
+                    repeated.push_str(string);
+                }
+            }
+            Ok(Value::String(repeated.into()))
+        }
+        Value::List(list) => {
+            let elements = list.borrow();
+            let length = repeated_length(elements.len(), count, mem::size_of::<Value>())?;
+            let mut repeated = Vec::new();
+            repeated.try_reserve_exact(length).map_err(|_| TOO_LARGE)?;
+            repeated.extend(elements.iter().cycle().take(length).cloned());
+            Ok(Value::from(repeated))
+        }
+        _ => Err(NOT_NUMBERS),
+    }
+}
+
+fn repeated_length(length: usize, count: usize, size: usize) -> Result<usize, Failure> {
+    let repeated = length.checked_mul(count).ok_or(TOO_LARGE)?;
+    match repeated.checked_mul(size) {
+        Some(bytes) if bytes <= MAX_REPETITION_BYTES => Ok(repeated),
+        _ => Err(TOO_LARGE),
+    }
+}
+
+pub(crate) fn negate(value: &Value) -> Option<Result<Value, Failure>> {
+    match value {
+        Value::Number(number) => Some(Ok(Value::Number(-number))),
+        Value::Int(integer) => Some(integer.checked_neg().map(Value::Int).ok_or(OVERFLOW)),
+        _ => None,
+    }
+}
+
+pub(crate) fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(number) => Some(*number),
+        Value::Int(integer) => Some(*integer as f64),
+        _ => None,
+    }
+}
+
+fn integer(operator: Operator, left: i64, right: i64) -> Result<Value, Failure> {
+    let result = match operator {
+        Operator::Subtract => left.checked_sub(right),
+        Operator::Multiply => left.checked_mul(right),
+        Operator::Divide => return Ok(Value::Number(left as f64 / right as f64)),
+        Operator::FloorDivide if right == 0 => return Err(DIVISION_BY_ZERO),
+        Operator::FloorDivide => left.checked_div(right).map(|quotient| {
+            if left % right != 0 && (left < 0) != (right < 0) {
+                quotient - 1
+            } else {
+                quotient
+            }
+        }),
+        Operator::Greater => return Ok(Value::Boolean(left > right)),
+        Operator::GreaterEqual => return Ok(Value::Boolean(left >= right)),
+        Operator::Less => return Ok(Value::Boolean(left < right)),
+        Operator::LessEqual => return Ok(Value::Boolean(left <= right)),
+    };
+    result.map(Value::Int).ok_or(OVERFLOW)
+}
+
+fn float(operator: Operator, left: f64, right: f64) -> Value {
+    match operator {
+        Operator::Subtract => Value::Number(left - right),
+        Operator::Multiply => Value::Number(left * right),
+        Operator::Divide => Value::Number(left / right),
+        Operator::FloorDivide => Value::Number(floor(left / right)),
+        Operator::Greater => Value::Boolean(left > right),
+        Operator::GreaterEqual => Value::Boolean(left >= right),
+        Operator::Less => Value::Boolean(left < right),
+        Operator::LessEqual => Value::Boolean(left <= right),
+    }
+}
+
+// f64::floor needs std.
+fn floor(number: f64) -> f64 {
+    if !number.is_finite() {
+        return number;
+    }
+    let fraction = number % 1.0;
+    if fraction < 0.0 {
+        number - fraction - 1.0
+    } else {
+        number - fraction
+    }
+}
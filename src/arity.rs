@@ -0,0 +1,52 @@
+use core::fmt;
+
+use crate::prelude::*;
+
+// How many arguments a callable takes: its required parameters, up to
+// `optional` more that have default values, and any number past those when
+// its last parameter gathers them into a list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Arity {
+    pub required: usize,
+    pub optional: usize,
+    pub variadic: bool,
+}
+
+impl Arity {
+    pub fn exactly(count: usize) -> Arity {
+        Arity {
+            required: count,
+            optional: 0,
+            variadic: false,
+        }
+    }
+
+    pub fn is_exact(&self) -> bool {
+        self.optional == 0 && !self.variadic
+    }
+
+    pub fn accepts(&self, count: usize) -> bool {
+        count >= self.required && (self.variadic || count <= self.required + self.optional)
+    }
+
+    // The parameters with names of their own, gathering one aside.
+    pub fn named(&self) -> usize {
+        self.required + self.optional
+    }
+
+    pub fn error(&self, count: usize) -> String {
+        format!("Expected {} arguments but got {}.", self, count)
+    }
+}
+
+impl fmt::Display for Arity {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        if self.variadic {
+            write!(formatter, "at least {}", self.required)
+        } else if self.optional > 0 {
+            write!(formatter, "{} to {}", self.required, self.named())
+        } else {
+            write!(formatter, "{}", self.required)
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use std::time::{Duration, Instant};
+
+use crate::error::LoxError;
+use crate::io::CaptureIo;
+use crate::lox::{Engine, Lox};
+
+pub const DEFAULT_BUDGET: Duration = Duration::from_secs(1);
+
+pub struct Workload {
+    pub name: &'static str,
+    pub source: &'static str,
+}
+
+// The standard workloads, shared by `lox bench` and the criterion suite.
+pub const WORKLOADS: &[Workload] = &[
+    Workload {
+        name: "fib",
+        source: include_str!("../benches/workloads/fib.lox"),
+    },
+    Workload {
+        name: "binary_trees",
+        source: include_str!("../benches/workloads/binary_trees.lox"),
+    },
+    Workload {
+        name: "string_concat",
+        source: include_str!("../benches/workloads/string_concat.lox"),
+    },
+    Workload {
+        name: "string_passing",
+        source: include_str!("../benches/workloads/string_passing.lox"),
+    },
+    Workload {
+        name: "method_dispatch",
+        source: include_str!("../benches/workloads/method_dispatch.lox"),
+    },
+    Workload {
+        name: "zoo",
+        source: include_str!("../benches/workloads/zoo.lox"),
+    },
+];
+
+// The experimental register VM can't compile classes yet, so it is left out.
+pub const ENGINES: &[Engine] = &[Engine::Walker, Engine::Vm];
+
+pub struct Measurement {
+    pub iterations: u32,
+    pub elapsed: Duration,
+}
+
+impl Measurement {
+    pub fn ops_per_second(&self) -> f64 {
+        f64::from(self.iterations) / self.elapsed.as_secs_f64()
+    }
+
+    pub fn mean(&self) -> Duration {
+        self.elapsed / self.iterations.max(1)
+    }
+}
+
+pub fn workload(name: &str) -> Option<&'static Workload> {
+    WORKLOADS.iter().find(|workload| workload.name == name)
+}
+
+// Runs one iteration in a fresh session, with output captured and dropped.
+pub fn run(workload: &Workload, engine: Engine) -> Result<(), LoxError> {
+    let mut lox = Lox::builder()
+        .with_io(Box::new(CaptureIo::new()))
+        .engine(engine)
+        .build();
+    lox.run(workload.source)
+}
+
+// Repeats the workload until the budget is spent, always at least once.
+pub fn measure(
+    workload: &Workload,
+    engine: Engine,
+    budget: Duration,
+) -> Result<Measurement, LoxError> {
+    let start = Instant::now();
+    let mut iterations = 0;
+    loop {
+        run(workload, engine)?;
+        iterations += 1;
+
+        let elapsed = start.elapsed();
+        if elapsed >= budget {
+            return Ok(Measurement {
+                iterations,
+                elapsed,
+            });
+        }
+    }
+}
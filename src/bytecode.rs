@@ -0,0 +1,342 @@
+use core::fmt;
+
+use crate::arity::Arity;
+use crate::prelude::*;
+use crate::symbol::Symbol;
+
+macro_rules! opcodes {
+    ($($name:ident),* $(,)?) => {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        #[repr(u8)]
+        pub enum OpCode {
+            $($name),*
+        }
+
+        impl OpCode {
+            const ALL: &'static [OpCode] = &[$(OpCode::$name),*];
+
+            pub fn name(self) -> &'static str {
+                match self {
+                    $(OpCode::$name => stringify!($name)),*
+                }
+            }
+        }
+    };
+}
+
+opcodes! {
+    Constant,
+    Nil,
+    True,
+    False,
+    Pop,
+    Equal,
+    Greater,
+    Less,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Not,
+    Negate,
+    Print,
+    DefineGlobal,
+    GetGlobal,
+    SetGlobal,
+    GetLocal,
+    SetLocal,
+    GreaterEqual,
+    LessEqual,
+    BuildList,
+    BuildMap,
+    Index,
+    SetIndex,
+    Call,
+    Closure,
+    GetUpvalue,
+    SetUpvalue,
+    CloseUpvalue,
+    Class,
+    GetProperty,
+    SetProperty,
+    GetSuper,
+    Jump,
+    JumpIfFalse,
+    Loop,
+    Return,
+    ConstantLong,
+    DefineGlobalLong,
+    GetGlobalLong,
+    SetGlobalLong,
+    GetLocalLong,
+    SetLocalLong,
+    ClosureLong,
+    ClassLong,
+    GetPropertyLong,
+    SetPropertyLong,
+    GetSuperLong,
+    FloorDivide,
+    Try,
+    EndTry,
+    Throw,
+    Defer,
+    Unpack,
+    Iterator,
+    Next,
+    Yield,
+    ExpectValues,
+}
+
+impl OpCode {
+    pub fn is_long(self) -> bool {
+        matches!(
+            self,
+            OpCode::ConstantLong
+                | OpCode::DefineGlobalLong
+                | OpCode::GetGlobalLong
+                | OpCode::SetGlobalLong
+                | OpCode::GetLocalLong
+                | OpCode::SetLocalLong
+                | OpCode::ClosureLong
+                | OpCode::ClassLong
+                | OpCode::GetPropertyLong
+                | OpCode::SetPropertyLong
+                | OpCode::GetSuperLong
+        )
+    }
+
+    pub fn long(self) -> OpCode {
+        match self {
+            OpCode::Constant => OpCode::ConstantLong,
+            OpCode::DefineGlobal => OpCode::DefineGlobalLong,
+            OpCode::GetGlobal => OpCode::GetGlobalLong,
+            OpCode::SetGlobal => OpCode::SetGlobalLong,
+            OpCode::GetLocal => OpCode::GetLocalLong,
+            OpCode::SetLocal => OpCode::SetLocalLong,
+            OpCode::Closure => OpCode::ClosureLong,
+            OpCode::Class => OpCode::ClassLong,
+            OpCode::GetProperty => OpCode::GetPropertyLong,
+            OpCode::SetProperty => OpCode::SetPropertyLong,
+            OpCode::GetSuper => OpCode::GetSuperLong,
+            op => op,
+        }
+    }
+
+    pub fn short(self) -> OpCode {
+        match self {
+            OpCode::ConstantLong => OpCode::Constant,
+            OpCode::DefineGlobalLong => OpCode::DefineGlobal,
+            OpCode::GetGlobalLong => OpCode::GetGlobal,
+            OpCode::SetGlobalLong => OpCode::SetGlobal,
+            OpCode::GetLocalLong => OpCode::GetLocal,
+            OpCode::SetLocalLong => OpCode::SetLocal,
+            OpCode::ClosureLong => OpCode::Closure,
+            OpCode::ClassLong => OpCode::Class,
+            OpCode::GetPropertyLong => OpCode::GetProperty,
+            OpCode::SetPropertyLong => OpCode::SetProperty,
+            OpCode::GetSuperLong => OpCode::GetSuper,
+            op => op,
+        }
+    }
+}
+
+impl TryFrom<u8> for OpCode {
+    type Error = u8;
+
+    fn try_from(byte: u8) -> Result<OpCode, u8> {
+        OpCode::ALL.get(byte as usize).copied().ok_or(byte)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Constant {
+    Number(f64),
+    Integer(i64),
+    String(Rc<str>),
+    Function(Rc<Prototype>),
+}
+
+impl Constant {
+    // Whether a chunk can hand out `other` in place of this constant.
+    // Numbers compare by their bits, so `-0` and `0` stay apart.
+    pub(crate) fn is_same(&self, other: &Constant) -> bool {
+        match (self, other) {
+            (Constant::Number(left), Constant::Number(right)) => left.to_bits() == right.to_bits(),
+            _ => self == other,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Prototype {
+    pub name: String,
+    pub arity: Arity,
+    // Where the code giving each optional parameter its default value
+    // starts, after the body. A call that leaves some out starts at the
+    // first one it left out instead of at the top.
+    pub defaults: Vec<usize>,
+    pub upvalue_count: usize,
+    // A call makes a generator of the arguments, which runs the code a
+    // `yield` at a time as a `for` loop asks for values.
+    pub is_generator: bool,
+    pub chunk: Chunk,
+}
+
+impl fmt::Display for Constant {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Constant::Number(number) => write!(formatter, "{}", number),
+            Constant::Integer(integer) => write!(formatter, "{}", integer),
+            Constant::String(string) => write!(formatter, "{:?}", string),
+            Constant::Function(prototype) => write!(formatter, "<fn {}>", prototype.name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct LineRun {
+    line: usize,
+    count: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Chunk {
+    code: Vec<u8>,
+    constants: Vec<Constant>,
+    symbols: Vec<Option<Symbol>>,
+    lines: Vec<LineRun>,
+}
+
+impl Chunk {
+    pub fn new() -> Chunk {
+        Chunk::default()
+    }
+
+    pub fn code(&self) -> &[u8] {
+        &self.code
+    }
+
+    pub fn len(&self) -> usize {
+        self.code.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.code.is_empty()
+    }
+
+    pub fn write(&mut self, byte: u8, line: usize) {
+        self.code.push(byte);
+
+        match self.lines.last_mut() {
+            Some(run) if run.line == line => run.count += 1,
+            _ => self.lines.push(LineRun { line, count: 1 }),
+        }
+    }
+
+    pub fn write_op(&mut self, op: OpCode, line: usize) {
+        self.write(op as u8, line);
+    }
+
+    pub fn write_u16(&mut self, value: u16, line: usize) {
+        let [high, low] = value.to_be_bytes();
+        self.write(high, line);
+        self.write(low, line);
+    }
+
+    pub fn write_u24(&mut self, value: u32, line: usize) {
+        let [_, high, middle, low] = value.to_be_bytes();
+        self.write(high, line);
+        self.write(middle, line);
+        self.write(low, line);
+    }
+
+    pub fn patch(&mut self, offset: usize, byte: u8) {
+        self.code[offset] = byte;
+    }
+
+    pub fn patch_u16(&mut self, offset: usize, value: u16) {
+        let [high, low] = value.to_be_bytes();
+        self.code[offset] = high;
+        self.code[offset + 1] = low;
+    }
+
+    pub fn read_u16(&self, offset: usize) -> u16 {
+        u16::from_be_bytes([self.code[offset], self.code[offset + 1]])
+    }
+
+    pub fn read_u24(&self, offset: usize) -> u32 {
+        u32::from_be_bytes([
+            0,
+            self.code[offset],
+            self.code[offset + 1],
+            self.code[offset + 2],
+        ])
+    }
+
+    pub fn add_constant(&mut self, constant: Constant) -> usize {
+        if let Some(index) = self
+            .constants
+            .iter()
+            .position(|existing| existing.is_same(&constant))
+        {
+            return index;
+        }
+
+        self.symbols.push(symbol(&constant));
+        self.constants.push(constant);
+        self.constants.len() - 1
+    }
+
+    pub fn constants(&self) -> &[Constant] {
+        &self.constants
+    }
+
+    pub fn constant(&self, index: usize) -> &Constant {
+        &self.constants[index]
+    }
+
+    // String constants as symbols, hashed once here rather than at each
+    // global or property access that names them.
+    pub fn symbol(&self, index: usize) -> Option<&Symbol> {
+        self.symbols[index].as_ref()
+    }
+
+    pub fn from_parts(
+        code: Vec<u8>,
+        constants: Vec<Constant>,
+        line_runs: Vec<(usize, usize)>,
+    ) -> Chunk {
+        Chunk {
+            code,
+            symbols: constants.iter().map(symbol).collect(),
+            constants,
+            lines: line_runs
+                .into_iter()
+                .map(|(line, count)| LineRun { line, count })
+                .collect(),
+        }
+    }
+
+    pub fn line_runs(&self) -> Vec<(usize, usize)> {
+        self.lines.iter().map(|run| (run.line, run.count)).collect()
+    }
+
+    pub fn line(&self, offset: usize) -> usize {
+        let mut remaining = offset;
+        for run in &self.lines {
+            if remaining < run.count {
+                return run.line;
+            }
+            remaining -= run.count;
+        }
+
+        self.lines.last().map_or(0, |run| run.line)
+    }
+}
+
+fn symbol(constant: &Constant) -> Option<Symbol> {
+    match constant {
+        Constant::String(string) => Some(Symbol::from(Rc::clone(string))),
+        _ => None,
+    }
+}
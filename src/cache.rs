@@ -0,0 +1,81 @@
+use alloc::rc::Weak;
+
+use crate::bytecode::Prototype;
+use crate::prelude::*;
+use crate::value::{Class, Method, Value};
+
+#[derive(Clone)]
+enum InlineCache {
+    Empty,
+    Global { version: u64, value: Value },
+    Method { class: Weak<Class>, method: Method },
+}
+
+// Caches are kept per instruction offset for every prototype the VM runs. They live on the VM
+// rather than in the chunk so cached values never form reference cycles through a prototype,
+// and each entry keeps a weak handle to its prototype or class so a freed address can't be
+// mistaken for a live one.
+#[derive(Default)]
+pub(crate) struct InlineCaches {
+    prototypes: HashMap<usize, usize>,
+    sites: Vec<(Weak<Prototype>, Vec<InlineCache>)>,
+}
+
+impl InlineCaches {
+    pub(crate) fn register(&mut self, prototype: &Rc<Prototype>) -> usize {
+        let address = Rc::as_ptr(prototype) as usize;
+        if let Some(&index) = self.prototypes.get(&address) {
+            return index;
+        }
+
+        let index = self.sites.len();
+        self.sites.push((
+            Rc::downgrade(prototype),
+            vec![InlineCache::Empty; prototype.chunk.len()],
+        ));
+        self.prototypes.insert(address, index);
+        index
+    }
+
+    pub(crate) fn global(&self, caches: usize, site: usize, version: u64) -> Option<Value> {
+        match &self.sites[caches].1[site] {
+            InlineCache::Global {
+                version: cached,
+                value,
+            } if *cached == version => Some(value.clone()),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn set_global(&mut self, caches: usize, site: usize, version: u64, value: Value) {
+        self.sites[caches].1[site] = InlineCache::Global { version, value };
+    }
+
+    pub(crate) fn method(&self, caches: usize, site: usize, class: &Rc<Class>) -> Option<Method> {
+        match &self.sites[caches].1[site] {
+            InlineCache::Method {
+                class: cached,
+                method,
+            } if cached.as_ptr() == Rc::as_ptr(class) => Some(method.clone()),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn set_method(
+        &mut self,
+        caches: usize,
+        site: usize,
+        class: &Rc<Class>,
+        method: Method,
+    ) {
+        self.sites[caches].1[site] = InlineCache::Method {
+            class: Rc::downgrade(class),
+            method,
+        };
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.prototypes.clear();
+        self.sites.clear();
+    }
+}
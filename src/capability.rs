@@ -0,0 +1,74 @@
+use core::fmt;
+use core::ops::{BitOr, BitOrAssign, Sub};
+
+use crate::prelude::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Capabilities(u8);
+
+impl Capabilities {
+    pub const NONE: Capabilities = Capabilities(0);
+    pub const FS: Capabilities = Capabilities(1);
+    pub const PROCESS: Capabilities = Capabilities(1 << 1);
+    pub const TIME: Capabilities = Capabilities(1 << 2);
+    pub const RANDOM: Capabilities = Capabilities(1 << 3);
+    pub const ALL: Capabilities = Capabilities(0b1111);
+
+    const NAMES: [(Capabilities, &'static str); 4] = [
+        (Capabilities::FS, "fs"),
+        (Capabilities::PROCESS, "process"),
+        (Capabilities::TIME, "time"),
+        (Capabilities::RANDOM, "random"),
+    ];
+
+    pub fn contains(self, other: Capabilities) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn from_name(name: &str) -> Option<Capabilities> {
+        Capabilities::NAMES
+            .iter()
+            .find(|(_, candidate)| *candidate == name)
+            .map(|(capability, _)| *capability)
+    }
+}
+
+impl Default for Capabilities {
+    fn default() -> Capabilities {
+        Capabilities::FS | Capabilities::TIME | Capabilities::RANDOM
+    }
+}
+
+impl BitOr for Capabilities {
+    type Output = Capabilities;
+
+    fn bitor(self, other: Capabilities) -> Capabilities {
+        Capabilities(self.0 | other.0)
+    }
+}
+
+impl BitOrAssign for Capabilities {
+    fn bitor_assign(&mut self, other: Capabilities) {
+        self.0 |= other.0;
+    }
+}
+
+impl Sub for Capabilities {
+    type Output = Capabilities;
+
+    fn sub(self, other: Capabilities) -> Capabilities {
+        Capabilities(self.0 & !other.0)
+    }
+}
+
+impl fmt::Display for Capabilities {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let names: Vec<&str> = Capabilities::NAMES
+            .iter()
+            .filter(|(capability, _)| self.contains(*capability))
+            .map(|(_, name)| *name)
+            .collect();
+
+        write!(formatter, "{}", names.join(", "))
+    }
+}
@@ -0,0 +1,83 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::error::Error;
+use crate::parser::Parser;
+use crate::resolver::Resolver;
+use crate::scanner::Scanner;
+
+// What checking one file found: its compile errors, or why it could not be
+// read.
+#[derive(Debug)]
+pub struct Checked {
+    pub path: PathBuf,
+    pub result: Result<Vec<Error>, io::Error>,
+}
+
+// Scans, parses and resolves each file, with files checked concurrently when
+// the `parallel` feature is on. A directory stands for the .lox files under
+// it. Results come back in the order the paths were given, a directory's
+// files sorted by path, however the work happened to be scheduled.
+pub fn check(paths: &[PathBuf]) -> Vec<Checked> {
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            lox_files(path, &mut files);
+        } else {
+            files.push(path.clone());
+        }
+    }
+
+    map(&files, |path| Checked {
+        path: path.clone(),
+        result: fs::read_to_string(path).map(|source| check_source(&source)),
+    })
+}
+
+// The errors compiling `source` would report, without compiling it.
+pub fn check_source(source: &str) -> Vec<Error> {
+    let tokens = match Scanner::new(source.to_string()).scan_tokens() {
+        Ok(tokens) => tokens,
+        Err(error) => return vec![error],
+    };
+    match Parser::new(tokens).parse() {
+        Ok(statements) => Resolver::analyze(&statements).1,
+        Err(errors) => errors,
+    }
+}
+
+// Skips hidden directories and build output.
+pub(crate) fn lox_files(directory: &Path, paths: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(directory) else {
+        return;
+    };
+    let mut entries: Vec<PathBuf> = entries.flatten().map(|entry| entry.path()).collect();
+    entries.sort();
+
+    for path in entries {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if path.is_dir() {
+            if !name.starts_with('.') && name != "target" {
+                lox_files(&path, paths);
+            }
+        } else if path.extension().is_some_and(|extension| extension == "lox") {
+            paths.push(path);
+        }
+    }
+}
+
+// Applies `f` to every item, spread over rayon's pool when there is one, and
+// keeps the items' order. Syntax trees share nodes through Rc, so `f` must
+// hand back only what it found, never the trees themselves.
+pub(crate) fn map<T: Sync, R: Send>(items: &[T], f: impl Fn(&T) -> R + Sync + Send) -> Vec<R> {
+    #[cfg(feature = "parallel")]
+    {
+        use rayon::prelude::*;
+        items.par_iter().map(f).collect()
+    }
+    #[cfg(not(feature = "parallel"))]
+    {
+        items.iter().map(f).collect()
+    }
+}
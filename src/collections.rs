@@ -0,0 +1,5 @@
+#[cfg(feature = "std")]
+pub use std::collections::HashMap;
+
+#[cfg(not(feature = "std"))]
+pub type HashMap<K, V> = alloc::collections::BTreeMap<K, V>;
@@ -0,0 +1,997 @@
+use crate::arity::Arity;
+use crate::bytecode::{Chunk, Constant, OpCode, Prototype};
+use crate::error::Error;
+use crate::expression::{Expr, ExpressionKind, Literal};
+use crate::prelude::*;
+use crate::statement::{FunctionDeclaration, Pattern, Statement};
+use crate::symbol::Interner;
+use crate::token::{Keyword, Kind, Token};
+
+const MAX_CONSTANTS: usize = 1 << 24;
+const MAX_LOCALS: usize = u16::MAX as usize + 1;
+const MAX_UPVALUES: usize = u8::MAX as usize + 1;
+const MAX_METHODS: usize = u8::MAX as usize;
+
+pub fn compile(statements: &[Rc<Statement>]) -> Result<Rc<Prototype>, Vec<Error>> {
+    Compiler::new().compile(statements)
+}
+
+struct Local {
+    name: String,
+    depth: usize,
+    captured: bool,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum FunctionKind {
+    Script,
+    Function,
+    Method,
+    Initializer,
+    // A deferred block, compiled as a closure whose first slot holds the
+    // value its function is returning, which it hands back.
+    Deferred,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+struct Capture {
+    index: u16,
+    is_local: bool,
+}
+
+struct FunctionState {
+    prototype: Prototype,
+    kind: FunctionKind,
+    locals: Vec<Local>,
+    captures: Vec<Capture>,
+    scope_depth: usize,
+}
+
+impl FunctionState {
+    fn new(name: String, arity: Arity, kind: FunctionKind) -> FunctionState {
+        let receiver = match kind {
+            FunctionKind::Method | FunctionKind::Initializer => "this",
+            FunctionKind::Script | FunctionKind::Function | FunctionKind::Deferred => "",
+        };
+
+        FunctionState {
+            prototype: Prototype {
+                name,
+                arity,
+                defaults: Vec::new(),
+                upvalue_count: 0,
+                is_generator: false,
+                chunk: Chunk::new(),
+            },
+            kind,
+            locals: vec![Local {
+                name: receiver.to_string(),
+                depth: 0,
+                captured: false,
+            }],
+            captures: Vec::new(),
+            scope_depth: 0,
+        }
+    }
+}
+
+pub struct Compiler {
+    functions: Vec<FunctionState>,
+    classes: Vec<bool>,
+    errors: Vec<Error>,
+    line: usize,
+    interner: Interner,
+}
+
+impl Default for Compiler {
+    fn default() -> Compiler {
+        Compiler::new()
+    }
+}
+
+impl Compiler {
+    pub fn new() -> Compiler {
+        Compiler {
+            functions: vec![FunctionState::new(
+                "script".to_string(),
+                Arity::exactly(0),
+                FunctionKind::Script,
+            )],
+            classes: Vec::new(),
+            errors: Vec::new(),
+            line: 1,
+            interner: Interner::new(),
+        }
+    }
+
+    pub fn compile(mut self, statements: &[Rc<Statement>]) -> Result<Rc<Prototype>, Vec<Error>> {
+        for statement in statements {
+            if let Err(error) = self.statement(statement) {
+                self.errors.push(error);
+            }
+        }
+
+        self.emit_op(OpCode::Nil);
+        self.emit_op(OpCode::Return);
+
+        if !self.errors.is_empty() {
+            return Err(self.errors);
+        }
+
+        let state = self.functions.pop().expect("script function");
+        Ok(Rc::new(state.prototype))
+    }
+
+    fn statement(&mut self, statement: &Statement) -> Result<(), Error> {
+        match statement {
+            Statement::Expression(expression) => {
+                self.expression(expression)?;
+                self.emit_op(OpCode::Pop);
+            }
+            Statement::Print { keyword, value } => {
+                self.line = keyword.line();
+                self.expression(value)?;
+                self.emit_op(OpCode::Print);
+            }
+            Statement::Variable { name, initializer } => {
+                self.line = name.line();
+                match initializer {
+                    Some(initializer) => self.expression(initializer)?,
+                    None => self.emit_op(OpCode::Nil),
+                }
+
+                if self.current().scope_depth > 0 {
+                    self.add_local(name)?;
+                } else {
+                    self.define_global(name)?;
+                }
+            }
+            Statement::Destructure {
+                keyword,
+                pattern,
+                names,
+                initializer,
+            } => {
+                self.line = keyword.line();
+                self.expression(initializer)?;
+                self.destructure(keyword, *pattern, names)?;
+            }
+            Statement::Block(statements) => {
+                self.begin_scope();
+                let result = self.block(statements);
+                self.end_scope();
+                result?;
+            }
+            Statement::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.expression(condition)?;
+                let then_jump = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit_op(OpCode::Pop);
+                self.statement(then_branch)?;
+
+                let else_jump = self.emit_jump(OpCode::Jump);
+                self.patch_jump(then_jump)?;
+                self.emit_op(OpCode::Pop);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch)?;
+                }
+                self.patch_jump(else_jump)?;
+            }
+            Statement::While {
+                condition, body, ..
+            } => {
+                let loop_start = self.chunk().len();
+                self.expression(condition)?;
+
+                let exit_jump = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit_op(OpCode::Pop);
+                self.statement(body)?;
+                self.emit_loop(loop_start)?;
+
+                self.patch_jump(exit_jump)?;
+                self.emit_op(OpCode::Pop);
+            }
+            // The iterator and how far into a list the loop has got stay
+            // behind as locals no name can reach. Each value gets a scope of
+            // its own, so closures in the body capture one apiece.
+            Statement::ForIn {
+                name,
+                iterable,
+                body,
+            } => {
+                self.begin_scope();
+                let result = self.for_in(name, iterable, body);
+                self.end_scope();
+                result?;
+            }
+            Statement::Function(declaration) => {
+                if self.current().scope_depth > 0 {
+                    self.add_local(&declaration.name)?;
+                    self.function(declaration, FunctionKind::Function)?;
+                } else {
+                    self.function(declaration, FunctionKind::Function)?;
+                    self.define_global(&declaration.name)?;
+                }
+            }
+            Statement::Return { keyword, value } => {
+                if self.current().kind == FunctionKind::Script {
+                    return Err(
+                        self.build_error(keyword, "Can't return from top-level code.".to_string())
+                    );
+                }
+
+                if self.current().kind == FunctionKind::Deferred {
+                    return Err(self
+                        .build_error(keyword, "Can't return from a deferred block.".to_string()));
+                }
+
+                self.line = keyword.line();
+                if self.current().kind == FunctionKind::Initializer {
+                    if value.is_some() {
+                        return Err(self.build_error(
+                            keyword,
+                            "Can't return a value from an initializer.".to_string(),
+                        ));
+                    }
+                    self.emit_return();
+                } else {
+                    match value {
+                        Some(value) => self.expression(value)?,
+                        None => self.emit_op(OpCode::Nil),
+                    }
+                    self.line = keyword.line();
+                    self.emit_op(OpCode::Return);
+                }
+            }
+            Statement::Yield { keyword, value } => {
+                match value {
+                    Some(value) => self.expression(value)?,
+                    None => self.emit_op(OpCode::Nil),
+                }
+                self.line = keyword.line();
+                self.emit_op(OpCode::Yield);
+            }
+            Statement::Try {
+                keyword,
+                body,
+                name,
+                handler,
+            } => {
+                self.line = keyword.line();
+                let handler_jump = self.emit_jump(OpCode::Try);
+                self.begin_scope();
+                let result = self.block(body);
+                self.end_scope();
+                result?;
+                self.emit_op(OpCode::EndTry);
+                let exit_jump = self.emit_jump(OpCode::Jump);
+
+                // The VM pushes the caught value where the handler's first
+                // local goes.
+                self.patch_jump(handler_jump)?;
+                self.begin_scope();
+                self.add_local(name)?;
+                let result = self.block(handler);
+                self.end_scope();
+                result?;
+                self.patch_jump(exit_jump)?;
+            }
+            Statement::Throw { keyword, value } => {
+                self.expression(value)?;
+                self.line = keyword.line();
+                self.emit_op(OpCode::Throw);
+            }
+            Statement::Defer { keyword, body } => {
+                if self.current().kind == FunctionKind::Script {
+                    return Err(
+                        self.build_error(keyword, "Can't defer outside of a function.".to_string())
+                    );
+                }
+
+                let name = self.current().prototype.name.clone();
+                self.functions.push(FunctionState::new(
+                    name,
+                    Arity::exactly(0),
+                    FunctionKind::Deferred,
+                ));
+                self.current_mut().scope_depth = 1;
+                let result = self.block(body);
+                self.emit_return();
+
+                let state = self.functions.pop().expect("function state");
+                result?;
+                self.line = keyword.line();
+                self.closure(state)?;
+                self.emit_op(OpCode::Defer);
+            }
+            Statement::Class {
+                name,
+                superclass,
+                methods,
+            } => self.class(name, superclass.as_ref(), methods)?,
+        }
+
+        Ok(())
+    }
+
+    // Unpacks the value on top of the stack into `names`. The value stays
+    // behind as a local no name can reach while they are taken from it; at
+    // the top level it is popped once the globals are defined.
+    fn destructure(
+        &mut self,
+        keyword: &Token,
+        pattern: Pattern,
+        names: &[Token],
+    ) -> Result<(), Error> {
+        let slot = self.current().locals.len();
+        let depth = self.current().scope_depth;
+        if pattern == Pattern::Values {
+            self.emit_op(OpCode::ExpectValues);
+            self.emit_u16(names.len() as u16);
+        }
+        self.add_hidden_local(keyword)?;
+
+        for (position, name) in names.iter().enumerate() {
+            self.line = keyword.line();
+            self.emit_slot_op(OpCode::GetLocal, slot);
+            match pattern {
+                Pattern::Elements | Pattern::Values => {
+                    self.emit_constant(Constant::Number(position as f64))?
+                }
+                Pattern::Fields => {
+                    let constant = self.name_constant(&name.lexeme())?;
+                    self.emit_constant_op(OpCode::Constant, constant);
+                }
+            }
+            self.emit_op(OpCode::Unpack);
+
+            if depth > 0 {
+                self.add_local(name)?;
+            } else {
+                self.define_global(name)?;
+            }
+        }
+
+        if depth == 0 {
+            self.current_mut().locals.pop();
+            self.emit_op(OpCode::Pop);
+        }
+        Ok(())
+    }
+
+    fn for_in(&mut self, name: &Token, iterable: &Expr, body: &Statement) -> Result<(), Error> {
+        self.expression(iterable)?;
+        self.line = name.line();
+        self.emit_op(OpCode::Iterator);
+        self.add_hidden_local(name)?;
+        self.add_hidden_local(name)?;
+
+        let loop_start = self.chunk().len();
+        let exit_jump = self.emit_jump(OpCode::Next);
+        self.begin_scope();
+        self.add_local(name)?;
+        let result = self.statement(body);
+        self.end_scope();
+        result?;
+        self.emit_loop(loop_start)?;
+
+        self.patch_jump(exit_jump)
+    }
+
+    fn block(&mut self, statements: &[Rc<Statement>]) -> Result<(), Error> {
+        for statement in statements {
+            self.statement(statement)?;
+        }
+
+        Ok(())
+    }
+
+    fn class(
+        &mut self,
+        name: &Token,
+        superclass: Option<&Expr>,
+        methods: &[Rc<FunctionDeclaration>],
+    ) -> Result<(), Error> {
+        if methods.len() > MAX_METHODS {
+            return Err(self.build_error(name, "Too many methods in one class.".to_string()));
+        }
+
+        self.line = name.line();
+        let slot = if self.current().scope_depth > 0 {
+            self.emit_op(OpCode::Nil);
+            self.add_local(name)?;
+            Some(self.current().locals.len() - 1)
+        } else {
+            None
+        };
+
+        let mut line = name.line();
+        if let Some(superclass) = superclass {
+            if let ExpressionKind::Variable(superclass) = &superclass.kind {
+                line = superclass.line();
+            }
+
+            self.begin_scope();
+            self.expression(superclass)?;
+            self.add_local(&Token {
+                kind: Kind::Keyword(Keyword::Super),
+                position: name.position,
+            })?;
+        }
+
+        self.classes.push(superclass.is_some());
+        let result = methods.iter().try_for_each(|method| {
+            let kind = if method.name.lexeme() == "init" {
+                FunctionKind::Initializer
+            } else {
+                FunctionKind::Method
+            };
+            self.function(method, kind)
+        });
+        self.classes.pop();
+
+        if superclass.is_some() {
+            let state = self.current_mut();
+            state.locals.pop();
+            state.scope_depth -= 1;
+        }
+        result?;
+
+        self.line = line;
+        let constant = self.name_constant(&name.lexeme())?;
+        self.emit_constant_op(OpCode::Class, constant);
+        self.emit_byte(methods.len() as u8);
+        self.emit_byte(u8::from(superclass.is_some()));
+
+        self.line = name.line();
+        match slot {
+            Some(slot) => {
+                self.emit_slot_op(OpCode::SetLocal, slot);
+                self.emit_op(OpCode::Pop);
+                Ok(())
+            }
+            None => self.define_global(name),
+        }
+    }
+
+    fn function(
+        &mut self,
+        declaration: &FunctionDeclaration,
+        kind: FunctionKind,
+    ) -> Result<(), Error> {
+        let name = &declaration.name;
+        self.functions
+            .push(FunctionState::new(name.lexeme(), declaration.arity(), kind));
+        self.current_mut().scope_depth = 1;
+        self.current_mut().prototype.is_generator = declaration.is_generator;
+
+        let result = declaration
+            .all_parameters()
+            .try_for_each(|parameter| self.add_local(parameter))
+            .and_then(|_| self.block(&declaration.body));
+
+        self.emit_return();
+        let result = result.and_then(|_| self.defaults(declaration));
+
+        let state = self.functions.pop().expect("function state");
+        result?;
+
+        self.line = name.line();
+        self.closure(state)
+    }
+
+    // The code a call that leaves out optional parameters starts in: each
+    // default pushes the value of its parameter's slot, and those left empty
+    // gather into a list for a rest parameter, before it loops back to the
+    // body. A default sees only the parameters before its own.
+    fn defaults(&mut self, declaration: &FunctionDeclaration) -> Result<(), Error> {
+        if declaration.defaults.is_empty() {
+            return Ok(());
+        }
+
+        let required = declaration.arity().required;
+        self.current_mut().locals.truncate(1 + required);
+        for (parameter, default) in declaration.parameters[required..]
+            .iter()
+            .zip(&declaration.defaults)
+        {
+            let start = self.chunk().len();
+            self.current_mut().prototype.defaults.push(start);
+            self.expression(default)?;
+            self.add_local(parameter)?;
+        }
+
+        self.line = declaration.name.line();
+        if declaration.rest.is_some() {
+            self.emit_op(OpCode::BuildList);
+            self.emit_u16(0);
+        }
+        self.emit_loop(0)
+    }
+
+    // Makes a closure of the function just compiled, capturing what it
+    // uses from the enclosing one.
+    fn closure(&mut self, mut state: FunctionState) -> Result<(), Error> {
+        state.prototype.upvalue_count = state.captures.len();
+        let constant = self.make_constant(Constant::Function(Rc::new(state.prototype)))?;
+        self.emit_constant_op(OpCode::Closure, constant);
+
+        for capture in state.captures {
+            self.emit_byte(u8::from(capture.is_local));
+            self.emit_u16(capture.index);
+        }
+
+        Ok(())
+    }
+
+    fn expression(&mut self, expression: &Expr) -> Result<(), Error> {
+        let (operand, links) = expression.chain();
+        self.node(&operand)?;
+        for link in &links {
+            self.node(link)?;
+        }
+        Ok(())
+    }
+
+    // Compiles one node; a link of a chain expects the value it continues
+    // from to be on the stack already.
+    fn node(&mut self, expression: &Expr) -> Result<(), Error> {
+        match &expression.kind {
+            ExpressionKind::Literal(literal) => match literal {
+                Literal::Nil => self.emit_op(OpCode::Nil),
+                Literal::Boolean(true) => self.emit_op(OpCode::True),
+                Literal::Boolean(false) => self.emit_op(OpCode::False),
+                Literal::Number(number) => self.emit_constant(Constant::Number(*number))?,
+                Literal::Integer(integer) => self.emit_constant(Constant::Integer(*integer))?,
+                Literal::String(string) => self.emit_constant(Constant::String(string.clone()))?,
+            },
+            ExpressionKind::Grouping(inner) => self.expression(&expression.get(*inner))?,
+            ExpressionKind::Unary { operator, right } => {
+                self.expression(&expression.get(*right))?;
+                self.line = operator.line();
+                match operator.kind {
+                    Kind::Minus => self.emit_op(OpCode::Negate),
+                    _ => self.emit_op(OpCode::Not),
+                }
+            }
+            ExpressionKind::Binary {
+                operator, right, ..
+            } => {
+                self.expression(&expression.get(*right))?;
+                self.line = operator.line();
+                match operator.kind {
+                    Kind::EqualEqual => self.emit_op(OpCode::Equal),
+                    Kind::ExclamationEqual => {
+                        self.emit_op(OpCode::Equal);
+                        self.emit_op(OpCode::Not);
+                    }
+                    Kind::Greater => self.emit_op(OpCode::Greater),
+                    Kind::GreaterEqual => self.emit_op(OpCode::GreaterEqual),
+                    Kind::Less => self.emit_op(OpCode::Less),
+                    Kind::LessEqual => self.emit_op(OpCode::LessEqual),
+                    Kind::Plus => self.emit_op(OpCode::Add),
+                    Kind::Minus => self.emit_op(OpCode::Subtract),
+                    Kind::Asterisk => self.emit_op(OpCode::Multiply),
+                    Kind::Keyword(Keyword::Div) => self.emit_op(OpCode::FloorDivide),
+                    _ => self.emit_op(OpCode::Divide),
+                }
+            }
+            ExpressionKind::Logical {
+                operator, right, ..
+            } => {
+                self.line = operator.line();
+
+                if operator.kind == Kind::Keyword(Keyword::And) {
+                    let end_jump = self.emit_jump(OpCode::JumpIfFalse);
+                    self.emit_op(OpCode::Pop);
+                    self.expression(&expression.get(*right))?;
+                    self.patch_jump(end_jump)?;
+                } else {
+                    let else_jump = self.emit_jump(OpCode::JumpIfFalse);
+                    let end_jump = self.emit_jump(OpCode::Jump);
+                    self.patch_jump(else_jump)?;
+                    self.emit_op(OpCode::Pop);
+                    self.expression(&expression.get(*right))?;
+                    self.patch_jump(end_jump)?;
+                }
+            }
+            ExpressionKind::Variable(name) => self.named_variable(name, false)?,
+            ExpressionKind::Assign { name, value } => {
+                self.expression(&expression.get(*value))?;
+                self.named_variable(name, true)?;
+            }
+            ExpressionKind::Call {
+                parenthesis,
+                arguments,
+                ..
+            } => {
+                for argument in arguments {
+                    self.expression(&expression.get(*argument))?;
+                }
+                self.line = parenthesis.line();
+                self.emit_op(OpCode::Call);
+                self.emit_byte(arguments.len() as u8);
+            }
+            ExpressionKind::List { bracket, elements } => {
+                for element in elements {
+                    self.expression(&expression.get(*element))?;
+                }
+                self.line = bracket.line();
+                self.emit_op(OpCode::BuildList);
+                self.emit_u16(self.count_operand(bracket, elements.len())?);
+            }
+            ExpressionKind::Map { brace, entries } => {
+                for (key, value) in entries {
+                    self.expression(&expression.get(*key))?;
+                    self.expression(&expression.get(*value))?;
+                }
+                self.line = brace.line();
+                self.emit_op(OpCode::BuildMap);
+                self.emit_u16(self.count_operand(brace, entries.len())?);
+            }
+            ExpressionKind::Index { bracket, index, .. } => {
+                self.expression(&expression.get(*index))?;
+                self.line = bracket.line();
+                self.emit_op(OpCode::Index);
+            }
+            ExpressionKind::SetIndex {
+                object,
+                bracket,
+                index,
+                value,
+            } => {
+                self.expression(&expression.get(*object))?;
+                self.expression(&expression.get(*index))?;
+                self.expression(&expression.get(*value))?;
+                self.line = bracket.line();
+                self.emit_op(OpCode::SetIndex);
+            }
+            ExpressionKind::Get { name, .. } => {
+                self.line = name.line();
+                let constant = self.name_constant(&name.lexeme())?;
+                self.emit_constant_op(OpCode::GetProperty, constant);
+            }
+            ExpressionKind::Set {
+                object,
+                name,
+                value,
+            } => {
+                self.expression(&expression.get(*object))?;
+                self.expression(&expression.get(*value))?;
+                self.line = name.line();
+                let constant = self.name_constant(&name.lexeme())?;
+                self.emit_constant_op(OpCode::SetProperty, constant);
+            }
+            ExpressionKind::This(keyword) => self.named_variable(keyword, false)?,
+            ExpressionKind::Super { keyword, method } => {
+                if self.classes.last() != Some(&true) {
+                    return Err(self.build_error(
+                        keyword,
+                        "Can't use 'super' outside of a subclass method.".to_string(),
+                    ));
+                }
+
+                self.named_variable(
+                    &Token {
+                        kind: Kind::Keyword(Keyword::This),
+                        position: keyword.position,
+                    },
+                    false,
+                )?;
+                self.named_variable(keyword, false)?;
+
+                self.line = method.line();
+                let constant = self.name_constant(&method.lexeme())?;
+                self.emit_constant_op(OpCode::GetSuper, constant);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn named_variable(&mut self, name: &Token, assign: bool) -> Result<(), Error> {
+        self.line = name.line();
+        let lexeme = name.lexeme();
+
+        let function = self.functions.len() - 1;
+        if let Some(slot) = self.resolve_local(function, &lexeme) {
+            let op = if assign {
+                OpCode::SetLocal
+            } else {
+                OpCode::GetLocal
+            };
+            self.emit_slot_op(op, slot);
+            return Ok(());
+        }
+
+        if let Some(index) = self.resolve_upvalue(function, name, &lexeme)? {
+            self.emit_op(if assign {
+                OpCode::SetUpvalue
+            } else {
+                OpCode::GetUpvalue
+            });
+            self.emit_byte(index);
+            return Ok(());
+        }
+
+        let constant = self.name_constant(&lexeme)?;
+        let op = if assign {
+            OpCode::SetGlobal
+        } else {
+            OpCode::GetGlobal
+        };
+        self.emit_constant_op(op, constant);
+
+        Ok(())
+    }
+
+    fn resolve_local(&self, function: usize, lexeme: &str) -> Option<usize> {
+        self.functions[function]
+            .locals
+            .iter()
+            .rposition(|local| local.name == lexeme)
+    }
+
+    fn resolve_upvalue(
+        &mut self,
+        function: usize,
+        name: &Token,
+        lexeme: &str,
+    ) -> Result<Option<u8>, Error> {
+        if function == 0 {
+            return Ok(None);
+        }
+
+        let enclosing = function - 1;
+        if let Some(slot) = self.resolve_local(enclosing, lexeme) {
+            self.functions[enclosing].locals[slot].captured = true;
+            return self
+                .add_capture(function, name, slot as u16, true)
+                .map(Some);
+        }
+
+        match self.resolve_upvalue(enclosing, name, lexeme)? {
+            Some(index) => self
+                .add_capture(function, name, u16::from(index), false)
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn add_capture(
+        &mut self,
+        function: usize,
+        name: &Token,
+        index: u16,
+        is_local: bool,
+    ) -> Result<u8, Error> {
+        let capture = Capture { index, is_local };
+        let captures = &mut self.functions[function].captures;
+        if let Some(position) = captures.iter().position(|existing| *existing == capture) {
+            return Ok(position as u8);
+        }
+
+        if captures.len() >= MAX_UPVALUES {
+            return Err(
+                self.build_error(name, "Too many closure variables in function.".to_string())
+            );
+        }
+
+        captures.push(capture);
+        Ok((captures.len() - 1) as u8)
+    }
+
+    fn define_global(&mut self, name: &Token) -> Result<(), Error> {
+        self.line = name.line();
+        let constant = self.name_constant(&name.lexeme())?;
+        self.emit_constant_op(OpCode::DefineGlobal, constant);
+
+        Ok(())
+    }
+
+    fn add_local(&mut self, name: &Token) -> Result<(), Error> {
+        if self.current().locals.len() >= MAX_LOCALS {
+            return Err(self.build_error(name, "Too many local variables in function.".to_string()));
+        }
+
+        let depth = self.current().scope_depth;
+        self.current_mut().locals.push(Local {
+            name: name.lexeme(),
+            depth,
+            captured: false,
+        });
+
+        Ok(())
+    }
+
+    // A local for a value the compiled code keeps on the stack, which no
+    // name in the source refers to.
+    fn add_hidden_local(&mut self, token: &Token) -> Result<(), Error> {
+        if self.current().locals.len() >= MAX_LOCALS {
+            return Err(
+                self.build_error(token, "Too many local variables in function.".to_string())
+            );
+        }
+
+        let depth = self.current().scope_depth;
+        self.current_mut().locals.push(Local {
+            name: String::new(),
+            depth,
+            captured: false,
+        });
+
+        Ok(())
+    }
+
+    fn begin_scope(&mut self) {
+        self.current_mut().scope_depth += 1;
+    }
+
+    fn end_scope(&mut self) {
+        let state = self.current_mut();
+        state.scope_depth -= 1;
+
+        let depth = state.scope_depth;
+        while let Some(local) = self.current().locals.last() {
+            if local.depth <= depth {
+                break;
+            }
+
+            let op = if local.captured {
+                OpCode::CloseUpvalue
+            } else {
+                OpCode::Pop
+            };
+            self.current_mut().locals.pop();
+            self.emit_op(op);
+        }
+    }
+
+    fn count_operand(&self, token: &Token, count: usize) -> Result<u16, Error> {
+        u16::try_from(count)
+            .map_err(|_| self.build_error(token, "Too many elements in one literal.".to_string()))
+    }
+
+    fn emit_constant(&mut self, constant: Constant) -> Result<(), Error> {
+        let index = self.make_constant(constant)?;
+        self.emit_constant_op(OpCode::Constant, index);
+
+        Ok(())
+    }
+
+    fn make_constant(&mut self, constant: Constant) -> Result<usize, Error> {
+        let index = self.chunk_mut().add_constant(constant);
+        if index >= MAX_CONSTANTS {
+            return Err(Error {
+                message: "Too many constants in one chunk.".to_string(),
+                line: self.line,
+                location: None,
+            });
+        }
+
+        Ok(index)
+    }
+
+    // Names share one string across every function in the script, so the
+    // symbols the VM looks them up by compare by pointer.
+    fn name_constant(&mut self, name: &str) -> Result<usize, Error> {
+        let name = self.interner.intern(name);
+        self.make_constant(Constant::String(name.text().clone()))
+    }
+
+    fn emit_return(&mut self) {
+        if matches!(
+            self.current().kind,
+            FunctionKind::Initializer | FunctionKind::Deferred
+        ) {
+            self.emit_op(OpCode::GetLocal);
+            self.emit_byte(0);
+        } else {
+            self.emit_op(OpCode::Nil);
+        }
+
+        self.emit_op(OpCode::Return);
+    }
+
+    fn emit_jump(&mut self, op: OpCode) -> usize {
+        self.emit_op(op);
+        self.emit_u16(u16::MAX);
+        self.chunk().len() - 2
+    }
+
+    fn patch_jump(&mut self, offset: usize) -> Result<(), Error> {
+        let jump = self.chunk().len() - offset - 2;
+        let jump = u16::try_from(jump).map_err(|_| Error {
+            message: "Too much code to jump over.".to_string(),
+            line: self.line,
+            location: None,
+        })?;
+
+        self.chunk_mut().patch_u16(offset, jump);
+        Ok(())
+    }
+
+    fn emit_loop(&mut self, loop_start: usize) -> Result<(), Error> {
+        self.emit_op(OpCode::Loop);
+
+        let offset = self.chunk().len() - loop_start + 2;
+        let offset = u16::try_from(offset).map_err(|_| Error {
+            message: "Loop body too large.".to_string(),
+            line: self.line,
+            location: None,
+        })?;
+
+        self.emit_u16(offset);
+        Ok(())
+    }
+
+    fn emit_op(&mut self, op: OpCode) {
+        let line = self.line;
+        self.chunk_mut().write_op(op, line);
+    }
+
+    fn emit_byte(&mut self, byte: u8) {
+        let line = self.line;
+        self.chunk_mut().write(byte, line);
+    }
+
+    fn emit_u16(&mut self, value: u16) {
+        let line = self.line;
+        self.chunk_mut().write_u16(value, line);
+    }
+
+    fn emit_constant_op(&mut self, op: OpCode, index: usize) {
+        match u8::try_from(index) {
+            Ok(index) => {
+                self.emit_op(op);
+                self.emit_byte(index);
+            }
+            Err(_) => {
+                let line = self.line;
+                self.emit_op(op.long());
+                self.chunk_mut().write_u24(index as u32, line);
+            }
+        }
+    }
+
+    fn emit_slot_op(&mut self, op: OpCode, slot: usize) {
+        match u8::try_from(slot) {
+            Ok(slot) => {
+                self.emit_op(op);
+                self.emit_byte(slot);
+            }
+            Err(_) => {
+                self.emit_op(op.long());
+                self.emit_u16(slot as u16);
+            }
+        }
+    }
+
+    fn current(&self) -> &FunctionState {
+        self.functions.last().expect("function state")
+    }
+
+    fn current_mut(&mut self) -> &mut FunctionState {
+        self.functions.last_mut().expect("function state")
+    }
+
+    fn chunk(&self) -> &Chunk {
+        &self.current().prototype.chunk
+    }
+
+    fn chunk_mut(&mut self) -> &mut Chunk {
+        &mut self.current_mut().prototype.chunk
+    }
+
+    fn build_error(&self, token: &Token, message: String) -> Error {
+        Error {
+            message,
+            line: token.line(),
+            location: None,
+        }
+    }
+}
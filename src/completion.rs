@@ -0,0 +1,638 @@
+use core::iter;
+
+use crate::environment::Environment;
+use crate::native;
+use crate::prelude::*;
+use crate::symbol::Symbol;
+use crate::syntax::{NodeKind, SyntaxElement, SyntaxNode, SyntaxToken, TokenKind};
+use crate::token::{Keyword, Kind};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completion {
+    pub label: String,
+    pub kind: CompletionKind,
+    // How the name is declared, e.g. "fun add(a, b)"; empty for keywords.
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompletionKind {
+    Keyword,
+    Variable,
+    Parameter,
+    Function,
+    Class,
+    Method,
+    Field,
+}
+
+// What the grammar allows at the cursor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Expected {
+    Statement,
+    Expression,
+    // After a complete operand: only a keyword operator.
+    Operator,
+    // A superclass.
+    Class,
+    // A name being declared, or punctuation.
+    Nothing,
+}
+
+const STATEMENT_KEYWORDS: [Keyword; 10] = [
+    Keyword::Class,
+    Keyword::Defer,
+    Keyword::For,
+    Keyword::Fun,
+    Keyword::If,
+    Keyword::Print,
+    Keyword::Throw,
+    Keyword::Try,
+    Keyword::Var,
+    Keyword::While,
+];
+
+const EXPRESSION_KEYWORDS: [Keyword; 3] = [Keyword::False, Keyword::Nil, Keyword::True];
+
+// A name in scope, and the declaration it comes from; natives have none.
+struct Name {
+    completion: Completion,
+    declaration: Option<SyntaxNode>,
+}
+
+// Superclass chains are followed this far, in case one loops.
+const MAX_SUPERCLASSES: usize = 32;
+
+// What can be typed at `offset` in the tree rooted at `root`, completing the
+// name or keyword the cursor is at the end of. Worked out from the syntax
+// tree alone, so a source that doesn't parse yet still gets completions.
+pub(crate) fn complete(root: &SyntaxNode, offset: usize) -> Vec<Completion> {
+    let tokens = root.tokens();
+    let at = tokens
+        .iter()
+        .find(|token| token.range().start < offset && offset <= token.range().end);
+    let (prefix, start) = match at.map(|token| (token, token.kind())) {
+        Some((token, TokenKind::Code(Kind::Identifier(_) | Kind::Keyword(_)))) => {
+            let typed = offset - token.range().start;
+            let prefix: String = token.text().chars().take(typed).collect();
+            (prefix, token.range().start)
+        }
+        Some((_, TokenKind::Comment | TokenKind::Code(Kind::String(_)))) => return Vec::new(),
+        _ => (String::new(), offset),
+    };
+    let previous = tokens
+        .iter()
+        .rev()
+        .find(|token| !token.kind().is_trivia() && token.range().end <= start);
+
+    let context = match previous {
+        // The block just closed is out of scope.
+        Some(token) if *token.kind() == TokenKind::Code(Kind::CloseCurlyBracket) => {
+            let parent = token.parent();
+            parent.parent().unwrap_or(parent)
+        }
+        Some(token) => token.parent(),
+        None => root.clone(),
+    };
+    let names = visible(&context, offset);
+
+    let mut completions =
+        match previous {
+            Some(dot) if *dot.kind() == TokenKind::Code(Kind::Dot) => {
+                let parent = dot.parent();
+                let class = if parent.kind() == NodeKind::Super {
+                    enclosing_class(&parent).and_then(|class| superclass(&class, &names))
+                } else {
+                    parent
+                        .children()
+                        .first()
+                        .and_then(|receiver| class_of(receiver, &names))
+                };
+                class.map_or_else(Vec::new, |class| members(&class, &names))
+            }
+            _ => {
+                let expected = expected(previous);
+                let mut completions: Vec<Completion> = match expected {
+                    Expected::Statement | Expected::Expression => {
+                        names.into_iter().map(|name| name.completion).collect()
+                    }
+                    // Not the class being declared: it can't inherit from
+                    // itself.
+                    Expected::Class => names
+                        .into_iter()
+                        .filter(|name| {
+                            name.completion.kind == CompletionKind::Class
+                                && name.declaration.as_ref() != Some(&context)
+                        })
+                        .map(|name| name.completion)
+                        .collect(),
+                    Expected::Operator | Expected::Nothing => Vec::new(),
+                };
+                completions.extend(keywords(expected, &context, previous).into_iter().map(
+                    |keyword| Completion {
+                        label: keyword.to_string(),
+                        kind: CompletionKind::Keyword,
+                        detail: String::new(),
+                    },
+                ));
+                completions
+            }
+        };
+
+    completions.retain(|completion| completion.label.starts_with(&prefix));
+    completions.sort_by(|a, b| a.label.cmp(&b.label));
+    completions
+}
+
+fn expected(previous: Option<&SyntaxToken>) -> Expected {
+    let Some(previous) = previous else {
+        return Expected::Statement;
+    };
+    let TokenKind::Code(kind) = previous.kind() else {
+        return Expected::Expression;
+    };
+    let parent = previous.parent();
+    match kind {
+        Kind::Semicolon if in_for_header(&parent) => Expected::Expression,
+        Kind::Semicolon => Expected::Statement,
+        Kind::OpenCurlyBracket => match parent.kind() {
+            NodeKind::ClassDeclaration => Expected::Nothing,
+            NodeKind::Map => Expected::Expression,
+            _ => Expected::Statement,
+        },
+        Kind::CloseCurlyBracket => match parent.kind() {
+            NodeKind::Map => Expected::Operator,
+            // A method's body closes inside the class body.
+            NodeKind::Block if parent.parent().is_some_and(is_method) => Expected::Nothing,
+            _ => Expected::Statement,
+        },
+        Kind::CloseParenthesis => match parent.kind() {
+            NodeKind::IfStatement
+            | NodeKind::WhileStatement
+            | NodeKind::ForStatement
+            | NodeKind::ForInStatement => Expected::Statement,
+            NodeKind::ParameterList | NodeKind::TryStatement => Expected::Nothing,
+            _ => Expected::Operator,
+        },
+        Kind::OpenParenthesis | Kind::Comma if parent.kind() == NodeKind::ParameterList => {
+            Expected::Nothing
+        }
+        Kind::Less if parent.kind() == NodeKind::ClassDeclaration => Expected::Class,
+        Kind::Identifier(_) => match parent.kind() {
+            NodeKind::VarDeclaration
+            | NodeKind::FunctionDeclaration
+            | NodeKind::ClassDeclaration
+            | NodeKind::ParameterList
+            | NodeKind::ForInStatement
+            | NodeKind::TryStatement => Expected::Nothing,
+            _ => Expected::Operator,
+        },
+        Kind::Number(_) | Kind::Integer(_) | Kind::String(_) | Kind::CloseSquareBracket => {
+            Expected::Operator
+        }
+        Kind::Keyword(keyword) => match keyword {
+            Keyword::Else => Expected::Statement,
+            Keyword::And
+            | Keyword::Div
+            | Keyword::Or
+            | Keyword::In
+            | Keyword::Print
+            | Keyword::Return
+            | Keyword::Throw
+            | Keyword::Yield => Expected::Expression,
+            Keyword::True | Keyword::False | Keyword::Nil | Keyword::This => Expected::Operator,
+            Keyword::Class
+            | Keyword::Fun
+            | Keyword::Var
+            | Keyword::For
+            | Keyword::If
+            | Keyword::While
+            | Keyword::Try
+            | Keyword::Catch
+            | Keyword::Defer
+            | Keyword::Super => Expected::Nothing,
+        },
+        _ => Expected::Expression,
+    }
+}
+
+fn keywords(
+    expected: Expected,
+    context: &SyntaxNode,
+    previous: Option<&SyntaxToken>,
+) -> Vec<Keyword> {
+    let mut keywords = Vec::new();
+    match expected {
+        Expected::Statement => {
+            keywords.extend(STATEMENT_KEYWORDS);
+            if iter::once(context.clone())
+                .chain(context.ancestors())
+                .any(|node| node.kind() == NodeKind::FunctionDeclaration)
+            {
+                keywords.extend([Keyword::Return, Keyword::Yield]);
+            }
+            if previous.is_some_and(ends_if_without_else) {
+                keywords.push(Keyword::Else);
+            }
+        }
+        Expected::Operator => keywords.extend([Keyword::And, Keyword::Or]),
+        Expected::Expression | Expected::Class | Expected::Nothing => {}
+    }
+
+    if matches!(expected, Expected::Statement | Expected::Expression) {
+        keywords.extend(EXPRESSION_KEYWORDS);
+        if let Some(class) = enclosing_class(context) {
+            keywords.push(Keyword::This);
+            if child_tokens(&class).any(|token| *token.kind() == TokenKind::Code(Kind::Less)) {
+                keywords.push(Keyword::Super);
+            }
+        }
+    }
+    keywords
+}
+
+// Whether `node` is a clause of a for loop's header rather than its body.
+fn in_for_header(node: &SyntaxNode) -> bool {
+    if node.kind() == NodeKind::ForStatement {
+        return true;
+    }
+    node.parent().is_some_and(|parent| {
+        parent.kind() == NodeKind::ForStatement
+            && !child_tokens(&parent).any(|token| {
+                *token.kind() == TokenKind::Code(Kind::CloseParenthesis)
+                    && token.range().start < node.range().start
+            })
+    })
+}
+
+fn ends_if_without_else(previous: &SyntaxToken) -> bool {
+    let end = previous.range().end;
+    let parent = previous.parent();
+    iter::once(parent.clone())
+        .chain(parent.ancestors())
+        .take_while(|node| node.range().end == end)
+        .any(|node| {
+            node.kind() == NodeKind::IfStatement
+                && !child_tokens(&node)
+                    .any(|token| *token.kind() == TokenKind::Code(Kind::Keyword(Keyword::Else)))
+        })
+}
+
+fn is_method(node: SyntaxNode) -> bool {
+    node.kind() == NodeKind::FunctionDeclaration
+        && node
+            .parent()
+            .is_some_and(|parent| parent.kind() == NodeKind::ClassDeclaration)
+}
+
+// The class of the method `node` is in, if it is in one.
+fn enclosing_class(node: &SyntaxNode) -> Option<SyntaxNode> {
+    iter::once(node.clone())
+        .chain(node.ancestors())
+        .find(|node| is_method(node.clone()))
+        .and_then(|method| method.parent())
+}
+
+// The names in scope at `offset`, inside `context`: innermost first, then
+// the globals and the natives.
+fn visible(context: &SyntaxNode, offset: usize) -> Vec<Name> {
+    let mut names = Vec::new();
+    let mut from: Option<SyntaxNode> = None;
+    let mut node = Some(context.clone());
+    while let Some(current) = node {
+        let declared = |child: &SyntaxNode| {
+            // A variable isn't in scope in its own initializer.
+            child.kind() != NodeKind::VarDeclaration
+                || from.as_ref() != Some(child)
+                || ends_with_semicolon(child, offset)
+        };
+        match current.kind() {
+            NodeKind::Program => names.extend(
+                current
+                    .children()
+                    .into_iter()
+                    .filter(|child| declared(child))
+                    .flat_map(|child| declarations(&child)),
+            ),
+            NodeKind::Block => names.extend(
+                current
+                    .children()
+                    .into_iter()
+                    .filter(|child| child.range().start < offset && declared(child))
+                    .flat_map(|child| declarations(&child)),
+            ),
+            NodeKind::FunctionDeclaration
+                if from
+                    .as_ref()
+                    .is_some_and(|from| from.kind() == NodeKind::Block) =>
+            {
+                let parameters = current
+                    .children()
+                    .into_iter()
+                    .find(|child| child.kind() == NodeKind::ParameterList);
+                names.extend(
+                    parameters
+                        .iter()
+                        .flat_map(identifiers)
+                        .map(|parameter| Name {
+                            completion: Completion {
+                                detail: format!("parameter {}", parameter),
+                                label: parameter,
+                                kind: CompletionKind::Parameter,
+                            },
+                            declaration: None,
+                        }),
+                );
+            }
+            NodeKind::ForStatement => names.extend(
+                current
+                    .children()
+                    .into_iter()
+                    .filter(|child| {
+                        child.kind() == NodeKind::VarDeclaration
+                            && (from.as_ref() != Some(child) || ends_with_semicolon(child, offset))
+                    })
+                    .flat_map(|child| declarations(&child)),
+            ),
+            NodeKind::ForInStatement => {
+                let body_started = child_tokens(&current).any(|token| {
+                    *token.kind() == TokenKind::Code(Kind::CloseParenthesis)
+                        && token.range().end <= offset
+                });
+                if body_started {
+                    names.extend(identifiers(&current).into_iter().take(1).map(|name| Name {
+                        completion: Completion {
+                            detail: format!("var {}", name),
+                            label: name,
+                            kind: CompletionKind::Variable,
+                        },
+                        declaration: None,
+                    }));
+                }
+            }
+            // The caught error is in scope in the catch block.
+            NodeKind::TryStatement => {
+                let handler_started = child_tokens(&current).any(|token| {
+                    *token.kind() == TokenKind::Code(Kind::CloseParenthesis)
+                        && token.range().end <= offset
+                });
+                if handler_started {
+                    names.extend(identifiers(&current).into_iter().take(1).map(|name| Name {
+                        completion: Completion {
+                            detail: format!("var {}", name),
+                            label: name,
+                            kind: CompletionKind::Variable,
+                        },
+                        declaration: None,
+                    }));
+                }
+            }
+            _ => {}
+        }
+        node = current.parent();
+        from = Some(current);
+    }
+
+    let mut globals = Environment::new();
+    native::define_natives(&mut globals);
+    let mut natives: Vec<&Symbol> = globals.values().keys().collect();
+    natives.sort();
+    names.extend(natives.into_iter().map(|native| Name {
+        completion: Completion {
+            label: native.to_string(),
+            kind: CompletionKind::Function,
+            detail: format!("native {}", native),
+        },
+        declaration: None,
+    }));
+
+    // Inner declarations shadow outer ones.
+    let mut seen = Vec::new();
+    names.retain(|name| {
+        let fresh = !seen.contains(&name.completion.label);
+        seen.push(name.completion.label.clone());
+        fresh
+    });
+    names
+}
+
+fn ends_with_semicolon(node: &SyntaxNode, offset: usize) -> bool {
+    node.range().end <= offset
+        && child_tokens(node)
+            .last()
+            .is_some_and(|token| *token.kind() == TokenKind::Code(Kind::Semicolon))
+}
+
+// The names a declaration introduces: several for a destructuring one.
+fn declarations(node: &SyntaxNode) -> Vec<Name> {
+    if !is_destructuring(node) {
+        return declaration(node).into_iter().collect();
+    }
+    identifiers(node)
+        .into_iter()
+        .map(|name| Name {
+            completion: Completion {
+                detail: format!("var {}", name),
+                label: name,
+                kind: CompletionKind::Variable,
+            },
+            declaration: Some(node.clone()),
+        })
+        .collect()
+}
+
+// `var [a, b] = value;`, `var {x, y} = value;` or `var a, b = value;`.
+fn is_destructuring(node: &SyntaxNode) -> bool {
+    if node.kind() != NodeKind::VarDeclaration {
+        return false;
+    }
+    let kinds: Vec<TokenKind> = child_tokens(node)
+        .map(|token| token.kind().clone())
+        .filter(|kind| matches!(kind, TokenKind::Code(_)))
+        .take(3)
+        .collect();
+    matches!(
+        kinds.as_slice(),
+        [
+            _,
+            TokenKind::Code(Kind::OpenSquareBracket | Kind::OpenCurlyBracket),
+            ..
+        ] | [_, _, TokenKind::Code(Kind::Comma)]
+    )
+}
+
+// The name a variable, function or class declaration introduces.
+fn declaration(node: &SyntaxNode) -> Option<Name> {
+    let name = identifiers(node).into_iter().next()?;
+    let (kind, detail) = match node.kind() {
+        NodeKind::VarDeclaration => (CompletionKind::Variable, format!("var {}", name)),
+        NodeKind::FunctionDeclaration => (
+            CompletionKind::Function,
+            format!("fun {}", signature(node, &name)),
+        ),
+        NodeKind::ClassDeclaration => {
+            let mut detail = format!("class {}", name);
+            if let Some(superclass) = superclass_name(node) {
+                detail.push_str(&format!(" < {}", superclass));
+            }
+            (CompletionKind::Class, detail)
+        }
+        _ => return None,
+    };
+    Some(Name {
+        completion: Completion {
+            label: name,
+            kind,
+            detail,
+        },
+        declaration: Some(node.clone()),
+    })
+}
+
+fn signature(function: &SyntaxNode, name: &str) -> String {
+    let parameters = function
+        .children()
+        .into_iter()
+        .find(|child| child.kind() == NodeKind::ParameterList)
+        .map(|parameters| {
+            let rest = child_tokens(&parameters)
+                .any(|token| *token.kind() == TokenKind::Code(Kind::Ellipsis));
+            let mut names = identifiers(&parameters);
+            if let Some(last) = names.last_mut().filter(|_| rest) {
+                last.insert_str(0, "...");
+            }
+            names
+        })
+        .unwrap_or_default();
+    format!("{}({})", name, parameters.join(", "))
+}
+
+// The class instances of `receiver` are known to belong to.
+fn class_of(receiver: &SyntaxNode, names: &[Name]) -> Option<SyntaxNode> {
+    match receiver.kind() {
+        NodeKind::This => enclosing_class(receiver),
+        NodeKind::Grouping => receiver
+            .children()
+            .first()
+            .and_then(|inner| class_of(inner, names)),
+        // Calling a class makes an instance of it.
+        NodeKind::Call => {
+            let callee = receiver.children().into_iter().next()?;
+            let declaration = lookup(&callee, names)?;
+            (declaration.kind() == NodeKind::ClassDeclaration).then_some(declaration)
+        }
+        // A variable holds what it was initialized with, as far as is known.
+        NodeKind::Variable => {
+            let declaration = lookup(receiver, names)?;
+            if declaration.kind() != NodeKind::VarDeclaration || is_destructuring(&declaration) {
+                return None;
+            }
+            let initializer = declaration.children().into_iter().next()?;
+            class_of(&initializer, names)
+        }
+        _ => None,
+    }
+}
+
+// The declaration the variable `node` names.
+fn lookup(node: &SyntaxNode, names: &[Name]) -> Option<SyntaxNode> {
+    if node.kind() != NodeKind::Variable {
+        return None;
+    }
+    let name = identifiers(node).into_iter().next()?;
+    names
+        .iter()
+        .find(|candidate| candidate.completion.label == name)
+        .and_then(|candidate| candidate.declaration.clone())
+}
+
+fn superclass_name(class: &SyntaxNode) -> Option<String> {
+    class
+        .children()
+        .into_iter()
+        .find(|child| child.kind() == NodeKind::Variable)
+        .and_then(|variable| identifiers(&variable).into_iter().next())
+}
+
+fn superclass(class: &SyntaxNode, names: &[Name]) -> Option<SyntaxNode> {
+    let superclass = class
+        .children()
+        .into_iter()
+        .find(|child| child.kind() == NodeKind::Variable)?;
+    lookup(&superclass, names)
+        .filter(|declaration| declaration.kind() == NodeKind::ClassDeclaration)
+}
+
+// The methods of `class` and the fields its methods set on `this`, then
+// those it inherits.
+fn members(class: &SyntaxNode, names: &[Name]) -> Vec<Completion> {
+    let mut members: Vec<Completion> = Vec::new();
+    let mut class = Some(class.clone());
+    for _ in 0..MAX_SUPERCLASSES {
+        let Some(current) = class else {
+            break;
+        };
+        let class_name = identifiers(&current).into_iter().next().unwrap_or_default();
+        for method in current.children() {
+            if method.kind() != NodeKind::FunctionDeclaration {
+                continue;
+            }
+            if let Some(name) = identifiers(&method).into_iter().next() {
+                members.push(Completion {
+                    detail: format!("{}.{}", class_name, signature(&method, &name)),
+                    label: name,
+                    kind: CompletionKind::Method,
+                });
+            }
+            for assignment in method.descendants() {
+                if let Some(field) = field_set(&assignment) {
+                    members.push(Completion {
+                        detail: format!("{}.{}", class_name, field),
+                        label: field,
+                        kind: CompletionKind::Field,
+                    });
+                }
+            }
+        }
+        class = superclass(&current, names);
+    }
+
+    let mut seen = Vec::new();
+    members.retain(|member| {
+        let fresh = !seen.contains(&member.label);
+        seen.push(member.label.clone());
+        fresh
+    });
+    members
+}
+
+// The field `this.name = ...` sets.
+fn field_set(node: &SyntaxNode) -> Option<String> {
+    if node.kind() != NodeKind::Assign {
+        return None;
+    }
+    let target = node.children().into_iter().next()?;
+    if target.kind() != NodeKind::Get || target.children().first()?.kind() != NodeKind::This {
+        return None;
+    }
+    identifiers(&target).into_iter().next()
+}
+
+fn child_tokens(node: &SyntaxNode) -> impl Iterator<Item = SyntaxToken> {
+    node.children_with_tokens()
+        .into_iter()
+        .filter_map(|element| match element {
+            SyntaxElement::Token(token) => Some(token),
+            SyntaxElement::Node(_) => None,
+        })
+}
+
+// The names among the tokens directly below `node`.
+fn identifiers(node: &SyntaxNode) -> Vec<String> {
+    child_tokens(node)
+        .filter_map(|token| match token.kind() {
+            TokenKind::Code(Kind::Identifier(name)) => Some(name.clone()),
+            _ => None,
+        })
+        .collect()
+}
@@ -0,0 +1,218 @@
+use alloc::collections::BTreeMap;
+use core::fmt;
+
+use crate::prelude::*;
+use crate::value::Value;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConversionError {
+    pub expected: &'static str,
+    pub found: &'static str,
+}
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "Expected {} but got {}.",
+            self.expected, self.found
+        )
+    }
+}
+
+pub trait IntoLox {
+    fn into_lox(self) -> Value;
+}
+
+pub trait FromLox: Sized {
+    fn from_lox(value: &Value) -> Result<Self, ConversionError>;
+}
+
+fn mismatch<T>(expected: &'static str, value: &Value) -> Result<T, ConversionError> {
+    Err(ConversionError {
+        expected,
+        found: value.type_name(),
+    })
+}
+
+impl IntoLox for Value {
+    fn into_lox(self) -> Value {
+        self
+    }
+}
+
+impl FromLox for Value {
+    fn from_lox(value: &Value) -> Result<Value, ConversionError> {
+        Ok(value.clone())
+    }
+}
+
+impl IntoLox for () {
+    fn into_lox(self) -> Value {
+        Value::Nil
+    }
+}
+
+impl IntoLox for f64 {
+    fn into_lox(self) -> Value {
+        Value::Number(self)
+    }
+}
+
+impl FromLox for f64 {
+    fn from_lox(value: &Value) -> Result<f64, ConversionError> {
+        match value {
+            Value::Number(number) => Ok(*number),
+            Value::Int(integer) => Ok(*integer as f64),
+            _ => mismatch("number", value),
+        }
+    }
+}
+
+impl IntoLox for bool {
+    fn into_lox(self) -> Value {
+        Value::Boolean(self)
+    }
+}
+
+impl FromLox for bool {
+    fn from_lox(value: &Value) -> Result<bool, ConversionError> {
+        match value {
+            Value::Boolean(boolean) => Ok(*boolean),
+            _ => mismatch("bool", value),
+        }
+    }
+}
+
+impl IntoLox for String {
+    fn into_lox(self) -> Value {
+        Value::String(self.into())
+    }
+}
+
+impl IntoLox for &str {
+    fn into_lox(self) -> Value {
+        Value::String(self.into())
+    }
+}
+
+impl FromLox for String {
+    fn from_lox(value: &Value) -> Result<String, ConversionError> {
+        match value {
+            Value::String(string) => Ok(string.to_string()),
+            _ => mismatch("string", value),
+        }
+    }
+}
+
+impl<T: IntoLox> IntoLox for Option<T> {
+    fn into_lox(self) -> Value {
+        self.map_or(Value::Nil, IntoLox::into_lox)
+    }
+}
+
+impl<T: FromLox> FromLox for Option<T> {
+    fn from_lox(value: &Value) -> Result<Option<T>, ConversionError> {
+        match value {
+            Value::Nil => Ok(None),
+            _ => T::from_lox(value).map(Some),
+        }
+    }
+}
+
+impl<T: IntoLox> IntoLox for Vec<T> {
+    fn into_lox(self) -> Value {
+        Value::from(
+            self.into_iter()
+                .map(IntoLox::into_lox)
+                .collect::<Vec<Value>>(),
+        )
+    }
+}
+
+impl<T: FromLox> FromLox for Vec<T> {
+    fn from_lox(value: &Value) -> Result<Vec<T>, ConversionError> {
+        match value {
+            Value::List(list) => list.borrow().iter().map(T::from_lox).collect(),
+            _ => mismatch("list", value),
+        }
+    }
+}
+
+impl<T: IntoLox> IntoLox for HashMap<String, T> {
+    fn into_lox(self) -> Value {
+        Value::from(
+            self.into_iter()
+                .map(|(key, value)| (key, value.into_lox()))
+                .collect::<BTreeMap<String, Value>>(),
+        )
+    }
+}
+
+impl<T: FromLox> FromLox for HashMap<String, T> {
+    fn from_lox(value: &Value) -> Result<HashMap<String, T>, ConversionError> {
+        match value {
+            Value::Map(map) => map
+                .borrow()
+                .iter()
+                .map(|(key, value)| Ok((key.clone(), T::from_lox(value)?)))
+                .collect(),
+            _ => mismatch("map", value),
+        }
+    }
+}
+
+impl From<f64> for Value {
+    fn from(number: f64) -> Value {
+        number.into_lox()
+    }
+}
+
+impl From<bool> for Value {
+    fn from(boolean: bool) -> Value {
+        boolean.into_lox()
+    }
+}
+
+impl From<String> for Value {
+    fn from(string: String) -> Value {
+        string.into_lox()
+    }
+}
+
+impl From<&str> for Value {
+    fn from(string: &str) -> Value {
+        string.into_lox()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: IntoLox> From<HashMap<String, T>> for Value {
+    fn from(map: HashMap<String, T>) -> Value {
+        map.into_lox()
+    }
+}
+
+impl TryFrom<Value> for f64 {
+    type Error = ConversionError;
+
+    fn try_from(value: Value) -> Result<f64, ConversionError> {
+        f64::from_lox(&value)
+    }
+}
+
+impl TryFrom<Value> for bool {
+    type Error = ConversionError;
+
+    fn try_from(value: Value) -> Result<bool, ConversionError> {
+        bool::from_lox(&value)
+    }
+}
+
+impl TryFrom<Value> for String {
+    type Error = ConversionError;
+
+    fn try_from(value: Value) -> Result<String, ConversionError> {
+        String::from_lox(&value)
+    }
+}
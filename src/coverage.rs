@@ -0,0 +1,143 @@
+use alloc::collections::BTreeMap;
+use core::fmt::Write;
+
+use crate::prelude::*;
+use crate::statement::{FunctionDeclaration, Statement};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Lcov,
+    Text,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Option<Format> {
+        match name {
+            "lcov" => Some(Format::Lcov),
+            "text" => Some(Format::Text),
+            _ => None,
+        }
+    }
+}
+
+// Hit counts for every line that starts a statement. Lines are registered
+// from the syntax tree before running, so statements that never execute
+// show up with a count of zero.
+#[derive(Debug, Clone, Default)]
+pub struct Coverage {
+    lines: BTreeMap<usize, u64>,
+}
+
+impl Coverage {
+    pub fn new() -> Coverage {
+        Coverage::default()
+    }
+
+    pub fn add(&mut self, statements: &[Rc<Statement>]) {
+        for statement in statements {
+            self.add_statement(statement);
+        }
+    }
+
+    fn add_statement(&mut self, statement: &Statement) {
+        if let Some(line) = statement.line() {
+            self.lines.entry(line).or_insert(0);
+        }
+
+        match statement {
+            Statement::Block(statements) => self.add(statements),
+            Statement::If {
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.add_statement(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.add_statement(else_branch);
+                }
+            }
+            Statement::While { body, .. } | Statement::ForIn { body, .. } => {
+                self.add_statement(body)
+            }
+            Statement::Function(declaration) => self.add_function(declaration),
+            Statement::Try { body, handler, .. } => {
+                self.add(body);
+                self.add(handler);
+            }
+            Statement::Defer { body, .. } => self.add(body),
+            Statement::Class { methods, .. } => {
+                for method in methods {
+                    self.add_function(method);
+                }
+            }
+            Statement::Expression(_)
+            | Statement::Print { .. }
+            | Statement::Variable { .. }
+            | Statement::Destructure { .. }
+            | Statement::Return { .. }
+            | Statement::Yield { .. }
+            | Statement::Throw { .. } => {}
+        }
+    }
+
+    fn add_function(&mut self, declaration: &FunctionDeclaration) {
+        self.add(&declaration.body);
+    }
+
+    // Lines that start no statement are ignored.
+    pub fn hit(&mut self, line: usize) {
+        if let Some(count) = self.lines.get_mut(&line) {
+            *count += 1;
+        }
+    }
+
+    pub fn lines(&self) -> &BTreeMap<usize, u64> {
+        &self.lines
+    }
+
+    pub fn covered(&self) -> usize {
+        self.lines.values().filter(|&&count| count > 0).count()
+    }
+
+    pub fn report(&self, format: Format, path: &str, source: &str) -> String {
+        match format {
+            Format::Lcov => self.lcov(path),
+            Format::Text => self.text(source),
+        }
+    }
+
+    pub fn lcov(&self, path: &str) -> String {
+        let mut report = format!("TN:\nSF:{}\n", path);
+        for (line, count) in &self.lines {
+            let _ = writeln!(report, "DA:{},{}", line, count);
+        }
+        let _ = writeln!(report, "LF:{}", self.lines.len());
+        let _ = writeln!(report, "LH:{}", self.covered());
+        report.push_str("end_of_record\n");
+        report
+    }
+
+    // A gcov-style listing: the hit count, "#####" for statements that never
+    // ran and "-" for lines that start no statement.
+    pub fn text(&self, source: &str) -> String {
+        let mut report = String::new();
+        for (index, text) in source.lines().enumerate() {
+            let line = index + 1;
+            let count = match self.lines.get(&line) {
+                Some(0) => "#####".to_string(),
+                Some(count) => count.to_string(),
+                None => "-".to_string(),
+            };
+            let _ = writeln!(report, "{:>9}:{:>5}:{}", count, line, text);
+        }
+
+        let total = self.lines.len();
+        let percent = if total == 0 {
+            100.0
+        } else {
+            self.covered() as f64 * 100.0 / total as f64
+        };
+        let _ = writeln!(report, "Lines executed: {:.2}% of {}", percent, total);
+        report
+    }
+}
@@ -0,0 +1,640 @@
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::coverage::Coverage;
+use crate::debugger::{self, DebugHook, Mode};
+use crate::error::{ErrorCategory, LoxError, RuntimeError, RuntimeErrorKind};
+use crate::interpreter::Interpreter;
+use crate::io::{HostIo, IoResult, StdIo};
+use crate::json::Json;
+use crate::parser::Parser;
+use crate::rpc::{object, string, Transport};
+use crate::scanner::Scanner;
+use crate::statement::Statement;
+use crate::value::Value;
+use crate::Lox;
+
+// The adapter runs a single thread; Lox has no others.
+const THREAD_ID: f64 = 1.0;
+
+// Reported in the "exited" event, matching the exit codes of `lox run`.
+const EXIT_DATA: i32 = 65;
+const EXIT_SOFTWARE: i32 = 70;
+
+const LOCALS_REFERENCE: usize = 1;
+const GLOBALS_REFERENCE: usize = 2;
+const FIRST_VALUE_REFERENCE: usize = 3;
+
+// A DAP session's transport, numbering the messages it sends.
+struct Connection {
+    transport: Transport,
+    seq: u64,
+    closed: bool,
+}
+
+impl Connection {
+    fn new(reader: impl BufRead + 'static, writer: impl Write + 'static) -> Connection {
+        Connection {
+            transport: Transport::new(reader, writer),
+            seq: 0,
+            closed: false,
+        }
+    }
+
+    fn receive(&mut self) -> io::Result<Option<Json>> {
+        self.transport.receive()
+    }
+
+    fn send(&mut self, kind: &str, fields: Vec<(&str, Json)>) -> io::Result<()> {
+        self.seq += 1;
+        let mut message = vec![
+            ("seq", Json::Number(self.seq as f64)),
+            ("type", string(kind)),
+        ];
+        message.extend(fields);
+
+        self.transport.send(&object(message))
+    }
+
+    fn respond(&mut self, request: &Json, body: Json) -> io::Result<()> {
+        self.send(
+            "response",
+            vec![
+                ("request_seq", request_seq(request)),
+                ("success", Json::Boolean(true)),
+                ("command", string(command(request))),
+                ("body", body),
+            ],
+        )
+    }
+
+    fn fail(&mut self, request: &Json, message: &str) -> io::Result<()> {
+        self.send(
+            "response",
+            vec![
+                ("request_seq", request_seq(request)),
+                ("success", Json::Boolean(false)),
+                ("command", string(command(request))),
+                ("message", string(message)),
+            ],
+        )
+    }
+
+    fn event(&mut self, event: &str, body: Json) -> io::Result<()> {
+        self.send("event", vec![("event", string(event)), ("body", body)])
+    }
+}
+
+// Program output becomes "output" events, since stdout carries the protocol.
+struct DapIo {
+    connection: Rc<RefCell<Connection>>,
+}
+
+impl DapIo {
+    fn output(&mut self, category: &str, text: &str) -> IoResult<()> {
+        self.connection.borrow_mut().event(
+            "output",
+            object(vec![
+                ("category", string(category)),
+                ("output", string(text)),
+            ]),
+        )
+    }
+}
+
+impl HostIo for DapIo {
+    fn write_stdout(&mut self, text: &str) -> IoResult<()> {
+        self.output("stdout", text)
+    }
+
+    fn write_stderr(&mut self, text: &str) -> IoResult<()> {
+        self.output("stderr", text)
+    }
+
+    fn read_line(&mut self) -> IoResult<Option<String>> {
+        Ok(None)
+    }
+
+    fn read_file(&mut self, path: &str) -> IoResult<String> {
+        StdIo.read_file(path)
+    }
+
+    fn write_file(&mut self, path: &str, contents: &str) -> IoResult<()> {
+        StdIo.write_file(path, contents)
+    }
+
+    fn append_file(&mut self, path: &str, contents: &str) -> IoResult<()> {
+        StdIo.append_file(path, contents)
+    }
+}
+
+struct Program {
+    path: String,
+    source: String,
+    stop_on_entry: bool,
+}
+
+// Breakpoints by line, kept from the configuration phase into the run.
+#[derive(Default)]
+struct Breakpoints {
+    lines: BTreeSet<usize>,
+    statement_lines: Option<BTreeSet<usize>>,
+}
+
+impl Breakpoints {
+    // Lines that start no statement can never be hit and are reported as
+    // unverified; before the program is known every line is accepted.
+    fn set(&mut self, request: &Json) -> Json {
+        let requested: Vec<usize> = arguments(request)
+            .get("breakpoints")
+            .and_then(Json::as_array)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|breakpoint| breakpoint.get("line").and_then(Json::as_number))
+            .map(|line| line as usize)
+            .collect();
+
+        self.lines = requested.iter().copied().collect();
+        let breakpoints = requested
+            .into_iter()
+            .map(|line| {
+                let verified = self
+                    .statement_lines
+                    .as_ref()
+                    .is_none_or(|lines| lines.contains(&line));
+                object(vec![
+                    ("verified", Json::Boolean(verified)),
+                    ("line", Json::Number(line as f64)),
+                ])
+            })
+            .collect();
+
+        object(vec![("breakpoints", Json::Array(breakpoints))])
+    }
+}
+
+// Serves one debug session: configuration requests, then a launch of the
+// program named by the "program" argument, then requests while it is
+// paused. The program runs on the tree-walker.
+pub fn serve(
+    lox: &mut Lox,
+    reader: impl BufRead + 'static,
+    writer: impl Write + 'static,
+) -> io::Result<()> {
+    let connection = Rc::new(RefCell::new(Connection::new(reader, writer)));
+    let mut breakpoints = Breakpoints::default();
+    let mut program = None;
+    let mut configured = false;
+
+    while program.is_none() || !configured {
+        let Some(request) = connection.borrow_mut().receive()? else {
+            return Ok(());
+        };
+        let mut connection = connection.borrow_mut();
+        match command(&request) {
+            "initialize" => {
+                connection.respond(
+                    &request,
+                    object(vec![
+                        ("supportsConfigurationDoneRequest", Json::Boolean(true)),
+                        ("supportsEvaluateForHovers", Json::Boolean(true)),
+                        ("supportsTerminateRequest", Json::Boolean(true)),
+                    ]),
+                )?;
+                connection.event("initialized", object(Vec::new()))?;
+            }
+            "launch" => match launch(&request) {
+                Ok(launched) => {
+                    breakpoints.statement_lines = Some(statement_lines(&launched.source));
+                    program = Some(launched);
+                    connection.respond(&request, object(Vec::new()))?;
+                }
+                Err(message) => connection.fail(&request, &message)?,
+            },
+            "setBreakpoints" => {
+                let body = breakpoints.set(&request);
+                connection.respond(&request, body)?;
+            }
+            "configurationDone" => {
+                configured = true;
+                connection.respond(&request, object(Vec::new()))?;
+            }
+            "threads" => connection.respond(&request, threads())?,
+            "disconnect" | "terminate" => {
+                connection.respond(&request, object(Vec::new()))?;
+                return Ok(());
+            }
+            _ => connection.fail(&request, "Not supported before launch.")?,
+        }
+    }
+
+    let Some(program) = program else {
+        return Ok(());
+    };
+    lox.interpreter().set_io(Box::new(DapIo {
+        connection: Rc::clone(&connection),
+    }));
+
+    let hook_connection = Rc::clone(&connection);
+    let result = lox.debug_with(&program.source, |statements| {
+        Box::new(DapHook {
+            connection: hook_connection,
+            path: program.path.clone(),
+            statements: statements.to_vec(),
+            breakpoints,
+            mode: if program.stop_on_entry {
+                Mode::Step
+            } else {
+                Mode::Continue
+            },
+            entered: false,
+            values: Vec::new(),
+        })
+    });
+
+    if connection.borrow().closed {
+        return Ok(());
+    }
+
+    let exit_code = match result {
+        Ok(()) => 0,
+        Err(LoxError::Runtime(RuntimeError {
+            kind: RuntimeErrorKind::Exit(code),
+            category: ErrorCategory::General,
+            ..
+        })) => code,
+        Err(error) => {
+            lox.interpreter()
+                .io()
+                .write_stderr(&format!("{}\n", error))?;
+            match error {
+                LoxError::Compile(_) => EXIT_DATA,
+                LoxError::Runtime(_) => EXIT_SOFTWARE,
+            }
+        }
+    };
+
+    let mut connection = connection.borrow_mut();
+    connection.event(
+        "exited",
+        object(vec![("exitCode", Json::Number(exit_code as f64))]),
+    )?;
+    connection.event("terminated", object(Vec::new()))?;
+
+    while let Some(request) = connection.receive()? {
+        match command(&request) {
+            "disconnect" | "terminate" => {
+                connection.respond(&request, object(Vec::new()))?;
+                break;
+            }
+            "threads" => connection.respond(&request, threads())?,
+            _ => connection.fail(&request, "The program has exited.")?,
+        }
+    }
+
+    Ok(())
+}
+
+fn launch(request: &Json) -> Result<Program, String> {
+    let arguments = arguments(request);
+    let path = arguments
+        .get("program")
+        .and_then(Json::as_str)
+        .ok_or("Missing 'program' to launch.")?;
+    let source = fs::read_to_string(path)
+        .map_err(|error| format!("Could not read '{}': {}", path, error))?;
+
+    Ok(Program {
+        path: path.to_string(),
+        source,
+        stop_on_entry: arguments
+            .get("stopOnEntry")
+            .and_then(Json::as_bool)
+            .unwrap_or(false),
+    })
+}
+
+fn statement_lines(source: &str) -> BTreeSet<usize> {
+    let statements = Scanner::new(source.to_string())
+        .scan_tokens()
+        .map_err(|error| vec![error])
+        .and_then(|tokens| Parser::new(tokens).parse())
+        .unwrap_or_default();
+
+    let mut coverage = Coverage::new();
+    coverage.add(&statements);
+    coverage.lines().keys().copied().collect()
+}
+
+// Pauses the program when stepping or at a breakpoint and answers the
+// client's requests until it is told to resume. Variables are only
+// available for the innermost frame; callers' frames list just globals.
+struct DapHook {
+    connection: Rc<RefCell<Connection>>,
+    path: String,
+    statements: Vec<Rc<Statement>>,
+    breakpoints: Breakpoints,
+    mode: Mode,
+    entered: bool,
+    // Lists, maps and instances shown while paused, so the client can
+    // expand them; cleared on resume.
+    values: Vec<Value>,
+}
+
+impl DapHook {
+    fn stopped(&mut self, reason: &str) -> io::Result<()> {
+        self.connection.borrow_mut().event(
+            "stopped",
+            object(vec![
+                ("reason", string(reason)),
+                ("threadId", Json::Number(THREAD_ID)),
+                ("allThreadsStopped", Json::Boolean(true)),
+            ]),
+        )
+    }
+
+    // Answers requests until one resumes the program. Returns false when the
+    // client disconnected or the connection closed.
+    fn pause(
+        &mut self,
+        interpreter: &mut Interpreter,
+        statement: &Rc<Statement>,
+        line: usize,
+    ) -> io::Result<bool> {
+        let depth = interpreter.call_depth();
+        loop {
+            let Some(request) = self.connection.borrow_mut().receive()? else {
+                return Ok(false);
+            };
+
+            let mode = match command(&request) {
+                "continue" => Some(Mode::Continue),
+                "next" => Some(Mode::Next(depth)),
+                "stepIn" => Some(Mode::Step),
+                "stepOut" => Some(Mode::Finish(depth)),
+                _ => None,
+            };
+            if let Some(mode) = mode {
+                self.mode = mode;
+                self.values.clear();
+                let body = object(vec![("allThreadsContinued", Json::Boolean(true))]);
+                self.connection.borrow_mut().respond(&request, body)?;
+                return Ok(true);
+            }
+
+            let body = match command(&request) {
+                "disconnect" | "terminate" => {
+                    let mut connection = self.connection.borrow_mut();
+                    connection.respond(&request, object(Vec::new()))?;
+                    connection.closed = true;
+                    return Ok(false);
+                }
+                "threads" => Ok(threads()),
+                "stackTrace" => Ok(self.stack_trace(interpreter, line)),
+                "scopes" => Ok(self.scopes(&request)),
+                "variables" => Ok(self.variables(interpreter, statement, &request)),
+                "evaluate" => self.evaluate(interpreter, statement, &request),
+                "setBreakpoints" => Ok(self.breakpoints.set(&request)),
+                "pause" | "configurationDone" | "setExceptionBreakpoints" => Ok(object(Vec::new())),
+                _ => Err("Not supported.".to_string()),
+            };
+
+            let mut connection = self.connection.borrow_mut();
+            match body {
+                Ok(body) => connection.respond(&request, body)?,
+                Err(message) => connection.fail(&request, &message)?,
+            }
+        }
+    }
+
+    fn stack_trace(&self, interpreter: &Interpreter, line: usize) -> Json {
+        let source = object(vec![
+            ("name", string(&file_name(&self.path))),
+            ("path", string(&self.path)),
+        ]);
+        let frame = |id: usize, name: &str, line: usize| {
+            object(vec![
+                ("id", Json::Number(id as f64)),
+                ("name", string(name)),
+                ("source", source.clone()),
+                ("line", Json::Number(line as f64)),
+                ("column", Json::Number(1.0)),
+            ])
+        };
+
+        let calls = interpreter.backtrace();
+        let mut frames = Vec::new();
+        let mut line = line;
+        for (id, (name, called_from)) in calls.iter().rev().enumerate() {
+            frames.push(frame(id, name, line));
+            line = *called_from;
+        }
+        frames.push(frame(calls.len(), "<script>", line));
+
+        object(vec![
+            ("totalFrames", Json::Number(frames.len() as f64)),
+            ("stackFrames", Json::Array(frames)),
+        ])
+    }
+
+    fn scopes(&self, request: &Json) -> Json {
+        let scope = |name: &str, reference: usize| {
+            object(vec![
+                ("name", string(name)),
+                ("variablesReference", Json::Number(reference as f64)),
+                ("expensive", Json::Boolean(false)),
+            ])
+        };
+
+        let frame = arguments(request)
+            .get("frameId")
+            .and_then(Json::as_number)
+            .unwrap_or(0.0);
+        let mut scopes = Vec::new();
+        if frame == 0.0 {
+            scopes.push(scope("Locals", LOCALS_REFERENCE));
+        }
+        scopes.push(scope("Globals", GLOBALS_REFERENCE));
+
+        object(vec![("scopes", Json::Array(scopes))])
+    }
+
+    fn variables(
+        &mut self,
+        interpreter: &Interpreter,
+        statement: &Rc<Statement>,
+        request: &Json,
+    ) -> Json {
+        let reference = arguments(request)
+            .get("variablesReference")
+            .and_then(Json::as_number)
+            .unwrap_or(0.0) as usize;
+
+        let entries: Vec<(String, Value)> = match reference {
+            LOCALS_REFERENCE => debugger::locals(interpreter, &self.statements, statement),
+            GLOBALS_REFERENCE => {
+                let mut globals: Vec<(String, Value)> = interpreter
+                    .globals()
+                    .borrow()
+                    .values()
+                    .iter()
+                    .filter(|(_, value)| !matches!(value, Value::Native(_)))
+                    .map(|(name, value)| (name.to_string(), value.clone()))
+                    .collect();
+                globals.sort_by(|a, b| a.0.cmp(&b.0));
+                globals
+            }
+            _ => match self
+                .values
+                .get(reference.wrapping_sub(FIRST_VALUE_REFERENCE))
+            {
+                Some(value) => children(value),
+                None => Vec::new(),
+            },
+        };
+
+        let variables = entries
+            .into_iter()
+            .map(|(name, value)| {
+                object(vec![
+                    ("name", string(&name)),
+                    ("value", string(&value.to_string())),
+                    ("type", string(value.type_name())),
+                    ("variablesReference", self.reference(value)),
+                ])
+            })
+            .collect();
+
+        object(vec![("variables", Json::Array(variables))])
+    }
+
+    fn evaluate(
+        &mut self,
+        interpreter: &mut Interpreter,
+        statement: &Rc<Statement>,
+        request: &Json,
+    ) -> Result<Json, String> {
+        let expression = arguments(request)
+            .get("expression")
+            .and_then(Json::as_str)
+            .unwrap_or_default();
+
+        let value = debugger::evaluate(interpreter, &self.statements, statement, expression)
+            .map_err(|messages| messages.join("\n"))?;
+        Ok(object(vec![
+            ("result", string(&value.to_string())),
+            ("type", string(value.type_name())),
+            ("variablesReference", self.reference(value)),
+        ]))
+    }
+
+    // Values with children get a handle the client can expand; 0 means none.
+    fn reference(&mut self, value: Value) -> Json {
+        match value {
+            Value::List(_) | Value::Map(_) | Value::Instance(_) => {
+                self.values.push(value);
+                Json::Number((self.values.len() - 1 + FIRST_VALUE_REFERENCE) as f64)
+            }
+            _ => Json::Number(0.0),
+        }
+    }
+}
+
+impl DebugHook for DapHook {
+    fn on_statement(
+        &mut self,
+        interpreter: &mut Interpreter,
+        statement: &Rc<Statement>,
+    ) -> Result<(), RuntimeError> {
+        let Some(line) = statement.line() else {
+            return Ok(());
+        };
+
+        let reason = if self.mode.stops_at(interpreter.call_depth()) {
+            if self.entered {
+                "step"
+            } else {
+                "entry"
+            }
+        } else if self.breakpoints.lines.contains(&line) {
+            "breakpoint"
+        } else {
+            return Ok(());
+        };
+        self.entered = true;
+
+        let resumed = self
+            .stopped(reason)
+            .and_then(|()| self.pause(interpreter, statement, line));
+        match resumed {
+            Ok(true) => Ok(()),
+            Ok(false) | Err(_) => Err(RuntimeError {
+                kind: RuntimeErrorKind::Exit(0),
+                category: ErrorCategory::General,
+                message: "Debugger disconnected.".to_string(),
+                line,
+            }),
+        }
+    }
+}
+
+fn children(value: &Value) -> Vec<(String, Value)> {
+    match value {
+        Value::List(elements) => elements
+            .borrow()
+            .iter()
+            .enumerate()
+            .map(|(index, element)| (index.to_string(), element.clone()))
+            .collect(),
+        Value::Map(entries) => entries
+            .borrow()
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect(),
+        Value::Instance(instance) => {
+            let mut fields: Vec<(String, Value)> = instance
+                .borrow()
+                .fields
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.clone()))
+                .collect();
+            fields.sort_by(|a, b| a.0.cmp(&b.0));
+            fields
+        }
+        _ => Vec::new(),
+    }
+}
+
+fn threads() -> Json {
+    let thread = object(vec![
+        ("id", Json::Number(THREAD_ID)),
+        ("name", string("main")),
+    ]);
+    object(vec![("threads", Json::Array(vec![thread]))])
+}
+
+fn command(request: &Json) -> &str {
+    request
+        .get("command")
+        .and_then(Json::as_str)
+        .unwrap_or_default()
+}
+
+fn arguments(request: &Json) -> &Json {
+    request.get("arguments").unwrap_or(&Json::Null)
+}
+
+fn request_seq(request: &Json) -> Json {
+    request.get("seq").cloned().unwrap_or(Json::Number(0.0))
+}
+
+fn file_name(path: &str) -> String {
+    Path::new(path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.to_string())
+}
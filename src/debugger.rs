@@ -0,0 +1,288 @@
+use alloc::collections::BTreeSet;
+
+use crate::error::{ErrorCategory, RuntimeError, RuntimeErrorKind};
+use crate::interpreter::Interpreter;
+use crate::parser::Parser;
+use crate::prelude::*;
+use crate::resolver::Resolver;
+use crate::scanner::Scanner;
+use crate::statement::Statement;
+use crate::value::Value;
+
+// Called by the tree-walker before it executes each statement.
+pub trait DebugHook {
+    fn on_statement(
+        &mut self,
+        interpreter: &mut Interpreter,
+        statement: &Rc<Statement>,
+    ) -> Result<(), RuntimeError>;
+}
+
+const HELP: &str = "\
+break LINE      stop whenever a statement on LINE runs (b)
+delete LINE     remove the breakpoint on LINE
+step            run to the next statement, entering calls (s)
+next            run to the next statement in this function (n)
+finish          run until the current function returns
+continue        run to the next breakpoint (c)
+backtrace       show the active calls (bt)
+locals          show the local variables in scope
+print EXPR      evaluate EXPR in the current frame (p)
+list            show the source around the current line (l)
+quit            stop the program (q)
+";
+
+// How far to run before pausing again, relative to the call depth at which
+// the command was given.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Mode {
+    Step,
+    Next(usize),
+    Finish(usize),
+    Continue,
+}
+
+impl Mode {
+    pub(crate) fn stops_at(self, depth: usize) -> bool {
+        match self {
+            Mode::Step => true,
+            Mode::Next(from) => depth <= from,
+            Mode::Finish(from) => depth < from,
+            Mode::Continue => false,
+        }
+    }
+}
+
+// An interactive line debugger that reads commands from the interpreter's
+// host input and writes to its host output. It starts paused on the first
+// statement.
+pub struct Debugger {
+    source: String,
+    statements: Vec<Rc<Statement>>,
+    breakpoints: BTreeSet<usize>,
+    mode: Mode,
+}
+
+enum Resume {
+    Run,
+    Quit,
+}
+
+impl Debugger {
+    pub fn new(source: &str, statements: &[Rc<Statement>]) -> Debugger {
+        Debugger {
+            source: source.to_string(),
+            statements: statements.to_vec(),
+            breakpoints: BTreeSet::new(),
+            mode: Mode::Step,
+        }
+    }
+
+    pub fn add_breakpoint(&mut self, line: usize) {
+        self.breakpoints.insert(line);
+    }
+
+    fn source_line(&self, line: usize) -> &str {
+        self.source
+            .lines()
+            .nth(line.saturating_sub(1))
+            .unwrap_or_default()
+    }
+
+    fn prompt(
+        &mut self,
+        interpreter: &mut Interpreter,
+        statement: &Rc<Statement>,
+        line: usize,
+    ) -> Result<Resume, RuntimeError> {
+        let depth = interpreter.call_depth();
+        loop {
+            write(interpreter, "(lox) ");
+            let input = match interpreter.io().read_line() {
+                Ok(Some(input)) => input,
+                Ok(None) | Err(_) => return Ok(Resume::Quit),
+            };
+
+            let input = input.trim();
+            let (command, argument) = input.split_once(' ').unwrap_or((input, ""));
+            let argument = argument.trim();
+            match command {
+                "" => {}
+                "s" | "step" => {
+                    self.mode = Mode::Step;
+                    return Ok(Resume::Run);
+                }
+                "n" | "next" => {
+                    self.mode = Mode::Next(depth);
+                    return Ok(Resume::Run);
+                }
+                "finish" => {
+                    self.mode = Mode::Finish(depth);
+                    return Ok(Resume::Run);
+                }
+                "c" | "continue" => {
+                    self.mode = Mode::Continue;
+                    return Ok(Resume::Run);
+                }
+                "q" | "quit" => return Ok(Resume::Quit),
+                "b" | "break" => match argument.parse::<usize>() {
+                    Ok(line) => {
+                        self.breakpoints.insert(line);
+                        write(interpreter, &format!("Breakpoint set at line {}.\n", line));
+                    }
+                    Err(_) => write(interpreter, "Usage: break LINE\n"),
+                },
+                "delete" => match argument.parse::<usize>() {
+                    Ok(line) if self.breakpoints.remove(&line) => {
+                        write(
+                            interpreter,
+                            &format!("Deleted breakpoint at line {}.\n", line),
+                        );
+                    }
+                    Ok(line) => {
+                        write(interpreter, &format!("No breakpoint at line {}.\n", line));
+                    }
+                    Err(_) => write(interpreter, "Usage: delete LINE\n"),
+                },
+                "bt" | "backtrace" => self.backtrace(interpreter, line),
+                "locals" => self.locals(interpreter, statement),
+                "p" | "print" => self.print(interpreter, statement, argument),
+                "l" | "list" => self.list(interpreter, line),
+                "h" | "help" => write(interpreter, HELP),
+                _ => write(
+                    interpreter,
+                    &format!("Unknown command '{}'. Try 'help'.\n", command),
+                ),
+            }
+        }
+    }
+
+    fn backtrace(&self, interpreter: &mut Interpreter, line: usize) {
+        let frames = interpreter.backtrace();
+        let mut output = String::new();
+        let mut line = line;
+        for (index, (name, called_from)) in frames.iter().rev().enumerate() {
+            output.push_str(&format!("#{} {}() at line {}\n", index, name, line));
+            line = *called_from;
+        }
+        output.push_str(&format!("#{} <script> at line {}\n", frames.len(), line));
+        write(interpreter, &output);
+    }
+
+    fn locals(&self, interpreter: &mut Interpreter, statement: &Rc<Statement>) {
+        let mut output = String::new();
+        for (name, value) in locals(interpreter, &self.statements, statement) {
+            output.push_str(&format!("{} = {}\n", name, value));
+        }
+        if output.is_empty() {
+            output.push_str("No locals.\n");
+        }
+        write(interpreter, &output);
+    }
+
+    fn print(&self, interpreter: &mut Interpreter, statement: &Rc<Statement>, source: &str) {
+        match evaluate(interpreter, &self.statements, statement, source) {
+            Ok(value) => write(interpreter, &format!("{}\n", value)),
+            Err(messages) => {
+                for message in messages {
+                    write(interpreter, &format!("Error: {}\n", message));
+                }
+            }
+        }
+    }
+
+    fn list(&self, interpreter: &mut Interpreter, line: usize) {
+        let first = line.saturating_sub(3).max(1);
+        let mut output = String::new();
+        for number in first..=line + 3 {
+            let Some(text) = self.source.lines().nth(number - 1) else {
+                break;
+            };
+            let marker = if number == line { "->" } else { "  " };
+            output.push_str(&format!("{} {:>4}  {}\n", marker, number, text));
+        }
+        write(interpreter, &output);
+    }
+}
+
+impl DebugHook for Debugger {
+    fn on_statement(
+        &mut self,
+        interpreter: &mut Interpreter,
+        statement: &Rc<Statement>,
+    ) -> Result<(), RuntimeError> {
+        let Some(line) = statement.line() else {
+            return Ok(());
+        };
+        let stepped = self.mode.stops_at(interpreter.call_depth());
+        if !stepped && !self.breakpoints.contains(&line) {
+            return Ok(());
+        }
+
+        if !stepped {
+            write(interpreter, &format!("Breakpoint at line {}.\n", line));
+        }
+        let location = format!("{:>4}  {}\n", line, self.source_line(line));
+        write(interpreter, &location);
+
+        match self.prompt(interpreter, statement, line)? {
+            Resume::Run => Ok(()),
+            Resume::Quit => Err(RuntimeError {
+                kind: RuntimeErrorKind::Exit(0),
+                category: ErrorCategory::General,
+                message: "Debugger quit.".to_string(),
+                line,
+            }),
+        }
+    }
+}
+
+// The local variables visible where `statement` is about to run, innermost
+// scope first.
+pub(crate) fn locals(
+    interpreter: &Interpreter,
+    statements: &[Rc<Statement>],
+    statement: &Rc<Statement>,
+) -> Vec<(String, Value)> {
+    let scopes = Resolver::scopes_at(statements, statement).unwrap_or_default();
+    let environment = interpreter.environment().borrow();
+
+    let mut locals = Vec::new();
+    for (depth, names) in scopes.iter().rev().enumerate() {
+        for (slot, name) in names.iter().enumerate() {
+            if let Some(value) = environment.get_at(depth, slot) {
+                locals.push((name.clone(), value));
+            }
+        }
+    }
+    locals
+}
+
+// Parses `source` as an expression and evaluates it with the locals visible
+// at `statement` in scope.
+pub(crate) fn evaluate(
+    interpreter: &mut Interpreter,
+    statements: &[Rc<Statement>],
+    statement: &Rc<Statement>,
+    source: &str,
+) -> Result<Value, Vec<String>> {
+    let expression = Scanner::new(source.to_string())
+        .scan_tokens()
+        .map_err(|error| vec![error])
+        .and_then(|tokens| Parser::new(tokens).parse_expression())
+        .map_err(|errors| {
+            errors
+                .into_iter()
+                .map(|error| error.message)
+                .collect::<Vec<_>>()
+        })?;
+
+    let scopes = Resolver::scopes_at(statements, statement).unwrap_or_default();
+    interpreter
+        .evaluate_in_scope(expression, scopes)
+        .map_err(|error| vec![error.message])
+}
+
+fn write(interpreter: &mut Interpreter, text: &str) {
+    let _ = interpreter.io().write_stdout(text);
+}
@@ -0,0 +1,175 @@
+use crate::prelude::*;
+use crate::token::Keyword;
+
+// The keywords this implementation adds to the language in the book.
+pub const EXTENSIONS: [Keyword; 6] = [
+    Keyword::In,
+    Keyword::Yield,
+    Keyword::Try,
+    Keyword::Catch,
+    Keyword::Throw,
+    Keyword::Defer,
+];
+
+// The keywords a dialect may leave out: the extensions, and `print`, which
+// can be a native function instead of a statement.
+pub const OPTIONAL: [Keyword; 7] = [
+    Keyword::In,
+    Keyword::Yield,
+    Keyword::Try,
+    Keyword::Catch,
+    Keyword::Throw,
+    Keyword::Defer,
+    Keyword::Print,
+];
+
+// Which keywords a program may use. The book's keywords are always there,
+// except that `print` can give way to `print` and `println` functions. A
+// keyword that is switched off scans as a plain identifier, as it would in
+// the book's Lox, and the parser points at it when it causes an error.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dialect {
+    disabled: Vec<Keyword>,
+    strict: bool,
+    integers: bool,
+    string_ordering: bool,
+    repetition: bool,
+    to_string_hook: bool,
+}
+
+impl Default for Dialect {
+    fn default() -> Dialect {
+        Dialect {
+            disabled: Vec::new(),
+            strict: false,
+            integers: false,
+            string_ordering: true,
+            repetition: true,
+            to_string_hook: true,
+        }
+    }
+}
+
+impl Dialect {
+    // Every extension switched on.
+    pub fn extended() -> Dialect {
+        Dialect::default()
+    }
+
+    // The language exactly as Crafting Interpreters defines it.
+    pub fn book() -> Dialect {
+        Dialect {
+            disabled: EXTENSIONS.to_vec(),
+            strict: false,
+            integers: false,
+            string_ordering: false,
+            repetition: false,
+            to_string_hook: false,
+        }
+    }
+
+    // The book's language and nothing else, for conformance runs: only its
+    // keywords and natives, characters only extensions use rejected when
+    // scanning, and errors worded, located and caught as the book does.
+    pub fn strict() -> Dialect {
+        Dialect {
+            strict: true,
+            ..Dialect::book()
+        }
+    }
+
+    pub fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    pub fn from_name(name: &str) -> Option<Dialect> {
+        match name {
+            "extended" => Some(Dialect::extended()),
+            "book" => Some(Dialect::book()),
+            "strict" => Some(Dialect::strict()),
+            _ => None,
+        }
+    }
+
+    // Switching a keyword that is not optional does nothing.
+    pub fn set_enabled(&mut self, keyword: Keyword, enabled: bool) {
+        self.disabled.retain(|&disabled| disabled != keyword);
+        if !enabled && OPTIONAL.contains(&keyword) {
+            self.disabled.push(keyword);
+        }
+    }
+
+    // Whether `print` is a statement, the default, or a native function
+    // alongside `println`.
+    pub fn print_statement(&self) -> bool {
+        self.is_enabled(Keyword::Print)
+    }
+
+    pub fn set_print_statement(&mut self, statement: bool) {
+        self.set_enabled(Keyword::Print, statement);
+    }
+
+    // Integers diverge furthest from the book, so no dialect has them
+    // unless asked: literals without a decimal point become integers, which
+    // stay integers through arithmetic with each other, and `div` divides
+    // them rounding down.
+    pub fn integers(&self) -> bool {
+        self.integers
+    }
+
+    pub fn set_integers(&mut self, integers: bool) {
+        self.integers = integers;
+    }
+
+    // Whether `<`, `<=`, `>` and `>=` also order two strings, comparing
+    // them lexicographically by code point. The book only orders numbers.
+    pub fn string_ordering(&self) -> bool {
+        self.string_ordering
+    }
+
+    pub fn set_string_ordering(&mut self, string_ordering: bool) {
+        self.string_ordering = string_ordering;
+    }
+
+    // Whether `*` repeats a string or a list a whole number of times, as in
+    // `"ab" * 3` or `[0] * 5`.
+    pub fn repetition(&self) -> bool {
+        self.repetition
+    }
+
+    pub fn set_repetition(&mut self, repetition: bool) {
+        self.repetition = repetition;
+    }
+
+    // Whether printing an instance, or passing it to `string()`, calls its
+    // class's `toString()` method when there is one.
+    pub fn to_string_hook(&self) -> bool {
+        self.to_string_hook
+    }
+
+    pub fn set_to_string_hook(&mut self, to_string_hook: bool) {
+        self.to_string_hook = to_string_hook;
+    }
+
+    pub fn is_enabled(&self, keyword: Keyword) -> bool {
+        match keyword {
+            Keyword::Div => self.integers,
+            _ => !self.disabled.contains(&keyword),
+        }
+    }
+
+    // The keyword `word` is in this dialect, if it is one.
+    pub fn keyword(&self, word: &str) -> Option<Keyword> {
+        word.parse()
+            .ok()
+            .filter(|&keyword| self.is_enabled(keyword))
+    }
+
+    // The extension keyword `word` spells that this dialect leaves out, if
+    // any.
+    pub fn disabled(&self, word: &str) -> Option<Keyword> {
+        word.parse()
+            .ok()
+            .filter(|&keyword| !self.is_enabled(keyword))
+    }
+}
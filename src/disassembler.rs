@@ -0,0 +1,268 @@
+use core::fmt::Write;
+
+use crate::bytecode::{Chunk, Constant, OpCode, Prototype};
+use crate::prelude::*;
+
+pub fn disassemble(prototype: &Prototype) -> String {
+    let mut output = disassemble_chunk(&prototype.chunk, &prototype.name);
+
+    for constant in prototype.chunk.constants() {
+        if let Constant::Function(function) = constant {
+            output.push('\n');
+            output.push_str(&disassemble(function));
+        }
+    }
+
+    output
+}
+
+pub fn disassemble_chunk(chunk: &Chunk, name: &str) -> String {
+    let mut output = format!("== {} ==\n", name);
+
+    let mut offset = 0;
+    while offset < chunk.len() {
+        let (line, next) = disassemble_instruction(chunk, offset);
+        output.push_str(&line);
+        output.push('\n');
+        offset = next;
+    }
+
+    output
+}
+
+const MAX_DIFF_CELLS: usize = 1 << 22;
+
+pub fn diff(before: &Prototype, after: &Prototype) -> String {
+    let mut output = format!("== {} ==\n", after.name);
+    let before_lines = listing(&before.chunk);
+    let after_lines = listing(&after.chunk);
+    diff_lines(&mut output, &before_lines, &after_lines);
+
+    let functions = |prototype: &Prototype| -> Vec<Rc<Prototype>> {
+        prototype
+            .chunk
+            .constants()
+            .iter()
+            .filter_map(|constant| match constant {
+                Constant::Function(function) => Some(Rc::clone(function)),
+                _ => None,
+            })
+            .collect()
+    };
+
+    let mut remaining = functions(after);
+    for function in functions(before) {
+        output.push('\n');
+        match remaining
+            .iter()
+            .position(|candidate| candidate.name == function.name)
+        {
+            Some(position) => {
+                let optimized = remaining.remove(position);
+                output.push_str(&diff(&function, &optimized));
+            }
+            None => {
+                let _ = writeln!(output, "== {} (removed) ==", function.name);
+                diff_lines(&mut output, &listing(&function.chunk), &[]);
+            }
+        }
+    }
+
+    output
+}
+
+fn listing(chunk: &Chunk) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut offset = 0;
+    while offset < chunk.len() {
+        let (instruction, next) = disassemble_instruction(chunk, offset);
+        lines.extend(instruction.lines().map(str::to_string));
+        offset = next;
+    }
+
+    lines
+}
+
+fn diff_lines(output: &mut String, before: &[String], after: &[String]) {
+    let key = |line: &str| -> String {
+        let rest = line.trim_start();
+        let rest = rest
+            .split_once(' ')
+            .map_or("", |(_, rest)| rest)
+            .trim_start();
+        let rest = rest.split_once(' ').map_or("", |(_, rest)| rest);
+        rest.trim().to_string()
+    };
+    let before_keys: Vec<String> = before.iter().map(|line| key(line)).collect();
+    let after_keys: Vec<String> = after.iter().map(|line| key(line)).collect();
+
+    let width = after.len() + 1;
+    if (before.len() + 1) * width > MAX_DIFF_CELLS {
+        for line in before {
+            let _ = writeln!(output, "- {}", line);
+        }
+        for line in after {
+            let _ = writeln!(output, "+ {}", line);
+        }
+        return;
+    }
+
+    let mut common = vec![0usize; (before.len() + 1) * width];
+    for row in (0..before.len()).rev() {
+        for column in (0..after.len()).rev() {
+            common[row * width + column] = if before_keys[row] == after_keys[column] {
+                common[(row + 1) * width + column + 1] + 1
+            } else {
+                common[(row + 1) * width + column].max(common[row * width + column + 1])
+            };
+        }
+    }
+
+    let (mut row, mut column) = (0, 0);
+    while row < before.len() || column < after.len() {
+        if row < before.len() && column < after.len() && before_keys[row] == after_keys[column] {
+            let _ = writeln!(output, "  {}", after[column]);
+            row += 1;
+            column += 1;
+        } else if row < before.len()
+            && (column == after.len()
+                || common[(row + 1) * width + column] >= common[row * width + column + 1])
+        {
+            let _ = writeln!(output, "- {}", before[row]);
+            row += 1;
+        } else {
+            let _ = writeln!(output, "+ {}", after[column]);
+            column += 1;
+        }
+    }
+}
+
+pub fn disassemble_instruction(chunk: &Chunk, offset: usize) -> (String, usize) {
+    let mut output = format!("{:04} ", offset);
+
+    let line = chunk.line(offset);
+    if offset > 0 && line == chunk.line(offset - 1) {
+        output.push_str("   | ");
+    } else {
+        let _ = write!(output, "{:>4} ", line);
+    }
+
+    let byte = chunk.code()[offset];
+    let Ok(op) = OpCode::try_from(byte) else {
+        let _ = write!(output, "Unknown opcode {}", byte);
+        return (output, offset + 1);
+    };
+
+    let next = match op {
+        OpCode::Constant
+        | OpCode::DefineGlobal
+        | OpCode::GetGlobal
+        | OpCode::SetGlobal
+        | OpCode::GetProperty
+        | OpCode::SetProperty
+        | OpCode::GetSuper
+        | OpCode::ConstantLong
+        | OpCode::DefineGlobalLong
+        | OpCode::GetGlobalLong
+        | OpCode::SetGlobalLong
+        | OpCode::GetPropertyLong
+        | OpCode::SetPropertyLong
+        | OpCode::GetSuperLong => {
+            let (index, next) = constant_operand(chunk, op, offset);
+            let _ = write!(
+                output,
+                "{:<16} {:>4} '{}'",
+                op.name(),
+                index,
+                chunk.constant(index)
+            );
+            next
+        }
+        OpCode::Closure | OpCode::ClosureLong => {
+            let (index, mut next) = constant_operand(chunk, op, offset);
+            let constant = chunk.constant(index);
+            let _ = write!(output, "{:<16} {:>4} {}", op.name(), index, constant);
+
+            let upvalue_count = match constant {
+                Constant::Function(prototype) => prototype.upvalue_count,
+                _ => 0,
+            };
+
+            for _ in 0..upvalue_count {
+                let kind = if chunk.code()[next] == 1 {
+                    "local"
+                } else {
+                    "upvalue"
+                };
+                let _ = write!(
+                    output,
+                    "\n{:04}    |                     {} {}",
+                    next,
+                    kind,
+                    chunk.read_u16(next + 1)
+                );
+                next += 3;
+            }
+
+            next
+        }
+        OpCode::Class | OpCode::ClassLong => {
+            let (index, next) = constant_operand(chunk, op, offset);
+            let _ = write!(
+                output,
+                "{:<16} {:>4} '{}' methods {} inherits {}",
+                op.name(),
+                index,
+                chunk.constant(index),
+                chunk.code()[next],
+                chunk.code()[next + 1] == 1
+            );
+            next + 2
+        }
+        OpCode::GetLocal
+        | OpCode::SetLocal
+        | OpCode::GetUpvalue
+        | OpCode::SetUpvalue
+        | OpCode::Call => {
+            let _ = write!(output, "{:<16} {:>4}", op.name(), chunk.code()[offset + 1]);
+            offset + 2
+        }
+        OpCode::GetLocalLong
+        | OpCode::SetLocalLong
+        | OpCode::BuildList
+        | OpCode::BuildMap
+        | OpCode::ExpectValues => {
+            let _ = write!(
+                output,
+                "{:<16} {:>4}",
+                op.name(),
+                chunk.read_u16(offset + 1)
+            );
+            offset + 3
+        }
+        OpCode::Jump | OpCode::JumpIfFalse | OpCode::Loop | OpCode::Try | OpCode::Next => {
+            let jump = chunk.read_u16(offset + 1) as usize;
+            let target = if op == OpCode::Loop {
+                offset + 3 - jump
+            } else {
+                offset + 3 + jump
+            };
+            let _ = write!(output, "{:<16} {:>4} -> {:04}", op.name(), offset, target);
+            offset + 3
+        }
+        _ => {
+            output.push_str(op.name());
+            offset + 1
+        }
+    };
+
+    (output, next)
+}
+
+fn constant_operand(chunk: &Chunk, op: OpCode, offset: usize) -> (usize, usize) {
+    if op.is_long() {
+        (chunk.read_u24(offset + 1) as usize, offset + 4)
+    } else {
+        (chunk.code()[offset + 1] as usize, offset + 2)
+    }
+}
@@ -0,0 +1,150 @@
+use core::cell::RefCell;
+use core::mem;
+
+use crate::prelude::*;
+use crate::symbol::{Symbol, SymbolTable};
+use crate::value::Value;
+
+#[derive(Default)]
+pub struct Environment {
+    values: SymbolTable<Value>,
+    slots: Vec<Value>,
+    enclosing: Option<Rc<RefCell<Environment>>>,
+    version: u64,
+}
+
+impl Environment {
+    pub fn new() -> Environment {
+        Environment::default()
+    }
+
+    pub fn with_enclosing(enclosing: Rc<RefCell<Environment>>) -> Environment {
+        Environment {
+            values: SymbolTable::new(),
+            slots: Vec::new(),
+            enclosing: Some(enclosing),
+            version: 0,
+        }
+    }
+
+    pub fn values(&self) -> &SymbolTable<Value> {
+        &self.values
+    }
+
+    pub fn set_values(&mut self, values: SymbolTable<Value>) {
+        self.version += 1;
+        self.values = values;
+    }
+
+    pub(crate) fn take_values(&mut self) -> SymbolTable<Value> {
+        self.version += 1;
+        mem::take(&mut self.values)
+    }
+
+    pub(crate) fn slots(&self) -> &[Value] {
+        &self.slots
+    }
+
+    pub(crate) fn take_slots(&mut self) -> Vec<Value> {
+        mem::take(&mut self.slots)
+    }
+
+    pub(crate) fn version(&self) -> u64 {
+        self.version
+    }
+
+    pub(crate) fn enclosing(&self) -> Option<&Rc<RefCell<Environment>>> {
+        self.enclosing.as_ref()
+    }
+
+    pub fn define(&mut self, name: impl Into<Symbol>, value: Value) {
+        self.version += 1;
+        self.values.insert(name.into(), value);
+    }
+
+    // Locals resolved ahead of time live in slots, pushed in declaration
+    // order and addressed by how many environments up they were declared.
+    pub(crate) fn push(&mut self, value: Value) {
+        self.slots.push(value);
+    }
+
+    pub(crate) fn get_at(&self, depth: usize, slot: usize) -> Option<Value> {
+        match depth {
+            0 => self.slots.get(slot).cloned(),
+            _ => self.enclosing.as_ref()?.borrow().get_at(depth - 1, slot),
+        }
+    }
+
+    pub(crate) fn assign_at(&mut self, depth: usize, slot: usize, value: Value) -> bool {
+        match depth {
+            0 => match self.slots.get_mut(slot) {
+                Some(current) => {
+                    *current = value;
+                    true
+                }
+                None => false,
+            },
+            _ => match &self.enclosing {
+                Some(enclosing) => enclosing.borrow_mut().assign_at(depth - 1, slot, value),
+                None => false,
+            },
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<Value> {
+        if let Some(value) = self.values.get(name) {
+            return Some(value.clone());
+        }
+
+        let mut environment = self.enclosing.clone();
+        while let Some(current) = environment {
+            if let Some(value) = current.borrow().values.get(name) {
+                return Some(value.clone());
+            }
+
+            environment = current.borrow().enclosing.clone();
+        }
+
+        None
+    }
+
+    // Lookups by a symbol the caller already has, which skip hashing the
+    // name. Only the bytecode VM has symbols ready; it keeps globals alone
+    // here, so these don't walk enclosing scopes.
+    pub(crate) fn get_symbol(&self, name: &Symbol) -> Option<Value> {
+        self.values.get_symbol(name).cloned()
+    }
+
+    pub(crate) fn assign_symbol(&mut self, name: &Symbol, value: Value) -> bool {
+        match self.values.get_symbol_mut(name) {
+            Some(slot) => {
+                *slot = value;
+                self.version += 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn assign(&mut self, name: &str, value: Value) -> bool {
+        if let Some(slot) = self.values.get_mut(name) {
+            *slot = value;
+            self.version += 1;
+            return true;
+        }
+
+        let mut environment = self.enclosing.clone();
+        while let Some(current) = environment {
+            let mut scope = current.borrow_mut();
+            if let Some(slot) = scope.values.get_mut(name) {
+                *slot = value;
+                scope.version += 1;
+                return true;
+            }
+
+            environment = scope.enclosing.clone();
+        }
+
+        false
+    }
+}
@@ -1,5 +1,127 @@
-#[derive(Debug)]
+use core::fmt;
+
+use crate::prelude::*;
+
+#[derive(Debug, Clone)]
 pub struct Error {
     pub message: String,
     pub line: usize,
+    // Only set in strict mode, which reports errors as the book does.
+    pub location: Option<Location>,
+}
+
+// The token a compile error is at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Location {
+    Token(String),
+    End,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match &self.location {
+            None => write!(formatter, "[line {}] Error: {}", self.line, self.message),
+            Some(Location::Token(lexeme)) => write!(
+                formatter,
+                "[line {}] Error at '{}': {}",
+                self.line, lexeme, self.message
+            ),
+            Some(Location::End) => {
+                write!(
+                    formatter,
+                    "[line {}] Error at end: {}",
+                    self.line, self.message
+                )
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum LoxError {
+    Compile(Vec<Error>),
+    Runtime(RuntimeError),
+}
+
+impl fmt::Display for LoxError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoxError::Compile(errors) => {
+                for (index, error) in errors.iter().enumerate() {
+                    if index > 0 {
+                        writeln!(formatter)?;
+                    }
+                    write!(formatter, "{}", error)?;
+                }
+                Ok(())
+            }
+            LoxError::Runtime(error) => write!(formatter, "{}", error),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuntimeErrorKind {
+    Error,
+    // A value a `throw` statement raised and nothing caught.
+    Thrown,
+    Exit(i32),
+    ResourceExceeded,
+    Interrupted,
+}
+
+impl RuntimeErrorKind {
+    // Whether a `try` can catch the error and deferred blocks run as it
+    // leaves their calls. Running out of resources, being interrupted and
+    // exiting end the run outright.
+    pub(crate) fn is_catchable(self) -> bool {
+        matches!(self, RuntimeErrorKind::Error | RuntimeErrorKind::Thrown)
+    }
+}
+
+// What went wrong, which decides the class a `catch` receives the error
+// as. The runtime sets it where it raises the error; errors from `error()`
+// and `assert` are always general.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ErrorCategory {
+    #[default]
+    General,
+    // A value of the wrong type, or a call with the wrong arguments.
+    Type,
+    // A variable or property that isn't defined.
+    Name,
+    // A position outside a list or string.
+    Index,
+}
+
+impl ErrorCategory {
+    pub(crate) fn class_name(self) -> &'static str {
+        match self {
+            ErrorCategory::General => "Error",
+            ErrorCategory::Type => "TypeError",
+            ErrorCategory::Name => "NameError",
+            ErrorCategory::Index => "IndexError",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct RuntimeError {
+    pub kind: RuntimeErrorKind,
+    pub category: ErrorCategory,
+    pub message: String,
+    pub line: usize,
+}
+
+impl RuntimeError {
+    pub fn with_category(mut self, category: ErrorCategory) -> RuntimeError {
+        self.category = category;
+        self
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{}\n[line {}]", self.message, self.line)
+    }
 }
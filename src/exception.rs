@@ -0,0 +1,16 @@
+// The classes runtime errors are caught as. They are written in Lox so that
+// scripts can subclass them, construct them and throw them like their own.
+pub(crate) const CLASSES: &str = "
+class Error {
+  init(message) {
+    this.message = message;
+    this.line = nil;
+    this.trace = nil;
+  }
+}
+class TypeError < Error {}
+class NameError < Error {}
+class IndexError < Error {}
+";
+
+pub(crate) const NAMES: [&str; 4] = ["Error", "TypeError", "NameError", "IndexError"];
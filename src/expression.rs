@@ -1 +1,398 @@
-use crate::token::Token;
+use core::cell::OnceCell;
+use core::fmt;
+use core::mem;
+use core::ops::Deref;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::prelude::*;
+use crate::token::{Kind, Token};
+
+// Names a node of an Ast: the tree it belongs to and its index there. Trees
+// are numbered from a process-wide counter, so an id from a freed tree can't
+// match a node of a new one in the tables kept beside them, such as the
+// resolver's bindings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ExprId {
+    ast: usize,
+    index: usize,
+}
+
+impl ExprId {
+    pub fn index(self) -> usize {
+        self.index
+    }
+}
+
+// The expressions of one parse, side by side in the order the parser made
+// them, so a node is an index into the tree rather than an allocation of its
+// own. Statements hold on to the tree through the Expr handles of their
+// expressions, so it lives as long as any code parsed into it, such as a
+// function a closure still refers to.
+pub struct Ast {
+    number: usize,
+    nodes: OnceCell<Vec<Expression>>,
+}
+
+impl Ast {
+    fn new() -> Ast {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        Ast {
+            number: NEXT.fetch_add(1, Ordering::Relaxed),
+            nodes: OnceCell::new(),
+        }
+    }
+
+    pub fn nodes(&self) -> &[Expression] {
+        self.nodes.get().expect("nodes of an unfinished tree")
+    }
+
+    pub fn get(&self, id: ExprId) -> &Expression {
+        debug_assert_eq!(id.ast, self.number, "node of another tree");
+        &self.nodes()[id.index]
+    }
+}
+
+// Builds an Ast as the parser goes. Handles to its nodes can be made before
+// it's finished, but only looked through once it is.
+pub(crate) struct AstBuilder {
+    ast: Rc<Ast>,
+    nodes: Vec<Expression>,
+}
+
+impl AstBuilder {
+    pub(crate) fn new() -> AstBuilder {
+        AstBuilder {
+            ast: Rc::new(Ast::new()),
+            nodes: Vec::new(),
+        }
+    }
+
+    pub(crate) fn add(&mut self, kind: ExpressionKind) -> ExprId {
+        let id = ExprId {
+            ast: self.ast.number,
+            index: self.nodes.len(),
+        };
+        self.nodes.push(Expression { id, kind });
+        id
+    }
+
+    pub(crate) fn get(&self, id: ExprId) -> &Expression {
+        &self.nodes[id.index]
+    }
+
+    pub(crate) fn handle(&self, id: ExprId) -> Expr {
+        Expr {
+            ast: Rc::clone(&self.ast),
+            id,
+        }
+    }
+
+    pub(crate) fn finish(self) {
+        let _ = self.ast.nodes.set(self.nodes);
+    }
+}
+
+#[derive(Debug)]
+pub struct Expression {
+    pub id: ExprId,
+    pub kind: ExpressionKind,
+}
+
+// A node together with the tree it's in, which it keeps alive. It derefs to
+// the node; `get` follows the ids of the node's children.
+#[derive(Clone)]
+pub struct Expr {
+    ast: Rc<Ast>,
+    id: ExprId,
+}
+
+impl Deref for Expr {
+    type Target = Expression;
+
+    fn deref(&self) -> &Expression {
+        self.ast.get(self.id)
+    }
+}
+
+impl fmt::Debug for Expr {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, formatter)
+    }
+}
+
+#[derive(Debug)]
+pub enum ExpressionKind {
+    Literal(Literal),
+    Grouping(ExprId),
+    Unary {
+        operator: Token,
+        right: ExprId,
+    },
+    Binary {
+        left: ExprId,
+        operator: Token,
+        right: ExprId,
+    },
+    Logical {
+        left: ExprId,
+        operator: Token,
+        right: ExprId,
+    },
+    Variable(Token),
+    Assign {
+        name: Token,
+        value: ExprId,
+    },
+    Call {
+        callee: ExprId,
+        parenthesis: Token,
+        arguments: Vec<ExprId>,
+    },
+    Get {
+        object: ExprId,
+        name: Token,
+    },
+    Set {
+        object: ExprId,
+        name: Token,
+        value: ExprId,
+    },
+    // Written bare, as `return a, b;` gives several values, the list has
+    // no brackets and `bracket` is its first comma.
+    List {
+        bracket: Token,
+        elements: Vec<ExprId>,
+    },
+    Map {
+        brace: Token,
+        entries: Vec<(ExprId, ExprId)>,
+    },
+    Index {
+        object: ExprId,
+        bracket: Token,
+        index: ExprId,
+    },
+    SetIndex {
+        object: ExprId,
+        bracket: Token,
+        index: ExprId,
+        value: ExprId,
+    },
+    This(Token),
+    Super {
+        keyword: Token,
+        method: Token,
+    },
+}
+
+impl Expr {
+    // The node `id` names in the same tree, such as a child of this one.
+    pub fn get(&self, id: ExprId) -> Expr {
+        Expr {
+            ast: Rc::clone(&self.ast),
+            id,
+        }
+    }
+
+    pub fn ast(&self) -> &Rc<Ast> {
+        &self.ast
+    }
+
+    // The line of the leftmost token, if the expression has one; literals
+    // don't keep theirs.
+    pub fn line(&self) -> Option<usize> {
+        let (operand, links) = self.chain();
+        operand
+            .own_line()
+            .or_else(|| links.first().and_then(|link| link.own_line()))
+    }
+
+    // These leave out the operand a link of a chain continues from, which
+    // line, token_count and node_count take from chain instead.
+    fn own_line(&self) -> Option<usize> {
+        match &self.kind {
+            ExpressionKind::Literal(_) => None,
+            ExpressionKind::Grouping(expression) => self.get(*expression).line(),
+            ExpressionKind::Unary { operator, .. } => Some(operator.line()),
+            ExpressionKind::Binary { operator, .. } | ExpressionKind::Logical { operator, .. } => {
+                Some(operator.line())
+            }
+            ExpressionKind::Variable(name) | ExpressionKind::This(name) => Some(name.line()),
+            ExpressionKind::Assign { name, .. } => Some(name.line()),
+            ExpressionKind::Call { parenthesis, .. } => Some(parenthesis.line()),
+            ExpressionKind::Get { name, .. } => Some(name.line()),
+            ExpressionKind::Set { object, name, .. } => {
+                self.get(*object).line().or(Some(name.line()))
+            }
+            ExpressionKind::List { bracket, .. } => Some(bracket.line()),
+            ExpressionKind::Map { brace, .. } => Some(brace.line()),
+            ExpressionKind::Index { bracket, .. } => Some(bracket.line()),
+            ExpressionKind::SetIndex {
+                object, bracket, ..
+            } => self.get(*object).line().or(Some(bracket.line())),
+            ExpressionKind::Super { keyword, .. } => Some(keyword.line()),
+        }
+    }
+
+    // How many tokens the expression was parsed from.
+    pub fn token_count(&self) -> usize {
+        let (operand, links) = self.chain();
+        operand.own_token_count()
+            + links
+                .iter()
+                .map(|link| link.own_token_count())
+                .sum::<usize>()
+    }
+
+    fn own_token_count(&self) -> usize {
+        match &self.kind {
+            ExpressionKind::Literal(_) | ExpressionKind::Variable(_) | ExpressionKind::This(_) => 1,
+            ExpressionKind::Grouping(expression) => self.get(*expression).token_count() + 2,
+            ExpressionKind::Unary { right, .. } => 1 + self.get(*right).token_count(),
+            ExpressionKind::Binary { right, .. } | ExpressionKind::Logical { right, .. } => {
+                1 + self.get(*right).token_count()
+            }
+            ExpressionKind::Assign { value, .. } => 2 + self.get(*value).token_count(),
+            ExpressionKind::Call { arguments, .. } => {
+                2 + arguments
+                    .iter()
+                    .map(|argument| self.get(*argument).token_count())
+                    .sum::<usize>()
+                    + list_count(arguments.len())
+            }
+            ExpressionKind::Get { .. } => 2,
+            ExpressionKind::Set { object, value, .. } => {
+                self.get(*object).token_count() + 3 + self.get(*value).token_count()
+            }
+            ExpressionKind::List { bracket, elements } => {
+                let brackets = if bracket.kind == Kind::Comma { 0 } else { 2 };
+                brackets
+                    + elements
+                        .iter()
+                        .map(|element| self.get(*element).token_count())
+                        .sum::<usize>()
+                    + list_count(elements.len())
+            }
+            ExpressionKind::Map { entries, .. } => {
+                2 + entries
+                    .iter()
+                    .map(|(key, value)| {
+                        self.get(*key).token_count() + 1 + self.get(*value).token_count()
+                    })
+                    .sum::<usize>()
+                    + list_count(entries.len())
+            }
+            ExpressionKind::Index { index, .. } => 2 + self.get(*index).token_count(),
+            ExpressionKind::SetIndex {
+                object,
+                index,
+                value,
+                ..
+            } => {
+                self.get(*object).token_count()
+                    + 3
+                    + self.get(*index).token_count()
+                    + self.get(*value).token_count()
+            }
+            ExpressionKind::Super { .. } => 3,
+        }
+    }
+
+    // How many expressions make up this one, itself included.
+    pub fn node_count(&self) -> usize {
+        let (operand, links) = self.chain();
+        operand.own_node_count()
+            + links
+                .iter()
+                .map(|link| link.own_node_count())
+                .sum::<usize>()
+    }
+
+    fn own_node_count(&self) -> usize {
+        let children = match &self.kind {
+            ExpressionKind::Literal(_)
+            | ExpressionKind::Variable(_)
+            | ExpressionKind::This(_)
+            | ExpressionKind::Super { .. } => 0,
+            ExpressionKind::Grouping(expression) => self.get(*expression).node_count(),
+            ExpressionKind::Unary { right, .. } => self.get(*right).node_count(),
+            ExpressionKind::Binary { right, .. } | ExpressionKind::Logical { right, .. } => {
+                self.get(*right).node_count()
+            }
+            ExpressionKind::Assign { value, .. } => self.get(*value).node_count(),
+            ExpressionKind::Call { arguments, .. } => self.count(arguments),
+            ExpressionKind::Get { .. } => 0,
+            ExpressionKind::Set { object, value, .. } => {
+                self.get(*object).node_count() + self.get(*value).node_count()
+            }
+            ExpressionKind::List { elements, .. } => self.count(elements),
+            ExpressionKind::Map { entries, .. } => entries
+                .iter()
+                .map(|(key, value)| self.get(*key).node_count() + self.get(*value).node_count())
+                .sum(),
+            ExpressionKind::Index { index, .. } => self.get(*index).node_count(),
+            ExpressionKind::SetIndex {
+                object,
+                index,
+                value,
+                ..
+            } => {
+                self.get(*object).node_count()
+                    + self.get(*index).node_count()
+                    + self.get(*value).node_count()
+            }
+        };
+        1 + children
+    }
+
+    // The operand a left-associative chain like `a + b + c`, `f()()` or
+    // `a.b[0]` continues from at this link, if the expression is one.
+    pub fn chained(&self) -> Option<ExprId> {
+        match &self.kind {
+            ExpressionKind::Binary { left, .. } | ExpressionKind::Logical { left, .. } => {
+                Some(*left)
+            }
+            ExpressionKind::Call { callee, .. } => Some(*callee),
+            ExpressionKind::Get { object, .. } | ExpressionKind::Index { object, .. } => {
+                Some(*object)
+            }
+            _ => None,
+        }
+    }
+
+    // The chain that ends at this expression: the operand it starts from and
+    // its links from the inside out. Passes over the tree follow chains with
+    // this rather than recursing once per link, so a long chain doesn't need
+    // a deep stack.
+    pub fn chain(&self) -> (Expr, Vec<Expr>) {
+        let mut links = Vec::new();
+        let mut operand = self.clone();
+        while let Some(chained) = operand.chained() {
+            let link = mem::replace(&mut operand, self.get(chained));
+            links.push(link);
+        }
+        links.reverse();
+        (operand, links)
+    }
+
+    fn count(&self, expressions: &[ExprId]) -> usize {
+        expressions
+            .iter()
+            .map(|expression| self.get(*expression).node_count())
+            .sum()
+    }
+}
+
+// The separating commas in a list of `length` elements.
+pub(crate) fn list_count(length: usize) -> usize {
+    length.saturating_sub(1)
+}
+
+#[derive(Debug, Clone)]
+pub enum Literal {
+    Nil,
+    Boolean(bool),
+    Number(f64),
+    Integer(i64),
+    String(Rc<str>),
+}
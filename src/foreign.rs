@@ -0,0 +1,97 @@
+use core::any::Any;
+use core::cell::{Ref, RefCell, RefMut};
+
+use crate::error::RuntimeError;
+use crate::interpreter::Interpreter;
+use crate::prelude::*;
+use crate::value::Value;
+
+pub type ForeignGetter = Rc<dyn Fn(&mut Interpreter, &Foreign) -> Result<Value, RuntimeError>>;
+pub type ForeignSetter = Rc<dyn Fn(&mut Interpreter, &Foreign, Value) -> Result<(), RuntimeError>>;
+pub type ForeignMethod =
+    Rc<dyn Fn(&mut Interpreter, &Foreign, &[Value]) -> Result<Value, RuntimeError>>;
+
+pub struct ForeignClass {
+    name: String,
+    getters: HashMap<String, ForeignGetter>,
+    setters: HashMap<String, ForeignSetter>,
+    methods: HashMap<String, (usize, ForeignMethod)>,
+}
+
+impl ForeignClass {
+    pub fn new(name: &str) -> ForeignClass {
+        ForeignClass {
+            name: name.to_string(),
+            getters: HashMap::new(),
+            setters: HashMap::new(),
+            methods: HashMap::new(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn getter<F>(mut self, name: &str, getter: F) -> ForeignClass
+    where
+        F: Fn(&mut Interpreter, &Foreign) -> Result<Value, RuntimeError> + 'static,
+    {
+        self.getters.insert(name.to_string(), Rc::new(getter));
+        self
+    }
+
+    pub fn setter<F>(mut self, name: &str, setter: F) -> ForeignClass
+    where
+        F: Fn(&mut Interpreter, &Foreign, Value) -> Result<(), RuntimeError> + 'static,
+    {
+        self.setters.insert(name.to_string(), Rc::new(setter));
+        self
+    }
+
+    pub fn method<F>(mut self, name: &str, arity: usize, method: F) -> ForeignClass
+    where
+        F: Fn(&mut Interpreter, &Foreign, &[Value]) -> Result<Value, RuntimeError> + 'static,
+    {
+        self.methods
+            .insert(name.to_string(), (arity, Rc::new(method)));
+        self
+    }
+
+    pub fn instance<T: Any>(self: &Rc<Self>, data: T) -> Value {
+        Value::Foreign(Rc::new(Foreign {
+            class: Rc::clone(self),
+            data: Rc::new(RefCell::new(data)),
+        }))
+    }
+
+    pub fn getter_for(&self, name: &str) -> Option<ForeignGetter> {
+        self.getters.get(name).cloned()
+    }
+
+    pub fn setter_for(&self, name: &str) -> Option<ForeignSetter> {
+        self.setters.get(name).cloned()
+    }
+
+    pub fn method_for(&self, name: &str) -> Option<(usize, ForeignMethod)> {
+        self.methods.get(name).cloned()
+    }
+}
+
+pub struct Foreign {
+    class: Rc<ForeignClass>,
+    data: Rc<RefCell<dyn Any>>,
+}
+
+impl Foreign {
+    pub fn class(&self) -> &ForeignClass {
+        &self.class
+    }
+
+    pub fn borrow<T: Any>(&self) -> Option<Ref<'_, T>> {
+        Ref::filter_map(self.data.borrow(), |data| data.downcast_ref::<T>()).ok()
+    }
+
+    pub fn borrow_mut<T: Any>(&self) -> Option<RefMut<'_, T>> {
+        RefMut::filter_map(self.data.borrow_mut(), |data| data.downcast_mut::<T>()).ok()
+    }
+}
@@ -0,0 +1,830 @@
+#[cfg(feature = "std")]
+use std::path::{Path, PathBuf};
+
+use crate::error::Error;
+use crate::expression::{self, Expr, ExprId, ExpressionKind, Literal};
+use crate::parser::Parser;
+use crate::prelude::*;
+use crate::scanner::Scanner;
+use crate::settings::{self, Setting, Value};
+use crate::statement::{FunctionDeclaration, Pattern, Statement};
+use crate::token::{Keyword, Kind, Token};
+
+// Looked for in the directory of the file being formatted and then in each
+// directory above it.
+pub const CONFIG_FILE: &str = ".loxfmt.toml";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BraceStyle {
+    // `if (ready) {`
+    SameLine,
+    // The opening brace on a line of its own, lined up with its statement.
+    NextLine,
+}
+
+impl BraceStyle {
+    pub fn from_name(name: &str) -> Option<BraceStyle> {
+        match name {
+            "same-line" => Some(BraceStyle::SameLine),
+            "next-line" => Some(BraceStyle::NextLine),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Style {
+    pub indent_width: usize,
+    pub use_tabs: bool,
+    pub max_width: usize,
+    pub brace_style: BraceStyle,
+}
+
+impl Default for Style {
+    fn default() -> Style {
+        Style {
+            indent_width: 2,
+            use_tabs: false,
+            max_width: 80,
+            brace_style: BraceStyle::SameLine,
+        }
+    }
+}
+
+impl Style {
+    // Reads a .loxfmt.toml over the defaults.
+    pub fn parse(text: &str) -> Result<Style, String> {
+        Style::from_settings(settings::parse(text)?)
+    }
+
+    // The nearest .loxfmt.toml at or above `directory`.
+    #[cfg(feature = "std")]
+    pub fn find(directory: &Path) -> Option<PathBuf> {
+        settings::find(directory, CONFIG_FILE)
+    }
+
+    #[cfg(feature = "std")]
+    pub fn load(path: &Path) -> Result<Style, String> {
+        Style::from_settings(settings::read(path)?)
+            .map_err(|message| format!("{}: {}", path.display(), message))
+    }
+
+    fn from_settings(settings: Vec<Setting>) -> Result<Style, String> {
+        let mut style = Style::default();
+        for setting in settings {
+            match (setting.key.as_str(), &setting.value) {
+                ("indent_width", Value::Integer(width)) if *width > 0 => {
+                    style.indent_width = *width
+                }
+                ("use_tabs", Value::Boolean(use_tabs)) => style.use_tabs = *use_tabs,
+                ("max_width", Value::Integer(width)) => style.max_width = *width,
+                ("brace_style", Value::String(name)) => {
+                    style.brace_style =
+                        BraceStyle::from_name(name).ok_or_else(|| setting.invalid())?
+                }
+                ("indent_width" | "use_tabs" | "max_width" | "brace_style", _) => {
+                    return Err(setting.invalid())
+                }
+                _ => return Err(setting.unknown()),
+            }
+        }
+        Ok(style)
+    }
+
+    fn indent(&self, depth: usize) -> String {
+        if self.use_tabs {
+            "\t".repeat(depth)
+        } else {
+            " ".repeat(depth * self.indent_width)
+        }
+    }
+}
+
+struct Comment {
+    text: String,
+    line: usize,
+    // Whether code comes before the comment on its line.
+    trailing: bool,
+}
+
+// Re-prints `source` from its syntax tree in `style`, one statement per line
+// with spaces around binary operators. Argument lists, list literals and map
+// literals that would run past the maximum width are broken one element per
+// line. Comments and single blank lines are carried over from the source.
+pub fn format(source: &str, style: &Style) -> Result<String, Vec<Error>> {
+    let mut scanner = Scanner::new(source.to_string());
+    scanner.set_trivia(true);
+    let tokens = scanner.scan_tokens().map_err(|error| vec![error])?;
+
+    let mut code: Vec<Token> = Vec::new();
+    let mut comments: Vec<Vec<Comment>> = vec![Vec::new()];
+    for token in tokens {
+        if let Kind::Comment(text) = &token.kind {
+            let trailing = code
+                .last()
+                .is_some_and(|previous| previous.line() == token.line());
+            if let Some(before) = comments.last_mut() {
+                before.push(Comment {
+                    text: format!("//{}", text.trim_end()),
+                    line: token.line(),
+                    trailing,
+                });
+            }
+        } else {
+            code.push(token);
+            comments.push(Vec::new());
+        }
+    }
+
+    let statements = Parser::new(code.clone()).parse()?;
+
+    let mut printer = Printer {
+        tokens: &code,
+        comments,
+        cursor: 0,
+        style,
+        lines: Vec::new(),
+        last_line: 0,
+        open: false,
+        opened: false,
+    };
+    printer.statements(&statements, 0);
+    printer.comments(0);
+
+    let mut output = printer.lines.join("\n");
+    if !output.is_empty() {
+        output.push('\n');
+    }
+    Ok(output)
+}
+
+// Walks the syntax tree alongside the tokens it was parsed from. The cursor
+// is the index of the next token to print, which places each comment and
+// recovers the clauses of `for` loops the parser has rewritten as `while`.
+struct Printer<'a> {
+    tokens: &'a [Token],
+    // The comments before each token.
+    comments: Vec<Vec<Comment>>,
+    cursor: usize,
+    style: &'a Style,
+    lines: Vec<String>,
+    // The source line printed last, for carrying blank lines over.
+    last_line: usize,
+    // Whether the last line can be extended, which it can't once it ends in
+    // a comment.
+    open: bool,
+    // Whether the last line opens a block.
+    opened: bool,
+}
+
+impl Printer<'_> {
+    fn statements(&mut self, statements: &[Rc<Statement>], depth: usize) {
+        for statement in statements {
+            self.statement(statement, depth, false);
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement, depth: usize, inline: bool) {
+        self.comments(depth);
+        let inline = inline && self.open;
+
+        if self.at(Kind::Keyword(Keyword::For)) && !matches!(statement, Statement::ForIn { .. }) {
+            return self.for_loop(statement, depth, inline);
+        }
+
+        match statement {
+            Statement::Expression(expression) => {
+                self.simple(depth, inline, String::new(), 0, Some(expression))
+            }
+            Statement::Print { value, .. } => {
+                self.simple(depth, inline, "print ".to_string(), 1, Some(value))
+            }
+            Statement::Variable { name, initializer } => match initializer {
+                Some(initializer) => self.simple(
+                    depth,
+                    inline,
+                    format!("var {} = ", name.lexeme()),
+                    3,
+                    Some(initializer),
+                ),
+                None => self.simple(depth, inline, format!("var {}", name.lexeme()), 2, None),
+            },
+            Statement::Destructure {
+                pattern,
+                names,
+                initializer,
+                ..
+            } => {
+                let tokens = 2 * names.len() + 1;
+                let names = names
+                    .iter()
+                    .map(Token::lexeme)
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let (pattern, tokens) = match pattern {
+                    Pattern::Elements => (format!("[{}]", names), tokens + 2),
+                    Pattern::Fields => (format!("{{{}}}", names), tokens + 2),
+                    Pattern::Values => (names, tokens),
+                };
+                let prefix = format!("var {} = ", pattern);
+                self.simple(depth, inline, prefix, tokens, Some(initializer))
+            }
+            Statement::Return { value, .. } => {
+                self.simple(depth, inline, "return ".to_string(), 1, value.as_ref())
+            }
+            Statement::Yield { value, .. } => {
+                self.simple(depth, inline, "yield ".to_string(), 1, value.as_ref())
+            }
+            Statement::Throw { value, .. } => {
+                self.simple(depth, inline, "throw ".to_string(), 1, Some(value))
+            }
+            Statement::Try {
+                body,
+                name,
+                handler,
+                ..
+            } => {
+                self.open_block(depth, inline, "try".to_string(), 1);
+                self.statements(body, depth + 1);
+                self.close_block(depth);
+
+                self.comments(depth);
+                let after_block = self.open
+                    && self.style.brace_style == BraceStyle::SameLine
+                    && self.lines.last().is_some_and(|line| line.ends_with('}'));
+                let header = format!("catch ({})", name.lexeme());
+                self.open_block(depth, after_block, header, 4);
+                self.statements(handler, depth + 1);
+                self.close_block(depth);
+            }
+            Statement::Defer { body, .. } => {
+                self.open_block(depth, inline, "defer".to_string(), 1);
+                self.statements(body, depth + 1);
+                self.close_block(depth);
+            }
+            Statement::Block(statements) => {
+                self.open_block(depth, inline, String::new(), 0);
+                self.statements(statements, depth + 1);
+                self.close_block(depth);
+            }
+            Statement::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.header(depth, inline, "if (", condition, ")", 3);
+                self.body(then_branch, depth);
+
+                if let Some(else_branch) = else_branch {
+                    self.comments(depth);
+                    let after_block = self.open
+                        && self.style.brace_style == BraceStyle::SameLine
+                        && self.lines.last().is_some_and(|line| line.ends_with('}'));
+                    self.emit(depth, after_block, "else".to_string());
+                    self.advance(1);
+
+                    if self.at(Kind::Keyword(Keyword::If)) {
+                        self.statement(else_branch, depth, true);
+                    } else {
+                        self.body(else_branch, depth);
+                    }
+                }
+            }
+            Statement::While {
+                condition, body, ..
+            } => {
+                self.header(depth, inline, "while (", condition, ")", 3);
+                self.body(body, depth);
+            }
+            Statement::ForIn {
+                name,
+                iterable,
+                body,
+            } => {
+                let prefix = format!("for (var {} in ", name.lexeme());
+                self.header(depth, inline, &prefix, iterable, ")", 6);
+                self.body(body, depth);
+            }
+            Statement::Function(declaration) => self.function(declaration, depth, inline, true),
+            Statement::Class {
+                name,
+                superclass,
+                methods,
+            } => {
+                let (header, tokens) = match superclass {
+                    Some(superclass) => {
+                        (format!("class {} < {}", name.lexeme(), flat(superclass)), 4)
+                    }
+                    None => (format!("class {}", name.lexeme()), 2),
+                };
+                self.open_block(depth, inline, header, tokens);
+                for method in methods {
+                    self.comments(depth + 1);
+                    self.function(method, depth + 1, false, false);
+                }
+                self.close_block(depth);
+            }
+        }
+    }
+
+    // A statement that ends in a semicolon, made of `tokens` tokens spelled
+    // by `prefix` and then `value`.
+    fn simple(
+        &mut self,
+        depth: usize,
+        inline: bool,
+        prefix: String,
+        tokens: usize,
+        value: Option<&Expr>,
+    ) {
+        let tokens = tokens + value.map_or(0, Expr::token_count) + 1;
+        let inline = !self.hoist(depth, tokens) && inline;
+
+        let column = self.column(depth, inline) + width(&prefix);
+        let text = match value {
+            Some(value) => format!("{}{};", prefix, self.expression(value, column, depth, 1)),
+            None => format!("{};", prefix.trim_end()),
+        };
+        self.emit(depth, inline, text);
+        self.advance(tokens);
+    }
+
+    // `prefix`, then `value`, then `suffix`: the part of a compound statement
+    // before its body, `tokens` tokens besides those of `value`.
+    fn header(
+        &mut self,
+        depth: usize,
+        inline: bool,
+        prefix: &str,
+        value: &Expr,
+        suffix: &str,
+        tokens: usize,
+    ) {
+        let tokens = tokens + value.token_count();
+        let inline = !self.hoist(depth, tokens) && inline;
+
+        let column = self.column(depth, inline) + width(prefix);
+        let value = self.expression(value, column, depth, width(suffix) + 2);
+        self.emit(depth, inline, format!("{}{}{}", prefix, value, suffix));
+        self.advance(tokens);
+    }
+
+    // Blocks go on the line of the statement that owns them; other bodies do
+    // too unless a comment stands in the way.
+    fn body(&mut self, body: &Statement, depth: usize) {
+        if self.at(Kind::OpenCurlyBracket) || self.comments[self.cursor].is_empty() {
+            self.statement(body, depth, true);
+        } else {
+            self.statement(body, depth + 1, false);
+        }
+    }
+
+    // The parser turns `for (initializer; condition; increment) body` into
+    // `{ initializer; while (condition) { body; increment; } }`, so which
+    // clauses were written is read back from the tokens.
+    fn for_loop(&mut self, statement: &Statement, depth: usize, inline: bool) {
+        let mut position = self.cursor + 2;
+        let mut header = "for (".to_string();
+
+        let mut loop_statement = statement;
+        if self.kind_at(position) == &Kind::Semicolon {
+            header.push(';');
+            position += 1;
+        } else if let Statement::Block(statements) = statement {
+            let initializer = match &*statements[0] {
+                Statement::Variable {
+                    name,
+                    initializer: Some(initializer),
+                } => {
+                    position += 4 + initializer.token_count();
+                    format!("var {} = {};", name.lexeme(), flat(initializer))
+                }
+                Statement::Variable { name, .. } => {
+                    position += 3;
+                    format!("var {};", name.lexeme())
+                }
+                Statement::Expression(expression) => {
+                    position += expression.token_count() + 1;
+                    format!("{};", flat(expression))
+                }
+                _ => unreachable!(),
+            };
+            header.push_str(&initializer);
+            loop_statement = &statements[1];
+        }
+
+        let Statement::While {
+            condition, body, ..
+        } = loop_statement
+        else {
+            unreachable!()
+        };
+        if self.kind_at(position) != &Kind::Semicolon {
+            header.push(' ');
+            header.push_str(&flat(condition));
+            position += condition.token_count();
+        }
+        header.push(';');
+        position += 1;
+
+        let mut body = &**body;
+        if self.kind_at(position) != &Kind::CloseParenthesis {
+            let Statement::Block(statements) = body else {
+                unreachable!()
+            };
+            let Statement::Expression(increment) = &*statements[1] else {
+                unreachable!()
+            };
+            header.push(' ');
+            header.push_str(&flat(increment));
+            position += increment.token_count();
+            body = &statements[0];
+        }
+        header.push(')');
+        position += 1;
+
+        let tokens = position - self.cursor;
+        let inline = !self.hoist(depth, tokens) && inline;
+        self.emit(depth, inline, header);
+        self.advance(tokens);
+        self.body(body, depth);
+    }
+
+    fn function(
+        &mut self,
+        declaration: &FunctionDeclaration,
+        depth: usize,
+        inline: bool,
+        keyword: bool,
+    ) {
+        let mut parameters: Vec<String> =
+            declaration.parameters.iter().map(Token::lexeme).collect();
+        let mut tokens = parameters.len();
+        let required = declaration.arity().required;
+        for (parameter, default) in parameters[required..].iter_mut().zip(&declaration.defaults) {
+            *parameter = format!("{} = {}", parameter, flat(default));
+            tokens += 1 + default.token_count();
+        }
+        if let Some(rest) = &declaration.rest {
+            parameters.push(format!("...{}", rest.lexeme()));
+            tokens += 2;
+        }
+        let header = format!(
+            "{}{}({})",
+            if keyword { "fun " } else { "" },
+            declaration.name.lexeme(),
+            parameters.join(", ")
+        );
+        let tokens = usize::from(keyword) + 3 + tokens + expression::list_count(parameters.len());
+
+        self.open_block(depth, inline, header, tokens);
+        self.statements(&declaration.body, depth + 1);
+        self.close_block(depth);
+    }
+
+    // `header` and then the opening brace, after `tokens` tokens.
+    fn open_block(&mut self, depth: usize, inline: bool, header: String, tokens: usize) {
+        let inline = !self.hoist(depth, tokens + 1) && inline;
+        match self.style.brace_style {
+            BraceStyle::SameLine if header.is_empty() => self.emit(depth, inline, "{".to_string()),
+            BraceStyle::SameLine => self.emit(depth, inline, format!("{} {{", header)),
+            BraceStyle::NextLine if header.is_empty() => self.emit(depth, false, "{".to_string()),
+            BraceStyle::NextLine => {
+                self.emit(depth, inline, header);
+                self.lines.push(format!("{}{{", self.style.indent(depth)));
+            }
+        }
+        self.advance(tokens + 1);
+        self.opened = true;
+    }
+
+    fn close_block(&mut self, depth: usize) {
+        self.comments(depth + 1);
+        if self.opened && self.open {
+            self.append("}");
+        } else {
+            self.lines.push(format!("{}}}", self.style.indent(depth)));
+            self.open = true;
+        }
+        self.opened = false;
+        self.advance(1);
+    }
+
+    // Prints the comments before the cursor: those that share a line with
+    // the code before them stay at the end of that line.
+    fn comments(&mut self, depth: usize) {
+        for comment in core::mem::take(&mut self.comments[self.cursor]) {
+            if comment.trailing && self.open {
+                self.append(" ");
+                self.append(&comment.text);
+            } else {
+                self.start(depth, comment.line, comment.text);
+            }
+            self.last_line = comment.line;
+            self.open = false;
+            self.opened = false;
+        }
+    }
+
+    // Moves the comments from within the next `tokens` tokens onto their own
+    // lines ahead of them, returning whether there were any.
+    fn hoist(&mut self, depth: usize, tokens: usize) -> bool {
+        let line = self.start_line(self.cursor);
+        let mut hoisted = false;
+        for index in self.cursor + 1..self.cursor + tokens {
+            for comment in core::mem::take(&mut self.comments[index]) {
+                self.start(depth, line, comment.text);
+                self.last_line = line;
+                self.open = false;
+                hoisted = true;
+            }
+        }
+        hoisted
+    }
+
+    fn emit(&mut self, depth: usize, inline: bool, text: String) {
+        if inline {
+            self.append(" ");
+            self.append(&text);
+        } else {
+            self.start(depth, self.start_line(self.cursor), text);
+        }
+        self.open = true;
+        self.opened = false;
+    }
+
+    // Begins a new line, keeping a blank line before it when the source had
+    // one, except at the top of a block.
+    fn start(&mut self, depth: usize, line: usize, text: String) {
+        if line > self.last_line + 1 && !self.lines.is_empty() && !self.opened {
+            self.lines.push(String::new());
+        }
+        self.lines
+            .push(format!("{}{}", self.style.indent(depth), text));
+    }
+
+    fn append(&mut self, text: &str) {
+        if let Some(line) = self.lines.last_mut() {
+            line.push_str(text);
+        }
+    }
+
+    fn advance(&mut self, tokens: usize) {
+        self.cursor += tokens;
+        self.last_line = self.tokens[self.cursor - 1].line();
+    }
+
+    // Where text would start on the current line.
+    fn column(&self, depth: usize, inline: bool) -> usize {
+        match (inline, self.lines.last()) {
+            (true, Some(line)) => {
+                let line = line.rsplit('\n').next().unwrap_or_default();
+                let tabs = line.chars().filter(|&character| character == '\t').count();
+                width(line) + tabs * self.style.indent_width.saturating_sub(1) + 1
+            }
+            _ => depth * self.style.indent_width,
+        }
+    }
+
+    fn at(&self, kind: Kind) -> bool {
+        self.kind_at(self.cursor) == &kind
+    }
+
+    fn kind_at(&self, index: usize) -> &Kind {
+        &self.tokens[index].kind
+    }
+
+    // Tokens record the line they end on, which differs for strings that
+    // span lines.
+    fn start_line(&self, index: usize) -> usize {
+        let token = &self.tokens[index];
+        match &token.kind {
+            Kind::String(string) => token.line() - string.matches('\n').count(),
+            _ => token.line(),
+        }
+    }
+
+    // `expression` starting at `column`, with `reserve` characters to follow
+    // it on its last line. When it doesn't fit, the rightmost argument list
+    // or literal is broken one element per line.
+    fn expression(&self, expression: &Expr, column: usize, depth: usize, reserve: usize) -> String {
+        let text = flat(expression);
+        if column + width(&text) + reserve <= self.style.max_width {
+            return text;
+        }
+
+        match &expression.kind {
+            ExpressionKind::Call {
+                callee, arguments, ..
+            } if !arguments.is_empty() => {
+                let elements = arguments
+                    .iter()
+                    .map(|argument| (String::new(), expression.get(*argument)));
+                format!(
+                    "{}({})",
+                    flat(&expression.get(*callee)),
+                    self.elements(elements, depth)
+                )
+            }
+            ExpressionKind::List { bracket, elements }
+                if bracket.kind != Kind::Comma && !elements.is_empty() =>
+            {
+                let elements = elements
+                    .iter()
+                    .map(|element| (String::new(), expression.get(*element)));
+                format!("[{}]", self.elements(elements, depth))
+            }
+            ExpressionKind::Map { entries, .. } if !entries.is_empty() => {
+                let entries = entries.iter().map(|(key, value)| {
+                    let key = format!("{}: ", flat(&expression.get(*key)));
+                    (key, expression.get(*value))
+                });
+                format!("{{{}}}", self.elements(entries, depth))
+            }
+            ExpressionKind::Grouping(inner) => {
+                format!(
+                    "({})",
+                    self.expression(&expression.get(*inner), column + 1, depth, reserve + 1)
+                )
+            }
+            ExpressionKind::Unary { operator, right } => {
+                let operator = operator.lexeme();
+                let right = self.expression(
+                    &expression.get(*right),
+                    column + width(&operator),
+                    depth,
+                    reserve,
+                );
+                format!("{}{}", operator, right)
+            }
+            ExpressionKind::Binary {
+                left,
+                operator,
+                right,
+            }
+            | ExpressionKind::Logical {
+                left,
+                operator,
+                right,
+            } => {
+                let prefix = format!("{} {} ", flat(&expression.get(*left)), operator.lexeme());
+                self.suffixed(prefix, &expression.get(*right), column, depth, reserve)
+            }
+            ExpressionKind::Assign { name, value } => self.suffixed(
+                format!("{} = ", name.lexeme()),
+                &expression.get(*value),
+                column,
+                depth,
+                reserve,
+            ),
+            ExpressionKind::Set {
+                object,
+                name,
+                value,
+            } => {
+                let prefix = format!("{}.{} = ", flat(&expression.get(*object)), name.lexeme());
+                self.suffixed(prefix, &expression.get(*value), column, depth, reserve)
+            }
+            ExpressionKind::SetIndex {
+                object,
+                index,
+                value,
+                ..
+            } => {
+                let prefix = format!(
+                    "{}[{}] = ",
+                    flat(&expression.get(*object)),
+                    flat(&expression.get(*index))
+                );
+                self.suffixed(prefix, &expression.get(*value), column, depth, reserve)
+            }
+            _ => text,
+        }
+    }
+
+    fn suffixed(
+        &self,
+        prefix: String,
+        expression: &Expr,
+        column: usize,
+        depth: usize,
+        reserve: usize,
+    ) -> String {
+        let column = column + width(&prefix);
+        prefix + &self.expression(expression, column, depth, reserve)
+    }
+
+    // One element per line, a level deeper than `depth`, with the closing
+    // bracket back on `depth`.
+    fn elements(&self, elements: impl Iterator<Item = (String, Expr)>, depth: usize) -> String {
+        let indent = self.style.indent(depth + 1);
+        let elements: Vec<String> = elements
+            .map(|(prefix, element)| {
+                let column = (depth + 1) * self.style.indent_width + width(&prefix);
+                let element = self.expression(&element, column, depth + 1, 1);
+                format!("\n{}{}{}", indent, prefix, element)
+            })
+            .collect();
+        format!("{}\n{}", elements.join(","), self.style.indent(depth))
+    }
+}
+
+fn flat(expression: &Expr) -> String {
+    let (operand, links) = expression.chain();
+    let mut text = flat_node(&operand, String::new());
+    for link in links {
+        text = flat_node(&link, text);
+    }
+    text
+}
+
+// `chained` is the text of the operand a link of a chain continues from.
+fn flat_node(expression: &Expr, chained: String) -> String {
+    match &expression.kind {
+        ExpressionKind::Literal(literal) => match literal {
+            Literal::Nil => "nil".to_string(),
+            Literal::Boolean(boolean) => boolean.to_string(),
+            Literal::Number(number) => format!("{}", number),
+            Literal::Integer(integer) => format!("{}", integer),
+            Literal::String(string) => format!("\"{}\"", string),
+        },
+        ExpressionKind::Grouping(inner) => format!("({})", flat(&expression.get(*inner))),
+        ExpressionKind::Unary { operator, right } => {
+            format!("{}{}", operator.lexeme(), flat(&expression.get(*right)))
+        }
+        ExpressionKind::Binary {
+            operator, right, ..
+        }
+        | ExpressionKind::Logical {
+            operator, right, ..
+        } => format!(
+            "{} {} {}",
+            chained,
+            operator.lexeme(),
+            flat(&expression.get(*right))
+        ),
+        ExpressionKind::Variable(name) | ExpressionKind::This(name) => name.lexeme(),
+        ExpressionKind::Assign { name, value } => {
+            format!("{} = {}", name.lexeme(), flat(&expression.get(*value)))
+        }
+        ExpressionKind::Call { arguments, .. } => {
+            format!("{}({})", chained, join(expression, arguments))
+        }
+        ExpressionKind::Get { name, .. } => format!("{}.{}", chained, name.lexeme()),
+        ExpressionKind::Set {
+            object,
+            name,
+            value,
+        } => format!(
+            "{}.{} = {}",
+            flat(&expression.get(*object)),
+            name.lexeme(),
+            flat(&expression.get(*value))
+        ),
+        ExpressionKind::List { bracket, elements } if bracket.kind == Kind::Comma => {
+            join(expression, elements)
+        }
+        ExpressionKind::List { elements, .. } => format!("[{}]", join(expression, elements)),
+        ExpressionKind::Map { entries, .. } => {
+            let entries: Vec<String> = entries
+                .iter()
+                .map(|(key, value)| {
+                    format!(
+                        "{}: {}",
+                        flat(&expression.get(*key)),
+                        flat(&expression.get(*value))
+                    )
+                })
+                .collect();
+            format!("{{{}}}", entries.join(", "))
+        }
+        ExpressionKind::Index { index, .. } => {
+            format!("{}[{}]", chained, flat(&expression.get(*index)))
+        }
+        ExpressionKind::SetIndex {
+            object,
+            index,
+            value,
+            ..
+        } => format!(
+            "{}[{}] = {}",
+            flat(&expression.get(*object)),
+            flat(&expression.get(*index)),
+            flat(&expression.get(*value))
+        ),
+        ExpressionKind::Super { method, .. } => format!("super.{}", method.lexeme()),
+    }
+}
+
+fn join(parent: &Expr, expressions: &[ExprId]) -> String {
+    let expressions: Vec<String> = expressions
+        .iter()
+        .map(|expression| flat(&parent.get(*expression)))
+        .collect();
+    expressions.join(", ")
+}
+
+fn width(text: &str) -> usize {
+    text.chars().count()
+}
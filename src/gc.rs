@@ -0,0 +1,744 @@
+use alloc::collections::btree_map::Entry;
+use alloc::collections::BTreeMap;
+use alloc::rc::Weak;
+use core::cell::RefCell;
+use core::mem;
+use core::time::Duration;
+
+use crate::environment::Environment;
+use crate::prelude::*;
+use crate::symbol::Symbol;
+use crate::trace::{self, Phase};
+use crate::value::{self, BoundMethod, Class, Closure, Function, Instance, Method, Upvalue, Value};
+
+pub const HEAP_GROW_FACTOR: usize = 2;
+pub const INITIAL_GC_THRESHOLD: usize = 1024 * 1024;
+pub const DEFAULT_NURSERY_BYTES: usize = 64 * 1024;
+
+pub(crate) trait Trace {
+    fn trace(&self, visit: &mut dyn FnMut(usize)) -> bool;
+
+    fn size(&self) -> usize;
+
+    // What the object is, for heap dumps.
+    fn describe(&self) -> String;
+
+    fn clear(&self) {}
+}
+
+// A live object as listed by `Heap::objects`, with the objects it refers to
+// as indices into the same listing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeapObject {
+    pub description: String,
+    pub size: usize,
+    pub references: Vec<usize>,
+}
+
+// Running totals since the heap's statistics were last reset.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HeapStats {
+    pub allocations: u64,
+    pub collections: u64,
+    pub peak_bytes: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GcMode {
+    // Every collection traces the whole heap.
+    #[default]
+    Full,
+    // New objects start in a nursery, collected on its own each time
+    // nursery_bytes have been allocated into it; whatever survives is
+    // promoted. A minor collection's pause depends on the nursery alone, but
+    // it can't free a cycle that runs through promoted objects, so the whole
+    // heap is still collected when it reaches the threshold.
+    Generational,
+}
+
+impl GcMode {
+    pub fn from_name(name: &str) -> Option<GcMode> {
+        match name {
+            "full" => Some(GcMode::Full),
+            "generational" => Some(GcMode::Generational),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GcConfig {
+    pub mode: GcMode,
+    pub nursery_bytes: usize,
+}
+
+impl Default for GcConfig {
+    fn default() -> GcConfig {
+        GcConfig {
+            mode: GcMode::Full,
+            nursery_bytes: DEFAULT_NURSERY_BYTES,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Collection {
+    pub minor: bool,
+    pub objects_freed: usize,
+    pub bytes_freed: usize,
+    pub bytes_remaining: usize,
+    pub next_gc: usize,
+    pub pause: Duration,
+}
+
+// Objects stay owned by `Rc`; the heap only keeps weak handles to everything that can take
+// part in a reference cycle. A collection counts the strong references each object receives
+// from other tracked objects. Any reference beyond those comes from outside the heap (the VM
+// stack and frames, globals, walker environments, values held by natives or the host), so
+// those objects are the roots. Whatever the mark phase can't reach from them is garbage kept
+// alive only by cycles, and sweeping clears its contents so the cycles fall apart.
+//
+// The objects before `old` have survived a collection; the rest are the nursery. A minor
+// collection runs the same algorithm over the nursery alone, where references from promoted
+// objects count as coming from outside and so keep their targets alive.
+pub struct Heap {
+    objects: Vec<Weak<dyn Trace>>,
+    old: usize,
+    config: GcConfig,
+    nursery_bytes: usize,
+    bytes_allocated: usize,
+    next_gc: usize,
+    stress: bool,
+    stats: HeapStats,
+    finalizers: Vec<(value::Weak, Box<dyn FnOnce()>)>,
+}
+
+impl Default for Heap {
+    fn default() -> Heap {
+        Heap::new()
+    }
+}
+
+impl Heap {
+    pub fn new() -> Heap {
+        Heap {
+            objects: Vec::new(),
+            old: 0,
+            config: GcConfig::default(),
+            nursery_bytes: 0,
+            bytes_allocated: 0,
+            next_gc: INITIAL_GC_THRESHOLD,
+            stress: false,
+            stats: HeapStats::default(),
+            finalizers: Vec::new(),
+        }
+    }
+
+    pub fn set_config(&mut self, config: GcConfig) {
+        self.config = config;
+    }
+
+    pub fn config(&self) -> GcConfig {
+        self.config
+    }
+
+    pub fn set_stress(&mut self, stress: bool) {
+        self.stress = stress;
+    }
+
+    pub fn stress(&self) -> bool {
+        self.stress
+    }
+
+    pub fn bytes_allocated(&self) -> usize {
+        self.bytes_allocated
+    }
+
+    pub fn next_gc(&self) -> usize {
+        self.next_gc
+    }
+
+    pub fn stats(&self) -> HeapStats {
+        self.stats
+    }
+
+    // The peak starts again from what is allocated now.
+    pub fn reset_stats(&mut self) {
+        self.stats = HeapStats {
+            peak_bytes: self.bytes_allocated,
+            ..HeapStats::default()
+        };
+    }
+
+    // Calls `finalizer` once the object has been freed, at the first
+    // collection after it goes or when the heap itself is dropped, for
+    // releasing the host resources an object stands for. Values that aren't
+    // objects can't have one.
+    pub fn add_finalizer(&mut self, value: &Value, finalizer: Box<dyn FnOnce()>) -> bool {
+        let Some(object) = value.downgrade() else {
+            return false;
+        };
+        self.finalizers.push((object, finalizer));
+        true
+    }
+
+    fn run_finalizers(&mut self) {
+        let (dead, alive) = mem::take(&mut self.finalizers)
+            .into_iter()
+            .partition(|(object, _)| !object.is_alive());
+        self.finalizers = alive;
+        for (_, finalizer) in dead {
+            finalizer();
+        }
+    }
+
+    pub(crate) fn track<T: Trace + 'static>(&mut self, object: &Rc<T>) -> Option<Collection> {
+        self.nursery_bytes += object.size();
+        self.stats.allocations += 1;
+        let weak: Weak<T> = Rc::downgrade(object);
+        self.objects.push(weak);
+
+        self.grow(object.size())
+    }
+
+    // Counts what a tracked object took on after it was tracked, like the
+    // elements pushed onto a list, which its size at tracking time missed.
+    pub(crate) fn grow(&mut self, bytes: usize) -> Option<Collection> {
+        self.bytes_allocated += bytes;
+        self.stats.peak_bytes = self.stats.peak_bytes.max(self.bytes_allocated);
+
+        if self.stress || self.bytes_allocated > self.next_gc {
+            Some(self.collect())
+        } else if self.config.mode == GcMode::Generational
+            && self.nursery_bytes > self.config.nursery_bytes
+        {
+            Some(self.collect_minor())
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn track_value(&mut self, value: &Value) -> Option<Collection> {
+        match value {
+            Value::Function(function) => {
+                let collection = self.track(&function.closure);
+                self.track(function).or(collection)
+            }
+            Value::Closure(closure) => self.track(closure),
+            Value::BoundMethod(bound) => self.track(bound),
+            Value::Class(class) => self.track(class),
+            Value::Instance(instance) => self.track(instance),
+            Value::List(list) => self.track(list),
+            Value::Map(map) => self.track(map),
+            _ => None,
+        }
+    }
+
+    // Every tracked object still alive, in the order it was tracked, each
+    // once, whether or not anything could still reach it.
+    pub fn objects(&self) -> Vec<HeapObject> {
+        let (objects, index) = self.live();
+        objects
+            .iter()
+            .map(|object| {
+                let mut references = Vec::new();
+                object.trace(&mut |child| references.extend(index.get(&child)));
+                HeapObject {
+                    description: object.describe(),
+                    size: object.size(),
+                    references,
+                }
+            })
+            .collect()
+    }
+
+    // The objects one per line, as "#index description (size bytes)" with
+    // the indices of what each refers to after an arrow.
+    pub fn dump(&self) -> String {
+        let objects = self.objects();
+        let bytes: usize = objects.iter().map(|object| object.size).sum();
+        let mut dump = format!("{} live objects, {} bytes\n", objects.len(), bytes);
+        for (index, object) in objects.iter().enumerate() {
+            dump.push_str(&format!(
+                "#{} {} ({} bytes)",
+                index, object.description, object.size
+            ));
+            if !object.references.is_empty() {
+                let references: Vec<String> = object
+                    .references
+                    .iter()
+                    .map(|reference| format!("#{}", reference))
+                    .collect();
+                dump.push_str(&format!(" -> {}", references.join(", ")));
+            }
+            dump.push('\n');
+        }
+        dump
+    }
+
+    // The live objects without duplicates, and where each one's address
+    // lies among them.
+    fn live(&self) -> (Vec<Rc<dyn Trace>>, BTreeMap<usize, usize>) {
+        live(&self.objects)
+    }
+
+    pub fn collect(&mut self) -> Collection {
+        let _collect = trace::enter(Phase::Collect);
+        self.stats.collections += 1;
+        let start = Stopwatch::start();
+
+        let (objects, index) = self.live();
+        self.objects = objects.iter().map(Rc::downgrade).collect();
+        let bytes_before = self.bytes_allocated;
+        sweep(objects, &index);
+        self.run_finalizers();
+
+        self.objects.retain(|object| object.strong_count() > 0);
+        self.old = self.objects.len();
+        self.nursery_bytes = 0;
+        self.bytes_allocated = bytes(&self.objects);
+        self.next_gc = (self.bytes_allocated * HEAP_GROW_FACTOR).max(INITIAL_GC_THRESHOLD);
+
+        Collection {
+            minor: false,
+            objects_freed: index.len() - self.objects.len(),
+            bytes_freed: bytes_before.saturating_sub(self.bytes_allocated),
+            bytes_remaining: self.bytes_allocated,
+            next_gc: self.next_gc,
+            pause: start.elapsed(),
+        }
+    }
+
+    // Collects the nursery and promotes what survives. Promoted objects that
+    // have since died stay listed until the next full collection.
+    pub fn collect_minor(&mut self) -> Collection {
+        let _collect = trace::enter(Phase::Collect);
+        self.stats.collections += 1;
+        let start = Stopwatch::start();
+
+        let (objects, index) = live(&self.objects[self.old..]);
+        let nursery_before: usize = objects.iter().map(|object| object.size()).sum();
+        self.objects.truncate(self.old);
+        self.objects.extend(objects.iter().map(Rc::downgrade));
+        sweep(objects, &index);
+        self.run_finalizers();
+
+        let mut survivors = self.objects.split_off(self.old);
+        survivors.retain(|object| object.strong_count() > 0);
+        let nursery_after = bytes(&survivors);
+        let objects_freed = index.len() - survivors.len();
+        self.objects.append(&mut survivors);
+        self.old = self.objects.len();
+        self.nursery_bytes = 0;
+        let bytes_freed = nursery_before.saturating_sub(nursery_after);
+        self.bytes_allocated = self.bytes_allocated.saturating_sub(bytes_freed);
+
+        Collection {
+            minor: true,
+            objects_freed,
+            bytes_freed,
+            bytes_remaining: self.bytes_allocated,
+            next_gc: self.next_gc,
+            pause: start.elapsed(),
+        }
+    }
+}
+
+// Times a collection's pause. wasm32-unknown-unknown has no clock, and
+// `Instant::now` panics there, so pauses read as zero.
+#[cfg(all(
+    feature = "std",
+    not(all(target_arch = "wasm32", target_os = "unknown"))
+))]
+struct Stopwatch(std::time::Instant);
+
+#[cfg(all(
+    feature = "std",
+    not(all(target_arch = "wasm32", target_os = "unknown"))
+))]
+impl Stopwatch {
+    fn start() -> Stopwatch {
+        Stopwatch(std::time::Instant::now())
+    }
+
+    fn elapsed(&self) -> Duration {
+        self.0.elapsed()
+    }
+}
+
+#[cfg(any(
+    not(feature = "std"),
+    all(target_arch = "wasm32", target_os = "unknown")
+))]
+struct Stopwatch;
+
+#[cfg(any(
+    not(feature = "std"),
+    all(target_arch = "wasm32", target_os = "unknown")
+))]
+impl Stopwatch {
+    fn start() -> Stopwatch {
+        Stopwatch
+    }
+
+    fn elapsed(&self) -> Duration {
+        Duration::ZERO
+    }
+}
+
+// Whatever is still alive is about to go with the session.
+impl Drop for Heap {
+    fn drop(&mut self) {
+        for (_, finalizer) in mem::take(&mut self.finalizers) {
+            finalizer();
+        }
+    }
+}
+
+// The live objects among `handles` without duplicates, and where each one's
+// address lies among them.
+fn live(handles: &[Weak<dyn Trace>]) -> (Vec<Rc<dyn Trace>>, BTreeMap<usize, usize>) {
+    let mut objects: Vec<Rc<dyn Trace>> = Vec::new();
+    let mut index: BTreeMap<usize, usize> = BTreeMap::new();
+    for object in handles.iter().filter_map(Weak::upgrade) {
+        if let Entry::Vacant(entry) = index.entry(address(&object)) {
+            entry.insert(objects.len());
+            objects.push(object);
+        }
+    }
+    (objects, index)
+}
+
+// Clears whatever among `objects` nothing outside them can reach. Strong
+// references beyond those the objects hold to each other make an object a
+// root.
+fn sweep(objects: Vec<Rc<dyn Trace>>, index: &BTreeMap<usize, usize>) {
+    let mut references: Vec<usize> = objects
+        .iter()
+        .map(|object| Rc::strong_count(object) - 1)
+        .collect();
+    let mut marked = vec![false; objects.len()];
+    for (position, object) in objects.iter().enumerate() {
+        let traced = object.trace(&mut |child| {
+            if let Some(&child) = index.get(&child) {
+                references[child] = references[child].saturating_sub(1);
+            }
+        });
+        if !traced {
+            marked[position] = true;
+        }
+    }
+
+    let mut gray: Vec<usize> = Vec::new();
+    for (position, mark) in marked.iter_mut().enumerate() {
+        if *mark || references[position] > 0 {
+            *mark = true;
+            gray.push(position);
+        }
+    }
+
+    while let Some(position) = gray.pop() {
+        objects[position].trace(&mut |child| {
+            if let Some(&child) = index.get(&child) {
+                if !marked[child] {
+                    marked[child] = true;
+                    gray.push(child);
+                }
+            }
+        });
+    }
+
+    for (object, _) in objects.iter().zip(&marked).filter(|(_, mark)| !**mark) {
+        object.clear();
+    }
+}
+
+fn bytes(objects: &[Weak<dyn Trace>]) -> usize {
+    objects
+        .iter()
+        .filter_map(Weak::upgrade)
+        .map(|object| object.size())
+        .sum()
+}
+
+fn address<T: ?Sized>(object: &Rc<T>) -> usize {
+    Rc::as_ptr(object) as *const () as usize
+}
+
+// The bytes a value owns outside the object holding it: a string's text,
+// which isn't tracked on its own.
+fn payload(value: &Value) -> usize {
+    match value {
+        Value::String(string) => string.len(),
+        _ => 0,
+    }
+}
+
+fn visit_value(value: &Value, visit: &mut dyn FnMut(usize)) {
+    match value {
+        Value::Function(function) => visit(address(function)),
+        Value::Closure(closure) => visit(address(closure)),
+        Value::BoundMethod(bound) => visit(address(bound)),
+        Value::Class(class) => visit(address(class)),
+        Value::Instance(instance) => visit(address(instance)),
+        Value::List(list) => visit(address(list)),
+        Value::Map(map) => visit(address(map)),
+        _ => {}
+    }
+}
+
+fn visit_method(method: &Method, visit: &mut dyn FnMut(usize)) {
+    match method {
+        Method::Function(function) => visit(address(function)),
+        Method::Closure(closure) => visit(address(closure)),
+    }
+}
+
+impl Trace for RefCell<Environment> {
+    fn trace(&self, visit: &mut dyn FnMut(usize)) -> bool {
+        let Ok(environment) = self.try_borrow() else {
+            return false;
+        };
+
+        for value in environment.values().values() {
+            visit_value(value, visit);
+        }
+        for value in environment.slots() {
+            visit_value(value, visit);
+        }
+        if let Some(enclosing) = environment.enclosing() {
+            visit(address(enclosing));
+        }
+        true
+    }
+
+    fn size(&self) -> usize {
+        let Ok(environment) = self.try_borrow() else {
+            return mem::size_of::<Environment>();
+        };
+        let values = environment.values().values().chain(environment.slots());
+        mem::size_of::<Environment>()
+            + environment.values().len() * mem::size_of::<(Symbol, Value)>()
+            + mem::size_of_val(environment.slots())
+            + values.map(payload).sum::<usize>()
+    }
+
+    fn describe(&self) -> String {
+        "environment".to_string()
+    }
+
+    fn clear(&self) {
+        let values = self
+            .try_borrow_mut()
+            .map(|mut environment| (environment.take_values(), environment.take_slots()));
+        drop(values);
+    }
+}
+
+impl Trace for RefCell<Upvalue> {
+    fn trace(&self, visit: &mut dyn FnMut(usize)) -> bool {
+        let Ok(upvalue) = self.try_borrow() else {
+            return false;
+        };
+
+        if let Upvalue::Closed(value) = &*upvalue {
+            visit_value(value, visit);
+        }
+        true
+    }
+
+    fn size(&self) -> usize {
+        let payload = match self.try_borrow().as_deref() {
+            Ok(Upvalue::Closed(value)) => payload(value),
+            _ => 0,
+        };
+        mem::size_of::<Upvalue>() + payload
+    }
+
+    fn describe(&self) -> String {
+        match self.try_borrow().as_deref() {
+            Ok(Upvalue::Open(slot)) => format!("upvalue (open, slot {})", slot),
+            _ => "upvalue".to_string(),
+        }
+    }
+
+    fn clear(&self) {
+        let value = self
+            .try_borrow_mut()
+            .map(|mut upvalue| mem::replace(&mut *upvalue, Upvalue::Closed(Value::Nil)));
+        drop(value);
+    }
+}
+
+impl Trace for Closure {
+    fn trace(&self, visit: &mut dyn FnMut(usize)) -> bool {
+        for upvalue in &self.upvalues {
+            visit(address(upvalue));
+        }
+        true
+    }
+
+    fn size(&self) -> usize {
+        mem::size_of::<Closure>() + self.upvalues.len() * mem::size_of::<Rc<RefCell<Upvalue>>>()
+    }
+
+    fn describe(&self) -> String {
+        format!("closure <fn {}>", self.prototype.name)
+    }
+}
+
+impl Trace for Function {
+    fn trace(&self, visit: &mut dyn FnMut(usize)) -> bool {
+        visit(address(&self.closure));
+        true
+    }
+
+    fn size(&self) -> usize {
+        mem::size_of::<Function>()
+    }
+
+    fn describe(&self) -> String {
+        format!("function <fn {}>", self.name())
+    }
+}
+
+impl Trace for BoundMethod {
+    fn trace(&self, visit: &mut dyn FnMut(usize)) -> bool {
+        visit_value(&self.receiver, visit);
+        visit(address(&self.method));
+        true
+    }
+
+    fn size(&self) -> usize {
+        mem::size_of::<BoundMethod>()
+    }
+
+    fn describe(&self) -> String {
+        format!("bound method <fn {}>", self.method.prototype.name)
+    }
+}
+
+impl Trace for Class {
+    fn trace(&self, visit: &mut dyn FnMut(usize)) -> bool {
+        if let Some(superclass) = &self.superclass {
+            visit(address(superclass));
+        }
+        for method in self.methods.values() {
+            visit_method(method, visit);
+        }
+        true
+    }
+
+    fn size(&self) -> usize {
+        mem::size_of::<Class>() + self.methods.len() * mem::size_of::<(String, Method)>()
+    }
+
+    fn describe(&self) -> String {
+        format!("class {}", self.name)
+    }
+}
+
+impl Trace for RefCell<Instance> {
+    fn trace(&self, visit: &mut dyn FnMut(usize)) -> bool {
+        let Ok(instance) = self.try_borrow() else {
+            return false;
+        };
+
+        visit(address(&instance.class));
+        for value in instance.fields.values() {
+            visit_value(value, visit);
+        }
+        true
+    }
+
+    fn size(&self) -> usize {
+        let fields = self.try_borrow().map_or(0, |instance| {
+            let payload: usize = instance.fields.values().map(payload).sum();
+            instance.fields.len() * mem::size_of::<(Symbol, Value)>() + payload
+        });
+        mem::size_of::<Instance>() + fields
+    }
+
+    fn describe(&self) -> String {
+        match self.try_borrow() {
+            Ok(instance) => format!("{} instance", instance.class.name),
+            Err(_) => "instance".to_string(),
+        }
+    }
+
+    fn clear(&self) {
+        let fields = self
+            .try_borrow_mut()
+            .map(|mut instance| mem::take(&mut instance.fields));
+        drop(fields);
+    }
+}
+
+impl Trace for RefCell<Vec<Value>> {
+    fn trace(&self, visit: &mut dyn FnMut(usize)) -> bool {
+        let Ok(list) = self.try_borrow() else {
+            return false;
+        };
+
+        for value in list.iter() {
+            visit_value(value, visit);
+        }
+        true
+    }
+
+    fn size(&self) -> usize {
+        let elements = self.try_borrow().map_or(0, |list| {
+            let payload: usize = list.iter().map(payload).sum();
+            list.len() * mem::size_of::<Value>() + payload
+        });
+        mem::size_of::<Self>() + elements
+    }
+
+    fn describe(&self) -> String {
+        match self.try_borrow() {
+            Ok(list) => format!("list of {}", list.len()),
+            Err(_) => "list".to_string(),
+        }
+    }
+
+    fn clear(&self) {
+        let elements = self.try_borrow_mut().map(|mut list| mem::take(&mut *list));
+        drop(elements);
+    }
+}
+
+impl Trace for RefCell<BTreeMap<String, Value>> {
+    fn trace(&self, visit: &mut dyn FnMut(usize)) -> bool {
+        let Ok(map) = self.try_borrow() else {
+            return false;
+        };
+
+        for value in map.values() {
+            visit_value(value, visit);
+        }
+        true
+    }
+
+    fn size(&self) -> usize {
+        let entries = self.try_borrow().map_or(0, |map| {
+            let payload: usize = map
+                .iter()
+                .map(|(key, value)| key.len() + payload(value))
+                .sum();
+            map.len() * mem::size_of::<(String, Value)>() + payload
+        });
+        mem::size_of::<Self>() + entries
+    }
+
+    fn describe(&self) -> String {
+        match self.try_borrow() {
+            Ok(map) => format!("map of {}", map.len()),
+            Err(_) => "map".to_string(),
+        }
+    }
+
+    fn clear(&self) {
+        let entries = self.try_borrow_mut().map(|mut map| mem::take(&mut *map));
+        drop(entries);
+    }
+}
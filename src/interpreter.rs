@@ -0,0 +1,2619 @@
+use alloc::collections::BTreeMap;
+use core::cell::RefCell;
+use core::mem;
+
+use crate::arithmetic::{self, Operator, Rules};
+use crate::arity::Arity;
+use crate::cancel::CancelHandle;
+use crate::capability::Capabilities;
+use crate::convert::FromLox;
+use crate::coverage::Coverage;
+use crate::debugger::DebugHook;
+use crate::dialect::Dialect;
+use crate::environment::Environment;
+use crate::error::{ErrorCategory, RuntimeError, RuntimeErrorKind};
+use crate::exception;
+use crate::expression::{Expr, ExpressionKind, Literal};
+use crate::foreign::Foreign;
+use crate::gc::{Collection, GcConfig, Heap, Trace};
+#[cfg(not(feature = "std"))]
+use crate::io::DisabledIo;
+use crate::io::HostIo;
+#[cfg(feature = "std")]
+use crate::io::StdIo;
+#[cfg(feature = "jit")]
+use crate::jit::Jit;
+use crate::native;
+use crate::parser::Parser;
+use crate::prelude::*;
+#[cfg(feature = "std")]
+use crate::profiler::Profiler;
+use crate::random::Random;
+#[cfg(feature = "regvm")]
+use crate::regvm::RegisterVm;
+use crate::resolver::{Binding, Bindings, Resolver};
+use crate::scanner::Scanner;
+use crate::snapshot::Snapshot;
+use crate::statement::{FunctionDeclaration, Pattern, Statement};
+use crate::symbol::Symbol;
+use crate::token::{Keyword, Kind, Token};
+use crate::trace::{self, Calls, Phase};
+use crate::value::{
+    concatenate, concatenate_lists, Class, Closure, Function, Instance, Method, Native, Value,
+};
+use crate::vm::{Suspended, Vm};
+
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 10_000;
+// Calls the host makes back into scripts, as `map` does, each run on the
+// Rust stack rather than the task stack, so they are held to a far smaller
+// depth of their own.
+const MAX_NESTED_CALLS: usize = 48;
+
+// What `x / 0` does. The book's Lox inherits IEEE 754 and gives infinity,
+// or NaN for `0 / 0`; an embedder that would rather catch the mistake can
+// make it a runtime error instead.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DivisionByZero {
+    #[default]
+    Infinity,
+    Error,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct InterpreterOptions {
+    pub max_steps: Option<u64>,
+    pub max_call_depth: usize,
+    // Measured against the objects still alive, as the collector counts
+    // them, not everything the script has allocated.
+    pub max_heap_bytes: Option<usize>,
+    pub division_by_zero: DivisionByZero,
+}
+
+impl Default for InterpreterOptions {
+    fn default() -> InterpreterOptions {
+        InterpreterOptions {
+            max_steps: None,
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            max_heap_bytes: None,
+            division_by_zero: DivisionByZero::Infinity,
+        }
+    }
+}
+
+enum Task {
+    Execute(Rc<Statement>),
+    Evaluate(Expr),
+    Discard,
+    Print,
+    Define(String),
+    Destructure(Rc<Statement>),
+    Branch {
+        then_branch: Rc<Statement>,
+        else_branch: Option<Rc<Statement>>,
+    },
+    Loop {
+        condition: Expr,
+        body: Rc<Statement>,
+    },
+    DefineClass(Rc<Statement>),
+    RestoreEnvironment(Rc<RefCell<Environment>>),
+    // Below a `try` block's tasks until it finishes. The values are counted
+    // from the frame's first, so a generator can move its tasks elsewhere.
+    Catch {
+        statement: Rc<Statement>,
+        values: usize,
+        environment: Rc<RefCell<Environment>>,
+    },
+    Throw(Token),
+    // Raises the error again once a deferred block run on its way out has
+    // finished.
+    Rethrow(Pending),
+    Return(Token),
+    Yield,
+    FinishCall,
+    // Finishes the call with the value on top, once a deferred block has
+    // run.
+    Leave,
+    ForIn {
+        name: Token,
+        body: Rc<Statement>,
+    },
+    StartIteration {
+        name: Token,
+        body: Rc<Statement>,
+    },
+    Iterate {
+        name: Token,
+        iterator: Value,
+        index: usize,
+        body: Rc<Statement>,
+    },
+    CheckDone {
+        name: Token,
+        iterator: Value,
+        body: Rc<Statement>,
+    },
+    ReceiveIteration {
+        name: Token,
+        iterator: Value,
+        body: Rc<Statement>,
+    },
+    Unary(Token),
+    Binary(Token),
+    Logical {
+        operator: Token,
+        right: Expr,
+    },
+    Assign {
+        name: Token,
+        binding: Binding,
+    },
+    Call {
+        parenthesis: Token,
+        count: usize,
+    },
+    Get(Token),
+    Set {
+        name: Token,
+        value: Expr,
+    },
+    SetField(Token),
+    BuildList(usize),
+    BuildMap {
+        brace: Token,
+        count: usize,
+    },
+    Index(Token),
+    SetIndex(Token),
+}
+
+struct Frame {
+    environment: Rc<RefCell<Environment>>,
+    task_base: usize,
+    value_base: usize,
+    receiver: Option<Value>,
+    function: Option<Rc<Function>>,
+    generator: Option<Rc<RefCell<Generator>>>,
+    line: usize,
+    // Deferred blocks with the environments they were reached in, to run
+    // last first as the call exits.
+    deferred: Vec<(Rc<Statement>, Rc<RefCell<Environment>>)>,
+}
+
+// An error on its way out of calls while their deferred blocks run, with the
+// value it threw, which a deferred block's own `throw` would replace.
+pub(crate) struct Pending {
+    error: RuntimeError,
+    thrown: Option<Value>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum GeneratorState {
+    Suspended,
+    Running,
+    Done,
+}
+
+pub struct Generator {
+    name: String,
+    state: GeneratorState,
+    continuation: Continuation,
+}
+
+// Where a suspended generator goes on from, kept in the form of the engine
+// that made it.
+enum Continuation {
+    Tasks {
+        tasks: Vec<Task>,
+        values: Vec<Value>,
+        environment: Rc<RefCell<Environment>>,
+    },
+    Frame(Suspended),
+    // Taken by the VM while it runs the generator.
+    Running,
+}
+
+impl Generator {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub(crate) fn from_frame(name: String, frame: Suspended) -> Generator {
+        Generator {
+            name,
+            state: GeneratorState::Suspended,
+            continuation: Continuation::Frame(frame),
+        }
+    }
+
+    // The VM's frame to run the suspended generator on from, marking it
+    // running.
+    pub(crate) fn resume_frame(&mut self) -> Result<Suspended, &'static str> {
+        if self.state == GeneratorState::Running {
+            return Err(GENERATOR_RUNNING);
+        }
+        match mem::replace(&mut self.continuation, Continuation::Running) {
+            Continuation::Frame(frame) => {
+                self.state = GeneratorState::Running;
+                Ok(frame)
+            }
+            continuation => {
+                self.continuation = continuation;
+                Err(FOREIGN_GENERATOR)
+            }
+        }
+    }
+
+    pub(crate) fn suspend_frame(&mut self, frame: Suspended) {
+        self.state = GeneratorState::Suspended;
+        self.continuation = Continuation::Frame(frame);
+    }
+
+    pub(crate) fn finish(&mut self) {
+        self.state = GeneratorState::Done;
+    }
+
+    pub(crate) fn is_done(&self) -> bool {
+        self.state == GeneratorState::Done
+    }
+}
+
+const GENERATOR_RUNNING: &str = "Generator is already running.";
+
+// A session can change engines between runs, but a generator can only go
+// on in the one that made it.
+const FOREIGN_GENERATOR: &str = "Can't resume a generator made by another engine.";
+
+pub struct Interpreter {
+    globals: Rc<RefCell<Environment>>,
+    environment: Rc<RefCell<Environment>>,
+    bindings: Bindings,
+    tasks: Vec<Task>,
+    values: Vec<Value>,
+    frames: Vec<Frame>,
+    vm: Vm,
+    #[cfg(feature = "regvm")]
+    register_vm: RegisterVm,
+    #[cfg(feature = "jit")]
+    jit: Option<Jit>,
+    #[cfg(feature = "std")]
+    profiler: Option<Profiler>,
+    calls: Calls,
+    coverage: Option<Coverage>,
+    debug_hook: Option<Box<dyn DebugHook>>,
+    options: InterpreterOptions,
+    steps: u64,
+    heap: Heap,
+    cancel: CancelHandle,
+    current_line: usize,
+    nested_calls: usize,
+    io: Box<dyn HostIo>,
+    capabilities: Capabilities,
+    random: Random,
+    trace_execution: bool,
+    string_ordering: bool,
+    repetition: bool,
+    to_string_hook: bool,
+    displaying: Vec<*const ()>,
+    // Error, TypeError, NameError and IndexError, whatever the globals now
+    // call them.
+    error_classes: Vec<Rc<Class>>,
+    // What the last `throw` raised, until a handler takes it.
+    thrown: Option<Value>,
+    gc_log: bool,
+}
+
+impl Default for Interpreter {
+    fn default() -> Interpreter {
+        Interpreter::new()
+    }
+}
+
+impl Interpreter {
+    pub fn new() -> Interpreter {
+        let mut globals = Environment::new();
+        native::define_natives(&mut globals);
+        let globals = Rc::new(RefCell::new(globals));
+
+        let mut interpreter = Interpreter {
+            environment: Rc::clone(&globals),
+            globals,
+            bindings: Bindings::new(),
+            tasks: Vec::new(),
+            values: Vec::new(),
+            frames: Vec::new(),
+            vm: Vm::new(),
+            #[cfg(feature = "regvm")]
+            register_vm: RegisterVm::new(),
+            #[cfg(feature = "jit")]
+            jit: None,
+            #[cfg(feature = "std")]
+            profiler: None,
+            calls: Calls::default(),
+            coverage: None,
+            debug_hook: None,
+            options: InterpreterOptions::default(),
+            steps: 0,
+            heap: Heap::new(),
+            cancel: CancelHandle::new(),
+            current_line: 1,
+            nested_calls: 0,
+            #[cfg(feature = "std")]
+            io: Box::new(StdIo),
+            #[cfg(not(feature = "std"))]
+            io: Box::new(DisabledIo),
+            capabilities: Capabilities::default(),
+            random: Random::from_time(),
+            trace_execution: false,
+            string_ordering: true,
+            repetition: true,
+            to_string_hook: true,
+            displaying: Vec::new(),
+            error_classes: Vec::new(),
+            thrown: None,
+            gc_log: false,
+        };
+        interpreter.define_error_classes();
+        // Nothing collects the globals, but what they hold counts as live.
+        let globals = Rc::clone(&interpreter.globals);
+        interpreter.track(&globals);
+        interpreter
+    }
+
+    // Runs the error classes' declarations straight into the globals.
+    fn define_error_classes(&mut self) {
+        let tokens = Scanner::new(exception::CLASSES.to_string())
+            .scan_tokens()
+            .expect("error classes scan");
+        let statements = Parser::new(tokens).parse().expect("error classes parse");
+        let mut resolver = Resolver::new();
+        resolver.resolve(&statements);
+        self.bindings.extend(resolver.finish());
+
+        for statement in &statements {
+            let superclass = match &**statement {
+                Statement::Class {
+                    superclass: Some(_),
+                    ..
+                } => self.globals.borrow().get("Error"),
+                _ => None,
+            };
+            let _ = self.define_class(statement, superclass);
+        }
+
+        let globals = self.globals.borrow();
+        self.error_classes = exception::NAMES
+            .iter()
+            .filter_map(|name| match globals.get(name) {
+                Some(Value::Class(class)) => Some(class),
+                _ => None,
+            })
+            .collect();
+    }
+
+    pub fn with_options(options: InterpreterOptions) -> Interpreter {
+        let mut interpreter = Interpreter::new();
+        interpreter.options = options;
+        interpreter
+    }
+
+    pub fn options(&self) -> InterpreterOptions {
+        self.options
+    }
+
+    pub fn set_options(&mut self, options: InterpreterOptions) {
+        self.options = options;
+    }
+
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.cancel.clone()
+    }
+
+    pub fn set_max_call_depth(&mut self, max_call_depth: usize) {
+        self.options.max_call_depth = max_call_depth;
+    }
+
+    pub fn set_io(&mut self, io: Box<dyn HostIo>) {
+        self.io = io;
+    }
+
+    pub fn io(&mut self) -> &mut dyn HostIo {
+        self.io.as_mut()
+    }
+
+    pub fn set_capabilities(&mut self, capabilities: Capabilities) {
+        self.capabilities = capabilities;
+    }
+
+    pub fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+
+    pub fn set_trace_execution(&mut self, trace_execution: bool) {
+        self.trace_execution = trace_execution;
+    }
+
+    pub fn trace_execution(&self) -> bool {
+        self.trace_execution
+    }
+
+    #[cfg(feature = "jit")]
+    pub fn enable_jit(&mut self, threshold: u32) -> Result<(), String> {
+        match &mut self.jit {
+            Some(jit) => jit.set_threshold(threshold),
+            None => self.jit = Some(Jit::new(threshold)?),
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "jit")]
+    pub fn disable_jit(&mut self) {
+        self.jit = None;
+    }
+
+    #[cfg(feature = "jit")]
+    pub fn jit(&self) -> Option<&Jit> {
+        self.jit.as_ref()
+    }
+
+    #[cfg(feature = "std")]
+    pub fn enable_profiler(&mut self) {
+        self.profiler = Some(Profiler::new());
+    }
+
+    #[cfg(feature = "std")]
+    pub fn take_profile(&mut self) -> Option<Profiler> {
+        let mut profiler = self.profiler.take()?;
+        profiler.finish();
+        Some(profiler)
+    }
+
+    pub fn enable_coverage(&mut self) {
+        self.coverage = Some(Coverage::new());
+    }
+
+    pub fn take_coverage(&mut self) -> Option<Coverage> {
+        self.coverage.take()
+    }
+
+    pub fn set_debug_hook(&mut self, hook: Option<Box<dyn DebugHook>>) {
+        self.debug_hook = hook;
+    }
+
+    pub fn set_gc_config(&mut self, config: GcConfig) {
+        self.heap.set_config(config);
+    }
+
+    pub fn set_gc_stress(&mut self, gc_stress: bool) {
+        self.heap.set_stress(gc_stress);
+    }
+
+    pub fn set_gc_log(&mut self, gc_log: bool) {
+        self.gc_log = gc_log;
+    }
+
+    pub fn random(&mut self) -> &mut Random {
+        &mut self.random
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        self.globals.borrow().get(name)
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot::capture(self.globals.borrow().values())
+    }
+
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.reset();
+        self.globals.borrow_mut().set_values(snapshot.bindings());
+    }
+
+    pub fn define_global(&mut self, name: &str, value: Value) {
+        self.globals.borrow_mut().define(name.to_string(), value);
+    }
+
+    pub(crate) fn retain_globals(&mut self, keep: impl Fn(&str) -> bool) {
+        let mut globals = self.globals.borrow_mut();
+        let values = globals.take_values();
+        globals.set_values(
+            values
+                .iter()
+                .filter(|(name, _)| keep(name))
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect(),
+        );
+    }
+
+    pub(crate) fn arithmetic_rules(&self) -> Rules {
+        Rules {
+            division: self.options.division_by_zero,
+            string_ordering: self.string_ordering,
+            repetition: self.repetition,
+        }
+    }
+
+    // The runtime extensions the dialect switches on.
+    pub(crate) fn set_extensions(&mut self, dialect: &Dialect) {
+        self.string_ordering = dialect.string_ordering();
+        self.repetition = dialect.repetition();
+        self.to_string_hook = dialect.to_string_hook();
+    }
+
+    // How `print` shows a value. An instance whose class defines
+    // `toString()` shows what that returns, which must be a string; one
+    // already being shown further up, as when `toString()` prints `this`,
+    // falls back to "Foo instance". Lists and maps show their elements the
+    // same way.
+    pub fn display(&mut self, value: &Value) -> Result<String, RuntimeError> {
+        if !self.to_string_hook {
+            return Ok(value.to_string());
+        }
+        self.display_nested(value, false)
+    }
+
+    fn display_nested(&mut self, value: &Value, quoted: bool) -> Result<String, RuntimeError> {
+        match value {
+            Value::Instance(instance) => {
+                let pointer = Rc::as_ptr(instance).cast();
+                if !has_method(instance, "toString") || self.displaying.contains(&pointer) {
+                    return Ok(value.to_string());
+                }
+
+                self.displaying.push(pointer);
+                let result = self
+                    .get_property(self.current_line, "toString", value.clone())
+                    .and_then(|method| self.call(method, Vec::new()));
+                self.displaying.pop();
+                match result? {
+                    Value::String(string) => Ok(string.to_string()),
+                    _ => Err(self.runtime_error("'toString' must return a string.".to_string())),
+                }
+            }
+            Value::List(list) => {
+                let pointer = Rc::as_ptr(list).cast();
+                if self.displaying.contains(&pointer) {
+                    return Ok("[...]".to_string());
+                }
+
+                let elements = list.borrow().clone();
+                self.displaying.push(pointer);
+                let parts: Result<Vec<String>, RuntimeError> = elements
+                    .iter()
+                    .map(|element| self.display_nested(element, true))
+                    .collect();
+                self.displaying.pop();
+                Ok(format!("[{}]", parts?.join(", ")))
+            }
+            Value::Map(map) => {
+                let pointer = Rc::as_ptr(map).cast();
+                if self.displaying.contains(&pointer) {
+                    return Ok("{...}".to_string());
+                }
+
+                let entries: Vec<(String, Value)> = map
+                    .borrow()
+                    .iter()
+                    .map(|(key, value)| (key.clone(), value.clone()))
+                    .collect();
+                self.displaying.push(pointer);
+                let parts: Result<Vec<String>, RuntimeError> = entries
+                    .iter()
+                    .map(|(key, value)| {
+                        let value = self.display_nested(value, true)?;
+                        Ok(format!("{:?}: {}", key, value))
+                    })
+                    .collect();
+                self.displaying.pop();
+                Ok(format!("{{{}}}", parts?.join(", ")))
+            }
+            _ if quoted => Ok(format!("{:?}", value)),
+            _ => Ok(value.to_string()),
+        }
+    }
+
+    pub(crate) fn define_print_natives(&mut self) {
+        native::define_print_natives(&mut self.globals.borrow_mut());
+    }
+
+    pub fn define_native<F>(&mut self, name: &str, arity: usize, function: F)
+    where
+        F: Fn(&mut Interpreter, &[Value]) -> Result<Value, RuntimeError> + 'static,
+    {
+        let native = Native {
+            name: name.to_string(),
+            arity,
+            function: Rc::new(function),
+        };
+
+        self.globals
+            .borrow_mut()
+            .define(name.to_string(), Value::Native(Rc::new(native)));
+    }
+
+    pub fn interpret(&mut self, statements: &[Rc<Statement>]) -> Result<(), RuntimeError> {
+        self.reset_usage();
+        self.add_coverage(statements);
+        {
+            let _resolve = trace::enter(Phase::Resolve);
+            let mut resolver = Resolver::new();
+            resolver.resolve(statements);
+            self.bindings.extend(resolver.finish());
+        }
+
+        for statement in statements.iter().rev() {
+            self.tasks.push(Task::Execute(Rc::clone(statement)));
+        }
+
+        let result = self.run(0);
+        if result.is_err() {
+            self.reset();
+        }
+
+        result
+    }
+
+    pub fn evaluate_expression(&mut self, expression: Expr) -> Result<Value, RuntimeError> {
+        self.reset_usage();
+        {
+            let _resolve = trace::enter(Phase::Resolve);
+            let mut resolver = Resolver::new();
+            resolver.expression(&expression);
+            self.bindings.extend(resolver.finish());
+        }
+
+        self.tasks.push(Task::Evaluate(expression));
+
+        match self.run(0) {
+            Ok(()) => Ok(self.values.pop().unwrap_or(Value::Nil)),
+            Err(error) => {
+                self.reset();
+                Err(error)
+            }
+        }
+    }
+
+    pub fn call(&mut self, callee: Value, arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        if self.nested_calls >= MAX_NESTED_CALLS {
+            return Err(self.runtime_error("Stack overflow.".to_string()));
+        }
+
+        self.nested_calls += 1;
+        let base = self.tasks.len();
+        let result = self
+            .call_value(callee, arguments, self.current_line)
+            .and_then(|()| self.run(base));
+        self.nested_calls -= 1;
+        result?;
+
+        Ok(self.values.pop().unwrap_or(Value::Nil))
+    }
+
+    pub fn argument<T: FromLox>(
+        &self,
+        arguments: &[Value],
+        index: usize,
+    ) -> Result<T, RuntimeError> {
+        let value = arguments.get(index).unwrap_or(&Value::Nil);
+        T::from_lox(value).map_err(|error| {
+            self.runtime_error(format!("Argument {}: {}", index + 1, error))
+                .with_category(ErrorCategory::Type)
+        })
+    }
+
+    // Fails if `bytes` more would take the live heap past its limit. The
+    // heap's count includes whatever has died since the last collection, so
+    // one runs before giving up.
+    pub fn allocate(&mut self, bytes: usize) -> Result<(), RuntimeError> {
+        let Some(limit) = self.options.max_heap_bytes else {
+            return Ok(());
+        };
+
+        if self.heap.bytes_allocated().saturating_add(bytes) > limit {
+            self.collect_garbage();
+        }
+        if self.heap.bytes_allocated().saturating_add(bytes) > limit {
+            return Err(self.resource_error("Heap limit exceeded."));
+        }
+        Ok(())
+    }
+
+    pub fn heap(&self) -> &Heap {
+        &self.heap
+    }
+
+    // See Heap::add_finalizer.
+    pub fn add_finalizer(&mut self, value: &Value, finalizer: impl FnOnce() + 'static) -> bool {
+        self.heap.add_finalizer(value, Box::new(finalizer))
+    }
+
+    pub fn collect_garbage(&mut self) -> Collection {
+        let collection = self.heap.collect();
+        self.log_collection(collection);
+        collection
+    }
+
+    pub fn runtime_error(&self, message: String) -> RuntimeError {
+        RuntimeError {
+            kind: RuntimeErrorKind::Error,
+            category: ErrorCategory::General,
+            message,
+            line: self.current_line,
+        }
+    }
+
+    fn resource_error(&self, message: &str) -> RuntimeError {
+        RuntimeError {
+            kind: RuntimeErrorKind::ResourceExceeded,
+            category: ErrorCategory::General,
+            message: message.to_string(),
+            line: self.current_line,
+        }
+    }
+
+    pub(crate) fn reset_usage(&mut self) {
+        self.steps = 0;
+        self.heap.reset_stats();
+    }
+
+    // Statements and expressions evaluated, or instructions executed, since
+    // the run began.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    pub(crate) fn globals(&self) -> &Rc<RefCell<Environment>> {
+        &self.globals
+    }
+
+    pub(crate) fn current_line(&self) -> usize {
+        self.current_line
+    }
+
+    pub(crate) fn set_current_line(&mut self, line: usize) {
+        self.current_line = line;
+    }
+
+    pub(crate) fn environment(&self) -> &Rc<RefCell<Environment>> {
+        &self.environment
+    }
+
+    // The walker's active calls, outermost first, with the line each was
+    // called from.
+    pub(crate) fn backtrace(&self) -> Vec<(String, usize)> {
+        self.frames
+            .iter()
+            .map(|frame| {
+                let name = match (&frame.function, &frame.generator) {
+                    (Some(function), _) => function.name(),
+                    (None, Some(generator)) => generator.borrow().name.clone(),
+                    (None, None) => "?".to_string(),
+                };
+                (name, frame.line)
+            })
+            .collect()
+    }
+
+    // The calls under way in whichever engine is running, innermost first and
+    // down to the script, as maps of each function's name and the line it
+    // has reached. `line` is where the innermost one is.
+    pub(crate) fn stack_trace(&self, line: usize) -> Value {
+        let mut calls = self.vm.calls();
+        if calls.is_empty() {
+            let mut reached = line;
+            for (name, called_from) in self.backtrace().into_iter().rev() {
+                calls.push((name, reached));
+                reached = called_from;
+            }
+            calls.push(("script".to_string(), reached));
+        }
+        if let Some(innermost) = calls.first_mut() {
+            innermost.1 = line;
+        }
+
+        let frames: Vec<Value> = calls
+            .into_iter()
+            .map(|(name, line)| {
+                Value::from(BTreeMap::from([
+                    ("function".to_string(), Value::String(name.into())),
+                    ("line".to_string(), Value::Number(line as f64)),
+                ]))
+            })
+            .collect();
+        Value::from(frames)
+    }
+
+    // Evaluates on top of a paused execution without disturbing it, resolving
+    // names against the given local scopes.
+    pub(crate) fn evaluate_in_scope(
+        &mut self,
+        expression: Expr,
+        scopes: Vec<Vec<String>>,
+    ) -> Result<Value, RuntimeError> {
+        let mut resolver = Resolver::with_scopes(scopes);
+        resolver.expression(&expression);
+        self.bindings.extend(resolver.finish());
+
+        let task_base = self.tasks.len();
+        let value_base = self.values.len();
+        let frame_base = self.frames.len();
+        let environment = Rc::clone(&self.environment);
+
+        self.tasks.push(Task::Evaluate(expression));
+        match self.run(task_base) {
+            Ok(()) => Ok(self.values.pop().unwrap_or(Value::Nil)),
+            Err(error) => {
+                self.tasks.truncate(task_base);
+                self.values.truncate(value_base);
+                self.frames.truncate(frame_base);
+                self.environment = environment;
+                Err(error)
+            }
+        }
+    }
+
+    pub(crate) fn call_depth(&self) -> usize {
+        let depth = self.frames.len() + self.vm.depth();
+        #[cfg(feature = "regvm")]
+        let depth = depth + self.register_vm.depth();
+        depth
+    }
+
+    pub(crate) fn vm_mut(&mut self) -> &mut Vm {
+        &mut self.vm
+    }
+
+    #[cfg(feature = "regvm")]
+    pub(crate) fn register_vm_mut(&mut self) -> &mut RegisterVm {
+        &mut self.register_vm
+    }
+
+    #[cfg(feature = "jit")]
+    pub(crate) fn jit_mut(&mut self) -> Option<&mut Jit> {
+        self.jit.as_mut()
+    }
+
+    // Whether calls are being watched, by a profiler or a tracing subscriber,
+    // and so can't be left to compiled code.
+    #[cfg(feature = "jit")]
+    pub(crate) fn profiling(&self) -> bool {
+        self.profiler.is_some() || self.calls.enabled()
+    }
+
+    // The name is only built while a profiler or subscriber wants it.
+    #[cfg(feature = "std")]
+    pub(crate) fn profile_enter(&mut self, name: impl FnOnce() -> String) {
+        let traced = self.calls.enabled();
+        if self.profiler.is_none() && !traced {
+            self.calls.enter(None);
+            return;
+        }
+
+        let name = name();
+        self.calls.enter(traced.then_some(name.as_str()));
+        if let Some(profiler) = &mut self.profiler {
+            profiler.enter(&name);
+        }
+    }
+
+    #[cfg(not(feature = "std"))]
+    pub(crate) fn profile_enter(&mut self, _name: impl FnOnce() -> String) {}
+
+    #[cfg(feature = "std")]
+    pub(crate) fn profile_leave(&mut self) {
+        self.calls.leave();
+        if let Some(profiler) = &mut self.profiler {
+            profiler.leave();
+        }
+    }
+
+    #[cfg(not(feature = "std"))]
+    pub(crate) fn profile_leave(&mut self) {}
+
+    fn covering(&self) -> bool {
+        self.coverage.is_some()
+    }
+
+    // Registers the statements' lines so the ones that never run are reported.
+    fn add_coverage(&mut self, statements: &[Rc<Statement>]) {
+        if let Some(coverage) = &mut self.coverage {
+            coverage.add(statements);
+        }
+    }
+
+    fn cover_line(&mut self, line: usize) {
+        if let Some(coverage) = &mut self.coverage {
+            coverage.hit(line);
+        }
+    }
+
+    pub(crate) fn count_step(&mut self) -> Result<(), RuntimeError> {
+        if self.cancel.take() {
+            return Err(RuntimeError {
+                kind: RuntimeErrorKind::Interrupted,
+                category: ErrorCategory::General,
+                message: "Interrupted.".to_string(),
+                line: self.current_line,
+            });
+        }
+
+        self.steps += 1;
+
+        match self.options.max_steps {
+            Some(limit) if self.steps > limit => Err(self.resource_error("Step limit exceeded.")),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn track<T: Trace + 'static>(&mut self, object: &Rc<T>) {
+        if let Some(collection) = self.heap.track(object) {
+            self.log_collection(collection);
+        }
+    }
+
+    pub(crate) fn track_value(&mut self, value: &Value) {
+        if let Some(collection) = self.heap.track_value(value) {
+            self.log_collection(collection);
+        }
+    }
+
+    // See Heap::grow.
+    pub(crate) fn grow(&mut self, bytes: usize) {
+        if let Some(collection) = self.heap.grow(bytes) {
+            self.log_collection(collection);
+        }
+    }
+
+    fn log_collection(&mut self, collection: Collection) {
+        trace::collection(&collection);
+        if !self.gc_log {
+            return;
+        }
+
+        let _ = self.io.write_stderr(&format!(
+            "-- {}: freed {} objects ({} bytes) in {}us, {} bytes live, next at {}\n",
+            if collection.minor { "minor gc" } else { "gc" },
+            collection.objects_freed,
+            collection.bytes_freed,
+            collection.pause.as_micros(),
+            collection.bytes_remaining,
+            collection.next_gc
+        ));
+    }
+
+    // Counts a value that was just made, then checks it fit.
+    pub(crate) fn allocate_value(&mut self, value: &Value) -> Result<(), RuntimeError> {
+        match value {
+            Value::String(string) => self.grow(string.len()),
+            _ => self.track_value(value),
+        }
+
+        self.allocate(0)
+    }
+
+    fn run(&mut self, base: usize) -> Result<(), RuntimeError> {
+        while self.tasks.len() > base {
+            if let Some(task) = self.tasks.pop() {
+                if let Err(error) = self.count_step().and_then(|()| self.step(task)) {
+                    self.unwind(error, base)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Hands the error to the innermost handler this run can reach, dropping
+    // whatever its `try` block left unfinished, calls included. Calls the
+    // error leaves run their deferred blocks first, one at a time, with the
+    // error raised again after each.
+    fn unwind(&mut self, error: RuntimeError, base: usize) -> Result<(), RuntimeError> {
+        if !error.kind.is_catchable() {
+            return Err(error);
+        }
+        let handler = self.tasks[base..]
+            .iter()
+            .rposition(|task| matches!(task, Task::Catch { .. }))
+            .map(|handler| base + handler);
+
+        let limit = handler.map_or(base, |index| index + 1);
+        let deferring = self
+            .frames
+            .iter()
+            .rev()
+            .take_while(|frame| frame.task_base >= limit)
+            .any(|frame| !frame.deferred.is_empty());
+        if deferring {
+            while let Some(frame) = self.frames.last_mut() {
+                if let Some(deferred) = frame.deferred.pop() {
+                    self.tasks.truncate(frame.task_base);
+                    self.values.truncate(frame.value_base);
+                    let pending = self.suspend_error(error);
+                    self.tasks.push(Task::Rethrow(pending));
+                    self.run_deferred(deferred);
+                    return Ok(());
+                }
+                self.abandon_call();
+            }
+        }
+
+        let Some(index) = handler else {
+            return Err(error);
+        };
+        let error = self.caught_value(error)?;
+
+        self.tasks.truncate(index + 1);
+        let Some(Task::Catch {
+            statement,
+            values,
+            environment,
+        }) = self.tasks.pop()
+        else {
+            return Ok(());
+        };
+        while self
+            .frames
+            .last()
+            .is_some_and(|frame| frame.task_base > index)
+        {
+            self.abandon_call();
+        }
+        let value_base = self.frames.last().map_or(0, |frame| frame.value_base);
+        self.values.truncate(value_base + values);
+
+        if let Statement::Try { handler, .. } = &*statement {
+            let mut scope = Environment::with_enclosing(Rc::clone(&environment));
+            scope.push(error);
+            self.environment = environment;
+            self.execute_block(handler, scope);
+        }
+        Ok(())
+    }
+
+    // Drops the innermost call without finishing it.
+    fn abandon_call(&mut self) {
+        if let Some(frame) = self.frames.pop() {
+            self.profile_leave();
+            if let Some(generator) = frame.generator {
+                generator.borrow_mut().state = GeneratorState::Done;
+            }
+        }
+    }
+
+    // The error a `throw` raises. The value waits here for a handler, and
+    // an instance whose `line` and `trace` are nil, as a new Error's are,
+    // gets the throw's.
+    pub(crate) fn throw_value(&mut self, value: Value, line: usize) -> RuntimeError {
+        let mut message = value.to_string();
+        let mut error_line = line;
+        if let Value::Instance(instance) = &value {
+            let untraced = matches!(instance.borrow().fields.get("trace"), Some(Value::Nil));
+            if untraced {
+                let trace = self.stack_trace(line);
+                instance
+                    .borrow_mut()
+                    .fields
+                    .insert(Symbol::new("trace"), trace);
+            }
+            let mut instance = instance.borrow_mut();
+            match instance.fields.get("line") {
+                Some(Value::Nil) => {
+                    let line = Value::Number(line as f64);
+                    instance.fields.insert(Symbol::new("line"), line);
+                }
+                Some(Value::Number(number)) => error_line = *number as usize,
+                _ => {}
+            }
+            if let Some(Value::String(text)) = instance.fields.get("message") {
+                message = text.to_string();
+            }
+        }
+
+        self.thrown = Some(value);
+        RuntimeError {
+            kind: RuntimeErrorKind::Thrown,
+            category: ErrorCategory::General,
+            message,
+            line: error_line,
+        }
+    }
+
+    // What a `catch` clause receives: the thrown value, or for an error the
+    // runtime raised, an instance of its class with its message, line and
+    // trace. It is called before the failed calls are unwound.
+    // Running out of resources, being interrupted and exiting can't be
+    // caught.
+    pub(crate) fn caught_value(&mut self, error: RuntimeError) -> Result<Value, RuntimeError> {
+        if error.kind == RuntimeErrorKind::Thrown {
+            let message = Value::String(error.message.into());
+            return Ok(self.thrown.take().unwrap_or(message));
+        }
+
+        let name = error.category.class_name();
+        let class = self.error_classes.iter().find(|class| class.name == name);
+        let (RuntimeErrorKind::Error, Some(class)) = (error.kind, class) else {
+            return Err(error);
+        };
+
+        let mut instance = Instance::new(Rc::clone(class));
+        let message = Value::String(error.message.into());
+        instance.fields.insert(Symbol::new("message"), message);
+        let line = Value::Number(error.line as f64);
+        instance.fields.insert(Symbol::new("line"), line);
+        let trace = self.stack_trace(error.line);
+        instance.fields.insert(Symbol::new("trace"), trace);
+        let instance = Rc::new(RefCell::new(instance));
+        self.track(&instance);
+        Ok(Value::Instance(instance))
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.tasks.clear();
+        self.values.clear();
+        self.frames.clear();
+        self.vm = Vm::new();
+        #[cfg(feature = "regvm")]
+        {
+            self.register_vm = RegisterVm::new();
+        }
+        self.environment = Rc::clone(&self.globals);
+        self.thrown = None;
+        self.calls.clear();
+        #[cfg(feature = "std")]
+        if let Some(profiler) = &mut self.profiler {
+            profiler.finish();
+        }
+    }
+
+    fn step(&mut self, task: Task) -> Result<(), RuntimeError> {
+        match task {
+            Task::Execute(statement) => self.execute(statement)?,
+            Task::Evaluate(expression) => self.evaluate(expression)?,
+            Task::Discard => {
+                self.pop_value();
+            }
+            Task::Print => {
+                let value = self.pop_value();
+                let text = self.display(&value)?;
+                self.io
+                    .write_stdout(&format!("{}\n", text))
+                    .map_err(|error| {
+                        self.runtime_error(format!("Could not write output: {}.", error))
+                    })?;
+            }
+            Task::Define(name) => {
+                let value = self.pop_value();
+                self.declare(name, value);
+            }
+            Task::Destructure(statement) => {
+                let Statement::Destructure {
+                    keyword,
+                    pattern,
+                    names,
+                    ..
+                } = &*statement
+                else {
+                    unreachable!("destructuring task for another statement");
+                };
+                let value = self.pop_value();
+                if *pattern == Pattern::Values {
+                    self.expect_values(keyword.line(), &value, names.len())?;
+                }
+                for (position, name) in names.iter().enumerate() {
+                    let key = destructuring_key(*pattern, position, name);
+                    let element = self.unpack(keyword.line(), &value, &key)?;
+                    self.declare(name.lexeme(), element);
+                }
+            }
+            Task::Branch {
+                then_branch,
+                else_branch,
+            } => {
+                if self.pop_value().is_truthy() {
+                    self.tasks.push(Task::Execute(then_branch));
+                } else if let Some(else_branch) = else_branch {
+                    self.tasks.push(Task::Execute(else_branch));
+                }
+            }
+            Task::Loop { condition, body } => {
+                if self.pop_value().is_truthy() {
+                    self.tasks.push(Task::Loop {
+                        condition: condition.clone(),
+                        body: Rc::clone(&body),
+                    });
+                    self.tasks.push(Task::Evaluate(condition));
+                    self.tasks.push(Task::Execute(body));
+                }
+            }
+            Task::DefineClass(statement) => {
+                let superclass = self.pop_value();
+                self.define_class(&statement, Some(superclass))?;
+            }
+            Task::RestoreEnvironment(environment) => self.environment = environment,
+            // The `try` block finished without an error.
+            Task::Catch { .. } => {}
+            Task::Throw(keyword) => {
+                let value = self.pop_value();
+                return Err(self.throw_value(value, keyword.line()));
+            }
+            Task::Return(keyword) => {
+                let value = self.pop_value();
+                match self.frames.last() {
+                    Some(frame) => {
+                        let task_base = frame.task_base;
+                        self.tasks.truncate(task_base);
+                        self.finish_call(value);
+                    }
+                    None => {
+                        return Err(self.build_error(
+                            &keyword,
+                            "Can't return from top-level code.".to_string(),
+                        ))
+                    }
+                }
+            }
+            Task::Yield => {
+                let value = self.pop_value();
+                if let Some(frame) = self.frames.pop() {
+                    self.profile_leave();
+                    if let Some(generator) = &frame.generator {
+                        let mut generator = generator.borrow_mut();
+                        generator.state = GeneratorState::Suspended;
+                        generator.continuation = Continuation::Tasks {
+                            tasks: self.tasks.split_off(frame.task_base),
+                            values: self.values.split_off(frame.value_base),
+                            environment: mem::replace(&mut self.environment, frame.environment),
+                        };
+                    }
+                }
+
+                self.values.push(value);
+            }
+            Task::FinishCall => self.finish_call(Value::Nil),
+            Task::Leave => {
+                let value = self.pop_value();
+                self.finish_call(value);
+            }
+            Task::Rethrow(pending) => return Err(self.resume_error(pending)),
+            // An instance whose class has an `iterator` method is asked for
+            // its iterator first; anything else is its own iterator.
+            Task::ForIn { name, body } => {
+                let iterable = self.pop_value();
+                let line = name.line();
+                let has_iterator = match &iterable {
+                    Value::Instance(instance) => has_method(instance, "iterator"),
+                    _ => false,
+                };
+
+                self.tasks.push(Task::StartIteration { name, body });
+                if has_iterator {
+                    self.call_method(iterable, "iterator", line)?;
+                } else {
+                    self.values.push(iterable);
+                }
+            }
+            Task::StartIteration { name, body } => {
+                let iterator = self.pop_value();
+                if let Err(message) = check_iterator(&iterator) {
+                    return Err(self
+                        .build_error(&name, message.to_string())
+                        .with_category(ErrorCategory::Type));
+                }
+
+                self.tasks.push(Task::Iterate {
+                    name,
+                    iterator,
+                    index: 0,
+                    body,
+                });
+            }
+            Task::Iterate {
+                name,
+                iterator,
+                index,
+                body,
+            } => match &iterator {
+                Value::Generator(generator) => {
+                    let generator = Rc::clone(generator);
+                    self.tasks.push(Task::ReceiveIteration {
+                        name: name.clone(),
+                        iterator,
+                        body,
+                    });
+                    self.resume(generator, &name)?;
+                }
+                Value::List(list) => {
+                    let element = list.borrow().get(index).cloned();
+                    if let Some(element) = element {
+                        self.iterate(name, iterator, index + 1, body, element);
+                    }
+                }
+                Value::Instance(_) => {
+                    let line = name.line();
+                    self.tasks.push(Task::CheckDone {
+                        name,
+                        iterator: iterator.clone(),
+                        body,
+                    });
+                    self.call_method(iterator, "done", line)?;
+                }
+                _ => (),
+            },
+            Task::CheckDone {
+                name,
+                iterator,
+                body,
+            } => {
+                if self.pop_value().is_truthy() {
+                    return Ok(());
+                }
+
+                let line = name.line();
+                self.tasks.push(Task::ReceiveIteration {
+                    name,
+                    iterator: iterator.clone(),
+                    body,
+                });
+                self.call_method(iterator, "next", line)?;
+            }
+            Task::ReceiveIteration {
+                name,
+                iterator,
+                body,
+            } => {
+                let value = self.pop_value();
+                if let Value::Generator(generator) = &iterator {
+                    if generator.borrow().state == GeneratorState::Done {
+                        return Ok(());
+                    }
+                }
+
+                self.iterate(name, iterator, 0, body, value);
+            }
+            Task::Unary(operator) => {
+                let right = self.pop_value();
+                let value = self.unary(&operator, right)?;
+                self.values.push(value);
+            }
+            Task::Binary(operator) => {
+                let right = self.pop_value();
+                let left = self.pop_value();
+                let value = self.binary(&operator, left, right)?;
+                self.allocate_value(&value)?;
+                self.values.push(value);
+            }
+            Task::Logical { operator, right } => {
+                let left = self.pop_value();
+                let short_circuit = match operator.kind {
+                    Kind::Keyword(Keyword::Or) => left.is_truthy(),
+                    _ => !left.is_truthy(),
+                };
+
+                if short_circuit {
+                    self.values.push(left);
+                } else {
+                    self.tasks.push(Task::Evaluate(right));
+                }
+            }
+            Task::Assign { name, binding } => {
+                let value = self.values.last().cloned().unwrap_or(Value::Nil);
+                let assigned = match binding {
+                    Binding::Local { depth, slot } => {
+                        self.environment.borrow_mut().assign_at(depth, slot, value)
+                    }
+                    Binding::Forward { depth, slot } => {
+                        self.environment
+                            .borrow_mut()
+                            .assign_at(depth, slot, value.clone())
+                            || self.globals.borrow_mut().assign(&name.lexeme(), value)
+                    }
+                    Binding::Global => self.globals.borrow_mut().assign(&name.lexeme(), value),
+                };
+                if !assigned {
+                    return Err(self
+                        .build_error(&name, format!("Undefined variable '{}'.", name.lexeme()))
+                        .with_category(ErrorCategory::Name));
+                }
+            }
+            Task::Call { parenthesis, count } => {
+                let arguments = self.values.split_off(self.values.len() - count);
+                let callee = self.pop_value();
+                self.call_value(callee, arguments, parenthesis.line())?;
+            }
+            Task::Get(name) => {
+                let object = self.pop_value();
+                let value = self.get_property(name.line(), &name.lexeme(), object)?;
+                self.values.push(value);
+            }
+            Task::Set { name, value } => {
+                let object = self.pop_value();
+                if !matches!(object, Value::Instance(_) | Value::Foreign(_)) {
+                    return Err(self
+                        .build_error(&name, "Only instances have fields.".to_string())
+                        .with_category(ErrorCategory::Type));
+                }
+
+                self.values.push(object);
+                self.tasks.push(Task::SetField(name));
+                self.tasks.push(Task::Evaluate(value));
+            }
+            Task::SetField(name) => {
+                let value = self.pop_value();
+                let object = self.pop_value();
+                self.set_property(
+                    name.line(),
+                    Symbol::from(name.lexeme()),
+                    object,
+                    value.clone(),
+                )?;
+                self.values.push(value);
+            }
+            Task::BuildList(count) => {
+                let elements = self.values.split_off(self.values.len() - count);
+                self.allocate(count * mem::size_of::<Value>())?;
+                let list = Value::from(elements);
+                self.track_value(&list);
+                self.values.push(list);
+            }
+            Task::BuildMap { brace, count } => {
+                let values = self.values.split_off(self.values.len() - count * 2);
+                self.allocate(count * mem::size_of::<(String, Value)>())?;
+
+                let mut entries: BTreeMap<String, Value> = BTreeMap::new();
+                let mut values = values.into_iter();
+                while let (Some(key), Some(value)) = (values.next(), values.next()) {
+                    let Value::String(key) = key else {
+                        return Err(self
+                            .build_error(&brace, "Map key must be a string.".to_string())
+                            .with_category(ErrorCategory::Type));
+                    };
+                    entries.insert(key.to_string(), value);
+                }
+
+                let map = Value::from(entries);
+                self.track_value(&map);
+                self.values.push(map);
+            }
+            Task::Index(bracket) => {
+                let index = self.pop_value();
+                let object = self.pop_value();
+                let value = self.index(bracket.line(), &object, &index)?;
+                self.values.push(value);
+            }
+            Task::SetIndex(bracket) => {
+                let value = self.pop_value();
+                let index = self.pop_value();
+                let object = self.pop_value();
+                self.allocate(mem::size_of::<Value>())?;
+                self.set_index(bracket.line(), &object, &index, value.clone())?;
+                self.values.push(value);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn execute(&mut self, statement: Rc<Statement>) -> Result<(), RuntimeError> {
+        if self.covering() {
+            if let Some(line) = statement.line() {
+                self.cover_line(line);
+            }
+        }
+        if let Some(mut hook) = self.debug_hook.take() {
+            let result = hook.on_statement(self, &statement);
+            self.debug_hook = Some(hook);
+            result?;
+        }
+
+        match &*statement {
+            Statement::Expression(expression) => {
+                self.tasks.push(Task::Discard);
+                self.tasks.push(Task::Evaluate(expression.clone()));
+            }
+            Statement::Print { value, .. } => {
+                self.tasks.push(Task::Print);
+                self.tasks.push(Task::Evaluate(value.clone()));
+            }
+            Statement::Variable { name, initializer } => {
+                self.tasks.push(Task::Define(name.lexeme()));
+                match initializer {
+                    Some(initializer) => self.tasks.push(Task::Evaluate(initializer.clone())),
+                    None => self.values.push(Value::Nil),
+                }
+            }
+            Statement::Destructure { initializer, .. } => {
+                self.tasks.push(Task::Destructure(Rc::clone(&statement)));
+                self.tasks.push(Task::Evaluate(initializer.clone()));
+            }
+            Statement::Block(statements) => {
+                let environment = Environment::with_enclosing(Rc::clone(&self.environment));
+                self.execute_block(statements, environment);
+            }
+            Statement::Try { body, .. } => {
+                let value_base = self.frames.last().map_or(0, |frame| frame.value_base);
+                self.tasks.push(Task::Catch {
+                    statement: Rc::clone(&statement),
+                    values: self.values.len() - value_base,
+                    environment: Rc::clone(&self.environment),
+                });
+                let environment = Environment::with_enclosing(Rc::clone(&self.environment));
+                self.execute_block(body, environment);
+            }
+            Statement::Throw { keyword, value } => {
+                self.tasks.push(Task::Throw(keyword.clone()));
+                self.tasks.push(Task::Evaluate(value.clone()));
+            }
+            Statement::Defer { .. } => {
+                let environment = Rc::clone(&self.environment);
+                if let Some(frame) = self.frames.last_mut() {
+                    frame.deferred.push((Rc::clone(&statement), environment));
+                }
+            }
+            Statement::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.tasks.push(Task::Branch {
+                    then_branch: Rc::clone(then_branch),
+                    else_branch: else_branch.clone(),
+                });
+                self.tasks.push(Task::Evaluate(condition.clone()));
+            }
+            Statement::While {
+                condition, body, ..
+            } => {
+                self.tasks.push(Task::Loop {
+                    condition: condition.clone(),
+                    body: Rc::clone(body),
+                });
+                self.tasks.push(Task::Evaluate(condition.clone()));
+            }
+            Statement::ForIn {
+                name,
+                iterable,
+                body,
+            } => {
+                self.tasks.push(Task::ForIn {
+                    name: name.clone(),
+                    body: Rc::clone(body),
+                });
+                self.tasks.push(Task::Evaluate(iterable.clone()));
+            }
+            Statement::Function(declaration) => {
+                let function = Rc::new(Function {
+                    declaration: Rc::clone(declaration),
+                    closure: Rc::clone(&self.environment),
+                    is_initializer: false,
+                    is_bound: false,
+                });
+                self.track(&function);
+
+                self.declare(declaration.name.lexeme(), Value::Function(function));
+            }
+            Statement::Return { keyword, value } => {
+                self.tasks.push(Task::Return(keyword.clone()));
+                match value {
+                    Some(value) => self.tasks.push(Task::Evaluate(value.clone())),
+                    None => self.values.push(Value::Nil),
+                }
+            }
+            Statement::Yield { value, .. } => {
+                self.tasks.push(Task::Yield);
+                match value {
+                    Some(value) => self.tasks.push(Task::Evaluate(value.clone())),
+                    None => self.values.push(Value::Nil),
+                }
+            }
+            Statement::Class { superclass, .. } => match superclass {
+                Some(superclass) => {
+                    let superclass = superclass.clone();
+                    self.tasks.push(Task::DefineClass(statement));
+                    self.tasks.push(Task::Evaluate(superclass));
+                }
+                None => self.define_class(&statement, None)?,
+            },
+        }
+
+        Ok(())
+    }
+
+    // Runs the statements in `environment`, then goes back to the current
+    // one.
+    fn execute_block(&mut self, statements: &[Rc<Statement>], environment: Environment) {
+        let environment = Rc::new(RefCell::new(environment));
+        self.track(&environment);
+        let previous = mem::replace(&mut self.environment, environment);
+
+        self.tasks.push(Task::RestoreEnvironment(previous));
+        for statement in statements.iter().rev() {
+            self.tasks.push(Task::Execute(Rc::clone(statement)));
+        }
+    }
+
+    fn evaluate(&mut self, expression: Expr) -> Result<(), RuntimeError> {
+        match &expression.kind {
+            ExpressionKind::Literal(literal) => {
+                let value = match literal {
+                    Literal::Nil => Value::Nil,
+                    Literal::Boolean(boolean) => Value::Boolean(*boolean),
+                    Literal::Number(number) => Value::Number(*number),
+                    Literal::Integer(integer) => Value::Int(*integer),
+                    Literal::String(string) => Value::String(string.clone()),
+                };
+
+                self.values.push(value);
+            }
+            ExpressionKind::Grouping(inner) => {
+                self.tasks.push(Task::Evaluate(expression.get(*inner)));
+            }
+            ExpressionKind::Unary { operator, right } => {
+                self.tasks.push(Task::Unary(operator.clone()));
+                self.tasks.push(Task::Evaluate(expression.get(*right)));
+            }
+            ExpressionKind::Binary {
+                left,
+                operator,
+                right,
+            } => {
+                self.tasks.push(Task::Binary(operator.clone()));
+                self.tasks.push(Task::Evaluate(expression.get(*right)));
+                self.tasks.push(Task::Evaluate(expression.get(*left)));
+            }
+            ExpressionKind::Logical {
+                left,
+                operator,
+                right,
+            } => {
+                self.tasks.push(Task::Logical {
+                    operator: operator.clone(),
+                    right: expression.get(*right),
+                });
+                self.tasks.push(Task::Evaluate(expression.get(*left)));
+            }
+            ExpressionKind::Variable(name) => {
+                let value = self.look_up(&expression, name)?;
+                self.values.push(value);
+            }
+            ExpressionKind::Assign { name, value } => {
+                self.tasks.push(Task::Assign {
+                    name: name.clone(),
+                    binding: self.binding(&expression),
+                });
+                self.tasks.push(Task::Evaluate(expression.get(*value)));
+            }
+            ExpressionKind::Call {
+                callee,
+                parenthesis,
+                arguments,
+            } => {
+                self.tasks.push(Task::Call {
+                    parenthesis: parenthesis.clone(),
+                    count: arguments.len(),
+                });
+                for argument in arguments.iter().rev() {
+                    self.tasks.push(Task::Evaluate(expression.get(*argument)));
+                }
+                self.tasks.push(Task::Evaluate(expression.get(*callee)));
+            }
+            ExpressionKind::Get { object, name } => {
+                self.tasks.push(Task::Get(name.clone()));
+                self.tasks.push(Task::Evaluate(expression.get(*object)));
+            }
+            ExpressionKind::Set {
+                object,
+                name,
+                value,
+            } => {
+                self.tasks.push(Task::Set {
+                    name: name.clone(),
+                    value: expression.get(*value),
+                });
+                self.tasks.push(Task::Evaluate(expression.get(*object)));
+            }
+            ExpressionKind::List { elements, .. } => {
+                self.tasks.push(Task::BuildList(elements.len()));
+                for element in elements.iter().rev() {
+                    self.tasks.push(Task::Evaluate(expression.get(*element)));
+                }
+            }
+            ExpressionKind::Map { brace, entries } => {
+                self.tasks.push(Task::BuildMap {
+                    brace: brace.clone(),
+                    count: entries.len(),
+                });
+                for (key, value) in entries.iter().rev() {
+                    self.tasks.push(Task::Evaluate(expression.get(*value)));
+                    self.tasks.push(Task::Evaluate(expression.get(*key)));
+                }
+            }
+            ExpressionKind::Index {
+                object,
+                bracket,
+                index,
+            } => {
+                self.tasks.push(Task::Index(bracket.clone()));
+                self.tasks.push(Task::Evaluate(expression.get(*index)));
+                self.tasks.push(Task::Evaluate(expression.get(*object)));
+            }
+            ExpressionKind::SetIndex {
+                object,
+                bracket,
+                index,
+                value,
+            } => {
+                self.tasks.push(Task::SetIndex(bracket.clone()));
+                self.tasks.push(Task::Evaluate(expression.get(*value)));
+                self.tasks.push(Task::Evaluate(expression.get(*index)));
+                self.tasks.push(Task::Evaluate(expression.get(*object)));
+            }
+            ExpressionKind::This(keyword) => {
+                let value = self.look_up(&expression, keyword)?;
+                self.values.push(value);
+            }
+            ExpressionKind::Super { keyword, method } => {
+                let (superclass, instance) = match self.binding(&expression) {
+                    Binding::Local { depth, slot } if depth > 0 => {
+                        let environment = self.environment.borrow();
+                        (
+                            environment.get_at(depth, slot),
+                            environment.get_at(depth - 1, 0),
+                        )
+                    }
+                    _ => (None, None),
+                };
+
+                let function = match (superclass, instance) {
+                    (Some(Value::Class(superclass)), Some(instance)) => superclass
+                        .find_method(&method.lexeme())
+                        .map(|function| function.bind(instance)),
+                    _ => {
+                        return Err(self.build_error(
+                            keyword,
+                            "Can't use 'super' outside of a subclass method.".to_string(),
+                        ))
+                    }
+                };
+
+                match function {
+                    Some(function) => {
+                        self.track_value(&function);
+                        self.values.push(function);
+                    }
+                    None => {
+                        return Err(self
+                            .build_error(
+                                method,
+                                format!("Undefined property '{}'.", method.lexeme()),
+                            )
+                            .with_category(ErrorCategory::Name))
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn call_value(
+        &mut self,
+        callee: Value,
+        arguments: Vec<Value>,
+        line: usize,
+    ) -> Result<(), RuntimeError> {
+        self.current_line = line;
+
+        match callee {
+            Value::Native(native) => {
+                self.check_arity(Arity::exactly(native.arity), arguments.len())?;
+                self.profile_enter(|| native.name.clone());
+                let value = (native.function)(self, &arguments);
+                self.profile_leave();
+                let value = value?;
+                self.allocate_value(&value)?;
+                self.values.push(value);
+            }
+            Value::Function(function) => {
+                self.check_arity(function.arity(), arguments.len())?;
+                self.call_function(function, arguments)?;
+            }
+            Value::Closure(closure) => {
+                self.check_arity(closure.prototype.arity, arguments.len())?;
+                let value =
+                    self.call_closure(Value::Closure(Rc::clone(&closure)), closure, arguments)?;
+                self.values.push(value);
+            }
+            Value::BoundMethod(bound) => {
+                self.check_arity(bound.method.prototype.arity, arguments.len())?;
+                let value =
+                    self.call_closure(bound.receiver.clone(), Rc::clone(&bound.method), arguments)?;
+                self.values.push(value);
+            }
+            #[cfg(feature = "regvm")]
+            Value::RegisterFunction(function) => {
+                self.check_arity(Arity::exactly(function.arity), arguments.len())?;
+                let mut vm = mem::take(&mut self.register_vm);
+                let result = vm.call(self, function, arguments);
+                self.register_vm = vm;
+                self.values.push(result?);
+            }
+            Value::Class(class) => {
+                self.check_arity(class.arity(), arguments.len())?;
+                self.allocate(mem::size_of::<Instance>())?;
+
+                let instance = Rc::new(RefCell::new(Instance::new(Rc::clone(&class))));
+                self.track(&instance);
+                let instance = Value::Instance(instance);
+
+                match class.find_method("init") {
+                    Some(Method::Function(initializer)) => {
+                        let initializer = initializer.bind(instance);
+                        self.call_function(Rc::new(initializer), arguments)?;
+                    }
+                    Some(Method::Closure(initializer)) => {
+                        let value = self.call_closure(instance, initializer, arguments)?;
+                        self.values.push(value);
+                    }
+                    None => self.values.push(instance),
+                }
+            }
+            _ => {
+                return Err(self
+                    .runtime_error("Can only call functions and classes.".to_string())
+                    .with_category(ErrorCategory::Type))
+            }
+        }
+
+        Ok(())
+    }
+
+    fn call_closure(
+        &mut self,
+        receiver: Value,
+        closure: Rc<Closure>,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let mut vm = mem::take(&mut self.vm);
+        let result = vm.call(self, receiver, closure, arguments);
+        self.vm = vm;
+        result
+    }
+
+    fn call_function(
+        &mut self,
+        function: Rc<Function>,
+        arguments: Vec<Value>,
+    ) -> Result<(), RuntimeError> {
+        let mut environment = Environment::with_enclosing(Rc::clone(&function.closure));
+        let count = arguments.len();
+        let mut arguments = arguments.into_iter();
+        for argument in arguments.by_ref().take(function.arity().named()) {
+            environment.push(argument);
+        }
+        let defaults = default_tasks(&function.declaration, count);
+        if function.declaration.rest.is_some() && defaults.is_empty() {
+            let rest: Vec<Value> = arguments.collect();
+            self.allocate(rest.len() * mem::size_of::<Value>())?;
+            let rest = Value::from(rest);
+            self.track_value(&rest);
+            environment.push(rest);
+        }
+
+        if function.declaration.is_generator {
+            let mut tasks = vec![Task::FinishCall];
+            for statement in function.declaration.body.iter().rev() {
+                tasks.push(Task::Execute(Rc::clone(statement)));
+            }
+            tasks.extend(defaults);
+
+            let environment = Rc::new(RefCell::new(environment));
+            self.track(&environment);
+            let generator = Generator {
+                name: function.name(),
+                state: GeneratorState::Suspended,
+                continuation: Continuation::Tasks {
+                    tasks,
+                    values: Vec::new(),
+                    environment,
+                },
+            };
+
+            self.values
+                .push(Value::Generator(Rc::new(RefCell::new(generator))));
+            return Ok(());
+        }
+
+        if self.call_depth() >= self.options.max_call_depth {
+            return Err(self.resource_error("Stack overflow."));
+        }
+
+        let receiver = if function.is_initializer {
+            function.closure.borrow().get_at(0, 0)
+        } else {
+            None
+        };
+
+        let environment = Rc::new(RefCell::new(environment));
+        self.track(&environment);
+        let previous = mem::replace(&mut self.environment, environment);
+
+        self.frames.push(Frame {
+            environment: previous,
+            task_base: self.tasks.len(),
+            value_base: self.values.len(),
+            receiver,
+            function: Some(Rc::clone(&function)),
+            generator: None,
+            line: self.current_line,
+            deferred: Vec::new(),
+        });
+        self.profile_enter(|| function.name());
+
+        self.tasks.push(Task::FinishCall);
+        for statement in function.declaration.body.iter().rev() {
+            self.tasks.push(Task::Execute(Rc::clone(statement)));
+        }
+        self.tasks.extend(defaults);
+
+        Ok(())
+    }
+
+    fn finish_call(&mut self, value: Value) {
+        let deferred = self
+            .frames
+            .last_mut()
+            .and_then(|frame| frame.deferred.pop());
+        if let Some(deferred) = deferred {
+            self.values.push(value);
+            self.tasks.push(Task::Leave);
+            self.run_deferred(deferred);
+            return;
+        }
+
+        if let Some(frame) = self.frames.pop() {
+            self.profile_leave();
+            self.values.truncate(frame.value_base);
+            self.environment = frame.environment;
+
+            match frame.generator {
+                Some(generator) => {
+                    generator.borrow_mut().state = GeneratorState::Done;
+                    self.values.push(Value::Nil);
+                }
+                None => self.values.push(frame.receiver.unwrap_or(value)),
+            }
+        }
+    }
+
+    fn run_deferred(
+        &mut self,
+        (statement, environment): (Rc<Statement>, Rc<RefCell<Environment>>),
+    ) {
+        if let Statement::Defer { body, .. } = &*statement {
+            self.execute_block(body, Environment::with_enclosing(environment));
+        }
+    }
+
+    pub(crate) fn suspend_error(&mut self, error: RuntimeError) -> Pending {
+        Pending {
+            error,
+            thrown: self.thrown.take(),
+        }
+    }
+
+    pub(crate) fn resume_error(&mut self, pending: Pending) -> RuntimeError {
+        self.thrown = pending.thrown;
+        pending.error
+    }
+
+    fn call_method(&mut self, object: Value, name: &str, line: usize) -> Result<(), RuntimeError> {
+        let method = self.get_property(line, name, object)?;
+        self.call_value(method, Vec::new(), line)
+    }
+
+    fn iterate(
+        &mut self,
+        name: Token,
+        iterator: Value,
+        index: usize,
+        body: Rc<Statement>,
+        value: Value,
+    ) {
+        let mut environment = Environment::with_enclosing(Rc::clone(&self.environment));
+        environment.push(value);
+        let environment = Rc::new(RefCell::new(environment));
+        self.track(&environment);
+        let previous = mem::replace(&mut self.environment, environment);
+
+        self.tasks.push(Task::Iterate {
+            name,
+            iterator,
+            index,
+            body: Rc::clone(&body),
+        });
+        self.tasks.push(Task::RestoreEnvironment(previous));
+        self.tasks.push(Task::Execute(body));
+    }
+
+    fn resume(
+        &mut self,
+        generator: Rc<RefCell<Generator>>,
+        token: &Token,
+    ) -> Result<(), RuntimeError> {
+        let state = generator.borrow().state;
+        match state {
+            GeneratorState::Done => {
+                self.values.push(Value::Nil);
+                return Ok(());
+            }
+            GeneratorState::Running => {
+                return Err(self.build_error(token, GENERATOR_RUNNING.to_string()))
+            }
+            GeneratorState::Suspended => (),
+        }
+
+        if self.call_depth() >= self.options.max_call_depth {
+            self.current_line = token.line();
+            return Err(self.resource_error("Stack overflow."));
+        }
+
+        let (tasks, values, environment) = {
+            let mut generator = generator.borrow_mut();
+            let Continuation::Tasks {
+                tasks,
+                values,
+                environment,
+            } = &mut generator.continuation
+            else {
+                return Err(self.build_error(token, FOREIGN_GENERATOR.to_string()));
+            };
+            let continuation = (mem::take(tasks), mem::take(values), Rc::clone(environment));
+            generator.state = GeneratorState::Running;
+            continuation
+        };
+
+        self.profile_enter(|| generator.borrow().name.clone());
+        let previous = mem::replace(&mut self.environment, environment);
+        self.frames.push(Frame {
+            environment: previous,
+            task_base: self.tasks.len(),
+            value_base: self.values.len(),
+            receiver: None,
+            function: None,
+            generator: Some(generator),
+            line: token.line(),
+            deferred: Vec::new(),
+        });
+
+        self.tasks.extend(tasks);
+        self.values.extend(values);
+
+        Ok(())
+    }
+
+    fn check_arity(&self, arity: Arity, count: usize) -> Result<(), RuntimeError> {
+        if arity.accepts(count) {
+            Ok(())
+        } else {
+            Err(self
+                .runtime_error(arity.error(count))
+                .with_category(ErrorCategory::Type))
+        }
+    }
+
+    fn define_class(
+        &mut self,
+        statement: &Statement,
+        superclass: Option<Value>,
+    ) -> Result<(), RuntimeError> {
+        let Statement::Class {
+            name,
+            superclass: superclass_expression,
+            methods,
+        } = statement
+        else {
+            return Ok(());
+        };
+
+        let superclass = match (
+            superclass,
+            superclass_expression
+                .as_deref()
+                .map(|expression| &expression.kind),
+        ) {
+            (Some(Value::Class(superclass)), _) => Some(superclass),
+            (Some(_), Some(ExpressionKind::Variable(superclass_name))) => {
+                return Err(self
+                    .build_error(superclass_name, "Superclass must be a class.".to_string())
+                    .with_category(ErrorCategory::Type))
+            }
+            (Some(_), _) => {
+                return Err(self
+                    .build_error(name, "Superclass must be a class.".to_string())
+                    .with_category(ErrorCategory::Type))
+            }
+            (None, _) => None,
+        };
+
+        let mut closure = Rc::clone(&self.environment);
+        if let Some(superclass) = &superclass {
+            let mut environment = Environment::with_enclosing(closure);
+            environment.push(Value::Class(Rc::clone(superclass)));
+            closure = Rc::new(RefCell::new(environment));
+            self.track(&closure);
+        }
+
+        let methods: HashMap<String, Method> = methods
+            .iter()
+            .map(|declaration| {
+                let method_name = declaration.name.lexeme();
+                let function = Rc::new(Function {
+                    declaration: Rc::clone(declaration),
+                    closure: Rc::clone(&closure),
+                    is_initializer: method_name == "init",
+                    is_bound: false,
+                });
+                self.track(&function);
+
+                (method_name, Method::Function(function))
+            })
+            .collect();
+
+        let class = Rc::new(Class {
+            name: name.lexeme(),
+            superclass,
+            methods,
+        });
+        self.track(&class);
+
+        self.declare(name.lexeme(), Value::Class(class));
+
+        Ok(())
+    }
+
+    // What a bytecode `for` loop takes its values from: the iterator an
+    // instance's `iterator` method gives, or the value itself.
+    pub(crate) fn iterator(&mut self, line: usize, iterable: Value) -> Result<Value, RuntimeError> {
+        let iterator = match &iterable {
+            Value::Instance(instance) if has_method(instance, "iterator") => {
+                let method = self.get_property(line, "iterator", iterable)?;
+                self.current_line = line;
+                self.call(method, Vec::new())?
+            }
+            _ => iterable,
+        };
+
+        check_iterator(&iterator).map_err(|message| {
+            self.line_error(line, message.to_string())
+                .with_category(ErrorCategory::Type)
+        })?;
+        Ok(iterator)
+    }
+
+    // The value after the first `index` from a list or an iterator instance
+    // for a bytecode `for` loop, or none once it is done. The VM resumes
+    // generators itself.
+    pub(crate) fn next_value(
+        &mut self,
+        line: usize,
+        iterator: &Value,
+        index: usize,
+    ) -> Result<Option<Value>, RuntimeError> {
+        match iterator {
+            Value::List(list) => Ok(list.borrow().get(index).cloned()),
+            Value::Instance(_) => {
+                self.current_line = line;
+                let done = self.get_property(line, "done", iterator.clone())?;
+                if self.call(done, Vec::new())?.is_truthy() {
+                    return Ok(None);
+                }
+
+                let next = self.get_property(line, "next", iterator.clone())?;
+                self.call(next, Vec::new()).map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    pub(crate) fn get_property(
+        &mut self,
+        line: usize,
+        property: &str,
+        object: Value,
+    ) -> Result<Value, RuntimeError> {
+        let instance = match object {
+            Value::Instance(instance) => instance,
+            Value::Foreign(foreign) => return self.get_foreign_property(line, property, foreign),
+            _ => {
+                return Err(self
+                    .line_error(line, "Only instances have properties.".to_string())
+                    .with_category(ErrorCategory::Type))
+            }
+        };
+
+        if let Some(value) = instance.borrow().fields.get(property) {
+            return Ok(value.clone());
+        }
+
+        let method = instance.borrow().class.find_method(property);
+        match method {
+            Some(method) => {
+                let method = method.bind(Value::Instance(Rc::clone(&instance)));
+                self.track_value(&method);
+                Ok(method)
+            }
+            None => Err(self
+                .line_error(line, format!("Undefined property '{}'.", property))
+                .with_category(ErrorCategory::Name)),
+        }
+    }
+
+    pub(crate) fn set_property(
+        &mut self,
+        line: usize,
+        property: Symbol,
+        object: Value,
+        value: Value,
+    ) -> Result<(), RuntimeError> {
+        match object {
+            Value::Instance(instance) => {
+                self.current_line = line;
+                self.allocate(mem::size_of::<Value>() + property.len())?;
+                if instance
+                    .borrow_mut()
+                    .fields
+                    .insert(property, value)
+                    .is_none()
+                {
+                    self.grow(mem::size_of::<(Symbol, Value)>());
+                }
+                Ok(())
+            }
+            Value::Foreign(foreign) => self.set_foreign_property(line, &property, &foreign, value),
+            _ => Err(self
+                .line_error(line, "Only instances have fields.".to_string())
+                .with_category(ErrorCategory::Type)),
+        }
+    }
+
+    fn get_foreign_property(
+        &mut self,
+        line: usize,
+        property: &str,
+        foreign: Rc<Foreign>,
+    ) -> Result<Value, RuntimeError> {
+        if let Some(getter) = foreign.class().getter_for(property) {
+            self.current_line = line;
+            return getter(self, &foreign);
+        }
+
+        match foreign.class().method_for(property) {
+            Some((arity, method)) => {
+                let native = Native {
+                    name: property.to_string(),
+                    arity,
+                    function: Rc::new(move |interpreter: &mut Interpreter, arguments: &[Value]| {
+                        method(interpreter, &foreign, arguments)
+                    }),
+                };
+                Ok(Value::Native(Rc::new(native)))
+            }
+            None => Err(self
+                .line_error(line, format!("Undefined property '{}'.", property))
+                .with_category(ErrorCategory::Name)),
+        }
+    }
+
+    fn set_foreign_property(
+        &mut self,
+        line: usize,
+        property: &str,
+        foreign: &Foreign,
+        value: Value,
+    ) -> Result<(), RuntimeError> {
+        match foreign.class().setter_for(property) {
+            Some(setter) => {
+                self.current_line = line;
+                setter(self, foreign, value)
+            }
+            None => Err(self.line_error(
+                line,
+                format!(
+                    "Can't set property '{}' on {}.",
+                    property,
+                    foreign.class().name()
+                ),
+            )),
+        }
+    }
+
+    pub(crate) fn index(
+        &self,
+        line: usize,
+        object: &Value,
+        index: &Value,
+    ) -> Result<Value, RuntimeError> {
+        match object {
+            Value::List(list) => {
+                let position = self.list_position(line, list.borrow().len(), index)?;
+                Ok(list.borrow()[position].clone())
+            }
+            Value::Map(map) => {
+                let key = self.map_key(line, index)?;
+                Ok(map.borrow().get(key).cloned().unwrap_or(Value::Nil))
+            }
+            _ => Err(self
+                .line_error(line, "Only lists and maps can be indexed.".to_string())
+                .with_category(ErrorCategory::Type)),
+        }
+    }
+
+    // What a destructuring pattern takes from `object` under `key`: a
+    // position for `[a, b]`, a name for `{x, y}`.
+    // Checks that `var a, b = value;` gets as many values as it names.
+    pub(crate) fn expect_values(
+        &self,
+        line: usize,
+        value: &Value,
+        count: usize,
+    ) -> Result<(), RuntimeError> {
+        match value {
+            Value::List(list) if list.borrow().len() == count => Ok(()),
+            Value::List(list) => Err(self
+                .line_error(
+                    line,
+                    format!("Expected {} values but got {}.", count, list.borrow().len()),
+                )
+                .with_category(ErrorCategory::Type)),
+            _ => Err(self
+                .line_error(line, "Can only destructure lists by position.".to_string())
+                .with_category(ErrorCategory::Type)),
+        }
+    }
+
+    pub(crate) fn unpack(
+        &mut self,
+        line: usize,
+        object: &Value,
+        key: &Value,
+    ) -> Result<Value, RuntimeError> {
+        match (object, key) {
+            (Value::List(_), Value::Number(_)) | (Value::Map(_), Value::String(_)) => {
+                self.index(line, object, key)
+            }
+            (Value::Instance(_) | Value::Foreign(_), Value::String(name)) => {
+                self.get_property(line, name, object.clone())
+            }
+            (_, Value::Number(_)) => Err(self
+                .line_error(line, "Can only destructure lists by position.".to_string())
+                .with_category(ErrorCategory::Type)),
+            _ => Err(self
+                .line_error(
+                    line,
+                    "Can only destructure instances and maps by name.".to_string(),
+                )
+                .with_category(ErrorCategory::Type)),
+        }
+    }
+
+    pub(crate) fn set_index(
+        &mut self,
+        line: usize,
+        object: &Value,
+        index: &Value,
+        value: Value,
+    ) -> Result<(), RuntimeError> {
+        match object {
+            Value::List(list) => {
+                let position = self.list_position(line, list.borrow().len(), index)?;
+                list.borrow_mut()[position] = value;
+            }
+            Value::Map(map) => {
+                let key = self.map_key(line, index)?;
+                if map.borrow_mut().insert(key.to_string(), value).is_none() {
+                    self.grow(mem::size_of::<(String, Value)>());
+                }
+            }
+            _ => {
+                return Err(self
+                    .line_error(line, "Only lists and maps can be indexed.".to_string())
+                    .with_category(ErrorCategory::Type))
+            }
+        }
+
+        Ok(())
+    }
+
+    fn map_key<'a>(&self, line: usize, index: &'a Value) -> Result<&'a str, RuntimeError> {
+        match index {
+            Value::String(key) => Ok(key),
+            _ => Err(self
+                .line_error(line, "Map key must be a string.".to_string())
+                .with_category(ErrorCategory::Type)),
+        }
+    }
+
+    fn list_position(
+        &self,
+        line: usize,
+        length: usize,
+        index: &Value,
+    ) -> Result<usize, RuntimeError> {
+        match index {
+            Value::Number(number) if number % 1.0 == 0.0 => {
+                if *number >= 0.0 && (*number as usize) < length {
+                    Ok(*number as usize)
+                } else {
+                    Err(self
+                        .line_error(line, "List index out of range.".to_string())
+                        .with_category(ErrorCategory::Index))
+                }
+            }
+            Value::Int(integer) => match usize::try_from(*integer) {
+                Ok(index) if index < length => Ok(index),
+                _ => Err(self
+                    .line_error(line, "List index out of range.".to_string())
+                    .with_category(ErrorCategory::Index)),
+            },
+            _ => Err(self
+                .line_error(line, "List index must be an integer.".to_string())
+                .with_category(ErrorCategory::Type)),
+        }
+    }
+
+    fn unary(&self, operator: &Token, right: Value) -> Result<Value, RuntimeError> {
+        match (&operator.kind, right) {
+            (Kind::Minus, right) => match arithmetic::negate(&right) {
+                Some(result) => result.map_err(|failure| self.operator_error(operator, failure)),
+                None => Err(self
+                    .build_error(operator, "Operand must be a number.".to_string())
+                    .with_category(ErrorCategory::Type)),
+            },
+            (_, right) => Ok(Value::Boolean(!right.is_truthy())),
+        }
+    }
+
+    fn binary(&self, operator: &Token, left: Value, right: Value) -> Result<Value, RuntimeError> {
+        match (&operator.kind, left, right) {
+            (Kind::EqualEqual, left, right) => Ok(Value::Boolean(left == right)),
+            (Kind::ExclamationEqual, left, right) => Ok(Value::Boolean(left != right)),
+            (Kind::Plus, Value::String(left), Value::String(right)) => {
+                Ok(Value::String(concatenate(&left, &right)))
+            }
+            (Kind::Plus, Value::List(left), Value::List(right)) => {
+                Ok(concatenate_lists(&left, &right))
+            }
+            (Kind::Plus, left, right) => match arithmetic::add(&left, &right) {
+                Some(result) => result.map_err(|failure| self.operator_error(operator, failure)),
+                None => Err(self
+                    .build_error(
+                        operator,
+                        "Operands must be two numbers or two strings.".to_string(),
+                    )
+                    .with_category(ErrorCategory::Type)),
+            },
+            (kind, left, right) => {
+                let operator_kind = match kind {
+                    Kind::Minus => Operator::Subtract,
+                    Kind::Slash => Operator::Divide,
+                    Kind::Asterisk => Operator::Multiply,
+                    Kind::Keyword(Keyword::Div) => Operator::FloorDivide,
+                    Kind::Greater => Operator::Greater,
+                    Kind::GreaterEqual => Operator::GreaterEqual,
+                    Kind::Less => Operator::Less,
+                    _ => Operator::LessEqual,
+                };
+                arithmetic::binary(operator_kind, &left, &right, self.arithmetic_rules())
+                    .map_err(|failure| self.operator_error(operator, failure))
+            }
+        }
+    }
+
+    fn operator_error(&self, operator: &Token, failure: arithmetic::Failure) -> RuntimeError {
+        self.build_error(operator, failure.message.to_string())
+            .with_category(failure.category)
+    }
+
+    fn binding(&self, expression: &Expr) -> Binding {
+        self.bindings
+            .get(&expression.id)
+            .copied()
+            .unwrap_or(Binding::Global)
+    }
+
+    // Top-level declarations are named globals; anything nested takes the
+    // next slot the resolver assigned it.
+    fn declare(&mut self, name: String, value: Value) {
+        if Rc::ptr_eq(&self.environment, &self.globals) {
+            self.globals.borrow_mut().define(name, value);
+        } else {
+            self.environment.borrow_mut().push(value);
+        }
+    }
+
+    fn look_up(&self, expression: &Expr, name: &Token) -> Result<Value, RuntimeError> {
+        let value = match self.binding(expression) {
+            Binding::Local { depth, slot } => self.environment.borrow().get_at(depth, slot),
+            Binding::Forward { depth, slot } => self
+                .environment
+                .borrow()
+                .get_at(depth, slot)
+                .or_else(|| self.globals.borrow().get(&name.lexeme())),
+            Binding::Global => self.globals.borrow().get(&name.lexeme()),
+        };
+
+        value.ok_or_else(|| {
+            self.build_error(name, format!("Undefined variable '{}'.", name.lexeme()))
+                .with_category(ErrorCategory::Name)
+        })
+    }
+
+    fn pop_value(&mut self) -> Value {
+        self.values.pop().unwrap_or(Value::Nil)
+    }
+
+    fn build_error(&self, token: &Token, message: String) -> RuntimeError {
+        self.line_error(token.line(), message)
+    }
+
+    fn line_error(&self, line: usize, message: String) -> RuntimeError {
+        RuntimeError {
+            kind: RuntimeErrorKind::Error,
+            category: ErrorCategory::General,
+            message,
+            line,
+        }
+    }
+}
+
+fn has_method(instance: &Rc<RefCell<Instance>>, name: &str) -> bool {
+    instance.borrow().class.find_method(name).is_some()
+}
+
+// Whether a `for` loop can take values from `iterator`, or why not.
+fn check_iterator(iterator: &Value) -> Result<(), &'static str> {
+    match iterator {
+        Value::Generator(_) | Value::List(_) => Ok(()),
+        Value::Instance(instance)
+            if has_method(instance, "next") && has_method(instance, "done") =>
+        {
+            Ok(())
+        }
+        Value::Instance(_) => Err("Iterator must have 'next' and 'done' methods."),
+        _ => Err("Can only iterate over generators, lists and iterators."),
+    }
+}
+
+// The tasks that give the parameters a call left out their default values,
+// in the call's environment so that each sees the parameters before it,
+// then an empty list to a rest parameter. They run before the body, so
+// they are pushed last and in reverse.
+fn default_tasks(declaration: &FunctionDeclaration, count: usize) -> Vec<Task> {
+    let arity = declaration.arity();
+    if count >= arity.named() {
+        return Vec::new();
+    }
+
+    let mut tasks = Vec::new();
+    if let Some(rest) = &declaration.rest {
+        tasks.push(Task::Define(rest.lexeme()));
+        tasks.push(Task::BuildList(0));
+    }
+    let missing = declaration.parameters[count..]
+        .iter()
+        .zip(&declaration.defaults[count - arity.required..]);
+    for (parameter, default) in missing.rev() {
+        tasks.push(Task::Define(parameter.lexeme()));
+        tasks.push(Task::Evaluate(default.clone()));
+    }
+    tasks
+}
+
+// The key `unpack` takes the name at `position` of a pattern by.
+pub(crate) fn destructuring_key(pattern: Pattern, position: usize, name: &Token) -> Value {
+    match pattern {
+        Pattern::Elements | Pattern::Values => Value::Number(position as f64),
+        Pattern::Fields => Value::String(name.lexeme().into()),
+    }
+}
@@ -0,0 +1,197 @@
+use core::cell::RefCell;
+#[cfg(not(feature = "std"))]
+use core::fmt;
+#[cfg(feature = "std")]
+use std::fs::{self, OpenOptions};
+#[cfg(feature = "std")]
+use std::io::{self, BufRead, Write};
+
+use crate::prelude::*;
+
+#[cfg(feature = "std")]
+pub type IoError = io::Error;
+
+#[cfg(not(feature = "std"))]
+#[derive(Debug)]
+pub struct IoError {
+    message: String,
+}
+
+#[cfg(not(feature = "std"))]
+impl IoError {
+    pub fn new(message: &str) -> IoError {
+        IoError {
+            message: message.to_string(),
+        }
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl fmt::Display for IoError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{}", self.message)
+    }
+}
+
+pub type IoResult<T> = Result<T, IoError>;
+
+pub trait HostIo {
+    fn write_stdout(&mut self, text: &str) -> IoResult<()>;
+    fn write_stderr(&mut self, text: &str) -> IoResult<()>;
+    fn read_line(&mut self) -> IoResult<Option<String>>;
+    fn read_file(&mut self, path: &str) -> IoResult<String>;
+    fn write_file(&mut self, path: &str, contents: &str) -> IoResult<()>;
+    fn append_file(&mut self, path: &str, contents: &str) -> IoResult<()>;
+}
+
+#[cfg(feature = "std")]
+pub struct StdIo;
+
+#[cfg(feature = "std")]
+impl HostIo for StdIo {
+    fn write_stdout(&mut self, text: &str) -> IoResult<()> {
+        io::stdout().lock().write_all(text.as_bytes())
+    }
+
+    fn write_stderr(&mut self, text: &str) -> IoResult<()> {
+        io::stderr().lock().write_all(text.as_bytes())
+    }
+
+    fn read_line(&mut self) -> IoResult<Option<String>> {
+        let mut line = String::new();
+        if io::stdin().lock().read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+
+        let length = line.trim_end_matches(['\n', '\r']).len();
+        line.truncate(length);
+
+        Ok(Some(line))
+    }
+
+    fn read_file(&mut self, path: &str) -> IoResult<String> {
+        fs::read_to_string(path)
+    }
+
+    fn write_file(&mut self, path: &str, contents: &str) -> IoResult<()> {
+        fs::write(path, contents)
+    }
+
+    fn append_file(&mut self, path: &str, contents: &str) -> IoResult<()> {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?
+            .write_all(contents.as_bytes())
+    }
+}
+
+pub struct DisabledIo;
+
+impl DisabledIo {
+    #[cfg(feature = "std")]
+    fn denied<T>() -> IoResult<T> {
+        Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "I/O is disabled",
+        ))
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn denied<T>() -> IoResult<T> {
+        Err(IoError::new("I/O is disabled"))
+    }
+}
+
+impl HostIo for DisabledIo {
+    #[cfg(feature = "std")]
+    fn write_stdout(&mut self, text: &str) -> IoResult<()> {
+        StdIo.write_stdout(text)
+    }
+
+    #[cfg(feature = "std")]
+    fn write_stderr(&mut self, text: &str) -> IoResult<()> {
+        StdIo.write_stderr(text)
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn write_stdout(&mut self, _text: &str) -> IoResult<()> {
+        Ok(())
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn write_stderr(&mut self, _text: &str) -> IoResult<()> {
+        Ok(())
+    }
+
+    fn read_line(&mut self) -> IoResult<Option<String>> {
+        DisabledIo::denied()
+    }
+
+    fn read_file(&mut self, _path: &str) -> IoResult<String> {
+        DisabledIo::denied()
+    }
+
+    fn write_file(&mut self, _path: &str, _contents: &str) -> IoResult<()> {
+        DisabledIo::denied()
+    }
+
+    fn append_file(&mut self, _path: &str, _contents: &str) -> IoResult<()> {
+        DisabledIo::denied()
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct CaptureIo {
+    input: Rc<RefCell<Vec<String>>>,
+    stdout: Rc<RefCell<String>>,
+    stderr: Rc<RefCell<String>>,
+}
+
+impl CaptureIo {
+    pub fn new() -> CaptureIo {
+        CaptureIo::default()
+    }
+
+    pub fn with_input(lines: Vec<String>) -> CaptureIo {
+        let capture = CaptureIo::new();
+        capture.input.borrow_mut().extend(lines.into_iter().rev());
+        capture
+    }
+
+    pub fn stdout(&self) -> String {
+        self.stdout.borrow().clone()
+    }
+
+    pub fn stderr(&self) -> String {
+        self.stderr.borrow().clone()
+    }
+}
+
+impl HostIo for CaptureIo {
+    fn write_stdout(&mut self, text: &str) -> IoResult<()> {
+        self.stdout.borrow_mut().push_str(text);
+        Ok(())
+    }
+
+    fn write_stderr(&mut self, text: &str) -> IoResult<()> {
+        self.stderr.borrow_mut().push_str(text);
+        Ok(())
+    }
+
+    fn read_line(&mut self) -> IoResult<Option<String>> {
+        Ok(self.input.borrow_mut().pop())
+    }
+
+    fn read_file(&mut self, _path: &str) -> IoResult<String> {
+        DisabledIo::denied()
+    }
+
+    fn write_file(&mut self, _path: &str, _contents: &str) -> IoResult<()> {
+        DisabledIo::denied()
+    }
+
+    fn append_file(&mut self, _path: &str, _contents: &str) -> IoResult<()> {
+        DisabledIo::denied()
+    }
+}
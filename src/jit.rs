@@ -0,0 +1,834 @@
+use alloc::rc::Weak;
+use core::mem;
+
+use cranelift_codegen::ir::condcodes::FloatCC;
+use cranelift_codegen::ir::{
+    types, AbiParam, Block, FuncRef, InstBuilder, MemFlags, StackSlot, StackSlotData,
+    StackSlotKind, Value as Ir,
+};
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{default_libcall_names, FuncId, Linkage, Module};
+
+use crate::bytecode::{Constant, OpCode, Prototype};
+use crate::error::{ErrorCategory, RuntimeError, RuntimeErrorKind};
+use crate::interpreter::{DivisionByZero, Interpreter};
+use crate::prelude::*;
+use crate::value::Value;
+
+pub const DEFAULT_HOT_THRESHOLD: u32 = 100;
+
+type Code = unsafe extern "C" fn(*mut Context, *const f64) -> f64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Type {
+    Number,
+    Boolean,
+    Nil,
+    Callee,
+    Closure,
+}
+
+impl Type {
+    fn value(self, number: f64) -> Value {
+        match self {
+            Type::Number => Value::Number(number),
+            Type::Boolean => Value::Boolean(number != 0.0),
+            _ => Value::Nil,
+        }
+    }
+}
+
+struct Instruction {
+    op: OpCode,
+    operand: usize,
+    line: usize,
+}
+
+struct Analysis {
+    states: Vec<Option<Vec<Type>>>,
+    returns: Type,
+    calls_itself: bool,
+    divides: bool,
+}
+
+#[derive(Clone, Copy)]
+struct Compiled {
+    code: Code,
+    returns: Type,
+    calls_itself: bool,
+    divides: bool,
+}
+
+enum Tier {
+    Counting(u32),
+    Compiled(Compiled),
+    Rejected,
+}
+
+// Compiled code reports failures through this context rather than unwinding: helpers record the
+// error and set `failed`, which the generated code checks after every helper and self call.
+#[repr(C)]
+struct Context {
+    failed: u8,
+    depth: usize,
+    max_depth: usize,
+    interpreter: *mut Interpreter,
+    error: Option<RuntimeError>,
+}
+
+impl Context {
+    fn fail(&mut self, error: RuntimeError) -> u8 {
+        self.error = Some(error);
+        self.failed = 1;
+        1
+    }
+}
+
+extern "C" fn poll(context: *mut Context, line: i64) -> u8 {
+    let context = unsafe { &mut *context };
+    let interpreter = unsafe { &mut *context.interpreter };
+    interpreter.set_current_line(line as usize);
+    match interpreter.count_step() {
+        Ok(()) => 0,
+        Err(error) => context.fail(error),
+    }
+}
+
+extern "C" fn enter(context: *mut Context, line: i64) -> u8 {
+    let context = unsafe { &mut *context };
+    if context.depth >= context.max_depth {
+        return context.fail(RuntimeError {
+            kind: RuntimeErrorKind::ResourceExceeded,
+            category: ErrorCategory::General,
+            message: "Stack overflow.".to_string(),
+            line: line as usize,
+        });
+    }
+
+    context.depth += 1;
+    0
+}
+
+extern "C" fn leave(context: *mut Context) {
+    let context = unsafe { &mut *context };
+    context.depth -= 1;
+}
+
+// Functions are counted on every VM call and compiled once they get hot. Only a numeric subset
+// of the bytecode is supported: a function that uses anything else, or whose stack types can't be
+// inferred statically, is rejected and keeps running on the VM.
+pub struct Jit {
+    module: Option<JITModule>,
+    threshold: u32,
+    functions: HashMap<usize, (Weak<Prototype>, Tier)>,
+    compiled: usize,
+    declared: usize,
+    poll: FuncId,
+    enter: FuncId,
+    leave: FuncId,
+}
+
+impl Jit {
+    pub fn new(threshold: u32) -> Result<Jit, String> {
+        let mut flags = settings::builder();
+        flags
+            .set("use_colocated_libcalls", "false")
+            .map_err(|error| error.to_string())?;
+        flags
+            .set("is_pic", "false")
+            .map_err(|error| error.to_string())?;
+        let isa = cranelift_native::builder()
+            .map_err(|error| error.to_string())?
+            .finish(settings::Flags::new(flags))
+            .map_err(|error| error.to_string())?;
+
+        let mut builder = JITBuilder::with_isa(isa, default_libcall_names());
+        builder.symbol("lox_jit_poll", poll as *const u8);
+        builder.symbol("lox_jit_enter", enter as *const u8);
+        builder.symbol("lox_jit_leave", leave as *const u8);
+        let mut module = JITModule::new(builder);
+
+        let pointer = module.target_config().pointer_type();
+        let mut signature = module.make_signature();
+        signature.params.push(AbiParam::new(pointer));
+        signature.params.push(AbiParam::new(types::I64));
+        signature.returns.push(AbiParam::new(types::I8));
+        let poll = module
+            .declare_function("lox_jit_poll", Linkage::Import, &signature)
+            .map_err(|error| error.to_string())?;
+        let enter = module
+            .declare_function("lox_jit_enter", Linkage::Import, &signature)
+            .map_err(|error| error.to_string())?;
+
+        let mut signature = module.make_signature();
+        signature.params.push(AbiParam::new(pointer));
+        let leave = module
+            .declare_function("lox_jit_leave", Linkage::Import, &signature)
+            .map_err(|error| error.to_string())?;
+
+        Ok(Jit {
+            module: Some(module),
+            threshold,
+            functions: HashMap::new(),
+            compiled: 0,
+            declared: 0,
+            poll,
+            enter,
+            leave,
+        })
+    }
+
+    pub fn threshold(&self) -> u32 {
+        self.threshold
+    }
+
+    pub fn set_threshold(&mut self, threshold: u32) {
+        self.threshold = threshold;
+    }
+
+    pub fn compiled_functions(&self) -> usize {
+        self.compiled
+    }
+
+    fn lookup(&mut self, prototype: &Rc<Prototype>) -> Option<Compiled> {
+        let address = Rc::as_ptr(prototype) as usize;
+        let threshold = self.threshold;
+        let entry = self
+            .functions
+            .entry(address)
+            .or_insert_with(|| (Weak::new(), Tier::Counting(0)));
+        if entry.0.as_ptr() != Rc::as_ptr(prototype) {
+            *entry = (Rc::downgrade(prototype), Tier::Counting(0));
+        }
+
+        match &mut entry.1 {
+            Tier::Compiled(compiled) => return Some(*compiled),
+            Tier::Rejected => return None,
+            Tier::Counting(count) => {
+                *count += 1;
+                if *count < threshold {
+                    return None;
+                }
+            }
+        }
+
+        let tier = match self.compile(prototype) {
+            Some(compiled) => Tier::Compiled(compiled),
+            None => Tier::Rejected,
+        };
+        let compiled = match tier {
+            Tier::Compiled(compiled) => Some(compiled),
+            _ => None,
+        };
+        self.functions
+            .insert(address, (Rc::downgrade(prototype), tier));
+        compiled
+    }
+
+    fn compile(&mut self, prototype: &Prototype) -> Option<Compiled> {
+        // A generator's frame has to outlive the call that makes it.
+        if prototype.is_generator {
+            return None;
+        }
+        let instructions = decode(prototype)?;
+        let analysis = analyze(prototype, &instructions)?;
+        let module = self.module.as_mut()?;
+
+        let pointer = module.target_config().pointer_type();
+        let mut signature = module.make_signature();
+        signature.params.push(AbiParam::new(pointer));
+        signature.params.push(AbiParam::new(pointer));
+        signature.returns.push(AbiParam::new(types::F64));
+
+        let name = format!("lox_{}_{}", self.declared, prototype.name);
+        self.declared += 1;
+        let id = module
+            .declare_function(&name, Linkage::Local, &signature)
+            .ok()?;
+
+        let mut context = module.make_context();
+        context.func.signature = signature;
+        let mut functions = FunctionBuilderContext::new();
+        let builder = FunctionBuilder::new(&mut context.func, &mut functions);
+        let generator = Generator {
+            poll: module.declare_func_in_func(self.poll, builder.func),
+            enter: module.declare_func_in_func(self.enter, builder.func),
+            leave: module.declare_func_in_func(self.leave, builder.func),
+            this: module.declare_func_in_func(id, builder.func),
+            builder,
+            pointer,
+            blocks: vec![None; instructions.len()],
+            exit: None,
+            arguments: None,
+            context: None,
+        };
+        generator.generate(prototype, &instructions, &analysis);
+
+        module.define_function(id, &mut context).ok()?;
+        module.clear_context(&mut context);
+        module.finalize_definitions().ok()?;
+        let code = module.get_finalized_function(id);
+        self.compiled += 1;
+
+        Some(Compiled {
+            code: unsafe { mem::transmute::<*const u8, Code>(code) },
+            returns: analysis.returns,
+            calls_itself: analysis.calls_itself,
+            divides: analysis.divides,
+        })
+    }
+}
+
+impl Drop for Jit {
+    fn drop(&mut self) {
+        if let Some(module) = self.module.take() {
+            unsafe { module.free_memory() };
+        }
+    }
+}
+
+// Runs `prototype` natively if it is hot and compiled, returning None when the call should stay
+// on the VM. Self-recursive functions are only entered while their global still names them.
+pub(crate) fn call(
+    interpreter: &mut Interpreter,
+    prototype: &Rc<Prototype>,
+    arguments: &[f64],
+    depth: usize,
+    line: usize,
+) -> Result<Option<Value>, RuntimeError> {
+    let division = interpreter.options().division_by_zero;
+    let Some(jit) = interpreter.jit_mut() else {
+        return Ok(None);
+    };
+    let Some(compiled) = jit.lookup(prototype) else {
+        return Ok(None);
+    };
+    // Native code divides as the hardware does, so it can't raise the error.
+    if compiled.divides && division == DivisionByZero::Error {
+        return Ok(None);
+    }
+
+    if compiled.calls_itself {
+        let global = interpreter.globals().borrow().get(&prototype.name);
+        match global {
+            Some(Value::Closure(closure)) if Rc::ptr_eq(&closure.prototype, prototype) => {}
+            _ => return Ok(None),
+        }
+    }
+
+    let max_depth = interpreter.options().max_call_depth;
+    if depth >= max_depth {
+        return Err(RuntimeError {
+            kind: RuntimeErrorKind::ResourceExceeded,
+            category: ErrorCategory::General,
+            message: "Stack overflow.".to_string(),
+            line,
+        });
+    }
+
+    let mut context = Context {
+        failed: 0,
+        depth: depth + 1,
+        max_depth,
+        interpreter: interpreter as *mut Interpreter,
+        error: None,
+    };
+    let result = unsafe { (compiled.code)(&mut context, arguments.as_ptr()) };
+    match context.error {
+        Some(error) => Err(error),
+        None => Ok(Some(compiled.returns.value(result))),
+    }
+}
+
+fn decode(prototype: &Prototype) -> Option<Vec<Instruction>> {
+    let chunk = &prototype.chunk;
+    let code = chunk.code();
+    let byte = |at: usize| code.get(at).copied().map(usize::from);
+    let short = |at: usize| Some(byte(at)? << 8 | byte(at + 1)?);
+
+    let mut instructions = Vec::new();
+    let mut offsets = Vec::new();
+    let mut offset = 0;
+    while offset < code.len() {
+        let op = OpCode::try_from(code[offset]).ok()?;
+        let (operand, length) = match op {
+            OpCode::Constant | OpCode::GetGlobal | OpCode::GetLocal | OpCode::SetLocal => {
+                (byte(offset + 1)?, 1)
+            }
+            OpCode::Call => (byte(offset + 1)?, 1),
+            OpCode::ConstantLong | OpCode::GetGlobalLong => {
+                (byte(offset + 1)? << 16 | short(offset + 2)?, 3)
+            }
+            OpCode::GetLocalLong | OpCode::SetLocalLong => (short(offset + 1)?, 2),
+            OpCode::Jump | OpCode::JumpIfFalse => (offset + 3 + short(offset + 1)?, 2),
+            OpCode::Loop => ((offset + 3).checked_sub(short(offset + 1)?)?, 2),
+            OpCode::Nil
+            | OpCode::True
+            | OpCode::False
+            | OpCode::Pop
+            | OpCode::Equal
+            | OpCode::Greater
+            | OpCode::GreaterEqual
+            | OpCode::Less
+            | OpCode::LessEqual
+            | OpCode::Add
+            | OpCode::Subtract
+            | OpCode::Multiply
+            | OpCode::Divide
+            | OpCode::Not
+            | OpCode::Negate
+            | OpCode::Return => (0, 0),
+            _ => return None,
+        };
+
+        instructions.push(Instruction {
+            op: op.short(),
+            operand,
+            line: chunk.line(offset),
+        });
+        offsets.push(offset);
+        offset += 1 + length;
+    }
+
+    for instruction in &mut instructions {
+        if matches!(
+            instruction.op,
+            OpCode::Jump | OpCode::JumpIfFalse | OpCode::Loop
+        ) {
+            instruction.operand = offsets.binary_search(&instruction.operand).ok()?;
+        }
+    }
+
+    Some(instructions)
+}
+
+fn analyze(prototype: &Prototype, instructions: &[Instruction]) -> Option<Analysis> {
+    // Defaults run from code past the body, which native code has no entry
+    // for.
+    if !prototype.arity.is_exact() {
+        return None;
+    }
+
+    let mut states: Vec<Option<Vec<Type>>> = vec![None; instructions.len()];
+    let mut entry = vec![Type::Closure];
+    entry.resize(prototype.arity.required + 1, Type::Number);
+    *states.first_mut()? = Some(entry);
+
+    let mut pending = vec![0];
+    let mut returns = None;
+    let mut calls_itself = false;
+    let mut divides = false;
+    while let Some(index) = pending.pop() {
+        let mut stack = states[index].clone()?;
+        let instruction = &instructions[index];
+        let mut successors = [Some(index + 1), None];
+
+        match instruction.op {
+            OpCode::Constant => match prototype.chunk.constants().get(instruction.operand)? {
+                Constant::Number(_) => stack.push(Type::Number),
+                _ => return None,
+            },
+            OpCode::Nil => stack.push(Type::Nil),
+            OpCode::True | OpCode::False => stack.push(Type::Boolean),
+            OpCode::Pop => {
+                stack.pop()?;
+            }
+            OpCode::GetLocal => match *stack.get(instruction.operand)? {
+                Type::Closure => return None,
+                kind => stack.push(kind),
+            },
+            OpCode::SetLocal => {
+                let kind = *stack.last()?;
+                *stack.get_mut(instruction.operand)? = kind;
+            }
+            OpCode::GetGlobal => match prototype.chunk.constants().get(instruction.operand)? {
+                Constant::String(name) if **name == *prototype.name => {
+                    calls_itself = true;
+                    stack.push(Type::Callee);
+                }
+                _ => return None,
+            },
+            OpCode::Equal => {
+                let right = stack.pop()?;
+                let left = stack.pop()?;
+                if [left, right]
+                    .iter()
+                    .any(|kind| matches!(kind, Type::Callee | Type::Closure))
+                {
+                    return None;
+                }
+                stack.push(Type::Boolean);
+            }
+            OpCode::Greater
+            | OpCode::GreaterEqual
+            | OpCode::Less
+            | OpCode::LessEqual
+            | OpCode::Add
+            | OpCode::Subtract
+            | OpCode::Multiply
+            | OpCode::Divide => {
+                if stack.pop()? != Type::Number || stack.pop()? != Type::Number {
+                    return None;
+                }
+                divides |= instruction.op == OpCode::Divide;
+                stack.push(match instruction.op {
+                    OpCode::Add | OpCode::Subtract | OpCode::Multiply | OpCode::Divide => {
+                        Type::Number
+                    }
+                    _ => Type::Boolean,
+                });
+            }
+            OpCode::Not => match stack.pop()? {
+                Type::Callee | Type::Closure => return None,
+                _ => stack.push(Type::Boolean),
+            },
+            OpCode::Negate => {
+                if *stack.last()? != Type::Number {
+                    return None;
+                }
+            }
+            OpCode::Jump | OpCode::Loop => successors = [Some(instruction.operand), None],
+            OpCode::JumpIfFalse => match *stack.last()? {
+                Type::Number => {}
+                Type::Nil => successors = [Some(instruction.operand), None],
+                Type::Boolean => successors[1] = Some(instruction.operand),
+                _ => return None,
+            },
+            OpCode::Call => {
+                if instruction.operand != prototype.arity.required {
+                    return None;
+                }
+                for _ in 0..instruction.operand {
+                    if stack.pop()? != Type::Number {
+                        return None;
+                    }
+                }
+                if stack.pop()? != Type::Callee {
+                    return None;
+                }
+                stack.push(Type::Number);
+            }
+            OpCode::Return => {
+                let kind = stack.pop()?;
+                if matches!(kind, Type::Callee | Type::Closure) || returns.unwrap_or(kind) != kind {
+                    return None;
+                }
+                returns = Some(kind);
+                successors = [None, None];
+            }
+            _ => return None,
+        }
+
+        for successor in successors.into_iter().flatten() {
+            match states.get(successor)? {
+                Some(existing) if *existing != stack => return None,
+                Some(_) => {}
+                None => {
+                    states[successor] = Some(stack.clone());
+                    pending.push(successor);
+                }
+            }
+        }
+    }
+
+    let returns = returns?;
+    if calls_itself && returns != Type::Number {
+        return None;
+    }
+
+    Some(Analysis {
+        states,
+        returns,
+        calls_itself,
+        divides,
+    })
+}
+
+// Every stack slot is an f64 variable; booleans are stored as 0 or 1 and nil as 0, which is
+// sound because the analysis fixes the type of each slot at every instruction.
+struct Generator<'a> {
+    builder: FunctionBuilder<'a>,
+    pointer: types::Type,
+    poll: FuncRef,
+    enter: FuncRef,
+    leave: FuncRef,
+    this: FuncRef,
+    blocks: Vec<Option<Block>>,
+    exit: Option<Block>,
+    arguments: Option<StackSlot>,
+    context: Option<Ir>,
+}
+
+impl Generator<'_> {
+    fn generate(
+        mut self,
+        prototype: &Prototype,
+        instructions: &[Instruction],
+        analysis: &Analysis,
+    ) {
+        let depth = analysis
+            .states
+            .iter()
+            .flatten()
+            .map(|stack| stack.len() + 1)
+            .max()
+            .unwrap_or(1);
+        for slot in 0..depth {
+            self.builder.declare_var(variable(slot), types::F64);
+        }
+
+        let entry = self.builder.create_block();
+        self.builder.append_block_params_for_function_params(entry);
+        self.builder.switch_to_block(entry);
+        let context = self.builder.block_params(entry)[0];
+        let arguments = self.builder.block_params(entry)[1];
+        self.context = Some(context);
+
+        let zero = self.builder.ins().f64const(0.0);
+        self.builder.def_var(variable(0), zero);
+        for slot in 0..prototype.arity.required {
+            let argument = self.builder.ins().load(
+                types::F64,
+                MemFlags::trusted(),
+                arguments,
+                slot as i32 * 8,
+            );
+            self.builder.def_var(variable(slot + 1), argument);
+        }
+        if analysis.calls_itself {
+            self.arguments = Some(self.builder.create_sized_stack_slot(StackSlotData::new(
+                StackSlotKind::ExplicitSlot,
+                (prototype.arity.required.max(1) * 8) as u32,
+                3,
+            )));
+        }
+        self.poll(instructions[0].line);
+
+        let mut starts = vec![false; instructions.len() + 1];
+        for (index, instruction) in instructions.iter().enumerate() {
+            if analysis.states[index].is_none() {
+                continue;
+            }
+            match instruction.op {
+                OpCode::Jump | OpCode::Loop => starts[instruction.operand] = true,
+                OpCode::JumpIfFalse => {
+                    starts[instruction.operand] = true;
+                    starts[index + 1] = true;
+                }
+                _ => {}
+            }
+        }
+
+        let mut filled = false;
+        for (index, instruction) in instructions.iter().enumerate() {
+            let Some(stack) = &analysis.states[index] else {
+                continue;
+            };
+            if starts[index] {
+                let block = self.block(index);
+                if !filled {
+                    self.builder.ins().jump(block, &[]);
+                }
+                self.builder.switch_to_block(block);
+                filled = false;
+            }
+
+            let top = stack.len();
+            match instruction.op {
+                OpCode::Constant => {
+                    let Constant::Number(number) = prototype.chunk.constant(instruction.operand)
+                    else {
+                        unreachable!()
+                    };
+                    self.push(top, *number);
+                }
+                OpCode::Nil | OpCode::False | OpCode::GetGlobal => self.push(top, 0.0),
+                OpCode::True => self.push(top, 1.0),
+                OpCode::Pop => {}
+                OpCode::GetLocal => {
+                    let value = self.builder.use_var(variable(instruction.operand));
+                    self.builder.def_var(variable(top), value);
+                }
+                OpCode::SetLocal => {
+                    let value = self.builder.use_var(variable(top - 1));
+                    self.builder.def_var(variable(instruction.operand), value);
+                }
+                OpCode::Equal => {
+                    let value = match (stack[top - 2], stack[top - 1]) {
+                        (Type::Nil, Type::Nil) => self.builder.ins().f64const(1.0),
+                        (left, right) if left != right => self.builder.ins().f64const(0.0),
+                        _ => self.compare(top, FloatCC::Equal),
+                    };
+                    self.builder.def_var(variable(top - 2), value);
+                }
+                OpCode::Greater => self.comparison(top, FloatCC::GreaterThan),
+                OpCode::GreaterEqual => self.comparison(top, FloatCC::GreaterThanOrEqual),
+                OpCode::Less => self.comparison(top, FloatCC::LessThan),
+                OpCode::LessEqual => self.comparison(top, FloatCC::LessThanOrEqual),
+                OpCode::Add | OpCode::Subtract | OpCode::Multiply | OpCode::Divide => {
+                    let left = self.builder.use_var(variable(top - 2));
+                    let right = self.builder.use_var(variable(top - 1));
+                    let value = match instruction.op {
+                        OpCode::Add => self.builder.ins().fadd(left, right),
+                        OpCode::Subtract => self.builder.ins().fsub(left, right),
+                        OpCode::Multiply => self.builder.ins().fmul(left, right),
+                        _ => self.builder.ins().fdiv(left, right),
+                    };
+                    self.builder.def_var(variable(top - 2), value);
+                }
+                OpCode::Not => {
+                    let value = match stack[top - 1] {
+                        Type::Number => self.builder.ins().f64const(0.0),
+                        Type::Nil => self.builder.ins().f64const(1.0),
+                        _ => {
+                            let operand = self.builder.use_var(variable(top - 1));
+                            let zero = self.builder.ins().f64const(0.0);
+                            let flag = self.builder.ins().fcmp(FloatCC::Equal, operand, zero);
+                            self.flag(flag)
+                        }
+                    };
+                    self.builder.def_var(variable(top - 1), value);
+                }
+                OpCode::Negate => {
+                    let operand = self.builder.use_var(variable(top - 1));
+                    let value = self.builder.ins().fneg(operand);
+                    self.builder.def_var(variable(top - 1), value);
+                }
+                OpCode::Jump | OpCode::Loop => {
+                    if instruction.operand <= index {
+                        self.poll(instruction.line);
+                    }
+                    let block = self.block(instruction.operand);
+                    self.builder.ins().jump(block, &[]);
+                    filled = true;
+                }
+                OpCode::JumpIfFalse => match stack[top - 1] {
+                    Type::Number => {}
+                    Type::Nil => {
+                        let block = self.block(instruction.operand);
+                        self.builder.ins().jump(block, &[]);
+                        filled = true;
+                    }
+                    _ => {
+                        let condition = self.builder.use_var(variable(top - 1));
+                        let zero = self.builder.ins().f64const(0.0);
+                        let flag = self.builder.ins().fcmp(FloatCC::NotEqual, condition, zero);
+                        let next = self.block(index + 1);
+                        let target = self.block(instruction.operand);
+                        self.builder.ins().brif(flag, next, &[], target, &[]);
+                        filled = true;
+                    }
+                },
+                OpCode::Call => self.call(top, instruction),
+                OpCode::Return => {
+                    let value = self.builder.use_var(variable(top - 1));
+                    self.builder.ins().return_(&[value]);
+                    filled = true;
+                }
+                _ => unreachable!(),
+            }
+        }
+
+        if let Some(exit) = self.exit {
+            self.builder.switch_to_block(exit);
+            let zero = self.builder.ins().f64const(0.0);
+            self.builder.ins().return_(&[zero]);
+        }
+        self.builder.seal_all_blocks();
+        self.builder.finalize();
+    }
+
+    fn block(&mut self, index: usize) -> Block {
+        match self.blocks[index] {
+            Some(block) => block,
+            None => {
+                let block = self.builder.create_block();
+                self.blocks[index] = Some(block);
+                block
+            }
+        }
+    }
+
+    fn push(&mut self, top: usize, number: f64) {
+        let value = self.builder.ins().f64const(number);
+        self.builder.def_var(variable(top), value);
+    }
+
+    fn flag(&mut self, flag: Ir) -> Ir {
+        let one = self.builder.ins().f64const(1.0);
+        let zero = self.builder.ins().f64const(0.0);
+        self.builder.ins().select(flag, one, zero)
+    }
+
+    fn compare(&mut self, top: usize, condition: FloatCC) -> Ir {
+        let left = self.builder.use_var(variable(top - 2));
+        let right = self.builder.use_var(variable(top - 1));
+        let flag = self.builder.ins().fcmp(condition, left, right);
+        self.flag(flag)
+    }
+
+    fn comparison(&mut self, top: usize, condition: FloatCC) {
+        let value = self.compare(top, condition);
+        self.builder.def_var(variable(top - 2), value);
+    }
+
+    fn check(&mut self, failed: Ir) {
+        let exit = match self.exit {
+            Some(exit) => exit,
+            None => {
+                let exit = self.builder.create_block();
+                self.exit = Some(exit);
+                exit
+            }
+        };
+        let next = self.builder.create_block();
+        self.builder.ins().brif(failed, exit, &[], next, &[]);
+        self.builder.switch_to_block(next);
+    }
+
+    fn poll(&mut self, line: usize) {
+        let context = self.context.unwrap();
+        let line = self.builder.ins().iconst(types::I64, line as i64);
+        let call = self.builder.ins().call(self.poll, &[context, line]);
+        let failed = self.builder.inst_results(call)[0];
+        self.check(failed);
+    }
+
+    fn call(&mut self, top: usize, instruction: &Instruction) {
+        let context = self.context.unwrap();
+        let slot = self.arguments.unwrap();
+        let count = instruction.operand;
+
+        let line = self
+            .builder
+            .ins()
+            .iconst(types::I64, instruction.line as i64);
+        let call = self.builder.ins().call(self.enter, &[context, line]);
+        let failed = self.builder.inst_results(call)[0];
+        self.check(failed);
+
+        for argument in 0..count {
+            let value = self.builder.use_var(variable(top - count + argument));
+            self.builder
+                .ins()
+                .stack_store(value, slot, argument as i32 * 8);
+        }
+        let arguments = self.builder.ins().stack_addr(self.pointer, slot, 0);
+        let call = self.builder.ins().call(self.this, &[context, arguments]);
+        let result = self.builder.inst_results(call)[0];
+        self.builder.ins().call(self.leave, &[context]);
+
+        let failed = self
+            .builder
+            .ins()
+            .load(types::I8, MemFlags::trusted(), context, 0);
+        self.check(failed);
+        self.builder.def_var(variable(top - count - 1), result);
+    }
+}
+
+fn variable(slot: usize) -> Variable {
+    Variable::from_u32(slot as u32)
+}
@@ -0,0 +1,345 @@
+use core::fmt;
+
+use crate::prelude::*;
+
+const MAX_DEPTH: usize = 512;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Boolean(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(entries) => entries
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            Json::Number(number) => Some(*number),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Boolean(boolean) => Some(*boolean),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(elements) => Some(elements),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Json::Null => write!(formatter, "null"),
+            Json::Boolean(boolean) => write!(formatter, "{}", boolean),
+            Json::Number(number) if number.is_finite() => write!(formatter, "{}", number),
+            Json::Number(_) => write!(formatter, "null"),
+            Json::String(string) => write_string(formatter, string),
+            Json::Array(elements) => {
+                write!(formatter, "[")?;
+                for (index, element) in elements.iter().enumerate() {
+                    if index > 0 {
+                        write!(formatter, ",")?;
+                    }
+                    write!(formatter, "{}", element)?;
+                }
+                write!(formatter, "]")
+            }
+            Json::Object(entries) => {
+                write!(formatter, "{{")?;
+                for (index, (key, value)) in entries.iter().enumerate() {
+                    if index > 0 {
+                        write!(formatter, ",")?;
+                    }
+                    write_string(formatter, key)?;
+                    write!(formatter, ":{}", value)?;
+                }
+                write!(formatter, "}}")
+            }
+        }
+    }
+}
+
+fn write_string(formatter: &mut fmt::Formatter, string: &str) -> fmt::Result {
+    write!(formatter, "\"")?;
+    for character in string.chars() {
+        match character {
+            '"' => write!(formatter, "\\\"")?,
+            '\\' => write!(formatter, "\\\\")?,
+            '\n' => write!(formatter, "\\n")?,
+            '\r' => write!(formatter, "\\r")?,
+            '\t' => write!(formatter, "\\t")?,
+            '\u{8}' => write!(formatter, "\\b")?,
+            '\u{c}' => write!(formatter, "\\f")?,
+            character if (character as u32) < 0x20 => {
+                write!(formatter, "\\u{:04x}", character as u32)?
+            }
+            character => write!(formatter, "{}", character)?,
+        }
+    }
+    write!(formatter, "\"")
+}
+
+pub fn parse(text: &str) -> Result<Json, String> {
+    let mut parser = JsonParser {
+        source: text.chars().collect(),
+        current_position: 0,
+    };
+
+    let value = parser.parse_value(0)?;
+    parser.skip_whitespace();
+
+    if parser.finished() {
+        Ok(value)
+    } else {
+        Err(parser.build_error("Unexpected trailing characters"))
+    }
+}
+
+struct JsonParser {
+    source: Vec<char>,
+    current_position: usize,
+}
+
+impl JsonParser {
+    fn parse_value(&mut self, depth: usize) -> Result<Json, String> {
+        if depth > MAX_DEPTH {
+            return Err(self.build_error("Nested too deeply"));
+        }
+
+        self.skip_whitespace();
+
+        match self.get_current_char() {
+            Some('n') => self.parse_literal("null", Json::Null),
+            Some('t') => self.parse_literal("true", Json::Boolean(true)),
+            Some('f') => self.parse_literal("false", Json::Boolean(false)),
+            Some('"') => self.parse_string().map(Json::String),
+            Some('[') => self.parse_array(depth),
+            Some('{') => self.parse_object(depth),
+            Some('-' | '0'..='9') => self.parse_number(),
+            Some(_) => Err(self.build_error("Unexpected character")),
+            None => Err(self.build_error("Unexpected end of input")),
+        }
+    }
+
+    fn parse_literal(&mut self, literal: &str, value: Json) -> Result<Json, String> {
+        for expected in literal.chars() {
+            if self.get_current_char() != Some(expected) {
+                return Err(self.build_error("Invalid literal"));
+            }
+            self.advance();
+        }
+
+        Ok(value)
+    }
+
+    fn parse_number(&mut self) -> Result<Json, String> {
+        let start = self.current_position;
+        while let Some('-' | '+' | '.' | 'e' | 'E' | '0'..='9') = self.get_current_char() {
+            self.advance();
+        }
+
+        let text: String = self.source[start..self.current_position].iter().collect();
+        let valid_start = text.strip_prefix('-').unwrap_or(&text);
+        if !valid_start.starts_with(|character: char| character.is_ascii_digit()) {
+            return Err(self.build_error("Invalid number"));
+        }
+
+        text.parse::<f64>()
+            .map(Json::Number)
+            .map_err(|_| self.build_error("Invalid number"))
+    }
+
+    fn parse_string(&mut self) -> Result<String, String> {
+        self.advance();
+
+        let mut string = String::new();
+        loop {
+            match self.get_current_char() {
+                None => return Err(self.build_error("Unterminated string")),
+                Some('"') => {
+                    self.advance();
+                    return Ok(string);
+                }
+                Some('\\') => {
+                    self.advance();
+                    let escaped = match self.get_current_char() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => self.parse_unicode_escape()?,
+                        _ => return Err(self.build_error("Invalid escape sequence")),
+                    };
+                    self.advance();
+                    string.push(escaped);
+                }
+                Some(character) if (character as u32) < 0x20 => {
+                    return Err(self.build_error("Control character in string"))
+                }
+                Some(character) => {
+                    self.advance();
+                    string.push(character);
+                }
+            }
+        }
+    }
+
+    fn parse_unicode_escape(&mut self) -> Result<char, String> {
+        let high = self.parse_hex_quad()?;
+
+        if (0xD800..0xDC00).contains(&high) {
+            if self.get_next_char() != Some('\\') {
+                return Err(self.build_error("Unpaired surrogate"));
+            }
+            self.advance();
+            if self.get_next_char() != Some('u') {
+                return Err(self.build_error("Unpaired surrogate"));
+            }
+            self.advance();
+
+            let low = self.parse_hex_quad()?;
+            if !(0xDC00..0xE000).contains(&low) {
+                return Err(self.build_error("Unpaired surrogate"));
+            }
+
+            let code = 0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00);
+            return char::from_u32(code).ok_or_else(|| self.build_error("Invalid code point"));
+        }
+
+        char::from_u32(high).ok_or_else(|| self.build_error("Invalid code point"))
+    }
+
+    fn parse_hex_quad(&mut self) -> Result<u32, String> {
+        let mut code = 0;
+        for _ in 0..4 {
+            self.advance();
+            let digit = self
+                .get_current_char()
+                .and_then(|character| character.to_digit(16))
+                .ok_or_else(|| self.build_error("Invalid unicode escape"))?;
+            code = code * 16 + digit;
+        }
+
+        Ok(code)
+    }
+
+    fn parse_array(&mut self, depth: usize) -> Result<Json, String> {
+        self.advance();
+
+        let mut elements: Vec<Json> = Vec::new();
+        self.skip_whitespace();
+        if self.get_current_char() == Some(']') {
+            self.advance();
+            return Ok(Json::Array(elements));
+        }
+
+        loop {
+            elements.push(self.parse_value(depth + 1)?);
+            self.skip_whitespace();
+
+            match self.get_current_char() {
+                Some(',') => self.advance(),
+                Some(']') => {
+                    self.advance();
+                    return Ok(Json::Array(elements));
+                }
+                _ => return Err(self.build_error("Expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn parse_object(&mut self, depth: usize) -> Result<Json, String> {
+        self.advance();
+
+        let mut entries: Vec<(String, Json)> = Vec::new();
+        self.skip_whitespace();
+        if self.get_current_char() == Some('}') {
+            self.advance();
+            return Ok(Json::Object(entries));
+        }
+
+        loop {
+            self.skip_whitespace();
+            if self.get_current_char() != Some('"') {
+                return Err(self.build_error("Expected string key"));
+            }
+
+            let key = self.parse_string()?;
+            self.skip_whitespace();
+            if self.get_current_char() != Some(':') {
+                return Err(self.build_error("Expected ':'"));
+            }
+            self.advance();
+
+            entries.push((key, self.parse_value(depth + 1)?));
+            self.skip_whitespace();
+
+            match self.get_current_char() {
+                Some(',') => self.advance(),
+                Some('}') => {
+                    self.advance();
+                    return Ok(Json::Object(entries));
+                }
+                _ => return Err(self.build_error("Expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(' ' | '\n' | '\r' | '\t') = self.get_current_char() {
+            self.advance();
+        }
+    }
+
+    fn advance(&mut self) {
+        self.current_position += 1;
+    }
+
+    fn finished(&self) -> bool {
+        self.current_position >= self.source.len()
+    }
+
+    fn get_current_char(&self) -> Option<char> {
+        self.source.get(self.current_position).copied()
+    }
+
+    fn get_next_char(&self) -> Option<char> {
+        self.source.get(self.current_position + 1).copied()
+    }
+
+    fn build_error(&self, message: &str) -> String {
+        format!("{} at position {}", message, self.current_position)
+    }
+}
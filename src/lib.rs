@@ -0,0 +1,76 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+pub mod analysis;
+mod arithmetic;
+pub mod arity;
+#[cfg(feature = "std")]
+pub mod bench;
+pub mod bytecode;
+mod cache;
+pub mod cancel;
+pub mod capability;
+#[cfg(feature = "std")]
+pub mod check;
+pub mod collections;
+pub mod compiler;
+pub mod completion;
+pub mod convert;
+pub mod coverage;
+#[cfg(feature = "std")]
+pub mod dap;
+pub mod debugger;
+pub mod dialect;
+pub mod disassembler;
+pub mod environment;
+pub mod error;
+mod exception;
+pub mod expression;
+pub mod foreign;
+pub mod formatter;
+pub mod gc;
+pub mod interpreter;
+pub mod io;
+#[cfg(feature = "jit")]
+pub mod jit;
+pub mod json;
+pub mod lint;
+pub mod lox;
+#[cfg(feature = "std")]
+pub mod lsp;
+pub mod native;
+pub mod optimizer;
+pub mod parser;
+mod prelude;
+#[cfg(feature = "std")]
+pub mod profiler;
+pub mod random;
+#[cfg(feature = "regvm")]
+pub mod regcompiler;
+#[cfg(feature = "regvm")]
+pub mod regvm;
+mod resolver;
+#[cfg(feature = "std")]
+mod rpc;
+pub mod scanner;
+pub mod serialize;
+mod settings;
+pub mod snapshot;
+pub mod statement;
+#[cfg(feature = "std")]
+pub mod suite;
+pub mod symbol;
+#[cfg(feature = "std")]
+pub mod sync;
+pub mod syntax;
+pub mod token;
+mod trace;
+pub mod transpiler;
+pub mod value;
+mod verifier;
+pub mod vm;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use crate::lox::{Engine, Lox};
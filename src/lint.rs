@@ -0,0 +1,864 @@
+#[cfg(feature = "std")]
+use std::path::{Path, PathBuf};
+
+use alloc::collections::{BTreeMap, BTreeSet};
+
+use crate::analysis::{self, Analysis, Fix, TextEdit};
+use crate::error::Error;
+use crate::expression::{Expr, ExprId, Expression, ExpressionKind, Literal};
+use crate::prelude::*;
+use crate::resolver::{SymbolKind, Symbols};
+use crate::settings::{self, Setting, Value};
+use crate::statement::{FunctionDeclaration, Statement};
+use crate::token::{Keyword, Kind, Position, Token};
+
+// Looked for in the directory of the file being linted and then in each
+// directory above it.
+pub const CONFIG_FILE: &str = ".loxlint.toml";
+
+// Fixing one warning can uncover another, such as a variable only an unused
+// one read, so fixes are applied in passes.
+const MAX_FIX_PASSES: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Rule {
+    UnusedVariable,
+    Shadowing,
+    EmptyBlock,
+    ConstantCondition,
+    NilComparison,
+    Naming,
+    Precedence,
+}
+
+impl Rule {
+    pub const ALL: [Rule; 7] = [
+        Rule::UnusedVariable,
+        Rule::Shadowing,
+        Rule::EmptyBlock,
+        Rule::ConstantCondition,
+        Rule::NilComparison,
+        Rule::Naming,
+        Rule::Precedence,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Rule::UnusedVariable => "unused-variable",
+            Rule::Shadowing => "shadowing",
+            Rule::EmptyBlock => "empty-block",
+            Rule::ConstantCondition => "constant-condition",
+            Rule::NilComparison => "nil-comparison",
+            Rule::Naming => "naming",
+            Rule::Precedence => "confusing-precedence",
+        }
+    }
+
+    pub fn code(self) -> &'static str {
+        match self {
+            Rule::UnusedVariable => "L001",
+            Rule::Shadowing => "L002",
+            Rule::EmptyBlock => "L003",
+            Rule::ConstantCondition => "L004",
+            Rule::NilComparison => "L005",
+            Rule::Naming => "L006",
+            Rule::Precedence => "L007",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Rule> {
+        Rule::ALL.into_iter().find(|rule| rule.name() == name)
+    }
+}
+
+// How functions, methods, variables and parameters are named; classes are
+// always PascalCase.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Case {
+    Camel,
+    Snake,
+}
+
+impl Case {
+    pub fn from_name(name: &str) -> Option<Case> {
+        match name {
+            "camelCase" => Some(Case::Camel),
+            "snake_case" => Some(Case::Snake),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Case::Camel => "camelCase",
+            Case::Snake => "snake_case",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    pub rules: BTreeSet<Rule>,
+    pub case: Case,
+}
+
+// Every rule but nil-comparison, which is a matter of taste.
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            rules: Rule::ALL
+                .into_iter()
+                .filter(|&rule| rule != Rule::NilComparison)
+                .collect(),
+            case: Case::Camel,
+        }
+    }
+}
+
+impl Config {
+    // Reads a .loxlint.toml over the defaults: `rule-name = false` turns a
+    // rule off, `= true` on, and `case` picks the naming convention.
+    pub fn parse(text: &str) -> Result<Config, String> {
+        Config::from_settings(settings::parse(text)?)
+    }
+
+    // The nearest .loxlint.toml at or above `directory`.
+    #[cfg(feature = "std")]
+    pub fn find(directory: &Path) -> Option<PathBuf> {
+        settings::find(directory, CONFIG_FILE)
+    }
+
+    #[cfg(feature = "std")]
+    pub fn load(path: &Path) -> Result<Config, String> {
+        Config::from_settings(settings::read(path)?)
+            .map_err(|message| format!("{}: {}", path.display(), message))
+    }
+
+    fn from_settings(settings: Vec<Setting>) -> Result<Config, String> {
+        let mut config = Config::default();
+        for setting in settings {
+            match (Rule::from_name(&setting.key), &setting.value) {
+                (Some(rule), Value::Boolean(true)) => {
+                    config.rules.insert(rule);
+                }
+                (Some(rule), Value::Boolean(false)) => {
+                    config.rules.remove(&rule);
+                }
+                (Some(_), _) => return Err(setting.invalid()),
+                (None, Value::String(name)) if setting.key == "case" => {
+                    config.case = Case::from_name(name).ok_or_else(|| setting.invalid())?
+                }
+                (None, _) if setting.key == "case" => return Err(setting.invalid()),
+                (None, _) => return Err(setting.unknown()),
+            }
+        }
+        Ok(config)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Warning {
+    pub rule: Rule,
+    pub message: String,
+    pub position: Position,
+    pub fix: Option<Fix>,
+    // The expression the warning is about, when it is about one.
+    pub expression: Option<ExprId>,
+}
+
+// Lints a program that compiles; otherwise its compile errors come back.
+pub fn lint(source: &str, config: &Config) -> Result<Vec<Warning>, Vec<Error>> {
+    let analysis = Analysis::new(source);
+    if analysis.diagnostics().is_empty() {
+        Ok(analysis.lint(config))
+    } else {
+        Err(analysis.diagnostics().to_vec())
+    }
+}
+
+// The source with every fix applied: those for missing semicolons while it
+// doesn't compile, then those for the enabled rules' warnings. Compile errors
+// without a fix come back.
+pub fn fix(source: &str, config: &Config) -> Result<String, Vec<Error>> {
+    let mut source = source.to_string();
+    for _ in 0..MAX_FIX_PASSES {
+        let analysis = Analysis::new(&source);
+        let diagnostics = analysis.diagnostics();
+        let fixes: Vec<Fix> = if diagnostics.is_empty() {
+            analysis
+                .lint(config)
+                .into_iter()
+                .filter_map(|warning| warning.fix)
+                .collect()
+        } else {
+            (0..diagnostics.len())
+                .filter_map(|index| analysis.fix_for(index).cloned())
+                .collect()
+        };
+
+        if fixes.is_empty() {
+            return if diagnostics.is_empty() {
+                Ok(source)
+            } else {
+                Err(diagnostics.to_vec())
+            };
+        }
+        source = apply_fixes(&source, &fixes);
+    }
+    lint(&source, config).map(|_| source)
+}
+
+// A fix with an edit overlapping one already taken waits for the next pass.
+fn apply_fixes(source: &str, fixes: &[Fix]) -> String {
+    let overlap = |a: &TextEdit, b: &TextEdit| {
+        (a.position.start < b.position.current && b.position.start < a.position.current)
+            || a.position.start == b.position.start
+    };
+
+    let mut edits: Vec<TextEdit> = Vec::new();
+    for fix in fixes {
+        let free = fix
+            .edits
+            .iter()
+            .all(|edit| !edits.iter().any(|taken| overlap(edit, taken)));
+        if free {
+            edits.extend(fix.edits.iter().cloned());
+        }
+    }
+    edits.sort_by_key(|edit| edit.position.start);
+    analysis::apply(source, &edits)
+}
+
+// `tokens` are those the statements were parsed from, without comments.
+pub(crate) fn check(
+    statements: &[Rc<Statement>],
+    symbols: &Symbols,
+    tokens: &[Token],
+    source: &str,
+    config: &Config,
+) -> Vec<Warning> {
+    let mut linter = Linter {
+        config,
+        tokens,
+        source: source.chars().collect(),
+        warnings: Vec::new(),
+        unused: BTreeMap::new(),
+        globals: BTreeSet::new(),
+        scopes: Vec::new(),
+    };
+    linter.declarations(symbols);
+    for statement in statements {
+        match &**statement {
+            Statement::Variable { name, .. } | Statement::Class { name, .. } => {
+                linter.globals.insert(name.lexeme());
+            }
+            Statement::Destructure { names, .. } => {
+                linter.globals.extend(names.iter().map(Token::lexeme));
+            }
+            Statement::Function(declaration) => {
+                linter.globals.insert(declaration.name.lexeme());
+            }
+            _ => {}
+        }
+    }
+    linter.statements(statements);
+
+    linter
+        .warnings
+        .sort_by_key(|warning| warning.position.start);
+    linter.warnings
+}
+
+// The unused-variable and naming rules read the resolver's symbols; the rest
+// walk the syntax tree, keeping the names declared in each enclosing scope
+// the way the resolver does. Fixes find the spans they edit by counting
+// tokens from a name or an operator.
+struct Linter<'a> {
+    config: &'a Config,
+    tokens: &'a [Token],
+    source: Vec<char>,
+    warnings: Vec<Warning>,
+    // The warning for each unused variable, by where its name starts.
+    unused: BTreeMap<usize, usize>,
+    // Top-level names, which every function can see wherever it is
+    // declared.
+    globals: BTreeSet<String>,
+    scopes: Vec<BTreeSet<String>>,
+}
+
+impl Linter<'_> {
+    fn warn(&mut self, rule: Rule, position: Position, message: String) -> Option<&mut Warning> {
+        if !self.config.rules.contains(&rule) {
+            return None;
+        }
+        self.warnings.push(Warning {
+            rule,
+            message,
+            position,
+            fix: None,
+            expression: None,
+        });
+        self.warnings.last_mut()
+    }
+
+    fn warn_about(
+        &mut self,
+        rule: Rule,
+        expression: &Expression,
+        position: Position,
+        message: String,
+    ) -> Option<&mut Warning> {
+        let warning = self.warn(rule, position, message)?;
+        warning.expression = Some(expression.id);
+        Some(warning)
+    }
+
+    fn declarations(&mut self, symbols: &Symbols) {
+        for (index, symbol) in symbols.declarations.iter().enumerate() {
+            let noun = match symbol.kind {
+                SymbolKind::Variable => "Variable",
+                SymbolKind::Parameter => "Parameter",
+                SymbolKind::Function => "Function",
+                SymbolKind::Method => "Method",
+                SymbolKind::Class => "Class",
+            };
+            // Top-level functions and classes are the program's interface
+            // rather than its working state. Every use of a global resolves
+            // to its last declaration, so earlier ones can't be judged.
+            let local = symbols.scopes[index] != 0;
+            let redeclared = !local && symbols.globals.get(&symbol.name) != Some(&index);
+            let unused = match symbol.kind {
+                SymbolKind::Variable | SymbolKind::Parameter => !redeclared,
+                SymbolKind::Function | SymbolKind::Class => local,
+                SymbolKind::Method => false,
+            };
+            if unused && symbols.uses[index].is_empty() && !symbol.name.starts_with('_') {
+                let message = format!("{} '{}' is never used.", noun, symbol.name);
+                let warned = self
+                    .warn(Rule::UnusedVariable, symbol.position, message)
+                    .is_some();
+                if warned && symbol.kind == SymbolKind::Variable {
+                    self.unused
+                        .insert(symbol.position.start, self.warnings.len() - 1);
+                }
+            }
+
+            let name = symbol.name.trim_start_matches('_');
+            let (follows, case) = match symbol.kind {
+                SymbolKind::Class => (is_pascal_case(name), "PascalCase"),
+                SymbolKind::Variable if is_constant_case(name) => (true, ""),
+                _ => match self.config.case {
+                    Case::Camel => (is_camel_case(name), Case::Camel.name()),
+                    Case::Snake => (is_snake_case(name), Case::Snake.name()),
+                },
+            };
+            if !name.is_empty() && !follows {
+                let message = format!("{} '{}' should be {}.", noun, symbol.name, case);
+                self.warn(Rule::Naming, symbol.position, message);
+            }
+        }
+    }
+
+    fn declare(&mut self, name: &Token) {
+        let Some((scope, enclosing)) = self.scopes.split_last_mut() else {
+            return;
+        };
+        let lexeme = name.lexeme();
+        let shadows =
+            enclosing.iter().any(|scope| scope.contains(&lexeme)) || self.globals.contains(&lexeme);
+        scope.insert(lexeme.clone());
+
+        if shadows {
+            let message = format!("'{}' shadows a declaration in an enclosing scope.", lexeme);
+            self.warn(Rule::Shadowing, name.position, message);
+        }
+    }
+
+    fn statements(&mut self, statements: &[Rc<Statement>]) {
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Expression(expression)
+            | Statement::Print {
+                value: expression, ..
+            } => self.expression(expression),
+            Statement::Variable { name, initializer } => {
+                if let Some(initializer) = initializer {
+                    self.expression(initializer);
+                }
+                self.declare(name);
+                if let Some(&warning) = self.unused.get(&name.position.start) {
+                    self.warnings[warning].fix = self.remove_variable(name, initializer.as_ref());
+                }
+            }
+            Statement::Destructure {
+                names, initializer, ..
+            } => {
+                self.expression(initializer);
+                for name in names {
+                    self.declare(name);
+                }
+            }
+            Statement::Block(statements) => {
+                self.scopes.push(BTreeSet::new());
+                self.statements(statements);
+                self.scopes.pop();
+            }
+            Statement::If {
+                keyword,
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.condition(keyword, condition);
+                if is_empty_block(then_branch) {
+                    self.warn(
+                        Rule::EmptyBlock,
+                        keyword.position,
+                        "Empty if body.".to_string(),
+                    );
+                }
+                self.statement(then_branch);
+
+                if let Some(else_branch) = else_branch {
+                    if is_empty_block(else_branch) {
+                        let message = "Empty else branch.".to_string();
+                        self.warn(Rule::EmptyBlock, keyword.position, message);
+                    }
+                    self.statement(else_branch);
+                }
+            }
+            Statement::While {
+                keyword,
+                condition,
+                body,
+            } => {
+                // `for (;;)` and `while (true)` loop forever on purpose.
+                if !matches!(
+                    &condition.kind,
+                    ExpressionKind::Literal(Literal::Boolean(true))
+                ) {
+                    self.condition(keyword, condition);
+                }
+                // A for loop's increment is appended to its body.
+                let empty = match &**body {
+                    Statement::Block(statements) if keyword.kind == Kind::Keyword(Keyword::For) => {
+                        statements.is_empty()
+                            || (statements.len() == 2 && is_empty_block(&statements[0]))
+                    }
+                    body => is_empty_block(body),
+                };
+                if empty {
+                    self.warn(
+                        Rule::EmptyBlock,
+                        keyword.position,
+                        "Empty loop body.".to_string(),
+                    );
+                }
+                self.statement(body);
+            }
+            Statement::ForIn {
+                name,
+                iterable,
+                body,
+            } => {
+                self.expression(iterable);
+                if is_empty_block(body) {
+                    self.warn(
+                        Rule::EmptyBlock,
+                        name.position,
+                        "Empty loop body.".to_string(),
+                    );
+                }
+                self.scopes.push(BTreeSet::new());
+                self.declare(name);
+                self.statement(body);
+                self.scopes.pop();
+            }
+            Statement::Function(declaration) => {
+                self.declare(&declaration.name);
+                self.function(declaration);
+            }
+            Statement::Return { value, .. } | Statement::Yield { value, .. } => {
+                if let Some(value) = value {
+                    self.expression(value);
+                }
+            }
+            Statement::Try {
+                body,
+                name,
+                handler,
+                ..
+            } => {
+                self.scopes.push(BTreeSet::new());
+                self.statements(body);
+                self.scopes.pop();
+
+                self.scopes.push(BTreeSet::new());
+                self.declare(name);
+                self.statements(handler);
+                self.scopes.pop();
+            }
+            Statement::Throw { value, .. } => self.expression(value),
+            Statement::Defer { body, .. } => {
+                self.scopes.push(BTreeSet::new());
+                self.statements(body);
+                self.scopes.pop();
+            }
+            Statement::Class {
+                name,
+                superclass,
+                methods,
+            } => {
+                self.declare(name);
+                if let Some(superclass) = superclass {
+                    self.expression(superclass);
+                }
+                for method in methods {
+                    self.function(method);
+                }
+            }
+        }
+    }
+
+    fn function(&mut self, declaration: &FunctionDeclaration) {
+        self.scopes.push(BTreeSet::new());
+        let required = declaration.arity().required;
+        for (index, parameter) in declaration.parameters.iter().enumerate() {
+            if let Some(default) = index.checked_sub(required) {
+                self.expression(&declaration.defaults[default]);
+            }
+            self.declare(parameter);
+        }
+        if let Some(rest) = &declaration.rest {
+            self.declare(rest);
+        }
+        self.statements(&declaration.body);
+        self.scopes.pop();
+    }
+
+    fn condition(&mut self, keyword: &Token, condition: &Expr) {
+        if is_constant(condition) {
+            let message = "Condition is constant.".to_string();
+            self.warn_about(
+                Rule::ConstantCondition,
+                condition,
+                keyword.position,
+                message,
+            );
+        }
+        self.expression(condition);
+    }
+
+    fn expression(&mut self, expression: &Expr) {
+        let (operand, links) = expression.chain();
+        self.node(&operand);
+        for link in &links {
+            self.node(link);
+        }
+    }
+
+    // Checks one node; the operand a link of a chain continues from has been
+    // checked already.
+    fn node(&mut self, expression: &Expr) {
+        match &expression.kind {
+            ExpressionKind::Literal(_)
+            | ExpressionKind::Variable(_)
+            | ExpressionKind::This(_)
+            | ExpressionKind::Super { .. } => {}
+            ExpressionKind::Grouping(inner) => self.expression(&expression.get(*inner)),
+            ExpressionKind::Unary { right, .. } => self.expression(&expression.get(*right)),
+            ExpressionKind::Binary {
+                left,
+                operator,
+                right,
+            } => {
+                let (left, right) = (expression.get(*left), expression.get(*right));
+                let comparison = matches!(
+                    operator.kind,
+                    Kind::EqualEqual
+                        | Kind::ExclamationEqual
+                        | Kind::Greater
+                        | Kind::GreaterEqual
+                        | Kind::Less
+                        | Kind::LessEqual
+                );
+                if let ExpressionKind::Unary { operator: not, .. } = &left.kind {
+                    if comparison && not.kind == Kind::Exclamation {
+                        let message = "'!' negates only the left operand, not the comparison.";
+                        let fix = self.parenthesize(&left, operator, true);
+                        if let Some(warning) = self.warn_about(
+                            Rule::Precedence,
+                            expression,
+                            not.position,
+                            message.to_string(),
+                        ) {
+                            warning.fix = fix;
+                        }
+                    }
+                }
+
+                let nil = |expression: &Expression| {
+                    matches!(&expression.kind, ExpressionKind::Literal(Literal::Nil))
+                };
+                if nil(&left) || nil(&right) {
+                    let message = match operator.kind {
+                        Kind::EqualEqual => {
+                            Some("'== nil' can be written with '!' if the value is never false.")
+                        }
+                        Kind::ExclamationEqual => {
+                            Some("'!= nil' can be left out if the value is never false.")
+                        }
+                        _ => None,
+                    };
+                    if let Some(message) = message {
+                        self.warn_about(
+                            Rule::NilComparison,
+                            expression,
+                            operator.position,
+                            message.to_string(),
+                        );
+                    }
+                }
+                self.expression(&right);
+            }
+            ExpressionKind::Logical {
+                left,
+                operator,
+                right,
+            } => {
+                if operator.kind == Kind::Keyword(Keyword::Or) {
+                    for (operand, is_left) in [(*left, true), (*right, false)] {
+                        let operand = expression.get(operand);
+                        let ExpressionKind::Logical { operator: and, .. } = &operand.kind else {
+                            continue;
+                        };
+                        if and.kind != Kind::Keyword(Keyword::And) {
+                            continue;
+                        }
+                        let message = "'and' binds more tightly than 'or'.".to_string();
+                        let fix = self.parenthesize(&operand, operator, is_left);
+                        if let Some(warning) =
+                            self.warn_about(Rule::Precedence, &operand, and.position, message)
+                        {
+                            warning.fix = fix;
+                        }
+                    }
+                }
+                self.expression(&expression.get(*right));
+            }
+            ExpressionKind::Assign { value, .. } => self.expression(&expression.get(*value)),
+            ExpressionKind::Call { arguments, .. } => {
+                for argument in arguments {
+                    self.expression(&expression.get(*argument));
+                }
+            }
+            ExpressionKind::Get { .. } => {}
+            ExpressionKind::Set { object, value, .. } => {
+                self.expression(&expression.get(*object));
+                self.expression(&expression.get(*value));
+            }
+            ExpressionKind::List { elements, .. } => {
+                for element in elements {
+                    self.expression(&expression.get(*element));
+                }
+            }
+            ExpressionKind::Map { entries, .. } => {
+                for (key, value) in entries {
+                    self.expression(&expression.get(*key));
+                    self.expression(&expression.get(*value));
+                }
+            }
+            ExpressionKind::Index { index, .. } => self.expression(&expression.get(*index)),
+            ExpressionKind::SetIndex {
+                object,
+                index,
+                value,
+                ..
+            } => {
+                self.expression(&expression.get(*object));
+                self.expression(&expression.get(*index));
+                self.expression(&expression.get(*value));
+            }
+        }
+    }
+
+    fn index_of(&self, token: &Token) -> Option<usize> {
+        self.tokens
+            .binary_search_by_key(&token.position.start, |token| token.position.start)
+            .ok()
+    }
+
+    // Deletes the declaration of an unused variable, or only its `var name =`
+    // when the initializer has side effects. A declaration alone on its line
+    // takes the line with it; one in a for loop leaves the clause's ';'.
+    fn remove_variable(&self, name: &Token, initializer: Option<&Expr>) -> Option<Fix> {
+        let index = self.index_of(name)?;
+        let keyword = &self.tokens[index.checked_sub(1)?];
+        let last = index + initializer.map_or(0, |initializer| 1 + initializer.token_count());
+        let semicolon = self.tokens.get(last + 1)?;
+        if keyword.kind != Kind::Keyword(Keyword::Var) || semicolon.kind != Kind::Semicolon {
+            return None;
+        }
+
+        let start = keyword.position.start;
+        if initializer.is_some_and(has_side_effects) {
+            return Some(Fix {
+                message: "Keep only the initializer, which has side effects.".to_string(),
+                edits: vec![edit(
+                    start,
+                    self.tokens[index + 2].position.start,
+                    keyword.line(),
+                    "",
+                )],
+            });
+        }
+
+        let clause = index >= 3
+            && self.tokens[index - 2].kind == Kind::OpenParenthesis
+            && self.tokens[index - 3].kind == Kind::Keyword(Keyword::For);
+        let (start, end) = if clause {
+            (start, self.tokens[last].position.current)
+        } else {
+            self.line_span(start, semicolon.position.current)
+        };
+        Some(Fix {
+            message: format!("Remove the unused variable '{}'.", name.lexeme()),
+            edits: vec![edit(start, end, keyword.line(), "")],
+        })
+    }
+
+    // Widens a deletion to its whole line when nothing else is on it, and
+    // otherwise to the blanks after it.
+    fn line_span(&self, start: usize, end: usize) -> (usize, usize) {
+        let blank = |character: &char| matches!(character, ' ' | '\t' | '\r');
+        let line_start = self.source[..start]
+            .iter()
+            .rposition(|&character| character == '\n')
+            .map_or(0, |newline| newline + 1);
+        let after = end + self.source[end..].iter().take_while(|c| blank(c)).count();
+
+        let alone = self.source[line_start..start].iter().all(blank)
+            && matches!(self.source.get(after), None | Some('\n'));
+        if alone {
+            (line_start, (after + 1).min(self.source.len()))
+        } else {
+            (start, after)
+        }
+    }
+
+    // Wraps `operand`, the left or right operand of `operator`, in
+    // parentheses.
+    fn parenthesize(&self, operand: &Expr, operator: &Token, left: bool) -> Option<Fix> {
+        let index = self.index_of(operator)?;
+        let count = operand.token_count();
+        let (first, last) = if left {
+            (index.checked_sub(count)?, index - 1)
+        } else {
+            (index + 1, index + count)
+        };
+        let first = &self.tokens[first].position;
+        let last = self.tokens.get(last)?.position;
+        Some(Fix {
+            message: "Add parentheses to make the precedence explicit.".to_string(),
+            edits: vec![
+                edit(first.start, first.start, first.line, "("),
+                edit(last.current, last.current, last.line, ")"),
+            ],
+        })
+    }
+}
+
+fn edit(start: usize, current: usize, line: usize, text: &str) -> TextEdit {
+    TextEdit {
+        position: Position {
+            start,
+            current,
+            line,
+        },
+        text: text.to_string(),
+    }
+}
+
+// Calls and assignments; everything else only reads.
+fn has_side_effects(expression: &Expr) -> bool {
+    let child = |id: &ExprId| has_side_effects(&expression.get(*id));
+    match &expression.kind {
+        ExpressionKind::Call { .. }
+        | ExpressionKind::Assign { .. }
+        | ExpressionKind::Set { .. }
+        | ExpressionKind::SetIndex { .. } => true,
+        ExpressionKind::Literal(_)
+        | ExpressionKind::Variable(_)
+        | ExpressionKind::This(_)
+        | ExpressionKind::Super { .. } => false,
+        ExpressionKind::Grouping(inner)
+        | ExpressionKind::Unary { right: inner, .. }
+        | ExpressionKind::Get { object: inner, .. } => child(inner),
+        ExpressionKind::Binary { left, right, .. }
+        | ExpressionKind::Logical { left, right, .. }
+        | ExpressionKind::Index {
+            object: left,
+            index: right,
+            ..
+        } => child(left) || child(right),
+        ExpressionKind::List { elements, .. } => elements.iter().any(child),
+        ExpressionKind::Map { entries, .. } => entries
+            .iter()
+            .any(|(key, value)| child(key) || child(value)),
+    }
+}
+
+fn is_empty_block(statement: &Statement) -> bool {
+    matches!(statement, Statement::Block(statements) if statements.is_empty())
+}
+
+// Literals and operators on them; list and map literals are always truthy
+// whatever they hold.
+fn is_constant(expression: &Expr) -> bool {
+    let child = |id: &ExprId| is_constant(&expression.get(*id));
+    match &expression.kind {
+        ExpressionKind::Literal(_) | ExpressionKind::List { .. } | ExpressionKind::Map { .. } => {
+            true
+        }
+        ExpressionKind::Grouping(inner) | ExpressionKind::Unary { right: inner, .. } => {
+            child(inner)
+        }
+        ExpressionKind::Binary { left, right, .. }
+        | ExpressionKind::Logical { left, right, .. } => child(left) && child(right),
+        _ => false,
+    }
+}
+
+fn is_camel_case(name: &str) -> bool {
+    name.starts_with(|character: char| character.is_ascii_lowercase())
+        && name
+            .chars()
+            .all(|character| character.is_ascii_alphanumeric())
+}
+
+fn is_snake_case(name: &str) -> bool {
+    name.starts_with(|character: char| character.is_ascii_lowercase())
+        && name.chars().all(|character| {
+            character.is_ascii_lowercase() || character.is_ascii_digit() || character == '_'
+        })
+}
+
+fn is_pascal_case(name: &str) -> bool {
+    name.starts_with(|character: char| character.is_ascii_uppercase())
+        && name
+            .chars()
+            .all(|character| character.is_ascii_alphanumeric())
+}
+
+// Variables may also be named like constants, e.g. MAX_DEPTH.
+fn is_constant_case(name: &str) -> bool {
+    name.starts_with(|character: char| character.is_ascii_uppercase())
+        && name.chars().all(|character| {
+            character.is_ascii_uppercase() || character.is_ascii_digit() || character == '_'
+        })
+}
@@ -0,0 +1,453 @@
+use core::cell::Cell;
+
+use crate::bytecode::Prototype;
+use crate::cancel::CancelHandle;
+use crate::capability::Capabilities;
+use crate::compiler;
+use crate::debugger::{DebugHook, Debugger};
+use crate::dialect::Dialect;
+use crate::error::{Error, LoxError, RuntimeError};
+use crate::gc::GcConfig;
+use crate::interpreter::{Interpreter, InterpreterOptions};
+use crate::io::HostIo;
+use crate::optimizer;
+use crate::parser::{Parser, DEFAULT_MAX_NESTING_DEPTH};
+use crate::prelude::*;
+#[cfg(feature = "regvm")]
+use crate::regcompiler;
+#[cfg(feature = "regvm")]
+use crate::regvm::RegisterVm;
+use crate::resolver::Resolver;
+use crate::scanner::Scanner;
+use crate::snapshot::Snapshot;
+use crate::statement::{self, Statement};
+use crate::token::Token;
+use crate::trace::{self, Phase};
+use crate::transpiler::{self, Target};
+use crate::value::Value;
+use crate::vm::Vm;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Engine {
+    #[default]
+    Walker,
+    Vm,
+    #[cfg(feature = "regvm")]
+    Register,
+}
+
+impl Engine {
+    pub fn from_name(name: &str) -> Option<Engine> {
+        match name {
+            "walker" => Some(Engine::Walker),
+            "vm" => Some(Engine::Vm),
+            #[cfg(feature = "regvm")]
+            "register" => Some(Engine::Register),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Engine::Walker => "walker",
+            Engine::Vm => "vm",
+            #[cfg(feature = "regvm")]
+            Engine::Register => "register",
+        }
+    }
+}
+
+// Counters from the last call to `run` or `eval`: what the front end saw,
+// then what running it did. On the tree-walker each statement or expression
+// evaluated counts as an instruction. Allocations and the peak cover the
+// objects the collector tracks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Metrics {
+    pub tokens: usize,
+    pub nodes: usize,
+    pub instructions: u64,
+    pub allocations: u64,
+    pub collections: u64,
+    pub peak_heap_bytes: usize,
+}
+
+impl Metrics {
+    pub fn report(&self) -> String {
+        let rows = [
+            ("tokens scanned", self.tokens as u64),
+            ("syntax nodes", self.nodes as u64),
+            ("instructions", self.instructions),
+            ("allocations", self.allocations),
+            ("collections", self.collections),
+            ("peak heap bytes", self.peak_heap_bytes as u64),
+        ];
+
+        let mut report = String::new();
+        for (name, count) in rows {
+            report.push_str(&format!("{:<16} {:>12}\n", name, count));
+        }
+        report
+    }
+}
+
+pub struct Lox {
+    interpreter: Interpreter,
+    max_nesting_depth: usize,
+    dialect: Dialect,
+    engine: Engine,
+    tokens: Cell<usize>,
+    nodes: Cell<usize>,
+}
+
+impl Default for Lox {
+    fn default() -> Lox {
+        Lox::new()
+    }
+}
+
+impl Lox {
+    pub fn new() -> Lox {
+        Lox::with_options(InterpreterOptions::default())
+    }
+
+    pub fn with_options(options: InterpreterOptions) -> Lox {
+        Lox {
+            interpreter: Interpreter::with_options(options),
+            max_nesting_depth: DEFAULT_MAX_NESTING_DEPTH,
+            dialect: Dialect::default(),
+            engine: Engine::default(),
+            tokens: Cell::new(0),
+            nodes: Cell::new(0),
+        }
+    }
+
+    pub fn builder() -> LoxBuilder {
+        LoxBuilder::new()
+    }
+
+    pub fn interpreter(&mut self) -> &mut Interpreter {
+        &mut self.interpreter
+    }
+
+    pub fn metrics(&self) -> Metrics {
+        let heap = self.interpreter.heap().stats();
+        Metrics {
+            tokens: self.tokens.get(),
+            nodes: self.nodes.get(),
+            instructions: self.interpreter.steps(),
+            allocations: heap.allocations,
+            collections: heap.collections,
+            peak_heap_bytes: heap.peak_bytes,
+        }
+    }
+
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.interpreter.cancel_handle()
+    }
+
+    pub fn set_max_nesting_depth(&mut self, max_nesting_depth: usize) {
+        self.max_nesting_depth = max_nesting_depth;
+    }
+
+    // Without the print statement, `print` and `println` become globals. In
+    // strict mode `clock` is the only native left, as in the book.
+    pub fn set_dialect(&mut self, dialect: Dialect) {
+        if !dialect.print_statement() {
+            self.interpreter.define_print_natives();
+        }
+        if dialect.is_strict() {
+            self.interpreter.retain_globals(|name| name == "clock");
+        }
+        self.interpreter.set_extensions(&dialect);
+        self.dialect = dialect;
+    }
+
+    pub fn engine(&self) -> Engine {
+        self.engine
+    }
+
+    pub fn set_engine(&mut self, engine: Engine) {
+        self.engine = engine;
+    }
+
+    pub fn register_native<F>(&mut self, name: &str, arity: usize, function: F)
+    where
+        F: Fn(&mut Interpreter, &[Value]) -> Result<Value, RuntimeError> + 'static,
+    {
+        self.interpreter.define_native(name, arity, function);
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        self.interpreter.get_global(name)
+    }
+
+    // The global bindings, to roll back to with `restore`. The objects they
+    // name are shared, not copied: a list, map or instance changed after the
+    // snapshot stays changed once it is restored, so a snapshot can undo a
+    // failed REPL line but can't fork a session.
+    pub fn snapshot(&self) -> Snapshot {
+        self.interpreter.snapshot()
+    }
+
+    // Puts back the bindings `snapshot` saw; see `snapshot` for what it
+    // leaves alone.
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.interpreter.restore(snapshot);
+    }
+
+    pub fn define_global(&mut self, name: &str, value: Value) {
+        self.interpreter.define_global(name, value);
+    }
+
+    pub fn call(&mut self, function: &Value, arguments: &[Value]) -> Result<Value, RuntimeError> {
+        self.interpreter.reset_usage();
+        let result = self.interpreter.call(function.clone(), arguments.to_vec());
+        if result.is_err() {
+            self.interpreter.reset();
+        }
+
+        result
+    }
+
+    // The value of a single expression. It is checked like a statement but
+    // always evaluated on the tree-walker, whatever the configured engine:
+    // the VM runs whole scripts and has no value to hand back.
+    pub fn eval(&mut self, source: &str) -> Result<Value, LoxError> {
+        let _run = trace::enter(Phase::Run);
+        self.nodes.set(0);
+        let tokens = self.scan(source).map_err(LoxError::Compile)?;
+
+        let expression = {
+            let _parse = trace::enter(Phase::Parse);
+            let mut parser = Parser::new(tokens);
+            parser.set_max_nesting_depth(self.max_nesting_depth);
+            parser.set_dialect(self.dialect.clone());
+            parser.parse_expression().map_err(LoxError::Compile)?
+        };
+        self.nodes.set(expression.node_count());
+
+        let errors = {
+            let _resolve = trace::enter(Phase::Resolve);
+            let statements = [Rc::new(Statement::Expression(expression.clone()))];
+            match self.dialect.is_strict() {
+                true => Resolver::check_strict(&statements),
+                false => Resolver::check(&statements),
+            }
+        };
+        if !errors.is_empty() {
+            return Err(LoxError::Compile(errors));
+        }
+
+        self.interpreter
+            .evaluate_expression(expression)
+            .map_err(LoxError::Runtime)
+    }
+
+    pub fn run(&mut self, source: &str) -> Result<(), LoxError> {
+        let _run = trace::enter(Phase::Run);
+        self.tokens.set(0);
+        self.nodes.set(0);
+        match self.engine {
+            Engine::Walker => {
+                let statements = self.parse(source).map_err(LoxError::Compile)?;
+                self.interpreter
+                    .interpret(&statements)
+                    .map_err(LoxError::Runtime)
+            }
+            Engine::Vm => {
+                let script = self.compile(source).map_err(LoxError::Compile)?;
+                self.run_script(script).map_err(LoxError::Runtime)
+            }
+            #[cfg(feature = "regvm")]
+            Engine::Register => {
+                let statements = self.parse(source).map_err(LoxError::Compile)?;
+                let script = {
+                    let _compile = trace::enter(Phase::Compile);
+                    regcompiler::compile(&statements).map_err(LoxError::Compile)?
+                };
+                RegisterVm::new()
+                    .interpret(&mut self.interpreter, script)
+                    .map_err(LoxError::Runtime)
+            }
+        }
+    }
+
+    // Runs the source under the interactive debugger, on the tree-walker
+    // whatever the configured engine.
+    pub fn debug(&mut self, source: &str) -> Result<(), LoxError> {
+        self.debug_with(source, |statements| {
+            Box::new(Debugger::new(source, statements))
+        })
+    }
+
+    // Like `debug`, with the hook built from the parsed program.
+    pub fn debug_with(
+        &mut self,
+        source: &str,
+        hook: impl FnOnce(&[Rc<Statement>]) -> Box<dyn DebugHook>,
+    ) -> Result<(), LoxError> {
+        let statements = self.parse(source).map_err(LoxError::Compile)?;
+
+        self.interpreter.set_debug_hook(Some(hook(&statements)));
+        let result = self.interpreter.interpret(&statements);
+        self.interpreter.set_debug_hook(None);
+        result.map_err(LoxError::Runtime)
+    }
+
+    pub fn run_script(&mut self, script: Rc<Prototype>) -> Result<(), RuntimeError> {
+        Vm::new().interpret(&mut self.interpreter, script)
+    }
+
+    pub fn compile(&self, source: &str) -> Result<Rc<Prototype>, Vec<Error>> {
+        let script = self.compile_unoptimized(source)?;
+        let _optimize = trace::enter(Phase::Optimize);
+        Ok(Rc::new(optimizer::optimize(&script)))
+    }
+
+    pub fn compile_unoptimized(&self, source: &str) -> Result<Rc<Prototype>, Vec<Error>> {
+        let statements = self.parse(source)?;
+        let _compile = trace::enter(Phase::Compile);
+        compiler::compile(&statements)
+    }
+
+    pub fn transpile(&self, source: &str, target: Target) -> Result<String, Vec<Error>> {
+        let statements = self.parse(source)?;
+        transpiler::transpile(&statements, target)
+    }
+
+    // In strict mode scanning carries on past errors, as the book's scanner
+    // does, and its errors come before the parser's. Then, only if there were
+    // none, the resolver checks run, the book's own in strict mode.
+    fn parse(&self, source: &str) -> Result<Vec<Rc<Statement>>, Vec<Error>> {
+        let (tokens, mut errors) = match self.dialect.is_strict() {
+            true => self.scan_all(source),
+            false => (self.scan(source)?, Vec::new()),
+        };
+
+        let statements = {
+            let _parse = trace::enter(Phase::Parse);
+            let mut parser = Parser::new(tokens);
+            parser.set_max_nesting_depth(self.max_nesting_depth);
+            parser.set_dialect(self.dialect.clone());
+            match parser.parse() {
+                Ok(statements) if errors.is_empty() => statements,
+                Ok(_) => return Err(errors),
+                Err(parse_errors) => {
+                    errors.extend(parse_errors);
+                    return Err(errors);
+                }
+            }
+        };
+        self.nodes.set(statement::count(&statements));
+
+        let errors = {
+            let _resolve = trace::enter(Phase::Resolve);
+            match self.dialect.is_strict() {
+                true => Resolver::check_strict(&statements),
+                false => Resolver::check(&statements),
+            }
+        };
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        Ok(statements)
+    }
+
+    fn scan(&self, source: &str) -> Result<Vec<Token>, Vec<Error>> {
+        let _scan = trace::enter(Phase::Scan);
+        self.tokens.set(0);
+        let mut scanner = Scanner::new(source.to_string());
+        scanner.set_dialect(self.dialect.clone());
+        let tokens = scanner.scan_tokens().map_err(|error| vec![error])?;
+        self.tokens.set(tokens.len() - 1);
+        Ok(tokens)
+    }
+
+    fn scan_all(&self, source: &str) -> (Vec<Token>, Vec<Error>) {
+        let _scan = trace::enter(Phase::Scan);
+        let mut scanner = Scanner::new(source.to_string());
+        scanner.set_dialect(self.dialect.clone());
+        let (tokens, errors) = scanner.scan_all();
+        self.tokens.set(tokens.len() - 1);
+        (tokens, errors.into_iter().map(|(_, error)| error).collect())
+    }
+}
+
+#[derive(Default)]
+pub struct LoxBuilder {
+    options: InterpreterOptions,
+    capabilities: Capabilities,
+    io: Option<Box<dyn HostIo>>,
+    seed: Option<u64>,
+    max_nesting_depth: Option<usize>,
+    dialect: Dialect,
+    engine: Engine,
+    gc: GcConfig,
+}
+
+impl LoxBuilder {
+    pub fn new() -> LoxBuilder {
+        LoxBuilder::default()
+    }
+
+    pub fn with_options(mut self, options: InterpreterOptions) -> LoxBuilder {
+        self.options = options;
+        self
+    }
+
+    pub fn with_capabilities(mut self, capabilities: Capabilities) -> LoxBuilder {
+        self.capabilities = capabilities;
+        self
+    }
+
+    pub fn with_io(mut self, io: Box<dyn HostIo>) -> LoxBuilder {
+        self.io = Some(io);
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> LoxBuilder {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn with_max_nesting_depth(mut self, max_nesting_depth: usize) -> LoxBuilder {
+        self.max_nesting_depth = Some(max_nesting_depth);
+        self
+    }
+
+    pub fn with_dialect(mut self, dialect: Dialect) -> LoxBuilder {
+        self.dialect = dialect;
+        self
+    }
+
+    pub fn with_gc(mut self, gc: GcConfig) -> LoxBuilder {
+        self.gc = gc;
+        self
+    }
+
+    pub fn engine(mut self, engine: Engine) -> LoxBuilder {
+        self.engine = engine;
+        self
+    }
+
+    pub fn build(self) -> Lox {
+        let mut lox = Lox::with_options(self.options);
+        lox.set_engine(self.engine);
+        lox.set_dialect(self.dialect);
+        if let Some(max_nesting_depth) = self.max_nesting_depth {
+            lox.set_max_nesting_depth(max_nesting_depth);
+        }
+
+        let interpreter = lox.interpreter();
+        interpreter.set_capabilities(self.capabilities);
+        interpreter.set_gc_config(self.gc);
+        if let Some(io) = self.io {
+            interpreter.set_io(io);
+        }
+        if let Some(seed) = self.seed {
+            interpreter.random().seed(seed);
+        }
+
+        lox
+    }
+}
@@ -0,0 +1,768 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::slice;
+
+use crate::analysis::{self, Analysis, Fix, OutlineItem, SymbolKind, TextEdit, TokenType};
+use crate::check::{self, lox_files};
+use crate::completion::CompletionKind;
+use crate::formatter::{self, Style};
+use crate::json::Json;
+use crate::lint::Config;
+use crate::rpc::{object, string, Transport};
+use crate::token::Position;
+
+// JSON-RPC's code for requests the server does not implement, and LSP's
+// for requests it understood but could not carry out.
+const METHOD_NOT_FOUND: f64 = -32601.0;
+const REQUEST_FAILED: f64 = -32803.0;
+
+// LSP's numbering of severities and of incremental document sync.
+const SEVERITY_ERROR: f64 = 1.0;
+const SEVERITY_WARNING: f64 = 2.0;
+const TEXT_DOCUMENT_SYNC_INCREMENTAL: f64 = 2.0;
+
+struct Document {
+    text: String,
+    analysis: Analysis,
+}
+
+// A diagnostic as published, with the characters it covers and the fix a
+// code action offers for it.
+struct Diagnostic {
+    span: Position,
+    json: Json,
+    fix: Option<Fix>,
+}
+
+impl Document {
+    fn new(text: String) -> Document {
+        let analysis = Analysis::new(&text);
+        Document { text, analysis }
+    }
+
+    // Applies a change as the client sends it: the text for a range, or the
+    // whole of the new text.
+    fn change(&mut self, change: &Json) {
+        let Some(text) = change.get("text").and_then(Json::as_str) else {
+            return;
+        };
+        let Some(range) = change.get("range") else {
+            *self = Document::new(text.to_string());
+            return;
+        };
+
+        let start = range.get("start");
+        let line = start
+            .and_then(|start| start.get("line"))
+            .and_then(Json::as_number)
+            .unwrap_or(0.0) as usize;
+        let edit = TextEdit {
+            position: Position {
+                start: offset_at(self, start),
+                current: offset_at(self, range.get("end")),
+                line: line + 1,
+            },
+            text: text.to_string(),
+        };
+        self.analysis = self.analysis.edit(&edit);
+        self.text = analysis::apply(&self.text, slice::from_ref(&edit));
+    }
+}
+
+// Serves the Language Server Protocol until the client sends "exit" or
+// closes the stream. Documents are synced by the ranges that change, and
+// re-analyzed from the tokens around each, publishing their diagnostics
+// each time.
+pub fn serve(reader: impl BufRead + 'static, writer: impl Write + 'static) -> io::Result<()> {
+    let mut transport = Transport::new(reader, writer);
+    let mut documents: BTreeMap<String, Document> = BTreeMap::new();
+    let mut root: Option<PathBuf> = None;
+
+    while let Some(message) = transport.receive()? {
+        let method = message
+            .get("method")
+            .and_then(Json::as_str)
+            .unwrap_or_default();
+        let params = message.get("params").unwrap_or(&Json::Null);
+        let uri = params
+            .get("textDocument")
+            .and_then(|document| document.get("uri"))
+            .and_then(Json::as_str)
+            .unwrap_or_default()
+            .to_string();
+
+        let result = match method {
+            "initialize" => {
+                root = params
+                    .get("rootUri")
+                    .and_then(Json::as_str)
+                    .and_then(path_from_uri)
+                    .or_else(|| {
+                        params
+                            .get("rootPath")
+                            .and_then(Json::as_str)
+                            .map(PathBuf::from)
+                    });
+                Ok(capabilities())
+            }
+            "shutdown" => Ok(Json::Null),
+            "exit" => return Ok(()),
+            "textDocument/didOpen" => {
+                let text = params
+                    .get("textDocument")
+                    .and_then(|document| document.get("text"))
+                    .and_then(Json::as_str)
+                    .unwrap_or_default();
+                let document = Document::new(text.to_string());
+                publish_diagnostics(&mut transport, &uri, &document)?;
+                documents.insert(uri, document);
+                Ok(Json::Null)
+            }
+            "textDocument/didChange" => {
+                let changes = params
+                    .get("contentChanges")
+                    .and_then(Json::as_array)
+                    .unwrap_or_default();
+                if !changes.is_empty() {
+                    let document = documents
+                        .entry(uri.clone())
+                        .or_insert_with(|| Document::new(String::new()));
+                    for change in changes {
+                        document.change(change);
+                    }
+                    publish_diagnostics(&mut transport, &uri, document)?;
+                }
+                Ok(Json::Null)
+            }
+            "textDocument/didClose" => {
+                documents.remove(&uri);
+                publish_diagnostics(&mut transport, &uri, &Document::new(String::new()))?;
+                Ok(Json::Null)
+            }
+            "textDocument/hover" => Ok(documents
+                .get(&uri)
+                .map_or(Json::Null, |document| hover(document, params))),
+            "textDocument/completion" => Ok(documents
+                .get(&uri)
+                .map_or(Json::Null, |document| completions(document, params))),
+            "textDocument/documentSymbol" => {
+                Ok(documents.get(&uri).map_or(Json::Null, document_symbols))
+            }
+            "workspace/symbol" => {
+                let query = params
+                    .get("query")
+                    .and_then(Json::as_str)
+                    .unwrap_or_default();
+                Ok(workspace_symbols(&documents, root.as_deref(), query))
+            }
+            "textDocument/definition" => Ok(documents
+                .get(&uri)
+                .map_or(Json::Null, |document| definition(&uri, document, params))),
+            "textDocument/references" => Ok(documents
+                .get(&uri)
+                .map_or(Json::Null, |document| references(&uri, document, params))),
+            "textDocument/semanticTokens/full" => {
+                Ok(documents.get(&uri).map_or(Json::Null, semantic_tokens))
+            }
+            "textDocument/formatting" => match documents.get(&uri) {
+                Some(document) => {
+                    formatting(&uri, document, params).map_err(|message| (REQUEST_FAILED, message))
+                }
+                None => Ok(Json::Null),
+            },
+            "textDocument/codeAction" => Ok(documents
+                .get(&uri)
+                .map_or(Json::Null, |document| code_actions(&uri, document, params))),
+            "textDocument/rename" => match documents.get(&uri) {
+                Some(document) => {
+                    rename(&uri, document, params).map_err(|message| (REQUEST_FAILED, message))
+                }
+                None => Ok(Json::Null),
+            },
+            _ => Err((METHOD_NOT_FOUND, format!("Unknown method '{}'.", method))),
+        };
+
+        // Notifications carry no id and get no reply.
+        let Some(id) = message.get("id").cloned() else {
+            continue;
+        };
+        let reply = match result {
+            Ok(result) => ("result", result),
+            Err((code, message)) => (
+                "error",
+                object(vec![
+                    ("code", Json::Number(code)),
+                    ("message", string(&message)),
+                ]),
+            ),
+        };
+        transport.send(&object(vec![("jsonrpc", string("2.0")), ("id", id), reply]))?;
+    }
+
+    Ok(())
+}
+
+fn capabilities() -> Json {
+    object(vec![
+        (
+            "capabilities",
+            object(vec![
+                (
+                    "textDocumentSync",
+                    Json::Number(TEXT_DOCUMENT_SYNC_INCREMENTAL),
+                ),
+                ("hoverProvider", Json::Boolean(true)),
+                (
+                    "completionProvider",
+                    object(vec![("triggerCharacters", Json::Array(vec![string(".")]))]),
+                ),
+                ("documentSymbolProvider", Json::Boolean(true)),
+                ("definitionProvider", Json::Boolean(true)),
+                ("referencesProvider", Json::Boolean(true)),
+                ("workspaceSymbolProvider", Json::Boolean(true)),
+                ("renameProvider", Json::Boolean(true)),
+                ("codeActionProvider", Json::Boolean(true)),
+                ("documentFormattingProvider", Json::Boolean(true)),
+                (
+                    "semanticTokensProvider",
+                    object(vec![
+                        (
+                            "legend",
+                            object(vec![
+                                (
+                                    "tokenTypes",
+                                    Json::Array(
+                                        TokenType::ALL
+                                            .iter()
+                                            .map(|token_type| string(token_type.name()))
+                                            .collect(),
+                                    ),
+                                ),
+                                ("tokenModifiers", Json::Array(Vec::new())),
+                            ]),
+                        ),
+                        ("full", Json::Boolean(true)),
+                    ]),
+                ),
+            ]),
+        ),
+        ("serverInfo", object(vec![("name", string("lox"))])),
+    ])
+}
+
+fn publish_diagnostics(
+    transport: &mut Transport,
+    uri: &str,
+    document: &Document,
+) -> io::Result<()> {
+    let diagnostics = diagnostics(uri, document)
+        .into_iter()
+        .map(|diagnostic| diagnostic.json)
+        .collect();
+    transport.send(&object(vec![
+        ("jsonrpc", string("2.0")),
+        ("method", string("textDocument/publishDiagnostics")),
+        (
+            "params",
+            object(vec![
+                ("uri", string(uri)),
+                ("diagnostics", Json::Array(diagnostics)),
+            ]),
+        ),
+    ]))
+}
+
+// Compile errors only carry a line, so each one spans its whole line. Lint
+// warnings follow them, with rules chosen by the nearest .loxlint.toml.
+fn diagnostics(uri: &str, document: &Document) -> Vec<Diagnostic> {
+    let lines: Vec<&str> = document.text.lines().collect();
+    let mut diagnostics: Vec<Diagnostic> = document
+        .analysis
+        .diagnostics()
+        .iter()
+        .enumerate()
+        .map(|(index, error)| {
+            let line = error.line.saturating_sub(1);
+            let length = lines
+                .get(line)
+                .map_or(0, |text| text.encode_utf16().count());
+            let json = object(vec![
+                (
+                    "range",
+                    object(vec![
+                        ("start", position(line, 0)),
+                        ("end", position(line, length)),
+                    ]),
+                ),
+                ("severity", Json::Number(SEVERITY_ERROR)),
+                ("source", string("lox")),
+                ("message", string(&error.message)),
+            ]);
+            Diagnostic {
+                span: Position {
+                    start: offset(&document.text, line, 0),
+                    current: offset(&document.text, line, length),
+                    line: error.line,
+                },
+                json,
+                fix: document.analysis.fix_for(index).cloned(),
+            }
+        })
+        .collect();
+
+    let config = path_from_uri(uri)
+        .and_then(|path| Config::find(path.parent()?))
+        .and_then(|path| Config::load(&path).ok())
+        .unwrap_or_default();
+    for warning in document.analysis.lint(&config) {
+        let json = object(vec![
+            ("range", range(&document.text, &warning.position)),
+            ("severity", Json::Number(SEVERITY_WARNING)),
+            ("code", string(warning.rule.code())),
+            ("source", string("lox")),
+            ("message", string(&warning.message)),
+        ]);
+        diagnostics.push(Diagnostic {
+            span: warning.position,
+            json,
+            fix: warning.fix,
+        });
+    }
+    diagnostics
+}
+
+// Quick fixes for the diagnostics that touch the requested range.
+fn code_actions(uri: &str, document: &Document, params: &Json) -> Json {
+    let range = params.get("range");
+    let start = offset_at(document, range.and_then(|range| range.get("start")));
+    let end = offset_at(document, range.and_then(|range| range.get("end")));
+
+    let actions = diagnostics(uri, document)
+        .into_iter()
+        .filter(|diagnostic| diagnostic.span.start <= end && start <= diagnostic.span.current)
+        .filter_map(|diagnostic| {
+            let fix = diagnostic.fix?;
+            Some(object(vec![
+                ("title", string(&fix.message)),
+                ("kind", string("quickfix")),
+                ("diagnostics", Json::Array(vec![diagnostic.json])),
+                ("isPreferred", Json::Boolean(true)),
+                ("edit", workspace_edit(uri, document, &fix.edits)),
+            ]))
+        })
+        .collect();
+    Json::Array(actions)
+}
+
+// The character offset of a request's "position".
+fn requested_offset(document: &Document, params: &Json) -> usize {
+    offset_at(document, params.get("position"))
+}
+
+fn offset_at(document: &Document, position: Option<&Json>) -> usize {
+    let coordinate = |key: &str| {
+        position
+            .and_then(|position| position.get(key))
+            .and_then(Json::as_number)
+            .unwrap_or(0.0) as usize
+    };
+    offset(&document.text, coordinate("line"), coordinate("character"))
+}
+
+fn hover(document: &Document, params: &Json) -> Json {
+    let offset = requested_offset(document, params);
+    let Some(value) = document.analysis.hover(offset) else {
+        return Json::Null;
+    };
+
+    object(vec![(
+        "contents",
+        object(vec![
+            ("kind", string("markdown")),
+            ("value", string(&value)),
+        ]),
+    )])
+}
+
+fn completions(document: &Document, params: &Json) -> Json {
+    let offset = requested_offset(document, params);
+    Json::Array(
+        document
+            .analysis
+            .completions(offset)
+            .iter()
+            .map(|completion| {
+                object(vec![
+                    ("label", string(&completion.label)),
+                    ("kind", Json::Number(completion_kind(completion.kind))),
+                    ("detail", string(&completion.detail)),
+                ])
+            })
+            .collect(),
+    )
+}
+
+fn definition(uri: &str, document: &Document, params: &Json) -> Json {
+    let offset = requested_offset(document, params);
+    document
+        .analysis
+        .definition_at(offset)
+        .map_or(Json::Null, |position| location(uri, document, &position))
+}
+
+fn references(uri: &str, document: &Document, params: &Json) -> Json {
+    let offset = requested_offset(document, params);
+    let include_declaration = params
+        .get("context")
+        .and_then(|context| context.get("includeDeclaration"))
+        .and_then(Json::as_bool)
+        .unwrap_or(false);
+
+    Json::Array(
+        document
+            .analysis
+            .references_at(offset, include_declaration)
+            .iter()
+            .map(|position| location(uri, document, position))
+            .collect(),
+    )
+}
+
+fn rename(uri: &str, document: &Document, params: &Json) -> Result<Json, String> {
+    let offset = requested_offset(document, params);
+    let new_name = params
+        .get("newName")
+        .and_then(Json::as_str)
+        .unwrap_or_default();
+
+    let edits = document.analysis.rename(offset, new_name)?;
+    Ok(workspace_edit(uri, document, &edits))
+}
+
+fn workspace_edit(uri: &str, document: &Document, edits: &[TextEdit]) -> Json {
+    let edits = edits
+        .iter()
+        .map(|edit| {
+            object(vec![
+                ("range", range(&document.text, &edit.position)),
+                ("newText", string(&edit.text)),
+            ])
+        })
+        .collect();
+    object(vec![("changes", object(vec![(uri, Json::Array(edits))]))])
+}
+
+// Styled by the nearest .loxfmt.toml, or by the client's indentation when
+// there is none. Documents that don't parse are left alone.
+fn formatting(uri: &str, document: &Document, params: &Json) -> Result<Json, String> {
+    let configured = path_from_uri(uri)
+        .and_then(|path| Style::find(path.parent()?))
+        .map(|path| Style::load(&path))
+        .transpose()?;
+    let style = configured.unwrap_or_else(|| {
+        let options = params.get("options");
+        let mut style = Style::default();
+        if let Some(size) = options.and_then(|options| options.get("tabSize")) {
+            style.indent_width = size
+                .as_number()
+                .map_or(style.indent_width, |size| size as usize);
+        }
+        if let Some(spaces) = options.and_then(|options| options.get("insertSpaces")) {
+            style.use_tabs = spaces.as_bool() == Some(false);
+        }
+        style
+    });
+
+    let Ok(formatted) = formatter::format(&document.text, &style) else {
+        return Ok(Json::Null);
+    };
+    if formatted == document.text {
+        return Ok(Json::Array(Vec::new()));
+    }
+
+    let whole = Position {
+        start: 0,
+        current: document.text.chars().count(),
+        line: 0,
+    };
+    Ok(Json::Array(vec![object(vec![
+        ("range", range(&document.text, &whole)),
+        ("newText", string(&formatted)),
+    ])]))
+}
+
+fn location(uri: &str, document: &Document, position: &Position) -> Json {
+    object(vec![
+        ("uri", string(uri)),
+        ("range", range(&document.text, position)),
+    ])
+}
+
+fn document_symbols(document: &Document) -> Json {
+    fn symbol(document: &Document, item: &OutlineItem) -> Json {
+        object(vec![
+            ("name", string(&item.name)),
+            ("detail", string(&item.detail)),
+            ("kind", Json::Number(symbol_kind(item.kind))),
+            ("range", range(&document.text, &item.span)),
+            ("selectionRange", range(&document.text, &item.position)),
+            (
+                "children",
+                Json::Array(
+                    item.children
+                        .iter()
+                        .map(|child| symbol(document, child))
+                        .collect(),
+                ),
+            ),
+        ])
+    }
+
+    Json::Array(
+        document
+            .analysis
+            .outline()
+            .iter()
+            .map(|item| symbol(document, item))
+            .collect(),
+    )
+}
+
+// Outline entries whose names contain the query, ignoring case, from the
+// open documents and from the .lox files under the workspace root.
+fn workspace_symbols(
+    documents: &BTreeMap<String, Document>,
+    root: Option<&Path>,
+    query: &str,
+) -> Json {
+    fn collect(
+        uri: &str,
+        text: &str,
+        items: &[OutlineItem],
+        container: Option<&str>,
+        query: &str,
+        symbols: &mut Vec<Json>,
+    ) {
+        for item in items {
+            if item.name.to_lowercase().contains(query) {
+                let mut symbol = vec![
+                    ("name", string(&item.name)),
+                    ("kind", Json::Number(symbol_kind(item.kind))),
+                    (
+                        "location",
+                        object(vec![
+                            ("uri", string(uri)),
+                            ("range", range(text, &item.span)),
+                        ]),
+                    ),
+                ];
+                if let Some(container) = container {
+                    symbol.push(("containerName", string(container)));
+                }
+                symbols.push(object(symbol));
+            }
+            collect(uri, text, &item.children, Some(&item.name), query, symbols);
+        }
+    }
+
+    let query = query.to_lowercase();
+    let mut symbols = Vec::new();
+    for (uri, document) in documents {
+        let outline = document.analysis.outline();
+        collect(uri, &document.text, &outline, None, &query, &mut symbols);
+    }
+
+    let mut paths = Vec::new();
+    if let Some(root) = root {
+        lox_files(root, &mut paths);
+    }
+    paths.retain(|path| !documents.contains_key(&uri_from_path(path)));
+    let outlines = check::map(&paths, |path| {
+        let text = fs::read_to_string(path).ok()?;
+        let outline = Analysis::new(&text).outline();
+        Some((text, outline))
+    });
+    for (path, outline) in paths.iter().zip(outlines) {
+        let Some((text, outline)) = outline else {
+            continue;
+        };
+        collect(
+            &uri_from_path(path),
+            &text,
+            &outline,
+            None,
+            &query,
+            &mut symbols,
+        );
+    }
+
+    Json::Array(symbols)
+}
+
+fn path_from_uri(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::new();
+    let mut index = 0;
+    while index < bytes.len() {
+        let escaped = (bytes[index] == b'%')
+            .then(|| path.get(index + 1..index + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            None => {
+                decoded.push(bytes[index]);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).ok().map(PathBuf::from)
+}
+
+fn uri_from_path(path: &Path) -> String {
+    let mut uri = "file://".to_string();
+    for byte in path.to_string_lossy().bytes() {
+        match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'/' | b'-' | b'.' | b'_' | b'~' => {
+                uri.push(byte as char)
+            }
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+// Five numbers per token: line and start relative to the previous token,
+// length, type and modifiers. Positions are in UTF-16 code units and a
+// string spanning lines is sent as one token per line.
+fn semantic_tokens(document: &Document) -> Json {
+    let characters: Vec<char> = document.text.chars().collect();
+    let mut data = Vec::new();
+    let (mut line, mut character, mut offset) = (0, 0, 0);
+    let (mut previous_line, mut previous_start) = (0, 0);
+    let mut push = |line: usize, start: usize, length: usize, token_type: usize| {
+        let delta_start = if line == previous_line {
+            start - previous_start
+        } else {
+            start
+        };
+        for value in [line - previous_line, delta_start, length, token_type, 0] {
+            data.push(Json::Number(value as f64));
+        }
+        (previous_line, previous_start) = (line, start);
+    };
+
+    for token in document.analysis.semantic_tokens() {
+        let token_type = TokenType::ALL
+            .iter()
+            .position(|candidate| *candidate == token.token_type)
+            .unwrap_or_default();
+
+        let mut start = character;
+        let mut length = 0;
+        while offset < token.start + token.length && offset < characters.len() {
+            let current = characters[offset];
+            offset += 1;
+            if current == '\n' {
+                if offset > token.start && length > 0 {
+                    push(line, start, length, token_type);
+                }
+                line += 1;
+                character = 0;
+                start = 0;
+                length = 0;
+                continue;
+            }
+
+            character += current.len_utf16();
+            if offset <= token.start {
+                start = character;
+            } else {
+                length += current.len_utf16();
+            }
+        }
+        if length > 0 {
+            push(line, start, length, token_type);
+        }
+    }
+
+    object(vec![("data", Json::Array(data))])
+}
+
+fn symbol_kind(kind: SymbolKind) -> f64 {
+    match kind {
+        SymbolKind::Class => 5.0,
+        SymbolKind::Method => 6.0,
+        SymbolKind::Function => 12.0,
+        SymbolKind::Variable | SymbolKind::Parameter => 13.0,
+    }
+}
+
+fn completion_kind(kind: CompletionKind) -> f64 {
+    match kind {
+        CompletionKind::Method => 2.0,
+        CompletionKind::Function => 3.0,
+        CompletionKind::Field => 5.0,
+        CompletionKind::Variable | CompletionKind::Parameter => 6.0,
+        CompletionKind::Class => 7.0,
+        CompletionKind::Keyword => 14.0,
+    }
+}
+
+fn range(text: &str, position: &Position) -> Json {
+    let (start_line, start_character) = line_and_character(text, position.start);
+    let (end_line, end_character) = line_and_character(text, position.current);
+    object(vec![
+        ("start", self::position(start_line, start_character)),
+        ("end", self::position(end_line, end_character)),
+    ])
+}
+
+fn position(line: usize, character: usize) -> Json {
+    object(vec![
+        ("line", Json::Number(line as f64)),
+        ("character", Json::Number(character as f64)),
+    ])
+}
+
+// Scanner offsets count characters; LSP counts lines from zero and
+// characters in UTF-16 code units.
+fn line_and_character(text: &str, offset: usize) -> (usize, usize) {
+    let mut line = 0;
+    let mut character = 0;
+    for current in text.chars().take(offset) {
+        if current == '\n' {
+            line += 1;
+            character = 0;
+        } else {
+            character += current.len_utf16();
+        }
+    }
+    (line, character)
+}
+
+fn offset(text: &str, line: usize, character: usize) -> usize {
+    let mut current_line = 0;
+    let mut current_character = 0;
+    for (offset, current) in text.chars().enumerate() {
+        if current_line == line && current_character >= character {
+            return offset;
+        }
+        if current == '\n' {
+            if current_line == line {
+                return offset;
+            }
+            current_line += 1;
+            current_character = 0;
+        } else {
+            current_character += current.len_utf16();
+        }
+    }
+    text.chars().count()
+}
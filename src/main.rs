@@ -1,12 +1,767 @@
-mod error;
-mod expression;
-mod scanner;
-mod token;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::{env, fs, process};
 
-use crate::scanner::Scanner;
+use lox::bench::{self, Workload};
+use lox::bytecode::Prototype;
+use lox::capability::Capabilities;
+use lox::check;
+use lox::coverage;
+use lox::dap;
+use lox::dialect::Dialect;
+use lox::disassembler;
+use lox::error::{LoxError, RuntimeErrorKind};
+use lox::formatter::{self, Style};
+use lox::gc::{GcConfig, GcMode};
+use lox::interpreter::{DivisionByZero, InterpreterOptions};
+#[cfg(feature = "jit")]
+use lox::jit::DEFAULT_HOT_THRESHOLD;
+use lox::lint::{self, Config};
+use lox::lox::Engine;
+use lox::lsp;
+use lox::optimizer;
+use lox::parser::DEFAULT_MAX_NESTING_DEPTH;
+use lox::serialize;
+use lox::suite;
+use lox::transpiler::Target;
+use lox::Lox;
+
+const EXIT_FINDINGS: i32 = 1;
+const EXIT_USAGE: i32 = 64;
+const EXIT_DATA: i32 = 65;
+const EXIT_SOFTWARE: i32 = 70;
+const EXIT_IO: i32 = 74;
 
 fn main() {
-    let mut scanner = Scanner::new("\"!=>=()//()\" 10 and some=10".to_string());
+    let mut options = Options {
+        limits: InterpreterOptions::default(),
+        max_nesting_depth: DEFAULT_MAX_NESTING_DEPTH,
+        dialect: Dialect::default(),
+        capabilities: Capabilities::default(),
+        seed: None,
+        engine: Engine::default(),
+        jit: false,
+        target: Target::JavaScript,
+        dump_bytecode: false,
+        diff_bytecode: false,
+        trace_execution: false,
+        gc: GcConfig::default(),
+        gc_stress: false,
+        gc_log: false,
+        profile: false,
+        stats: false,
+        heap_dump: false,
+        profile_folded: None,
+        coverage: None,
+        check: false,
+        fix: false,
+        differential: false,
+        width: None,
+        output: None,
+    };
+    let mut paths: Vec<String> = Vec::new();
+
+    let mut arguments = env::args().skip(1);
+    while let Some(argument) = arguments.next() {
+        if argument == "-o" {
+            options.output = Some(arguments.next().unwrap_or_else(|| usage()));
+        } else if let Some(value) = argument.strip_prefix("--max-call-depth=") {
+            options.limits.max_call_depth = parse_limit(value);
+        } else if let Some(value) = argument.strip_prefix("--max-steps=") {
+            options.limits.max_steps = Some(parse_limit(value) as u64);
+        } else if let Some(value) = argument.strip_prefix("--max-heap-bytes=") {
+            options.limits.max_heap_bytes = Some(parse_limit(value));
+        } else if let Some(value) = argument.strip_prefix("--division-by-zero=") {
+            options.limits.division_by_zero = match value {
+                "infinity" => DivisionByZero::Infinity,
+                "error" => DivisionByZero::Error,
+                _ => usage(),
+            };
+        } else if let Some(value) = argument.strip_prefix("--max-nesting-depth=") {
+            options.max_nesting_depth = parse_limit(value);
+        } else if let Some(value) = argument.strip_prefix("--dialect=") {
+            let print_statement = options.dialect.print_statement();
+            let integers = options.dialect.integers();
+            options.dialect = Dialect::from_name(value).unwrap_or_else(|| usage());
+            options.dialect.set_print_statement(print_statement);
+            options.dialect.set_integers(integers);
+        } else if argument == "--strict" {
+            options.dialect = Dialect::strict();
+        } else if let Some(value) = argument.strip_prefix("--print=") {
+            options.dialect.set_print_statement(match value {
+                "statement" => true,
+                "function" => false,
+                _ => usage(),
+            });
+        } else if argument == "--integers" {
+            options.dialect.set_integers(true);
+        } else if let Some(value) = argument.strip_prefix("--seed=") {
+            options.seed = Some(value.parse::<u64>().unwrap_or_else(|_| usage()));
+        } else if let Some(value) = argument.strip_prefix("--engine=") {
+            options.engine = Engine::from_name(value).unwrap_or_else(|| usage());
+        } else if let Some(value) = argument.strip_prefix("--target=") {
+            options.target = Target::from_name(value).unwrap_or_else(|| usage());
+        } else if argument == "--jit" {
+            options.jit = true;
+        } else if let Some(value) = argument.strip_prefix("--allow=") {
+            options.capabilities |= parse_capabilities(value);
+        } else if argument == "--allow-exec" {
+            options.capabilities |= Capabilities::PROCESS;
+        } else if let Some(value) = argument.strip_prefix("--deny=") {
+            options.capabilities = options.capabilities - parse_capabilities(value);
+        } else if argument == "--dump-bytecode" {
+            options.dump_bytecode = true;
+        } else if argument == "--dump-bytecode=diff" {
+            options.dump_bytecode = true;
+            options.diff_bytecode = true;
+        } else if argument == "--trace-execution" {
+            options.trace_execution = true;
+        } else if let Some(value) = argument.strip_prefix("--gc=") {
+            options.gc.mode = GcMode::from_name(value).unwrap_or_else(|| usage());
+        } else if let Some(value) = argument.strip_prefix("--nursery-bytes=") {
+            options.gc.nursery_bytes = parse_limit(value);
+        } else if argument == "--gc-stress" {
+            options.gc_stress = true;
+        } else if argument == "--gc-log" {
+            options.gc_log = true;
+        } else if argument == "--profile" {
+            options.profile = true;
+        } else if argument == "--stats" {
+            options.stats = true;
+        } else if argument == "--heap-dump-on-exit" {
+            options.heap_dump = true;
+        } else if let Some(value) = argument.strip_prefix("--profile-folded=") {
+            options.profile_folded = Some(value.to_string());
+        } else if let Some(value) = argument.strip_prefix("--coverage=") {
+            options.coverage = Some(coverage::Format::from_name(value).unwrap_or_else(|| usage()));
+        } else if argument == "--check" {
+            options.check = true;
+        } else if argument == "--fix" {
+            options.fix = true;
+        } else if argument == "--differential" {
+            options.differential = true;
+        } else if let Some(value) = argument.strip_prefix("--width=") {
+            options.width = Some(parse_limit(value));
+        } else {
+            paths.push(argument);
+        }
+    }
+
+    if options.jit {
+        options.engine = Engine::Vm;
+    }
+    // Statement coverage comes from the tree-walker, which sees statements
+    // rather than the bytecode's approximate line table.
+    if options.coverage.is_some() {
+        options.engine = Engine::Walker;
+    }
+    // Dumping bytecode doesn't run the script, and tracing runs it as
+    // bytecode, which coverage can't follow.
+    let runs = options.trace_execution
+        || options.profile
+        || options.profile_folded.is_some()
+        || options.coverage.is_some();
+    if (options.dump_bytecode && runs) || (options.trace_execution && options.coverage.is_some()) {
+        usage();
+    }
+
+    let mut builder = Lox::builder()
+        .with_options(options.limits)
+        .with_capabilities(options.capabilities)
+        .with_max_nesting_depth(options.max_nesting_depth)
+        .with_dialect(options.dialect.clone())
+        .with_gc(options.gc)
+        .engine(options.engine);
+    if let Some(seed) = options.seed {
+        builder = builder.with_seed(seed);
+    }
+    let mut lox = builder.build();
+    lox.interpreter().set_gc_stress(options.gc_stress);
+    lox.interpreter().set_gc_log(options.gc_log);
+    if options.jit {
+        enable_jit(&mut lox);
+    }
+    if options.profile || options.profile_folded.is_some() {
+        lox.interpreter().enable_profiler();
+    }
+    if options.coverage.is_some() {
+        lox.interpreter().enable_coverage();
+    }
+
+    match paths.as_slice() {
+        [command, path] if command == "compile" => {
+            compile_file(&lox, path, options.output.as_deref())
+        }
+        [command, path] if command == "run" => run_file(&mut lox, path, &options),
+        [command, path] if command == "debug" => debug_file(&mut lox, path),
+        [command] if command == "dap" => serve_dap(&mut lox),
+        [command] if command == "lsp" => serve_lsp(),
+        [command, names @ ..] if command == "bench" => run_benchmarks(names),
+        [command, paths @ ..] if command == "fmt" => format_files(paths, &options),
+        [command, paths @ ..] if command == "lint" => lint_files(paths, &options),
+        [command, paths @ ..] if command == "check" => check_files(paths),
+        [command, path] if command == "test-suite" => run_test_suite(path, &options),
+        [command, path] if command == "transpile" => {
+            transpile_file(&lox, path, options.target, options.output.as_deref())
+        }
+        [] if !options.dump_bytecode && !options.trace_execution => run_prompt(&mut lox),
+        [path] => run_file(&mut lox, path, &options),
+        _ => usage(),
+    }
+}
+
+struct Options {
+    limits: InterpreterOptions,
+    max_nesting_depth: usize,
+    dialect: Dialect,
+    capabilities: Capabilities,
+    seed: Option<u64>,
+    engine: Engine,
+    jit: bool,
+    target: Target,
+    dump_bytecode: bool,
+    diff_bytecode: bool,
+    trace_execution: bool,
+    gc: GcConfig,
+    gc_stress: bool,
+    gc_log: bool,
+    profile: bool,
+    stats: bool,
+    heap_dump: bool,
+    profile_folded: Option<String>,
+    coverage: Option<coverage::Format>,
+    check: bool,
+    fix: bool,
+    differential: bool,
+    width: Option<usize>,
+    output: Option<String>,
+}
+
+#[cfg(feature = "jit")]
+fn enable_jit(lox: &mut Lox) {
+    if let Err(message) = lox.interpreter().enable_jit(DEFAULT_HOT_THRESHOLD) {
+        eprintln!("Cannot enable the JIT: {}", message);
+        process::exit(EXIT_SOFTWARE);
+    }
+}
+
+#[cfg(not(feature = "jit"))]
+fn enable_jit(_: &mut Lox) {
+    eprintln!("--jit requires lox to be built with the jit feature.");
+    process::exit(EXIT_USAGE);
+}
+
+fn parse_limit(value: &str) -> usize {
+    value.parse::<usize>().unwrap_or_else(|_| usage())
+}
+
+fn parse_capabilities(value: &str) -> Capabilities {
+    let mut capabilities = Capabilities::NONE;
+    for name in value.split(',') {
+        capabilities |= Capabilities::from_name(name).unwrap_or_else(|| usage());
+    }
+
+    capabilities
+}
+
+fn usage() -> ! {
+    eprintln!("Usage: lox [compile [-o OUTPUT] | run | transpile [--target=js] [-o OUTPUT] | debug | dap | lsp | bench [WORKLOAD...] | fmt [--check] [--width=N] [FILE...] | lint [--fix] FILE... | check FILE|DIRECTORY... | test-suite [--differential] DIRECTORY] [--max-call-depth=N] [--max-nesting-depth=N] [--dialect=extended|book|strict] [--strict] [--print=statement|function] [--integers] [--max-steps=N] [--max-heap-bytes=N] [--division-by-zero=infinity|error] [--allow=CAPABILITIES] [--allow-exec] [--deny=CAPABILITIES] [--seed=N] [--engine=walker|vm] [--jit] [--dump-bytecode[=diff]] [--trace-execution] [--gc=full|generational] [--nursery-bytes=N] [--gc-stress] [--gc-log] [--profile] [--stats] [--heap-dump-on-exit] [--profile-folded=PATH] [--coverage=lcov|text] [script]");
+    process::exit(EXIT_USAGE);
+}
+
+fn read_source(path: &str) -> String {
+    match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(error) => {
+            eprintln!("Could not read '{}': {}", path, error);
+            process::exit(EXIT_IO);
+        }
+    }
+}
+
+// `lox FILE` and `lox run FILE` alike, for source and compiled files.
+fn run_file(lox: &mut Lox, path: &str, options: &Options) {
+    let script = load_script(path);
+    if options.dump_bytecode {
+        match script {
+            Script::Source(source) if options.diff_bytecode => diff_bytecode(lox, &source),
+            Script::Source(source) => {
+                print!("{}", disassembler::disassemble(&compile(lox, &source)))
+            }
+            // A compiled file has lost the unoptimized bytecode to diff.
+            Script::Compiled(_) if options.diff_bytecode => usage(),
+            Script::Compiled(script) => print!("{}", disassembler::disassemble(&script)),
+        }
+        return;
+    }
+
+    lox.interpreter()
+        .set_trace_execution(options.trace_execution);
+    let result = match script {
+        Script::Source(source) if options.trace_execution => {
+            let script = compile(lox, &source);
+            execute(lox, script)
+        }
+        Script::Source(source) => run(lox, &source),
+        // Coverage comes from the tree-walker, which has no tree to walk.
+        Script::Compiled(_) if options.coverage.is_some() => usage(),
+        Script::Compiled(script) => execute(lox, script),
+    };
+    write_stats(lox, options);
+    write_profile(lox, options);
+    write_coverage(lox, options, path);
+    if let Err(code) = result {
+        process::exit(code);
+    }
+}
+
+enum Script {
+    Source(String),
+    Compiled(Rc<Prototype>),
+}
+
+fn load_script(path: &str) -> Script {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(error) => {
+            eprintln!("Could not read '{}': {}", path, error);
+            process::exit(EXIT_IO);
+        }
+    };
+
+    if !serialize::is_compiled(&bytes) {
+        return Script::Source(read_source(path));
+    }
+
+    match serialize::deserialize(&bytes) {
+        Ok(script) => Script::Compiled(Rc::new(script)),
+        Err(message) => {
+            eprintln!("Could not load '{}': {}", path, message);
+            process::exit(EXIT_DATA);
+        }
+    }
+}
+
+fn compile_source(lox: &Lox, path: &str) -> Rc<Prototype> {
+    compile(lox, &read_source(path))
+}
+
+fn compile(lox: &Lox, source: &str) -> Rc<Prototype> {
+    match lox.compile(source) {
+        Ok(script) => script,
+        Err(errors) => {
+            eprintln!("{}", LoxError::Compile(errors));
+            process::exit(EXIT_DATA);
+        }
+    }
+}
+
+fn diff_bytecode(lox: &Lox, source: &str) {
+    match lox.compile_unoptimized(source) {
+        Ok(script) => {
+            let optimized = optimizer::optimize(&script);
+            print!("{}", disassembler::diff(&script, &optimized));
+        }
+        Err(errors) => {
+            eprintln!("{}", LoxError::Compile(errors));
+            process::exit(EXIT_DATA);
+        }
+    }
+}
+
+fn compile_file(lox: &Lox, path: &str, output: Option<&str>) {
+    let script = compile_source(lox, path);
+    let output = match output {
+        Some(output) => output.to_string(),
+        None => Path::new(path)
+            .with_extension("loxc")
+            .to_string_lossy()
+            .into_owned(),
+    };
+
+    if let Err(error) = fs::write(&output, serialize::serialize(&script)) {
+        eprintln!("Could not write '{}': {}", output, error);
+        process::exit(EXIT_IO);
+    }
+}
+
+fn transpile_file(lox: &Lox, path: &str, target: Target, output: Option<&str>) {
+    let source = read_source(path);
+    let code = match lox.transpile(&source, target) {
+        Ok(code) => code,
+        Err(errors) => {
+            eprintln!("{}", LoxError::Compile(errors));
+            process::exit(EXIT_DATA);
+        }
+    };
+
+    match output {
+        Some(output) => {
+            if let Err(error) = fs::write(output, code) {
+                eprintln!("Could not write '{}': {}", output, error);
+                process::exit(EXIT_IO);
+            }
+        }
+        None => print!("{}", code),
+    }
+}
+
+fn debug_file(lox: &mut Lox, path: &str) {
+    let source = read_source(path);
+
+    if let Err(error) = lox.debug(&source) {
+        if let Err(code) = report(lox, error) {
+            process::exit(code);
+        }
+    }
+}
+
+// Speaks the Debug Adapter Protocol on stdin and stdout; the program to run
+// comes from the client's launch request.
+fn serve_dap(lox: &mut Lox) {
+    if let Err(error) = dap::serve(lox, io::stdin().lock(), io::stdout()) {
+        eprintln!("Debug adapter failed: {}", error);
+        process::exit(EXIT_IO);
+    }
+}
+
+fn serve_lsp() {
+    if let Err(error) = lsp::serve(io::stdin().lock(), io::stdout()) {
+        eprintln!("Language server failed: {}", error);
+        process::exit(EXIT_IO);
+    }
+}
+
+// Rewrites each file in place, or standard input to standard output when
+// there are none, in the style of the nearest .loxfmt.toml. With --check
+// nothing is written; the files that would change are listed and the exit
+// status says whether there were any.
+fn format_files(paths: &[String], options: &Options) {
+    if paths.is_empty() {
+        let mut source = String::new();
+        if let Err(error) = io::Read::read_to_string(&mut io::stdin(), &mut source) {
+            eprintln!("Could not read standard input: {}", error);
+            process::exit(EXIT_IO);
+        }
+
+        let formatted = format_source(&source, &style_for(Path::new("."), options));
+        if options.check {
+            if formatted != source {
+                process::exit(EXIT_FINDINGS);
+            }
+        } else {
+            print!("{}", formatted);
+        }
+        return;
+    }
+
+    let mut unformatted = false;
+    for path in paths {
+        let source = read_source(path);
+        let directory = Path::new(path).parent().unwrap_or(Path::new("."));
+        let formatted = format_source(&source, &style_for(directory, options));
+        if formatted == source {
+            continue;
+        }
+
+        if options.check {
+            println!("{}", path);
+            unformatted = true;
+        } else if let Err(error) = fs::write(path, formatted) {
+            eprintln!("Could not write '{}': {}", path, error);
+            process::exit(EXIT_IO);
+        }
+    }
+
+    if unformatted {
+        process::exit(EXIT_FINDINGS);
+    }
+}
+
+// --width overrides the configured maximum width.
+fn style_for(directory: &Path, options: &Options) -> Style {
+    let mut style = match Style::find(directory) {
+        Some(path) => Style::load(&path).unwrap_or_else(|message| {
+            eprintln!("{}", message);
+            process::exit(EXIT_DATA);
+        }),
+        None => Style::default(),
+    };
+    if let Some(width) = options.width {
+        style.max_width = width;
+    }
+    style
+}
+
+fn format_source(source: &str, style: &Style) -> String {
+    match formatter::format(source, style) {
+        Ok(formatted) => formatted,
+        Err(errors) => {
+            eprintln!("{}", LoxError::Compile(errors));
+            process::exit(EXIT_DATA);
+        }
+    }
+}
+
+// Prints each warning as "path:line:column: warning[code]: message (rule)",
+// with rules chosen by the nearest .loxlint.toml, and exits with status 1 if
+// there were any. With --fix each file is first rewritten with every fix
+// applied, and only the warnings left are printed.
+fn lint_files(paths: &[String], options: &Options) {
+    if paths.is_empty() {
+        usage();
+    }
+
+    let mut warned = false;
+    for path in paths {
+        let source = read_source(path);
+        let directory = Path::new(path).parent().unwrap_or(Path::new("."));
+        let config = match Config::find(directory) {
+            Some(config) => Config::load(&config).unwrap_or_else(|message| {
+                eprintln!("{}", message);
+                process::exit(EXIT_DATA);
+            }),
+            None => Config::default(),
+        };
+
+        let compile_error = |errors| -> ! {
+            eprintln!("{}", LoxError::Compile(errors));
+            process::exit(EXIT_DATA);
+        };
+        let source = if options.fix {
+            let fixed = lint::fix(&source, &config).unwrap_or_else(|errors| compile_error(errors));
+            if fixed != source {
+                if let Err(error) = fs::write(path, &fixed) {
+                    eprintln!("Could not write '{}': {}", path, error);
+                    process::exit(EXIT_IO);
+                }
+            }
+            fixed
+        } else {
+            source
+        };
+
+        let warnings = lint::lint(&source, &config).unwrap_or_else(|errors| compile_error(errors));
+        for warning in warnings {
+            let before: String = source.chars().take(warning.position.start).collect();
+            let column = before
+                .chars()
+                .rev()
+                .take_while(|&character| character != '\n')
+                .count()
+                + 1;
+            println!(
+                "{}:{}:{}: warning[{}]: {} ({})",
+                path,
+                warning.position.line,
+                column,
+                warning.rule.code(),
+                warning.message,
+                warning.rule.name()
+            );
+            warned = true;
+        }
+    }
+
+    if warned {
+        process::exit(EXIT_FINDINGS);
+    }
+}
+
+// Prints each compile error as "path:line: error: message" without running
+// anything, directories standing for the .lox files under them, and exits
+// with status 1 if there were any. Files are checked concurrently but
+// reported in order.
+fn check_files(paths: &[String]) {
+    if paths.is_empty() {
+        usage();
+    }
+
+    let paths: Vec<PathBuf> = paths.iter().map(PathBuf::from).collect();
+    let mut failed = false;
+    let mut unreadable = false;
+    for checked in check::check(&paths) {
+        let path = checked.path.display();
+        match checked.result {
+            Ok(errors) => {
+                for error in errors {
+                    println!("{}:{}: error: {}", path, error.line, error.message);
+                    failed = true;
+                }
+            }
+            Err(error) => {
+                eprintln!("Could not read '{}': {}", path, error);
+                unreadable = true;
+            }
+        }
+    }
+
+    if unreadable {
+        process::exit(EXIT_IO);
+    }
+    if failed {
+        process::exit(EXIT_FINDINGS);
+    }
+}
+
+// Runs the craftinginterpreters test suite, or any directory of tests written
+// in its format, and exits with status 1 if any failed. With --differential
+// the expectations are ignored and each program must instead behave the same
+// on the tree-walker and the VM.
+fn run_test_suite(path: &str, options: &Options) {
+    let summary = if options.differential {
+        suite::differential(Path::new(path))
+    } else {
+        suite::run(Path::new(path), options.engine)
+    };
+    let summary = match summary {
+        Ok(summary) => summary,
+        Err(error) => {
+            eprintln!("Could not read '{}': {}", path, error);
+            process::exit(EXIT_IO);
+        }
+    };
+
+    print!("{}", summary.report());
+    if !summary.failures.is_empty() {
+        process::exit(EXIT_FINDINGS);
+    }
+}
+
+fn run_benchmarks(names: &[String]) {
+    let workloads: Vec<&Workload> = if names.is_empty() {
+        bench::WORKLOADS.iter().collect()
+    } else {
+        names
+            .iter()
+            .map(|name| {
+                bench::workload(name).unwrap_or_else(|| {
+                    eprintln!("Unknown workload '{}'.", name);
+                    process::exit(EXIT_USAGE);
+                })
+            })
+            .collect()
+    };
+
+    println!(
+        "{:<16} {:<10} {:>12} {:>12}",
+        "workload", "engine", "ops/sec", "mean"
+    );
+    for workload in workloads {
+        for &engine in bench::ENGINES {
+            match bench::measure(workload, engine, bench::DEFAULT_BUDGET) {
+                Ok(measurement) => println!(
+                    "{:<16} {:<10} {:>12.2} {:>12}",
+                    workload.name,
+                    engine.name(),
+                    measurement.ops_per_second(),
+                    format!("{:.3?}", measurement.mean())
+                ),
+                Err(error) => {
+                    eprintln!("{} ({}): {}", workload.name, engine.name(), error);
+                    process::exit(EXIT_SOFTWARE);
+                }
+            }
+        }
+    }
+}
+
+// Written to -o when given, otherwise to stderr so it stays apart from the
+// script's own output.
+fn write_coverage(lox: &mut Lox, options: &Options, path: &str) {
+    let (Some(format), Some(coverage)) = (options.coverage, lox.interpreter().take_coverage())
+    else {
+        return;
+    };
+
+    let source = fs::read_to_string(path).unwrap_or_default();
+    let report = coverage.report(format, path, &source);
+    match &options.output {
+        Some(output) => {
+            if let Err(error) = fs::write(output, report) {
+                eprintln!("Could not write '{}': {}", output, error);
+                process::exit(EXIT_IO);
+            }
+        }
+        None => eprint!("{}", report),
+    }
+}
+
+fn execute(lox: &mut Lox, script: Rc<Prototype>) -> Result<(), i32> {
+    match lox.run_script(script) {
+        Ok(()) => Ok(()),
+        Err(error) => report(lox, LoxError::Runtime(error)),
+    }
+}
+
+// On stderr, after whatever the script printed. The heap is collected before
+// it is dumped, so that only what the collector could not free is listed.
+fn write_stats(lox: &mut Lox, options: &Options) {
+    if options.stats {
+        eprint!("{}", lox.metrics().report());
+    }
+    if options.heap_dump {
+        lox.interpreter().collect_garbage();
+        eprint!("{}", lox.interpreter().heap().dump());
+    }
+}
+
+fn write_profile(lox: &mut Lox, options: &Options) {
+    let Some(profile) = lox.interpreter().take_profile() else {
+        return;
+    };
+
+    if options.profile {
+        eprint!("{}", profile.report());
+    }
+    if let Some(path) = &options.profile_folded {
+        if let Err(error) = fs::write(path, profile.folded_stacks()) {
+            eprintln!("Could not write '{}': {}", path, error);
+            process::exit(EXIT_IO);
+        }
+    }
+}
+
+fn run_prompt(lox: &mut Lox) {
+    let stdin = io::stdin();
+
+    loop {
+        print!("> ");
+        let _ = io::stdout().flush();
+
+        let mut line = String::new();
+        match stdin.lock().read_line(&mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {
+                let snapshot = lox.snapshot();
+                if run(lox, &line).is_err() {
+                    lox.restore(&snapshot);
+                }
+            }
+        }
+    }
+}
+
+fn run(lox: &mut Lox, source: &str) -> Result<(), i32> {
+    match lox.run(source) {
+        Ok(()) => Ok(()),
+        Err(error) => report(lox, error),
+    }
+}
+
+fn report(lox: &mut Lox, error: LoxError) -> Result<(), i32> {
+    let code = match &error {
+        LoxError::Compile(_) => EXIT_DATA,
+        LoxError::Runtime(runtime) => match runtime.kind {
+            RuntimeErrorKind::Exit(code) => process::exit(code),
+            RuntimeErrorKind::Error
+            | RuntimeErrorKind::Thrown
+            | RuntimeErrorKind::ResourceExceeded
+            | RuntimeErrorKind::Interrupted => EXIT_SOFTWARE,
+        },
+    };
 
-    println!("{:?}", scanner.scan_tokens());
+    let _ = lox.interpreter().io().write_stderr(&format!("{}\n", error));
+    Err(code)
 }
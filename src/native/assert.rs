@@ -0,0 +1,24 @@
+use crate::environment::Environment;
+use crate::error::RuntimeError;
+use crate::interpreter::Interpreter;
+use crate::prelude::*;
+use crate::value::Value;
+
+use super::define;
+
+pub fn define_natives(environment: &mut Environment) {
+    define(environment, "assert", 2, assert);
+    define(environment, "error", 1, error);
+}
+
+fn assert(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    if arguments[0].is_truthy() {
+        Ok(Value::Nil)
+    } else {
+        Err(interpreter.runtime_error(format!("Assertion failed: {}", arguments[1])))
+    }
+}
+
+fn error(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    Err(interpreter.runtime_error(arguments[0].to_string()))
+}
@@ -0,0 +1,44 @@
+use crate::environment::Environment;
+use crate::error::RuntimeError;
+use crate::interpreter::Interpreter;
+use crate::prelude::*;
+use crate::value::Value;
+
+use super::{define, index_argument, string_argument};
+
+pub fn define_natives(environment: &mut Environment) {
+    define(environment, "number", 1, number);
+    define(environment, "string", 1, string);
+    define(environment, "parseInt", 2, parse_int);
+}
+
+fn number(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let text = string_argument(interpreter, "number", &arguments[0])?.trim();
+
+    let is_numeric = text
+        .chars()
+        .all(|character| character.is_ascii_digit() || "+-.eE".contains(character));
+
+    match text.parse::<f64>() {
+        Ok(number) if is_numeric => Ok(Value::Number(number)),
+        _ => Ok(Value::Nil),
+    }
+}
+
+fn string(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    Ok(Value::String(interpreter.display(&arguments[0])?.into()))
+}
+
+fn parse_int(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let text = string_argument(interpreter, "parseInt", &arguments[0])?.trim();
+    let base = index_argument(interpreter, "parseInt", &arguments[1])?;
+
+    if !(2..=36).contains(&base) {
+        return Err(interpreter.runtime_error("Base must be between 2 and 36.".to_string()));
+    }
+
+    match i64::from_str_radix(text, base as u32) {
+        Ok(number) => Ok(Value::Number(number as f64)),
+        Err(_) => Ok(Value::Nil),
+    }
+}
@@ -0,0 +1,59 @@
+use crate::capability::Capabilities;
+use crate::environment::Environment;
+use crate::error::RuntimeError;
+use crate::interpreter::Interpreter;
+use crate::prelude::*;
+use crate::value::Value;
+
+use super::{define, define_gated, string_argument};
+
+pub fn define_natives(environment: &mut Environment) {
+    define(environment, "readLine", 0, read_line);
+    define_gated(environment, "readFile", 1, Capabilities::FS, read_file);
+    define_gated(environment, "writeFile", 2, Capabilities::FS, write_file);
+    define_gated(environment, "appendFile", 2, Capabilities::FS, append_file);
+}
+
+fn read_line(interpreter: &mut Interpreter, _arguments: &[Value]) -> Result<Value, RuntimeError> {
+    match interpreter.io().read_line() {
+        Ok(Some(line)) => Ok(Value::String(line.into())),
+        Ok(None) => Ok(Value::Nil),
+        Err(error) => Err(interpreter.runtime_error(format!("Could not read line: {}.", error))),
+    }
+}
+
+fn read_file(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let path = string_argument(interpreter, "readFile", &arguments[0])?;
+
+    match interpreter.io().read_file(path) {
+        Ok(contents) => Ok(Value::String(contents.into())),
+        Err(error) => {
+            Err(interpreter.runtime_error(format!("Could not read file '{}': {}.", path, error)))
+        }
+    }
+}
+
+fn write_file(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let path = string_argument(interpreter, "writeFile", &arguments[0])?;
+    let contents = string_argument(interpreter, "writeFile", &arguments[1])?;
+
+    match interpreter.io().write_file(path, contents) {
+        Ok(()) => Ok(Value::Nil),
+        Err(error) => {
+            Err(interpreter.runtime_error(format!("Could not write file '{}': {}.", path, error)))
+        }
+    }
+}
+
+fn append_file(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let path = string_argument(interpreter, "appendFile", &arguments[0])?;
+    let contents = string_argument(interpreter, "appendFile", &arguments[1])?;
+
+    match interpreter.io().append_file(path, contents) {
+        Ok(()) => Ok(Value::Nil),
+        Err(error) => {
+            Err(interpreter
+                .runtime_error(format!("Could not append to file '{}': {}.", path, error)))
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use alloc::collections::BTreeMap;
+
+use crate::environment::Environment;
+use crate::error::RuntimeError;
+use crate::interpreter::Interpreter;
+use crate::json::{self, Json};
+use crate::prelude::*;
+use crate::value::Value;
+
+use super::{define, string_argument};
+
+const MAX_DEPTH: usize = 512;
+
+pub fn define_natives(environment: &mut Environment) {
+    define(environment, "jsonParse", 1, json_parse);
+    define(environment, "jsonStringify", 1, json_stringify);
+}
+
+fn json_parse(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let text = string_argument(interpreter, "jsonParse", &arguments[0])?;
+
+    match json::parse(text) {
+        Ok(json) => Ok(to_value(json)),
+        Err(error) => Err(interpreter.runtime_error(format!("Invalid JSON: {}.", error))),
+    }
+}
+
+fn json_stringify(
+    interpreter: &mut Interpreter,
+    arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    match to_json(&arguments[0], 0) {
+        Ok(json) => Ok(Value::String(json.to_string().into())),
+        Err(message) => Err(interpreter.runtime_error(message)),
+    }
+}
+
+fn to_value(json: Json) -> Value {
+    match json {
+        Json::Null => Value::Nil,
+        Json::Boolean(boolean) => Value::Boolean(boolean),
+        Json::Number(number) => Value::Number(number),
+        Json::String(string) => Value::String(string.into()),
+        Json::Array(elements) => {
+            Value::from(elements.into_iter().map(to_value).collect::<Vec<Value>>())
+        }
+        Json::Object(entries) => Value::from(
+            entries
+                .into_iter()
+                .map(|(key, value)| (key, to_value(value)))
+                .collect::<BTreeMap<String, Value>>(),
+        ),
+    }
+}
+
+fn to_json(value: &Value, depth: usize) -> Result<Json, String> {
+    if depth > MAX_DEPTH {
+        return Err("Value is nested too deeply to encode as JSON.".to_string());
+    }
+
+    match value {
+        Value::Nil => Ok(Json::Null),
+        Value::Boolean(boolean) => Ok(Json::Boolean(*boolean)),
+        Value::Number(number) if number.is_finite() => Ok(Json::Number(*number)),
+        Value::Number(_) => Err("Can't encode a non-finite number as JSON.".to_string()),
+        Value::Int(integer) => Ok(Json::Number(*integer as f64)),
+        Value::String(string) => Ok(Json::String(string.to_string())),
+        Value::List(list) => list
+            .borrow()
+            .iter()
+            .map(|element| to_json(element, depth + 1))
+            .collect::<Result<Vec<Json>, String>>()
+            .map(Json::Array),
+        Value::Map(map) => map
+            .borrow()
+            .iter()
+            .map(|(key, value)| Ok((key.clone(), to_json(value, depth + 1)?)))
+            .collect::<Result<Vec<(String, Json)>, String>>()
+            .map(Json::Object),
+        other => Err(format!("Can't encode a {} as JSON.", other.type_name())),
+    }
+}
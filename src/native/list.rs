@@ -0,0 +1,174 @@
+use core::cmp::Ordering;
+use core::mem;
+
+use crate::environment::Environment;
+use crate::error::{ErrorCategory, RuntimeError};
+use crate::interpreter::Interpreter;
+use crate::prelude::*;
+use crate::value::Value;
+
+use super::{define, index_argument, list_argument};
+
+pub fn define_natives(environment: &mut Environment) {
+    define(environment, "map", 2, map);
+    define(environment, "filter", 2, filter);
+    define(environment, "reduce", 3, reduce);
+    define(environment, "sort", 2, sort);
+    define(environment, "push", 2, push);
+    define(environment, "pop", 1, pop);
+    define(environment, "insert", 3, insert);
+    define(environment, "remove", 2, remove);
+}
+
+fn map(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let elements = list_argument(interpreter, "map", &arguments[1])?
+        .borrow()
+        .clone();
+
+    let mut mapped: Vec<Value> = Vec::with_capacity(elements.len());
+    for element in elements {
+        mapped.push(interpreter.call(arguments[0].clone(), vec![element])?);
+    }
+
+    Ok(Value::from(mapped))
+}
+
+fn filter(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let elements = list_argument(interpreter, "filter", &arguments[1])?
+        .borrow()
+        .clone();
+
+    let mut filtered: Vec<Value> = Vec::new();
+    for element in elements {
+        if interpreter
+            .call(arguments[0].clone(), vec![element.clone()])?
+            .is_truthy()
+        {
+            filtered.push(element);
+        }
+    }
+
+    Ok(Value::from(filtered))
+}
+
+fn reduce(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let elements = list_argument(interpreter, "reduce", &arguments[2])?
+        .borrow()
+        .clone();
+
+    let mut accumulator = arguments[1].clone();
+    for element in elements {
+        accumulator = interpreter.call(arguments[0].clone(), vec![accumulator, element])?;
+    }
+
+    Ok(accumulator)
+}
+
+fn sort(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let list = list_argument(interpreter, "sort", &arguments[0])?;
+    let elements = list.borrow().clone();
+
+    let sorted = merge_sort(interpreter, &arguments[1], elements)?;
+    *list.borrow_mut() = sorted;
+
+    Ok(arguments[0].clone())
+}
+
+fn merge_sort(
+    interpreter: &mut Interpreter,
+    comparator: &Value,
+    mut elements: Vec<Value>,
+) -> Result<Vec<Value>, RuntimeError> {
+    if elements.len() <= 1 {
+        return Ok(elements);
+    }
+
+    let right = elements.split_off(elements.len() / 2);
+    let left = merge_sort(interpreter, comparator, elements)?;
+    let right = merge_sort(interpreter, comparator, right)?;
+
+    let mut merged: Vec<Value> = Vec::with_capacity(left.len() + right.len());
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+
+    while let (Some(first), Some(second)) = (left.peek(), right.peek()) {
+        let ordering = compare(interpreter, comparator, first.clone(), second.clone())?;
+        let next = if ordering == Ordering::Greater {
+            right.next()
+        } else {
+            left.next()
+        };
+
+        merged.extend(next);
+    }
+
+    merged.extend(left);
+    merged.extend(right);
+
+    Ok(merged)
+}
+
+fn compare(
+    interpreter: &mut Interpreter,
+    comparator: &Value,
+    left: Value,
+    right: Value,
+) -> Result<Ordering, RuntimeError> {
+    match interpreter.call(comparator.clone(), vec![left, right])? {
+        Value::Number(number) if number < 0.0 => Ok(Ordering::Less),
+        Value::Number(number) if number > 0.0 => Ok(Ordering::Greater),
+        Value::Number(_) => Ok(Ordering::Equal),
+        Value::Int(integer) => Ok(integer.cmp(&0)),
+        _ => Err(interpreter
+            .runtime_error("Comparator must return a number.".to_string())
+            .with_category(ErrorCategory::Type)),
+    }
+}
+
+fn push(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let list = list_argument(interpreter, "push", &arguments[0])?;
+    interpreter.allocate(mem::size_of::<Value>())?;
+    list.borrow_mut().push(arguments[1].clone());
+    interpreter.grow(mem::size_of::<Value>());
+
+    Ok(Value::Nil)
+}
+
+fn pop(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let list = list_argument(interpreter, "pop", &arguments[0])?;
+    let element = list.borrow_mut().pop();
+
+    element.ok_or_else(|| interpreter.runtime_error("Can't pop from an empty list.".to_string()))
+}
+
+fn insert(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let list = list_argument(interpreter, "insert", &arguments[0])?;
+    let index = index_argument(interpreter, "insert", &arguments[1])?;
+
+    if index > list.borrow().len() {
+        return Err(interpreter
+            .runtime_error("List index out of range.".to_string())
+            .with_category(ErrorCategory::Index));
+    }
+
+    interpreter.allocate(mem::size_of::<Value>())?;
+    list.borrow_mut().insert(index, arguments[2].clone());
+    interpreter.grow(mem::size_of::<Value>());
+
+    Ok(Value::Nil)
+}
+
+fn remove(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let list = list_argument(interpreter, "remove", &arguments[0])?;
+    let index = index_argument(interpreter, "remove", &arguments[1])?;
+
+    if index >= list.borrow().len() {
+        return Err(interpreter
+            .runtime_error("List index out of range.".to_string())
+            .with_category(ErrorCategory::Index));
+    }
+
+    let element = list.borrow_mut().remove(index);
+
+    Ok(element)
+}
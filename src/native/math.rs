@@ -0,0 +1,64 @@
+use core::f64::consts::PI;
+
+use crate::environment::Environment;
+use crate::error::RuntimeError;
+use crate::interpreter::Interpreter;
+use crate::value::Value;
+
+use super::{define, number_argument};
+
+pub fn define_natives(environment: &mut Environment) {
+    define(environment, "sqrt", 1, sqrt);
+    define(environment, "abs", 1, abs);
+    define(environment, "floor", 1, floor);
+    define(environment, "ceil", 1, ceil);
+    define(environment, "round", 1, round);
+    define(environment, "min", 2, min);
+    define(environment, "max", 2, max);
+    define(environment, "pow", 2, pow);
+
+    environment.define("pi".to_string(), Value::Number(PI));
+}
+
+fn sqrt(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let number = number_argument(interpreter, "sqrt", &arguments[0])?;
+    Ok(Value::Number(number.sqrt()))
+}
+
+fn abs(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let number = number_argument(interpreter, "abs", &arguments[0])?;
+    Ok(Value::Number(number.abs()))
+}
+
+fn floor(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let number = number_argument(interpreter, "floor", &arguments[0])?;
+    Ok(Value::Number(number.floor()))
+}
+
+fn ceil(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let number = number_argument(interpreter, "ceil", &arguments[0])?;
+    Ok(Value::Number(number.ceil()))
+}
+
+fn round(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let number = number_argument(interpreter, "round", &arguments[0])?;
+    Ok(Value::Number(number.round()))
+}
+
+fn min(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let left = number_argument(interpreter, "min", &arguments[0])?;
+    let right = number_argument(interpreter, "min", &arguments[1])?;
+    Ok(Value::Number(left.min(right)))
+}
+
+fn max(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let left = number_argument(interpreter, "max", &arguments[0])?;
+    let right = number_argument(interpreter, "max", &arguments[1])?;
+    Ok(Value::Number(left.max(right)))
+}
+
+fn pow(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let base = number_argument(interpreter, "pow", &arguments[0])?;
+    let exponent = number_argument(interpreter, "pow", &arguments[1])?;
+    Ok(Value::Number(base.powf(exponent)))
+}
@@ -0,0 +1,140 @@
+mod assert;
+mod conversion;
+mod file;
+mod json;
+mod list;
+#[cfg(feature = "std")]
+mod math;
+mod output;
+#[cfg(feature = "std")]
+mod process;
+mod random;
+mod reflection;
+mod string;
+#[cfg(feature = "std")]
+mod time;
+
+use core::cell::RefCell;
+
+use crate::capability::Capabilities;
+use crate::environment::Environment;
+use crate::error::{ErrorCategory, RuntimeError};
+use crate::interpreter::Interpreter;
+use crate::prelude::*;
+use crate::value::{Native, Value};
+
+pub fn define_natives(environment: &mut Environment) {
+    #[cfg(feature = "std")]
+    time::define_natives(environment);
+    #[cfg(feature = "std")]
+    math::define_natives(environment);
+    random::define_natives(environment);
+    string::define_natives(environment);
+    conversion::define_natives(environment);
+    file::define_natives(environment);
+    assert::define_natives(environment);
+    reflection::define_natives(environment);
+    list::define_natives(environment);
+    json::define_natives(environment);
+    #[cfg(feature = "std")]
+    process::define_natives(environment);
+}
+
+// `print` and `println` as functions, for dialects without the statement.
+pub fn define_print_natives(environment: &mut Environment) {
+    output::define_natives(environment);
+}
+
+type Builtin = fn(&mut Interpreter, &[Value]) -> Result<Value, RuntimeError>;
+
+fn define(environment: &mut Environment, name: &str, arity: usize, function: Builtin) {
+    let native = Native {
+        name: name.to_string(),
+        arity,
+        function: Rc::new(function),
+    };
+
+    environment.define(name.to_string(), Value::Native(Rc::new(native)));
+}
+
+fn define_gated(
+    environment: &mut Environment,
+    name: &'static str,
+    arity: usize,
+    capability: Capabilities,
+    function: Builtin,
+) {
+    let native = Native {
+        name: name.to_string(),
+        arity,
+        function: Rc::new(move |interpreter: &mut Interpreter, arguments: &[Value]| {
+            if !interpreter.capabilities().contains(capability) {
+                return Err(interpreter.runtime_error(format!(
+                    "'{}' requires the '{}' capability.",
+                    name, capability
+                )));
+            }
+
+            function(interpreter, arguments)
+        }),
+    };
+
+    environment.define(name.to_string(), Value::Native(Rc::new(native)));
+}
+
+fn number_argument(
+    interpreter: &Interpreter,
+    name: &str,
+    argument: &Value,
+) -> Result<f64, RuntimeError> {
+    match argument {
+        Value::Number(number) => Ok(*number),
+        Value::Int(integer) => Ok(*integer as f64),
+        _ => Err(interpreter
+            .runtime_error(format!("Argument to '{}' must be a number.", name))
+            .with_category(ErrorCategory::Type)),
+    }
+}
+
+fn string_argument<'a>(
+    interpreter: &Interpreter,
+    name: &str,
+    argument: &'a Value,
+) -> Result<&'a str, RuntimeError> {
+    match argument {
+        Value::String(string) => Ok(string),
+        _ => Err(interpreter
+            .runtime_error(format!("Argument to '{}' must be a string.", name))
+            .with_category(ErrorCategory::Type)),
+    }
+}
+
+fn index_argument(
+    interpreter: &Interpreter,
+    name: &str,
+    argument: &Value,
+) -> Result<usize, RuntimeError> {
+    match argument {
+        Value::Number(number) if number % 1.0 == 0.0 && *number >= 0.0 => Ok(*number as usize),
+        Value::Int(integer) if *integer >= 0 => Ok(*integer as usize),
+        _ => Err(interpreter
+            .runtime_error(format!(
+                "Argument to '{}' must be a non-negative integer.",
+                name
+            ))
+            .with_category(ErrorCategory::Type)),
+    }
+}
+
+fn list_argument(
+    interpreter: &Interpreter,
+    name: &str,
+    argument: &Value,
+) -> Result<Rc<RefCell<Vec<Value>>>, RuntimeError> {
+    match argument {
+        Value::List(list) => Ok(Rc::clone(list)),
+        _ => Err(interpreter
+            .runtime_error(format!("Argument to '{}' must be a list.", name))
+            .with_category(ErrorCategory::Type)),
+    }
+}
@@ -0,0 +1,31 @@
+use crate::environment::Environment;
+use crate::error::RuntimeError;
+use crate::interpreter::Interpreter;
+use crate::prelude::*;
+use crate::value::Value;
+
+use super::define;
+
+// Only for dialects where `print` is not a statement. Output goes through
+// the host, like the statement's, so embedders can capture or redirect it.
+pub fn define_natives(environment: &mut Environment) {
+    define(environment, "print", 1, print);
+    define(environment, "println", 1, println);
+}
+
+fn print(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let text = interpreter.display(&arguments[0])?;
+    write(interpreter, &text)
+}
+
+fn println(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let text = interpreter.display(&arguments[0])?;
+    write(interpreter, &format!("{}\n", text))
+}
+
+fn write(interpreter: &mut Interpreter, text: &str) -> Result<Value, RuntimeError> {
+    match interpreter.io().write_stdout(text) {
+        Ok(()) => Ok(Value::Nil),
+        Err(error) => Err(interpreter.runtime_error(format!("Could not write output: {}.", error))),
+    }
+}
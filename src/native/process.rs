@@ -0,0 +1,47 @@
+use std::env;
+use std::process::Command;
+
+use crate::capability::Capabilities;
+use crate::environment::Environment;
+use crate::error::{RuntimeError, RuntimeErrorKind};
+use crate::interpreter::Interpreter;
+use crate::value::Value;
+
+use super::{define, define_gated, number_argument, string_argument};
+
+pub fn define_natives(environment: &mut Environment) {
+    define_gated(environment, "getenv", 1, Capabilities::PROCESS, getenv);
+    define(environment, "exit", 1, exit);
+    define_gated(environment, "exec", 1, Capabilities::PROCESS, exec);
+}
+
+fn getenv(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let name = string_argument(interpreter, "getenv", &arguments[0])?;
+
+    match env::var(name) {
+        Ok(value) => Ok(Value::String(value.into())),
+        Err(_) => Ok(Value::Nil),
+    }
+}
+
+fn exit(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let code = number_argument(interpreter, "exit", &arguments[0])?;
+
+    let mut error = interpreter.runtime_error(format!("Exited with code {}.", code));
+    error.kind = RuntimeErrorKind::Exit(code as i32);
+
+    Err(error)
+}
+
+fn exec(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let command = string_argument(interpreter, "exec", &arguments[0])?;
+
+    match Command::new("sh").arg("-c").arg(command).output() {
+        Ok(output) => Ok(Value::String(
+            String::from_utf8_lossy(&output.stdout).into_owned().into(),
+        )),
+        Err(error) => {
+            Err(interpreter.runtime_error(format!("Could not run '{}': {}.", command, error)))
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use crate::capability::Capabilities;
+use crate::environment::Environment;
+use crate::error::{ErrorCategory, RuntimeError};
+use crate::interpreter::Interpreter;
+use crate::prelude::*;
+use crate::value::Value;
+
+use super::{define_gated, number_argument};
+
+const MAX_SAFE_INTEGER: f64 = 9_007_199_254_740_991.0;
+
+pub fn define_natives(environment: &mut Environment) {
+    define_gated(environment, "random", 0, Capabilities::RANDOM, random);
+    define_gated(
+        environment,
+        "randomInt",
+        2,
+        Capabilities::RANDOM,
+        random_int,
+    );
+    define_gated(
+        environment,
+        "seedRandom",
+        1,
+        Capabilities::RANDOM,
+        seed_random,
+    );
+}
+
+fn random(interpreter: &mut Interpreter, _arguments: &[Value]) -> Result<Value, RuntimeError> {
+    Ok(Value::Number(interpreter.random().next_f64()))
+}
+
+fn random_int(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let low = integer_argument(interpreter, "randomInt", &arguments[0])?;
+    let high = integer_argument(interpreter, "randomInt", &arguments[1])?;
+
+    if low > high {
+        return Err(interpreter.runtime_error(
+            "Lower bound of 'randomInt' must not exceed the upper bound.".to_string(),
+        ));
+    }
+
+    Ok(Value::Number(
+        interpreter.random().next_in_range(low, high) as f64
+    ))
+}
+
+fn seed_random(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let seed = integer_argument(interpreter, "seedRandom", &arguments[0])?;
+    interpreter.random().seed(seed as u64);
+
+    Ok(Value::Nil)
+}
+
+fn integer_argument(
+    interpreter: &Interpreter,
+    name: &str,
+    argument: &Value,
+) -> Result<i64, RuntimeError> {
+    let number = number_argument(interpreter, name, argument)?;
+
+    if number % 1.0 == 0.0 && (-MAX_SAFE_INTEGER..=MAX_SAFE_INTEGER).contains(&number) {
+        Ok(number as i64)
+    } else {
+        Err(interpreter
+            .runtime_error(format!("Argument to '{}' must be an integer.", name))
+            .with_category(ErrorCategory::Type))
+    }
+}
@@ -0,0 +1,193 @@
+use crate::environment::Environment;
+use crate::error::{ErrorCategory, RuntimeError};
+use crate::interpreter::Interpreter;
+use crate::prelude::*;
+use crate::value::Value;
+
+use super::{define, string_argument};
+
+pub fn define_natives(environment: &mut Environment) {
+    define(environment, "type", 1, type_of);
+    define(environment, "fields", 1, fields);
+    define(environment, "methods", 1, methods);
+    define(environment, "has", 2, has);
+    define(environment, "delete", 2, delete);
+    define(environment, "arity", 1, arity);
+    define(environment, "name", 1, name);
+    define(environment, "classOf", 1, class_of);
+    define(environment, "className", 1, class_name);
+    define(environment, "superclassOf", 1, superclass_of);
+    define(environment, "stackTrace", 0, stack_trace);
+    #[cfg(debug_assertions)]
+    define(environment, "heapDump", 0, heap_dump);
+}
+
+fn type_of(_interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    Ok(Value::String(arguments[0].type_name().into()))
+}
+
+fn fields(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let Value::Instance(instance) = &arguments[0] else {
+        return Err(interpreter
+            .runtime_error("Argument to 'fields' must be an instance.".to_string())
+            .with_category(ErrorCategory::Type));
+    };
+
+    let mut names: Vec<String> = instance
+        .borrow()
+        .fields
+        .keys()
+        .map(ToString::to_string)
+        .collect();
+    names.sort();
+
+    Ok(Value::from(
+        names
+            .into_iter()
+            .map(|name| Value::String(name.into()))
+            .collect::<Vec<Value>>(),
+    ))
+}
+
+// Whether an instance has the field, or a map the key. An instance's methods
+// don't count: `has` is for probing fields that may never have been set.
+fn has(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let name = string_argument(interpreter, "has", &arguments[1])?;
+    let present = match &arguments[0] {
+        Value::Instance(instance) => instance.borrow().fields.contains_key(name),
+        Value::Map(map) => map.borrow().contains_key(name),
+        _ => {
+            return Err(interpreter
+                .runtime_error("Argument to 'has' must be an instance or a map.".to_string())
+                .with_category(ErrorCategory::Type))
+        }
+    };
+    Ok(Value::Boolean(present))
+}
+
+// Removes a field or a key, returning whether it was there.
+fn delete(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let name = string_argument(interpreter, "delete", &arguments[1])?;
+    let removed = match &arguments[0] {
+        Value::Instance(instance) => instance.borrow_mut().fields.remove(name),
+        Value::Map(map) => map.borrow_mut().remove(name),
+        _ => {
+            return Err(interpreter
+                .runtime_error("Argument to 'delete' must be an instance or a map.".to_string())
+                .with_category(ErrorCategory::Type))
+        }
+    };
+    Ok(Value::Boolean(removed.is_some()))
+}
+
+// How many arguments a function, method or class needs when called, leaving
+// out those with defaults and those a rest parameter gathers.
+fn arity(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let arity = match &arguments[0] {
+        Value::Function(function) => function.arity().required,
+        Value::Closure(closure) => closure.prototype.arity.required,
+        Value::BoundMethod(bound) => bound.method.prototype.arity.required,
+        Value::Native(native) => native.arity,
+        Value::Class(class) => class.arity().required,
+        #[cfg(feature = "regvm")]
+        Value::RegisterFunction(function) => function.arity,
+        _ => return Err(not_callable(interpreter, "arity")),
+    };
+    Ok(Value::Number(arity as f64))
+}
+
+fn name(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let name = match &arguments[0] {
+        Value::Function(function) => function.name(),
+        Value::Closure(closure) => closure.prototype.name.clone(),
+        Value::BoundMethod(bound) => bound.method.prototype.name.clone(),
+        Value::Native(native) => native.name.clone(),
+        Value::Class(class) => class.name.clone(),
+        #[cfg(feature = "regvm")]
+        Value::RegisterFunction(function) => function.name.clone(),
+        _ => return Err(not_callable(interpreter, "name")),
+    };
+    Ok(Value::String(name.into()))
+}
+
+fn not_callable(interpreter: &Interpreter, name: &str) -> RuntimeError {
+    interpreter
+        .runtime_error(format!(
+            "Argument to '{}' must be a function or a class.",
+            name
+        ))
+        .with_category(ErrorCategory::Type)
+}
+
+fn class_of(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let Value::Instance(instance) = &arguments[0] else {
+        return Err(interpreter
+            .runtime_error("Argument to 'classOf' must be an instance.".to_string())
+            .with_category(ErrorCategory::Type));
+    };
+    Ok(Value::Class(Rc::clone(&instance.borrow().class)))
+}
+
+// The name of a class, or of an instance's class.
+fn class_name(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let name = match &arguments[0] {
+        Value::Class(class) => class.name.clone(),
+        Value::Instance(instance) => instance.borrow().class.name.clone(),
+        _ => {
+            return Err(interpreter
+                .runtime_error(
+                    "Argument to 'className' must be a class or an instance.".to_string(),
+                )
+                .with_category(ErrorCategory::Type))
+        }
+    };
+    Ok(Value::String(name.into()))
+}
+
+// Nil for a class that inherits from nothing.
+fn superclass_of(
+    interpreter: &mut Interpreter,
+    arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    let Value::Class(class) = &arguments[0] else {
+        return Err(interpreter
+            .runtime_error("Argument to 'superclassOf' must be a class.".to_string())
+            .with_category(ErrorCategory::Type));
+    };
+    Ok(class
+        .superclass
+        .as_ref()
+        .map_or(Value::Nil, |superclass| Value::Class(Rc::clone(superclass))))
+}
+
+// The frames calling `stackTrace()`, innermost first, each a map with the
+// function's name and line.
+fn stack_trace(interpreter: &mut Interpreter, _arguments: &[Value]) -> Result<Value, RuntimeError> {
+    Ok(interpreter.stack_trace(interpreter.current_line()))
+}
+
+// Lists the heap as it stands on stderr, for debugging the collector.
+#[cfg(debug_assertions)]
+fn heap_dump(interpreter: &mut Interpreter, _arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let dump = interpreter.heap().dump();
+    interpreter.io().write_stderr(&dump).map_err(|error| {
+        interpreter.runtime_error(format!("Could not write output: {}.", error))
+    })?;
+    Ok(Value::Nil)
+}
+
+fn methods(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let Value::Class(class) = &arguments[0] else {
+        return Err(interpreter
+            .runtime_error("Argument to 'methods' must be a class.".to_string())
+            .with_category(ErrorCategory::Type));
+    };
+
+    Ok(Value::from(
+        class
+            .method_names()
+            .into_iter()
+            .map(|name| Value::String(name.into()))
+            .collect::<Vec<Value>>(),
+    ))
+}
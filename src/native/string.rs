@@ -0,0 +1,123 @@
+use crate::environment::Environment;
+use crate::error::{ErrorCategory, RuntimeError};
+use crate::interpreter::Interpreter;
+use crate::prelude::*;
+use crate::value::Value;
+
+use super::{define, index_argument, string_argument};
+
+pub fn define_natives(environment: &mut Environment) {
+    define(environment, "len", 1, len);
+    define(environment, "substring", 3, substring);
+    define(environment, "upper", 1, upper);
+    define(environment, "lower", 1, lower);
+    define(environment, "trim", 1, trim);
+    define(environment, "split", 2, split);
+    define(environment, "contains", 2, contains);
+    define(environment, "indexOf", 2, index_of);
+    define(environment, "chr", 1, chr);
+    define(environment, "ord", 1, ord);
+}
+
+// Characters in a string, elements in a list or entries in a map.
+fn len(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let length = match &arguments[0] {
+        Value::String(string) => string.chars().count(),
+        Value::List(list) => list.borrow().len(),
+        Value::Map(map) => map.borrow().len(),
+        _ => {
+            return Err(interpreter
+                .runtime_error("Argument to 'len' must be a string, list or map.".to_string())
+                .with_category(ErrorCategory::Type))
+        }
+    };
+    Ok(Value::Number(length as f64))
+}
+
+fn substring(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let string = string_argument(interpreter, "substring", &arguments[0])?;
+    let start = index_argument(interpreter, "substring", &arguments[1])?;
+    let end = index_argument(interpreter, "substring", &arguments[2])?;
+
+    if start > end || end > string.chars().count() {
+        return Err(interpreter
+            .runtime_error("Substring range out of bounds.".to_string())
+            .with_category(ErrorCategory::Index));
+    }
+
+    let substring: String = string.chars().skip(start).take(end - start).collect();
+    Ok(Value::String(substring.into()))
+}
+
+fn upper(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let string = string_argument(interpreter, "upper", &arguments[0])?;
+    Ok(Value::String(string.to_uppercase().into()))
+}
+
+fn lower(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let string = string_argument(interpreter, "lower", &arguments[0])?;
+    Ok(Value::String(string.to_lowercase().into()))
+}
+
+fn trim(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let string = string_argument(interpreter, "trim", &arguments[0])?;
+    Ok(Value::String(string.trim().into()))
+}
+
+fn split(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let string = string_argument(interpreter, "split", &arguments[0])?;
+    let separator = string_argument(interpreter, "split", &arguments[1])?;
+
+    let parts: Vec<Value> = if separator.is_empty() {
+        string
+            .chars()
+            .map(|character| Value::String(character.to_string().into()))
+            .collect()
+    } else {
+        string
+            .split(separator)
+            .map(|part| Value::String(part.into()))
+            .collect()
+    };
+
+    Ok(Value::from(parts))
+}
+
+fn contains(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let string = string_argument(interpreter, "contains", &arguments[0])?;
+    let needle = string_argument(interpreter, "contains", &arguments[1])?;
+    Ok(Value::Boolean(string.contains(needle)))
+}
+
+fn index_of(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let string = string_argument(interpreter, "indexOf", &arguments[0])?;
+    let needle = string_argument(interpreter, "indexOf", &arguments[1])?;
+
+    let index = match string.find(needle) {
+        Some(offset) => string[..offset].chars().count() as f64,
+        None => -1.0,
+    };
+
+    Ok(Value::Number(index))
+}
+
+fn chr(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let code = index_argument(interpreter, "chr", &arguments[0])?;
+
+    match u32::try_from(code).ok().and_then(char::from_u32) {
+        Some(character) => Ok(Value::String(character.to_string().into())),
+        None => Err(interpreter.runtime_error(format!("Invalid character code {}.", code))),
+    }
+}
+
+fn ord(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let string = string_argument(interpreter, "ord", &arguments[0])?;
+
+    let mut characters = string.chars();
+    match (characters.next(), characters.next()) {
+        (Some(character), None) => Ok(Value::Number(character as u32 as f64)),
+        _ => Err(interpreter
+            .runtime_error("Argument to 'ord' must be a single character.".to_string())
+            .with_category(ErrorCategory::Type)),
+    }
+}
@@ -0,0 +1,115 @@
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::capability::Capabilities;
+use crate::environment::Environment;
+use crate::error::{ErrorCategory, RuntimeError};
+use crate::interpreter::Interpreter;
+use crate::value::Value;
+
+use super::{define_gated, number_argument, string_argument};
+
+const SECONDS_PER_DAY: u64 = 86_400;
+
+pub fn define_natives(environment: &mut Environment) {
+    define_gated(environment, "clock", 0, Capabilities::TIME, clock);
+    define_gated(
+        environment,
+        "timeMillis",
+        0,
+        Capabilities::TIME,
+        time_millis,
+    );
+    define_gated(environment, "sleep", 1, Capabilities::TIME, sleep);
+    define_gated(
+        environment,
+        "dateString",
+        1,
+        Capabilities::TIME,
+        date_string,
+    );
+}
+
+fn clock(_interpreter: &mut Interpreter, _arguments: &[Value]) -> Result<Value, RuntimeError> {
+    Ok(Value::Number(since_epoch().as_secs_f64()))
+}
+
+fn time_millis(
+    _interpreter: &mut Interpreter,
+    _arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    Ok(Value::Number(since_epoch().as_millis() as f64))
+}
+
+fn sleep(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let seconds = number_argument(interpreter, "sleep", &arguments[0])?;
+
+    match Duration::try_from_secs_f64(seconds) {
+        Ok(duration) => {
+            thread::sleep(duration);
+            Ok(Value::Nil)
+        }
+        Err(_) => Err(interpreter
+            .runtime_error("Argument to 'sleep' must be a non-negative number.".to_string())
+            .with_category(ErrorCategory::Type)),
+    }
+}
+
+fn date_string(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let format = string_argument(interpreter, "dateString", &arguments[0])?;
+
+    let seconds = since_epoch().as_secs();
+    let (year, month, day) = civil_from_days(seconds / SECONDS_PER_DAY);
+    let time = seconds % SECONDS_PER_DAY;
+
+    let mut output = String::new();
+    let mut characters = format.chars();
+    while let Some(character) = characters.next() {
+        if character != '%' {
+            output.push(character);
+            continue;
+        }
+
+        match characters.next() {
+            Some('Y') => output.push_str(&format!("{:04}", year)),
+            Some('m') => output.push_str(&format!("{:02}", month)),
+            Some('d') => output.push_str(&format!("{:02}", day)),
+            Some('H') => output.push_str(&format!("{:02}", time / 3600)),
+            Some('M') => output.push_str(&format!("{:02}", time % 3600 / 60)),
+            Some('S') => output.push_str(&format!("{:02}", time % 60)),
+            Some('%') => output.push('%'),
+            Some(other) => {
+                return Err(interpreter
+                    .runtime_error(format!("Unknown date format specifier '%{}'.", other)))
+            }
+            None => output.push('%'),
+        }
+    }
+
+    Ok(Value::String(output.into()))
+}
+
+fn since_epoch() -> Duration {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+
+    (year, month, day)
+}
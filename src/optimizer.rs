@@ -0,0 +1,627 @@
+use alloc::collections::BTreeMap;
+
+use crate::bytecode::{Chunk, Constant, OpCode, Prototype};
+use crate::prelude::*;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    None,
+    Constant(usize),
+    Slot(usize),
+    Byte(u8),
+    Count(u16),
+    Target(usize),
+    Closure {
+        constant: usize,
+        captures: Vec<(u8, u16)>,
+    },
+    Class {
+        constant: usize,
+        methods: u8,
+        inherits: u8,
+    },
+}
+
+#[derive(Debug, Clone)]
+struct Instruction {
+    op: OpCode,
+    operand: Operand,
+    line: usize,
+}
+
+enum Literal {
+    Nil,
+    Boolean(bool),
+    Number(f64),
+    String(Rc<str>),
+}
+
+pub fn optimize(prototype: &Prototype) -> Prototype {
+    let constants: Vec<Constant> = prototype
+        .chunk
+        .constants()
+        .iter()
+        .map(|constant| match constant {
+            Constant::Function(function) => Constant::Function(Rc::new(optimize(function))),
+            constant => constant.clone(),
+        })
+        .collect();
+
+    // Defaults are entered from offsets the rewritten code would move.
+    if !prototype.defaults.is_empty() {
+        return prototype.clone();
+    }
+    let Some(instructions) = decode(&prototype.chunk, &constants) else {
+        return prototype.clone();
+    };
+
+    let mut optimizer = Optimizer {
+        instructions,
+        constants,
+    };
+    while optimizer.pass() {}
+
+    match optimizer.encode() {
+        Some(chunk) => Prototype {
+            name: prototype.name.clone(),
+            arity: prototype.arity,
+            defaults: Vec::new(),
+            upvalue_count: prototype.upvalue_count,
+            is_generator: prototype.is_generator,
+            chunk,
+        },
+        None => prototype.clone(),
+    }
+}
+
+fn decode(chunk: &Chunk, constants: &[Constant]) -> Option<Vec<Instruction>> {
+    let code = chunk.code();
+    let byte = |at: usize| code.get(at).copied();
+    let short = |at: usize| Some(u16::from_be_bytes([byte(at)?, byte(at + 1)?]));
+
+    let mut instructions = Vec::new();
+    let mut offsets = Vec::new();
+    let mut offset = 0;
+    while offset < code.len() {
+        let op = OpCode::try_from(code[offset]).ok()?;
+        let constant = |at: usize| -> Option<(usize, usize)> {
+            if op.is_long() {
+                Some((
+                    u32::from_be_bytes([0, byte(at)?, byte(at + 1)?, byte(at + 2)?]) as usize,
+                    3,
+                ))
+            } else {
+                Some((byte(at)? as usize, 1))
+            }
+        };
+
+        let (operand, length) = match op.short() {
+            OpCode::Constant
+            | OpCode::DefineGlobal
+            | OpCode::GetGlobal
+            | OpCode::SetGlobal
+            | OpCode::GetProperty
+            | OpCode::SetProperty
+            | OpCode::GetSuper => {
+                let (index, length) = constant(offset + 1)?;
+                (Operand::Constant(index), length)
+            }
+            OpCode::GetLocal | OpCode::SetLocal if op.is_long() => {
+                (Operand::Slot(short(offset + 1)? as usize), 2)
+            }
+            OpCode::GetLocal | OpCode::SetLocal => (Operand::Slot(byte(offset + 1)? as usize), 1),
+            OpCode::GetUpvalue | OpCode::SetUpvalue | OpCode::Call => {
+                (Operand::Byte(byte(offset + 1)?), 1)
+            }
+            OpCode::BuildList | OpCode::BuildMap | OpCode::ExpectValues => {
+                (Operand::Count(short(offset + 1)?), 2)
+            }
+            OpCode::Jump | OpCode::JumpIfFalse | OpCode::Next => {
+                let jump = short(offset + 1)? as usize;
+                (Operand::Target(offset + 3 + jump), 2)
+            }
+            OpCode::Loop => {
+                let jump = short(offset + 1)? as usize;
+                (Operand::Target((offset + 3).checked_sub(jump)?), 2)
+            }
+            OpCode::Closure => {
+                let (index, mut length) = constant(offset + 1)?;
+                let Some(Constant::Function(function)) = constants.get(index) else {
+                    return None;
+                };
+
+                let mut captures = Vec::new();
+                for _ in 0..function.upvalue_count {
+                    let at = offset + 1 + length;
+                    captures.push((byte(at)?, short(at + 1)?));
+                    length += 3;
+                }
+
+                (
+                    Operand::Closure {
+                        constant: index,
+                        captures,
+                    },
+                    length,
+                )
+            }
+            OpCode::Class => {
+                let (index, length) = constant(offset + 1)?;
+                (
+                    Operand::Class {
+                        constant: index,
+                        methods: byte(offset + 1 + length)?,
+                        inherits: byte(offset + 2 + length)?,
+                    },
+                    length + 2,
+                )
+            }
+            // A handler is only reached by an error, which the optimizer
+            // can't follow.
+            OpCode::Try => return None,
+            _ => (Operand::None, 0),
+        };
+
+        let op = match op.short() {
+            OpCode::Loop => OpCode::Jump,
+            op => op,
+        };
+        instructions.push(Instruction {
+            op,
+            operand,
+            line: chunk.line(offset),
+        });
+        offsets.push(offset);
+        offset += 1 + length;
+    }
+
+    for instruction in &mut instructions {
+        if let Operand::Target(target) = &mut instruction.operand {
+            *target = match offsets.binary_search(target) {
+                Ok(index) => index,
+                Err(index) if *target == code.len() => index,
+                Err(_) => return None,
+            };
+        }
+    }
+
+    Some(instructions)
+}
+
+struct Optimizer {
+    instructions: Vec<Instruction>,
+    constants: Vec<Constant>,
+}
+
+impl Optimizer {
+    fn pass(&mut self) -> bool {
+        let threaded = self.thread_jumps();
+        let folded = self.fold_constants();
+        let paired = self.remove_push_pop_pairs();
+        let jumps = self.remove_jumps_to_next();
+        let unreachable = self.remove_unreachable();
+
+        threaded || folded || paired || jumps || unreachable
+    }
+
+    fn thread_jumps(&mut self) -> bool {
+        let mut changed = false;
+
+        for index in 0..self.instructions.len() {
+            let Operand::Target(original) = self.instructions[index].operand else {
+                continue;
+            };
+            let op = self.instructions[index].op;
+
+            let mut target = original;
+            for _ in 0..self.instructions.len() {
+                let Some(next) = self.instructions.get(target) else {
+                    break;
+                };
+                let Operand::Target(next_target) = next.operand else {
+                    break;
+                };
+
+                let follows = next.op == OpCode::Jump
+                    || (op == OpCode::JumpIfFalse && next.op == OpCode::JumpIfFalse);
+                let backwards = op == OpCode::JumpIfFalse && next_target <= index;
+                if !follows || backwards || next_target == target {
+                    break;
+                }
+
+                target = next_target;
+            }
+
+            if target != original {
+                self.instructions[index].operand = Operand::Target(target);
+                changed = true;
+            }
+        }
+
+        changed
+    }
+
+    fn fold_constants(&mut self) -> bool {
+        let targets = self.jump_targets();
+        let mut keep = vec![true; self.instructions.len()];
+        let mut changed = false;
+
+        let mut index = 0;
+        while index < self.instructions.len() {
+            if let Some(folded) = self.fold_binary(index, &targets) {
+                self.instructions[index] = folded;
+                keep[index + 1] = false;
+                keep[index + 2] = false;
+                changed = true;
+                index += 3;
+            } else if let Some(folded) = self.fold_unary(index, &targets) {
+                self.instructions[index] = folded;
+                keep[index + 1] = false;
+                changed = true;
+                index += 2;
+            } else {
+                index += 1;
+            }
+        }
+
+        if changed {
+            self.remove(&keep);
+        }
+        changed
+    }
+
+    fn fold_binary(&mut self, index: usize, targets: &[bool]) -> Option<Instruction> {
+        let operator = self.instructions.get(index + 2)?;
+        if targets[index + 1] || targets[index + 2] {
+            return None;
+        }
+
+        let left = self.literal(&self.instructions[index])?;
+        let right = self.literal(&self.instructions[index + 1])?;
+        let line = operator.line;
+
+        let result = match (operator.op, left, right) {
+            (OpCode::Equal, left, right) => Literal::Boolean(match (left, right) {
+                (Literal::Nil, Literal::Nil) => true,
+                (Literal::Boolean(left), Literal::Boolean(right)) => left == right,
+                (Literal::Number(left), Literal::Number(right)) => left == right,
+                (Literal::String(left), Literal::String(right)) => left == right,
+                _ => false,
+            }),
+            (op, Literal::Number(left), Literal::Number(right)) => match op {
+                OpCode::Add => Literal::Number(left + right),
+                OpCode::Subtract => Literal::Number(left - right),
+                OpCode::Multiply => Literal::Number(left * right),
+                // Whether dividing by zero is an error is only known at run time.
+                OpCode::Divide if right != 0.0 => Literal::Number(left / right),
+                OpCode::Greater => Literal::Boolean(left > right),
+                OpCode::GreaterEqual => Literal::Boolean(left >= right),
+                OpCode::Less => Literal::Boolean(left < right),
+                OpCode::LessEqual => Literal::Boolean(left <= right),
+                _ => return None,
+            },
+            _ => return None,
+        };
+
+        Some(self.load(result, line))
+    }
+
+    fn fold_unary(&mut self, index: usize, targets: &[bool]) -> Option<Instruction> {
+        let operator = self.instructions.get(index + 1)?;
+        if targets[index + 1] {
+            return None;
+        }
+
+        let operand = self.literal(&self.instructions[index])?;
+        let line = operator.line;
+
+        let result = match (operator.op, operand) {
+            (OpCode::Not, Literal::Nil | Literal::Boolean(false)) => Literal::Boolean(true),
+            (OpCode::Not, _) => Literal::Boolean(false),
+            (OpCode::Negate, Literal::Number(number)) => Literal::Number(-number),
+            _ => return None,
+        };
+
+        Some(self.load(result, line))
+    }
+
+    fn literal(&self, instruction: &Instruction) -> Option<Literal> {
+        match (instruction.op, &instruction.operand) {
+            (OpCode::Nil, _) => Some(Literal::Nil),
+            (OpCode::True, _) => Some(Literal::Boolean(true)),
+            (OpCode::False, _) => Some(Literal::Boolean(false)),
+            (OpCode::Constant, Operand::Constant(index)) => match &self.constants[*index] {
+                Constant::Number(number) => Some(Literal::Number(*number)),
+                Constant::String(string) => Some(Literal::String(string.clone())),
+                // Folding as floats would change integer results.
+                Constant::Integer(_) | Constant::Function(_) => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn load(&mut self, literal: Literal, line: usize) -> Instruction {
+        let (op, operand) = match literal {
+            Literal::Nil => (OpCode::Nil, Operand::None),
+            Literal::Boolean(true) => (OpCode::True, Operand::None),
+            Literal::Boolean(false) => (OpCode::False, Operand::None),
+            Literal::Number(number) => (
+                OpCode::Constant,
+                Operand::Constant(self.add_constant(Constant::Number(number))),
+            ),
+            Literal::String(string) => (
+                OpCode::Constant,
+                Operand::Constant(self.add_constant(Constant::String(string))),
+            ),
+        };
+
+        Instruction { op, operand, line }
+    }
+
+    fn add_constant(&mut self, constant: Constant) -> usize {
+        match self
+            .constants
+            .iter()
+            .position(|existing| existing.is_same(&constant))
+        {
+            Some(index) => index,
+            None => {
+                self.constants.push(constant);
+                self.constants.len() - 1
+            }
+        }
+    }
+
+    fn remove_push_pop_pairs(&mut self) -> bool {
+        let targets = self.jump_targets();
+        let mut keep = vec![true; self.instructions.len()];
+        let mut changed = false;
+
+        let mut index = 0;
+        while index + 1 < self.instructions.len() {
+            let pure = matches!(
+                self.instructions[index].op,
+                OpCode::Constant
+                    | OpCode::Nil
+                    | OpCode::True
+                    | OpCode::False
+                    | OpCode::GetLocal
+                    | OpCode::GetUpvalue
+            );
+
+            if pure && self.instructions[index + 1].op == OpCode::Pop && !targets[index + 1] {
+                keep[index] = false;
+                keep[index + 1] = false;
+                changed = true;
+                index += 2;
+            } else {
+                index += 1;
+            }
+        }
+
+        if changed {
+            self.remove(&keep);
+        }
+        changed
+    }
+
+    fn remove_jumps_to_next(&mut self) -> bool {
+        let keep: Vec<bool> = self
+            .instructions
+            .iter()
+            .enumerate()
+            .map(|(index, instruction)| {
+                // `Next` pushes a value when it doesn't jump.
+                instruction.op == OpCode::Next || instruction.operand != Operand::Target(index + 1)
+            })
+            .collect();
+
+        let changed = keep.contains(&false);
+        if changed {
+            self.remove(&keep);
+        }
+        changed
+    }
+
+    fn remove_unreachable(&mut self) -> bool {
+        let mut reachable = vec![false; self.instructions.len()];
+        let mut pending = vec![0];
+
+        while let Some(index) = pending.pop() {
+            if index >= self.instructions.len() || reachable[index] {
+                continue;
+            }
+            reachable[index] = true;
+
+            let instruction = &self.instructions[index];
+            match (instruction.op, &instruction.operand) {
+                (OpCode::Return, _) => {}
+                (OpCode::Jump, Operand::Target(target)) => pending.push(*target),
+                (OpCode::JumpIfFalse | OpCode::Next, Operand::Target(target)) => {
+                    pending.push(*target);
+                    pending.push(index + 1);
+                }
+                _ => pending.push(index + 1),
+            }
+        }
+
+        let changed = reachable.contains(&false);
+        if changed {
+            self.remove(&reachable);
+        }
+        changed
+    }
+
+    fn jump_targets(&self) -> Vec<bool> {
+        let mut targets = vec![false; self.instructions.len() + 1];
+        for instruction in &self.instructions {
+            if let Operand::Target(target) = instruction.operand {
+                targets[target] = true;
+            }
+        }
+
+        targets
+    }
+
+    fn remove(&mut self, keep: &[bool]) {
+        let mut positions = Vec::with_capacity(keep.len() + 1);
+        let mut kept = 0;
+        for &keep in keep {
+            positions.push(kept);
+            if keep {
+                kept += 1;
+            }
+        }
+        positions.push(kept);
+
+        let instructions = core::mem::take(&mut self.instructions);
+        self.instructions = instructions
+            .into_iter()
+            .zip(keep)
+            .filter(|(_, keep)| **keep)
+            .map(|(mut instruction, _)| {
+                if let Operand::Target(target) = &mut instruction.operand {
+                    *target = positions[*target];
+                }
+                instruction
+            })
+            .collect();
+    }
+
+    fn encode(&self) -> Option<Chunk> {
+        let mut chunk = Chunk::new();
+        let mut remapped: BTreeMap<usize, usize> = BTreeMap::new();
+        let mut constant = |chunk: &mut Chunk, index: usize| {
+            *remapped
+                .entry(index)
+                .or_insert_with(|| chunk.add_constant(self.constants[index].clone()))
+        };
+
+        let mut operands = Vec::with_capacity(self.instructions.len());
+        for instruction in &self.instructions {
+            operands.push(match &instruction.operand {
+                Operand::Constant(index) => Operand::Constant(constant(&mut chunk, *index)),
+                Operand::Closure {
+                    constant: index,
+                    captures,
+                } => Operand::Closure {
+                    constant: constant(&mut chunk, *index),
+                    captures: captures.clone(),
+                },
+                Operand::Class {
+                    constant: index,
+                    methods,
+                    inherits,
+                } => Operand::Class {
+                    constant: constant(&mut chunk, *index),
+                    methods: *methods,
+                    inherits: *inherits,
+                },
+                operand => operand.clone(),
+            });
+        }
+
+        let mut offsets = Vec::with_capacity(operands.len() + 1);
+        let mut offset = 0;
+        for operand in &operands {
+            offsets.push(offset);
+            offset += 1 + operand_length(operand);
+        }
+        offsets.push(offset);
+
+        for ((instruction, operand), &offset) in
+            self.instructions.iter().zip(&operands).zip(&offsets)
+        {
+            let line = instruction.line;
+            let constant_op = |chunk: &mut Chunk, index: usize| match u8::try_from(index) {
+                Ok(index) => {
+                    chunk.write_op(instruction.op, line);
+                    chunk.write(index, line);
+                }
+                Err(_) => {
+                    chunk.write_op(instruction.op.long(), line);
+                    chunk.write_u24(index as u32, line);
+                }
+            };
+
+            match operand {
+                Operand::None => chunk.write_op(instruction.op, line),
+                Operand::Constant(index) => constant_op(&mut chunk, *index),
+                Operand::Slot(slot) => match u8::try_from(*slot) {
+                    Ok(slot) => {
+                        chunk.write_op(instruction.op, line);
+                        chunk.write(slot, line);
+                    }
+                    Err(_) => {
+                        chunk.write_op(instruction.op.long(), line);
+                        chunk.write_u16(*slot as u16, line);
+                    }
+                },
+                Operand::Byte(byte) => {
+                    chunk.write_op(instruction.op, line);
+                    chunk.write(*byte, line);
+                }
+                Operand::Count(count) => {
+                    chunk.write_op(instruction.op, line);
+                    chunk.write_u16(*count, line);
+                }
+                Operand::Target(target) => {
+                    let target = offsets[*target];
+                    let (op, jump) = if target >= offset + 3 {
+                        (instruction.op, target - offset - 3)
+                    } else if instruction.op == OpCode::Jump {
+                        (OpCode::Loop, offset + 3 - target)
+                    } else {
+                        return None;
+                    };
+
+                    chunk.write_op(op, line);
+                    chunk.write_u16(u16::try_from(jump).ok()?, line);
+                }
+                Operand::Closure { constant, captures } => {
+                    constant_op(&mut chunk, *constant);
+                    for &(is_local, index) in captures {
+                        chunk.write(is_local, line);
+                        chunk.write_u16(index, line);
+                    }
+                }
+                Operand::Class {
+                    constant,
+                    methods,
+                    inherits,
+                } => {
+                    constant_op(&mut chunk, *constant);
+                    chunk.write(*methods, line);
+                    chunk.write(*inherits, line);
+                }
+            }
+        }
+
+        Some(chunk)
+    }
+}
+
+fn operand_length(operand: &Operand) -> usize {
+    let constant = |index: usize| if index <= u8::MAX as usize { 1 } else { 3 };
+
+    match operand {
+        Operand::None => 0,
+        Operand::Constant(index) => constant(*index),
+        Operand::Slot(slot) => {
+            if *slot <= u8::MAX as usize {
+                1
+            } else {
+                2
+            }
+        }
+        Operand::Byte(_) => 1,
+        Operand::Count(_) | Operand::Target(_) => 2,
+        Operand::Closure {
+            constant: index,
+            captures,
+        } => constant(*index) + captures.len() * 3,
+        Operand::Class {
+            constant: index, ..
+        } => constant(*index) + 2,
+    }
+}
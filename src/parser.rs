@@ -0,0 +1,1071 @@
+use crate::dialect::Dialect;
+use crate::error::{Error, Location};
+use crate::expression::{AstBuilder, Expr, ExprId, ExpressionKind, Literal};
+use crate::prelude::*;
+use crate::statement::{FunctionDeclaration, Pattern, Statement};
+use crate::token::{Keyword, Kind, Position, Token};
+
+const MAX_ARGUMENTS: usize = 255;
+
+pub const DEFAULT_MAX_NESTING_DEPTH: usize = 256;
+// Each link of a left-associative chain like `1 + 1 + …` or `f()()…` makes
+// the tree a level deeper without the parser recursing, and the passes over
+// the tree follow chains without recursing either, so chains get a limit of
+// their own: this many links for each level expressions may nest.
+pub const CHAIN_LINKS_PER_LEVEL: usize = 8;
+
+pub struct Parser {
+    tokens: Vec<Token>,
+    current_position: usize,
+    errors: Vec<Error>,
+    nesting_depth: usize,
+    max_nesting_depth: usize,
+    chain_length: usize,
+    ast: AstBuilder,
+    aborted: bool,
+    function_yields: Option<bool>,
+    dialect: Dialect,
+    // For each error reporting a missing ';', its index among the errors
+    // and the token the ';' belongs after.
+    missing_semicolons: Vec<(usize, Position)>,
+}
+
+impl Parser {
+    pub fn new(tokens: Vec<Token>) -> Parser {
+        Parser {
+            tokens,
+            current_position: 0,
+            errors: Vec::new(),
+            nesting_depth: 0,
+            max_nesting_depth: DEFAULT_MAX_NESTING_DEPTH,
+            chain_length: 0,
+            ast: AstBuilder::new(),
+            aborted: false,
+            function_yields: None,
+            dialect: Dialect::default(),
+            missing_semicolons: Vec::new(),
+        }
+    }
+
+    pub fn set_max_nesting_depth(&mut self, max_nesting_depth: usize) {
+        self.max_nesting_depth = max_nesting_depth;
+    }
+
+    // The dialect the tokens were scanned in, so that errors caused by an
+    // extension keyword it leaves out can say so.
+    pub fn set_dialect(&mut self, dialect: Dialect) {
+        self.dialect = dialect;
+    }
+
+    pub fn parse(&mut self) -> Result<Vec<Rc<Statement>>, Vec<Error>> {
+        let mut statements: Vec<Rc<Statement>> = Vec::new();
+        while !self.finished() {
+            if let Some(statement) = self.declaration() {
+                statements.push(statement);
+            }
+        }
+        self.finish_ast();
+
+        if self.errors.is_empty() {
+            Ok(statements)
+        } else {
+            Err(core::mem::take(&mut self.errors))
+        }
+    }
+
+    pub(crate) fn missing_semicolons(&self) -> &[(usize, Position)] {
+        &self.missing_semicolons
+    }
+
+    pub fn parse_expression(&mut self) -> Result<Expr, Vec<Error>> {
+        let result = self.expression().and_then(|expression| {
+            self.matches(&[Kind::Semicolon]);
+            if self.finished() {
+                Ok(expression)
+            } else {
+                Err(self.build_error(self.peek(), "Expect end of expression.".to_string()))
+            }
+        });
+        self.finish_ast();
+
+        match result {
+            Ok(expression) if self.errors.is_empty() => Ok(expression),
+            Ok(_) => Err(core::mem::take(&mut self.errors)),
+            Err(error) => {
+                self.errors.push(error);
+                Err(core::mem::take(&mut self.errors))
+            }
+        }
+    }
+
+    fn declaration(&mut self) -> Option<Rc<Statement>> {
+        let result = if self.matches_keyword(Keyword::Class) {
+            self.class_declaration()
+        } else if self.matches_keyword(Keyword::Fun) {
+            self.function("function").map(Statement::Function)
+        } else if self.matches_keyword(Keyword::Var) {
+            self.variable_declaration()
+        } else {
+            self.statement()
+        };
+
+        match result {
+            Ok(statement) => Some(Rc::new(statement)),
+            Err(error) => {
+                if self.aborted {
+                    self.current_position = self.tokens.len() - 1;
+                } else {
+                    self.errors.push(error);
+                    self.synchronize();
+                }
+
+                None
+            }
+        }
+    }
+
+    fn class_declaration(&mut self) -> Result<Statement, Error> {
+        let name = self.consume_identifier("Expect class name.")?;
+
+        let superclass = if self.matches(&[Kind::Less]) {
+            let superclass = self.consume_identifier("Expect superclass name.")?;
+            let superclass = self.ast.add(ExpressionKind::Variable(superclass));
+            Some(self.ast.handle(superclass))
+        } else {
+            None
+        };
+
+        self.consume(Kind::OpenCurlyBracket, "Expect '{' before class body.")?;
+
+        let mut methods: Vec<Rc<FunctionDeclaration>> = Vec::new();
+        while !self.check(&Kind::CloseCurlyBracket) && !self.finished() {
+            methods.push(self.function("method")?);
+        }
+
+        self.consume(Kind::CloseCurlyBracket, "Expect '}' after class body.")?;
+
+        Ok(Statement::Class {
+            name,
+            superclass,
+            methods,
+        })
+    }
+
+    fn function(&mut self, kind: &str) -> Result<Rc<FunctionDeclaration>, Error> {
+        let name = self.consume_identifier(&format!("Expect {} name.", kind))?;
+        self.consume(
+            Kind::OpenParenthesis,
+            &format!("Expect '(' after {} name.", kind),
+        )?;
+
+        let mut parameters: Vec<Token> = Vec::new();
+        let mut defaults = Vec::new();
+        let mut rest = None;
+        if !self.check(&Kind::CloseParenthesis) {
+            loop {
+                if parameters.len() >= MAX_ARGUMENTS {
+                    let error = self.build_error(
+                        self.peek(),
+                        "Can't have more than 255 parameters.".to_string(),
+                    );
+                    self.errors.push(error);
+                }
+
+                if self.matches(&[Kind::Ellipsis]) {
+                    rest = Some(self.consume_identifier("Expect parameter name after '...'.")?);
+                    break;
+                }
+
+                let parameter = self.consume_identifier("Expect parameter name.")?;
+                if !self.dialect.is_strict() && self.matches(&[Kind::Equal]) {
+                    defaults.push(self.expression()?);
+                } else if !defaults.is_empty() {
+                    let error = self.build_error(
+                        &parameter,
+                        "Expect a default value after parameters with one.".to_string(),
+                    );
+                    self.errors.push(error);
+                }
+                parameters.push(parameter);
+
+                if !self.matches(&[Kind::Comma]) {
+                    break;
+                }
+            }
+        }
+
+        let message = match rest {
+            Some(_) => "Expect ')' after rest parameter.",
+            None => "Expect ')' after parameters.",
+        };
+        self.consume(Kind::CloseParenthesis, message)?;
+        self.consume(
+            Kind::OpenCurlyBracket,
+            &format!("Expect '{{' before {} body.", kind),
+        )?;
+
+        let enclosing_yields = self.function_yields.replace(false);
+        let body = self.block();
+        let is_generator = self.function_yields == Some(true);
+        self.function_yields = enclosing_yields;
+        let body = body?;
+
+        if is_generator && kind == "method" && name.lexeme() == "init" {
+            return Err(self.build_error(&name, "Can't yield from an initializer.".to_string()));
+        }
+
+        Ok(Rc::new(FunctionDeclaration {
+            name,
+            parameters,
+            defaults,
+            rest,
+            body,
+            is_generator,
+        }))
+    }
+
+    fn variable_declaration(&mut self) -> Result<Statement, Error> {
+        let keyword = self.previous().clone();
+        if !self.dialect.is_strict()
+            && self.matches(&[Kind::OpenSquareBracket, Kind::OpenCurlyBracket])
+        {
+            let (pattern, close, message) = match self.previous().kind {
+                Kind::OpenSquareBracket => (
+                    Pattern::Elements,
+                    Kind::CloseSquareBracket,
+                    "Expect ']' after names.",
+                ),
+                _ => (
+                    Pattern::Fields,
+                    Kind::CloseCurlyBracket,
+                    "Expect '}' after names.",
+                ),
+            };
+            let names = self.names(Vec::new())?;
+            self.consume(close, message)?;
+            return self.destructuring_declaration(keyword, pattern, names);
+        }
+
+        let name = self.consume_identifier("Expect variable name.")?;
+        if !self.dialect.is_strict() && self.matches(&[Kind::Comma]) {
+            let names = self.names(vec![name])?;
+            return self.destructuring_declaration(keyword, Pattern::Values, names);
+        }
+        self.finish_variable_declaration(name)
+    }
+
+    // The names of a destructuring pattern, after any already consumed.
+    fn names(&mut self, mut names: Vec<Token>) -> Result<Vec<Token>, Error> {
+        loop {
+            names.push(self.consume_identifier("Expect variable name.")?);
+            if !self.matches(&[Kind::Comma]) {
+                return Ok(names);
+            }
+        }
+    }
+
+    // The rest of `var [a, b] = value;`, `var {x, y} = value;` or
+    // `var a, b = value;`, from the `=`. The bare form may take several
+    // values, as in `var a, b = 1, 2;`.
+    fn destructuring_declaration(
+        &mut self,
+        keyword: Token,
+        pattern: Pattern,
+        names: Vec<Token>,
+    ) -> Result<Statement, Error> {
+        self.consume(Kind::Equal, "Expect '=' after destructuring pattern.")?;
+        let initializer = match pattern {
+            Pattern::Values => self.values()?,
+            _ => self.expression()?,
+        };
+        self.consume(Kind::Semicolon, "Expect ';' after variable declaration.")?;
+
+        Ok(Statement::Destructure {
+            keyword,
+            pattern,
+            names,
+            initializer,
+        })
+    }
+
+    fn finish_variable_declaration(&mut self, name: Token) -> Result<Statement, Error> {
+        let initializer = if self.matches(&[Kind::Equal]) {
+            Some(self.expression()?)
+        } else {
+            None
+        };
+
+        self.consume(Kind::Semicolon, "Expect ';' after variable declaration.")?;
+
+        Ok(Statement::Variable { name, initializer })
+    }
+
+    fn statement(&mut self) -> Result<Statement, Error> {
+        self.nested("Statement", Parser::unnested_statement)
+    }
+
+    fn unnested_statement(&mut self) -> Result<Statement, Error> {
+        if self.matches_keyword(Keyword::For) {
+            self.for_statement()
+        } else if self.matches_keyword(Keyword::If) {
+            self.if_statement()
+        } else if self.matches_keyword(Keyword::Print) {
+            self.print_statement()
+        } else if self.matches_keyword(Keyword::Return) {
+            self.return_statement()
+        } else if self.matches_keyword(Keyword::While) {
+            self.while_statement()
+        } else if self.matches_keyword(Keyword::Yield) {
+            self.yield_statement()
+        } else if self.matches_keyword(Keyword::Try) {
+            self.try_statement()
+        } else if self.matches_keyword(Keyword::Throw) {
+            self.throw_statement()
+        } else if self.matches_keyword(Keyword::Defer) {
+            self.defer_statement()
+        } else if self.matches(&[Kind::OpenCurlyBracket]) {
+            Ok(Statement::Block(self.block()?))
+        } else {
+            self.expression_statement()
+        }
+    }
+
+    fn for_statement(&mut self) -> Result<Statement, Error> {
+        let keyword = self.previous().clone();
+        self.consume(Kind::OpenParenthesis, "Expect '(' after 'for'.")?;
+
+        let initializer = if self.matches(&[Kind::Semicolon]) {
+            None
+        } else if self.matches_keyword(Keyword::Var) {
+            let name = self.consume_identifier("Expect variable name.")?;
+            if self.matches_keyword(Keyword::In) {
+                return self.for_in_statement(name);
+            }
+
+            Some(self.finish_variable_declaration(name)?)
+        } else {
+            Some(self.expression_statement()?)
+        };
+
+        let condition = if self.check(&Kind::Semicolon) {
+            let condition = self
+                .ast
+                .add(ExpressionKind::Literal(Literal::Boolean(true)));
+            self.ast.handle(condition)
+        } else {
+            self.expression()?
+        };
+        self.consume(Kind::Semicolon, "Expect ';' after loop condition.")?;
+
+        let increment = if self.check(&Kind::CloseParenthesis) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.consume(Kind::CloseParenthesis, "Expect ')' after for clauses.")?;
+
+        let mut body = self.statement()?;
+
+        if let Some(increment) = increment {
+            body = Statement::Block(vec![
+                Rc::new(body),
+                Rc::new(Statement::Expression(increment)),
+            ]);
+        }
+
+        body = Statement::While {
+            keyword,
+            condition,
+            body: Rc::new(body),
+        };
+
+        if let Some(initializer) = initializer {
+            body = Statement::Block(vec![Rc::new(initializer), Rc::new(body)]);
+        }
+
+        Ok(body)
+    }
+
+    fn for_in_statement(&mut self, name: Token) -> Result<Statement, Error> {
+        let iterable = self.expression()?;
+        self.consume(Kind::CloseParenthesis, "Expect ')' after for clauses.")?;
+
+        let body = Rc::new(self.statement()?);
+
+        Ok(Statement::ForIn {
+            name,
+            iterable,
+            body,
+        })
+    }
+
+    fn if_statement(&mut self) -> Result<Statement, Error> {
+        let keyword = self.previous().clone();
+        self.consume(Kind::OpenParenthesis, "Expect '(' after 'if'.")?;
+        let condition = self.expression()?;
+        self.consume(Kind::CloseParenthesis, "Expect ')' after if condition.")?;
+
+        let then_branch = Rc::new(self.statement()?);
+        let else_branch = if self.matches_keyword(Keyword::Else) {
+            Some(Rc::new(self.statement()?))
+        } else {
+            None
+        };
+
+        Ok(Statement::If {
+            keyword,
+            condition,
+            then_branch,
+            else_branch,
+        })
+    }
+
+    fn print_statement(&mut self) -> Result<Statement, Error> {
+        let keyword = self.previous().clone();
+        let value = self.expression()?;
+        self.consume(Kind::Semicolon, "Expect ';' after value.")?;
+
+        Ok(Statement::Print { keyword, value })
+    }
+
+    fn return_statement(&mut self) -> Result<Statement, Error> {
+        let keyword = self.previous().clone();
+
+        let value = if self.check(&Kind::Semicolon) {
+            None
+        } else {
+            Some(self.values()?)
+        };
+        self.consume(Kind::Semicolon, "Expect ';' after return value.")?;
+
+        Ok(Statement::Return { keyword, value })
+    }
+
+    // One value, or several separated by commas that come as a bare list:
+    // `return quotient, remainder;`.
+    fn values(&mut self) -> Result<Expr, Error> {
+        let first = self.subexpression()?;
+        if self.dialect.is_strict() || !self.check(&Kind::Comma) {
+            return Ok(self.ast.handle(first));
+        }
+
+        let comma = self.peek().clone();
+        let mut elements = vec![first];
+        while self.matches(&[Kind::Comma]) {
+            elements.push(self.subexpression()?);
+        }
+        let values = self.ast.add(ExpressionKind::List {
+            bracket: comma,
+            elements,
+        });
+        Ok(self.ast.handle(values))
+    }
+
+    fn yield_statement(&mut self) -> Result<Statement, Error> {
+        let keyword = self.previous().clone();
+
+        let value = if self.check(&Kind::Semicolon) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.consume(Kind::Semicolon, "Expect ';' after yield value.")?;
+
+        match self.function_yields {
+            Some(_) => self.function_yields = Some(true),
+            None => {
+                let error =
+                    self.build_error(&keyword, "Can't yield outside of a function.".to_string());
+                self.errors.push(error);
+            }
+        }
+
+        Ok(Statement::Yield { keyword, value })
+    }
+
+    fn try_statement(&mut self) -> Result<Statement, Error> {
+        let keyword = self.previous().clone();
+        self.consume(Kind::OpenCurlyBracket, "Expect '{' after 'try'.")?;
+        let body = self.block()?;
+
+        self.consume(
+            Kind::Keyword(Keyword::Catch),
+            "Expect 'catch' after try block.",
+        )?;
+        self.consume(Kind::OpenParenthesis, "Expect '(' after 'catch'.")?;
+        let name = self.consume_identifier("Expect error variable name.")?;
+        self.consume(
+            Kind::CloseParenthesis,
+            "Expect ')' after error variable name.",
+        )?;
+        self.consume(Kind::OpenCurlyBracket, "Expect '{' after catch clause.")?;
+        let handler = self.block()?;
+
+        Ok(Statement::Try {
+            keyword,
+            body,
+            name,
+            handler,
+        })
+    }
+
+    fn throw_statement(&mut self) -> Result<Statement, Error> {
+        let keyword = self.previous().clone();
+        let value = self.expression()?;
+        self.consume(Kind::Semicolon, "Expect ';' after thrown value.")?;
+
+        Ok(Statement::Throw { keyword, value })
+    }
+
+    fn defer_statement(&mut self) -> Result<Statement, Error> {
+        let keyword = self.previous().clone();
+        self.consume(Kind::OpenCurlyBracket, "Expect '{' after 'defer'.")?;
+        let body = self.block()?;
+
+        Ok(Statement::Defer { keyword, body })
+    }
+
+    fn while_statement(&mut self) -> Result<Statement, Error> {
+        let keyword = self.previous().clone();
+        self.consume(Kind::OpenParenthesis, "Expect '(' after 'while'.")?;
+        let condition = self.expression()?;
+        self.consume(Kind::CloseParenthesis, "Expect ')' after condition.")?;
+
+        let body = Rc::new(self.statement()?);
+
+        Ok(Statement::While {
+            keyword,
+            condition,
+            body,
+        })
+    }
+
+    fn block(&mut self) -> Result<Vec<Rc<Statement>>, Error> {
+        let mut statements: Vec<Rc<Statement>> = Vec::new();
+        while !self.check(&Kind::CloseCurlyBracket) && !self.finished() {
+            if let Some(statement) = self.declaration() {
+                statements.push(statement);
+            }
+        }
+
+        self.consume(Kind::CloseCurlyBracket, "Expect '}' after block.")?;
+
+        Ok(statements)
+    }
+
+    fn expression_statement(&mut self) -> Result<Statement, Error> {
+        let expression = self.expression()?;
+        self.consume(Kind::Semicolon, "Expect ';' after expression.")?;
+
+        Ok(Statement::Expression(expression))
+    }
+
+    // A whole expression, as statements hold them.
+    fn expression(&mut self) -> Result<Expr, Error> {
+        let expression = self.subexpression()?;
+        Ok(self.ast.handle(expression))
+    }
+
+    fn subexpression(&mut self) -> Result<ExprId, Error> {
+        self.nested("Expression", Parser::assignment)
+    }
+
+    fn assignment(&mut self) -> Result<ExprId, Error> {
+        let expression = self.or()?;
+
+        if self.matches(&[Kind::Equal]) {
+            let equals = self.previous().clone();
+            let value = self.nested("Expression", Parser::assignment)?;
+
+            let target = match &self.ast.get(expression).kind {
+                ExpressionKind::Variable(name) => Some(ExpressionKind::Assign {
+                    name: name.clone(),
+                    value,
+                }),
+                ExpressionKind::Get { object, name } => Some(ExpressionKind::Set {
+                    object: *object,
+                    name: name.clone(),
+                    value,
+                }),
+                ExpressionKind::Index {
+                    object,
+                    bracket,
+                    index,
+                } => Some(ExpressionKind::SetIndex {
+                    object: *object,
+                    bracket: bracket.clone(),
+                    index: *index,
+                    value,
+                }),
+                _ => None,
+            };
+            return match target {
+                Some(kind) => Ok(self.ast.add(kind)),
+                None => {
+                    let error = self.build_error(&equals, "Invalid assignment target.".to_string());
+                    self.errors.push(error);
+                    Ok(expression)
+                }
+            };
+        }
+
+        Ok(expression)
+    }
+
+    fn or(&mut self) -> Result<ExprId, Error> {
+        let mut links = 0;
+        let mut expression = self.and()?;
+
+        while self.matches_keyword(Keyword::Or) {
+            let operator = self.previous().clone();
+            self.link(&mut links)?;
+            let right = self.and()?;
+            expression = self.ast.add(ExpressionKind::Logical {
+                left: expression,
+                operator,
+                right,
+            });
+        }
+
+        self.chain_length -= links;
+
+        Ok(expression)
+    }
+
+    fn and(&mut self) -> Result<ExprId, Error> {
+        let mut links = 0;
+        let mut expression = self.equality()?;
+
+        while self.matches_keyword(Keyword::And) {
+            let operator = self.previous().clone();
+            self.link(&mut links)?;
+            let right = self.equality()?;
+            expression = self.ast.add(ExpressionKind::Logical {
+                left: expression,
+                operator,
+                right,
+            });
+        }
+
+        self.chain_length -= links;
+
+        Ok(expression)
+    }
+
+    fn equality(&mut self) -> Result<ExprId, Error> {
+        let mut links = 0;
+        let mut expression = self.comparison()?;
+
+        while self.matches(&[Kind::ExclamationEqual, Kind::EqualEqual]) {
+            let operator = self.previous().clone();
+            self.link(&mut links)?;
+            let right = self.comparison()?;
+            expression = self.ast.add(ExpressionKind::Binary {
+                left: expression,
+                operator,
+                right,
+            });
+        }
+
+        self.chain_length -= links;
+
+        Ok(expression)
+    }
+
+    fn comparison(&mut self) -> Result<ExprId, Error> {
+        let mut links = 0;
+        let mut expression = self.term()?;
+
+        while self.matches(&[
+            Kind::Greater,
+            Kind::GreaterEqual,
+            Kind::Less,
+            Kind::LessEqual,
+        ]) {
+            let operator = self.previous().clone();
+            self.link(&mut links)?;
+            let right = self.term()?;
+            expression = self.ast.add(ExpressionKind::Binary {
+                left: expression,
+                operator,
+                right,
+            });
+        }
+
+        self.chain_length -= links;
+
+        Ok(expression)
+    }
+
+    fn term(&mut self) -> Result<ExprId, Error> {
+        let mut links = 0;
+        let mut expression = self.factor()?;
+
+        while self.matches(&[Kind::Minus, Kind::Plus]) {
+            let operator = self.previous().clone();
+            self.link(&mut links)?;
+            let right = self.factor()?;
+            expression = self.ast.add(ExpressionKind::Binary {
+                left: expression,
+                operator,
+                right,
+            });
+        }
+
+        self.chain_length -= links;
+
+        Ok(expression)
+    }
+
+    fn factor(&mut self) -> Result<ExprId, Error> {
+        let mut links = 0;
+        let mut expression = self.unary()?;
+
+        while self.matches(&[Kind::Slash, Kind::Asterisk, Kind::Keyword(Keyword::Div)]) {
+            let operator = self.previous().clone();
+            self.link(&mut links)?;
+            let right = self.unary()?;
+            expression = self.ast.add(ExpressionKind::Binary {
+                left: expression,
+                operator,
+                right,
+            });
+        }
+
+        self.chain_length -= links;
+
+        Ok(expression)
+    }
+
+    fn unary(&mut self) -> Result<ExprId, Error> {
+        if self.matches(&[Kind::Exclamation, Kind::Minus]) {
+            let operator = self.previous().clone();
+            let right = self.nested("Expression", Parser::unary)?;
+
+            return Ok(self.ast.add(ExpressionKind::Unary { operator, right }));
+        }
+
+        self.call()
+    }
+
+    fn call(&mut self) -> Result<ExprId, Error> {
+        let mut links = 0;
+        let mut expression = self.primary()?;
+
+        loop {
+            if self.matches(&[Kind::OpenParenthesis]) {
+                self.link(&mut links)?;
+                expression = self.finish_call(expression)?;
+            } else if self.matches(&[Kind::Dot]) {
+                self.link(&mut links)?;
+                let name = self.consume_identifier("Expect property name after '.'.")?;
+                expression = self.ast.add(ExpressionKind::Get {
+                    object: expression,
+                    name,
+                });
+            } else if self.matches(&[Kind::OpenSquareBracket]) {
+                self.link(&mut links)?;
+                let bracket = self.previous().clone();
+                let index = self.subexpression()?;
+                self.consume(Kind::CloseSquareBracket, "Expect ']' after index.")?;
+
+                expression = self.ast.add(ExpressionKind::Index {
+                    object: expression,
+                    bracket,
+                    index,
+                });
+            } else {
+                break;
+            }
+        }
+
+        self.chain_length -= links;
+
+        Ok(expression)
+    }
+
+    fn finish_call(&mut self, callee: ExprId) -> Result<ExprId, Error> {
+        let mut arguments: Vec<ExprId> = Vec::new();
+        if !self.check(&Kind::CloseParenthesis) {
+            loop {
+                if arguments.len() >= MAX_ARGUMENTS {
+                    let error = self.build_error(
+                        self.peek(),
+                        "Can't have more than 255 arguments.".to_string(),
+                    );
+                    self.errors.push(error);
+                }
+
+                arguments.push(self.subexpression()?);
+
+                if !self.matches(&[Kind::Comma]) {
+                    break;
+                }
+            }
+        }
+
+        let parenthesis = self.consume(Kind::CloseParenthesis, "Expect ')' after arguments.")?;
+
+        Ok(self.ast.add(ExpressionKind::Call {
+            callee,
+            parenthesis,
+            arguments,
+        }))
+    }
+
+    fn primary(&mut self) -> Result<ExprId, Error> {
+        let position = self.current_position;
+        let token = self.advance();
+
+        let kind = match &token.kind {
+            Kind::Keyword(Keyword::False) => ExpressionKind::Literal(Literal::Boolean(false)),
+            Kind::Keyword(Keyword::True) => ExpressionKind::Literal(Literal::Boolean(true)),
+            Kind::Keyword(Keyword::Nil) => ExpressionKind::Literal(Literal::Nil),
+            Kind::Number(number) => ExpressionKind::Literal(Literal::Number(*number)),
+            Kind::Integer(integer) => ExpressionKind::Literal(Literal::Integer(*integer)),
+            Kind::String(string) => {
+                ExpressionKind::Literal(Literal::String(string.as_str().into()))
+            }
+            Kind::Keyword(Keyword::This) => ExpressionKind::This(token),
+            Kind::Keyword(Keyword::Super) => {
+                self.consume(Kind::Dot, "Expect '.' after 'super'.")?;
+                let method = self.consume_identifier("Expect superclass method name.")?;
+
+                ExpressionKind::Super {
+                    keyword: token,
+                    method,
+                }
+            }
+            Kind::Identifier(_) => ExpressionKind::Variable(token),
+            Kind::OpenParenthesis => {
+                let expression = self.subexpression()?;
+                self.consume(Kind::CloseParenthesis, "Expect ')' after expression.")?;
+
+                ExpressionKind::Grouping(expression)
+            }
+            Kind::OpenSquareBracket => {
+                let mut elements: Vec<ExprId> = Vec::new();
+                if !self.check(&Kind::CloseSquareBracket) {
+                    loop {
+                        elements.push(self.subexpression()?);
+
+                        if !self.matches(&[Kind::Comma]) {
+                            break;
+                        }
+                    }
+                }
+
+                self.consume(Kind::CloseSquareBracket, "Expect ']' after list elements.")?;
+
+                ExpressionKind::List {
+                    bracket: token,
+                    elements,
+                }
+            }
+            Kind::OpenCurlyBracket => {
+                let mut entries: Vec<(ExprId, ExprId)> = Vec::new();
+                if !self.check(&Kind::CloseCurlyBracket) {
+                    loop {
+                        let key = self.subexpression()?;
+                        self.consume(Kind::Colon, "Expect ':' after map key.")?;
+                        entries.push((key, self.subexpression()?));
+
+                        if !self.matches(&[Kind::Comma]) {
+                            break;
+                        }
+                    }
+                }
+
+                self.consume(Kind::CloseCurlyBracket, "Expect '}' after map entries.")?;
+
+                ExpressionKind::Map {
+                    brace: token,
+                    entries,
+                }
+            }
+            _ => {
+                self.current_position = position;
+                return Err(self.build_error(&token, "Expect expression.".to_string()));
+            }
+        };
+
+        Ok(self.ast.add(kind))
+    }
+
+    // Hands the nodes made so far to their tree, which the statements holding
+    // them can look into from then on.
+    fn finish_ast(&mut self) {
+        core::mem::replace(&mut self.ast, AstBuilder::new()).finish();
+    }
+
+    fn nested<T>(
+        &mut self,
+        construct: &str,
+        parse: fn(&mut Parser) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        if self.nesting_depth >= self.max_nesting_depth {
+            let error = self.build_error(self.peek(), format!("{} too deeply nested.", construct));
+            self.errors.push(error.clone());
+            self.aborted = true;
+
+            return Err(error);
+        }
+
+        // Restoring rather than decrementing also drops the links of any
+        // chain that an error cut short inside `parse`.
+        let (depth, length) = (self.nesting_depth, self.chain_length);
+        self.nesting_depth += 1;
+        let result = parse(self);
+        (self.nesting_depth, self.chain_length) = (depth, length);
+
+        result
+    }
+
+    // Counts one more operator or call of a chain against the chain limit
+    // until the chain ends.
+    fn link(&mut self, links: &mut usize) -> Result<(), Error> {
+        if self.chain_length >= self.max_nesting_depth.saturating_mul(CHAIN_LINKS_PER_LEVEL) {
+            let error = self.build_error(self.peek(), "Expression too deeply nested.".to_string());
+            self.errors.push(error.clone());
+            self.aborted = true;
+
+            return Err(error);
+        }
+
+        self.chain_length += 1;
+        *links += 1;
+
+        Ok(())
+    }
+
+    fn synchronize(&mut self) {
+        self.advance();
+
+        while !self.finished() {
+            if self.previous().kind == Kind::Semicolon {
+                return;
+            }
+
+            if let Kind::Keyword(
+                Keyword::Class
+                | Keyword::Fun
+                | Keyword::Var
+                | Keyword::For
+                | Keyword::If
+                | Keyword::While
+                | Keyword::Print
+                | Keyword::Return
+                | Keyword::Yield
+                | Keyword::Try
+                | Keyword::Throw
+                | Keyword::Defer,
+            ) = self.peek().kind
+            {
+                return;
+            }
+
+            self.advance();
+        }
+    }
+
+    fn consume(&mut self, kind: Kind, message: &str) -> Result<Token, Error> {
+        if self.check(&kind) {
+            Ok(self.advance())
+        } else {
+            // The error is recorded by the enclosing declaration, before any
+            // other.
+            if kind == Kind::Semicolon && self.current_position > 0 {
+                let position = self.previous().position;
+                self.missing_semicolons.push((self.errors.len(), position));
+            }
+            Err(self.build_error(self.peek(), message.to_string()))
+        }
+    }
+
+    fn consume_identifier(&mut self, message: &str) -> Result<Token, Error> {
+        if let Kind::Identifier(_) = self.peek().kind {
+            Ok(self.advance())
+        } else {
+            Err(self.build_error(self.peek(), message.to_string()))
+        }
+    }
+
+    fn matches(&mut self, kinds: &[Kind]) -> bool {
+        if kinds.iter().any(|kind| self.check(kind)) {
+            self.advance();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn matches_keyword(&mut self, keyword: Keyword) -> bool {
+        self.matches(&[Kind::Keyword(keyword)])
+    }
+
+    fn check(&self, kind: &Kind) -> bool {
+        !self.finished() && self.peek().kind == *kind
+    }
+
+    fn advance(&mut self) -> Token {
+        let token = self.peek().clone();
+        if !self.finished() {
+            self.current_position += 1;
+        }
+
+        token
+    }
+
+    fn finished(&self) -> bool {
+        self.peek().kind == Kind::EndOfFile
+    }
+
+    fn peek(&self) -> &Token {
+        &self.tokens[self.current_position]
+    }
+
+    fn previous(&self) -> &Token {
+        &self.tokens[self.current_position - 1]
+    }
+
+    // An error at or just after a word the dialect does not treat as a
+    // keyword is most likely the word being used as one. Strict mode says
+    // only what the book would, and where.
+    fn build_error(&self, token: &Token, mut message: String) -> Error {
+        if self.dialect.is_strict() {
+            let location = match token.kind {
+                Kind::EndOfFile => Location::End,
+                _ => Location::Token(token.lexeme()),
+            };
+            return Error {
+                message,
+                line: token.line(),
+                location: Some(location),
+            };
+        }
+
+        let previous = self
+            .current_position
+            .checked_sub(1)
+            .map(|index| &self.tokens[index]);
+        let disabled = [Some(token), previous]
+            .into_iter()
+            .flatten()
+            .find_map(|token| match &token.kind {
+                Kind::Identifier(name) => self.dialect.disabled(name),
+                _ => None,
+            });
+        if let Some(keyword) = disabled {
+            message.push_str(&format!(
+                " ('{}' is not a keyword in this dialect.)",
+                keyword
+            ));
+        }
+
+        Error {
+            message,
+            line: token.line(),
+            location: None,
+        }
+    }
+}
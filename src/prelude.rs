@@ -0,0 +1,8 @@
+pub use alloc::boxed::Box;
+pub use alloc::format;
+pub use alloc::rc::Rc;
+pub use alloc::string::{String, ToString};
+pub use alloc::vec;
+pub use alloc::vec::Vec;
+
+pub use crate::collections::HashMap;
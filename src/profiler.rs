@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Default)]
+pub struct FunctionProfile {
+    pub name: String,
+    pub calls: u64,
+    pub self_time: Duration,
+    pub total_time: Duration,
+}
+
+struct Entry {
+    function: usize,
+    start: Instant,
+    children: Duration,
+}
+
+// Instruments function entry and exit. Cumulative time is only counted for
+// the outermost activation of a function, so recursion is not double-counted.
+#[derive(Default)]
+pub struct Profiler {
+    functions: Vec<FunctionProfile>,
+    indices: HashMap<String, usize>,
+    active: Vec<u32>,
+    stack: Vec<Entry>,
+    folded: HashMap<Vec<usize>, Duration>,
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler::default()
+    }
+
+    pub fn enter(&mut self, name: &str) {
+        let function = match self.indices.get(name) {
+            Some(&function) => function,
+            None => {
+                let function = self.functions.len();
+                self.functions.push(FunctionProfile {
+                    name: name.to_string(),
+                    ..FunctionProfile::default()
+                });
+                self.active.push(0);
+                self.indices.insert(name.to_string(), function);
+                function
+            }
+        };
+
+        self.functions[function].calls += 1;
+        self.active[function] += 1;
+        self.stack.push(Entry {
+            function,
+            start: Instant::now(),
+            children: Duration::ZERO,
+        });
+    }
+
+    pub fn leave(&mut self) {
+        self.leave_at(Instant::now());
+    }
+
+    // Closes every activation still open, e.g. after a runtime error unwound
+    // the call stack without returning.
+    pub fn finish(&mut self) {
+        let now = Instant::now();
+        while !self.stack.is_empty() {
+            self.leave_at(now);
+        }
+    }
+
+    fn leave_at(&mut self, now: Instant) {
+        let path: Vec<usize> = self.stack.iter().map(|entry| entry.function).collect();
+        let Some(entry) = self.stack.pop() else {
+            return;
+        };
+
+        let elapsed = now.saturating_duration_since(entry.start);
+        let self_time = elapsed.saturating_sub(entry.children);
+        let profile = &mut self.functions[entry.function];
+        profile.self_time += self_time;
+
+        self.active[entry.function] -= 1;
+        if self.active[entry.function] == 0 {
+            profile.total_time += elapsed;
+        }
+
+        *self.folded.entry(path).or_default() += self_time;
+        if let Some(parent) = self.stack.last_mut() {
+            parent.children += elapsed;
+        }
+    }
+
+    // Sorted by self time, most expensive first.
+    pub fn functions(&self) -> Vec<&FunctionProfile> {
+        let mut functions: Vec<&FunctionProfile> = self.functions.iter().collect();
+        functions.sort_by(|a, b| b.self_time.cmp(&a.self_time).then(a.name.cmp(&b.name)));
+        functions
+    }
+
+    pub fn report(&self) -> String {
+        let mut report = format!(
+            "{:<24} {:>10} {:>14} {:>14}\n",
+            "function", "calls", "self", "cumulative"
+        );
+        for function in self.functions() {
+            let _ = writeln!(
+                report,
+                "{:<24} {:>10} {:>14} {:>14}",
+                function.name,
+                function.calls,
+                format!("{:.3?}", function.self_time),
+                format!("{:.3?}", function.total_time)
+            );
+        }
+
+        report
+    }
+
+    // One "outer;inner microseconds" line per distinct call stack, the input
+    // format of flamegraph.pl and inferno.
+    pub fn folded_stacks(&self) -> String {
+        let mut lines: Vec<String> = self
+            .folded
+            .iter()
+            .map(|(path, time)| {
+                let names: Vec<&str> = path
+                    .iter()
+                    .map(|&function| self.functions[function].name.as_str())
+                    .collect();
+                format!("{} {}", names.join(";"), time.as_micros())
+            })
+            .collect();
+        lines.sort();
+
+        let mut folded = lines.join("\n");
+        if !folded.is_empty() {
+            folded.push('\n');
+        }
+        folded
+    }
+}
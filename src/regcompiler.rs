@@ -0,0 +1,743 @@
+use crate::error::Error;
+use crate::expression::{Expr, ExprId, ExpressionKind, Literal};
+use crate::prelude::*;
+use crate::regvm::{Instruction, RegisterFunction};
+use crate::statement::{FunctionDeclaration, Statement};
+use crate::token::{Keyword, Kind, Token};
+use crate::value::Value;
+
+const MAX_REGISTERS: usize = u16::MAX as usize + 1;
+
+pub fn compile(statements: &[Rc<Statement>]) -> Result<Rc<RegisterFunction>, Vec<Error>> {
+    RegisterCompiler::new().compile(statements)
+}
+
+struct Local {
+    name: String,
+    depth: usize,
+    register: u16,
+}
+
+struct FunctionState {
+    function: RegisterFunction,
+    is_script: bool,
+    locals: Vec<Local>,
+    scope_depth: usize,
+    next: usize,
+}
+
+impl FunctionState {
+    fn new(name: String, arity: usize, is_script: bool) -> FunctionState {
+        FunctionState {
+            function: RegisterFunction {
+                name,
+                arity,
+                registers: 1,
+                code: Vec::new(),
+                lines: Vec::new(),
+                constants: Vec::new(),
+            },
+            is_script,
+            locals: vec![Local {
+                name: String::new(),
+                depth: 0,
+                register: 0,
+            }],
+            scope_depth: 0,
+            next: 1,
+        }
+    }
+}
+
+// Compiles the AST for the register machine. Locals live in fixed registers for the whole
+// scope, and every expression is evaluated straight into the register its consumer asked for,
+// so reading a local never copies it onto a stack first. Temporaries are allocated above the
+// locals and released as soon as the statement that needed them is done.
+pub struct RegisterCompiler {
+    functions: Vec<FunctionState>,
+    errors: Vec<Error>,
+    line: usize,
+}
+
+impl Default for RegisterCompiler {
+    fn default() -> RegisterCompiler {
+        RegisterCompiler::new()
+    }
+}
+
+impl RegisterCompiler {
+    pub fn new() -> RegisterCompiler {
+        RegisterCompiler {
+            functions: vec![FunctionState::new("script".to_string(), 0, true)],
+            errors: Vec::new(),
+            line: 1,
+        }
+    }
+
+    pub fn compile(
+        mut self,
+        statements: &[Rc<Statement>],
+    ) -> Result<Rc<RegisterFunction>, Vec<Error>> {
+        for statement in statements {
+            if let Err(error) = self.statement(statement) {
+                self.errors.push(error);
+            }
+        }
+
+        if let Err(error) = self.emit_return_nil() {
+            self.errors.push(error);
+        }
+
+        if !self.errors.is_empty() {
+            return Err(self.errors);
+        }
+
+        let state = self.functions.pop().expect("script function");
+        Ok(Rc::new(state.function))
+    }
+
+    fn statement(&mut self, statement: &Statement) -> Result<(), Error> {
+        let result = self.execute(statement);
+        self.release();
+        result
+    }
+
+    fn execute(&mut self, statement: &Statement) -> Result<(), Error> {
+        match statement {
+            Statement::Expression(expression) => match &expression.kind {
+                ExpressionKind::Assign { name, .. } => match self.resolve(name)? {
+                    Some(local) => self.expression(expression, local)?,
+                    None => {
+                        self.operand(expression)?;
+                    }
+                },
+                _ => {
+                    self.operand(expression)?;
+                }
+            },
+            Statement::Print { value, .. } => {
+                let source = self.operand(value)?;
+                self.emit(Instruction::Print { source });
+            }
+            Statement::Variable { name, initializer } => {
+                self.line = name.line();
+                let register = self.allocate(name)?;
+                match initializer {
+                    Some(initializer) => self.expression(initializer, register)?,
+                    None => self.emit(Instruction::LoadNil { target: register }),
+                }
+
+                self.line = name.line();
+                self.declare(name, register)?;
+            }
+            Statement::Block(statements) => {
+                self.begin_scope();
+                let result = statements
+                    .iter()
+                    .try_for_each(|statement| self.statement(statement));
+                self.end_scope();
+                result?;
+            }
+            Statement::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                let condition = self.operand(condition)?;
+                let then_jump = self.emit_jump(Instruction::JumpIfFalse { condition, to: 0 });
+                self.release();
+                self.statement(then_branch)?;
+
+                let else_jump = self.emit_jump(Instruction::Jump { to: 0 });
+                self.patch_jump(then_jump)?;
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch)?;
+                }
+                self.patch_jump(else_jump)?;
+            }
+            Statement::While {
+                condition, body, ..
+            } => {
+                let loop_start = self.code_len()?;
+                let condition = self.operand(condition)?;
+                let exit_jump = self.emit_jump(Instruction::JumpIfFalse { condition, to: 0 });
+                self.release();
+                self.statement(body)?;
+                self.emit(Instruction::Jump { to: loop_start });
+                self.patch_jump(exit_jump)?;
+            }
+            Statement::ForIn { name, .. } => {
+                return Err(self.unsupported(name, "'for-in' loops are"));
+            }
+            Statement::Function(declaration) => {
+                let name = &declaration.name;
+                self.line = name.line();
+                let register = self.allocate(name)?;
+                let is_local = self.current().scope_depth > 0;
+                if is_local {
+                    self.declare(name, register)?;
+                }
+
+                let constant = self.function(declaration)?;
+                self.emit(Instruction::LoadConstant {
+                    target: register,
+                    constant,
+                });
+                if !is_local {
+                    self.declare(name, register)?;
+                }
+            }
+            Statement::Return { keyword, value } => {
+                if self.current().is_script {
+                    return Err(
+                        self.build_error(keyword, "Can't return from top-level code.".to_string())
+                    );
+                }
+
+                self.line = keyword.line();
+                match value {
+                    Some(value) => {
+                        let source = self.operand(value)?;
+                        self.line = keyword.line();
+                        self.emit(Instruction::Return { source });
+                    }
+                    None => self.emit_return_nil()?,
+                }
+            }
+            Statement::Yield { keyword, .. } => {
+                return Err(self.unsupported(keyword, "Generators are"));
+            }
+            Statement::Class { name, .. } => {
+                return Err(self.unsupported(name, "Classes are"));
+            }
+            Statement::Try { keyword, .. } => {
+                return Err(self.unsupported(keyword, "'try' statements are"));
+            }
+            Statement::Throw { keyword, .. } => {
+                return Err(self.unsupported(keyword, "'throw' statements are"));
+            }
+            Statement::Defer { keyword, .. } => {
+                return Err(self.unsupported(keyword, "'defer' statements are"));
+            }
+            Statement::Destructure { keyword, .. } => {
+                return Err(self.unsupported(keyword, "Destructuring declarations are"));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn function(&mut self, declaration: &FunctionDeclaration) -> Result<u32, Error> {
+        let name = &declaration.name;
+        if declaration.is_generator {
+            return Err(self.unsupported(name, "Generators are"));
+        }
+        if !declaration.arity().is_exact() {
+            return Err(self.unsupported(name, "Default and rest parameters are"));
+        }
+
+        self.functions.push(FunctionState::new(
+            name.lexeme(),
+            declaration.parameters.len(),
+            false,
+        ));
+        self.current_mut().scope_depth = 1;
+
+        let result = declaration
+            .parameters
+            .iter()
+            .try_for_each(|parameter| {
+                let register = self.allocate(parameter)?;
+                self.declare(parameter, register)
+            })
+            .and_then(|_| {
+                declaration
+                    .body
+                    .iter()
+                    .try_for_each(|statement| self.statement(statement))
+            })
+            .and_then(|_| self.emit_return_nil());
+
+        let state = self.functions.pop().expect("function state");
+        result?;
+
+        self.line = name.line();
+        self.make_constant(Value::RegisterFunction(Rc::new(state.function)))
+    }
+
+    fn expression(&mut self, expression: &Expr, target: u16) -> Result<(), Error> {
+        // The register each link of a chain finds the value it continues
+        // from in, picked from the outside in the way nested expressions
+        // would allocate them.
+        let (operand, links) = expression.chain();
+        let mut sources = vec![target; links.len()];
+        let mut source = target;
+        let mut compile_operand = true;
+        for (index, link) in links.iter().enumerate().rev() {
+            source = match &link.kind {
+                ExpressionKind::Binary { right, .. } => {
+                    let local = match &operand.kind {
+                        ExpressionKind::Variable(name)
+                            if index == 0 && !assigns(&link.get(*right)) =>
+                        {
+                            self.resolve(name)?
+                        }
+                        _ => None,
+                    };
+                    compile_operand = local.is_none();
+                    match local {
+                        Some(register) => register,
+                        None => self.allocate_temporary()?,
+                    }
+                }
+                ExpressionKind::Call { parenthesis, .. } => self.allocate(parenthesis)?,
+                ExpressionKind::Get { name, .. } => {
+                    return Err(self.unsupported(name, "Properties are"));
+                }
+                ExpressionKind::Index { bracket, .. } => {
+                    return Err(self.unsupported(bracket, "Lists are"));
+                }
+                _ => source,
+            };
+            sources[index] = source;
+        }
+
+        if compile_operand {
+            self.node(&operand, source)?;
+        }
+        for (index, link) in links.iter().enumerate() {
+            let target = sources.get(index + 1).copied().unwrap_or(target);
+            self.link(link, sources[index], target)?;
+        }
+        Ok(())
+    }
+
+    // A link of a chain whose earlier links left their value in `source`.
+    fn link(&mut self, link: &Expr, source: u16, target: u16) -> Result<(), Error> {
+        match &link.kind {
+            ExpressionKind::Binary {
+                operator, right, ..
+            } => {
+                let left = source;
+                let right = self.operand(&link.get(*right))?;
+                self.line = operator.line();
+                let instruction = match operator.kind {
+                    Kind::EqualEqual | Kind::ExclamationEqual => Instruction::Equal {
+                        target,
+                        left,
+                        right,
+                    },
+                    Kind::Greater => Instruction::Greater {
+                        target,
+                        left,
+                        right,
+                    },
+                    Kind::GreaterEqual => Instruction::GreaterEqual {
+                        target,
+                        left,
+                        right,
+                    },
+                    Kind::Less => Instruction::Less {
+                        target,
+                        left,
+                        right,
+                    },
+                    Kind::LessEqual => Instruction::LessEqual {
+                        target,
+                        left,
+                        right,
+                    },
+                    Kind::Plus => Instruction::Add {
+                        target,
+                        left,
+                        right,
+                    },
+                    Kind::Minus => Instruction::Subtract {
+                        target,
+                        left,
+                        right,
+                    },
+                    Kind::Asterisk => Instruction::Multiply {
+                        target,
+                        left,
+                        right,
+                    },
+                    Kind::Keyword(Keyword::Div) => Instruction::FloorDivide {
+                        target,
+                        left,
+                        right,
+                    },
+                    _ => Instruction::Divide {
+                        target,
+                        left,
+                        right,
+                    },
+                };
+                self.emit(instruction);
+                if operator.kind == Kind::ExclamationEqual {
+                    self.emit(Instruction::Not {
+                        target,
+                        source: target,
+                    });
+                }
+            }
+            ExpressionKind::Logical {
+                operator, right, ..
+            } => {
+                self.line = operator.line();
+                let jump = if operator.kind == Kind::Keyword(Keyword::And) {
+                    Instruction::JumpIfFalse {
+                        condition: target,
+                        to: 0,
+                    }
+                } else {
+                    Instruction::JumpIfTrue {
+                        condition: target,
+                        to: 0,
+                    }
+                };
+                let end_jump = self.emit_jump(jump);
+                self.expression(&link.get(*right), target)?;
+                self.patch_jump(end_jump)?;
+            }
+            ExpressionKind::Call {
+                parenthesis,
+                arguments,
+                ..
+            } => {
+                let base = source;
+                for argument in arguments {
+                    let register = self.allocate(parenthesis)?;
+                    self.expression(&link.get(*argument), register)?;
+                }
+
+                self.line = parenthesis.line();
+                self.emit(Instruction::Call {
+                    base,
+                    count: arguments.len() as u8,
+                });
+                if target != base {
+                    self.emit(Instruction::Move {
+                        target,
+                        source: base,
+                    });
+                }
+                self.current_mut().next = base as usize;
+            }
+            _ => unreachable!("not a link"),
+        }
+
+        Ok(())
+    }
+
+    fn node(&mut self, expression: &Expr, target: u16) -> Result<(), Error> {
+        match &expression.kind {
+            ExpressionKind::Literal(literal) => match literal {
+                Literal::Nil => self.emit(Instruction::LoadNil { target }),
+                Literal::Boolean(value) => self.emit(Instruction::LoadBoolean {
+                    target,
+                    value: *value,
+                }),
+                Literal::Number(number) => self.load_constant(Value::Number(*number), target)?,
+                Literal::Integer(integer) => self.load_constant(Value::Int(*integer), target)?,
+                Literal::String(string) => {
+                    self.load_constant(Value::String(string.clone()), target)?
+                }
+            },
+            ExpressionKind::Grouping(inner) => self.expression(&expression.get(*inner), target)?,
+            ExpressionKind::Unary { operator, right } => {
+                let source = self.operand(&expression.get(*right))?;
+                self.line = operator.line();
+                match operator.kind {
+                    Kind::Minus => self.emit(Instruction::Negate { target, source }),
+                    _ => self.emit(Instruction::Not { target, source }),
+                }
+            }
+            ExpressionKind::Variable(name) => {
+                self.line = name.line();
+                match self.resolve(name)? {
+                    Some(source) if source == target => {}
+                    Some(source) => self.emit(Instruction::Move { target, source }),
+                    None => {
+                        let name = self.make_constant(Value::String(name.lexeme().into()))?;
+                        self.emit(Instruction::GetGlobal { target, name });
+                    }
+                }
+            }
+            ExpressionKind::Assign { name, value } => {
+                let value = &expression.get(*value);
+                self.line = name.line();
+                match self.resolve(name)? {
+                    Some(local) => {
+                        // Only expressions that write their result last may target the local
+                        // directly; anything else could observe a half-updated variable.
+                        let source = if writes_last(value) {
+                            self.expression(value, local)?;
+                            local
+                        } else {
+                            self.operand(value)?
+                        };
+                        if source != local {
+                            self.emit(Instruction::Move {
+                                target: local,
+                                source,
+                            });
+                        }
+                        if target != local {
+                            self.emit(Instruction::Move {
+                                target,
+                                source: local,
+                            });
+                        }
+                    }
+                    None => {
+                        self.expression(value, target)?;
+                        self.line = name.line();
+                        let name = self.make_constant(Value::String(name.lexeme().into()))?;
+                        self.emit(Instruction::SetGlobal {
+                            source: target,
+                            name,
+                        });
+                    }
+                }
+            }
+            ExpressionKind::Binary { .. }
+            | ExpressionKind::Logical { .. }
+            | ExpressionKind::Call { .. }
+            | ExpressionKind::Index { .. } => unreachable!("links are compiled by expression"),
+            ExpressionKind::Get { name, .. } | ExpressionKind::Set { name, .. } => {
+                return Err(self.unsupported(name, "Properties are"));
+            }
+            ExpressionKind::This(keyword) | ExpressionKind::Super { keyword, .. } => {
+                return Err(self.unsupported(keyword, "Classes are"));
+            }
+            ExpressionKind::List { bracket, .. } | ExpressionKind::SetIndex { bracket, .. } => {
+                return Err(self.unsupported(bracket, "Lists are"));
+            }
+            ExpressionKind::Map { brace, .. } => {
+                return Err(self.unsupported(brace, "Maps are"));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn operand(&mut self, expression: &Expr) -> Result<u16, Error> {
+        if let ExpressionKind::Variable(name) = &expression.kind {
+            if let Some(register) = self.resolve(name)? {
+                return Ok(register);
+            }
+        }
+
+        let register = self.allocate_temporary()?;
+        self.expression(expression, register)?;
+        Ok(register)
+    }
+
+    fn resolve(&self, name: &Token) -> Result<Option<u16>, Error> {
+        let lexeme = name.lexeme();
+        let (current, enclosing) = self.functions.split_last().expect("function state");
+
+        if let Some(local) = current
+            .locals
+            .iter()
+            .rev()
+            .find(|local| local.name == lexeme)
+        {
+            return Ok(Some(local.register));
+        }
+
+        let captured = enclosing
+            .iter()
+            .any(|function| function.locals.iter().any(|local| local.name == lexeme));
+        if captured {
+            return Err(self.unsupported(name, "Closures are"));
+        }
+
+        Ok(None)
+    }
+
+    fn declare(&mut self, name: &Token, register: u16) -> Result<(), Error> {
+        if self.current().scope_depth == 0 {
+            let name = self.make_constant(Value::String(name.lexeme().into()))?;
+            self.emit(Instruction::DefineGlobal {
+                source: register,
+                name,
+            });
+            return Ok(());
+        }
+
+        let depth = self.current().scope_depth;
+        self.current_mut().locals.push(Local {
+            name: name.lexeme(),
+            depth,
+            register,
+        });
+
+        Ok(())
+    }
+
+    fn allocate(&mut self, token: &Token) -> Result<u16, Error> {
+        self.allocate_temporary()
+            .map_err(|error| self.build_error(token, error.message))
+    }
+
+    fn allocate_temporary(&mut self) -> Result<u16, Error> {
+        let state = self.current_mut();
+        if state.next >= MAX_REGISTERS {
+            return Err(Error {
+                message: "Too many registers in function.".to_string(),
+                line: self.line,
+                location: None,
+            });
+        }
+
+        let register = state.next;
+        state.next += 1;
+        state.function.registers = state.function.registers.max(state.next);
+        Ok(register as u16)
+    }
+
+    fn release(&mut self) {
+        let state = self.current_mut();
+        state.next = state
+            .locals
+            .last()
+            .map_or(1, |local| local.register as usize + 1);
+    }
+
+    fn begin_scope(&mut self) {
+        self.current_mut().scope_depth += 1;
+    }
+
+    fn end_scope(&mut self) {
+        let state = self.current_mut();
+        state.scope_depth -= 1;
+
+        let depth = state.scope_depth;
+        while state.locals.last().is_some_and(|local| local.depth > depth) {
+            state.locals.pop();
+        }
+        self.release();
+    }
+
+    fn load_constant(&mut self, value: Value, target: u16) -> Result<(), Error> {
+        let constant = self.make_constant(value)?;
+        self.emit(Instruction::LoadConstant { target, constant });
+
+        Ok(())
+    }
+
+    fn make_constant(&mut self, value: Value) -> Result<u32, Error> {
+        let constants = &mut self.current_mut().function.constants;
+        let existing = match &value {
+            Value::Number(number) => constants
+                .iter()
+                .position(|constant| matches!(constant, Value::Number(other) if other.to_bits() == number.to_bits())),
+            Value::String(_) => constants.iter().position(|constant| *constant == value),
+            _ => None,
+        };
+
+        let index = existing.unwrap_or_else(|| {
+            constants.push(value);
+            constants.len() - 1
+        });
+        u32::try_from(index).map_err(|_| Error {
+            message: "Too many constants in one chunk.".to_string(),
+            line: self.line,
+            location: None,
+        })
+    }
+
+    fn emit_return_nil(&mut self) -> Result<(), Error> {
+        let source = self.allocate_temporary()?;
+        self.emit(Instruction::LoadNil { target: source });
+        self.emit(Instruction::Return { source });
+
+        Ok(())
+    }
+
+    fn emit(&mut self, instruction: Instruction) {
+        let line = self.line;
+        let function = &mut self.current_mut().function;
+        function.code.push(instruction);
+        function.lines.push(line);
+    }
+
+    fn emit_jump(&mut self, instruction: Instruction) -> usize {
+        self.emit(instruction);
+        self.current().function.code.len() - 1
+    }
+
+    fn patch_jump(&mut self, index: usize) -> Result<(), Error> {
+        let to = self.code_len()?;
+        match &mut self.current_mut().function.code[index] {
+            Instruction::Jump { to: target }
+            | Instruction::JumpIfFalse { to: target, .. }
+            | Instruction::JumpIfTrue { to: target, .. } => *target = to,
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn code_len(&self) -> Result<u32, Error> {
+        u32::try_from(self.current().function.code.len()).map_err(|_| Error {
+            message: "Too much code in one function.".to_string(),
+            line: self.line,
+            location: None,
+        })
+    }
+
+    fn current(&self) -> &FunctionState {
+        self.functions.last().expect("function state")
+    }
+
+    fn current_mut(&mut self) -> &mut FunctionState {
+        self.functions.last_mut().expect("function state")
+    }
+
+    fn unsupported(&self, token: &Token, feature: &str) -> Error {
+        self.build_error(
+            token,
+            format!("{} not supported by the register compiler yet.", feature),
+        )
+    }
+
+    fn build_error(&self, token: &Token, message: String) -> Error {
+        Error {
+            message,
+            line: token.line(),
+            location: None,
+        }
+    }
+}
+
+fn writes_last(expression: &Expr) -> bool {
+    match &expression.kind {
+        ExpressionKind::Grouping(inner) => writes_last(&expression.get(*inner)),
+        ExpressionKind::Literal(_)
+        | ExpressionKind::Variable(_)
+        | ExpressionKind::Unary { .. }
+        | ExpressionKind::Binary { .. }
+        | ExpressionKind::Call { .. } => true,
+        _ => false,
+    }
+}
+
+fn assigns(expression: &Expr) -> bool {
+    let child = |id: &ExprId| assigns(&expression.get(*id));
+    match &expression.kind {
+        ExpressionKind::Assign { .. } => true,
+        ExpressionKind::Grouping(operand) | ExpressionKind::Unary { right: operand, .. } => {
+            child(operand)
+        }
+        ExpressionKind::Binary { left, right, .. }
+        | ExpressionKind::Logical { left, right, .. } => child(left) || child(right),
+        ExpressionKind::Call {
+            callee, arguments, ..
+        } => child(callee) || arguments.iter().any(child),
+        _ => false,
+    }
+}
@@ -0,0 +1,491 @@
+use core::mem;
+
+use crate::arithmetic::{self, Operator};
+use crate::error::{ErrorCategory, RuntimeError, RuntimeErrorKind};
+use crate::interpreter::Interpreter;
+use crate::prelude::*;
+use crate::value::{concatenate, concatenate_lists, Value};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    LoadConstant { target: u16, constant: u32 },
+    LoadNil { target: u16 },
+    LoadBoolean { target: u16, value: bool },
+    Move { target: u16, source: u16 },
+    DefineGlobal { source: u16, name: u32 },
+    GetGlobal { target: u16, name: u32 },
+    SetGlobal { source: u16, name: u32 },
+    Equal { target: u16, left: u16, right: u16 },
+    Greater { target: u16, left: u16, right: u16 },
+    GreaterEqual { target: u16, left: u16, right: u16 },
+    Less { target: u16, left: u16, right: u16 },
+    LessEqual { target: u16, left: u16, right: u16 },
+    Add { target: u16, left: u16, right: u16 },
+    Subtract { target: u16, left: u16, right: u16 },
+    Multiply { target: u16, left: u16, right: u16 },
+    Divide { target: u16, left: u16, right: u16 },
+    FloorDivide { target: u16, left: u16, right: u16 },
+    Not { target: u16, source: u16 },
+    Negate { target: u16, source: u16 },
+    Jump { to: u32 },
+    JumpIfFalse { condition: u16, to: u32 },
+    JumpIfTrue { condition: u16, to: u32 },
+    Call { base: u16, count: u8 },
+    Print { source: u16 },
+    Return { source: u16 },
+}
+
+pub struct RegisterFunction {
+    pub name: String,
+    pub arity: usize,
+    pub registers: usize,
+    pub code: Vec<Instruction>,
+    pub lines: Vec<usize>,
+    pub constants: Vec<Value>,
+}
+
+struct CallFrame {
+    function: Rc<RegisterFunction>,
+    ip: usize,
+    base: usize,
+}
+
+#[derive(Default)]
+pub struct RegisterVm {
+    registers: Vec<Value>,
+    frames: Vec<CallFrame>,
+    running_script: bool,
+}
+
+impl RegisterVm {
+    pub fn new() -> RegisterVm {
+        RegisterVm::default()
+    }
+
+    pub fn interpret(
+        &mut self,
+        interpreter: &mut Interpreter,
+        script: Rc<RegisterFunction>,
+    ) -> Result<(), RuntimeError> {
+        interpreter.reset_usage();
+
+        self.registers
+            .push(Value::RegisterFunction(Rc::clone(&script)));
+        self.registers.resize(script.registers, Value::Nil);
+        self.frames.push(CallFrame {
+            function: script,
+            ip: 0,
+            base: 0,
+        });
+        self.running_script = true;
+
+        let result = self.run(interpreter, 0).map(|_| ());
+        if result.is_err() {
+            interpreter.reset();
+        }
+
+        self.reset();
+        result
+    }
+
+    pub fn call(
+        &mut self,
+        interpreter: &mut Interpreter,
+        function: Rc<RegisterFunction>,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let base = self.frames.len();
+        let register_base = self.registers.len();
+        let line = interpreter.current_line();
+
+        self.registers
+            .push(Value::RegisterFunction(Rc::clone(&function)));
+        self.registers.extend(arguments);
+
+        let result = self
+            .call_function(interpreter, function, register_base, line)
+            .and_then(|_| self.run(interpreter, base));
+        self.frames.truncate(base);
+        self.registers.truncate(register_base);
+
+        result
+    }
+
+    pub(crate) fn depth(&self) -> usize {
+        self.frames.len() - usize::from(self.running_script)
+    }
+
+    fn reset(&mut self) {
+        self.registers.clear();
+        self.frames.clear();
+        self.running_script = false;
+    }
+
+    fn run(&mut self, interpreter: &mut Interpreter, base: usize) -> Result<Value, RuntimeError> {
+        loop {
+            if let Err(mut error) = interpreter.count_step() {
+                error.line = self.line();
+                return Err(error);
+            }
+
+            let frame = self.frame_mut();
+            let instruction = frame.function.code[frame.ip];
+            frame.ip += 1;
+            let slots = frame.base;
+
+            match instruction {
+                Instruction::LoadConstant { target, constant } => {
+                    let value = self.frame().function.constants[constant as usize].clone();
+                    self.registers[slots + target as usize] = value;
+                }
+                Instruction::LoadNil { target } => {
+                    self.registers[slots + target as usize] = Value::Nil;
+                }
+                Instruction::LoadBoolean { target, value } => {
+                    self.registers[slots + target as usize] = Value::Boolean(value);
+                }
+                Instruction::Move { target, source } => {
+                    let value = self.registers[slots + source as usize].clone();
+                    self.registers[slots + target as usize] = value;
+                }
+                Instruction::DefineGlobal { source, name } => {
+                    let name = self.name(name);
+                    let value = self.registers[slots + source as usize].clone();
+                    interpreter.globals().borrow_mut().define(name, value);
+                }
+                Instruction::GetGlobal { target, name } => {
+                    let name = self.name(name);
+                    let value = interpreter.globals().borrow().get(&name);
+                    match value {
+                        Some(value) => self.registers[slots + target as usize] = value,
+                        None => {
+                            return Err(self.name_error(format!("Undefined variable '{}'.", name)))
+                        }
+                    }
+                }
+                Instruction::SetGlobal { source, name } => {
+                    let name = self.name(name);
+                    let value = self.registers[slots + source as usize].clone();
+                    if !interpreter.globals().borrow_mut().assign(&name, value) {
+                        return Err(self.name_error(format!("Undefined variable '{}'.", name)));
+                    }
+                }
+                Instruction::Equal {
+                    target,
+                    left,
+                    right,
+                } => {
+                    let equal = self.registers[slots + left as usize]
+                        == self.registers[slots + right as usize];
+                    self.registers[slots + target as usize] = Value::Boolean(equal);
+                }
+                Instruction::Greater {
+                    target,
+                    left,
+                    right,
+                } => {
+                    self.registers[slots + target as usize] =
+                        self.arithmetic(interpreter, slots, Operator::Greater, left, right)?;
+                }
+                Instruction::GreaterEqual {
+                    target,
+                    left,
+                    right,
+                } => {
+                    self.registers[slots + target as usize] =
+                        self.arithmetic(interpreter, slots, Operator::GreaterEqual, left, right)?;
+                }
+                Instruction::Less {
+                    target,
+                    left,
+                    right,
+                } => {
+                    self.registers[slots + target as usize] =
+                        self.arithmetic(interpreter, slots, Operator::Less, left, right)?;
+                }
+                Instruction::LessEqual {
+                    target,
+                    left,
+                    right,
+                } => {
+                    self.registers[slots + target as usize] =
+                        self.arithmetic(interpreter, slots, Operator::LessEqual, left, right)?;
+                }
+                Instruction::Add {
+                    target,
+                    left,
+                    right,
+                } => {
+                    let value = match (
+                        &self.registers[slots + left as usize],
+                        &self.registers[slots + right as usize],
+                    ) {
+                        (Value::String(left), Value::String(right)) => {
+                            let value = Value::String(concatenate(left, right));
+                            self.allocate_value(interpreter, &value)?;
+                            value
+                        }
+                        (Value::List(left), Value::List(right)) => {
+                            let value = concatenate_lists(left, right);
+                            self.allocate_value(interpreter, &value)?;
+                            value
+                        }
+                        (left, right) => match arithmetic::add(left, right) {
+                            Some(result) => result.map_err(|failure| self.failure(failure))?,
+                            None => {
+                                return Err(self.type_error(
+                                    "Operands must be two numbers or two strings.".to_string(),
+                                ))
+                            }
+                        },
+                    };
+                    self.registers[slots + target as usize] = value;
+                }
+                Instruction::Subtract {
+                    target,
+                    left,
+                    right,
+                } => {
+                    self.registers[slots + target as usize] =
+                        self.arithmetic(interpreter, slots, Operator::Subtract, left, right)?;
+                }
+                Instruction::Multiply {
+                    target,
+                    left,
+                    right,
+                } => {
+                    let value =
+                        self.arithmetic(interpreter, slots, Operator::Multiply, left, right)?;
+                    if matches!(value, Value::String(_) | Value::List(_)) {
+                        self.allocate_value(interpreter, &value)?;
+                    }
+                    self.registers[slots + target as usize] = value;
+                }
+                Instruction::Divide {
+                    target,
+                    left,
+                    right,
+                } => {
+                    self.registers[slots + target as usize] =
+                        self.arithmetic(interpreter, slots, Operator::Divide, left, right)?;
+                }
+                Instruction::FloorDivide {
+                    target,
+                    left,
+                    right,
+                } => {
+                    self.registers[slots + target as usize] =
+                        self.arithmetic(interpreter, slots, Operator::FloorDivide, left, right)?;
+                }
+                Instruction::Not { target, source } => {
+                    let value = !self.registers[slots + source as usize].is_truthy();
+                    self.registers[slots + target as usize] = Value::Boolean(value);
+                }
+                Instruction::Negate { target, source } => {
+                    match arithmetic::negate(&self.registers[slots + source as usize]) {
+                        Some(result) => {
+                            self.registers[slots + target as usize] =
+                                result.map_err(|failure| self.failure(failure))?
+                        }
+                        None => {
+                            return Err(self.type_error("Operand must be a number.".to_string()))
+                        }
+                    }
+                }
+                Instruction::Jump { to } => self.frame_mut().ip = to as usize,
+                Instruction::JumpIfFalse { condition, to } => {
+                    if !self.registers[slots + condition as usize].is_truthy() {
+                        self.frame_mut().ip = to as usize;
+                    }
+                }
+                Instruction::JumpIfTrue { condition, to } => {
+                    if self.registers[slots + condition as usize].is_truthy() {
+                        self.frame_mut().ip = to as usize;
+                    }
+                }
+                Instruction::Call { base, count } => {
+                    self.call_value(interpreter, slots + base as usize, count as usize)?;
+                }
+                Instruction::Print { source } => {
+                    let value = self.registers[slots + source as usize].clone();
+                    let text = interpreter.display(&value).map_err(|mut error| {
+                        error.line = self.line();
+                        error
+                    })?;
+                    if let Err(error) = interpreter.io().write_stdout(&format!("{}\n", text)) {
+                        return Err(self.error(format!("Could not write output: {}.", error)));
+                    }
+                }
+                Instruction::Return { source } => {
+                    let value =
+                        mem::replace(&mut self.registers[slots + source as usize], Value::Nil);
+                    self.frames.pop();
+                    if self.frames.len() == base {
+                        return Ok(value);
+                    }
+
+                    self.registers[slots] = value;
+                    let caller = self.frame();
+                    let extent = caller.base + caller.function.registers;
+                    self.registers.resize(extent, Value::Nil);
+                }
+            }
+        }
+    }
+
+    fn call_value(
+        &mut self,
+        interpreter: &mut Interpreter,
+        slot: usize,
+        count: usize,
+    ) -> Result<(), RuntimeError> {
+        let line = self.line();
+        let callee = self.registers[slot].clone();
+
+        match callee {
+            Value::RegisterFunction(function) => {
+                if function.arity != count {
+                    return Err(self.arity_error(function.arity, count));
+                }
+                self.call_function(interpreter, function, slot, line)
+            }
+            Value::Native(native) => {
+                if native.arity != count {
+                    return Err(self.arity_error(native.arity, count));
+                }
+
+                let arguments = self.registers[slot + 1..slot + 1 + count].to_vec();
+                interpreter.set_current_line(line);
+                let value = self.call_out(interpreter, |interpreter| {
+                    (native.function)(interpreter, &arguments)
+                })?;
+                self.allocate_value(interpreter, &value)?;
+                self.registers[slot] = value;
+                Ok(())
+            }
+            Value::Function(_) | Value::Closure(_) | Value::BoundMethod(_) | Value::Class(_) => {
+                let arguments = self.registers[slot + 1..slot + 1 + count].to_vec();
+                interpreter.set_current_line(line);
+                let value = self.call_out(interpreter, |interpreter| {
+                    interpreter.call(callee, arguments)
+                })?;
+                self.registers[slot] = value;
+                Ok(())
+            }
+            _ => Err(self.type_error("Can only call functions and classes.".to_string())),
+        }
+    }
+
+    fn call_function(
+        &mut self,
+        interpreter: &mut Interpreter,
+        function: Rc<RegisterFunction>,
+        slot: usize,
+        line: usize,
+    ) -> Result<(), RuntimeError> {
+        if interpreter.call_depth() + self.depth() >= interpreter.options().max_call_depth {
+            return Err(RuntimeError {
+                kind: RuntimeErrorKind::ResourceExceeded,
+                category: ErrorCategory::General,
+                message: "Stack overflow.".to_string(),
+                line,
+            });
+        }
+
+        self.registers.truncate(slot + function.arity + 1);
+        self.registers.resize(slot + function.registers, Value::Nil);
+        self.frames.push(CallFrame {
+            function,
+            ip: 0,
+            base: slot,
+        });
+
+        Ok(())
+    }
+
+    fn call_out<T>(
+        &mut self,
+        interpreter: &mut Interpreter,
+        body: impl FnOnce(&mut Interpreter) -> T,
+    ) -> T {
+        mem::swap(self, interpreter.register_vm_mut());
+        let result = body(interpreter);
+        mem::swap(self, interpreter.register_vm_mut());
+        result
+    }
+
+    fn allocate_value(
+        &self,
+        interpreter: &mut Interpreter,
+        value: &Value,
+    ) -> Result<(), RuntimeError> {
+        interpreter.allocate_value(value).map_err(|mut error| {
+            error.line = self.line();
+            error
+        })
+    }
+
+    fn arithmetic(
+        &self,
+        interpreter: &Interpreter,
+        slots: usize,
+        operator: Operator,
+        left: u16,
+        right: u16,
+    ) -> Result<Value, RuntimeError> {
+        arithmetic::binary(
+            operator,
+            &self.registers[slots + left as usize],
+            &self.registers[slots + right as usize],
+            interpreter.arithmetic_rules(),
+        )
+        .map_err(|failure| self.failure(failure))
+    }
+
+    fn name(&self, index: u32) -> String {
+        match &self.frame().function.constants[index as usize] {
+            Value::String(name) => name.to_string(),
+            constant => constant.to_string(),
+        }
+    }
+
+    fn frame(&self) -> &CallFrame {
+        self.frames.last().expect("call frame")
+    }
+
+    fn frame_mut(&mut self) -> &mut CallFrame {
+        self.frames.last_mut().expect("call frame")
+    }
+
+    fn line(&self) -> usize {
+        self.frames
+            .last()
+            .map_or(0, |frame| frame.function.lines[frame.ip.saturating_sub(1)])
+    }
+
+    fn arity_error(&self, arity: usize, count: usize) -> RuntimeError {
+        self.type_error(format!("Expected {} arguments but got {}.", arity, count))
+    }
+
+    fn failure(&self, failure: arithmetic::Failure) -> RuntimeError {
+        self.error(failure.message.to_string())
+            .with_category(failure.category)
+    }
+
+    fn type_error(&self, message: String) -> RuntimeError {
+        self.error(message).with_category(ErrorCategory::Type)
+    }
+
+    fn name_error(&self, message: String) -> RuntimeError {
+        self.error(message).with_category(ErrorCategory::Name)
+    }
+
+    fn error(&self, message: String) -> RuntimeError {
+        RuntimeError {
+            kind: RuntimeErrorKind::Error,
+            category: ErrorCategory::General,
+            message,
+            line: self.line(),
+        }
+    }
+}
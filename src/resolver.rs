@@ -0,0 +1,651 @@
+use alloc::collections::BTreeMap;
+use core::mem;
+
+use crate::error::{Error, Location};
+use crate::expression::{Expr, ExprId, Expression, ExpressionKind};
+use crate::prelude::*;
+use crate::statement::{FunctionDeclaration, Statement};
+use crate::token::{Position, Token};
+
+// Where a variable expression finds its value at runtime: a slot in an
+// enclosing local scope, or a name in the globals. A forward binding names a
+// local declared later in its scope and reads the globals until that
+// declaration has run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Binding {
+    Global,
+    Local { depth: usize, slot: usize },
+    Forward { depth: usize, slot: usize },
+}
+
+// Keyed by the id of the resolved expression node; an ordered map keeps the
+// per-lookup cost well below hashing on every variable read.
+pub(crate) type Bindings = BTreeMap<ExprId, Binding>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Variable,
+    Parameter,
+    Function,
+    Method,
+    Class,
+}
+
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    // How the declaration reads, e.g. "fun add(a, b)".
+    pub detail: String,
+    pub position: Position,
+    // Index of the function, method or class declared around it.
+    pub container: Option<usize>,
+}
+
+// A variable, assignment, "this" or "super" naming `symbol`, or None when
+// it names a native or nothing at all.
+#[derive(Debug, Clone)]
+pub(crate) struct Reference {
+    pub(crate) name: String,
+    pub(crate) position: Position,
+    pub(crate) symbol: Option<usize>,
+}
+
+// Declarations and the references resolved to them, for editor tooling.
+#[derive(Debug, Default)]
+pub(crate) struct Symbols {
+    pub(crate) declarations: Vec<Symbol>,
+    pub(crate) references: Vec<Reference>,
+    // For each declaration, the indices of the references resolved to it,
+    // and an id of the scope it is declared in; 0 is the top level.
+    pub(crate) uses: Vec<Vec<usize>>,
+    pub(crate) scopes: Vec<usize>,
+    open_scopes: Vec<usize>,
+    next_scope: usize,
+    // Declarations by (scope index, slot) while their scope is open, and
+    // top-level declarations by name.
+    scoped: BTreeMap<(usize, usize), usize>,
+    pub(crate) globals: BTreeMap<String, usize>,
+    containers: Vec<usize>,
+}
+
+// Mirrors the environments the interpreter creates at runtime: one per
+// block, call, for-in iteration, bound method ("this") and subclass
+// ("super"), with slots numbered in declaration order.
+// A name that matched no declaration in the scopes around it when it was
+// seen. `scopes` counts those scopes; `enclosing` counts the ones still open,
+// so a later scope at the same index is not mistaken for one of them.
+struct Unresolved {
+    expression: ExprId,
+    name: String,
+    scopes: usize,
+    enclosing: usize,
+    reference: Option<usize>,
+}
+
+#[derive(Default)]
+pub(crate) struct Resolver {
+    scopes: Vec<Vec<String>>,
+    bindings: Bindings,
+    unresolved: Vec<Unresolved>,
+    target: usize,
+    found: Option<Vec<Vec<String>>>,
+    errors: Vec<Error>,
+    functions: usize,
+    // Enclosing classes, true for subclasses.
+    classes: Vec<bool>,
+    symbols: Option<Symbols>,
+    // Strict mode adds the book's checks that the language otherwise
+    // relaxes, and locates errors as the book does.
+    strict: bool,
+    // Enclosing functions, true for initializers.
+    initializers: Vec<bool>,
+    // Enclosing functions, true for generators.
+    generators: Vec<bool>,
+    // Enclosing functions, true while resolving one of their deferred
+    // blocks.
+    deferring: Vec<bool>,
+    // The local whose initializer is being resolved, with its scope's depth.
+    initializing: Option<(usize, String)>,
+}
+
+impl Resolver {
+    pub(crate) fn new() -> Resolver {
+        Resolver::default()
+    }
+
+    // Starts from the scopes visible at some statement, so expressions
+    // evaluated there (e.g. by the debugger) can see its locals.
+    pub(crate) fn with_scopes(scopes: Vec<Vec<String>>) -> Resolver {
+        Resolver {
+            scopes,
+            ..Resolver::default()
+        }
+    }
+
+    // The local names in scope, innermost last, where `target` executes.
+    pub(crate) fn scopes_at(
+        statements: &[Rc<Statement>],
+        target: &Rc<Statement>,
+    ) -> Option<Vec<Vec<String>>> {
+        let mut resolver = Resolver {
+            target: Rc::as_ptr(target) as usize,
+            ..Resolver::default()
+        };
+        resolver.resolve(statements);
+        resolver.found
+    }
+
+    // Declarations, references and the errors the resolver can find before
+    // running: misplaced "return", "this" and "super", values returned from
+    // initializers, and classes that inherit from themselves.
+    pub(crate) fn analyze(statements: &[Rc<Statement>]) -> (Symbols, Vec<Error>) {
+        let mut resolver = Resolver {
+            symbols: Some(Symbols::default()),
+            ..Resolver::default()
+        };
+        resolver.resolve(statements);
+
+        let mut symbols = resolver.symbols.take().unwrap_or_default();
+        symbols.uses = vec![Vec::new(); symbols.declarations.len()];
+        for (index, reference) in symbols.references.iter_mut().enumerate() {
+            if reference.symbol.is_none() {
+                reference.symbol = symbols.globals.get(&reference.name).copied();
+            }
+            if let Some(symbol) = reference.symbol {
+                symbols.uses[symbol].push(index);
+            }
+        }
+        (symbols, resolver.errors)
+    }
+
+    // The errors the book's resolver reports, worded and located as it
+    // words and locates them. Unlike this language, the book rejects
+    // redeclaring a local and reading one in its own initializer.
+    pub(crate) fn check_strict(statements: &[Rc<Statement>]) -> Vec<Error> {
+        let mut resolver = Resolver {
+            strict: true,
+            ..Resolver::default()
+        };
+        resolver.resolve(statements);
+        resolver.errors
+    }
+
+    pub(crate) fn check(statements: &[Rc<Statement>]) -> Vec<Error> {
+        let mut resolver = Resolver::new();
+        resolver.resolve(statements);
+        resolver.errors
+    }
+
+    pub(crate) fn finish(self) -> Bindings {
+        self.bindings
+    }
+
+    pub(crate) fn resolve(&mut self, statements: &[Rc<Statement>]) {
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        if statement as *const Statement as usize == self.target {
+            self.found = Some(self.scopes.clone());
+        }
+
+        match statement {
+            Statement::Expression(expression)
+            | Statement::Print {
+                value: expression, ..
+            } => {
+                self.expression(expression);
+            }
+            Statement::Variable { name, initializer } => {
+                if let Some(initializer) = initializer {
+                    if self.strict && !self.scopes.is_empty() {
+                        self.initializing = Some((self.scopes.len(), name.lexeme()));
+                    }
+                    self.expression(initializer);
+                    self.initializing = None;
+                }
+                let detail = format!("var {}", name.lexeme());
+                self.declare(name, SymbolKind::Variable, detail);
+            }
+            Statement::Destructure {
+                names, initializer, ..
+            } => {
+                self.expression(initializer);
+                for (position, name) in names.iter().enumerate() {
+                    let lexeme = name.lexeme();
+                    if names[..position]
+                        .iter()
+                        .any(|other| other.lexeme() == lexeme)
+                    {
+                        self.error(name, "Duplicate name in destructuring pattern.");
+                        continue;
+                    }
+                    let detail = format!("var {}", lexeme);
+                    self.declare(name, SymbolKind::Variable, detail);
+                }
+            }
+            Statement::Block(statements) => {
+                self.begin_scope(Vec::new());
+                self.resolve(statements);
+                self.end_scope();
+            }
+            Statement::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.expression(condition);
+                self.statement(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch);
+                }
+            }
+            Statement::While {
+                condition, body, ..
+            } => {
+                self.expression(condition);
+                self.statement(body);
+            }
+            Statement::ForIn {
+                name,
+                iterable,
+                body,
+            } => {
+                self.expression(iterable);
+                self.begin_scope(Vec::new());
+                let detail = format!("var {}", name.lexeme());
+                self.declare(name, SymbolKind::Variable, detail);
+                self.statement(body);
+                self.end_scope();
+            }
+            Statement::Function(declaration) => {
+                let detail = format!("fun {}", signature(declaration));
+                let symbol = self.declare(&declaration.name, SymbolKind::Function, detail);
+                self.function(declaration, symbol, false);
+            }
+            Statement::Return { keyword, value } => {
+                if self.functions == 0 {
+                    self.error(keyword, "Can't return from top-level code.");
+                }
+                if value.is_some() && self.initializers.last() == Some(&true) {
+                    self.error(keyword, "Can't return a value from an initializer.");
+                }
+                if self.deferring.last() == Some(&true) {
+                    self.error(keyword, "Can't return from a deferred block.");
+                }
+                if let Some(value) = value {
+                    self.expression(value);
+                }
+            }
+            Statement::Yield { value, .. } => {
+                if let Some(value) = value {
+                    self.expression(value);
+                }
+            }
+            // The caught error shares the handler's scope, as parameters
+            // share a function body's.
+            Statement::Try {
+                body,
+                name,
+                handler,
+                ..
+            } => {
+                self.begin_scope(Vec::new());
+                self.resolve(body);
+                self.end_scope();
+
+                self.begin_scope(Vec::new());
+                let detail = format!("var {}", name.lexeme());
+                self.declare(name, SymbolKind::Variable, detail);
+                self.resolve(handler);
+                self.end_scope();
+            }
+            Statement::Throw { value, .. } => self.expression(value),
+            // A generator can be left suspended forever, so there would be
+            // no exit to run its deferred blocks at.
+            Statement::Defer { keyword, body } => {
+                if self.functions == 0 {
+                    self.error(keyword, "Can't defer outside of a function.");
+                } else if self.generators.last() == Some(&true) {
+                    self.error(keyword, "Can't defer in a generator.");
+                }
+
+                let enclosing = self
+                    .deferring
+                    .last_mut()
+                    .map(|deferring| mem::replace(deferring, true));
+                self.begin_scope(Vec::new());
+                self.resolve(body);
+                self.end_scope();
+                if let (Some(deferring), Some(enclosing)) = (self.deferring.last_mut(), enclosing) {
+                    *deferring = enclosing;
+                }
+            }
+            Statement::Class {
+                name,
+                superclass,
+                methods,
+            } => {
+                let mut detail = format!("class {}", name.lexeme());
+                if let Some(superclass) = superclass {
+                    if let ExpressionKind::Variable(superclass_name) = &superclass.kind {
+                        if superclass_name.lexeme() == name.lexeme() {
+                            self.error(superclass_name, "A class can't inherit from itself.");
+                        }
+                        detail.push_str(&format!(" < {}", superclass_name.lexeme()));
+                    }
+                    self.expression(superclass);
+                }
+                let class = self.declare(name, SymbolKind::Class, detail);
+
+                if superclass.is_some() {
+                    self.begin_scope(vec!["super".to_string()]);
+                }
+                self.classes.push(superclass.is_some());
+                self.enter_container(class);
+
+                for method in methods {
+                    let detail = format!("{}.{}", name.lexeme(), signature(method));
+                    let symbol = self.record(&method.name, SymbolKind::Method, detail);
+                    self.begin_scope(vec!["this".to_string()]);
+                    let initializer = method.name.lexeme() == "init";
+                    self.function(method, symbol, initializer);
+                    self.end_scope();
+                }
+
+                self.leave_container(class);
+                self.classes.pop();
+                if superclass.is_some() {
+                    self.end_scope();
+                }
+            }
+        }
+    }
+
+    // Parameters and the body's own declarations share the call environment.
+    fn function(
+        &mut self,
+        declaration: &FunctionDeclaration,
+        symbol: Option<usize>,
+        initializer: bool,
+    ) {
+        self.enter_container(symbol);
+        self.functions += 1;
+        self.initializers.push(initializer);
+        self.generators.push(declaration.is_generator);
+        self.deferring.push(false);
+        self.begin_scope(Vec::new());
+        // A default value sees the parameters before its own.
+        let required = declaration.arity().required;
+        for (index, parameter) in declaration.parameters.iter().enumerate() {
+            let default = index
+                .checked_sub(required)
+                .map(|index| &declaration.defaults[index]);
+            let detail = match default {
+                Some(default) => {
+                    self.expression(default);
+                    format!("parameter {} (optional)", parameter.lexeme())
+                }
+                None => format!("parameter {}", parameter.lexeme()),
+            };
+            self.declare(parameter, SymbolKind::Parameter, detail);
+        }
+        if let Some(rest) = &declaration.rest {
+            let detail = format!("parameter ...{}", rest.lexeme());
+            self.declare(rest, SymbolKind::Parameter, detail);
+        }
+
+        self.resolve(&declaration.body);
+        self.end_scope();
+        self.deferring.pop();
+        self.generators.pop();
+        self.initializers.pop();
+        self.functions -= 1;
+        self.leave_container(symbol);
+    }
+
+    pub(crate) fn expression(&mut self, expression: &Expr) {
+        let (operand, links) = expression.chain();
+        self.node(&operand);
+        for link in &links {
+            self.node(link);
+        }
+    }
+
+    // Resolves one node; the operand a link of a chain continues from has
+    // been resolved already.
+    fn node(&mut self, expression: &Expr) {
+        match &expression.kind {
+            ExpressionKind::Literal(_) => {}
+            ExpressionKind::Grouping(inner) => self.expression(&expression.get(*inner)),
+            ExpressionKind::Unary { right, .. } => self.expression(&expression.get(*right)),
+            ExpressionKind::Binary { right, .. } | ExpressionKind::Logical { right, .. } => {
+                self.expression(&expression.get(*right))
+            }
+            ExpressionKind::Variable(name) => {
+                if self.initializing == Some((self.scopes.len(), name.lexeme())) {
+                    self.error(name, "Can't read local variable in its own initializer.");
+                }
+                self.bind(expression, name)
+            }
+            ExpressionKind::This(keyword) => {
+                if self.classes.is_empty() {
+                    self.error(keyword, "Can't use 'this' outside of a class.");
+                }
+                self.bind(expression, keyword);
+            }
+            ExpressionKind::Assign { name, value } => {
+                self.expression(&expression.get(*value));
+                self.bind(expression, name);
+            }
+            ExpressionKind::Call { arguments, .. } => {
+                for argument in arguments {
+                    self.expression(&expression.get(*argument));
+                }
+            }
+            ExpressionKind::Get { .. } => {}
+            ExpressionKind::Set { object, value, .. } => {
+                self.expression(&expression.get(*object));
+                self.expression(&expression.get(*value));
+            }
+            ExpressionKind::List { elements, .. } => {
+                for element in elements {
+                    self.expression(&expression.get(*element));
+                }
+            }
+            ExpressionKind::Map { entries, .. } => {
+                for (key, value) in entries {
+                    self.expression(&expression.get(*key));
+                    self.expression(&expression.get(*value));
+                }
+            }
+            ExpressionKind::Index { index, .. } => self.expression(&expression.get(*index)),
+            ExpressionKind::SetIndex {
+                object,
+                index,
+                value,
+                ..
+            } => {
+                self.expression(&expression.get(*object));
+                self.expression(&expression.get(*index));
+                self.expression(&expression.get(*value));
+            }
+            ExpressionKind::Super { keyword, .. } => {
+                match self.classes.last() {
+                    Some(true) => {}
+                    Some(false) if self.strict => {
+                        self.error(keyword, "Can't use 'super' in a class with no superclass.")
+                    }
+                    None if self.strict => {
+                        self.error(keyword, "Can't use 'super' outside of a class.")
+                    }
+                    _ => self.error(keyword, "Can't use 'super' outside of a subclass method."),
+                }
+                self.bind(expression, keyword);
+            }
+        }
+    }
+
+    fn begin_scope(&mut self, names: Vec<String>) {
+        self.scopes.push(names);
+        if let Some(symbols) = self.symbols.as_mut() {
+            symbols.next_scope += 1;
+            symbols.open_scopes.push(symbols.next_scope);
+        }
+    }
+
+    // Names left unresolved inside this scope bind forward to its first
+    // declaration of the same name, if any.
+    fn end_scope(&mut self) {
+        let Some(scope) = self.scopes.pop() else {
+            return;
+        };
+        let index = self.scopes.len();
+
+        self.unresolved.retain_mut(|unresolved| {
+            if unresolved.enclosing <= index {
+                return true;
+            }
+            let Some(slot) = scope
+                .iter()
+                .position(|declared| *declared == unresolved.name)
+            else {
+                unresolved.enclosing = index;
+                return true;
+            };
+
+            let depth = unresolved.scopes - 1 - index;
+            self.bindings
+                .insert(unresolved.expression, Binding::Forward { depth, slot });
+            if let (Some(symbols), Some(reference)) = (self.symbols.as_mut(), unresolved.reference)
+            {
+                symbols.references[reference].symbol = symbols.scoped.get(&(index, slot)).copied();
+            }
+            false
+        });
+
+        if let Some(symbols) = self.symbols.as_mut() {
+            symbols.scoped.split_off(&(index, 0));
+            symbols.open_scopes.pop();
+        }
+    }
+
+    fn declare(&mut self, name: &Token, kind: SymbolKind, detail: String) -> Option<usize> {
+        let depth = self.scopes.len();
+        let Some(scope) = self.scopes.last_mut() else {
+            let symbol = self.record(name, kind, detail)?;
+            if let Some(symbols) = self.symbols.as_mut() {
+                symbols.globals.insert(name.lexeme(), symbol);
+            }
+            return Some(symbol);
+        };
+
+        if self.strict && scope.contains(&name.lexeme()) {
+            self.error(name, "Already a variable with this name in this scope.");
+            return None;
+        }
+        scope.push(name.lexeme());
+        let key = (depth - 1, scope.len() - 1);
+        let symbol = self.record(name, kind, detail)?;
+        if let Some(symbols) = self.symbols.as_mut() {
+            symbols.scoped.insert(key, symbol);
+        }
+        Some(symbol)
+    }
+
+    // Only when analyzing for tooling; running a program skips this.
+    fn record(&mut self, name: &Token, kind: SymbolKind, detail: String) -> Option<usize> {
+        let symbols = self.symbols.as_mut()?;
+        symbols.declarations.push(Symbol {
+            name: name.lexeme(),
+            kind,
+            detail,
+            position: name.position,
+            container: symbols.containers.last().copied(),
+        });
+        symbols
+            .scopes
+            .push(symbols.open_scopes.last().copied().unwrap_or(0));
+        Some(symbols.declarations.len() - 1)
+    }
+
+    fn enter_container(&mut self, symbol: Option<usize>) {
+        if let (Some(symbols), Some(symbol)) = (self.symbols.as_mut(), symbol) {
+            symbols.containers.push(symbol);
+        }
+    }
+
+    fn leave_container(&mut self, symbol: Option<usize>) {
+        if let (Some(symbols), Some(_)) = (self.symbols.as_mut(), symbol) {
+            symbols.containers.pop();
+        }
+    }
+
+    fn error(&mut self, token: &Token, message: &str) {
+        let location = self.strict.then(|| Location::Token(token.lexeme()));
+        self.errors.push(Error {
+            message: message.to_string(),
+            line: token.line(),
+            location,
+        });
+    }
+
+    fn bind(&mut self, expression: &Expression, token: &Token) {
+        let name = token.lexeme();
+        let binding = self
+            .scopes
+            .iter()
+            .rev()
+            .enumerate()
+            .find_map(|(depth, scope)| {
+                scope
+                    .iter()
+                    .rposition(|declared| *declared == name)
+                    .map(|slot| Binding::Local { depth, slot })
+            })
+            .unwrap_or(Binding::Global);
+
+        let reference = self.symbols.as_mut().map(|symbols| {
+            let symbol = match binding {
+                Binding::Local { depth, slot } => symbols
+                    .scoped
+                    .get(&(self.scopes.len() - 1 - depth, slot))
+                    .copied(),
+                _ => None,
+            };
+            symbols.references.push(Reference {
+                name: name.clone(),
+                position: token.position,
+                symbol,
+            });
+            symbols.references.len() - 1
+        });
+
+        if binding == Binding::Global && !self.scopes.is_empty() {
+            self.unresolved.push(Unresolved {
+                expression: expression.id,
+                name,
+                scopes: self.scopes.len(),
+                enclosing: self.scopes.len(),
+                reference,
+            });
+        }
+        self.bindings.insert(expression.id, binding);
+    }
+}
+
+fn signature(declaration: &FunctionDeclaration) -> String {
+    let mut parameters: Vec<String> = declaration
+        .parameters
+        .iter()
+        .map(|parameter| parameter.lexeme())
+        .collect();
+    if let Some(rest) = &declaration.rest {
+        parameters.push(format!("...{}", rest.lexeme()));
+    }
+    format!("{}({})", declaration.name.lexeme(), parameters.join(", "))
+}
@@ -0,0 +1,70 @@
+use std::io::{self, BufRead, Write};
+
+use crate::json::{self, Json};
+
+// Content-Length framed JSON messages over a pair of streams, the transport
+// of both the Debug Adapter and Language Server protocols.
+pub(crate) struct Transport {
+    reader: Box<dyn BufRead>,
+    writer: Box<dyn Write>,
+}
+
+impl Transport {
+    pub(crate) fn new(reader: impl BufRead + 'static, writer: impl Write + 'static) -> Transport {
+        Transport {
+            reader: Box::new(reader),
+            writer: Box::new(writer),
+        }
+    }
+
+    // None once the reader is exhausted.
+    pub(crate) fn receive(&mut self) -> io::Result<Option<Json>> {
+        let mut length = None;
+        loop {
+            let mut header = String::new();
+            if self.reader.read_line(&mut header)? == 0 {
+                return Ok(None);
+            }
+
+            let header = header.trim_end();
+            if header.is_empty() && length.is_some() {
+                break;
+            }
+            if let Some(value) = header.strip_prefix("Content-Length:") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+
+        let mut body = vec![0; length.unwrap_or_default()];
+        self.reader.read_exact(&mut body)?;
+        let body = String::from_utf8(body)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        json::parse(&body)
+            .map(Some)
+            .map_err(|message| io::Error::new(io::ErrorKind::InvalidData, message))
+    }
+
+    pub(crate) fn send(&mut self, message: &Json) -> io::Result<()> {
+        let body = message.to_string();
+        write!(
+            self.writer,
+            "Content-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        )?;
+        self.writer.flush()
+    }
+}
+
+pub(crate) fn object(entries: Vec<(&str, Json)>) -> Json {
+    Json::Object(
+        entries
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect(),
+    )
+}
+
+pub(crate) fn string(text: &str) -> Json {
+    Json::String(text.to_string())
+}
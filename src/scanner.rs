@@ -1,11 +1,15 @@
+use crate::dialect::Dialect;
 use crate::error::Error;
-use crate::token::{Keyword, Kind, Position, Token};
+use crate::prelude::*;
+use crate::token::{Kind, Position, Token};
 
 pub struct Scanner {
     source: Vec<char>,
     current_position: usize,
     current_start: usize,
     current_line: usize,
+    trivia: bool,
+    dialect: Dialect,
 }
 
 impl Scanner {
@@ -15,23 +19,82 @@ impl Scanner {
             current_position: 0,
             current_start: 0,
             current_line: 1,
+            trivia: false,
+            dialect: Dialect::default(),
         }
     }
 
+    // Keeps comments as tokens, for tools that work on the source text
+    // rather than the program; the parser does not expect them.
+    pub fn set_trivia(&mut self, trivia: bool) {
+        self.trivia = trivia;
+    }
+
+    // Which words are keywords; those the dialect leaves out are scanned as
+    // identifiers.
+    pub fn set_dialect(&mut self, dialect: Dialect) {
+        self.dialect = dialect;
+    }
+
     pub fn scan_tokens(&mut self) -> Result<Vec<Token>, Error> {
         let mut tokens: Vec<Token> = Vec::new();
         while !self.finished() {
             self.mark_start();
 
-            match self.scan_token()? {
-                Some(token) => tokens.push(token),
-                None => (),
+            if let Some(token) = self.scan_token()? {
+                tokens.push(token);
             }
         }
 
+        self.mark_start();
+        tokens.push(self.build_token(Kind::EndOfFile));
+
         Ok(tokens)
     }
 
+    // Scans past errors rather than stopping at the first, skipping the
+    // characters of each rejected token. Errors come with the offset of what
+    // was rejected.
+    pub(crate) fn scan_all(&mut self) -> (Vec<Token>, Vec<(usize, Error)>) {
+        let mut tokens: Vec<Token> = Vec::new();
+        let mut errors: Vec<(usize, Error)> = Vec::new();
+        while !self.finished() {
+            self.mark_start();
+
+            match self.scan_token() {
+                Ok(Some(token)) => tokens.push(token),
+                Ok(None) => {}
+                Err(error) => errors.push((self.current_start, error)),
+            }
+        }
+
+        self.mark_start();
+        tokens.push(self.build_token(Kind::EndOfFile));
+
+        (tokens, errors)
+    }
+
+    // Continues from `position`, the end of a token on `line`, as when only
+    // the source after it has changed.
+    pub(crate) fn seek(&mut self, position: usize, line: usize) {
+        self.current_position = position;
+        self.current_line = line;
+    }
+
+    // The next token, or the end of the file.
+    pub(crate) fn next_token(&mut self) -> Result<Token, Error> {
+        while !self.finished() {
+            self.mark_start();
+
+            if let Some(token) = self.scan_token()? {
+                return Ok(token);
+            }
+        }
+
+        self.mark_start();
+        Ok(self.build_token(Kind::EndOfFile))
+    }
+
     fn scan_token(&mut self) -> Result<Option<Token>, Error> {
         if let Some(character) = self.get_current_char_and_advance() {
             match character {
@@ -39,14 +102,28 @@ impl Scanner {
                 ')' => Ok(Some(self.build_token(Kind::CloseParenthesis))),
                 '{' => Ok(Some(self.build_token(Kind::OpenCurlyBracket))),
                 '}' => Ok(Some(self.build_token(Kind::CloseCurlyBracket))),
+                '[' | ']' | ':' if self.dialect.is_strict() => {
+                    Err(self.build_error("Unexpected character.".to_string()))
+                }
+                '[' => Ok(Some(self.build_token(Kind::OpenSquareBracket))),
+                ']' => Ok(Some(self.build_token(Kind::CloseSquareBracket))),
                 ',' => Ok(Some(self.build_token(Kind::Comma))),
+                ':' => Ok(Some(self.build_token(Kind::Colon))),
+                '.' if !self.dialect.is_strict()
+                    && self.get_current_char() == Some('.')
+                    && self.get_next_char() == Some('.') =>
+                {
+                    self.advance();
+                    self.advance();
+                    Ok(Some(self.build_token(Kind::Ellipsis)))
+                }
                 '.' => Ok(Some(self.build_token(Kind::Dot))),
                 '-' => Ok(Some(self.build_token(Kind::Minus))),
                 '+' => Ok(Some(self.build_token(Kind::Plus))),
                 ';' => Ok(Some(self.build_token(Kind::Semicolon))),
                 '*' => Ok(Some(self.build_token(Kind::Asterisk))),
 
-                '!' if self.get_next_char() == Some('=') => {
+                '!' if self.get_current_char() == Some('=') => {
                     self.advance();
                     Ok(Some(self.build_token(Kind::ExclamationEqual)))
                 }
@@ -75,7 +152,14 @@ impl Scanner {
                         self.advance();
                     }
 
-                    Ok(None)
+                    if !self.trivia {
+                        return Ok(None);
+                    }
+                    let comment: String = self.source
+                        [(self.current_start + 2)..self.current_position]
+                        .iter()
+                        .collect();
+                    Ok(Some(self.build_token(Kind::Comment(comment))))
                 }
                 '/' => Ok(Some(self.build_token(Kind::Slash))),
 
@@ -91,6 +175,9 @@ impl Scanner {
                     Ok(None)
                 }
 
+                _ if self.dialect.is_strict() => {
+                    Err(self.build_error("Unexpected character.".to_string()))
+                }
                 _ => Err(self.build_error("Invalid syntax.".to_string())),
             }
         } else {
@@ -108,7 +195,11 @@ impl Scanner {
         }
 
         if self.finished() {
-            Err(self.build_error("EOF while scanning string literal".to_string()))
+            let message = match self.dialect.is_strict() {
+                true => "Unterminated string.",
+                false => "EOF while scanning string literal",
+            };
+            Err(self.build_error(message.to_string()))
         } else {
             self.advance();
 
@@ -134,12 +225,17 @@ impl Scanner {
             }
         }
 
-        let number: f64 = self.source[self.current_start..self.current_position]
+        let text: String = self.source[self.current_start..self.current_position]
             .iter()
-            .collect::<String>()
-            .parse::<f64>()
-            .unwrap();
+            .collect();
+        if self.dialect.integers() && !text.contains('.') {
+            return match text.parse::<i64>() {
+                Ok(integer) => Ok(Some(self.build_token(Kind::Integer(integer)))),
+                Err(_) => Err(self.build_error("Integer literal is too large.".to_string())),
+            };
+        }
 
+        let number: f64 = text.parse().unwrap();
         Ok(Some(self.build_token(Kind::Number(number))))
     }
 
@@ -152,9 +248,9 @@ impl Scanner {
             .iter()
             .collect();
 
-        match str::parse::<Keyword>(&string) {
-            Ok(keyword) => Ok(Some(self.build_token(Kind::Keyword(keyword)))),
-            Err(_) => Ok(Some(self.build_token(Kind::Identifier(string)))),
+        match self.dialect.keyword(&string) {
+            Some(keyword) => Ok(Some(self.build_token(Kind::Keyword(keyword)))),
+            None => Ok(Some(self.build_token(Kind::Identifier(string)))),
         }
     }
 
@@ -165,15 +261,15 @@ impl Scanner {
         character
     }
 
-    fn advance(&mut self) -> () {
+    fn advance(&mut self) {
         self.current_position += 1;
     }
 
-    fn advance_line(&mut self) -> () {
+    fn advance_line(&mut self) {
         self.current_line += 1;
     }
 
-    fn mark_start(&mut self) -> () {
+    fn mark_start(&mut self) {
         self.current_start = self.current_position;
     }
 
@@ -186,7 +282,7 @@ impl Scanner {
     }
 
     fn is_valid_position(&self, position: usize) -> bool {
-        position <= self.source.len()
+        position < self.source.len()
     }
 
     fn get_current_char(&self) -> Option<char> {
@@ -218,27 +314,25 @@ impl Scanner {
         Error {
             message,
             line: self.current_line,
+            location: None,
         }
     }
 }
 
 fn is_numeric(character: Option<char>) -> bool {
-    if let Some(_ch @ '0'..='9') = character {
-        true
-    } else {
-        false
-    }
+    matches!(character, Some('0'..='9'))
+}
+
+// Whether `name` scans as a single identifier rather than a keyword.
+pub fn is_identifier(name: &str) -> bool {
+    let mut characters = name.chars();
+    is_alpha(characters.next())
+        && characters.all(|character| is_alphanumeric(Some(character)))
+        && Dialect::default().keyword(name).is_none()
 }
 
 fn is_alpha(character: Option<char>) -> bool {
-    if let Some(ch) = character {
-        match ch {
-            'a'..='z' | 'A'..='Z' | '_' => true,
-            _ => false,
-        }
-    } else {
-        false
-    }
+    matches!(character, Some('a'..='z' | 'A'..='Z' | '_'))
 }
 
 fn is_alphanumeric(character: Option<char>) -> bool {
@@ -0,0 +1,225 @@
+use crate::arity::Arity;
+use crate::bytecode::{Chunk, Constant, Prototype};
+use crate::prelude::*;
+use crate::verifier;
+
+pub const MAGIC: &[u8; 4] = b"LOXC";
+pub const FORMAT_VERSION: u16 = 3;
+
+const MAX_DEPTH: usize = 512;
+
+const TAG_NUMBER: u8 = 0;
+const TAG_STRING: u8 = 1;
+const TAG_FUNCTION: u8 = 2;
+const TAG_INTEGER: u8 = 3;
+
+pub fn is_compiled(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+pub fn serialize(script: &Prototype) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    write_prototype(&mut bytes, script);
+    bytes
+}
+
+pub fn deserialize(bytes: &[u8]) -> Result<Prototype, String> {
+    if !is_compiled(bytes) {
+        return Err("Not a compiled Lox file.".to_string());
+    }
+
+    let mut reader = Reader {
+        bytes,
+        position: MAGIC.len(),
+    };
+
+    let version = u16::from_le_bytes([reader.byte()?, reader.byte()?]);
+    if version != FORMAT_VERSION {
+        return Err(format!(
+            "Unsupported bytecode version {} (expected {}).",
+            version, FORMAT_VERSION
+        ));
+    }
+
+    let script = reader.prototype(0)?;
+    if reader.position != bytes.len() {
+        return Err("Unexpected data after the script.".to_string());
+    }
+    verifier::verify(&script)?;
+
+    Ok(script)
+}
+
+fn write_prototype(bytes: &mut Vec<u8>, prototype: &Prototype) {
+    write_string(bytes, &prototype.name);
+    write_length(bytes, prototype.arity.required);
+    write_length(bytes, prototype.defaults.len());
+    for &offset in &prototype.defaults {
+        write_length(bytes, offset);
+    }
+    bytes.push(u8::from(prototype.arity.variadic));
+    write_length(bytes, prototype.upvalue_count);
+    bytes.push(u8::from(prototype.is_generator));
+
+    let chunk = &prototype.chunk;
+    write_length(bytes, chunk.len());
+    bytes.extend_from_slice(chunk.code());
+
+    write_length(bytes, chunk.constants().len());
+    for constant in chunk.constants() {
+        match constant {
+            Constant::Number(number) => {
+                bytes.push(TAG_NUMBER);
+                bytes.extend_from_slice(&number.to_bits().to_le_bytes());
+            }
+            Constant::Integer(integer) => {
+                bytes.push(TAG_INTEGER);
+                bytes.extend_from_slice(&integer.to_le_bytes());
+            }
+            Constant::String(string) => {
+                bytes.push(TAG_STRING);
+                write_string(bytes, string);
+            }
+            Constant::Function(function) => {
+                bytes.push(TAG_FUNCTION);
+                write_prototype(bytes, function);
+            }
+        }
+    }
+
+    let line_runs = chunk.line_runs();
+    write_length(bytes, line_runs.len());
+    for (line, count) in line_runs {
+        write_length(bytes, line);
+        write_length(bytes, count);
+    }
+}
+
+fn write_length(bytes: &mut Vec<u8>, length: usize) {
+    bytes.extend_from_slice(&(length as u32).to_le_bytes());
+}
+
+fn write_string(bytes: &mut Vec<u8>, string: &str) {
+    write_length(bytes, string.len());
+    bytes.extend_from_slice(string.as_bytes());
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl Reader<'_> {
+    fn prototype(&mut self, depth: usize) -> Result<Prototype, String> {
+        if depth > MAX_DEPTH {
+            return Err("Functions are nested too deeply.".to_string());
+        }
+
+        let name = self.string()?;
+        let required = self.length()?;
+        let default_count = self.length()?;
+        let mut defaults = Vec::new();
+        for _ in 0..default_count {
+            defaults.push(self.length()?);
+        }
+        let variadic = match self.byte()? {
+            0 => false,
+            1 => true,
+            byte => return Err(format!("Invalid variadic flag {}.", byte)),
+        };
+        let upvalue_count = self.length()?;
+        let is_generator = match self.byte()? {
+            0 => false,
+            1 => true,
+            byte => return Err(format!("Invalid generator flag {}.", byte)),
+        };
+
+        let code_length = self.length()?;
+        let code = self.take(code_length)?.to_vec();
+        if defaults.iter().any(|&offset| offset >= code_length) {
+            return Err(format!("Defaults for '{}' start outside its code.", name));
+        }
+
+        let constant_count = self.length()?;
+        let mut constants = Vec::new();
+        for _ in 0..constant_count {
+            let constant = match self.byte()? {
+                TAG_NUMBER => {
+                    let bits = self.take(8)?;
+                    let mut buffer = [0; 8];
+                    buffer.copy_from_slice(bits);
+                    Constant::Number(f64::from_bits(u64::from_le_bytes(buffer)))
+                }
+                TAG_INTEGER => {
+                    let bytes = self.take(8)?;
+                    let mut buffer = [0; 8];
+                    buffer.copy_from_slice(bytes);
+                    Constant::Integer(i64::from_le_bytes(buffer))
+                }
+                TAG_STRING => Constant::String(self.string()?.into()),
+                TAG_FUNCTION => Constant::Function(Rc::new(self.prototype(depth + 1)?)),
+                tag => return Err(format!("Unknown constant tag {}.", tag)),
+            };
+            constants.push(constant);
+        }
+
+        let run_count = self.length()?;
+        let mut line_runs = Vec::new();
+        let mut covered = 0usize;
+        for _ in 0..run_count {
+            let line = self.length()?;
+            let count = self.length()?;
+            covered = covered.saturating_add(count);
+            line_runs.push((line, count));
+        }
+        if covered != code.len() {
+            return Err(format!(
+                "Line table for '{}' does not match its code.",
+                name
+            ));
+        }
+
+        Ok(Prototype {
+            name,
+            arity: Arity {
+                required,
+                optional: defaults.len(),
+                variadic,
+            },
+            defaults,
+            upvalue_count,
+            is_generator,
+            chunk: Chunk::from_parts(code, constants, line_runs),
+        })
+    }
+
+    fn byte(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn length(&mut self) -> Result<usize, String> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let length = self.length()?;
+        let bytes = self.take(length)?;
+        String::from_utf8(bytes.to_vec())
+            .map_err(|_| "Invalid UTF-8 in string constant.".to_string())
+    }
+
+    fn take(&mut self, length: usize) -> Result<&[u8], String> {
+        let end = self
+            .position
+            .checked_add(length)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or_else(|| "Unexpected end of file.".to_string())?;
+
+        let bytes = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+}
@@ -0,0 +1,95 @@
+#[cfg(feature = "std")]
+use std::path::{Path, PathBuf};
+
+use crate::prelude::*;
+
+// The flat subset of TOML the tools' configuration files use: `key = value`
+// lines whose values are integers, booleans or basic strings, and `#`
+// comments.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Value {
+    Integer(usize),
+    Boolean(bool),
+    String(String),
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Setting {
+    pub(crate) key: String,
+    pub(crate) value: Value,
+    pub(crate) line: usize,
+}
+
+impl Setting {
+    pub(crate) fn invalid(&self) -> String {
+        format!("Invalid value for '{}' on line {}.", self.key, self.line)
+    }
+
+    pub(crate) fn unknown(&self) -> String {
+        format!("Unknown option '{}' on line {}.", self.key, self.line)
+    }
+}
+
+pub(crate) fn parse(text: &str) -> Result<Vec<Setting>, String> {
+    let mut settings = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let number = index + 1;
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(format!("Expect 'key = value' on line {}.", number));
+        };
+        let value = value.trim();
+        let value = if let Some(string) = value
+            .strip_prefix('"')
+            .and_then(|value| value.strip_suffix('"'))
+        {
+            Value::String(string.to_string())
+        } else if let Ok(boolean) = value.parse::<bool>() {
+            Value::Boolean(boolean)
+        } else if let Ok(integer) = value.parse::<usize>() {
+            Value::Integer(integer)
+        } else {
+            return Err(format!("Invalid value '{}' on line {}.", value, number));
+        };
+
+        settings.push(Setting {
+            key: key.trim().to_string(),
+            value,
+            line: number,
+        });
+    }
+    Ok(settings)
+}
+
+// A `#` outside a string starts a comment.
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (index, character) in line.char_indices() {
+        match character {
+            '"' => quoted = !quoted,
+            '#' if !quoted => return &line[..index],
+            _ => {}
+        }
+    }
+    line
+}
+
+// The nearest file called `name` in `directory` or a directory above it.
+#[cfg(feature = "std")]
+pub(crate) fn find(directory: &Path, name: &str) -> Option<PathBuf> {
+    directory
+        .ancestors()
+        .map(|directory| directory.join(name))
+        .find(|path| path.is_file())
+}
+
+#[cfg(feature = "std")]
+pub(crate) fn read(path: &Path) -> Result<Vec<Setting>, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|error| format!("Could not read '{}': {}", path.display(), error))?;
+    parse(&text).map_err(|message| format!("{}: {}", path.display(), message))
+}
@@ -0,0 +1,30 @@
+use crate::prelude::*;
+use crate::symbol::SymbolTable;
+use crate::value::Value;
+
+// The global bindings at one moment, to roll a failed REPL line back to.
+// Only the bindings are kept: the lists, maps and instances they name are
+// shared with everything else that holds them, so closures and the
+// restored globals still agree on which object is which.
+#[derive(Clone)]
+pub struct Snapshot {
+    globals: SymbolTable<Value>,
+}
+
+impl Snapshot {
+    pub fn capture(globals: &SymbolTable<Value>) -> Snapshot {
+        Snapshot {
+            globals: globals.clone(),
+        }
+    }
+
+    pub fn bindings(&self) -> SymbolTable<Value> {
+        self.globals.clone()
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.globals.keys().map(ToString::to_string).collect();
+        names.sort();
+        names
+    }
+}
@@ -0,0 +1,259 @@
+use core::mem;
+
+use crate::arity::Arity;
+use crate::expression::Expr;
+use crate::prelude::*;
+use crate::token::Token;
+
+#[derive(Debug)]
+pub enum Statement {
+    Expression(Expr),
+    Print {
+        keyword: Token,
+        value: Expr,
+    },
+    Variable {
+        name: Token,
+        initializer: Option<Expr>,
+    },
+    // `var [a, b] = value;`, `var {x, y} = value;` or `var a, b = value;`,
+    // which declares each name with the element at its position or the
+    // field or key it names.
+    Destructure {
+        keyword: Token,
+        pattern: Pattern,
+        names: Vec<Token>,
+        initializer: Expr,
+    },
+    Block(Vec<Rc<Statement>>),
+    If {
+        keyword: Token,
+        condition: Expr,
+        then_branch: Rc<Statement>,
+        else_branch: Option<Rc<Statement>>,
+    },
+    While {
+        keyword: Token,
+        condition: Expr,
+        body: Rc<Statement>,
+    },
+    ForIn {
+        name: Token,
+        iterable: Expr,
+        body: Rc<Statement>,
+    },
+    Function(Rc<FunctionDeclaration>),
+    Return {
+        keyword: Token,
+        value: Option<Expr>,
+    },
+    Yield {
+        keyword: Token,
+        value: Option<Expr>,
+    },
+    Class {
+        name: Token,
+        superclass: Option<Expr>,
+        methods: Vec<Rc<FunctionDeclaration>>,
+    },
+    // `try { body } catch (name) { handler }`.
+    Try {
+        keyword: Token,
+        body: Vec<Rc<Statement>>,
+        name: Token,
+        handler: Vec<Rc<Statement>>,
+    },
+    Throw {
+        keyword: Token,
+        value: Expr,
+    },
+    // `defer { body }`, which runs the body as the enclosing function
+    // exits, however it exits.
+    Defer {
+        keyword: Token,
+        body: Vec<Rc<Statement>>,
+    },
+}
+
+impl Statement {
+    // The line a statement starts on. Blocks have none of their own.
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            Statement::Expression(expression) => expression.line(),
+            Statement::Print { keyword, .. }
+            | Statement::Return { keyword, .. }
+            | Statement::Yield { keyword, .. }
+            | Statement::Try { keyword, .. }
+            | Statement::Throw { keyword, .. }
+            | Statement::Defer { keyword, .. }
+            | Statement::Destructure { keyword, .. } => Some(keyword.line()),
+            Statement::Variable { name, .. }
+            | Statement::ForIn { name, .. }
+            | Statement::Class { name, .. } => Some(name.line()),
+            Statement::Block(_) => None,
+            Statement::If { condition, .. } | Statement::While { condition, .. } => {
+                condition.line()
+            }
+            Statement::Function(declaration) => Some(declaration.name.line()),
+        }
+    }
+
+    // How many statements and expressions make up this one, itself included.
+    pub fn node_count(&self) -> usize {
+        let expression = |expression: &Option<Expr>| {
+            expression
+                .as_ref()
+                .map_or(0, |expression| expression.node_count())
+        };
+        let children = match self {
+            Statement::Expression(value) | Statement::Print { value, .. } => value.node_count(),
+            Statement::Variable { initializer, .. } => expression(initializer),
+            Statement::Destructure { initializer, .. } => initializer.node_count(),
+            Statement::Block(statements) => count(statements),
+            Statement::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                condition.node_count()
+                    + then_branch.node_count()
+                    + else_branch.as_ref().map_or(0, |branch| branch.node_count())
+            }
+            Statement::While {
+                condition, body, ..
+            } => condition.node_count() + body.node_count(),
+            Statement::ForIn { iterable, body, .. } => iterable.node_count() + body.node_count(),
+            Statement::Function(declaration) => declaration.node_count(),
+            Statement::Return { value, .. } | Statement::Yield { value, .. } => expression(value),
+            Statement::Class {
+                superclass,
+                methods,
+                ..
+            } => {
+                expression(superclass)
+                    + methods
+                        .iter()
+                        .map(|method| 1 + method.node_count())
+                        .sum::<usize>()
+            }
+            Statement::Try { body, handler, .. } => count(body) + count(handler),
+            Statement::Throw { value, .. } => value.node_count(),
+            Statement::Defer { body, .. } => count(body),
+        };
+        1 + children
+    }
+}
+
+// A statement moves out the statements only it holds before it goes, so
+// that dropping deeply nested code doesn't recurse. Expressions need no such
+// care: their tree drops as one flat list of nodes.
+impl Drop for Statement {
+    fn drop(&mut self) {
+        let mut detached = Vec::new();
+        detach(self, &mut detached);
+        while let Some(mut statement) = detached.pop() {
+            detach(&mut statement, &mut detached);
+        }
+    }
+}
+
+// Moves each child statement nothing else shares onto `detached`, leaving an
+// empty block in its place.
+fn detach(statement: &mut Statement, detached: &mut Vec<Statement>) {
+    let mut take = |child: &mut Rc<Statement>| {
+        if let Some(child) = Rc::get_mut(child) {
+            detached.push(mem::replace(child, Statement::Block(Vec::new())));
+        }
+    };
+    match statement {
+        Statement::Block(body) | Statement::Defer { body, .. } => {
+            body.iter_mut().for_each(take);
+        }
+        Statement::Try { body, handler, .. } => {
+            body.iter_mut().chain(handler).for_each(take);
+        }
+        Statement::If {
+            then_branch,
+            else_branch,
+            ..
+        } => {
+            take(then_branch);
+            else_branch.iter_mut().for_each(take);
+        }
+        Statement::While { body, .. } | Statement::ForIn { body, .. } => take(body),
+        Statement::Function(declaration) => {
+            if let Some(declaration) = Rc::get_mut(declaration) {
+                declaration.body.iter_mut().for_each(take);
+            }
+        }
+        Statement::Class { methods, .. } => {
+            for method in methods.iter_mut().filter_map(Rc::get_mut) {
+                method.body.iter_mut().for_each(&mut take);
+            }
+        }
+        Statement::Expression(_)
+        | Statement::Print { .. }
+        | Statement::Variable { .. }
+        | Statement::Destructure { .. }
+        | Statement::Return { .. }
+        | Statement::Yield { .. }
+        | Statement::Throw { .. } => {}
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pattern {
+    // `[a, b]`, taking a list's elements in order.
+    Elements,
+    // `{x, y}`, taking an instance's fields or a map's keys by name.
+    Fields,
+    // `x, y`, taking a list's elements in order like `[x, y]`: the values a
+    // function gives with `return x, y;`.
+    Values,
+}
+
+// The nodes in a list of statements, as parsed from a program.
+pub fn count(statements: &[Rc<Statement>]) -> usize {
+    statements
+        .iter()
+        .map(|statement| statement.node_count())
+        .sum()
+}
+
+#[derive(Debug)]
+pub struct FunctionDeclaration {
+    pub name: Token,
+    pub parameters: Vec<Token>,
+    // The default values of the last parameters, which calls may leave out.
+    pub defaults: Vec<Expr>,
+    // `...name`, which gathers the arguments past the others into a list.
+    pub rest: Option<Token>,
+    pub body: Vec<Rc<Statement>>,
+    pub is_generator: bool,
+}
+
+impl FunctionDeclaration {
+    pub fn arity(&self) -> Arity {
+        Arity {
+            required: self.parameters.len() - self.defaults.len(),
+            optional: self.defaults.len(),
+            variadic: self.rest.is_some(),
+        }
+    }
+
+    // Every parameter in the order calls fill them, the gathering one last.
+    pub fn all_parameters(&self) -> impl Iterator<Item = &Token> {
+        self.parameters.iter().chain(&self.rest)
+    }
+
+    // The nodes in its default values and body.
+    fn node_count(&self) -> usize {
+        let defaults = self
+            .defaults
+            .iter()
+            .map(|default| default.node_count())
+            .sum::<usize>();
+        defaults + count(&self.body)
+    }
+}
@@ -0,0 +1,263 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::error::{LoxError, RuntimeErrorKind};
+use crate::io::CaptureIo;
+use crate::lox::{Engine, Lox};
+
+// Tags, taken from a test's top-level directory as in the craftinginterpreters
+// runner, for the parts of that suite that don't test the finished language:
+// the chapter 4 and 6 tests print tokens and trees instead of running, the
+// benchmarks take minutes, and the limits are clox's own.
+pub const SKIPPED_TAGS: &[&str] = &["benchmark", "expressions", "limit", "scanning"];
+
+// What a test expects, read from its comments in the suite's format:
+// "// expect: " for each line of output, "// expect runtime error: " for the
+// error that ends it, and "// Error..." or "// [line N] Error..." for compile
+// errors. Expectations marked "[c line N]" belong to clox's parser and are
+// ignored; "[java line N]" ones are ours.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expectations {
+    pub output: Vec<String>,
+    pub error: Option<String>,
+}
+
+impl Expectations {
+    pub fn parse(source: &str) -> Expectations {
+        let mut output = Vec::new();
+        let mut compile_errors = Vec::new();
+        let mut runtime_error = None;
+        for (index, line) in source.lines().enumerate() {
+            let number = index + 1;
+            if let Some(text) = after(line, "// expect:") {
+                output.push(text.strip_prefix(' ').unwrap_or(text).to_string());
+            } else if let Some(message) = after(line, "// expect runtime error: ") {
+                runtime_error = Some(format!("{}\n[line {}]", message, number));
+            } else if let Some(message) = after(line, "// Error") {
+                compile_errors.push(format!("[line {}] Error{}", number, message));
+            } else if let Some(error) = after(line, "// [java line ") {
+                compile_errors.push(format!("[line {}", error));
+            } else if let Some(error) = after(line, "// [line ") {
+                compile_errors.push(format!("[line {}", error));
+            }
+        }
+
+        let error = if compile_errors.is_empty() {
+            runtime_error
+        } else {
+            Some(compile_errors.join("\n"))
+        };
+        Expectations { output, error }
+    }
+
+    pub fn transcript(&self) -> Vec<String> {
+        transcript(&self.output.join("\n"), self.error.as_deref())
+    }
+}
+
+fn after<'a>(line: &'a str, marker: &str) -> Option<&'a str> {
+    line.find(marker).map(|start| &line[start + marker.len()..])
+}
+
+// What a run printed, then its error with each line marked.
+pub fn transcript(output: &str, error: Option<&str>) -> Vec<String> {
+    output
+        .lines()
+        .map(str::to_string)
+        .chain(
+            error
+                .into_iter()
+                .flat_map(str::lines)
+                .map(|line| format!("! {}", line)),
+        )
+        .collect()
+}
+
+// Line by line: unmarked where the two agree, "-" for what was expected and
+// "+" for what happened where they don't.
+pub fn diff(expected: &[String], actual: &[String]) -> String {
+    let mut lines = Vec::new();
+    for index in 0..expected.len().max(actual.len()) {
+        match (expected.get(index), actual.get(index)) {
+            (Some(expected), Some(actual)) if expected == actual => {
+                lines.push(format!("  {}", expected))
+            }
+            (expected, actual) => {
+                lines.extend(expected.map(|line| format!("- {}", line)));
+                lines.extend(actual.map(|line| format!("+ {}", line)));
+            }
+        }
+    }
+    lines.join("\n")
+}
+
+// Runs one test in a fresh session, returning the diff when it fails.
+pub fn check(source: &str, engine: Engine) -> Result<(), String> {
+    let capture = CaptureIo::new();
+    let mut lox = Lox::builder()
+        .with_io(Box::new(capture.clone()))
+        .engine(engine)
+        .build();
+    let error = lox.run(source).err().map(|error| error.to_string());
+
+    let expected = Expectations::parse(source).transcript();
+    let actual = transcript(&capture.stdout(), error.as_deref());
+    if actual == expected {
+        Ok(())
+    } else {
+        Err(diff(&expected, &actual))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorClass {
+    Compile,
+    Runtime,
+    ResourceExceeded,
+    Interrupted,
+}
+
+impl ErrorClass {
+    pub fn name(self) -> &'static str {
+        match self {
+            ErrorClass::Compile => "compile error",
+            ErrorClass::Runtime => "runtime error",
+            ErrorClass::ResourceExceeded => "resource limit",
+            ErrorClass::Interrupted => "interrupted",
+        }
+    }
+}
+
+// How a run ended, with the exit code the command line would have given it.
+// A call to exit() is not an error, only a code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Outcome {
+    pub stdout: String,
+    pub error: Option<ErrorClass>,
+    pub exit_code: i32,
+}
+
+pub fn outcome(source: &str, engine: Engine) -> Outcome {
+    let capture = CaptureIo::new();
+    let mut lox = Lox::builder()
+        .with_io(Box::new(capture.clone()))
+        .engine(engine)
+        .build();
+
+    let (error, exit_code) = match lox.run(source) {
+        Ok(()) => (None, 0),
+        Err(LoxError::Compile(_)) => (Some(ErrorClass::Compile), 65),
+        Err(LoxError::Runtime(error)) => match error.kind {
+            RuntimeErrorKind::Exit(code) => (None, code),
+            RuntimeErrorKind::Error | RuntimeErrorKind::Thrown => (Some(ErrorClass::Runtime), 70),
+            RuntimeErrorKind::ResourceExceeded => (Some(ErrorClass::ResourceExceeded), 70),
+            RuntimeErrorKind::Interrupted => (Some(ErrorClass::Interrupted), 70),
+        },
+    };
+    Outcome {
+        stdout: capture.stdout(),
+        error,
+        exit_code,
+    }
+}
+
+// Runs one program on the tree-walker and the VM, describing how they differ
+// when they do.
+pub fn compare(source: &str) -> Result<(), String> {
+    let walker = outcome(source, Engine::Walker);
+    let vm = outcome(source, Engine::Vm);
+    if walker == vm {
+        return Ok(());
+    }
+
+    let ending = |outcome: &Outcome| match outcome.error {
+        Some(class) => format!("{} (exit {})", class.name(), outcome.exit_code),
+        None => format!("exit {}", outcome.exit_code),
+    };
+    let mut report = format!("walker: {}\nvm: {}", ending(&walker), ending(&vm));
+    if walker.stdout != vm.stdout {
+        let lines = |stdout: &str| stdout.lines().map(str::to_string).collect::<Vec<_>>();
+        report.push('\n');
+        report.push_str(&diff(&lines(&walker.stdout), &lines(&vm.stdout)));
+    }
+    Err(report)
+}
+
+// The top-level directory a test sits in, if it isn't at the root.
+pub fn tag(relative: &Path) -> Option<&str> {
+    let mut components = relative.components();
+    let first = components.next()?;
+    components.next()?;
+    first.as_os_str().to_str()
+}
+
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub passed: usize,
+    pub skipped: usize,
+    pub failures: Vec<(PathBuf, String)>,
+}
+
+impl Summary {
+    // Each failure with its diff, then the counts.
+    pub fn report(&self) -> String {
+        let mut report = String::new();
+        for (path, diff) in &self.failures {
+            report.push_str(&format!("FAIL {}\n{}\n\n", path.display(), diff));
+        }
+        report.push_str(&format!(
+            "{} passed, {} failed, {} skipped\n",
+            self.passed,
+            self.failures.len(),
+            self.skipped
+        ));
+        report
+    }
+}
+
+// Runs every .lox file under root against its expectations.
+pub fn run(root: &Path, engine: Engine) -> io::Result<Summary> {
+    walk(root, |source| check(source, engine))
+}
+
+// Runs every .lox file under root on both backends, for differences between
+// them rather than from the expectations.
+pub fn differential(root: &Path) -> io::Result<Summary> {
+    walk(root, compare)
+}
+
+// Goes through the files in path order, skipping those whose tag is in
+// SKIPPED_TAGS. Failures are recorded relative to root.
+fn walk(root: &Path, test: impl Fn(&str) -> Result<(), String>) -> io::Result<Summary> {
+    let mut files = Vec::new();
+    lox_files(root, &mut files)?;
+    files.sort();
+
+    let mut summary = Summary::default();
+    for path in files {
+        let relative = path.strip_prefix(root).unwrap_or(&path).to_path_buf();
+        if tag(&relative).is_some_and(|tag| SKIPPED_TAGS.contains(&tag)) {
+            summary.skipped += 1;
+            continue;
+        }
+
+        match test(&fs::read_to_string(&path)?) {
+            Ok(()) => summary.passed += 1,
+            Err(diff) => summary.failures.push((relative, diff)),
+        }
+    }
+    Ok(summary)
+}
+
+fn lox_files(directory: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            lox_files(&path, files)?;
+        } else if path.extension().is_some_and(|extension| extension == "lox") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,290 @@
+use core::cmp::Ordering;
+use core::fmt;
+use core::hash::{Hash, Hasher};
+use core::mem;
+use core::ops::Deref;
+
+use crate::prelude::*;
+
+// An identifier with its hash worked out once. Symbols made from the same
+// interned string compare by pointer; others fall back to the hash and then
+// the text, so a symbol built on the spot still finds its entry.
+#[derive(Clone)]
+pub struct Symbol {
+    text: Rc<str>,
+    hash: u64,
+}
+
+impl Symbol {
+    pub fn new(text: &str) -> Symbol {
+        Symbol::from(Rc::<str>::from(text))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    pub fn text(&self) -> &Rc<str> {
+        &self.text
+    }
+}
+
+impl From<Rc<str>> for Symbol {
+    fn from(text: Rc<str>) -> Symbol {
+        let hash = hash(&text);
+        Symbol { text, hash }
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(text: &str) -> Symbol {
+        Symbol::new(text)
+    }
+}
+
+impl From<String> for Symbol {
+    fn from(text: String) -> Symbol {
+        Symbol::from(Rc::<str>::from(text))
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.text
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Symbol) -> bool {
+        Rc::ptr_eq(&self.text, &other.text) || (self.hash == other.hash && self.text == other.text)
+    }
+}
+
+impl Eq for Symbol {}
+
+impl PartialOrd for Symbol {
+    fn partial_cmp(&self, other: &Symbol) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Symbol {
+    fn cmp(&self, other: &Symbol) -> Ordering {
+        self.text.cmp(&other.text)
+    }
+}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.hash);
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{:?}", &*self.text)
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str(&self.text)
+    }
+}
+
+// FNV-1a, which is quick on the short strings identifiers tend to be.
+fn hash(text: &str) -> u64 {
+    text.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+// Hands out one shared string per distinct identifier, so that symbols for
+// the same name compare by pointer.
+#[derive(Default)]
+pub struct Interner {
+    symbols: SymbolTable<()>,
+}
+
+impl Interner {
+    pub fn new() -> Interner {
+        Interner::default()
+    }
+
+    pub fn intern(&mut self, text: &str) -> Symbol {
+        if let Some((symbol, _)) = self.symbols.entry(hash(text), text) {
+            return symbol.clone();
+        }
+        let symbol = Symbol::new(text);
+        self.symbols.insert(symbol.clone(), ());
+        symbol
+    }
+}
+
+const MIN_CAPACITY: usize = 8;
+
+// An open-addressing table keyed by symbols, probing linearly from the
+// cached hash. Removing an entry shifts the ones probed past it back, so
+// there are no tombstones; the table doubles once it is three quarters full.
+#[derive(Clone)]
+pub struct SymbolTable<V> {
+    slots: Vec<Option<(Symbol, V)>>,
+    len: usize,
+}
+
+impl<V> Default for SymbolTable<V> {
+    fn default() -> SymbolTable<V> {
+        SymbolTable::new()
+    }
+}
+
+impl<V> SymbolTable<V> {
+    pub fn new() -> SymbolTable<V> {
+        SymbolTable {
+            slots: Vec::new(),
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, name: &str) -> Option<&V> {
+        self.entry(hash(name), name).map(|(_, value)| value)
+    }
+
+    pub fn get_symbol(&self, symbol: &Symbol) -> Option<&V> {
+        let index = self.find(symbol.hash, |key| key == symbol)?;
+        self.slots[index].as_ref().map(|(_, value)| value)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut V> {
+        let hash = hash(name);
+        let index = self.find(hash, |key| key.hash == hash && key.as_str() == name)?;
+        self.slots[index].as_mut().map(|(_, value)| value)
+    }
+
+    pub fn get_symbol_mut(&mut self, symbol: &Symbol) -> Option<&mut V> {
+        let index = self.find(symbol.hash, |key| key == symbol)?;
+        self.slots[index].as_mut().map(|(_, value)| value)
+    }
+
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    // Returns the value the name had before, if any.
+    pub fn insert(&mut self, symbol: Symbol, value: V) -> Option<V> {
+        if (self.len + 1) * 4 > self.slots.len() * 3 {
+            self.grow();
+        }
+
+        let mask = self.slots.len() - 1;
+        let mut index = symbol.hash as usize & mask;
+        loop {
+            match &mut self.slots[index] {
+                Some((key, current)) if *key == symbol => {
+                    return Some(mem::replace(current, value))
+                }
+                Some(_) => index = (index + 1) & mask,
+                empty => {
+                    *empty = Some((symbol, value));
+                    self.len += 1;
+                    return None;
+                }
+            }
+        }
+    }
+
+    // Returns the value the name had, if any.
+    pub fn remove(&mut self, name: &str) -> Option<V> {
+        let hash = hash(name);
+        let mut index = self.find(hash, |key| key.hash == hash && key.as_str() == name)?;
+        let (_, value) = self.slots[index].take()?;
+        self.len -= 1;
+
+        // An entry further along the run may only move back into the hole
+        // if the hole lies between its home slot and where it sits now.
+        let mask = self.slots.len() - 1;
+        let mut next = (index + 1) & mask;
+        while let Some((key, _)) = &self.slots[next] {
+            let home = key.hash as usize & mask;
+            if (next.wrapping_sub(home) & mask) >= (next.wrapping_sub(index) & mask) {
+                self.slots[index] = self.slots[next].take();
+                index = next;
+            }
+            next = (next + 1) & mask;
+        }
+        Some(value)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Symbol, &V)> {
+        self.slots
+            .iter()
+            .flatten()
+            .map(|(symbol, value)| (symbol, value))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &Symbol> {
+        self.iter().map(|(symbol, _)| symbol)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.iter().map(|(_, value)| value)
+    }
+
+    fn entry(&self, hash: u64, name: &str) -> Option<(&Symbol, &V)> {
+        let index = self.find(hash, |key| key.hash == hash && key.as_str() == name)?;
+        self.slots[index]
+            .as_ref()
+            .map(|(symbol, value)| (symbol, value))
+    }
+
+    fn find(&self, hash: u64, matches: impl Fn(&Symbol) -> bool) -> Option<usize> {
+        if self.slots.is_empty() {
+            return None;
+        }
+
+        let mask = self.slots.len() - 1;
+        let mut index = hash as usize & mask;
+        loop {
+            match &self.slots[index] {
+                Some((key, _)) if matches(key) => return Some(index),
+                Some(_) => index = (index + 1) & mask,
+                None => return None,
+            }
+        }
+    }
+
+    fn grow(&mut self) {
+        let capacity = (self.slots.len() * 2).max(MIN_CAPACITY);
+        let slots = mem::replace(&mut self.slots, (0..capacity).map(|_| None).collect());
+        self.len = 0;
+        for (symbol, value) in slots.into_iter().flatten() {
+            self.insert(symbol, value);
+        }
+    }
+}
+
+impl<V> FromIterator<(Symbol, V)> for SymbolTable<V> {
+    fn from_iter<I: IntoIterator<Item = (Symbol, V)>>(entries: I) -> SymbolTable<V> {
+        let mut table = SymbolTable::new();
+        for (symbol, value) in entries {
+            table.insert(symbol, value);
+        }
+        table
+    }
+}
+
+impl<V: fmt::Debug> fmt::Debug for SymbolTable<V> {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.debug_map().entries(self.iter()).finish()
+    }
+}
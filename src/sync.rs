@@ -0,0 +1,168 @@
+use std::collections::BTreeMap;
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+
+use crate::cancel::CancelHandle;
+use crate::error::{ErrorCategory, LoxError};
+use crate::value::Value;
+use crate::Lox;
+
+const MAX_DEPTH: usize = 512;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SharedValue {
+    Nil,
+    Boolean(bool),
+    Number(f64),
+    Int(i64),
+    String(String),
+    List(Vec<SharedValue>),
+    Map(BTreeMap<String, SharedValue>),
+}
+
+impl SharedValue {
+    pub fn from_value(value: &Value) -> Result<SharedValue, String> {
+        SharedValue::convert(value, 0)
+    }
+
+    fn convert(value: &Value, depth: usize) -> Result<SharedValue, String> {
+        if depth > MAX_DEPTH {
+            return Err("Value is nested too deeply to share across threads.".to_string());
+        }
+
+        match value {
+            Value::Nil => Ok(SharedValue::Nil),
+            Value::Boolean(boolean) => Ok(SharedValue::Boolean(*boolean)),
+            Value::Number(number) => Ok(SharedValue::Number(*number)),
+            Value::Int(integer) => Ok(SharedValue::Int(*integer)),
+            Value::String(string) => Ok(SharedValue::String(string.to_string())),
+            Value::List(list) => list
+                .borrow()
+                .iter()
+                .map(|element| SharedValue::convert(element, depth + 1))
+                .collect::<Result<Vec<SharedValue>, String>>()
+                .map(SharedValue::List),
+            Value::Map(map) => map
+                .borrow()
+                .iter()
+                .map(|(key, value)| Ok((key.clone(), SharedValue::convert(value, depth + 1)?)))
+                .collect::<Result<BTreeMap<String, SharedValue>, String>>()
+                .map(SharedValue::Map),
+            other => Err(format!(
+                "Can't share a {} across threads.",
+                other.type_name()
+            )),
+        }
+    }
+
+    pub fn into_value(self) -> Value {
+        match self {
+            SharedValue::Nil => Value::Nil,
+            SharedValue::Boolean(boolean) => Value::Boolean(boolean),
+            SharedValue::Number(number) => Value::Number(number),
+            SharedValue::Int(integer) => Value::Int(integer),
+            SharedValue::String(string) => Value::String(string.into()),
+            SharedValue::List(elements) => Value::from(
+                elements
+                    .into_iter()
+                    .map(SharedValue::into_value)
+                    .collect::<Vec<Value>>(),
+            ),
+            SharedValue::Map(entries) => Value::from(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key, value.into_value()))
+                    .collect::<BTreeMap<String, Value>>(),
+            ),
+        }
+    }
+}
+
+type Job = Box<dyn FnOnce(&mut Lox) + Send>;
+
+#[derive(Clone)]
+pub struct LoxHandle {
+    sender: Sender<Job>,
+    cancel: CancelHandle,
+}
+
+impl LoxHandle {
+    pub fn spawn<F>(build: F) -> LoxHandle
+    where
+        F: FnOnce() -> Lox + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let (cancel_sender, cancel_receiver) = mpsc::channel();
+
+        thread::spawn(move || {
+            let mut lox = build();
+            let _ = cancel_sender.send(lox.cancel_handle());
+
+            while let Ok(job) = receiver.recv() {
+                job(&mut lox);
+            }
+        });
+
+        let cancel = cancel_receiver
+            .recv()
+            .expect("Lox worker thread stopped during startup");
+
+        LoxHandle { sender, cancel }
+    }
+
+    pub fn with<F, R>(&self, job: F) -> R
+    where
+        F: FnOnce(&mut Lox) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+        self.sender
+            .send(Box::new(move |lox: &mut Lox| {
+                let _ = sender.send(job(lox));
+            }))
+            .expect("Lox worker thread stopped");
+
+        receiver.recv().expect("Lox worker thread stopped")
+    }
+
+    pub fn run(&self, source: &str) -> Result<(), LoxError> {
+        let source = source.to_string();
+        self.with(move |lox| lox.run(&source))
+    }
+
+    pub fn eval(&self, source: &str) -> Result<SharedValue, LoxError> {
+        let source = source.to_string();
+        self.with(move |lox| {
+            let value = lox.eval(&source)?;
+            share(lox, &value)
+        })
+    }
+
+    pub fn call(&self, name: &str, arguments: Vec<SharedValue>) -> Result<SharedValue, LoxError> {
+        let name = name.to_string();
+        self.with(move |lox| {
+            let Some(function) = lox.get_global(&name) else {
+                let message = format!("Undefined variable '{}'.", name);
+                let error = lox.interpreter().runtime_error(message);
+                return Err(LoxError::Runtime(error.with_category(ErrorCategory::Name)));
+            };
+
+            let arguments: Vec<Value> =
+                arguments.into_iter().map(SharedValue::into_value).collect();
+            let value = lox.call(&function, &arguments).map_err(LoxError::Runtime)?;
+            share(lox, &value)
+        })
+    }
+
+    pub fn cancel(&self) {
+        self.cancel.cancel();
+    }
+}
+
+fn share(lox: &mut Lox, value: &Value) -> Result<SharedValue, LoxError> {
+    SharedValue::from_value(value).map_err(|message| unshareable(lox, message))
+}
+
+fn unshareable(lox: &mut Lox, message: String) -> LoxError {
+    LoxError::Runtime(lox.interpreter().runtime_error(message))
+}
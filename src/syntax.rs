@@ -0,0 +1,1501 @@
+use core::fmt::{self, Write};
+use core::iter;
+use core::ops::Range;
+use core::slice;
+
+use crate::analysis::{self, TextEdit};
+use crate::error::Error;
+use crate::parser::{CHAIN_LINKS_PER_LEVEL, DEFAULT_MAX_NESTING_DEPTH};
+use crate::prelude::*;
+use crate::scanner::{self, Scanner};
+use crate::token::{Keyword, Kind};
+
+// The leaves of the tree: the tokens the parser reads, and the trivia it
+// skips between them.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenKind {
+    Code(Kind),
+    Whitespace,
+    Comment,
+    // Characters the scanner rejected.
+    Invalid,
+}
+
+impl TokenKind {
+    pub fn is_trivia(&self) -> bool {
+        !matches!(self, TokenKind::Code(_))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+    Program,
+    ClassDeclaration,
+    FunctionDeclaration,
+    ParameterList,
+    VarDeclaration,
+    Block,
+    ExpressionStatement,
+    PrintStatement,
+    IfStatement,
+    WhileStatement,
+    ForStatement,
+    ForInStatement,
+    ReturnStatement,
+    YieldStatement,
+    TryStatement,
+    ThrowStatement,
+    DeferStatement,
+    Literal,
+    Variable,
+    This,
+    Super,
+    Grouping,
+    Unary,
+    Binary,
+    Logical,
+    Assign,
+    Call,
+    ArgumentList,
+    Get,
+    Index,
+    List,
+    Map,
+    MapEntry,
+    // Tokens the parser could not place.
+    Error,
+}
+
+impl NodeKind {
+    pub fn is_statement(&self) -> bool {
+        matches!(
+            self,
+            NodeKind::ClassDeclaration
+                | NodeKind::FunctionDeclaration
+                | NodeKind::VarDeclaration
+                | NodeKind::Block
+                | NodeKind::ExpressionStatement
+                | NodeKind::PrintStatement
+                | NodeKind::IfStatement
+                | NodeKind::WhileStatement
+                | NodeKind::ForStatement
+                | NodeKind::ForInStatement
+                | NodeKind::ReturnStatement
+                | NodeKind::YieldStatement
+                | NodeKind::TryStatement
+                | NodeKind::ThrowStatement
+                | NodeKind::DeferStatement
+        )
+    }
+
+    pub fn is_expression(&self) -> bool {
+        matches!(
+            self,
+            NodeKind::Literal
+                | NodeKind::Variable
+                | NodeKind::This
+                | NodeKind::Super
+                | NodeKind::Grouping
+                | NodeKind::Unary
+                | NodeKind::Binary
+                | NodeKind::Logical
+                | NodeKind::Assign
+                | NodeKind::Call
+                | NodeKind::Get
+                | NodeKind::Index
+                | NodeKind::List
+                | NodeKind::Map
+        )
+    }
+}
+
+// Binary operators from the loosest binding to the tightest, with the node
+// each makes.
+const OPERATORS: [(NodeKind, &[Kind]); 6] = [
+    (NodeKind::Logical, &[Kind::Keyword(Keyword::Or)]),
+    (NodeKind::Logical, &[Kind::Keyword(Keyword::And)]),
+    (
+        NodeKind::Binary,
+        &[Kind::ExclamationEqual, Kind::EqualEqual],
+    ),
+    (
+        NodeKind::Binary,
+        &[
+            Kind::Greater,
+            Kind::GreaterEqual,
+            Kind::Less,
+            Kind::LessEqual,
+        ],
+    ),
+    (NodeKind::Binary, &[Kind::Minus, Kind::Plus]),
+    (
+        NodeKind::Binary,
+        &[Kind::Slash, Kind::Asterisk, Kind::Keyword(Keyword::Div)],
+    ),
+];
+
+// Green tokens and nodes know their kind, their text or children and their
+// width in characters, but not where they are, so an unchanged subtree can
+// be shared between versions of a document.
+#[derive(Debug, PartialEq)]
+pub struct GreenToken {
+    kind: TokenKind,
+    text: String,
+    width: usize,
+}
+
+impl GreenToken {
+    pub fn new(kind: TokenKind, text: String) -> GreenToken {
+        let width = text.chars().count();
+        GreenToken { kind, text, width }
+    }
+
+    pub fn kind(&self) -> &TokenKind {
+        &self.kind
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct GreenNode {
+    kind: NodeKind,
+    width: usize,
+    children: Vec<GreenElement>,
+}
+
+impl GreenNode {
+    pub fn new(kind: NodeKind, children: Vec<GreenElement>) -> GreenNode {
+        let width = children.iter().map(GreenElement::width).sum();
+        GreenNode {
+            kind,
+            width,
+            children,
+        }
+    }
+
+    pub fn kind(&self) -> NodeKind {
+        self.kind
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn children(&self) -> &[GreenElement] {
+        &self.children
+    }
+
+    // A copy sharing every child but the one at `index`.
+    pub fn replace_child(&self, index: usize, child: GreenElement) -> GreenNode {
+        let mut children = self.children.clone();
+        children[index] = child;
+        GreenNode::new(self.kind, children)
+    }
+
+    fn write_text(&self, text: &mut String) {
+        for child in &self.children {
+            match child {
+                GreenElement::Node(node) => node.write_text(text),
+                GreenElement::Token(token) => text.push_str(&token.text),
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GreenElement {
+    Node(Rc<GreenNode>),
+    Token(Rc<GreenToken>),
+}
+
+impl GreenElement {
+    pub fn width(&self) -> usize {
+        match self {
+            GreenElement::Node(node) => node.width,
+            GreenElement::Token(token) => token.width,
+        }
+    }
+}
+
+// A green node seen from its place in one tree: red nodes are made on the
+// way down from the root and know their parent and offset.
+#[derive(Clone)]
+pub struct SyntaxNode(Rc<NodeData>);
+
+struct NodeData {
+    green: Rc<GreenNode>,
+    parent: Option<SyntaxNode>,
+    index: usize,
+    offset: usize,
+}
+
+impl SyntaxNode {
+    pub fn new_root(green: Rc<GreenNode>) -> SyntaxNode {
+        SyntaxNode(Rc::new(NodeData {
+            green,
+            parent: None,
+            index: 0,
+            offset: 0,
+        }))
+    }
+
+    pub fn kind(&self) -> NodeKind {
+        self.0.green.kind
+    }
+
+    pub fn green(&self) -> &Rc<GreenNode> {
+        &self.0.green
+    }
+
+    pub fn parent(&self) -> Option<SyntaxNode> {
+        self.0.parent.clone()
+    }
+
+    // The nodes this one is below, from its parent out to the root.
+    pub fn ancestors(&self) -> Vec<SyntaxNode> {
+        iter::successors(self.parent(), SyntaxNode::parent).collect()
+    }
+
+    // Counted in characters from the start of the source.
+    pub fn range(&self) -> Range<usize> {
+        self.0.offset..self.0.offset + self.0.green.width
+    }
+
+    pub fn children_with_tokens(&self) -> Vec<SyntaxElement> {
+        let mut offset = self.0.offset;
+        let mut elements = Vec::new();
+        for (index, child) in self.0.green.children.iter().enumerate() {
+            elements.push(match child {
+                GreenElement::Node(green) => SyntaxElement::Node(SyntaxNode(Rc::new(NodeData {
+                    green: Rc::clone(green),
+                    parent: Some(self.clone()),
+                    index,
+                    offset,
+                }))),
+                GreenElement::Token(green) => SyntaxElement::Token(SyntaxToken {
+                    green: Rc::clone(green),
+                    parent: self.clone(),
+                    index,
+                    offset,
+                }),
+            });
+            offset += child.width();
+        }
+        elements
+    }
+
+    pub fn children(&self) -> Vec<SyntaxNode> {
+        self.children_with_tokens()
+            .into_iter()
+            .filter_map(|element| match element {
+                SyntaxElement::Node(node) => Some(node),
+                SyntaxElement::Token(_) => None,
+            })
+            .collect()
+    }
+
+    // This node and every node below it, parents before their children.
+    pub fn descendants(&self) -> Vec<SyntaxNode> {
+        let mut nodes = vec![self.clone()];
+        for child in self.children() {
+            nodes.extend(child.descendants());
+        }
+        nodes
+    }
+
+    // Every leaf below this node, trivia included, in source order.
+    pub fn tokens(&self) -> Vec<SyntaxToken> {
+        let mut tokens = Vec::new();
+        for element in self.children_with_tokens() {
+            match element {
+                SyntaxElement::Node(node) => tokens.extend(node.tokens()),
+                SyntaxElement::Token(token) => tokens.push(token),
+            }
+        }
+        tokens
+    }
+
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        self.0.green.write_text(&mut text);
+        text
+    }
+
+    // The leaf covering the character at `offset`.
+    pub fn token_at(&self, offset: usize) -> Option<SyntaxToken> {
+        self.children_with_tokens()
+            .into_iter()
+            .find(|element| element.range().contains(&offset))
+            .and_then(|element| match element {
+                SyntaxElement::Node(node) => node.token_at(offset),
+                SyntaxElement::Token(token) => Some(token),
+            })
+    }
+
+    // The root of a new tree in which this node is replaced; everything off
+    // the path from here to the root is shared with the old tree.
+    pub fn replace_with(&self, replacement: Rc<GreenNode>) -> Rc<GreenNode> {
+        let mut green = replacement;
+        let mut node = self.clone();
+        while let Some(parent) = node.parent() {
+            let child = GreenElement::Node(green);
+            green = Rc::new(parent.0.green.replace_child(node.0.index, child));
+            node = parent;
+        }
+        green
+    }
+
+    // One line per node and token, indented by depth, for tests and
+    // debugging.
+    pub fn dump(&self) -> String {
+        let mut dump = String::new();
+        self.write_dump(&mut dump, 0);
+        dump
+    }
+
+    fn write_dump(&self, dump: &mut String, depth: usize) {
+        let _ = writeln!(dump, "{:indent$}{:?}", "", self, indent = depth * 2);
+        for element in self.children_with_tokens() {
+            match element {
+                SyntaxElement::Node(node) => node.write_dump(dump, depth + 1),
+                SyntaxElement::Token(token) => {
+                    let _ = writeln!(dump, "{:indent$}{:?}", "", token, indent = depth * 2 + 2);
+                }
+            }
+        }
+    }
+}
+
+impl PartialEq for SyntaxNode {
+    fn eq(&self, other: &SyntaxNode) -> bool {
+        Rc::ptr_eq(&self.0.green, &other.0.green) && self.0.offset == other.0.offset
+    }
+}
+
+impl fmt::Debug for SyntaxNode {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let range = self.range();
+        write!(
+            formatter,
+            "{:?}@{}..{}",
+            self.kind(),
+            range.start,
+            range.end
+        )
+    }
+}
+
+#[derive(Clone, PartialEq)]
+pub struct SyntaxToken {
+    green: Rc<GreenToken>,
+    parent: SyntaxNode,
+    index: usize,
+    offset: usize,
+}
+
+impl SyntaxToken {
+    pub fn kind(&self) -> &TokenKind {
+        &self.green.kind
+    }
+
+    pub fn text(&self) -> &str {
+        &self.green.text
+    }
+
+    pub fn range(&self) -> Range<usize> {
+        self.offset..self.offset + self.green.width
+    }
+
+    pub fn parent(&self) -> SyntaxNode {
+        self.parent.clone()
+    }
+
+    // The root of a new tree in which this token is replaced.
+    pub fn replace_with(&self, replacement: GreenToken) -> Rc<GreenNode> {
+        let child = GreenElement::Token(Rc::new(replacement));
+        let parent = self.parent.0.green.replace_child(self.index, child);
+        self.parent.replace_with(Rc::new(parent))
+    }
+}
+
+impl fmt::Debug for SyntaxToken {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let range = self.range();
+        write!(
+            formatter,
+            "{:?}@{}..{} {:?}",
+            self.kind(),
+            range.start,
+            range.end,
+            self.text()
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(SyntaxToken),
+}
+
+impl SyntaxElement {
+    pub fn range(&self) -> Range<usize> {
+        match self {
+            SyntaxElement::Node(node) => node.range(),
+            SyntaxElement::Token(token) => token.range(),
+        }
+    }
+}
+
+// A concrete syntax tree and the errors found building it. The tree holds
+// every character of the source whatever the errors.
+pub struct Parse {
+    green: Rc<GreenNode>,
+    errors: Vec<Error>,
+    // Where each error was found, in characters.
+    offsets: Vec<usize>,
+}
+
+impl Parse {
+    fn new(green: Rc<GreenNode>, mut errors: Vec<(usize, Error)>) -> Parse {
+        errors.sort_by_key(|(offset, _)| *offset);
+        let (offsets, errors) = errors.into_iter().unzip();
+        Parse {
+            green,
+            errors,
+            offsets,
+        }
+    }
+
+    pub fn green(&self) -> &Rc<GreenNode> {
+        &self.green
+    }
+
+    pub fn syntax(&self) -> SyntaxNode {
+        SyntaxNode::new_root(Rc::clone(&self.green))
+    }
+
+    // In source order.
+    pub fn errors(&self) -> &[Error] {
+        &self.errors
+    }
+
+    // The parse of the source after `edit`, rebuilding as little as it can:
+    // an edit within a name, a comment or whitespace that leaves it one
+    // replaces that token, and one within a block that keeps its braces
+    // matched reparses the block. Anything else parses the source again.
+    pub fn reparse(&self, edit: &TextEdit) -> Parse {
+        let root = self.syntax();
+        self.reparse_token(&root, edit)
+            .or_else(|| self.reparse_block(&root, edit))
+            .unwrap_or_else(|| parse(&analysis::apply(&root.text(), slice::from_ref(edit))))
+    }
+
+    fn reparse_token(&self, root: &SyntaxNode, edit: &TextEdit) -> Option<Parse> {
+        let (start, end) = (edit.position.start, edit.position.current);
+        [start.checked_sub(1), Some(start)]
+            .into_iter()
+            .flatten()
+            .filter_map(|offset| root.token_at(offset))
+            .find_map(|token| {
+                let range = token.range();
+                if start < range.start || range.end < end {
+                    return None;
+                }
+                let text = splice(
+                    token.text(),
+                    start - range.start..end - range.start,
+                    &edit.text,
+                );
+                let after_comment = range
+                    .start
+                    .checked_sub(1)
+                    .and_then(|offset| root.token_at(offset))
+                    .is_some_and(|token| *token.kind() == TokenKind::Comment);
+                let kind = match token.kind() {
+                    // A comment runs on to the end of its line.
+                    TokenKind::Whitespace
+                        if !text.is_empty()
+                            && (text.starts_with('\n') || !after_comment)
+                            && text
+                                .chars()
+                                .all(|character| matches!(character, ' ' | '\r' | '\t' | '\n')) =>
+                    {
+                        TokenKind::Whitespace
+                    }
+                    TokenKind::Comment if text.starts_with("//") && !text.contains('\n') => {
+                        TokenKind::Comment
+                    }
+                    TokenKind::Code(Kind::Identifier(_)) if scanner::is_identifier(&text) => {
+                        TokenKind::Code(Kind::Identifier(text.clone()))
+                    }
+                    _ => return None,
+                };
+
+                let green = token.replace_with(GreenToken::new(kind, text.clone()));
+                Some(self.spliced(green, range.start, token.text(), &text, Vec::new()))
+            })
+    }
+
+    fn reparse_block(&self, root: &SyntaxNode, edit: &TextEdit) -> Option<Parse> {
+        let (start, end) = (edit.position.start, edit.position.current);
+        let block = covering_block(root, start, end)?;
+        let range = block.range();
+        let old = block.text();
+        let text = splice(&old, start - range.start..end - range.start, &edit.text);
+
+        let line = 1 + newlines(&self.green, range.start);
+        let mut builder = Builder::new(&text, line, range.start);
+        if !builder.balanced() {
+            return None;
+        }
+        // At least as deep as the parser was when it reached the block.
+        builder.depth = block.ancestors().len() - 1;
+        match block.parent().map(|parent| parent.kind()) {
+            Some(NodeKind::FunctionDeclaration) => builder.block(),
+            _ => builder.statement(),
+        }
+        if !builder.finished() || builder.gave_up {
+            return None;
+        }
+        let (_, children) = builder.stack.pop()?;
+        let [GreenElement::Node(green)] = children.as_slice() else {
+            return None;
+        };
+        // Recovery that ran past the closing brace would have gone on past
+        // it in the whole source too.
+        let closed = matches!(
+            green.children.last(),
+            Some(GreenElement::Token(token))
+                if token.kind == TokenKind::Code(Kind::CloseCurlyBracket)
+        );
+        if green.kind != NodeKind::Block || !closed {
+            return None;
+        }
+
+        let green = block.replace_with(Rc::clone(green));
+        Some(self.spliced(green, range.start, &old, &text, builder.errors))
+    }
+
+    // The parse with `green` as its tree, after `old` at `start` became
+    // `new`: errors found within `old` give way to `errors`, and those after
+    // it move along.
+    fn spliced(
+        &self,
+        green: Rc<GreenNode>,
+        start: usize,
+        old: &str,
+        new: &str,
+        mut errors: Vec<(usize, Error)>,
+    ) -> Parse {
+        let end = start + old.chars().count();
+        let shift = new.chars().count() as isize - old.chars().count() as isize;
+        let lines = new.matches('\n').count() as isize - old.matches('\n').count() as isize;
+        for (offset, error) in self.offsets.iter().zip(&self.errors) {
+            if *offset <= start {
+                errors.push((*offset, error.clone()));
+            } else if *offset >= end {
+                let error = Error {
+                    message: error.message.clone(),
+                    line: (error.line as isize + lines) as usize,
+                    location: error.location.clone(),
+                };
+                errors.push(((*offset as isize + shift) as usize, error));
+            }
+        }
+        Parse::new(green, errors)
+    }
+}
+
+// Parses the grammar the parser does, but keeps every token and the trivia
+// between them, and recovers from errors instead of dropping statements.
+// Desugaring is left to the parser: a for loop stays a for loop.
+pub fn parse(source: &str) -> Parse {
+    let mut builder = Builder::new(source, 1, 0);
+    while !builder.finished() {
+        builder.recovering(Builder::declaration);
+    }
+    builder.trivia();
+    let (kind, children) = builder
+        .stack
+        .pop()
+        .unwrap_or((NodeKind::Program, Vec::new()));
+
+    Parse::new(Rc::new(GreenNode::new(kind, children)), builder.errors)
+}
+
+// The innermost block around the characters from `start` to `end`, not
+// counting its braces.
+fn covering_block(root: &SyntaxNode, start: usize, end: usize) -> Option<SyntaxNode> {
+    let mut block = None;
+    let mut node = root.clone();
+    while let Some(child) = node.children().into_iter().find(|child| {
+        let range = child.range();
+        range.start < start && end < range.end
+    }) {
+        if child.kind() == NodeKind::Block {
+            block = Some(child.clone());
+        }
+        node = child;
+    }
+    block
+}
+
+// Newlines in the first `offset` characters below `node`.
+fn newlines(node: &GreenNode, offset: usize) -> usize {
+    let mut count = 0;
+    let mut position = 0;
+    for child in &node.children {
+        if position >= offset {
+            break;
+        }
+        count += match child {
+            GreenElement::Node(child) => newlines(child, offset - position),
+            GreenElement::Token(token) => token
+                .text
+                .chars()
+                .take(offset - position)
+                .filter(|&character| character == '\n')
+                .count(),
+        };
+        position += child.width();
+    }
+    count
+}
+
+// `text` with the characters in `range` replaced.
+fn splice(text: &str, range: Range<usize>, replacement: &str) -> String {
+    let characters: Vec<char> = text.chars().collect();
+    let mut spliced: String = characters[..range.start].iter().collect();
+    spliced.push_str(replacement);
+    spliced.extend(&characters[range.end..]);
+    spliced
+}
+
+struct Leaf {
+    kind: TokenKind,
+    text: String,
+    offset: usize,
+    line: usize,
+}
+
+// Every character of `source` in one leaf or another: the scanner's tokens,
+// and the gaps between them split into whitespace and rejected text. The
+// source starts at `offset` on `line` of a larger one. Also the line the
+// source ends on.
+fn leaves(source: &str, line: usize, offset: usize) -> (Vec<Leaf>, usize, Vec<(usize, Error)>) {
+    let characters: Vec<char> = source.chars().collect();
+    let mut scanner = Scanner::new(source.to_string());
+    scanner.set_trivia(true);
+    let (tokens, errors) = scanner.scan_all();
+    let base = offset;
+    let line_base = line - 1;
+    let errors = errors
+        .into_iter()
+        .map(|(offset, error)| {
+            let error = Error {
+                message: error.message,
+                line: error.line + line_base,
+                location: error.location,
+            };
+            (base + offset, error)
+        })
+        .collect();
+
+    let mut leaves = Vec::new();
+    let gap = |leaves: &mut Vec<Leaf>, range: Range<usize>, line: usize| {
+        let text = &characters[range.clone()];
+        let blank = |character: &char| matches!(character, ' ' | '\r' | '\t' | '\n');
+        let mut start = 0;
+        while start < text.len() {
+            let whitespace = blank(&text[start]);
+            let length = text[start..]
+                .iter()
+                .take_while(|character| blank(character) == whitespace)
+                .count();
+            leaves.push(Leaf {
+                kind: if whitespace {
+                    TokenKind::Whitespace
+                } else {
+                    TokenKind::Invalid
+                },
+                text: text[start..start + length].iter().collect(),
+                offset: base + range.start + start,
+                line,
+            });
+            start += length;
+        }
+    };
+
+    let mut offset = 0;
+    let mut end_line = 1;
+    for token in tokens {
+        if token.kind == Kind::EndOfFile {
+            end_line = token.line();
+            break;
+        }
+        gap(
+            &mut leaves,
+            offset..token.position.start,
+            token.line() + line_base,
+        );
+        leaves.push(Leaf {
+            kind: match token.kind {
+                Kind::Comment(_) => TokenKind::Comment,
+                kind => TokenKind::Code(kind),
+            },
+            text: characters[token.position.start..token.position.current]
+                .iter()
+                .collect(),
+            offset: base + token.position.start,
+            line: token.position.line + line_base,
+        });
+        offset = token.position.current;
+    }
+    gap(&mut leaves, offset..characters.len(), end_line + line_base);
+
+    (leaves, end_line + line_base, errors)
+}
+
+// Builds green nodes on a stack as it parses. Trivia is attached to the
+// innermost open node when the next token is read, so each node starts at
+// a token of its own; a node's checkpoint lets an operator wrap the operand
+// parsed before it.
+struct Builder {
+    leaves: Vec<Leaf>,
+    position: usize,
+    // Tokens other than trivia read so far.
+    consumed: usize,
+    stack: Vec<(NodeKind, Vec<GreenElement>)>,
+    errors: Vec<(usize, Error)>,
+    // Where the last error was reported, so that a construct missing several
+    // tokens in a row is reported once.
+    last_error: Option<usize>,
+    depth: usize,
+    // The links of the chains under way; see CHAIN_LINKS_PER_LEVEL.
+    chain_length: usize,
+    gave_up: bool,
+    // The offset and line the source ends at.
+    end: (usize, usize),
+}
+
+impl Builder {
+    fn new(source: &str, line: usize, offset: usize) -> Builder {
+        let (leaves, end_line, errors) = leaves(source, line, offset);
+        Builder {
+            leaves,
+            position: 0,
+            consumed: 0,
+            // Opened here rather than with `start`, which would put the
+            // leading trivia before it and so outside the tree.
+            stack: vec![(NodeKind::Program, Vec::new())],
+            errors,
+            last_error: None,
+            depth: 0,
+            chain_length: 0,
+            gave_up: false,
+            end: (offset + source.chars().count(), end_line),
+        }
+    }
+
+    // Whether the source is one pair of braces with everything else matched
+    // between them.
+    fn balanced(&self) -> bool {
+        let mut depth = 0;
+        let code: Vec<&Kind> = self
+            .leaves
+            .iter()
+            .filter_map(|leaf| match &leaf.kind {
+                TokenKind::Code(kind) => Some(kind),
+                _ => None,
+            })
+            .collect();
+        for (index, kind) in code.iter().enumerate() {
+            match kind {
+                Kind::OpenCurlyBracket => depth += 1,
+                Kind::CloseCurlyBracket => depth -= 1,
+                _ => {}
+            }
+            if depth == 0 && index + 1 < code.len() {
+                return false;
+            }
+        }
+        depth == 0
+            && code.first() == Some(&&Kind::OpenCurlyBracket)
+            && code.last() == Some(&&Kind::CloseCurlyBracket)
+    }
+
+    fn declaration(&mut self) {
+        match self.peek() {
+            Some(Kind::Keyword(Keyword::Class)) => self.class_declaration(),
+            Some(Kind::Keyword(Keyword::Fun)) => {
+                self.start(NodeKind::FunctionDeclaration);
+                self.bump();
+                self.function("function");
+                self.finish();
+            }
+            Some(Kind::Keyword(Keyword::Var)) => self.variable_declaration(),
+            _ => self.statement(),
+        }
+    }
+
+    fn class_declaration(&mut self) {
+        self.start(NodeKind::ClassDeclaration);
+        self.bump();
+        self.expect_identifier("Expect class name.");
+        if self.eat(&Kind::Less) {
+            if matches!(self.peek(), Some(Kind::Identifier(_))) {
+                self.leaf_node(NodeKind::Variable);
+            } else {
+                self.error("Expect superclass name.");
+            }
+        }
+
+        if self.expect(&Kind::OpenCurlyBracket, "Expect '{' before class body.") {
+            while !self.at(&Kind::CloseCurlyBracket) && !self.finished() {
+                if matches!(self.peek(), Some(Kind::Identifier(_))) {
+                    self.start(NodeKind::FunctionDeclaration);
+                    self.function("method");
+                    self.finish();
+                } else {
+                    self.error("Expect method name.");
+                    self.leaf_node(NodeKind::Error);
+                }
+            }
+            self.expect(&Kind::CloseCurlyBracket, "Expect '}' after class body.");
+        }
+        self.finish();
+    }
+
+    // A function or method from its name on.
+    fn function(&mut self, kind: &str) {
+        self.expect_identifier(&format!("Expect {} name.", kind));
+        if self.at(&Kind::OpenParenthesis) {
+            self.start(NodeKind::ParameterList);
+            self.bump();
+            if !self.at(&Kind::CloseParenthesis) {
+                loop {
+                    if self.eat(&Kind::Ellipsis) {
+                        self.expect_identifier("Expect parameter name after '...'.");
+                        break;
+                    }
+                    self.expect_identifier("Expect parameter name.");
+                    if self.eat(&Kind::Equal) {
+                        self.expression();
+                    }
+                    if !self.eat(&Kind::Comma) {
+                        break;
+                    }
+                }
+            }
+            self.expect(&Kind::CloseParenthesis, "Expect ')' after parameters.");
+            self.finish();
+        } else {
+            self.error(&format!("Expect '(' after {} name.", kind));
+        }
+
+        if self.at(&Kind::OpenCurlyBracket) {
+            self.block();
+        } else {
+            self.error(&format!("Expect '{{' before {} body.", kind));
+        }
+    }
+
+    fn variable_declaration(&mut self) {
+        self.start(NodeKind::VarDeclaration);
+        self.bump();
+        let close = match self.peek() {
+            Some(Kind::OpenSquareBracket) => {
+                Some((Kind::CloseSquareBracket, "Expect ']' after names."))
+            }
+            Some(Kind::OpenCurlyBracket) => {
+                Some((Kind::CloseCurlyBracket, "Expect '}' after names."))
+            }
+            _ => None,
+        };
+        // A destructuring pattern's names are all tokens of the declaration
+        // itself.
+        match close {
+            Some((close, message)) => {
+                self.bump();
+                self.names();
+                self.expect(&close, message);
+                self.expect(&Kind::Equal, "Expect '=' after destructuring pattern.");
+                self.expression();
+            }
+            None => {
+                self.expect_identifier("Expect variable name.");
+                if self.eat(&Kind::Comma) {
+                    self.names();
+                    self.expect(&Kind::Equal, "Expect '=' after destructuring pattern.");
+                    self.expression();
+                    while self.eat(&Kind::Comma) {
+                        self.expression();
+                    }
+                } else if self.eat(&Kind::Equal) {
+                    self.expression();
+                }
+            }
+        }
+        self.expect(&Kind::Semicolon, "Expect ';' after variable declaration.");
+        self.finish();
+    }
+
+    fn names(&mut self) {
+        loop {
+            self.expect_identifier("Expect variable name.");
+            if !self.eat(&Kind::Comma) {
+                break;
+            }
+        }
+    }
+
+    fn statement(&mut self) {
+        if !self.enter("Statement") {
+            return;
+        }
+
+        match self.peek() {
+            Some(Kind::Keyword(Keyword::For)) => self.for_statement(),
+            Some(Kind::Keyword(Keyword::If)) => self.if_statement(),
+            Some(Kind::Keyword(Keyword::Print)) => {
+                self.start(NodeKind::PrintStatement);
+                self.bump();
+                self.expression();
+                self.expect(&Kind::Semicolon, "Expect ';' after value.");
+                self.finish();
+            }
+            Some(Kind::Keyword(Keyword::Return)) => {
+                self.value_statement(NodeKind::ReturnStatement, "Expect ';' after return value.")
+            }
+            Some(Kind::Keyword(Keyword::Yield)) => {
+                self.value_statement(NodeKind::YieldStatement, "Expect ';' after yield value.")
+            }
+            Some(Kind::Keyword(Keyword::Try)) => self.try_statement(),
+            Some(Kind::Keyword(Keyword::Throw)) => {
+                self.start(NodeKind::ThrowStatement);
+                self.bump();
+                self.expression();
+                self.expect(&Kind::Semicolon, "Expect ';' after thrown value.");
+                self.finish();
+            }
+            Some(Kind::Keyword(Keyword::Defer)) => {
+                self.start(NodeKind::DeferStatement);
+                self.bump();
+                if self.at(&Kind::OpenCurlyBracket) {
+                    self.block();
+                } else {
+                    self.error("Expect '{' after 'defer'.");
+                }
+                self.finish();
+            }
+            Some(Kind::Keyword(Keyword::While)) => {
+                self.start(NodeKind::WhileStatement);
+                self.bump();
+                self.expect(&Kind::OpenParenthesis, "Expect '(' after 'while'.");
+                self.expression();
+                self.expect(&Kind::CloseParenthesis, "Expect ')' after condition.");
+                self.statement();
+                self.finish();
+            }
+            Some(Kind::OpenCurlyBracket) => self.block(),
+            _ if self.stops_expression() => self.error("Expect expression."),
+            _ => {
+                self.start(NodeKind::ExpressionStatement);
+                self.expression();
+                self.expect(&Kind::Semicolon, "Expect ';' after expression.");
+                self.finish();
+            }
+        }
+        self.depth -= 1;
+    }
+
+    fn for_statement(&mut self) {
+        let for_in = self.nth(1) == Some(&Kind::OpenParenthesis)
+            && self.nth(2) == Some(&Kind::Keyword(Keyword::Var))
+            && matches!(self.nth(3), Some(Kind::Identifier(_)))
+            && self.nth(4) == Some(&Kind::Keyword(Keyword::In));
+        if for_in {
+            self.start(NodeKind::ForInStatement);
+            for _ in 0..5 {
+                self.bump();
+            }
+            self.expression();
+        } else {
+            self.start(NodeKind::ForStatement);
+            self.bump();
+            self.expect(&Kind::OpenParenthesis, "Expect '(' after 'for'.");
+            match self.peek() {
+                Some(Kind::Semicolon) => self.bump(),
+                Some(Kind::Keyword(Keyword::Var)) => self.variable_declaration(),
+                _ => {
+                    self.start(NodeKind::ExpressionStatement);
+                    self.expression();
+                    self.expect(&Kind::Semicolon, "Expect ';' after expression.");
+                    self.finish();
+                }
+            }
+
+            if !self.at(&Kind::Semicolon) {
+                self.expression();
+            }
+            self.expect(&Kind::Semicolon, "Expect ';' after loop condition.");
+            if !self.at(&Kind::CloseParenthesis) {
+                self.expression();
+            }
+        }
+        self.expect(&Kind::CloseParenthesis, "Expect ')' after for clauses.");
+        self.statement();
+        self.finish();
+    }
+
+    fn if_statement(&mut self) {
+        self.start(NodeKind::IfStatement);
+        self.bump();
+        self.expect(&Kind::OpenParenthesis, "Expect '(' after 'if'.");
+        self.expression();
+        self.expect(&Kind::CloseParenthesis, "Expect ')' after if condition.");
+        self.statement();
+        if self.eat(&Kind::Keyword(Keyword::Else)) {
+            self.statement();
+        }
+        self.finish();
+    }
+
+    fn try_statement(&mut self) {
+        self.start(NodeKind::TryStatement);
+        self.bump();
+        if self.at(&Kind::OpenCurlyBracket) {
+            self.block();
+        } else {
+            self.error("Expect '{' after 'try'.");
+        }
+        self.expect(
+            &Kind::Keyword(Keyword::Catch),
+            "Expect 'catch' after try block.",
+        );
+        self.expect(&Kind::OpenParenthesis, "Expect '(' after 'catch'.");
+        self.expect_identifier("Expect error variable name.");
+        self.expect(
+            &Kind::CloseParenthesis,
+            "Expect ')' after error variable name.",
+        );
+        if self.at(&Kind::OpenCurlyBracket) {
+            self.block();
+        } else {
+            self.error("Expect '{' after catch clause.");
+        }
+        self.finish();
+    }
+
+    // `return` or `yield`, with a value or without. A `return` may give
+    // several values.
+    fn value_statement(&mut self, kind: NodeKind, message: &str) {
+        self.start(kind);
+        self.bump();
+        if !self.at(&Kind::Semicolon) {
+            self.expression();
+            while kind == NodeKind::ReturnStatement && self.eat(&Kind::Comma) {
+                self.expression();
+            }
+        }
+        self.expect(&Kind::Semicolon, message);
+        self.finish();
+    }
+
+    fn block(&mut self) {
+        self.start(NodeKind::Block);
+        self.bump();
+        while !self.at(&Kind::CloseCurlyBracket) && !self.finished() {
+            self.recovering(Builder::declaration);
+        }
+        self.expect(&Kind::CloseCurlyBracket, "Expect '}' after block.");
+        self.finish();
+    }
+
+    fn expression(&mut self) {
+        if self.enter("Expression") {
+            self.assignment();
+            self.depth -= 1;
+        }
+    }
+
+    fn assignment(&mut self) {
+        let checkpoint = self.checkpoint();
+        self.binary(0);
+        if !self.at(&Kind::Equal) {
+            return;
+        }
+
+        let target =
+            self.stack
+                .last()
+                .and_then(|(_, children)| match children.get(checkpoint..)? {
+                    [GreenElement::Node(node)] => Some(node.kind),
+                    _ => None,
+                });
+        if !matches!(
+            target,
+            Some(NodeKind::Variable | NodeKind::Get | NodeKind::Index)
+        ) {
+            self.error("Invalid assignment target.");
+        }
+        self.start_at(checkpoint, NodeKind::Assign);
+        self.bump();
+        self.expression();
+        self.finish();
+    }
+
+    fn binary(&mut self, level: usize) {
+        let Some((kind, operators)) = OPERATORS.get(level) else {
+            return self.unary();
+        };
+
+        let checkpoint = self.checkpoint();
+        let mut links = 0;
+        self.binary(level + 1);
+        while operators.iter().any(|operator| self.at(operator)) {
+            if !self.link() {
+                break;
+            }
+            links += 1;
+            self.start_at(checkpoint, *kind);
+            self.bump();
+            self.binary(level + 1);
+            self.finish();
+        }
+        self.chain_length -= links;
+    }
+
+    fn unary(&mut self) {
+        if !self.at(&Kind::Exclamation) && !self.at(&Kind::Minus) {
+            return self.call();
+        }
+
+        if self.enter("Expression") {
+            self.start(NodeKind::Unary);
+            self.bump();
+            self.unary();
+            self.finish();
+            self.depth -= 1;
+        }
+    }
+
+    fn call(&mut self) {
+        let checkpoint = self.checkpoint();
+        let mut links = 0;
+        self.primary();
+        loop {
+            let linked = self.at(&Kind::OpenParenthesis)
+                || self.at(&Kind::Dot)
+                || self.at(&Kind::OpenSquareBracket);
+            if linked && !self.link() {
+                break;
+            }
+            links += usize::from(linked);
+
+            if self.at(&Kind::OpenParenthesis) {
+                self.start_at(checkpoint, NodeKind::Call);
+                self.start(NodeKind::ArgumentList);
+                self.bump();
+                self.elements(&Kind::CloseParenthesis, Builder::expression);
+                self.expect(&Kind::CloseParenthesis, "Expect ')' after arguments.");
+                self.finish();
+                self.finish();
+            } else if self.at(&Kind::Dot) {
+                self.start_at(checkpoint, NodeKind::Get);
+                self.bump();
+                self.expect_identifier("Expect property name after '.'.");
+                self.finish();
+            } else if self.at(&Kind::OpenSquareBracket) {
+                self.start_at(checkpoint, NodeKind::Index);
+                self.bump();
+                self.expression();
+                self.expect(&Kind::CloseSquareBracket, "Expect ']' after index.");
+                self.finish();
+            } else {
+                break;
+            }
+        }
+        self.chain_length -= links;
+    }
+
+    fn primary(&mut self) {
+        match self.peek() {
+            Some(
+                Kind::Keyword(Keyword::False | Keyword::True | Keyword::Nil)
+                | Kind::Number(_)
+                | Kind::Integer(_)
+                | Kind::String(_),
+            ) => self.leaf_node(NodeKind::Literal),
+            Some(Kind::Keyword(Keyword::This)) => self.leaf_node(NodeKind::This),
+            Some(Kind::Identifier(_)) => self.leaf_node(NodeKind::Variable),
+            Some(Kind::Keyword(Keyword::Super)) => {
+                self.start(NodeKind::Super);
+                self.bump();
+                self.expect(&Kind::Dot, "Expect '.' after 'super'.");
+                self.expect_identifier("Expect superclass method name.");
+                self.finish();
+            }
+            Some(Kind::OpenParenthesis) => {
+                self.start(NodeKind::Grouping);
+                self.bump();
+                self.expression();
+                self.expect(&Kind::CloseParenthesis, "Expect ')' after expression.");
+                self.finish();
+            }
+            Some(Kind::OpenSquareBracket) => {
+                self.start(NodeKind::List);
+                self.bump();
+                self.elements(&Kind::CloseSquareBracket, Builder::expression);
+                self.expect(&Kind::CloseSquareBracket, "Expect ']' after list elements.");
+                self.finish();
+            }
+            Some(Kind::OpenCurlyBracket) => {
+                self.start(NodeKind::Map);
+                self.bump();
+                self.elements(&Kind::CloseCurlyBracket, |builder| {
+                    builder.start(NodeKind::MapEntry);
+                    builder.expression();
+                    builder.expect(&Kind::Colon, "Expect ':' after map key.");
+                    builder.expression();
+                    builder.finish();
+                });
+                self.expect(&Kind::CloseCurlyBracket, "Expect '}' after map entries.");
+                self.finish();
+            }
+            _ => {
+                self.error("Expect expression.");
+                if !self.stops_expression() {
+                    self.leaf_node(NodeKind::Error);
+                }
+            }
+        }
+    }
+
+    // Comma-separated elements up to `close`, which is left for the caller.
+    fn elements(&mut self, close: &Kind, element: fn(&mut Builder)) {
+        if self.at(close) {
+            return;
+        }
+        loop {
+            element(self);
+            if !self.eat(&Kind::Comma) {
+                break;
+            }
+        }
+    }
+
+    // Runs `parse`, and if it read nothing, puts the next token in an error
+    // node so that the caller's loop moves on.
+    fn recovering(&mut self, parse: fn(&mut Builder)) {
+        let consumed = self.consumed;
+        parse(self);
+        if self.consumed == consumed && !self.finished() {
+            self.leaf_node(NodeKind::Error);
+        }
+    }
+
+    // Closing brackets, separators and the keywords that start declarations
+    // and statements end an expression that is missing; they are left for
+    // whatever expects them.
+    fn stops_expression(&self) -> bool {
+        matches!(
+            self.peek(),
+            None | Some(
+                Kind::CloseParenthesis
+                    | Kind::CloseSquareBracket
+                    | Kind::CloseCurlyBracket
+                    | Kind::Semicolon
+                    | Kind::Comma
+                    | Kind::Keyword(
+                        Keyword::Class
+                            | Keyword::Fun
+                            | Keyword::Var
+                            | Keyword::For
+                            | Keyword::If
+                            | Keyword::While
+                            | Keyword::Print
+                            | Keyword::Return
+                            | Keyword::Yield
+                            | Keyword::Try
+                            | Keyword::Throw
+                            | Keyword::Defer
+                    )
+            )
+        )
+    }
+
+    // Like the parser, gives up on a program nested deeper than it can
+    // follow; the rest of the source goes in an error node.
+    fn enter(&mut self, construct: &str) -> bool {
+        if self.depth < DEFAULT_MAX_NESTING_DEPTH {
+            self.depth += 1;
+            return true;
+        }
+
+        self.give_up(construct)
+    }
+
+    fn link(&mut self) -> bool {
+        if self.chain_length < DEFAULT_MAX_NESTING_DEPTH * CHAIN_LINKS_PER_LEVEL {
+            self.chain_length += 1;
+            return true;
+        }
+
+        self.give_up("Expression")
+    }
+
+    fn give_up(&mut self, construct: &str) -> bool {
+        self.error(&format!("{} too deeply nested.", construct));
+        self.gave_up = true;
+        self.start(NodeKind::Error);
+        while !self.finished() {
+            self.bump();
+        }
+        self.finish();
+        false
+    }
+
+    fn leaf_node(&mut self, kind: NodeKind) {
+        self.start(kind);
+        self.bump();
+        self.finish();
+    }
+
+    fn expect(&mut self, kind: &Kind, message: &str) -> bool {
+        let found = self.eat(kind);
+        if !found {
+            self.error(message);
+        }
+        found
+    }
+
+    fn expect_identifier(&mut self, message: &str) {
+        if matches!(self.peek(), Some(Kind::Identifier(_))) {
+            self.bump();
+        } else {
+            self.error(message);
+        }
+    }
+
+    fn eat(&mut self, kind: &Kind) -> bool {
+        let found = self.at(kind);
+        if found {
+            self.bump();
+        }
+        found
+    }
+
+    fn error(&mut self, message: &str) {
+        let at = self.code(0);
+        if self.last_error == Some(at) {
+            return;
+        }
+        self.last_error = Some(at);
+        let (offset, line) = self
+            .leaves
+            .get(at)
+            .map_or(self.end, |leaf| (leaf.offset, leaf.line));
+        let error = Error {
+            message: message.to_string(),
+            line,
+            location: None,
+        };
+        self.errors.push((offset, error));
+    }
+
+    fn at(&self, kind: &Kind) -> bool {
+        self.peek() == Some(kind)
+    }
+
+    fn peek(&self) -> Option<&Kind> {
+        self.nth(0)
+    }
+
+    fn finished(&self) -> bool {
+        self.peek().is_none()
+    }
+
+    // The `n`th token ahead, not counting trivia.
+    fn nth(&self, n: usize) -> Option<&Kind> {
+        match &self.leaves.get(self.code(n))?.kind {
+            TokenKind::Code(kind) => Some(kind),
+            _ => None,
+        }
+    }
+
+    // The index of the `n`th leaf ahead that isn't trivia, or the number of
+    // leaves if there aren't that many.
+    fn code(&self, n: usize) -> usize {
+        self.leaves[self.position..]
+            .iter()
+            .enumerate()
+            .filter(|(_, leaf)| !leaf.kind.is_trivia())
+            .nth(n)
+            .map_or(self.leaves.len(), |(index, _)| self.position + index)
+    }
+
+    fn trivia(&mut self) {
+        while self
+            .leaves
+            .get(self.position)
+            .is_some_and(|leaf| leaf.kind.is_trivia())
+        {
+            self.push_leaf();
+        }
+    }
+
+    fn bump(&mut self) {
+        self.trivia();
+        if self.position < self.leaves.len() {
+            self.push_leaf();
+            self.consumed += 1;
+        }
+    }
+
+    fn push_leaf(&mut self) {
+        let leaf = &mut self.leaves[self.position];
+        let token = GreenToken::new(leaf.kind.clone(), core::mem::take(&mut leaf.text));
+        self.position += 1;
+        if let Some((_, children)) = self.stack.last_mut() {
+            children.push(GreenElement::Token(Rc::new(token)));
+        }
+    }
+
+    fn start(&mut self, kind: NodeKind) {
+        self.trivia();
+        self.stack.push((kind, Vec::new()));
+    }
+
+    fn checkpoint(&mut self) -> usize {
+        self.trivia();
+        self.stack.last().map_or(0, |(_, children)| children.len())
+    }
+
+    // Opens a node holding what was added since `checkpoint`.
+    fn start_at(&mut self, checkpoint: usize, kind: NodeKind) {
+        let children = match self.stack.last_mut() {
+            Some((_, children)) => children.split_off(checkpoint),
+            None => Vec::new(),
+        };
+        self.stack.push((kind, children));
+    }
+
+    fn finish(&mut self) {
+        let Some((kind, children)) = self.stack.pop() else {
+            return;
+        };
+        let node = GreenElement::Node(Rc::new(GreenNode::new(kind, children)));
+        if let Some((_, children)) = self.stack.last_mut() {
+            children.push(node);
+        }
+    }
+}
@@ -1,13 +1,20 @@
-use std::str::FromStr;
+use core::fmt;
+use core::str::FromStr;
 
-#[derive(Debug)]
+use crate::prelude::*;
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Kind {
     OpenParenthesis,
     CloseParenthesis,
     OpenCurlyBracket,
     CloseCurlyBracket,
+    OpenSquareBracket,
+    CloseSquareBracket,
     Comma,
+    Colon,
     Dot,
+    Ellipsis,
     Minus,
     Plus,
     Semicolon,
@@ -24,63 +31,149 @@ pub enum Kind {
     Identifier(String),
     String(String),
     Number(f64),
+    // Only produced in dialects with integers.
+    Integer(i64),
     Keyword(Keyword),
-    EOF,
+    // Only produced by a scanner keeping trivia.
+    Comment(String),
+    EndOfFile,
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Kind::OpenParenthesis => write!(formatter, "("),
+            Kind::CloseParenthesis => write!(formatter, ")"),
+            Kind::OpenCurlyBracket => write!(formatter, "{{"),
+            Kind::CloseCurlyBracket => write!(formatter, "}}"),
+            Kind::OpenSquareBracket => write!(formatter, "["),
+            Kind::CloseSquareBracket => write!(formatter, "]"),
+            Kind::Comma => write!(formatter, ","),
+            Kind::Colon => write!(formatter, ":"),
+            Kind::Dot => write!(formatter, "."),
+            Kind::Ellipsis => write!(formatter, "..."),
+            Kind::Minus => write!(formatter, "-"),
+            Kind::Plus => write!(formatter, "+"),
+            Kind::Semicolon => write!(formatter, ";"),
+            Kind::Slash => write!(formatter, "/"),
+            Kind::Asterisk => write!(formatter, "*"),
+            Kind::Exclamation => write!(formatter, "!"),
+            Kind::ExclamationEqual => write!(formatter, "!="),
+            Kind::Equal => write!(formatter, "="),
+            Kind::EqualEqual => write!(formatter, "=="),
+            Kind::Greater => write!(formatter, ">"),
+            Kind::GreaterEqual => write!(formatter, ">="),
+            Kind::Less => write!(formatter, "<"),
+            Kind::LessEqual => write!(formatter, "<="),
+            Kind::Identifier(name) => write!(formatter, "{}", name),
+            Kind::String(string) => write!(formatter, "\"{}\"", string),
+            Kind::Number(number) => write!(formatter, "{}", number),
+            Kind::Integer(integer) => write!(formatter, "{}", integer),
+            Kind::Keyword(keyword) => write!(formatter, "{}", keyword),
+            Kind::Comment(comment) => write!(formatter, "//{}", comment),
+            Kind::EndOfFile => write!(formatter, "end"),
+        }
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Keyword {
     And,
+    Catch,
     Class,
+    Defer,
+    Div,
     Else,
     False,
     Fun,
     For,
     If,
+    In,
     Nil,
     Or,
     Print,
     Return,
     Super,
     This,
+    Throw,
     True,
+    Try,
     Var,
     While,
+    Yield,
+}
+
+impl Keyword {
+    // Every keyword with its spelling, which the scanner looks words up in.
+    pub const ALL: [(&'static str, Keyword); 23] = [
+        ("and", Keyword::And),
+        ("catch", Keyword::Catch),
+        ("class", Keyword::Class),
+        ("defer", Keyword::Defer),
+        ("div", Keyword::Div),
+        ("else", Keyword::Else),
+        ("false", Keyword::False),
+        ("fun", Keyword::Fun),
+        ("for", Keyword::For),
+        ("if", Keyword::If),
+        ("in", Keyword::In),
+        ("nil", Keyword::Nil),
+        ("or", Keyword::Or),
+        ("print", Keyword::Print),
+        ("return", Keyword::Return),
+        ("super", Keyword::Super),
+        ("this", Keyword::This),
+        ("throw", Keyword::Throw),
+        ("true", Keyword::True),
+        ("try", Keyword::Try),
+        ("var", Keyword::Var),
+        ("while", Keyword::While),
+        ("yield", Keyword::Yield),
+    ];
+
+    pub fn name(self) -> &'static str {
+        Keyword::ALL
+            .iter()
+            .find(|(_, keyword)| *keyword == self)
+            .map_or("", |(name, _)| name)
+    }
 }
 
 impl FromStr for Keyword {
     type Err = ();
 
     fn from_str(string: &str) -> Result<Self, Self::Err> {
-        match string {
-            "and" => Ok(Keyword::And),
-            "class" => Ok(Keyword::Class),
-            "else" => Ok(Keyword::Else),
-            "false" => Ok(Keyword::False),
-            "fun" => Ok(Keyword::Fun),
-            "for" => Ok(Keyword::For),
-            "if" => Ok(Keyword::If),
-            "nil" => Ok(Keyword::Nil),
-            "or" => Ok(Keyword::Or),
-            "print" => Ok(Keyword::Print),
-            "return" => Ok(Keyword::Return),
-            "super" => Ok(Keyword::Super),
-            "this" => Ok(Keyword::This),
-            "true" => Ok(Keyword::True),
-            "var" => Ok(Keyword::Var),
-            "while" => Ok(Keyword::While),
-            _ => Err(()),
-        }
+        Keyword::ALL
+            .iter()
+            .find(|(name, _)| *name == string)
+            .map(|(_, keyword)| *keyword)
+            .ok_or(())
     }
 }
 
-#[derive(Debug)]
+impl fmt::Display for Keyword {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str(self.name())
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Token {
     pub kind: Kind,
     pub position: Position,
 }
 
-#[derive(Debug)]
+impl Token {
+    pub fn lexeme(&self) -> String {
+        self.kind.to_string()
+    }
+
+    pub fn line(&self) -> usize {
+        self.position.line
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
 pub struct Position {
     pub start: usize,
     pub current: usize,
@@ -0,0 +1,109 @@
+// Spans and events for the tracing crate, so that an embedder's subscriber
+// can see what scripts are doing inside a larger application. Everything is
+// under the "lox" target: phases at debug level, function calls at trace
+// level. Without the trace feature these compile to nothing.
+
+use crate::gc::Collection;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Phase {
+    Run,
+    Scan,
+    Parse,
+    Resolve,
+    Compile,
+    Optimize,
+    Collect,
+}
+
+#[cfg(feature = "trace")]
+pub(crate) type Span = tracing::span::EnteredSpan;
+
+#[cfg(not(feature = "trace"))]
+pub(crate) struct Span;
+
+// Entered until the returned guard is dropped.
+#[cfg(feature = "trace")]
+pub(crate) fn enter(phase: Phase) -> Span {
+    let span = match phase {
+        Phase::Run => tracing::debug_span!(target: "lox", "run"),
+        Phase::Scan => tracing::debug_span!(target: "lox", "scan"),
+        Phase::Parse => tracing::debug_span!(target: "lox", "parse"),
+        Phase::Resolve => tracing::debug_span!(target: "lox", "resolve"),
+        Phase::Compile => tracing::debug_span!(target: "lox", "compile"),
+        Phase::Optimize => tracing::debug_span!(target: "lox", "optimize"),
+        Phase::Collect => tracing::debug_span!(target: "lox", "collect"),
+    };
+    span.entered()
+}
+
+#[cfg(not(feature = "trace"))]
+pub(crate) fn enter(_phase: Phase) -> Span {
+    Span
+}
+
+#[cfg(feature = "trace")]
+pub(crate) fn collection(collection: &Collection) {
+    tracing::debug!(
+        target: "lox",
+        minor = collection.minor,
+        objects_freed = collection.objects_freed,
+        bytes_freed = collection.bytes_freed,
+        bytes_remaining = collection.bytes_remaining,
+        next_gc = collection.next_gc,
+        pause_us = collection.pause.as_micros() as u64,
+        "garbage collected"
+    );
+}
+
+#[cfg(not(feature = "trace"))]
+pub(crate) fn collection(_collection: &Collection) {}
+
+// A span for each active call, entered and left as the engines call and
+// return rather than in the nesting of Rust code. Calls made while no
+// subscriber wanted them hold no span, so that entries and exits stay paired.
+#[derive(Default)]
+pub(crate) struct Calls {
+    #[cfg(feature = "trace")]
+    stack: Vec<Option<Span>>,
+}
+
+#[cfg(feature = "trace")]
+impl Calls {
+    pub(crate) fn enabled(&self) -> bool {
+        tracing::enabled!(target: "lox", tracing::Level::TRACE)
+    }
+
+    pub(crate) fn enter(&mut self, function: Option<&str>) {
+        self.stack.push(
+            function
+                .map(|function| tracing::trace_span!(target: "lox", "call", function).entered()),
+        );
+    }
+
+    pub(crate) fn leave(&mut self) {
+        self.stack.pop();
+    }
+
+    // After an error, innermost first.
+    pub(crate) fn clear(&mut self) {
+        while self.stack.pop().is_some() {}
+    }
+}
+
+// Only the profiling hooks, which need std, watch calls.
+#[cfg(not(feature = "trace"))]
+impl Calls {
+    #[cfg(feature = "std")]
+    pub(crate) fn enabled(&self) -> bool {
+        false
+    }
+
+    #[cfg(feature = "std")]
+    pub(crate) fn enter(&mut self, _function: Option<&str>) {}
+
+    #[cfg(feature = "std")]
+    pub(crate) fn leave(&mut self) {}
+
+    pub(crate) fn clear(&mut self) {}
+}
@@ -0,0 +1,1172 @@
+use core::mem;
+
+use crate::error::Error;
+use crate::expression::{Expr, ExpressionKind, Literal};
+use crate::prelude::*;
+use crate::statement::{FunctionDeclaration, Pattern, Statement};
+use crate::token::{Keyword, Kind, Token};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    JavaScript,
+}
+
+impl Target {
+    pub fn from_name(name: &str) -> Option<Target> {
+        match name {
+            "js" | "javascript" => Some(Target::JavaScript),
+            _ => None,
+        }
+    }
+}
+
+pub fn transpile(statements: &[Rc<Statement>], target: Target) -> Result<String, Vec<Error>> {
+    match target {
+        Target::JavaScript => JavaScript::new().program(statements),
+    }
+}
+
+// The runtime keeps Lox semantics that JavaScript doesn't share: only nil and false are falsey,
+// arithmetic doesn't coerce, properties are looked up on instances and bound on access, and
+// values print the way the interpreter prints them. Every helper starts with `$`, which can't
+// appear in a Lox identifier.
+const JAVASCRIPT_RUNTIME: &str = r##""use strict";
+
+const $classOf = Symbol("class");
+
+function $truthy(value) {
+    return value !== null && value !== false;
+}
+
+function $isInstance(value) {
+    return value !== null && typeof value === "object" && value[$classOf] !== undefined;
+}
+
+function $isGeneratorFunction(value) {
+    return Object.getPrototypeOf(value) === Object.getPrototypeOf(function* () {});
+}
+
+function $number(number) {
+    if (Object.is(number, -0)) return "-0";
+    const text = String(number);
+    const match = /^(-?)(\d)(?:\.(\d+))?e([+-]\d+)$/.exec(text);
+    if (match === null) return text;
+    const [, sign, head, tail = "", exponent] = match;
+    const digits = head + tail;
+    const point = 1 + Number(exponent);
+    if (point <= 0) return sign + "0." + "0".repeat(-point) + digits;
+    return sign + digits + "0".repeat(point - digits.length);
+}
+
+const $displaying = new Set();
+
+function $stringify(value, quoted = false) {
+    if (value === null || value === undefined) return "nil";
+    if (typeof value === "number") return $number(value);
+    if (typeof value === "string") return quoted ? JSON.stringify(value) : value;
+    if (typeof value === "boolean") return String(value);
+    if (typeof value === "function") {
+        if (value.$class !== undefined) return value.$class;
+        if (value.$native) return "<native fn>";
+        return `<fn ${value.$name ?? value.name}>`;
+    }
+    if (Array.isArray(value)) {
+        return `[${value.map((element) => $stringify(element, true)).join(", ")}]`;
+    }
+    if (value instanceof Map) {
+        const entries = [...value.entries()].sort(([left], [right]) => (left < right ? -1 : 1));
+        return `{${entries
+            .map(([key, entry]) => `${JSON.stringify(key)}: ${$stringify(entry, true)}`)
+            .join(", ")}}`;
+    }
+    if ($isInstance(value)) {
+        if (typeof value.toString !== "function" || $displaying.has(value)) {
+            return `${value[$classOf].$class} instance`;
+        }
+        $displaying.add(value);
+        let text;
+        try {
+            text = value.toString();
+        } finally {
+            $displaying.delete(value);
+        }
+        if (typeof text !== "string") throw new Error("'toString' must return a string.");
+        return text;
+    }
+    if (Object.prototype.toString.call(value) === "[object Generator]") {
+        return `<generator ${value.$name}>`;
+    }
+    return String(value);
+}
+
+function $print(value) {
+    console.log($stringify(value));
+}
+
+function $equal(left, right, comparing = []) {
+    if (left === right) return true;
+    if (!Array.isArray(left) || !Array.isArray(right) || left.length !== right.length) {
+        return false;
+    }
+    if (comparing.some(([a, b]) => a === left && b === right)) return true;
+    comparing.push([left, right]);
+    const equal = left.every((element, index) => $equal(element, right[index], comparing));
+    comparing.pop();
+    return equal;
+}
+
+function $numbers(left, right) {
+    if (typeof left !== "number" || typeof right !== "number") {
+        throw $fail("TypeError", "Operands must be numbers.");
+    }
+}
+
+function $add(left, right) {
+    if (typeof left === "number" && typeof right === "number") return left + right;
+    if (typeof left === "string" && typeof right === "string") return left + right;
+    if (Array.isArray(left) && Array.isArray(right)) return [...left, ...right];
+    throw $fail("TypeError", "Operands must be two numbers or two strings.");
+}
+
+function $subtract(left, right) {
+    $numbers(left, right);
+    return left - right;
+}
+
+function $multiply(left, right) {
+    if (typeof left === "string" || Array.isArray(left)) return $repeat(left, right);
+    if (typeof right === "string" || Array.isArray(right)) return $repeat(right, left);
+    $numbers(left, right);
+    return left * right;
+}
+
+function $repeat(sequence, count) {
+    if (typeof count !== "number") throw $fail("TypeError", "Operands must be numbers.");
+    if (!Number.isInteger(count)) throw new Error("Repetition count must be an integer.");
+    if (count < 0) throw new Error("Repetition count must not be negative.");
+    if (typeof sequence === "string") return sequence.repeat(count);
+    const result = [];
+    for (let i = 0; i < count; i++) result.push(...sequence);
+    return result;
+}
+
+function $divide(left, right) {
+    $numbers(left, right);
+    return left / right;
+}
+
+function $floorDivide(left, right) {
+    $numbers(left, right);
+    return Math.floor(left / right);
+}
+
+function $ordered(left, right) {
+    if (typeof left === "string" && typeof right === "string") return;
+    if (typeof left === "string" || typeof right === "string") {
+        throw $fail("TypeError", "Operands must be two numbers or two strings.");
+    }
+    $numbers(left, right);
+}
+
+function $greater(left, right) {
+    $ordered(left, right);
+    return left > right;
+}
+
+function $greaterEqual(left, right) {
+    $ordered(left, right);
+    return left >= right;
+}
+
+function $less(left, right) {
+    $ordered(left, right);
+    return left < right;
+}
+
+function $lessEqual(left, right) {
+    $ordered(left, right);
+    return left <= right;
+}
+
+function $negate(operand) {
+    if (typeof operand !== "number") throw $fail("TypeError", "Operand must be a number.");
+    return -operand;
+}
+
+function $and(left, right) {
+    return $truthy(left) ? right() : left;
+}
+
+function $or(left, right) {
+    return $truthy(left) ? left : right();
+}
+
+function $generator(fn, name) {
+    fn.prototype.$name = name;
+}
+
+// One bound function per receiver and method, so that looking a method up
+// twice gives equal values.
+const $bindings = new WeakMap();
+
+function $bind(object, method, name) {
+    let bindings = $bindings.get(object);
+    if (bindings === undefined) $bindings.set(object, (bindings = new Map()));
+    let bound = bindings.get(method);
+    if (bound === undefined) {
+        bound = method.bind(object);
+        bound.$name = name;
+        bindings.set(method, bound);
+    }
+    return bound;
+}
+
+function $class(name, superclass, methods) {
+    if (superclass !== null && (typeof superclass !== "function" || superclass.$class === undefined)) {
+        throw $fail("TypeError", "Superclass must be a class.");
+    }
+
+    const prototype = methods(superclass);
+    Object.setPrototypeOf(prototype, superclass === null ? null : superclass.prototype);
+    for (const key of Object.keys(prototype)) {
+        if ($isGeneratorFunction(prototype[key])) $generator(prototype[key], key);
+    }
+
+    const klass = function (...args) {
+        const instance = Object.create(prototype);
+        if (prototype.init !== undefined) prototype.init.apply(instance, args);
+        return instance;
+    };
+    Object.defineProperty(prototype, $classOf, { value: klass });
+    klass.prototype = prototype;
+    klass.$class = name;
+    return klass;
+}
+
+function $property(object, name) {
+    if (!$isInstance(object)) throw $fail("TypeError", "Only instances have properties.");
+    if (Object.hasOwn(object, name)) return { field: object[name] };
+    const method = object[name];
+    if (method === undefined) throw $fail("NameError", `Undefined property '${name}'.`);
+    return { method };
+}
+
+function $get(object, name) {
+    const { field, method } = $property(object, name);
+    return method === undefined ? field : $bind(object, method, name);
+}
+
+function $invoke(object, name, ...args) {
+    const { field, method } = $property(object, name);
+    return method === undefined ? field(...args) : method.apply(object, args);
+}
+
+function $set(object, name, value) {
+    if (!$isInstance(object)) throw $fail("TypeError", "Only instances have fields.");
+    object[name] = value;
+    return value;
+}
+
+function $super(superclass, object, name) {
+    const method = superclass.prototype[name];
+    if (method === undefined) throw $fail("NameError", `Undefined property '${name}'.`);
+    return $bind(object, method, name);
+}
+
+function $map(entries) {
+    const map = new Map();
+    for (const [key, value] of entries) $setIndex(map, key, value);
+    return map;
+}
+
+function $position(list, index) {
+    if (typeof index !== "number" || index % 1 !== 0) {
+        throw $fail("TypeError", "List index must be an integer.");
+    }
+    if (index < 0 || index >= list.length) throw $fail("IndexError", "List index out of range.");
+    return index;
+}
+
+function $key(key) {
+    if (typeof key !== "string") throw $fail("TypeError", "Map key must be a string.");
+    return key;
+}
+
+function $index(object, index) {
+    if (Array.isArray(object)) return object[$position(object, index)];
+    if (object instanceof Map) return object.get($key(index)) ?? null;
+    throw $fail("TypeError", "Only lists and maps can be indexed.");
+}
+
+function $setIndex(object, index, value) {
+    if (Array.isArray(object)) object[$position(object, index)] = value;
+    else if (object instanceof Map) object.set($key(index), value);
+    else throw $fail("TypeError", "Only lists and maps can be indexed.");
+    return value;
+}
+
+function $values(object, count) {
+    if (Array.isArray(object) && object.length !== count) {
+        throw $fail("TypeError", "Expected " + count + " values but got " + object.length + ".");
+    }
+    return object;
+}
+
+function $unpack(object, keys) {
+    return keys.map((key) => {
+        if (typeof key === "number") {
+            if (!Array.isArray(object)) throw $fail("TypeError", "Can only destructure lists by position.");
+            return $index(object, key);
+        }
+        if (object instanceof Map) return $index(object, key);
+        if ($isInstance(object)) return $get(object, key);
+        throw $fail("TypeError", "Can only destructure instances and maps by name.");
+    });
+}
+
+function $iterate(iterable) {
+    if ($isInstance(iterable) && typeof iterable.iterator === "function") {
+        iterable = iterable.iterator();
+    }
+    if (Array.isArray(iterable)) return iterable;
+    if (Object.prototype.toString.call(iterable) === "[object Generator]") return iterable;
+    if ($isInstance(iterable)) {
+        if (typeof iterable.next !== "function" || typeof iterable.done !== "function") {
+            throw $fail("TypeError", "Iterator must have 'next' and 'done' methods.");
+        }
+        return $protocol(iterable);
+    }
+    throw $fail("TypeError", "Can only iterate over generators, lists and iterators.");
+}
+
+function* $protocol(iterator) {
+    while (!$truthy(iterator.done())) yield iterator.next();
+}
+
+function $argument(name, value, type) {
+    if (typeof value !== type) throw $fail("TypeError", `Argument to '${name}' must be a ${type}.`);
+    return value;
+}
+
+function $list(name, value) {
+    if (!Array.isArray(value)) throw $fail("TypeError", `Argument to '${name}' must be a list.`);
+    return value;
+}
+
+const $natives = {
+    clock: () => Date.now() / 1000,
+    sqrt: (number) => Math.sqrt($argument("sqrt", number, "number")),
+    abs: (number) => Math.abs($argument("abs", number, "number")),
+    floor: (number) => Math.floor($argument("floor", number, "number")),
+    ceil: (number) => Math.ceil($argument("ceil", number, "number")),
+    round: (number) => {
+        $argument("round", number, "number");
+        return Math.sign(number) * Math.round(Math.abs(number));
+    },
+    min: (left, right) => Math.min($argument("min", left, "number"), $argument("min", right, "number")),
+    max: (left, right) => Math.max($argument("max", left, "number"), $argument("max", right, "number")),
+    pow: (base, exponent) => Math.pow($argument("pow", base, "number"), $argument("pow", exponent, "number")),
+    len: (value) => {
+        if (typeof value === "string") return [...value].length;
+        if (Array.isArray(value)) return value.length;
+        if (value instanceof Map) return value.size;
+        throw $fail("TypeError", "Argument to 'len' must be a string, list or map.");
+    },
+    upper: (string) => $argument("upper", string, "string").toUpperCase(),
+    lower: (string) => $argument("lower", string, "string").toLowerCase(),
+    string: (value) => $stringify(value),
+    type: (value) => {
+        if (value === null) return "nil";
+        if (typeof value === "boolean") return "bool";
+        if (typeof value === "function") return value.$class === undefined ? "function" : "class";
+        if (typeof value !== "object") return typeof value;
+        if (Array.isArray(value)) return "list";
+        if (value instanceof Map) return "map";
+        return $isInstance(value) ? "instance" : "generator";
+    },
+    map: (fn, list) => [...$list("map", list)].map((element) => fn(element)),
+    filter: (fn, list) => [...$list("filter", list)].filter((element) => $truthy(fn(element))),
+    reduce: (fn, initial, list) => [...$list("reduce", list)].reduce((total, element) => fn(total, element), initial),
+    sort: (list, comparator) => {
+        const sorted = [...$list("sort", list)].sort((left, right) => {
+            const order = comparator(left, right);
+            if (typeof order !== "number") throw $fail("TypeError", "Comparator must return a number.");
+            return order;
+        });
+        list.splice(0, list.length, ...sorted);
+        return list;
+    },
+    push: (list, value) => {
+        $list("push", list).push(value);
+        return null;
+    },
+    pop: (list) => {
+        if ($list("pop", list).length === 0) throw new Error("Can't pop from an empty list.");
+        return list.pop();
+    },
+    error: (value) => {
+        throw new Error($stringify(value));
+    },
+    arity: (fn) => {
+        if (typeof fn !== "function") throw $fail("TypeError", "Argument to 'arity' must be a function or a class.");
+        if (fn.$class === undefined) return fn.length;
+        return fn.prototype.init === undefined ? 0 : fn.prototype.init.length;
+    },
+    name: (fn) => {
+        if (typeof fn !== "function") throw $fail("TypeError", "Argument to 'name' must be a function or a class.");
+        return fn.$class ?? fn.$name ?? fn.name;
+    },
+    classOf: (instance) => {
+        if (!$isInstance(instance)) throw $fail("TypeError", "Argument to 'classOf' must be an instance.");
+        return instance[$classOf];
+    },
+    className: (value) => {
+        if ($isInstance(value)) return value[$classOf].$class;
+        if (typeof value === "function" && value.$class !== undefined) return value.$class;
+        throw $fail("TypeError", "Argument to 'className' must be a class or an instance.");
+    },
+    superclassOf: (klass) => {
+        if (typeof klass !== "function" || klass.$class === undefined) {
+            throw $fail("TypeError", "Argument to 'superclassOf' must be a class.");
+        }
+        return Object.getPrototypeOf(klass.prototype)?.[$classOf] ?? null;
+    },
+    has: (object, name) => {
+        $argument("has", name, "string");
+        if (object instanceof Map) return object.has(name);
+        if ($isInstance(object)) return Object.hasOwn(object, name);
+        throw $fail("TypeError", "Argument to 'has' must be an instance or a map.");
+    },
+    delete: (object, name) => {
+        $argument("delete", name, "string");
+        if (object instanceof Map) return object.delete(name);
+        if (!$isInstance(object)) throw $fail("TypeError", "Argument to 'delete' must be an instance or a map.");
+        const present = Object.hasOwn(object, name);
+        delete object[name];
+        return present;
+    },
+};
+for (const native of Object.values($natives)) native.$native = true;
+
+// The classes runtime errors are caught as, which scripts can subclass and
+// throw like their own.
+const $errors = {
+    Error: $class("Error", null, () => ({
+        init(message) {
+            this.message = message;
+            this.line = null;
+            this.trace = null;
+        },
+    })),
+};
+for (const name of ["TypeError", "NameError", "IndexError"]) {
+    $errors[name] = $class(name, $errors.Error, () => ({}));
+}
+
+// An error the runtime raises, marked with the class it is caught as.
+// Errors without a mark, like the ones `error()` raises, are plain `Error`s.
+function $fail(name, message) {
+    const error = new Error(message);
+    error.$class = name;
+    return error;
+}
+
+// What a `catch` clause receives: an error the runtime raised as an instance
+// of its class, or whatever the script threw.
+function $caught(error) {
+    if (!(error instanceof Error)) return error;
+    const { message } = error;
+    if (error instanceof ReferenceError) {
+        return $errors.NameError(`Undefined variable '${message.split(" ")[0]}'.`);
+    }
+    return $errors[error.$class ?? "Error"](message);
+}
+
+// Runs a call's deferred blocks, last first. One that fails still leaves the
+// rest to run, and its error replaces any the call was leaving with.
+function $runDeferred(deferred) {
+    while (deferred.length > 0) {
+        try {
+            deferred.pop()();
+        } catch (error) {
+            $runDeferred(deferred);
+            throw error;
+        }
+    }
+}
+"##;
+
+const NATIVES: &[&str] = &[
+    "clock",
+    "sqrt",
+    "abs",
+    "floor",
+    "ceil",
+    "round",
+    "min",
+    "max",
+    "pow",
+    "len",
+    "upper",
+    "lower",
+    "string",
+    "type",
+    "map",
+    "filter",
+    "reduce",
+    "sort",
+    "push",
+    "pop",
+    "error",
+    "arity",
+    "name",
+    "classOf",
+    "className",
+    "superclassOf",
+    "has",
+    "delete",
+];
+
+const RESERVED: &[&str] = &[
+    "arguments",
+    "await",
+    "break",
+    "case",
+    "catch",
+    "const",
+    "continue",
+    "debugger",
+    "default",
+    "delete",
+    "do",
+    "enum",
+    "eval",
+    "export",
+    "extends",
+    "finally",
+    "function",
+    "implements",
+    "import",
+    "Infinity",
+    "instanceof",
+    "interface",
+    "let",
+    "NaN",
+    "new",
+    "null",
+    "package",
+    "private",
+    "protected",
+    "public",
+    "static",
+    "switch",
+    "throw",
+    "try",
+    "typeof",
+    "undefined",
+    "void",
+    "with",
+];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum FunctionKind {
+    Function,
+    Method,
+    Initializer,
+}
+
+struct JavaScript {
+    output: String,
+    indent: usize,
+    scopes: Vec<HashMap<String, String>>,
+    renamed: usize,
+    functions: usize,
+    initializer: bool,
+    classes: Vec<bool>,
+    errors: Vec<Error>,
+}
+
+impl JavaScript {
+    fn new() -> JavaScript {
+        JavaScript {
+            output: String::new(),
+            indent: 0,
+            scopes: vec![HashMap::new()],
+            renamed: 0,
+            functions: 0,
+            initializer: false,
+            classes: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    fn program(mut self, statements: &[Rc<Statement>]) -> Result<String, Vec<Error>> {
+        self.output.push_str(JAVASCRIPT_RUNTIME);
+        self.line("");
+        self.line("(() => {");
+        self.indent += 1;
+        let natives: Vec<String> = NATIVES
+            .iter()
+            .map(|&native| match RESERVED.contains(&native) {
+                true => format!("{}: {}$", native, native),
+                false => native.to_string(),
+            })
+            .collect();
+        self.line(&format!("var {{ {} }} = $natives;", natives.join(", ")));
+        self.line("var { Error, TypeError, NameError, IndexError } = $errors;");
+        for statement in statements {
+            self.statement(statement);
+        }
+        self.indent -= 1;
+        self.line("})();");
+
+        if self.errors.is_empty() {
+            Ok(self.output)
+        } else {
+            Err(self.errors)
+        }
+    }
+
+    fn line(&mut self, text: &str) {
+        if !text.is_empty() {
+            for _ in 0..self.indent {
+                self.output.push_str("    ");
+            }
+            self.output.push_str(text);
+        }
+        self.output.push('\n');
+    }
+
+    fn declaration(&self) -> &'static str {
+        if self.scopes.len() == 1 {
+            "var"
+        } else {
+            "let"
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Expression(expression) => {
+                let expression = match &expression.kind {
+                    ExpressionKind::Assign { name, value } => {
+                        let value = self.expression(&expression.get(*value));
+                        format!("{} = {}", self.resolve(name), value)
+                    }
+                    _ => self.expression(expression),
+                };
+                self.line(&format!("{};", expression));
+            }
+            Statement::Print { value, .. } => {
+                let expression = self.expression(value);
+                self.line(&format!("$print({});", expression));
+            }
+            Statement::Variable { name, initializer } => {
+                let value = match initializer {
+                    Some(initializer) => self.expression(initializer),
+                    None => "null".to_string(),
+                };
+                let keyword = self.declaration();
+                let name = self.declare(name);
+                self.line(&format!("{} {} = {};", keyword, name, value));
+            }
+            Statement::Destructure {
+                pattern,
+                names,
+                initializer,
+                ..
+            } => {
+                let mut value = self.expression(initializer);
+                if *pattern == Pattern::Values {
+                    value = format!("$values({}, {})", value, names.len());
+                }
+                let keys = names
+                    .iter()
+                    .enumerate()
+                    .map(|(position, name)| match pattern {
+                        Pattern::Elements | Pattern::Values => position.to_string(),
+                        Pattern::Fields => quote(&name.lexeme()),
+                    })
+                    .collect::<Vec<_>>();
+                let keyword = self.declaration();
+                let names = names
+                    .iter()
+                    .map(|name| self.declare(name))
+                    .collect::<Vec<_>>();
+                self.line(&format!(
+                    "{} [{}] = $unpack({}, [{}]);",
+                    keyword,
+                    names.join(", "),
+                    value,
+                    keys.join(", ")
+                ));
+            }
+            Statement::Block(statements) => {
+                self.line("{");
+                self.block(statements);
+                self.line("}");
+            }
+            Statement::Try {
+                body,
+                name,
+                handler,
+                ..
+            } => {
+                self.line("try {");
+                self.block(body);
+                self.line("} catch ($error) {");
+                self.handler_block(Some(name), handler);
+                self.line("}");
+            }
+            Statement::Throw { value, .. } => {
+                let value = self.expression(value);
+                self.line(&format!("throw {};", value));
+            }
+            Statement::Defer { body, .. } => {
+                self.line("$deferred.push(() => {");
+                self.block(body);
+                self.line("});");
+            }
+            Statement::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                let condition = self.expression(condition);
+                self.line(&format!("if ($truthy({})) {{", condition));
+                self.body(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.line("} else {");
+                    self.body(else_branch);
+                }
+                self.line("}");
+            }
+            Statement::While {
+                condition, body, ..
+            } => {
+                let condition = self.expression(condition);
+                self.line(&format!("while ($truthy({})) {{", condition));
+                self.body(body);
+                self.line("}");
+            }
+            Statement::ForIn {
+                name,
+                iterable,
+                body,
+            } => {
+                let iterable = self.expression(iterable);
+                self.scopes.push(HashMap::new());
+                let name = self.declare(name);
+                self.line(&format!("for (let {} of $iterate({})) {{", name, iterable));
+                self.body(body);
+                self.line("}");
+                self.scopes.pop();
+            }
+            Statement::Function(declaration) => {
+                let keyword = self.declaration();
+                let name = self.declare(&declaration.name);
+                let star = if declaration.is_generator { "*" } else { "" };
+                self.function(
+                    declaration,
+                    FunctionKind::Function,
+                    &format!("{} {} = function{} ", keyword, name, star),
+                    "};",
+                );
+                if declaration.is_generator {
+                    self.line(&format!(
+                        "$generator({}, {});",
+                        name,
+                        quote(&declaration.name.lexeme())
+                    ));
+                }
+            }
+            Statement::Return { keyword, value } => {
+                if self.functions == 0 {
+                    self.errors.push(Error {
+                        message: "Can't return from top-level code.".to_string(),
+                        line: keyword.line(),
+                        location: None,
+                    });
+                }
+                if value.is_some() && self.initializer {
+                    self.errors.push(Error {
+                        message: "Can't return a value from an initializer.".to_string(),
+                        line: keyword.line(),
+                        location: None,
+                    });
+                }
+
+                let value = match value {
+                    Some(value) => self.expression(value),
+                    None if self.initializer => "$this".to_string(),
+                    None => "null".to_string(),
+                };
+                self.line(&format!("return {};", value));
+            }
+            Statement::Yield { value, .. } => {
+                let value = match value {
+                    Some(value) => self.expression(value),
+                    None => "null".to_string(),
+                };
+                self.line(&format!("yield {};", value));
+            }
+            Statement::Class {
+                name,
+                superclass,
+                methods,
+            } => {
+                let superclass = match superclass {
+                    Some(superclass) => self.expression(superclass),
+                    None => "null".to_string(),
+                };
+                self.classes.push(superclass != "null");
+                let keyword = self.declaration();
+                let variable = self.declare(name);
+                self.line(&format!(
+                    "{} {} = $class({}, {}, ($superclass) => ({{",
+                    keyword,
+                    variable,
+                    quote(&name.lexeme()),
+                    superclass
+                ));
+                self.indent += 1;
+                for method in methods {
+                    let star = if method.is_generator { "*" } else { "" };
+                    let name = method.name.lexeme();
+                    let kind = if name == "init" {
+                        FunctionKind::Initializer
+                    } else {
+                        FunctionKind::Method
+                    };
+                    self.function(method, kind, &format!("{}{}", star, quote(&name)), "},");
+                }
+                self.indent -= 1;
+                self.line("}));");
+                self.classes.pop();
+            }
+        }
+    }
+
+    fn block(&mut self, statements: &[Rc<Statement>]) {
+        self.handler_block(None, statements);
+    }
+
+    // A block that starts by binding what the enclosing `catch` caught.
+    fn handler_block(&mut self, caught: Option<&Token>, statements: &[Rc<Statement>]) {
+        self.indent += 1;
+        self.scopes.push(HashMap::new());
+        if let Some(caught) = caught {
+            let name = self.declare(caught);
+            self.line(&format!("let {} = $caught($error);", name));
+        }
+        for statement in statements {
+            self.statement(statement);
+        }
+        self.scopes.pop();
+        self.indent -= 1;
+    }
+
+    fn body(&mut self, statement: &Rc<Statement>) {
+        match statement.as_ref() {
+            Statement::Block(statements) => self.block(statements),
+            _ => self.block(core::slice::from_ref(statement)),
+        }
+    }
+
+    fn function(
+        &mut self,
+        declaration: &FunctionDeclaration,
+        kind: FunctionKind,
+        header: &str,
+        footer: &str,
+    ) {
+        self.scopes.push(HashMap::new());
+        // JavaScript fills in defaults and gathers rest parameters the same
+        // way, a default seeing the parameters before it.
+        let required = declaration.arity().required;
+        let mut parameters = Vec::new();
+        for (index, parameter) in declaration.parameters.iter().enumerate() {
+            let default = index
+                .checked_sub(required)
+                .map(|index| self.expression(&declaration.defaults[index]));
+            let parameter = self.declare(parameter);
+            parameters.push(match default {
+                Some(default) => format!("{} = {}", parameter, default),
+                None => parameter,
+            });
+        }
+        if let Some(rest) = &declaration.rest {
+            let rest = self.declare(rest);
+            parameters.push(format!("...{}", rest));
+        }
+        self.line(&format!("{}({}) {{", header, parameters.join(", ")));
+
+        let initializer = kind == FunctionKind::Initializer;
+        let enclosing = mem::replace(&mut self.initializer, initializer);
+        self.indent += 1;
+        self.functions += 1;
+        if kind != FunctionKind::Function {
+            self.line("const $this = this;");
+        }
+        let deferring = defers(&declaration.body);
+        if deferring {
+            self.line("const $deferred = [];");
+            self.line("try {");
+            self.indent += 1;
+        }
+        for statement in &declaration.body {
+            self.statement(statement);
+        }
+        if !matches!(
+            declaration.body.last().map(Rc::as_ref),
+            Some(Statement::Return { .. })
+        ) {
+            self.line(if initializer {
+                "return $this;"
+            } else {
+                "return null;"
+            });
+        }
+        if deferring {
+            self.indent -= 1;
+            self.line("} finally {");
+            self.line("    $runDeferred($deferred);");
+            self.line("}");
+        }
+        self.functions -= 1;
+        self.indent -= 1;
+        self.initializer = enclosing;
+        self.scopes.pop();
+        self.line(footer);
+    }
+
+    // Locals that shadow a visible name get a fresh JavaScript name, so an initializer like
+    // `var a = a + 1;` still reads the outer variable instead of hitting the new binding early.
+    fn declare(&mut self, name: &Token) -> String {
+        let lexeme = name.lexeme();
+        let mut target = identifier(name);
+        if self.scopes.len() > 1 && self.scopes.iter().any(|scope| scope.contains_key(&lexeme)) {
+            self.renamed += 1;
+            target = format!("{}${}", target, self.renamed);
+        }
+
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .insert(lexeme, target.clone());
+        target
+    }
+
+    fn resolve(&self, name: &Token) -> String {
+        let lexeme = name.lexeme();
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(&lexeme).cloned())
+            .unwrap_or_else(|| identifier(name))
+    }
+
+    fn expression(&mut self, expression: &Expr) -> String {
+        let (operand, links) = expression.chain();
+        let mut code = self.node(&operand, String::new());
+        let mut links = links.into_iter().peekable();
+        while let Some(link) = links.next() {
+            // A method call becomes a single `$invoke`, so the property it
+            // calls is left to the call.
+            let invoked = matches!(link.kind, ExpressionKind::Get { .. })
+                && links
+                    .peek()
+                    .is_some_and(|next| matches!(next.kind, ExpressionKind::Call { .. }));
+            if !invoked {
+                code = self.node(&link, code);
+            }
+        }
+        code
+    }
+
+    // Transpiles one node; `chained` is the code for the operand a link of a
+    // chain continues from.
+    fn node(&mut self, expression: &Expr, chained: String) -> String {
+        match &expression.kind {
+            ExpressionKind::Literal(literal) => match literal {
+                Literal::Nil => "null".to_string(),
+                Literal::Boolean(boolean) => boolean.to_string(),
+                Literal::Number(number) => number.to_string(),
+                Literal::Integer(integer) => integer.to_string(),
+                Literal::String(string) => quote(string),
+            },
+            ExpressionKind::Grouping(inner) => {
+                format!("({})", self.expression(&expression.get(*inner)))
+            }
+            ExpressionKind::Unary { operator, right } => {
+                let right = self.expression(&expression.get(*right));
+                match operator.kind {
+                    Kind::Minus => format!("$negate({})", right),
+                    _ => format!("!$truthy({})", right),
+                }
+            }
+            ExpressionKind::Binary {
+                operator, right, ..
+            } => {
+                let left = chained;
+                let right = self.expression(&expression.get(*right));
+                let helper = match operator.kind {
+                    Kind::EqualEqual => return format!("$equal({}, {})", left, right),
+                    Kind::ExclamationEqual => return format!("!$equal({}, {})", left, right),
+                    Kind::Plus => "$add",
+                    Kind::Minus => "$subtract",
+                    Kind::Asterisk => "$multiply",
+                    Kind::Slash => "$divide",
+                    Kind::Keyword(Keyword::Div) => "$floorDivide",
+                    Kind::Greater => "$greater",
+                    Kind::GreaterEqual => "$greaterEqual",
+                    Kind::Less => "$less",
+                    _ => "$lessEqual",
+                };
+                format!("{}({}, {})", helper, left, right)
+            }
+            ExpressionKind::Logical {
+                operator, right, ..
+            } => {
+                let left = chained;
+                let right = self.expression(&expression.get(*right));
+                let helper = match operator.kind {
+                    Kind::Keyword(Keyword::Or) => "$or",
+                    _ => "$and",
+                };
+                format!("{}({}, () => {})", helper, left, right)
+            }
+            ExpressionKind::Variable(name) => self.resolve(name),
+            ExpressionKind::Assign { name, value } => {
+                let value = self.expression(&expression.get(*value));
+                format!("({} = {})", self.resolve(name), value)
+            }
+            ExpressionKind::Call {
+                callee, arguments, ..
+            } => {
+                let arguments: Vec<String> = arguments
+                    .iter()
+                    .map(|argument| self.expression(&expression.get(*argument)))
+                    .collect();
+                match &expression.get(*callee).kind {
+                    ExpressionKind::Get { name, .. } => {
+                        let mut parts = vec![chained, quote(&name.lexeme())];
+                        parts.extend(arguments);
+                        format!("$invoke({})", parts.join(", "))
+                    }
+                    _ => format!("{}({})", chained, arguments.join(", ")),
+                }
+            }
+            ExpressionKind::Get { name, .. } => {
+                format!("$get({}, {})", chained, quote(&name.lexeme()))
+            }
+            ExpressionKind::Set {
+                object,
+                name,
+                value,
+            } => format!(
+                "$set({}, {}, {})",
+                self.expression(&expression.get(*object)),
+                quote(&name.lexeme()),
+                self.expression(&expression.get(*value))
+            ),
+            ExpressionKind::List { elements, .. } => {
+                let elements: Vec<String> = elements
+                    .iter()
+                    .map(|element| self.expression(&expression.get(*element)))
+                    .collect();
+                format!("[{}]", elements.join(", "))
+            }
+            ExpressionKind::Map { entries, .. } => {
+                let entries: Vec<String> = entries
+                    .iter()
+                    .map(|(key, value)| {
+                        format!(
+                            "[{}, {}]",
+                            self.expression(&expression.get(*key)),
+                            self.expression(&expression.get(*value))
+                        )
+                    })
+                    .collect();
+                format!("$map([{}])", entries.join(", "))
+            }
+            ExpressionKind::Index { index, .. } => {
+                format!(
+                    "$index({}, {})",
+                    chained,
+                    self.expression(&expression.get(*index))
+                )
+            }
+            ExpressionKind::SetIndex {
+                object,
+                index,
+                value,
+                ..
+            } => format!(
+                "$setIndex({}, {}, {})",
+                self.expression(&expression.get(*object)),
+                self.expression(&expression.get(*index)),
+                self.expression(&expression.get(*value))
+            ),
+            ExpressionKind::This(_) => "$this".to_string(),
+            ExpressionKind::Super { keyword, method } => {
+                if self.classes.last() != Some(&true) {
+                    self.errors.push(Error {
+                        message: "Can't use 'super' outside of a subclass method.".to_string(),
+                        line: keyword.line(),
+                        location: None,
+                    });
+                }
+
+                format!("$super($superclass, $this, {})", quote(&method.lexeme()))
+            }
+        }
+    }
+}
+
+// Whether the statements reach a `defer` of their own function's.
+fn defers(statements: &[Rc<Statement>]) -> bool {
+    statements.iter().any(|statement| match statement.as_ref() {
+        Statement::Defer { .. } => true,
+        Statement::Block(statements) => defers(statements),
+        Statement::If {
+            then_branch,
+            else_branch,
+            ..
+        } => defers(core::slice::from_ref(then_branch)) || defers(else_branch.as_slice()),
+        Statement::While { body, .. } | Statement::ForIn { body, .. } => {
+            defers(core::slice::from_ref(body))
+        }
+        Statement::Try { body, handler, .. } => defers(body) || defers(handler),
+        _ => false,
+    })
+}
+
+fn identifier(name: &Token) -> String {
+    let name = name.lexeme();
+    if RESERVED.contains(&name.as_str()) {
+        format!("{}$", name)
+    } else {
+        name
+    }
+}
+
+fn quote(string: &str) -> String {
+    let mut quoted = String::from("\"");
+    for character in string.chars() {
+        match character {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            character if character < ' ' || character == '\u{2028}' || character == '\u{2029}' => {
+                quoted.push_str(&format!("\\u{:04x}", character as u32))
+            }
+            character => quoted.push(character),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
@@ -0,0 +1,463 @@
+use alloc::collections::BTreeMap;
+use alloc::rc;
+use core::cell::RefCell;
+use core::fmt;
+
+use crate::arity::Arity;
+use crate::bytecode::Prototype;
+use crate::environment::Environment;
+use crate::error::RuntimeError;
+use crate::foreign::Foreign;
+use crate::interpreter::{Generator, Interpreter};
+use crate::prelude::*;
+#[cfg(feature = "regvm")]
+use crate::regvm::RegisterFunction;
+use crate::statement::FunctionDeclaration;
+use crate::symbol::SymbolTable;
+
+#[derive(Clone)]
+pub enum Value {
+    Nil,
+    Boolean(bool),
+    Number(f64),
+    // Only in dialects with integers.
+    Int(i64),
+    String(Rc<str>),
+    Function(Rc<Function>),
+    Closure(Rc<Closure>),
+    BoundMethod(Rc<BoundMethod>),
+    Native(Rc<Native>),
+    Class(Rc<Class>),
+    Instance(Rc<RefCell<Instance>>),
+    Generator(Rc<RefCell<Generator>>),
+    List(Rc<RefCell<Vec<Value>>>),
+    Map(Rc<RefCell<BTreeMap<String, Value>>>),
+    Foreign(Rc<Foreign>),
+    #[cfg(feature = "regvm")]
+    RegisterFunction(Rc<RegisterFunction>),
+}
+
+impl From<Vec<Value>> for Value {
+    fn from(elements: Vec<Value>) -> Value {
+        Value::List(Rc::new(RefCell::new(elements)))
+    }
+}
+
+impl From<BTreeMap<String, Value>> for Value {
+    fn from(entries: BTreeMap<String, Value>) -> Value {
+        Value::Map(Rc::new(RefCell::new(entries)))
+    }
+}
+
+impl Value {
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Value::Nil | Value::Boolean(false))
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Nil => "nil",
+            Value::Boolean(_) => "bool",
+            Value::Number(_) => "number",
+            Value::Int(_) => "int",
+            Value::String(_) => "string",
+            Value::Function(_) | Value::Closure(_) | Value::BoundMethod(_) | Value::Native(_) => {
+                "function"
+            }
+            Value::Class(_) => "class",
+            Value::Instance(_) => "instance",
+            Value::Generator(_) => "generator",
+            Value::List(_) => "list",
+            Value::Map(_) => "map",
+            Value::Foreign(_) => "foreign",
+            #[cfg(feature = "regvm")]
+            Value::RegisterFunction(_) => "function",
+        }
+    }
+}
+
+// A handle on a heap value that doesn't keep it alive, for natives and
+// foreign objects that need to find an object again without owning it.
+// Upgrading fails once the object has been freed, whether its last reference
+// went away or the collector broke the cycle it was in.
+#[derive(Clone)]
+pub enum Weak {
+    Function(rc::Weak<Function>),
+    Closure(rc::Weak<Closure>),
+    BoundMethod(rc::Weak<BoundMethod>),
+    Native(rc::Weak<Native>),
+    Class(rc::Weak<Class>),
+    Instance(rc::Weak<RefCell<Instance>>),
+    Generator(rc::Weak<RefCell<Generator>>),
+    List(rc::Weak<RefCell<Vec<Value>>>),
+    Map(rc::Weak<RefCell<BTreeMap<String, Value>>>),
+    Foreign(rc::Weak<Foreign>),
+    #[cfg(feature = "regvm")]
+    RegisterFunction(rc::Weak<RegisterFunction>),
+}
+
+impl Weak {
+    pub fn upgrade(&self) -> Option<Value> {
+        Some(match self {
+            Weak::Function(function) => Value::Function(function.upgrade()?),
+            Weak::Closure(closure) => Value::Closure(closure.upgrade()?),
+            Weak::BoundMethod(bound) => Value::BoundMethod(bound.upgrade()?),
+            Weak::Native(native) => Value::Native(native.upgrade()?),
+            Weak::Class(class) => Value::Class(class.upgrade()?),
+            Weak::Instance(instance) => Value::Instance(instance.upgrade()?),
+            Weak::Generator(generator) => Value::Generator(generator.upgrade()?),
+            Weak::List(list) => Value::List(list.upgrade()?),
+            Weak::Map(map) => Value::Map(map.upgrade()?),
+            Weak::Foreign(foreign) => Value::Foreign(foreign.upgrade()?),
+            #[cfg(feature = "regvm")]
+            Weak::RegisterFunction(function) => Value::RegisterFunction(function.upgrade()?),
+        })
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.upgrade().is_some()
+    }
+}
+
+impl Value {
+    // Nil, booleans, numbers and strings are values rather than objects, and
+    // have no weak handle.
+    pub fn downgrade(&self) -> Option<Weak> {
+        Some(match self {
+            Value::Nil
+            | Value::Boolean(_)
+            | Value::Number(_)
+            | Value::Int(_)
+            | Value::String(_) => return None,
+            Value::Function(function) => Weak::Function(Rc::downgrade(function)),
+            Value::Closure(closure) => Weak::Closure(Rc::downgrade(closure)),
+            Value::BoundMethod(bound) => Weak::BoundMethod(Rc::downgrade(bound)),
+            Value::Native(native) => Weak::Native(Rc::downgrade(native)),
+            Value::Class(class) => Weak::Class(Rc::downgrade(class)),
+            Value::Instance(instance) => Weak::Instance(Rc::downgrade(instance)),
+            Value::Generator(generator) => Weak::Generator(Rc::downgrade(generator)),
+            Value::List(list) => Weak::List(Rc::downgrade(list)),
+            Value::Map(map) => Weak::Map(Rc::downgrade(map)),
+            Value::Foreign(foreign) => Weak::Foreign(Rc::downgrade(foreign)),
+            #[cfg(feature = "regvm")]
+            Value::RegisterFunction(function) => Weak::RegisterFunction(Rc::downgrade(function)),
+        })
+    }
+}
+
+// Joins two strings into a new buffer, or shares one of them when the other
+// is empty.
+pub fn concatenate(left: &Rc<str>, right: &Rc<str>) -> Rc<str> {
+    if right.is_empty() {
+        return left.clone();
+    }
+    if left.is_empty() {
+        return right.clone();
+    }
+    let mut joined = String::with_capacity(left.len() + right.len());
+    joined.push_str(left);
+    joined.push_str(right);
+    joined.into()
+}
+
+// A new list holding the elements of `left` followed by those of `right`.
+pub fn concatenate_lists(left: &RefCell<Vec<Value>>, right: &RefCell<Vec<Value>>) -> Value {
+    let mut elements = left.borrow().clone();
+    elements.extend(right.borrow().iter().cloned());
+    Value::from(elements)
+}
+
+type ListPair = (*const RefCell<Vec<Value>>, *const RefCell<Vec<Value>>);
+
+impl Value {
+    // Lists compare element by element. A pair of lists met again while
+    // still comparing them counts as equal, so lists that contain themselves
+    // compare without recursing forever.
+    fn equals(&self, other: &Value, comparing: &mut Vec<ListPair>) -> bool {
+        match (self, other) {
+            (Value::List(left), Value::List(right)) => {
+                let pair: ListPair = (Rc::as_ptr(left), Rc::as_ptr(right));
+                if Rc::ptr_eq(left, right) || comparing.contains(&pair) {
+                    return true;
+                }
+
+                let (left, right) = (left.borrow(), right.borrow());
+                if left.len() != right.len() {
+                    return false;
+                }
+                comparing.push(pair);
+                let equal = left
+                    .iter()
+                    .zip(right.iter())
+                    .all(|(left, right)| left.equals(right, comparing));
+                comparing.pop();
+                equal
+            }
+            _ => self == other,
+        }
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Nil, Value::Nil) => true,
+            (Value::Boolean(left), Value::Boolean(right)) => left == right,
+            (Value::Number(left), Value::Number(right)) => left == right,
+            (Value::Int(left), Value::Int(right)) => left == right,
+            (Value::Int(integer), Value::Number(number))
+            | (Value::Number(number), Value::Int(integer)) => *integer as f64 == *number,
+            (Value::String(left), Value::String(right)) => left == right,
+            (Value::Function(left), Value::Function(right)) => {
+                Rc::ptr_eq(left, right) || left.same_binding(right)
+            }
+            (Value::Closure(left), Value::Closure(right)) => Rc::ptr_eq(left, right),
+            (Value::BoundMethod(left), Value::BoundMethod(right)) => {
+                Rc::ptr_eq(&left.method, &right.method) && left.receiver == right.receiver
+            }
+            (Value::Native(left), Value::Native(right)) => Rc::ptr_eq(left, right),
+            (Value::Class(left), Value::Class(right)) => Rc::ptr_eq(left, right),
+            (Value::Instance(left), Value::Instance(right)) => Rc::ptr_eq(left, right),
+            (Value::Generator(left), Value::Generator(right)) => Rc::ptr_eq(left, right),
+            (Value::List(_), Value::List(_)) => self.equals(other, &mut Vec::new()),
+            (Value::Map(left), Value::Map(right)) => Rc::ptr_eq(left, right),
+            (Value::Foreign(left), Value::Foreign(right)) => Rc::ptr_eq(left, right),
+            #[cfg(feature = "regvm")]
+            (Value::RegisterFunction(left), Value::RegisterFunction(right)) => {
+                Rc::ptr_eq(left, right)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write_value(formatter, self, false, &mut Vec::new())
+    }
+}
+
+impl fmt::Debug for Value {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write_value(formatter, self, true, &mut Vec::new())
+    }
+}
+
+// Writes `value`, with strings quoted when it sits inside a list or map.
+// `visiting` holds the lists and maps being written around it, so one that
+// contains itself shows up inside as `[...]` or `{...}` instead of without
+// end.
+fn write_value(
+    formatter: &mut fmt::Formatter,
+    value: &Value,
+    quoted: bool,
+    visiting: &mut Vec<*const ()>,
+) -> fmt::Result {
+    match value {
+        Value::Nil => write!(formatter, "nil"),
+        Value::Boolean(boolean) => write!(formatter, "{}", boolean),
+        Value::Number(number) if number.is_infinite() => {
+            let sign = if number.is_sign_negative() { "-" } else { "" };
+            write!(formatter, "{}Infinity", sign)
+        }
+        Value::Number(number) => write!(formatter, "{}", number),
+        Value::Int(integer) => write!(formatter, "{}", integer),
+        Value::String(string) if quoted => write!(formatter, "{:?}", string),
+        Value::String(string) => write!(formatter, "{}", string),
+        Value::Function(function) => write!(formatter, "<fn {}>", function.name()),
+        Value::Closure(closure) => write!(formatter, "<fn {}>", closure.prototype.name),
+        Value::BoundMethod(bound) => write!(formatter, "<fn {}>", bound.method.prototype.name),
+        Value::Native(_) => write!(formatter, "<native fn>"),
+        Value::Class(class) => write!(formatter, "{}", class.name),
+        Value::Instance(instance) => {
+            write!(formatter, "{} instance", instance.borrow().class.name)
+        }
+        Value::Generator(generator) => {
+            write!(formatter, "<generator {}>", generator.borrow().name())
+        }
+        Value::List(list) => {
+            let pointer = Rc::as_ptr(list).cast();
+            if visiting.contains(&pointer) {
+                return write!(formatter, "[...]");
+            }
+
+            visiting.push(pointer);
+            write!(formatter, "[")?;
+            for (index, element) in list.borrow().iter().enumerate() {
+                if index > 0 {
+                    write!(formatter, ", ")?;
+                }
+                write_value(formatter, element, true, visiting)?;
+            }
+            visiting.pop();
+            write!(formatter, "]")
+        }
+        Value::Map(map) => {
+            let pointer = Rc::as_ptr(map).cast();
+            if visiting.contains(&pointer) {
+                return write!(formatter, "{{...}}");
+            }
+
+            visiting.push(pointer);
+            write!(formatter, "{{")?;
+            for (index, (key, value)) in map.borrow().iter().enumerate() {
+                if index > 0 {
+                    write!(formatter, ", ")?;
+                }
+                write!(formatter, "{:?}: ", key)?;
+                write_value(formatter, value, true, visiting)?;
+            }
+            visiting.pop();
+            write!(formatter, "}}")
+        }
+        Value::Foreign(foreign) => write!(formatter, "{} instance", foreign.class().name()),
+        #[cfg(feature = "regvm")]
+        Value::RegisterFunction(function) => write!(formatter, "<fn {}>", function.name),
+    }
+}
+
+pub struct Function {
+    pub declaration: Rc<FunctionDeclaration>,
+    pub closure: Rc<RefCell<Environment>>,
+    pub is_initializer: bool,
+    // A method bound to the `this` its closure holds.
+    pub is_bound: bool,
+}
+
+impl Function {
+    pub fn name(&self) -> String {
+        self.declaration.name.lexeme()
+    }
+
+    pub fn arity(&self) -> Arity {
+        self.declaration.arity()
+    }
+
+    pub fn bind(&self, instance: Value) -> Function {
+        let mut environment = Environment::with_enclosing(Rc::clone(&self.closure));
+        environment.push(instance);
+
+        Function {
+            declaration: Rc::clone(&self.declaration),
+            closure: Rc::new(RefCell::new(environment)),
+            is_initializer: self.is_initializer,
+            is_bound: true,
+        }
+    }
+
+    // Two bindings of one method to one receiver are the same method, however
+    // many times it was looked up.
+    fn same_binding(&self, other: &Function) -> bool {
+        if !self.is_bound || !other.is_bound || !Rc::ptr_eq(&self.declaration, &other.declaration) {
+            return false;
+        }
+        let receiver = self.closure.borrow().get_at(0, 0);
+        receiver.is_some() && receiver == other.closure.borrow().get_at(0, 0)
+    }
+}
+
+pub struct Closure {
+    pub prototype: Rc<Prototype>,
+    pub upvalues: Vec<Rc<RefCell<Upvalue>>>,
+}
+
+pub enum Upvalue {
+    Open(usize),
+    Closed(Value),
+}
+
+pub struct BoundMethod {
+    pub receiver: Value,
+    pub method: Rc<Closure>,
+}
+
+#[derive(Clone)]
+pub enum Method {
+    Function(Rc<Function>),
+    Closure(Rc<Closure>),
+}
+
+impl Method {
+    pub fn arity(&self) -> Arity {
+        match self {
+            Method::Function(function) => function.arity(),
+            Method::Closure(closure) => closure.prototype.arity,
+        }
+    }
+
+    pub fn bind(&self, instance: Value) -> Value {
+        match self {
+            Method::Function(function) => Value::Function(Rc::new(function.bind(instance))),
+            Method::Closure(closure) => Value::BoundMethod(Rc::new(BoundMethod {
+                receiver: instance,
+                method: Rc::clone(closure),
+            })),
+        }
+    }
+}
+
+pub type NativeFunction = Rc<dyn Fn(&mut Interpreter, &[Value]) -> Result<Value, RuntimeError>>;
+
+pub struct Native {
+    pub name: String,
+    pub arity: usize,
+    pub function: NativeFunction,
+}
+
+pub struct Class {
+    pub name: String,
+    pub superclass: Option<Rc<Class>>,
+    pub methods: HashMap<String, Method>,
+}
+
+impl Class {
+    pub fn find_method(&self, name: &str) -> Option<Method> {
+        let mut class = self;
+        loop {
+            if let Some(method) = class.methods.get(name) {
+                return Some(method.clone());
+            }
+
+            match &class.superclass {
+                Some(superclass) => class = superclass,
+                None => return None,
+            }
+        }
+    }
+
+    pub fn method_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = Vec::new();
+        let mut class = Some(self);
+        while let Some(current) = class {
+            for name in current.methods.keys() {
+                if !names.contains(name) {
+                    names.push(name.clone());
+                }
+            }
+
+            class = current.superclass.as_deref();
+        }
+
+        names.sort();
+        names
+    }
+
+    pub fn arity(&self) -> Arity {
+        self.find_method("init")
+            .map_or(Arity::exactly(0), |initializer| initializer.arity())
+    }
+}
+
+pub struct Instance {
+    pub class: Rc<Class>,
+    pub fields: SymbolTable<Value>,
+}
+
+impl Instance {
+    pub fn new(class: Rc<Class>) -> Instance {
+        Instance {
+            class,
+            fields: SymbolTable::new(),
+        }
+    }
+}
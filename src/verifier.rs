@@ -0,0 +1,355 @@
+use alloc::collections::BTreeMap;
+
+use crate::bytecode::{Constant, OpCode, Prototype};
+use crate::prelude::*;
+
+// Checks loaded bytecode for what the VM takes on trust from the compiler:
+// known opcodes with their operands inside the code, constants, locals and
+// upvalues that exist, and jumps that stay inside the function. Locals are
+// checked against how many values the stack holds, which is followed along
+// every path through the code and must come out the same wherever two
+// paths meet.
+pub(crate) fn verify(script: &Prototype) -> Result<(), String> {
+    if script.arity.required > 0
+        || script.arity.optional > 0
+        || script.arity.variadic
+        || script.upvalue_count > 0
+        || script.is_generator
+    {
+        return Err("The script can't take parameters, capture variables or yield.".to_string());
+    }
+
+    verify_function(script)
+}
+
+// The stack at the start of an instruction: the values the call has on it,
+// its own slot included, and the height it had at each `try` block it is
+// inside. A handler puts the caught value back where the stack stood at its
+// `try`, so nothing in the block may pop below that.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct State {
+    height: usize,
+    tries: Vec<usize>,
+}
+
+fn verify_function(prototype: &Prototype) -> Result<(), String> {
+    for constant in prototype.chunk.constants() {
+        if let Constant::Function(function) = constant {
+            verify_function(function)?;
+        }
+    }
+
+    let mut verifier = Verifier {
+        prototype,
+        states: BTreeMap::new(),
+        pending: Vec::new(),
+    };
+
+    let arity = prototype.arity;
+    let entry = State {
+        height: 1 + arity.named() + usize::from(arity.variadic),
+        tries: Vec::new(),
+    };
+    verifier.flow(0, entry)?;
+    for (skipped, &offset) in prototype.defaults.iter().enumerate() {
+        let state = State {
+            height: 1 + arity.required + skipped,
+            tries: Vec::new(),
+        };
+        verifier.flow(offset, state)?;
+    }
+
+    while let Some(offset) = verifier.pending.pop() {
+        verifier.step(offset)?;
+    }
+
+    Ok(())
+}
+
+struct Verifier<'a> {
+    prototype: &'a Prototype,
+    states: BTreeMap<usize, State>,
+    pending: Vec<usize>,
+}
+
+impl Verifier<'_> {
+    fn step(&mut self, offset: usize) -> Result<(), String> {
+        let State { height, mut tries } = self.states[&offset].clone();
+        let floor = tries.last().copied().unwrap_or(0);
+        let byte = self.byte(offset)?;
+        let op = OpCode::try_from(byte)
+            .map_err(|byte| self.error(offset, format!("Unknown opcode {}", byte)))?;
+
+        let next = offset + 1;
+        let (pops, pushes, next) = match op {
+            OpCode::Nil | OpCode::True | OpCode::False => (0, 1, next),
+            OpCode::Pop | OpCode::Print | OpCode::CloseUpvalue | OpCode::Defer => (1, 0, next),
+            OpCode::Not | OpCode::Negate => (1, 1, next),
+            OpCode::Iterator => (1, 2, next),
+            OpCode::Yield if self.prototype.is_generator => (1, 0, next),
+            OpCode::Yield => {
+                return Err(self.error(offset, "Yield outside a generator".to_string()))
+            }
+            OpCode::Equal
+            | OpCode::Greater
+            | OpCode::GreaterEqual
+            | OpCode::Less
+            | OpCode::LessEqual
+            | OpCode::Add
+            | OpCode::Subtract
+            | OpCode::Multiply
+            | OpCode::Divide
+            | OpCode::FloorDivide
+            | OpCode::Index
+            | OpCode::Unpack => (2, 1, next),
+            OpCode::SetIndex => (3, 1, next),
+            OpCode::Constant | OpCode::ConstantLong => {
+                let (index, next) = self.index(op, offset)?;
+                if let Constant::Function(function) = self.constant(offset, index)? {
+                    if function.upvalue_count > 0 {
+                        return Err(
+                            self.error(offset, "Constant function captures variables".to_string())
+                        );
+                    }
+                }
+                (0, 1, next)
+            }
+            OpCode::DefineGlobal | OpCode::DefineGlobalLong => (1, 0, self.name(op, offset)?),
+            OpCode::GetGlobal | OpCode::GetGlobalLong => (0, 1, self.name(op, offset)?),
+            OpCode::SetGlobal | OpCode::SetGlobalLong => (1, 1, self.name(op, offset)?),
+            OpCode::GetProperty | OpCode::GetPropertyLong => (1, 1, self.name(op, offset)?),
+            OpCode::SetProperty
+            | OpCode::SetPropertyLong
+            | OpCode::GetSuper
+            | OpCode::GetSuperLong => (2, 1, self.name(op, offset)?),
+            OpCode::GetLocal | OpCode::SetLocal | OpCode::GetLocalLong | OpCode::SetLocalLong => {
+                let (slot, next) = if op.is_long() {
+                    (self.short(offset + 1)? as usize, offset + 3)
+                } else {
+                    (self.byte(offset + 1)? as usize, offset + 2)
+                };
+                if slot >= height {
+                    return Err(self.error(offset, format!("Local slot {} out of range", slot)));
+                }
+                match op.short() {
+                    OpCode::GetLocal => (0, 1, next),
+                    _ => (1, 1, next),
+                }
+            }
+            OpCode::GetUpvalue | OpCode::SetUpvalue => {
+                let index = self.byte(offset + 1)? as usize;
+                if index >= self.prototype.upvalue_count {
+                    return Err(self.error(offset, format!("Upvalue {} out of range", index)));
+                }
+                match op {
+                    OpCode::GetUpvalue => (0, 1, offset + 2),
+                    _ => (1, 1, offset + 2),
+                }
+            }
+            OpCode::Closure | OpCode::ClosureLong => {
+                let (index, mut next) = self.index(op, offset)?;
+                let Constant::Function(function) = self.constant(offset, index)? else {
+                    return Err(self.error(
+                        offset,
+                        format!("Closure of constant {}, which isn't a function", index),
+                    ));
+                };
+
+                // A local function captures the slot its closure is about
+                // to take, one past the top.
+                for _ in 0..function.upvalue_count {
+                    let is_local = self.flag(next)?;
+                    let index = self.short(next + 1)? as usize;
+                    if (is_local && index > height)
+                        || (!is_local && index >= self.prototype.upvalue_count)
+                    {
+                        return Err(
+                            self.error(offset, format!("Captured variable {} out of range", index))
+                        );
+                    }
+                    next += 3;
+                }
+                (0, 1, next)
+            }
+            OpCode::Class | OpCode::ClassLong => {
+                let next = self.name(op, offset)?;
+                let methods = self.byte(next)? as usize;
+                let inherits = usize::from(self.flag(next + 1)?);
+                (methods + inherits, 1, next + 2)
+            }
+            OpCode::BuildList | OpCode::BuildMap => {
+                let count = self.short(offset + 1)? as usize;
+                let pops = match op {
+                    OpCode::BuildList => count,
+                    _ => count * 2,
+                };
+                (pops, 1, offset + 3)
+            }
+            OpCode::Call => (self.byte(offset + 1)? as usize + 1, 1, offset + 2),
+            OpCode::ExpectValues => {
+                self.short(offset + 1)?;
+                (1, 1, offset + 3)
+            }
+            OpCode::Jump | OpCode::Loop => {
+                let target = self.target(op, offset)?;
+                return self.flow(target, State { height, tries });
+            }
+            OpCode::JumpIfFalse => {
+                let target = self.target(op, offset)?;
+                self.pops(offset, height - floor, 1)?;
+                let state = State {
+                    height,
+                    tries: tries.clone(),
+                };
+                self.flow(target, state)?;
+                (0, 0, offset + 3)
+            }
+            // The iterator and its count stay on the stack, with the next
+            // value above them unless the loop is over.
+            OpCode::Next => {
+                let target = self.target(op, offset)?;
+                self.pops(offset, height - floor, 2)?;
+                let state = State {
+                    height,
+                    tries: tries.clone(),
+                };
+                self.flow(target, state)?;
+                (0, 1, offset + 3)
+            }
+            OpCode::Try => {
+                let target = self.target(op, offset)?;
+                let handler = State {
+                    height: height + 1,
+                    tries: tries.clone(),
+                };
+                self.flow(target, handler)?;
+                tries.push(height);
+                return self.flow(offset + 3, State { height, tries });
+            }
+            OpCode::EndTry => {
+                if tries.pop().is_none() {
+                    return Err(self.error(offset, "End of a try block outside one".to_string()));
+                }
+                return self.flow(next, State { height, tries });
+            }
+            OpCode::Throw | OpCode::Return => {
+                return self.pops(offset, height - floor, 1);
+            }
+        };
+
+        self.pops(offset, height - floor, pops)?;
+        self.flow(
+            next,
+            State {
+                height: height - pops + pushes,
+                tries,
+            },
+        )
+    }
+
+    fn flow(&mut self, offset: usize, state: State) -> Result<(), String> {
+        if offset >= self.prototype.chunk.len() {
+            return Err(format!(
+                "Code in '{}' runs past its end.",
+                self.prototype.name
+            ));
+        }
+
+        match self.states.get(&offset) {
+            Some(existing) if *existing == state => Ok(()),
+            Some(_) => Err(self.error(offset, "Paths meet with different stacks".to_string())),
+            None => {
+                self.states.insert(offset, state);
+                self.pending.push(offset);
+                Ok(())
+            }
+        }
+    }
+
+    // Whether `count` values can come off a stack with `available` on it
+    // above the innermost `try`.
+    fn pops(&self, offset: usize, available: usize, count: usize) -> Result<(), String> {
+        if count > available {
+            return Err(self.error(offset, format!("Stack underflow popping {}", count)));
+        }
+        Ok(())
+    }
+
+    fn byte(&self, offset: usize) -> Result<u8, String> {
+        self.prototype
+            .chunk
+            .code()
+            .get(offset)
+            .copied()
+            .ok_or_else(|| {
+                format!(
+                    "Code in '{}' ends inside an instruction.",
+                    self.prototype.name
+                )
+            })
+    }
+
+    fn short(&self, offset: usize) -> Result<u16, String> {
+        Ok(u16::from_be_bytes([
+            self.byte(offset)?,
+            self.byte(offset + 1)?,
+        ]))
+    }
+
+    fn flag(&self, offset: usize) -> Result<bool, String> {
+        match self.byte(offset)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            byte => Err(self.error(offset, format!("Invalid flag {}", byte))),
+        }
+    }
+
+    // A constant operand and where the instruction goes on after it.
+    fn index(&self, op: OpCode, offset: usize) -> Result<(usize, usize), String> {
+        if op.is_long() {
+            let bytes = [
+                0,
+                self.byte(offset + 1)?,
+                self.byte(offset + 2)?,
+                self.byte(offset + 3)?,
+            ];
+            Ok((u32::from_be_bytes(bytes) as usize, offset + 4))
+        } else {
+            Ok((self.byte(offset + 1)? as usize, offset + 2))
+        }
+    }
+
+    fn constant(&self, offset: usize, index: usize) -> Result<&Constant, String> {
+        self.prototype
+            .chunk
+            .constants()
+            .get(index)
+            .ok_or_else(|| self.error(offset, format!("Constant {} out of range", index)))
+    }
+
+    fn name(&self, op: OpCode, offset: usize) -> Result<usize, String> {
+        let (index, next) = self.index(op, offset)?;
+        match self.constant(offset, index)? {
+            Constant::String(_) => Ok(next),
+            _ => Err(self.error(offset, format!("Name constant {} isn't a string", index))),
+        }
+    }
+
+    fn target(&self, op: OpCode, offset: usize) -> Result<usize, String> {
+        let jump = self.short(offset + 1)? as usize;
+        let target = match op {
+            OpCode::Loop => (offset + 3).checked_sub(jump),
+            _ => Some(offset + 3 + jump),
+        };
+        target
+            .filter(|&target| target < self.prototype.chunk.len())
+            .ok_or_else(|| self.error(offset, format!("Jump by {} leaves the code", jump)))
+    }
+
+    fn error(&self, offset: usize, problem: String) -> String {
+        format!(
+            "{} at offset {} in '{}'.",
+            problem, offset, self.prototype.name
+        )
+    }
+}
@@ -0,0 +1,1264 @@
+use alloc::collections::BTreeMap;
+use core::cell::RefCell;
+use core::mem;
+
+use crate::arithmetic::{self, Operator};
+use crate::arity::Arity;
+use crate::bytecode::{Constant, OpCode, Prototype};
+use crate::cache::InlineCaches;
+use crate::disassembler::disassemble_instruction;
+use crate::error::{ErrorCategory, RuntimeError, RuntimeErrorKind};
+use crate::interpreter::{Generator, Interpreter, Pending};
+#[cfg(feature = "jit")]
+use crate::jit;
+use crate::prelude::*;
+use crate::symbol::Symbol;
+use crate::value::{
+    concatenate, concatenate_lists, Class, Closure, Instance, Method, Upvalue, Value,
+};
+
+// Where to go when a `try` block fails: the handler's code, in the frame
+// that ran the `try`, with the stack cut back to what it was then.
+struct Handler {
+    frame: usize,
+    stack: usize,
+    ip: usize,
+}
+
+struct CallFrame {
+    closure: Rc<Closure>,
+    ip: usize,
+    slots: usize,
+    caches: usize,
+    // Closures for the deferred blocks reached, run last first as the call
+    // returns.
+    deferred: Vec<Rc<Closure>>,
+    // For a deferred block run as an error left its call, the error to
+    // raise again when it returns.
+    rethrow: Option<Pending>,
+    // For a generator's frame, the generator and where in the loop that
+    // resumed it to go once it finishes.
+    generator: Option<(Rc<RefCell<Generator>>, usize)>,
+}
+
+impl CallFrame {
+    // Finishes the generator, if the frame is one's, as an error drops it.
+    fn abandon(&self) {
+        if let Some((generator, _)) = &self.generator {
+            generator.borrow_mut().finish();
+        }
+    }
+}
+
+// A generator's frame between values: where its code has got to, its
+// slots, the `try` blocks it is inside, and the variables closures have
+// captured from its slots, which hold their values until it runs again.
+// Handlers and captures are kept relative to the first slot.
+pub(crate) struct Suspended {
+    closure: Rc<Closure>,
+    ip: usize,
+    slots: Vec<Value>,
+    handlers: Vec<(usize, usize)>,
+    upvalues: Vec<(usize, Rc<RefCell<Upvalue>>)>,
+    deferred: Vec<Rc<Closure>>,
+}
+
+#[derive(Default)]
+pub struct Vm {
+    stack: Vec<Value>,
+    frames: Vec<CallFrame>,
+    open_upvalues: Vec<Rc<RefCell<Upvalue>>>,
+    handlers: Vec<Handler>,
+    caches: InlineCaches,
+    running_script: bool,
+}
+
+impl Vm {
+    pub fn new() -> Vm {
+        Vm::default()
+    }
+
+    pub fn interpret(
+        &mut self,
+        interpreter: &mut Interpreter,
+        script: Rc<Prototype>,
+    ) -> Result<(), RuntimeError> {
+        interpreter.reset_usage();
+
+        let closure = Rc::new(Closure {
+            prototype: script,
+            upvalues: Vec::new(),
+        });
+        self.stack.push(Value::Closure(Rc::clone(&closure)));
+        let caches = self.caches.register(&closure.prototype);
+        self.frames.push(CallFrame {
+            closure,
+            ip: 0,
+            slots: 0,
+            caches,
+            deferred: Vec::new(),
+            rethrow: None,
+            generator: None,
+        });
+        self.running_script = true;
+
+        let result = self.run(interpreter, 0).map(|_| ());
+        if result.is_err() {
+            interpreter.reset();
+        }
+
+        self.reset();
+        result
+    }
+
+    pub fn call(
+        &mut self,
+        interpreter: &mut Interpreter,
+        receiver: Value,
+        closure: Rc<Closure>,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let base = self.frames.len();
+        let stack_base = self.stack.len();
+        let line = interpreter.current_line();
+
+        self.stack.push(receiver);
+        let count = arguments.len();
+        self.stack.extend(arguments);
+
+        // A generator function hands back its generator without running.
+        let result = self
+            .call_closure(interpreter, closure, count, line)
+            .and_then(|_| {
+                if self.frames.len() == base {
+                    Ok(self.pop())
+                } else {
+                    self.run(interpreter, base)
+                }
+            });
+        if result.is_err() {
+            for frame in self.frames.drain(base..) {
+                frame.abandon();
+            }
+            while self
+                .handlers
+                .last()
+                .is_some_and(|handler| handler.frame >= base)
+            {
+                self.handlers.pop();
+            }
+            self.close_upvalues(stack_base);
+            self.stack.truncate(stack_base);
+        }
+
+        result
+    }
+
+    // The calls under way, innermost first, with the line each has reached.
+    pub(crate) fn calls(&self) -> Vec<(String, usize)> {
+        self.frames
+            .iter()
+            .rev()
+            .map(|frame| {
+                let prototype = &frame.closure.prototype;
+                let line = prototype.chunk.line(frame.ip.saturating_sub(1));
+                (prototype.name.clone(), line)
+            })
+            .collect()
+    }
+
+    pub(crate) fn depth(&self) -> usize {
+        self.frames.len() - usize::from(self.running_script)
+    }
+
+    fn reset(&mut self) {
+        self.stack.clear();
+        for frame in self.frames.drain(..) {
+            frame.abandon();
+        }
+        self.open_upvalues.clear();
+        self.handlers.clear();
+        self.caches.clear();
+        self.running_script = false;
+    }
+
+    fn run(&mut self, interpreter: &mut Interpreter, base: usize) -> Result<Value, RuntimeError> {
+        loop {
+            match self.execute(interpreter, base) {
+                Err(error) => self.catch(interpreter, error, base)?,
+                result => return result,
+            }
+        }
+    }
+
+    // Hands the error to the innermost handler this run can reach, dropping
+    // the frames its `try` block had called into.
+    fn catch(
+        &mut self,
+        interpreter: &mut Interpreter,
+        error: RuntimeError,
+        base: usize,
+    ) -> Result<(), RuntimeError> {
+        if !error.kind.is_catchable() {
+            return Err(error);
+        }
+        let handler = self
+            .handlers
+            .last()
+            .map(|handler| handler.frame)
+            .filter(|&frame| frame >= base);
+        let limit = handler.map_or(base, |frame| frame + 1);
+        if self.frames[limit..]
+            .iter()
+            .any(|frame| !frame.deferred.is_empty())
+        {
+            return self.defer_error(interpreter, error);
+        }
+        if handler.is_none() {
+            return Err(error);
+        }
+
+        let value = self.call_out(interpreter, |interpreter| interpreter.caught_value(error))?;
+        let handler = self.handlers.pop().expect("handler");
+
+        while self.frames.len() > handler.frame + 1 {
+            if let Some(frame) = self.frames.pop() {
+                frame.abandon();
+            }
+            interpreter.profile_leave();
+        }
+        self.close_upvalues(handler.stack);
+        self.stack.truncate(handler.stack);
+        self.stack.push(value);
+        self.frame_mut().ip = handler.ip;
+        Ok(())
+    }
+
+    // Runs the innermost deferred block of the calls the error is leaving,
+    // dropping the calls above it, and has the block raise the error again
+    // when it returns.
+    fn defer_error(
+        &mut self,
+        interpreter: &mut Interpreter,
+        error: RuntimeError,
+    ) -> Result<(), RuntimeError> {
+        while let Some(frame) = self.frames.last_mut() {
+            if let Some(deferred) = frame.deferred.pop() {
+                let pending = interpreter.suspend_error(error);
+                let line = self.line();
+                self.stack.push(Value::Nil);
+                self.call_closure(interpreter, deferred, 0, line)?;
+                self.frame_mut().rethrow = Some(pending);
+                return Ok(());
+            }
+
+            if let Some(frame) = self.frames.pop() {
+                frame.abandon();
+                interpreter.profile_leave();
+                self.close_upvalues(frame.slots);
+                self.stack.truncate(frame.slots);
+            }
+        }
+
+        Err(error)
+    }
+
+    fn execute(
+        &mut self,
+        interpreter: &mut Interpreter,
+        base: usize,
+    ) -> Result<Value, RuntimeError> {
+        loop {
+            if let Err(mut error) = interpreter.count_step() {
+                error.line = self.line();
+                return Err(error);
+            }
+
+            if interpreter.trace_execution() {
+                self.trace(interpreter);
+            }
+
+            let op = self.read_byte();
+            let op = OpCode::try_from(op)
+                .map_err(|byte| self.error(format!("Unknown opcode {}.", byte)))?;
+
+            match op {
+                OpCode::Constant | OpCode::ConstantLong => {
+                    let value = match self.read_constant(op) {
+                        Constant::Number(number) => Value::Number(*number),
+                        Constant::Integer(integer) => Value::Int(*integer),
+                        Constant::String(string) => Value::String(string.clone()),
+                        Constant::Function(prototype) => Value::Closure(Rc::new(Closure {
+                            prototype: Rc::clone(prototype),
+                            upvalues: Vec::new(),
+                        })),
+                    };
+                    interpreter.track_value(&value);
+                    self.stack.push(value);
+                }
+                OpCode::Nil => self.stack.push(Value::Nil),
+                OpCode::True => self.stack.push(Value::Boolean(true)),
+                OpCode::False => self.stack.push(Value::Boolean(false)),
+                OpCode::Pop => {
+                    self.pop();
+                }
+                OpCode::DefineGlobal | OpCode::DefineGlobalLong => {
+                    let name = self.read_name(op);
+                    let value = self.pop();
+                    interpreter.globals().borrow_mut().define(name, value);
+                }
+                OpCode::GetGlobal | OpCode::GetGlobalLong => {
+                    let (caches, site) = self.site();
+                    let index = self.read_index(op);
+                    let version = interpreter.globals().borrow().version();
+                    if let Some(value) = self.caches.global(caches, site, version) {
+                        self.stack.push(value);
+                        continue;
+                    }
+
+                    let name = self.name(index);
+                    let value = interpreter.globals().borrow().get_symbol(&name);
+                    match value {
+                        Some(value) => {
+                            self.caches.set_global(caches, site, version, value.clone());
+                            self.stack.push(value);
+                        }
+                        None => {
+                            return Err(self.name_error(format!("Undefined variable '{}'.", name)))
+                        }
+                    }
+                }
+                OpCode::SetGlobal | OpCode::SetGlobalLong => {
+                    let name = self.read_name(op);
+                    let value = self.peek(0).clone();
+                    if !interpreter
+                        .globals()
+                        .borrow_mut()
+                        .assign_symbol(&name, value)
+                    {
+                        return Err(self.name_error(format!("Undefined variable '{}'.", name)));
+                    }
+                }
+                OpCode::GetLocal | OpCode::GetLocalLong => {
+                    let slot = self.read_slot(op);
+                    let value = self.stack[self.frame().slots + slot].clone();
+                    self.stack.push(value);
+                }
+                OpCode::SetLocal | OpCode::SetLocalLong => {
+                    let slot = self.read_slot(op);
+                    let index = self.frame().slots + slot;
+                    self.stack[index] = self.peek(0).clone();
+                }
+                OpCode::GetUpvalue => {
+                    let index = self.read_byte() as usize;
+                    let value = match &*self.frame().closure.upvalues[index].borrow() {
+                        Upvalue::Open(slot) => self.stack.get(*slot).cloned().unwrap_or(Value::Nil),
+                        Upvalue::Closed(value) => value.clone(),
+                    };
+                    self.stack.push(value);
+                }
+                OpCode::SetUpvalue => {
+                    let index = self.read_byte() as usize;
+                    let value = self.peek(0).clone();
+                    let upvalue = Rc::clone(&self.frame().closure.upvalues[index]);
+                    let mut upvalue = upvalue.borrow_mut();
+                    match &mut *upvalue {
+                        Upvalue::Open(slot) => {
+                            if let Some(open) = self.stack.get_mut(*slot) {
+                                *open = value;
+                            }
+                        }
+                        Upvalue::Closed(closed) => *closed = value,
+                    }
+                }
+                OpCode::Closure | OpCode::ClosureLong => {
+                    let Constant::Function(prototype) = self.read_constant(op) else {
+                        return Err(self.error("Closure operand must be a function.".to_string()));
+                    };
+                    let prototype = Rc::clone(prototype);
+
+                    let mut upvalues = Vec::with_capacity(prototype.upvalue_count);
+                    for _ in 0..prototype.upvalue_count {
+                        let is_local = self.read_byte() == 1;
+                        let index = self.read_u16() as usize;
+                        let upvalue = if is_local {
+                            self.capture_upvalue(interpreter, self.frame().slots + index)
+                        } else {
+                            Rc::clone(&self.frame().closure.upvalues[index])
+                        };
+                        upvalues.push(upvalue);
+                    }
+
+                    let closure = Rc::new(Closure {
+                        prototype,
+                        upvalues,
+                    });
+                    interpreter.track(&closure);
+                    self.stack.push(Value::Closure(closure));
+                }
+                OpCode::CloseUpvalue => {
+                    self.close_upvalues(self.stack.len() - 1);
+                    self.pop();
+                }
+                OpCode::Class | OpCode::ClassLong => {
+                    let name = self.read_name(op);
+                    let count = self.read_byte() as usize;
+                    let has_superclass = self.read_byte() == 1;
+
+                    let methods = self.stack.split_off(self.stack.len() - count);
+                    let superclass = if has_superclass {
+                        let slot = self.stack.len() - 1;
+                        let Value::Class(superclass) = self.peek(0).clone() else {
+                            return Err(self.type_error("Superclass must be a class.".to_string()));
+                        };
+                        self.close_upvalues(slot);
+                        self.pop();
+                        Some(superclass)
+                    } else {
+                        None
+                    };
+
+                    let methods = methods
+                        .into_iter()
+                        .filter_map(|method| match method {
+                            Value::Closure(closure) => {
+                                Some((closure.prototype.name.clone(), Method::Closure(closure)))
+                            }
+                            _ => None,
+                        })
+                        .collect();
+
+                    let class = Rc::new(Class {
+                        name: name.to_string(),
+                        superclass,
+                        methods,
+                    });
+                    interpreter.track(&class);
+                    self.stack.push(Value::Class(class));
+                }
+                OpCode::GetProperty | OpCode::GetPropertyLong => {
+                    let (caches, site) = self.site();
+                    let index = self.read_index(op);
+                    let name = self.name(index);
+                    let object = self.pop();
+
+                    if let Value::Instance(instance) = &object {
+                        let instance = instance.borrow();
+                        if let Some(value) = instance.fields.get_symbol(&name) {
+                            let value = value.clone();
+                            drop(instance);
+                            self.stack.push(value);
+                            continue;
+                        }
+
+                        let class = Rc::clone(&instance.class);
+                        drop(instance);
+                        let method = match self.caches.method(caches, site, &class) {
+                            Some(method) => method,
+                            None => match class.find_method(&name) {
+                                Some(method) => {
+                                    self.caches.set_method(caches, site, &class, method.clone());
+                                    method
+                                }
+                                None => {
+                                    return Err(
+                                        self.name_error(format!("Undefined property '{}'.", name))
+                                    )
+                                }
+                            },
+                        };
+
+                        let method = method.bind(object);
+                        interpreter.track_value(&method);
+                        self.stack.push(method);
+                        continue;
+                    }
+
+                    let line = self.line();
+                    let value = self.call_out(interpreter, |interpreter| {
+                        interpreter.get_property(line, &name, object)
+                    })?;
+                    self.stack.push(value);
+                }
+                OpCode::SetProperty | OpCode::SetPropertyLong => {
+                    let name = self.read_name(op);
+                    let value = self.pop();
+                    let object = self.pop();
+                    let line = self.line();
+                    self.call_out(interpreter, |interpreter| {
+                        interpreter.set_property(line, name, object, value.clone())
+                    })?;
+                    self.stack.push(value);
+                }
+                OpCode::GetSuper | OpCode::GetSuperLong => {
+                    let name = self.read_name(op);
+                    let superclass = self.pop();
+                    let instance = self.pop();
+
+                    let method = match &superclass {
+                        Value::Class(superclass) => superclass.find_method(&name),
+                        _ => None,
+                    };
+                    match method {
+                        Some(method) => {
+                            let method = method.bind(instance);
+                            interpreter.track_value(&method);
+                            self.stack.push(method);
+                        }
+                        None => {
+                            return Err(self.name_error(format!("Undefined property '{}'.", name)))
+                        }
+                    }
+                }
+                OpCode::Equal => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    self.stack.push(Value::Boolean(left == right));
+                }
+                OpCode::Greater
+                | OpCode::GreaterEqual
+                | OpCode::Less
+                | OpCode::LessEqual
+                | OpCode::Subtract
+                | OpCode::Multiply
+                | OpCode::Divide
+                | OpCode::FloorDivide => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    let operator = match op {
+                        OpCode::Greater => Operator::Greater,
+                        OpCode::GreaterEqual => Operator::GreaterEqual,
+                        OpCode::Less => Operator::Less,
+                        OpCode::LessEqual => Operator::LessEqual,
+                        OpCode::Subtract => Operator::Subtract,
+                        OpCode::Multiply => Operator::Multiply,
+                        OpCode::Divide => Operator::Divide,
+                        _ => Operator::FloorDivide,
+                    };
+                    let rules = interpreter.arithmetic_rules();
+                    match arithmetic::binary(operator, &left, &right, rules) {
+                        // Only repetition builds a new object.
+                        Ok(value @ (Value::String(_) | Value::List(_))) => {
+                            self.allocate_value(interpreter, &value)?;
+                            self.stack.push(value);
+                        }
+                        Ok(value) => self.stack.push(value),
+                        Err(failure) => return Err(self.failure(failure)),
+                    }
+                }
+                OpCode::Add => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    let value = match (left, right) {
+                        (Value::String(left), Value::String(right)) => {
+                            Value::String(concatenate(&left, &right))
+                        }
+                        (Value::List(left), Value::List(right)) => concatenate_lists(&left, &right),
+                        (left, right) => match arithmetic::add(&left, &right) {
+                            Some(Ok(value)) => value,
+                            Some(Err(failure)) => return Err(self.failure(failure)),
+                            None => {
+                                return Err(self.type_error(
+                                    "Operands must be two numbers or two strings.".to_string(),
+                                ))
+                            }
+                        },
+                    };
+
+                    self.allocate_value(interpreter, &value)?;
+                    self.stack.push(value);
+                }
+                OpCode::Not => {
+                    let value = self.pop();
+                    self.stack.push(Value::Boolean(!value.is_truthy()));
+                }
+                OpCode::Negate => match arithmetic::negate(&self.pop()) {
+                    Some(Ok(value)) => self.stack.push(value),
+                    Some(Err(failure)) => return Err(self.failure(failure)),
+                    None => return Err(self.type_error("Operand must be a number.".to_string())),
+                },
+                OpCode::Print => {
+                    let value = self.pop();
+                    let text = interpreter.display(&value).map_err(|mut error| {
+                        error.line = self.line();
+                        error
+                    })?;
+                    if let Err(error) = interpreter.io().write_stdout(&format!("{}\n", text)) {
+                        return Err(self.error(format!("Could not write output: {}.", error)));
+                    }
+                }
+                OpCode::BuildList => {
+                    let count = self.read_u16() as usize;
+                    let elements = self.stack.split_off(self.stack.len() - count);
+                    self.allocate(interpreter, count * mem::size_of::<Value>())?;
+                    let list = Value::from(elements);
+                    interpreter.track_value(&list);
+                    self.stack.push(list);
+                }
+                OpCode::BuildMap => {
+                    let count = self.read_u16() as usize;
+                    let values = self.stack.split_off(self.stack.len() - count * 2);
+                    self.allocate(interpreter, count * mem::size_of::<(String, Value)>())?;
+
+                    let mut entries = BTreeMap::new();
+                    let mut values = values.into_iter();
+                    while let (Some(key), Some(value)) = (values.next(), values.next()) {
+                        let Value::String(key) = key else {
+                            return Err(self.type_error("Map key must be a string.".to_string()));
+                        };
+                        entries.insert(key.to_string(), value);
+                    }
+
+                    let map = Value::from(entries);
+                    interpreter.track_value(&map);
+                    self.stack.push(map);
+                }
+                OpCode::Index => {
+                    let index = self.pop();
+                    let object = self.pop();
+                    let value = interpreter.index(self.line(), &object, &index)?;
+                    self.stack.push(value);
+                }
+                OpCode::ExpectValues => {
+                    let count = self.read_u16() as usize;
+                    interpreter.expect_values(self.line(), self.peek(0), count)?;
+                }
+                OpCode::Unpack => {
+                    let key = self.pop();
+                    let object = self.pop();
+                    let value = interpreter.unpack(self.line(), &object, &key)?;
+                    self.stack.push(value);
+                }
+                OpCode::SetIndex => {
+                    let value = self.pop();
+                    let index = self.pop();
+                    let object = self.pop();
+                    self.allocate(interpreter, mem::size_of::<Value>())?;
+                    interpreter.set_index(self.line(), &object, &index, value.clone())?;
+                    self.stack.push(value);
+                }
+                OpCode::Call => {
+                    let count = self.read_byte() as usize;
+                    self.call_value(interpreter, count)?;
+                }
+                OpCode::Jump => {
+                    let offset = self.read_u16() as usize;
+                    self.frame_mut().ip += offset;
+                }
+                OpCode::JumpIfFalse => {
+                    let offset = self.read_u16() as usize;
+                    if !self.peek(0).is_truthy() {
+                        self.frame_mut().ip += offset;
+                    }
+                }
+                OpCode::Loop => {
+                    let offset = self.read_u16() as usize;
+                    self.frame_mut().ip -= offset;
+                }
+                OpCode::Try => {
+                    let offset = self.read_u16() as usize;
+                    self.handlers.push(Handler {
+                        frame: self.frames.len() - 1,
+                        stack: self.stack.len(),
+                        ip: self.frame().ip + offset,
+                    });
+                }
+                OpCode::EndTry => {
+                    self.handlers.pop();
+                }
+                OpCode::Throw => {
+                    let value = self.pop();
+                    let line = self.line();
+                    return Err(self.call_out(interpreter, |interpreter| {
+                        interpreter.throw_value(value, line)
+                    }));
+                }
+                OpCode::Iterator => {
+                    let iterable = self.pop();
+                    let line = self.line();
+                    let iterator = self.call_out(interpreter, |interpreter| {
+                        interpreter.iterator(line, iterable)
+                    })?;
+                    self.stack.push(iterator);
+                    self.stack.push(Value::Number(0.0));
+                }
+                // Takes the next value from the iterator under the count of
+                // those taken, or leaves the loop once there are none.
+                OpCode::Next => {
+                    let offset = self.read_u16() as usize;
+                    let exit = self.frame().ip + offset;
+                    let index = match self.peek(0) {
+                        Value::Number(index) => *index as usize,
+                        _ => 0,
+                    };
+                    let iterator = self.peek(1).clone();
+                    if let Value::Generator(generator) = iterator {
+                        self.resume(interpreter, generator, exit)?;
+                        continue;
+                    }
+
+                    let line = self.line();
+                    let value = self.call_out(interpreter, |interpreter| {
+                        interpreter.next_value(line, &iterator, index)
+                    })?;
+                    match value {
+                        Some(value) => {
+                            let top = self.stack.len() - 1;
+                            self.stack[top] = Value::Number((index + 1) as f64);
+                            self.stack.push(value);
+                        }
+                        None => self.frame_mut().ip = exit,
+                    }
+                }
+                OpCode::Yield => {
+                    if self.frame().generator.is_none() {
+                        return Err(self.type_error("Can only yield from a generator.".to_string()));
+                    }
+                    let value = self.pop();
+                    self.suspend(interpreter);
+                    self.stack.push(value);
+                }
+                OpCode::Defer => match self.pop() {
+                    Value::Closure(closure)
+                        if closure.prototype.arity.accepts(0)
+                            && !closure.prototype.is_generator =>
+                    {
+                        self.frame_mut().deferred.push(closure);
+                    }
+                    _ => {}
+                },
+                OpCode::Return => {
+                    let value = self.pop();
+                    // A deferred block gets the value in its first slot and
+                    // hands it back to this instruction, which runs again.
+                    if let Some(deferred) = self.frame_mut().deferred.pop() {
+                        self.frame_mut().ip -= 1;
+                        self.stack.push(value);
+                        let line = self.line();
+                        self.call_closure(interpreter, deferred, 0, line)?;
+                        continue;
+                    }
+
+                    let frame = self.frames.pop().expect("call frame");
+                    while self
+                        .handlers
+                        .last()
+                        .is_some_and(|handler| handler.frame >= self.frames.len())
+                    {
+                        self.handlers.pop();
+                    }
+                    if !(self.running_script && self.frames.is_empty()) {
+                        interpreter.profile_leave();
+                    }
+                    self.close_upvalues(frame.slots);
+                    self.stack.truncate(frame.slots);
+
+                    if let Some(pending) = frame.rethrow {
+                        return Err(interpreter.resume_error(pending));
+                    }
+                    if let Some((generator, exit)) = frame.generator {
+                        generator.borrow_mut().finish();
+                        self.frame_mut().ip = exit;
+                        continue;
+                    }
+                    if self.frames.len() == base {
+                        return Ok(value);
+                    }
+
+                    self.stack.push(value);
+                }
+            }
+        }
+    }
+
+    fn call_value(
+        &mut self,
+        interpreter: &mut Interpreter,
+        count: usize,
+    ) -> Result<(), RuntimeError> {
+        let line = self.line();
+        let callee = self.peek(count).clone();
+
+        match callee {
+            Value::Closure(closure) => {
+                if !closure.prototype.arity.accepts(count) {
+                    return Err(self.arity_error(closure.prototype.arity, count));
+                }
+
+                #[cfg(feature = "jit")]
+                if let Some(value) = self.call_compiled(interpreter, &closure, count, line)? {
+                    self.stack.truncate(self.stack.len() - count - 1);
+                    self.stack.push(value);
+                    return Ok(());
+                }
+                self.call_closure(interpreter, closure, count, line)
+            }
+            Value::Native(native) => {
+                if native.arity != count {
+                    return Err(self.arity_error(Arity::exactly(native.arity), count));
+                }
+
+                let arguments = self.stack.split_off(self.stack.len() - count);
+                self.pop();
+
+                interpreter.set_current_line(line);
+                interpreter.profile_enter(|| native.name.clone());
+                let value = self.call_out(interpreter, |interpreter| {
+                    (native.function)(interpreter, &arguments)
+                });
+                interpreter.profile_leave();
+                let value = value?;
+                self.allocate_value(interpreter, &value)?;
+                self.stack.push(value);
+                Ok(())
+            }
+            Value::BoundMethod(bound) => {
+                if !bound.method.prototype.arity.accepts(count) {
+                    return Err(self.arity_error(bound.method.prototype.arity, count));
+                }
+
+                let slot = self.stack.len() - count - 1;
+                self.stack[slot] = bound.receiver.clone();
+                self.call_closure(interpreter, Rc::clone(&bound.method), count, line)
+            }
+            Value::Class(class) => {
+                let initializer = class.find_method("init");
+                let arity = initializer
+                    .as_ref()
+                    .map_or(Arity::exactly(0), Method::arity);
+                if !arity.accepts(count) {
+                    return Err(self.arity_error(arity, count));
+                }
+
+                self.allocate(interpreter, mem::size_of::<Instance>())?;
+                let instance = Rc::new(RefCell::new(Instance::new(class)));
+                interpreter.track(&instance);
+                let instance = Value::Instance(instance);
+
+                match initializer {
+                    Some(Method::Closure(initializer)) => {
+                        let slot = self.stack.len() - count - 1;
+                        self.stack[slot] = instance;
+                        self.call_closure(interpreter, initializer, count, line)
+                    }
+                    Some(method) => {
+                        let arguments = self.stack.split_off(self.stack.len() - count);
+                        self.pop();
+
+                        interpreter.set_current_line(line);
+                        let value = self.call_out(interpreter, |interpreter| {
+                            interpreter.call(method.bind(instance), arguments)
+                        })?;
+                        self.stack.push(value);
+                        Ok(())
+                    }
+                    None => {
+                        self.pop();
+                        self.stack.push(instance);
+                        Ok(())
+                    }
+                }
+            }
+            Value::Function(_) => {
+                let arguments = self.stack.split_off(self.stack.len() - count);
+                self.pop();
+
+                interpreter.set_current_line(line);
+                let value = self.call_out(interpreter, |interpreter| {
+                    interpreter.call(callee, arguments)
+                })?;
+                self.stack.push(value);
+                Ok(())
+            }
+            _ => Err(self.type_error("Can only call functions and classes.".to_string())),
+        }
+    }
+
+    #[cfg(feature = "jit")]
+    fn call_compiled(
+        &self,
+        interpreter: &mut Interpreter,
+        closure: &Closure,
+        count: usize,
+        line: usize,
+    ) -> Result<Option<Value>, RuntimeError> {
+        if interpreter.trace_execution() || interpreter.profiling() {
+            return Ok(None);
+        }
+
+        let arguments: Option<Vec<f64>> = self.stack[self.stack.len() - count..]
+            .iter()
+            .map(|argument| match argument {
+                Value::Number(number) => Some(*number),
+                _ => None,
+            })
+            .collect();
+        let Some(arguments) = arguments else {
+            return Ok(None);
+        };
+
+        let depth = interpreter.call_depth() + self.depth();
+        jit::call(interpreter, &closure.prototype, &arguments, depth, line)
+    }
+
+    // Calls `closure` with the receiver and `count` arguments on top of the
+    // stack, already checked against its arity.
+    fn call_closure(
+        &mut self,
+        interpreter: &mut Interpreter,
+        closure: Rc<Closure>,
+        count: usize,
+        line: usize,
+    ) -> Result<(), RuntimeError> {
+        let slots = self.stack.len() - count - 1;
+        let arity = closure.prototype.arity;
+        let mut ip = 0;
+        if count < arity.named() {
+            ip = closure.prototype.defaults[count - arity.required];
+        } else if arity.variadic {
+            let rest = self.stack.split_off(slots + 1 + arity.named());
+            self.allocate(interpreter, rest.len() * mem::size_of::<Value>())?;
+            let rest = Value::from(rest);
+            interpreter.track_value(&rest);
+            self.stack.push(rest);
+        }
+
+        // A generator keeps the arguments for its first run.
+        if closure.prototype.is_generator {
+            let name = closure.prototype.name.clone();
+            let frame = Suspended {
+                closure,
+                ip,
+                slots: self.stack.split_off(slots),
+                handlers: Vec::new(),
+                upvalues: Vec::new(),
+                deferred: Vec::new(),
+            };
+            let generator = Generator::from_frame(name, frame);
+            self.stack
+                .push(Value::Generator(Rc::new(RefCell::new(generator))));
+            return Ok(());
+        }
+
+        if interpreter.call_depth() + self.depth() >= interpreter.options().max_call_depth {
+            return Err(RuntimeError {
+                kind: RuntimeErrorKind::ResourceExceeded,
+                category: ErrorCategory::General,
+                message: "Stack overflow.".to_string(),
+                line,
+            });
+        }
+
+        interpreter.profile_enter(|| closure.prototype.name.clone());
+        let caches = self.caches.register(&closure.prototype);
+        self.frames.push(CallFrame {
+            closure,
+            ip,
+            slots,
+            caches,
+            deferred: Vec::new(),
+            rethrow: None,
+            generator: None,
+        });
+
+        Ok(())
+    }
+
+    // Runs a generator on from where it last yielded, in a frame above the
+    // loop that asked for a value. Its `yield` hands the loop the value and
+    // its return sends the loop to `exit`.
+    fn resume(
+        &mut self,
+        interpreter: &mut Interpreter,
+        generator: Rc<RefCell<Generator>>,
+        exit: usize,
+    ) -> Result<(), RuntimeError> {
+        if generator.borrow().is_done() {
+            self.frame_mut().ip = exit;
+            return Ok(());
+        }
+        if interpreter.call_depth() + self.depth() >= interpreter.options().max_call_depth {
+            return Err(RuntimeError {
+                kind: RuntimeErrorKind::ResourceExceeded,
+                category: ErrorCategory::General,
+                message: "Stack overflow.".to_string(),
+                line: self.line(),
+            });
+        }
+
+        let frame = generator.borrow_mut().resume_frame();
+        let Suspended {
+            closure,
+            ip,
+            slots: values,
+            handlers,
+            upvalues,
+            deferred,
+        } = frame.map_err(|message| self.error(message.to_string()))?;
+
+        let slots = self.stack.len();
+        self.stack.extend(values);
+        for (offset, upvalue) in upvalues {
+            let open = Upvalue::Open(slots + offset);
+            if let Upvalue::Closed(value) = mem::replace(&mut *upvalue.borrow_mut(), open) {
+                if let Some(slot) = self.stack.get_mut(slots + offset) {
+                    *slot = value;
+                }
+            }
+            self.open_upvalues.push(upvalue);
+        }
+        for (stack, ip) in handlers {
+            self.handlers.push(Handler {
+                frame: self.frames.len(),
+                stack: slots + stack,
+                ip,
+            });
+        }
+
+        interpreter.profile_enter(|| closure.prototype.name.clone());
+        let caches = self.caches.register(&closure.prototype);
+        self.frames.push(CallFrame {
+            closure,
+            ip,
+            slots,
+            caches,
+            deferred,
+            rethrow: None,
+            generator: Some((generator, exit)),
+        });
+
+        Ok(())
+    }
+
+    // Takes the innermost frame, a generator's, off the stack until the
+    // generator is resumed, closing what closures captured from its slots.
+    fn suspend(&mut self, interpreter: &mut Interpreter) {
+        let frame = self.frames.pop().expect("call frame");
+        interpreter.profile_leave();
+
+        let mut handlers = Vec::new();
+        while self
+            .handlers
+            .last()
+            .is_some_and(|handler| handler.frame >= self.frames.len())
+        {
+            let handler = self.handlers.pop().expect("handler");
+            handlers.push((handler.stack.saturating_sub(frame.slots), handler.ip));
+        }
+        handlers.reverse();
+
+        let mut upvalues = Vec::new();
+        let stack = &self.stack;
+        self.open_upvalues.retain(|upvalue| {
+            let slot = match *upvalue.borrow() {
+                Upvalue::Open(slot) if slot >= frame.slots => slot,
+                _ => return true,
+            };
+            let value = stack.get(slot).cloned().unwrap_or(Value::Nil);
+            *upvalue.borrow_mut() = Upvalue::Closed(value);
+            upvalues.push((slot - frame.slots, Rc::clone(upvalue)));
+            false
+        });
+
+        let slots = self.stack.split_off(frame.slots.min(self.stack.len()));
+        if let Some((generator, _)) = frame.generator {
+            generator.borrow_mut().suspend_frame(Suspended {
+                closure: frame.closure,
+                ip: frame.ip,
+                slots,
+                handlers,
+                upvalues,
+                deferred: frame.deferred,
+            });
+        }
+    }
+
+    fn capture_upvalue(
+        &mut self,
+        interpreter: &mut Interpreter,
+        slot: usize,
+    ) -> Rc<RefCell<Upvalue>> {
+        let existing = self
+            .open_upvalues
+            .iter()
+            .find(|upvalue| matches!(*upvalue.borrow(), Upvalue::Open(open) if open == slot));
+        if let Some(upvalue) = existing {
+            return Rc::clone(upvalue);
+        }
+
+        let upvalue = Rc::new(RefCell::new(Upvalue::Open(slot)));
+        interpreter.track(&upvalue);
+        self.open_upvalues.push(Rc::clone(&upvalue));
+        upvalue
+    }
+
+    fn close_upvalues(&mut self, from: usize) {
+        let stack = &self.stack;
+        self.open_upvalues.retain(|upvalue| {
+            let mut upvalue = upvalue.borrow_mut();
+            match *upvalue {
+                Upvalue::Open(slot) if slot >= from => {
+                    *upvalue = Upvalue::Closed(stack.get(slot).cloned().unwrap_or(Value::Nil));
+                    false
+                }
+                _ => true,
+            }
+        });
+    }
+
+    fn call_out<T>(
+        &mut self,
+        interpreter: &mut Interpreter,
+        body: impl FnOnce(&mut Interpreter) -> T,
+    ) -> T {
+        mem::swap(self, interpreter.vm_mut());
+        let result = body(interpreter);
+        mem::swap(self, interpreter.vm_mut());
+        result
+    }
+
+    fn allocate(&self, interpreter: &mut Interpreter, bytes: usize) -> Result<(), RuntimeError> {
+        interpreter.allocate(bytes).map_err(|mut error| {
+            error.line = self.line();
+            error
+        })
+    }
+
+    fn allocate_value(
+        &self,
+        interpreter: &mut Interpreter,
+        value: &Value,
+    ) -> Result<(), RuntimeError> {
+        interpreter.allocate_value(value).map_err(|mut error| {
+            error.line = self.line();
+            error
+        })
+    }
+
+    fn trace(&self, interpreter: &mut Interpreter) {
+        let frame = self.frame();
+        let mut output = String::from("          ");
+        for value in &self.stack {
+            output.push_str(&format!("[ {:?} ]", value));
+        }
+
+        let (instruction, _) = disassemble_instruction(&frame.closure.prototype.chunk, frame.ip);
+        output.push('\n');
+        output.push_str(&instruction);
+        output.push('\n');
+
+        let _ = interpreter.io().write_stderr(&output);
+    }
+
+    fn frame(&self) -> &CallFrame {
+        self.frames.last().expect("call frame")
+    }
+
+    fn frame_mut(&mut self) -> &mut CallFrame {
+        self.frames.last_mut().expect("call frame")
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let frame = self.frame_mut();
+        let byte = frame.closure.prototype.chunk.code()[frame.ip];
+        frame.ip += 1;
+        byte
+    }
+
+    fn read_u16(&mut self) -> u16 {
+        let frame = self.frame_mut();
+        let value = frame.closure.prototype.chunk.read_u16(frame.ip);
+        frame.ip += 2;
+        value
+    }
+
+    fn read_u24(&mut self) -> u32 {
+        let frame = self.frame_mut();
+        let value = frame.closure.prototype.chunk.read_u24(frame.ip);
+        frame.ip += 3;
+        value
+    }
+
+    fn read_slot(&mut self, op: OpCode) -> usize {
+        if op.is_long() {
+            self.read_u16() as usize
+        } else {
+            self.read_byte() as usize
+        }
+    }
+
+    fn read_index(&mut self, op: OpCode) -> usize {
+        if op.is_long() {
+            self.read_u24() as usize
+        } else {
+            self.read_byte() as usize
+        }
+    }
+
+    fn read_constant(&mut self, op: OpCode) -> &Constant {
+        let index = self.read_index(op);
+        self.frame().closure.prototype.chunk.constant(index)
+    }
+
+    fn site(&self) -> (usize, usize) {
+        let frame = self.frame();
+        (frame.caches, frame.ip - 1)
+    }
+
+    fn name(&self, index: usize) -> Symbol {
+        let chunk = &self.frame().closure.prototype.chunk;
+        match chunk.symbol(index) {
+            Some(name) => name.clone(),
+            None => Symbol::from(chunk.constant(index).to_string()),
+        }
+    }
+
+    fn read_name(&mut self, op: OpCode) -> Symbol {
+        let index = self.read_index(op);
+        self.name(index)
+    }
+
+    fn peek(&self, distance: usize) -> &Value {
+        &self.stack[self.stack.len() - 1 - distance]
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().unwrap_or(Value::Nil)
+    }
+
+    fn line(&self) -> usize {
+        self.frames.last().map_or(0, |frame| {
+            frame
+                .closure
+                .prototype
+                .chunk
+                .line(frame.ip.saturating_sub(1))
+        })
+    }
+
+    fn arity_error(&self, arity: Arity, count: usize) -> RuntimeError {
+        self.type_error(arity.error(count))
+    }
+
+    fn failure(&self, failure: arithmetic::Failure) -> RuntimeError {
+        self.error(failure.message.to_string())
+            .with_category(failure.category)
+    }
+
+    fn type_error(&self, message: String) -> RuntimeError {
+        self.error(message).with_category(ErrorCategory::Type)
+    }
+
+    fn name_error(&self, message: String) -> RuntimeError {
+        self.error(message).with_category(ErrorCategory::Name)
+    }
+
+    fn error(&self, message: String) -> RuntimeError {
+        RuntimeError {
+            kind: RuntimeErrorKind::Error,
+            category: ErrorCategory::General,
+            message,
+            line: self.line(),
+        }
+    }
+}
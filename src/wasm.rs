@@ -0,0 +1,45 @@
+use wasm_bindgen::prelude::*;
+
+use crate::capability::Capabilities;
+use crate::error::LoxError;
+use crate::io::CaptureIo;
+use crate::Lox;
+
+#[wasm_bindgen]
+pub struct RunResult {
+    output: String,
+    diagnostics: Vec<String>,
+}
+
+#[wasm_bindgen]
+impl RunResult {
+    #[wasm_bindgen(getter)]
+    pub fn output(&self) -> String {
+        self.output.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn diagnostics(&self) -> Vec<String> {
+        self.diagnostics.clone()
+    }
+}
+
+#[wasm_bindgen]
+pub fn run(source: &str) -> RunResult {
+    let capture = CaptureIo::new();
+    let mut lox = Lox::builder()
+        .with_capabilities(Capabilities::RANDOM)
+        .with_io(Box::new(capture.clone()))
+        .build();
+
+    let diagnostics = match lox.run(source) {
+        Ok(()) => Vec::new(),
+        Err(LoxError::Compile(errors)) => errors.iter().map(ToString::to_string).collect(),
+        Err(LoxError::Runtime(error)) => vec![error.to_string()],
+    };
+
+    RunResult {
+        output: capture.stdout(),
+        diagnostics,
+    }
+}
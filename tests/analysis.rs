@@ -0,0 +1,327 @@
+use lox::analysis;
+use lox::syntax::NodeKind;
+use lox::token::Position;
+
+#[test]
+fn semantic_tokens_classify_names() {
+    let source = "// point\nclass P { init(x) { this.x = x; } }\nfun f(n) { return P(n).x; }\nprint f(1) + clock();\n";
+    let characters: Vec<char> = source.chars().collect();
+    let tokens: Vec<(String, &str)> = analysis::semantic_tokens(source)
+        .iter()
+        .map(|token| {
+            let text = characters[token.start..token.start + token.length]
+                .iter()
+                .collect();
+            (text, token.token_type.name())
+        })
+        .collect();
+    let tokens: Vec<(&str, &str)> = tokens
+        .iter()
+        .map(|(text, name)| (text.as_str(), *name))
+        .collect();
+
+    assert_eq!(
+        tokens,
+        vec![
+            ("// point", "comment"),
+            ("class", "keyword"),
+            ("P", "class"),
+            ("init", "method"),
+            ("x", "parameter"),
+            ("this", "keyword"),
+            ("x", "property"),
+            ("x", "parameter"),
+            ("fun", "keyword"),
+            ("f", "function"),
+            ("n", "parameter"),
+            ("return", "keyword"),
+            ("P", "class"),
+            ("n", "parameter"),
+            ("x", "property"),
+            ("print", "keyword"),
+            ("f", "function"),
+            ("1", "number"),
+            ("clock", "function"),
+        ]
+    );
+}
+
+#[test]
+fn analysis_finds_definitions_and_references() {
+    let source = "var x = 1;\n{\n  var x = 2;\n  fun even(n) { if (n == 0) return true; return odd(n - 1); }\n  fun odd(n) { if (n == 0) return false; return even(n - 1); }\n  print x + 1;\n}\nprint x;\n";
+    let analysis = analysis::Analysis::new(source);
+    let offset_of = |needle: &str, nth: usize| {
+        let byte = source.match_indices(needle).nth(nth).unwrap().0;
+        source[..byte].chars().count()
+    };
+    let lines = |positions: Vec<lox::token::Position>| -> Vec<usize> {
+        positions.iter().map(|position| position.line).collect()
+    };
+
+    let inner = analysis.definition_at(offset_of("x + 1", 0)).unwrap();
+    assert_eq!(inner.line, 3);
+    let outer = analysis
+        .definition_at(offset_of("print x;", 0) + 6)
+        .unwrap();
+    assert_eq!(outer.line, 1);
+
+    let odd = analysis.definition_at(offset_of("odd(n - 1)", 0)).unwrap();
+    assert_eq!((odd.line, odd.start), (5, offset_of("odd(n) {", 0)));
+    assert_eq!(lines(analysis.references_at(odd.start, true)), vec![4, 5]);
+    assert_eq!(
+        lines(analysis.references_at(offset_of("var x = 2", 0) + 4, false)),
+        vec![6]
+    );
+    assert!(analysis.definition_at(offset_of("print", 0)).is_none());
+}
+
+#[test]
+fn analysis_hovers_with_arity_and_documentation() {
+    let source = "/// Adds two numbers.\n///\n///   Exactly.\nfun add(a, b) { return a + b; }\n\n// Not documentation.\nvar total = add(1, 2);\n//// Nor this.\nclass Point {\n  /// Makes a point.\n  init(x, y) {}\n}\nclass Origin < Point {}\n/// Too far.\n\nfun f() {}\n";
+    let analysis = analysis::Analysis::new(source);
+    let hover = |needle: &str| analysis.hover(source.find(needle).unwrap()).unwrap();
+
+    assert_eq!(
+        hover("add(1"),
+        "```lox\nfun add(a, b)\n```\n\nTakes 2 arguments.\n\nAdds two numbers.\n\n  Exactly."
+    );
+    assert_eq!(hover("total"), "```lox\nvar total\n```");
+    assert_eq!(
+        hover("Point {"),
+        "```lox\nclass Point\n```\n\nTakes 2 arguments."
+    );
+    assert_eq!(
+        hover("init"),
+        "```lox\nPoint.init(x, y)\n```\n\nTakes 2 arguments.\n\nMakes a point."
+    );
+    assert_eq!(hover("Origin"), "```lox\nclass Origin < Point\n```");
+    assert_eq!(hover("f()"), "```lox\nfun f()\n```\n\nTakes no arguments.");
+    assert_eq!(
+        hover("b) {"),
+        "```lox\nparameter b\n```\n\nParameter of `add`."
+    );
+    assert!(analysis.hover(source.find("return").unwrap()).is_none());
+}
+
+#[test]
+fn analysis_finds_nodes_at_offsets() {
+    let source = "var a = 1;\nfun f(x) {\n  return g(x * (a + 2));\n}\nprint f(3) ;\n";
+    let analysis = analysis::Analysis::new(source);
+    let kinds = |offset: usize| -> Vec<NodeKind> {
+        analysis
+            .node_at(offset)
+            .iter()
+            .map(|node| node.kind())
+            .collect()
+    };
+
+    let a = source.find("a + 2").unwrap();
+    assert_eq!(
+        kinds(a),
+        [
+            NodeKind::Variable,
+            NodeKind::Binary,
+            NodeKind::Grouping,
+            NodeKind::Binary,
+            NodeKind::ArgumentList,
+            NodeKind::Call,
+            NodeKind::ReturnStatement,
+            NodeKind::Block,
+            NodeKind::FunctionDeclaration,
+            NodeKind::Program,
+        ]
+    );
+    let nodes = analysis.node_at(a);
+    assert_eq!(nodes[1].text(), "a + 2");
+    assert_eq!(nodes[3].range(), source.find("x * ").unwrap()..a + 6);
+
+    // Trivia inside a node belongs to it; between statements, to what
+    // holds them.
+    assert_eq!(
+        kinds(source.find(" ;").unwrap())[0],
+        NodeKind::PrintStatement
+    );
+    assert_eq!(
+        kinds(source.find("\n  return").unwrap()),
+        [
+            NodeKind::Block,
+            NodeKind::FunctionDeclaration,
+            NodeKind::Program,
+        ]
+    );
+    assert!(kinds(source.find("\nfun").unwrap()).is_empty());
+    assert_eq!(
+        kinds(source.find("(x)").unwrap())[0],
+        NodeKind::FunctionDeclaration
+    );
+
+    // A source that doesn't parse still has nodes.
+    let broken = analysis::Analysis::new("print (1 + ;\nvar b = c.d;");
+    assert_eq!(
+        broken
+            .node_at(22)
+            .iter()
+            .map(|node| node.kind())
+            .collect::<Vec<_>>(),
+        [NodeKind::Get, NodeKind::VarDeclaration, NodeKind::Program]
+    );
+}
+
+#[test]
+fn analysis_completes_names_members_and_keywords() {
+    // The cursor is at the '|'.
+    let complete = |source: &str| -> Vec<String> {
+        let offset = source.find('|').unwrap();
+        analysis::Analysis::new(&source.replace('|', ""))
+            .completions(offset)
+            .into_iter()
+            .map(|completion| completion.label)
+            .collect()
+    };
+
+    let source = "var total = 1;\nfun f(count) {\n  var local = 2;\n  |\n}\nvar later;\n";
+    let labels = complete(source);
+    for expected in [
+        "count", "local", "total", "f", "later", "clock", "return", "while", "nil",
+    ] {
+        assert!(
+            labels.contains(&expected.to_string()),
+            "{} in {:?}",
+            expected,
+            labels
+        );
+    }
+    assert!(!labels.contains(&"this".to_string()));
+    assert_eq!(complete(&source.replace("  |", "  to|")), ["total"]);
+    assert!(!complete("var total = 1;\n|").contains(&"return".to_string()));
+    assert_eq!(complete("{ var inner = 1; }\ninn|"), Vec::<String>::new());
+    assert_eq!(complete("var value = val|"), Vec::<String>::new());
+
+    let classes = "class Shape { area() { return 0; } }\nclass Square < Shape {\n  init(side) { this.side = side; }\n  area() { return this.|; }\n  scale() { return super.|; }\n}\n";
+    let first = classes.find('|').unwrap();
+    let second = classes.rfind('|').unwrap();
+    let this = format!("{}{}", &classes[..second], &classes[second + 1..]);
+    assert_eq!(complete(&this), ["area", "init", "scale", "side"]);
+    let super_ = format!("{}{}", &classes[..first], &classes[first + 1..]);
+    assert_eq!(complete(&super_), ["area"]);
+
+    let instance =
+        "class Point { init(x) { this.x = x; } norm() {} }\nvar p = Point(1);\nprint p.n|";
+    assert_eq!(complete(instance), ["norm"]);
+    assert_eq!(complete("fun f(a) { print a.| }"), Vec::<String>::new());
+    assert_eq!(complete("class A {}\nvar B = 1;\nclass C < |"), ["A"]);
+
+    assert_eq!(complete("var a = 1; print a |"), ["and", "or"]);
+    assert!(complete("if (true) print 1; |").contains(&"else".to_string()));
+    assert!(!complete("if (true) print 1; else print 2; |").contains(&"else".to_string()));
+    let loop_ = "for (var i = 0; i < 3; i = i + 1) print i|";
+    assert!(complete(loop_).contains(&"i".to_string()));
+    assert!(!complete(&loop_.replace("i|", "1;\nprint i|")).contains(&"i".to_string()));
+    assert!(complete("class A {\n  m() { print t|").contains(&"this".to_string()));
+    assert_eq!(complete("// var|"), Vec::<String>::new());
+    assert_eq!(complete("fun |"), Vec::<String>::new());
+}
+
+#[test]
+fn analysis_renames_scope_correctly() {
+    let source = "var total = 0;\nfun add(n) {\n  var step = n;\n  total = total + step;\n}\n{\n  var total = 5;\n  print total;\n}\nclass Counter { bump() { add(1); } }\nprint clock();\n";
+    let analysis = analysis::Analysis::new(source);
+    let rename = |offset: usize, name: &str| {
+        analysis.rename(offset, name).map(|edits| {
+            let characters: Vec<char> = source.chars().collect();
+            let mut renamed = String::new();
+            let mut from = 0;
+            for edit in edits {
+                renamed.extend(&characters[from..edit.position.start]);
+                renamed.push_str(&edit.text);
+                from = edit.position.current;
+            }
+            renamed.extend(&characters[from..]);
+            renamed
+        })
+    };
+    let offset_of = |needle: &str| source.find(needle).unwrap();
+
+    assert_eq!(
+        rename(offset_of("total = 0"), "sum").unwrap(),
+        source
+            .replacen("total", "sum", 1)
+            .replacen("total = total", "sum = sum", 1)
+    );
+    assert_eq!(
+        rename(offset_of("n) {"), "amount").unwrap(),
+        source
+            .replace("add(n)", "add(amount)")
+            .replace("step = n;", "step = amount;")
+    );
+    assert!(rename(offset_of("step = n"), "n")
+        .unwrap_err()
+        .contains("already declared"));
+    assert!(rename(offset_of("step = n"), "total")
+        .unwrap_err()
+        .contains("refer to"));
+    assert!(rename(offset_of("add(n)"), "clock")
+        .unwrap_err()
+        .contains("refer to"));
+    assert!(rename(offset_of("bump"), "grow")
+        .unwrap_err()
+        .contains("Methods"));
+    assert!(rename(offset_of("add(n)"), "while")
+        .unwrap_err()
+        .contains("not a valid name"));
+    assert!(rename(offset_of("print"), "x").is_err());
+}
+
+#[test]
+fn analysis_outlines_declarations() {
+    let source = "var limit = 3;\nclass Point < Base {\n  init(x) { this.x = x; }\n  norm() { return this.x; }\n}\nfun scale(p, by) {\n  var factor = by;\n  fun helper() { return factor; }\n  return helper();\n}\n{ var hidden = 1; }\n";
+    let characters: Vec<char> = source.chars().collect();
+    let text = |position: &Position| -> String {
+        characters[position.start..position.current]
+            .iter()
+            .collect()
+    };
+    let summary = |item: &analysis::OutlineItem| {
+        (
+            item.name.clone(),
+            item.kind,
+            text(&item.position),
+            text(&item.span),
+        )
+    };
+
+    let outline = analysis::Analysis::new(source).outline();
+    let top: Vec<_> = outline.iter().map(summary).collect();
+    assert_eq!(
+        top,
+        vec![
+            (
+                "limit".to_string(),
+                analysis::SymbolKind::Variable,
+                "limit".to_string(),
+                "var limit = 3;".to_string()
+            ),
+            (
+                "Point".to_string(),
+                analysis::SymbolKind::Class,
+                "Point".to_string(),
+                source[15..source.find("}\nfun").unwrap() + 1].to_string()
+            ),
+            (
+                "scale".to_string(),
+                analysis::SymbolKind::Function,
+                "scale".to_string(),
+                source[source.find("fun scale").unwrap()..source.find("\n{").unwrap()].to_string()
+            ),
+        ]
+    );
+
+    let methods: Vec<_> = outline[1].children.iter().map(summary).collect();
+    assert_eq!(methods[0].3, "init(x) { this.x = x; }");
+    assert_eq!(methods[1].0, "norm");
+    assert_eq!(methods[1].1, analysis::SymbolKind::Method);
+
+    let nested: Vec<_> = outline[2].children.iter().map(summary).collect();
+    assert_eq!(nested.len(), 1);
+    assert_eq!(nested[0].3, "fun helper() { return factor; }");
+}
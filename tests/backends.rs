@@ -0,0 +1,150 @@
+mod common;
+
+use std::rc::Rc;
+
+use lox::bench;
+use lox::compiler;
+use lox::optimizer;
+use lox::parser::Parser;
+use lox::scanner::Scanner;
+use lox::Engine;
+
+use common::{compile, run_engine, run_script, run_vm, run_walker, CASES};
+
+#[test]
+fn backends_agree() {
+    for (name, source, expected) in CASES {
+        let walker = run_walker(source, false);
+        let vm = run_vm(source, false);
+        let optimized = run_script(Rc::new(optimizer::optimize(&compile(source))), false);
+
+        assert_eq!(walker.0, *expected, "walker output for '{}'", name);
+        assert_eq!(vm, walker, "vm and walker disagree on '{}'", name);
+        assert_eq!(
+            optimized, walker,
+            "optimized vm and walker disagree on '{}'",
+            name
+        );
+    }
+}
+
+#[test]
+fn backends_agree_under_gc_stress() {
+    for (name, source, _) in CASES {
+        let expected = run_walker(source, false);
+
+        assert_eq!(
+            run_walker(source, true),
+            expected,
+            "walker under gc stress on '{}'",
+            name
+        );
+        assert_eq!(
+            run_vm(source, true),
+            expected,
+            "vm under gc stress on '{}'",
+            name
+        );
+    }
+}
+
+#[test]
+fn engines_agree() {
+    for (name, source, _) in CASES {
+        assert_eq!(
+            run_engine(source, Engine::Vm),
+            run_engine(source, Engine::Walker),
+            "engines disagree on '{}'",
+            name
+        );
+    }
+}
+
+#[test]
+fn bench_workloads_agree() {
+    for workload in bench::WORKLOADS {
+        let (output, error) = run_engine(workload.source, Engine::Walker);
+        assert_eq!(error, None, "workload '{}' failed", workload.name);
+
+        for &engine in bench::ENGINES {
+            assert_eq!(
+                run_engine(workload.source, engine),
+                (output.clone(), None),
+                "{} disagrees on workload '{}'",
+                engine.name(),
+                workload.name
+            );
+        }
+    }
+}
+
+#[cfg(feature = "regvm")]
+#[test]
+fn register_engine_agrees() {
+    for (name, source, _) in CASES {
+        let register = run_engine(source, Engine::Register);
+        let unsupported = register
+            .1
+            .as_ref()
+            .is_some_and(|error| error.contains("not supported by the register compiler"));
+        if unsupported {
+            continue;
+        }
+
+        assert_eq!(
+            register,
+            run_engine(source, Engine::Walker),
+            "register engine and walker disagree on '{}'",
+            name
+        );
+    }
+}
+
+#[cfg(feature = "jit")]
+#[test]
+fn jit_agrees() {
+    use common::capturing;
+
+    for (name, source, _) in CASES {
+        let (mut lox, capture) = capturing(Engine::Vm);
+        lox.interpreter().enable_jit(1).unwrap();
+
+        let error = lox.run(source).err().map(|error| error.to_string());
+        assert_eq!(
+            (capture.stdout(), error),
+            run_engine(source, Engine::Walker),
+            "jit and walker disagree on '{}'",
+            name
+        );
+    }
+
+    let (mut lox, _) = capturing(Engine::Vm);
+    lox.interpreter().enable_jit(1).unwrap();
+    lox.run("fun fib(n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2); }\nprint fib(15);")
+        .unwrap();
+    assert_eq!(lox.interpreter().jit().unwrap().compiled_functions(), 1);
+}
+
+#[test]
+fn runtime_errors_report_lines() {
+    let (_, error) = run_vm("print 1;\nprint missing;", false);
+    assert_eq!(
+        error.as_deref(),
+        Some("Undefined variable 'missing'.\n[line 2]")
+    );
+
+    let (_, error) = run_vm("fun inner() {\n  return nil + 1;\n}\ninner();", false);
+    assert_eq!(
+        error.as_deref(),
+        Some("Operands must be two numbers or two strings.\n[line 2]")
+    );
+}
+
+#[test]
+fn top_level_return_is_a_compile_error() {
+    let tokens = Scanner::new("return 1;".to_string()).scan_tokens().unwrap();
+    let statements = Parser::new(tokens).parse().unwrap();
+    let errors = compiler::compile(&statements).unwrap_err();
+
+    assert_eq!(errors[0].message, "Can't return from top-level code.");
+}
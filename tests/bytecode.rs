@@ -0,0 +1,137 @@
+mod common;
+
+use std::rc::Rc;
+
+use lox::bytecode::OpCode;
+use lox::dialect::Dialect;
+use lox::disassembler;
+use lox::interpreter::InterpreterOptions;
+use lox::optimizer;
+use lox::serialize;
+use lox::Engine;
+
+use common::{capturing_with, compile, run_vm, run_walker, CASES};
+
+#[test]
+fn long_operands() {
+    let mut source = String::from("fun wide() {\n");
+    for index in 0..300 {
+        source.push_str(&format!("  var local{} = {};\n", index, index));
+    }
+    source.push_str(
+        "  fun last() { return local299; }\n  print local0 + local299;\n  return last;\n}\n",
+    );
+    for index in 0..300 {
+        source.push_str(&format!("var global{} = \"value {}\";\n", index, index));
+    }
+    source.push_str("print wide()(); print global299;\n");
+
+    let walker = run_walker(&source, false);
+    assert_eq!(walker.0, "299\n299\nvalue 299\n");
+    assert_eq!(run_vm(&source, false), walker);
+
+    let listing = disassembler::disassemble(&compile(&source));
+    assert!(listing.contains("ConstantLong"));
+    assert!(listing.contains("GetLocalLong"));
+    assert!(listing.contains("DefineGlobalLong"));
+}
+
+#[test]
+fn optimizer_folds_and_prunes() {
+    let script = optimizer::optimize(&compile(
+        "print 1 + 2 * 3; print !(1 < 2); fun f() { return 1; print \"dead\"; }",
+    ));
+    let listing = disassembler::disassemble(&script);
+
+    assert!(listing.contains("'7'"));
+    assert!(!listing.contains("Multiply"));
+    assert!(!listing.contains("Less"));
+    assert!(!listing.contains("dead"));
+}
+
+#[test]
+fn compiled_scripts_round_trip() {
+    for (name, source, _) in CASES {
+        let script = compile(source);
+
+        let bytes = serialize::serialize(&script);
+        let loaded = serialize::deserialize(&bytes).unwrap();
+        assert_eq!(loaded, *script, "round trip of '{}'", name);
+
+        assert!(serialize::deserialize(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    assert_eq!(
+        serialize::deserialize(b"LOXC\x02\x00").unwrap_err(),
+        "Unsupported bytecode version 2 (expected 3)."
+    );
+}
+
+#[test]
+fn damaged_scripts_fail_to_load_or_run_safely() {
+    let source = "class A {\n  init(x) { this.x = x; }\n  get() { return this.x; }\n}\nclass B < A {\n  get() { return super.get() + 1; }\n}\nfun outer(a, b = 2, ...rest) {\n  var total = a + b;\n  fun add() { total = total + len(rest); return total; }\n  defer { print \"deferred\"; }\n  try { throw \"boom\"; } catch (e) { print e; }\n  var [p, q] = [1, 2];\n  print p + q + B(5).get();\n  return add();\n}\nprint outer(1, 3, 4);\nvar i = 0;\nwhile (i < 3) i = i + 1;\nprint {\"i\": i};\nfun pairs(n) {\n  var k = 0;\n  fun bump() { k = k + 1; }\n  try { while (k < n) { yield k; bump(); } } catch (e) { print e; }\n}\nfor (var v in pairs(2)) for (var w in [v, v]) print w;";
+    let options = InterpreterOptions {
+        max_steps: Some(10_000),
+        ..InterpreterOptions::default()
+    };
+    let run = |script| {
+        let (mut lox, capture) = capturing_with(Engine::Vm, Dialect::default(), options);
+        let _ = lox.run_script(Rc::new(script));
+        capture.stdout()
+    };
+
+    for script in [
+        compile(source),
+        Rc::new(optimizer::optimize(&compile(source))),
+    ] {
+        let bytes = serialize::serialize(&script);
+        assert_eq!(
+            run(serialize::deserialize(&bytes).unwrap()),
+            "boom\n9\ndeferred\n5\n{\"i\": 3}\n0\n0\n1\n1\n"
+        );
+
+        for length in 0..bytes.len() {
+            assert!(serialize::deserialize(&bytes[..length]).is_err());
+        }
+        for position in 0..bytes.len() {
+            for flip in [0x01, 0x02, 0x10, 0x80, 0xff] {
+                let mut damaged = bytes.clone();
+                damaged[position] ^= flip;
+                if let Ok(script) = serialize::deserialize(&damaged) {
+                    run(script);
+                }
+            }
+        }
+    }
+
+    let script = compile("print 1;");
+    let bytes = serialize::serialize(&script);
+    let code = script.chunk.code();
+    let start = bytes
+        .windows(code.len())
+        .position(|window| window == code)
+        .unwrap();
+    let jump = OpCode::Jump as u8;
+    for (patch, error) in [
+        (
+            &[OpCode::GetLocal as u8, 5][..],
+            "Local slot 5 out of range at offset 0 in 'script'.",
+        ),
+        (
+            &[OpCode::Constant as u8, 9],
+            "Constant 9 out of range at offset 0 in 'script'.",
+        ),
+        (
+            &[jump, 0, 40],
+            "Jump by 40 leaves the code at offset 0 in 'script'.",
+        ),
+        (
+            &[OpCode::EndTry as u8],
+            "End of a try block outside one at offset 0 in 'script'.",
+        ),
+    ] {
+        let mut damaged = bytes.clone();
+        damaged[start..start + patch.len()].copy_from_slice(patch);
+        assert_eq!(serialize::deserialize(&damaged).unwrap_err(), error);
+    }
+}
@@ -0,0 +1,39 @@
+use lox::check;
+
+#[test]
+fn checking_a_directory_reports_files_in_order() {
+    let root = std::env::temp_dir().join(format!("lox-check-{}", std::process::id()));
+    std::fs::create_dir_all(root.join("nested")).unwrap();
+    let mut expected = Vec::new();
+    for index in 0..20 {
+        let source = match index % 3 {
+            0 => "print 1;".to_string(),
+            1 => format!("var a{} = ;", index),
+            _ => "return 1;\nprint 2;\nreturn 3;".to_string(),
+        };
+        let path = root.join("nested").join(format!("file{:02}.lox", index));
+        std::fs::write(&path, &source).unwrap();
+        expected.push((path, check::check_source(&source).len()));
+    }
+    std::fs::write(root.join("nested").join("notes.txt"), "var = ;").unwrap();
+    let missing = root.join("missing.lox");
+
+    let checked = check::check(&[root.clone(), missing.clone()]);
+    std::fs::remove_dir_all(&root).unwrap();
+
+    assert_eq!(checked.len(), 21);
+    for (checked, (path, errors)) in checked.iter().zip(&expected) {
+        assert_eq!(&checked.path, path);
+        assert_eq!(checked.result.as_ref().unwrap().len(), *errors);
+    }
+    assert_eq!(
+        expected.iter().filter(|(_, errors)| *errors == 0).count(),
+        7
+    );
+    assert_eq!(
+        expected.iter().filter(|(_, errors)| *errors == 2).count(),
+        6
+    );
+    assert_eq!(checked[20].path, missing);
+    assert!(checked[20].result.is_err());
+}
@@ -0,0 +1,87 @@
+use std::process::Command;
+
+fn lox(arguments: &[&str]) -> (String, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_lox"))
+        .args(arguments)
+        .env("LOX_CLI_TEST", "set")
+        .output()
+        .unwrap();
+    (
+        String::from_utf8(output.stdout).unwrap(),
+        String::from_utf8(output.stderr).unwrap(),
+    )
+}
+
+#[test]
+fn allow_exec_grants_the_process_capability() {
+    let dir = std::env::temp_dir().join(format!("lox-cli-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let script = dir.join("getenv.lox");
+    std::fs::write(&script, "print getenv(\"LOX_CLI_TEST\");\n").unwrap();
+    let script = script.to_str().unwrap();
+
+    let (_, stderr) = lox(&[script]);
+    assert!(stderr.contains("getenv"), "{}", stderr);
+    for flag in ["--allow-exec", "--allow=process"] {
+        assert_eq!(lox(&[flag, script]), ("set\n".to_string(), String::new()));
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+fn exit_code(arguments: &[&str]) -> Option<i32> {
+    Command::new(env!("CARGO_BIN_EXE_lox"))
+        .args(arguments)
+        .output()
+        .unwrap()
+        .status
+        .code()
+}
+
+#[test]
+fn flags_apply_to_every_way_of_running_a_file() {
+    let dir = std::env::temp_dir().join(format!("lox-cli-flags-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let source = dir.join("double.lox");
+    std::fs::write(
+        &source,
+        "fun double(n) { return n * 2; }\nprint double(21);\n",
+    )
+    .unwrap();
+    let source = source.to_str().unwrap();
+    let compiled = dir.join("double.loxc");
+    let compiled = compiled.to_str().unwrap();
+    assert_eq!(exit_code(&["compile", "-o", compiled, source]), Some(0));
+
+    for path in [source, compiled] {
+        for run in [&[][..], &["run"][..]] {
+            let with = |flag: &str| {
+                let mut arguments = vec![flag];
+                arguments.extend_from_slice(run);
+                arguments.push(path);
+                lox(&arguments)
+            };
+            let (stdout, stderr) = with("--trace-execution");
+            assert_eq!(stdout, "42\n", "{:?} {}", run, path);
+            assert!(stderr.contains("Multiply"), "{:?} {}", run, path);
+            let (stdout, _) = with("--dump-bytecode");
+            assert!(stdout.starts_with("== script =="), "{:?} {}", run, path);
+            let (stdout, stderr) = with("--profile");
+            assert_eq!(stdout, "42\n", "{:?} {}", run, path);
+            assert!(stderr.contains("double"), "{:?} {}", run, path);
+        }
+    }
+    let (_, stderr) = lox(&["--coverage=text", source]);
+    assert!(stderr.contains("Lines executed"), "{}", stderr);
+
+    for arguments in [
+        &["--coverage=text", compiled][..],
+        &["--dump-bytecode=diff", "run", compiled][..],
+        &["--dump-bytecode", "--profile", source][..],
+        &["--trace-execution", "--coverage=text", "run", source][..],
+    ] {
+        assert_eq!(exit_code(arguments), Some(64), "{:?}", arguments);
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,405 @@
+// Helpers shared by the integration tests; each test file uses some of them.
+#![allow(dead_code)]
+
+use std::cell::RefCell;
+use std::io::{ErrorKind, Write};
+use std::process::{Command, Stdio};
+use std::rc::Rc;
+
+use lox::bytecode::Prototype;
+use lox::compiler;
+use lox::dialect::Dialect;
+use lox::error::LoxError;
+use lox::interpreter::InterpreterOptions;
+use lox::io::CaptureIo;
+use lox::parser::Parser;
+use lox::scanner::Scanner;
+use lox::vm::Vm;
+use lox::{Engine, Lox};
+
+pub const CASES: &[(&str, &str, &str)] = &[
+    (
+        "arithmetic",
+        "print 1 + 2 * 3; print (1 + 2) * 3; print 10 / 4; print -(3 - 5); print 1 / 0;",
+        "7\n9\n2.5\n2\nInfinity\n",
+    ),
+    (
+        "comparison",
+        "print 1 < 2; print 2 <= 2; print 3 > 4; print 4 >= 5; print 1 == 1; print \"a\" != \"a\";",
+        "true\ntrue\nfalse\nfalse\ntrue\nfalse\n",
+    ),
+    (
+        "not a number",
+        "var nan = 0 / 0; print nan == nan; print nan != nan; print nan <= nan;",
+        "false\ntrue\nfalse\n",
+    ),
+    (
+        "truthiness",
+        "print !nil; print !0; print !\"\"; print nil or \"default\"; print 1 and 2; print false and 1;",
+        "true\nfalse\nfalse\ndefault\n2\nfalse\n",
+    ),
+    (
+        "strings",
+        "var greeting = \"hello\"; print greeting + \", \" + \"world\"; print len(greeting);",
+        "hello, world\n5\n",
+    ),
+    (
+        "globals",
+        "var a = 1; var b; print b; a = a + 1; print a; var a = \"again\"; print a;",
+        "nil\n2\nagain\n",
+    ),
+    (
+        "locals",
+        "var a = \"global\"; { var a = \"outer\"; { var a = a + \" inner\"; print a; } print a; } print a;",
+        "outer inner\nouter\nglobal\n",
+    ),
+    (
+        "nested locals",
+        "{ var a = 1; { var b = 2; { fun f() { return a + b; } print f(); } } var n = 0; fun bump() { n = n + 1; } bump(); bump(); print n; }",
+        "3\n2\n",
+    ),
+    (
+        "globals beside a later local",
+        "var total = 0; fun add(n) { var step = n; total = total + step; } { var total = 5; print total; } add(3); print total;",
+        "5\n3\n",
+    ),
+    (
+        "assignment",
+        "var a; var b; a = b = 3; print a + b; { var c = 1; c = c + a; print c; }",
+        "6\n4\n",
+    ),
+    (
+        "control flow",
+        "for (var i = 0; i < 3; i = i + 1) { if (i == 1) print \"one\"; else print i; }",
+        "0\none\n2\n",
+    ),
+    (
+        "while",
+        "var total = 0; var i = 0; while (i < 100) { total = total + i; i = i + 1; } print total;",
+        "4950\n",
+    ),
+    (
+        "functions",
+        "fun fib(n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2); } print fib(15); print fib;",
+        "610\n<fn fib>\n",
+    ),
+    (
+        "numeric functions",
+        "fun sum(n) { var total = 0; for (var i = 0; i < n; i = i + 1) total = total + i; return total; }\nfun odd(n) { return !(n == 0) and n != 2; }\nfun depth(n) { if (n <= 0) return nil == nil; return depth(n - 1) and true; }\nprint sum(10); print -sum(4); print odd(3); print odd(0); print depth(20); print sum(true);",
+        "45\n-6\ntrue\nfalse\ntrue\n",
+    ),
+    (
+        "implicit return",
+        "fun noop() {} print noop(); fun early(x) { if (x) return \"yes\"; print \"no\"; } print early(true); early(false);",
+        "nil\nyes\nno\n",
+    ),
+    (
+        "nested calls",
+        "fun add(a, b) { return a + b; } fun twice(x) { var y = add(x, x); return y; } print twice(add(1, 2));",
+        "6\n",
+    ),
+    (
+        "natives",
+        "print sqrt(16); print max(3, 7); print upper(\"abc\"); print type(nil);",
+        "4\n7\nABC\nnil\n",
+    ),
+    (
+        "callbacks",
+        "fun double(x) { return x * 2; } print map(double, [1, 2, 3]); fun sum(a, b) { return a + b; } print reduce(sum, 0, [1, 2, 3]);",
+        "[2, 4, 6]\n6\n",
+    ),
+    (
+        "lists and maps",
+        "var list = [1, \"two\", nil]; list[0] = 10; print list; print list[1]; var map = {\"a\": 1}; map[\"b\"] = 2; print map; print map[\"c\"];",
+        "[10, \"two\", nil]\ntwo\n{\"a\": 1, \"b\": 2}\nnil\n",
+    ),
+    (
+        "shared counter",
+        "fun makeCounter() { var count = 0; fun increment() { count = count + 1; return count; } fun get() { return count; } return [increment, get]; } var counter = makeCounter(); counter[0](); counter[0](); print counter[1](); print makeCounter()[1]();",
+        "2\n0\n",
+    ),
+    (
+        "loop capture",
+        "var fns = []; for (var i = 0; i < 3; i = i + 1) { var j = i; fun show() { print j; } push(fns, show); } for (var k = 0; k < 3; k = k + 1) fns[k]();",
+        "0\n1\n2\n",
+    ),
+    (
+        "shared loop variable",
+        "var fns = []; for (var i = 0; i < 3; i = i + 1) { fun show() { print i; } push(fns, show); } fns[0](); fns[2]();",
+        "3\n3\n",
+    ),
+    (
+        "nested upvalues",
+        "fun outer() { var x = \"outer\"; fun middle() { fun inner() { x = x + \"!\"; return x; } return inner; } return middle(); } var inner = outer(); inner(); print inner();",
+        "outer!!\n",
+    ),
+    (
+        "local recursion",
+        "{ fun countdown(n) { if (n == 0) return \"done\"; return countdown(n - 1); } print countdown(5); }",
+        "done\n",
+    ),
+    (
+        "upvalue through native callback",
+        "fun outer() { var total = 0; fun add(x) { total = total + x; return x; } map(add, [1, 2, 3]); return total; } print outer();",
+        "6\n",
+    ),
+    (
+        "closed block local",
+        "var get; { var hidden = \"kept\"; fun reveal() { return hidden; } get = reveal; } print get();",
+        "kept\n",
+    ),
+    (
+        "classes and fields",
+        "class Point {} var p = Point(); p.x = 1; p.y = p.x + 1; print p.y; print p; print Point;",
+        "2\nPoint instance\nPoint\n",
+    ),
+    (
+        "methods",
+        "class Greeter { greet(name) { return \"hi \" + name + \" from \" + this.who; } } var g = Greeter(); g.who = \"lox\"; print g.greet(\"you\"); var bound = g.greet; g.who = \"later\"; print bound(\"me\"); print bound;",
+        "hi you from lox\nhi me from later\n<fn greet>\n",
+    ),
+    (
+        "initializers",
+        "class Pair { init(a, b) { this.a = a; this.b = b; if (a == nil) return; this.sum = a + b; } } var p = Pair(1, 2); print p.sum; print p.init(3, 4).sum; print Pair(nil, nil).a;",
+        "3\n7\nnil\n",
+    ),
+    (
+        "inheritance",
+        "class A { name() { return \"A\"; } describe() { return \"I am \" + this.name(); } } class B < A { name() { return \"B\" + super.name(); } } class C < B { describe() { var method = super.describe; return method() + \"!\"; } } print B().describe(); print C().describe();",
+        "I am BA\nI am BA!\n",
+    ),
+    (
+        "inherited initializer",
+        "class Base { init(x) { this.x = x; } } class Derived < Base { init(x) { super.init(x * 2); this.y = x; } } var d = Derived(3); print d.x + d.y;",
+        "9\n",
+    ),
+    (
+        "local classes",
+        "fun make() { class Node { init(next) { this.next = next; } chain() { return Node(this); } } return Node(nil); } print make().chain().chain().next.next.next;",
+        "nil\n",
+    ),
+    (
+        "methods capturing this",
+        "class Counter { init() { this.count = 0; } incrementer() { fun increment() { this.count = this.count + 1; return this.count; } return increment; } } var c = Counter(); var inc = c.incrementer(); inc(); print inc(); print c.count;",
+        "2\n2\n",
+    ),
+    (
+        "fields shadow methods",
+        "class Box { value() { return \"method\"; } } var b = Box(); fun field() { return \"field\"; } b.value = field; print b.value();",
+        "field\n",
+    ),
+    (
+        "redefined globals",
+        "fun f() { return 1; } fun g() { return f(); } print g(); fun f() { return 2; } print g(); var x = 1; fun h() { return x; } for (var i = 0; i < 2; i = i + 1) { x = x + 1; print h(); }",
+        "1\n2\n2\n3\n",
+    ),
+    (
+        "polymorphic call site",
+        "class A { m() { return \"A\"; } } class B < A { m() { return \"B\"; } } class C < A {} fun call(o) { return o.m(); } var a = A(); print call(a) + call(B()) + call(C()); fun f() { return \"field\"; } a.m = f; print call(a);",
+        "ABA\nfield\n",
+    ),
+    (
+        "undefined variable",
+        "print 1;\nprint missing;",
+        "1\n",
+    ),
+    (
+        "undefined assignment",
+        "missing = 1;",
+        "",
+    ),
+    (
+        "operand types",
+        "print 1;\nprint 1 + \"a\";",
+        "1\n",
+    ),
+    (
+        "comparison types",
+        "print \"a\" < 1;",
+        "",
+    ),
+    (
+        "negation type",
+        "print -\"a\";",
+        "",
+    ),
+    (
+        "arity",
+        "fun f(a) {}\nf(1, 2);",
+        "",
+    ),
+    (
+        "native arity",
+        "print\nlen();",
+        "",
+    ),
+    (
+        "not callable",
+        "var x = 1;\nx();",
+        "",
+    ),
+    (
+        "index out of range",
+        "var list = [1];\nprint list[1];",
+        "",
+    ),
+    (
+        "map key",
+        "var map = {};\nmap[1] = 2;",
+        "",
+    ),
+    (
+        "native error",
+        "print\nsqrt(\"four\");",
+        "",
+    ),
+    (
+        "stack overflow",
+        "fun recurse(n) { return recurse(n + 1); }\nrecurse(0);",
+        "",
+    ),
+    (
+        "undefined property",
+        "class Empty {}\nprint Empty().missing;",
+        "",
+    ),
+    (
+        "property on non-instance",
+        "var x = 1;\nprint x.field;",
+        "",
+    ),
+    (
+        "field on non-instance",
+        "var x = \"str\";\nx.field = 1;",
+        "",
+    ),
+    (
+        "superclass must be a class",
+        "var NotAClass = 1;\nclass Sub < NotAClass {}",
+        "",
+    ),
+    (
+        "initializer arity",
+        "class Point { init(x, y) {} }\nPoint(1);",
+        "",
+    ),
+    (
+        "class without initializer arity",
+        "class Empty {}\nEmpty(1);",
+        "",
+    ),
+    (
+        "error inside function",
+        "fun inner() {\n  return nil + 1;\n}\nfun outer() { return inner(); }\nprint outer();",
+        "",
+    ),
+];
+
+pub const STRESS_MAX_CALL_DEPTH: usize = 100;
+
+// A Lox running `engine` with what it prints captured.
+pub fn capturing(engine: Engine) -> (Lox, CaptureIo) {
+    capturing_with(engine, Dialect::default(), InterpreterOptions::default())
+}
+
+pub fn capturing_with(
+    engine: Engine,
+    dialect: Dialect,
+    options: InterpreterOptions,
+) -> (Lox, CaptureIo) {
+    let capture = CaptureIo::new();
+    let lox = Lox::builder()
+        .with_io(Box::new(capture.clone()))
+        .with_dialect(dialect)
+        .with_options(options)
+        .engine(engine)
+        .build();
+    (lox, capture)
+}
+
+fn stressed(gc_stress: bool) -> (Lox, CaptureIo) {
+    let mut options = InterpreterOptions::default();
+    if gc_stress {
+        options.max_call_depth = STRESS_MAX_CALL_DEPTH;
+    }
+
+    let (mut lox, capture) = capturing_with(Engine::default(), Dialect::default(), options);
+    lox.interpreter().set_gc_stress(gc_stress);
+    (lox, capture)
+}
+
+pub fn run_walker(source: &str, gc_stress: bool) -> (String, Option<String>) {
+    let (mut lox, capture) = stressed(gc_stress);
+
+    let error = match lox.run(source) {
+        Ok(()) => None,
+        Err(LoxError::Runtime(error)) => Some(error.to_string()),
+        Err(error) => panic!("unexpected compile error: {}", error),
+    };
+
+    (capture.stdout(), error)
+}
+
+pub fn compile(source: &str) -> Rc<Prototype> {
+    let tokens = Scanner::new(source.to_string()).scan_tokens().unwrap();
+    let statements = Parser::new(tokens).parse().unwrap();
+    match compiler::compile(&statements) {
+        Ok(script) => script,
+        Err(errors) => panic!("unexpected compile error: {}", LoxError::Compile(errors)),
+    }
+}
+
+pub fn run_vm(source: &str, gc_stress: bool) -> (String, Option<String>) {
+    run_script(compile(source), gc_stress)
+}
+
+pub fn run_script(script: Rc<Prototype>, gc_stress: bool) -> (String, Option<String>) {
+    let (mut lox, capture) = stressed(gc_stress);
+
+    let error = Vm::new()
+        .interpret(lox.interpreter(), script)
+        .err()
+        .map(|error| error.to_string());
+
+    (capture.stdout(), error)
+}
+
+pub fn run_engine(source: &str, engine: Engine) -> (String, Option<String>) {
+    let (mut lox, capture) = capturing(engine);
+    let error = lox.run(source).err().map(|error| error.to_string());
+    (capture.stdout(), error)
+}
+
+pub fn run_node(code: &str) -> Option<String> {
+    let mut node = match Command::new("node")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+    {
+        Ok(node) => node,
+        Err(error) if error.kind() == ErrorKind::NotFound => return None,
+        Err(error) => panic!("could not start node: {}", error),
+    };
+
+    node.stdin
+        .take()
+        .unwrap()
+        .write_all(code.as_bytes())
+        .unwrap();
+    let output = node.wait_with_output().unwrap();
+    Some(String::from_utf8(output.stdout).unwrap())
+}
+
+#[derive(Clone, Default)]
+pub struct SharedBuffer(pub Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(bytes);
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,91 @@
+mod common;
+
+use lox::dap;
+use lox::io::CaptureIo;
+use lox::Lox;
+
+use common::SharedBuffer;
+
+#[test]
+fn debugger_stops_at_breakpoints() {
+    let source = "fun add(a, b) {\n  var sum = a + b;\n  return sum;\n}\n{\n  var x = 10;\n  print add(x, 5);\n}\n";
+    let commands = [
+        "break 3",
+        "continue",
+        "locals",
+        "print sum * 2",
+        "bt",
+        "continue",
+    ];
+    let capture = CaptureIo::with_input(commands.iter().map(|line| line.to_string()).collect());
+    let mut lox = Lox::builder().with_io(Box::new(capture.clone())).build();
+    lox.debug(source).expect("debugged run failed");
+
+    assert_eq!(
+        capture.stdout(),
+        "   1  fun add(a, b) {\n\
+         (lox) Breakpoint set at line 3.\n\
+         (lox) Breakpoint at line 3.\n   3    return sum;\n\
+         (lox) a = 10\nb = 5\nsum = 15\n\
+         (lox) 30\n\
+         (lox) #0 add() at line 3\n#1 <script> at line 7\n\
+         (lox) 15\n"
+    );
+}
+
+#[test]
+fn dap_session_stops_at_breakpoints() {
+    let path = std::env::temp_dir().join(format!("lox-dap-{}.lox", std::process::id()));
+    std::fs::write(
+        &path,
+        "fun add(a, b) {\n  var sum = a + b;\n  return sum;\n}\nprint add(1, 5);\n",
+    )
+    .unwrap();
+    let path = path.to_string_lossy().into_owned();
+
+    let requests = [
+        r#"{"command":"initialize","arguments":{}}"#.to_string(),
+        format!(
+            r#"{{"command":"launch","arguments":{{"program":"{}"}}}}"#,
+            path
+        ),
+        r#"{"command":"setBreakpoints","arguments":{"breakpoints":[{"line":3},{"line":4}]}}"#
+            .to_string(),
+        r#"{"command":"configurationDone"}"#.to_string(),
+        r#"{"command":"variables","arguments":{"variablesReference":1}}"#.to_string(),
+        r#"{"command":"evaluate","arguments":{"expression":"sum * 2"}}"#.to_string(),
+        r#"{"command":"continue"}"#.to_string(),
+        r#"{"command":"disconnect"}"#.to_string(),
+    ];
+    let mut input = String::new();
+    for request in requests {
+        input.push_str(&format!(
+            "Content-Length: {}\r\n\r\n{}",
+            request.len(),
+            request
+        ));
+    }
+
+    let output = SharedBuffer::default();
+    let mut lox = Lox::new();
+    dap::serve(&mut lox, std::io::Cursor::new(input), output.clone()).expect("session failed");
+    let _ = std::fs::remove_file(&path);
+    let output = String::from_utf8(output.0.borrow().clone()).unwrap();
+
+    for expected in [
+        r#""breakpoints":[{"verified":true,"line":3},{"verified":false,"line":4}]"#,
+        r#""event":"stopped","body":{"reason":"breakpoint""#,
+        r#"{"name":"sum","value":"6","type":"number","variablesReference":0}"#,
+        r#""result":"12""#,
+        r#""output":"6\n""#,
+        r#""event":"exited","body":{"exitCode":0}"#,
+        r#""command":"disconnect""#,
+    ] {
+        assert!(
+            output.contains(expected),
+            "missing {} in {}",
+            expected,
+            output
+        );
+    }
+}
@@ -0,0 +1,287 @@
+mod common;
+
+use lox::bench;
+use lox::dialect::Dialect;
+use lox::error::LoxError;
+use lox::interpreter::{DivisionByZero, InterpreterOptions};
+use lox::scanner::Scanner;
+use lox::token::{Keyword, Kind};
+use lox::{Engine, Lox};
+
+use common::{capturing, capturing_with};
+
+#[test]
+fn book_dialect_treats_extension_keywords_as_names() {
+    for engine in bench::ENGINES {
+        let (mut lox, capture) =
+            capturing_with(*engine, Dialect::book(), InterpreterOptions::default());
+        lox.run("var yield = 1;\nvar in = 2;\nprint yield + in;")
+            .unwrap();
+        assert_eq!(capture.stdout(), "3\n");
+
+        let Err(LoxError::Compile(errors)) = lox.run("fun f() {\n  yield 1;\n}") else {
+            panic!("expected a compile error");
+        };
+        assert_eq!(
+            errors[0].to_string(),
+            "[line 2] Error: Expect ';' after expression. ('yield' is not a keyword in this dialect.)"
+        );
+    }
+
+    let mut dialect = Dialect::book();
+    dialect.set_enabled(Keyword::Yield, true);
+    dialect.set_enabled(Keyword::While, false);
+    assert_eq!(dialect.keyword("yield"), Some(Keyword::Yield));
+    assert_eq!(dialect.keyword("while"), Some(Keyword::While));
+    assert_eq!(dialect.keyword("in"), None);
+    assert_eq!(dialect.disabled("in"), Some(Keyword::In));
+
+    let mut scanner = Scanner::new("in yield".to_string());
+    scanner.set_dialect(dialect);
+    let kinds: Vec<Kind> = scanner
+        .scan_tokens()
+        .unwrap()
+        .into_iter()
+        .map(|token| token.kind)
+        .collect();
+    assert_eq!(
+        kinds,
+        [
+            Kind::Identifier("in".to_string()),
+            Kind::Keyword(Keyword::Yield),
+            Kind::EndOfFile
+        ]
+    );
+}
+
+#[test]
+fn print_can_be_a_native_function() {
+    let mut dialect = Dialect::default();
+    dialect.set_print_statement(false);
+    for engine in bench::ENGINES {
+        let (mut lox, capture) =
+            capturing_with(*engine, dialect.clone(), InterpreterOptions::default());
+        lox.run("print(1); print(\"a\");\nprintln(nil);\nvar say = println;\nsay(2 + 3);")
+            .unwrap();
+        assert_eq!(capture.stdout(), "1anil\n5\n");
+
+        let Err(LoxError::Compile(errors)) = lox.run("print 1;") else {
+            panic!("expected a compile error");
+        };
+        assert_eq!(
+            errors[0].to_string(),
+            "[line 1] Error: Expect ';' after expression. ('print' is not a keyword in this dialect.)"
+        );
+    }
+
+    let (mut lox, capture) = capturing(Engine::default());
+    lox.run("print 1;").unwrap();
+    assert_eq!(capture.stdout(), "1\n");
+    assert!(lox.get_global("println").is_none());
+}
+
+#[test]
+fn strict_mode_reports_errors_as_the_book_does() {
+    fn compile_errors(lox: &mut Lox, source: &str) -> Vec<String> {
+        match lox.run(source) {
+            Err(LoxError::Compile(errors)) => errors.iter().map(ToString::to_string).collect(),
+            other => panic!("expected compile errors, got {:?}", other.err()),
+        }
+    }
+
+    for engine in bench::ENGINES {
+        let (mut lox, capture) =
+            capturing_with(*engine, Dialect::strict(), InterpreterOptions::default());
+
+        assert_eq!(
+            compile_errors(&mut lox, "var a = [1];\nvar = 2;\nprint 1"),
+            [
+                "[line 1] Error: Unexpected character.",
+                "[line 1] Error: Unexpected character.",
+                "[line 2] Error at '=': Expect variable name.",
+                "[line 3] Error at end: Expect ';' after value.",
+            ]
+        );
+        assert_eq!(
+            compile_errors(
+                &mut lox,
+                "{ var a = 1; var a = 2; }\n{ var b = b; }\nclass A { init() { return 1; } }\nreturn;"
+            ),
+            [
+                "[line 1] Error at 'a': Already a variable with this name in this scope.",
+                "[line 2] Error at 'b': Can't read local variable in its own initializer.",
+                "[line 3] Error at 'return': Can't return a value from an initializer.",
+                "[line 4] Error at 'return': Can't return from top-level code.",
+            ]
+        );
+        assert_eq!(
+            compile_errors(&mut lox, "print \"open;"),
+            [
+                "[line 1] Error: Unterminated string.",
+                "[line 1] Error at end: Expect expression.",
+            ]
+        );
+
+        lox.run("var yield = clock() >= 0;\nprint yield;").unwrap();
+        assert_eq!(capture.stdout(), "true\n");
+        let Err(LoxError::Runtime(error)) = lox.run("print len;") else {
+            panic!("expected a runtime error");
+        };
+        assert_eq!(error.to_string(), "Undefined variable 'len'.\n[line 1]");
+    }
+
+    let (mut lox, capture) = capturing(Engine::default());
+    assert_eq!(
+        compile_errors(&mut lox, "var = 2;"),
+        ["[line 1] Error: Expect variable name."]
+    );
+    lox.run("{ var a = 1; var a = a + 1; print a; }").unwrap();
+    assert_eq!(
+        capture.stdout(),
+        "2
+"
+    );
+}
+
+#[test]
+fn integers_are_a_dialect_option() {
+    let mut dialect = Dialect::default();
+    dialect.set_integers(true);
+    for engine in bench::ENGINES {
+        let (mut lox, capture) =
+            capturing_with(*engine, dialect.clone(), InterpreterOptions::default());
+        lox.run(
+            "print 7 div 2;\nprint -7 div 2;\nprint 7 / 2;\nprint 1 + 0.5;\nprint 3 * 4 - 2;\nprint type(1);\nprint type(1.0);\nprint 2 == 2.0;\nprint 1 < 1.5;",
+        )
+        .unwrap();
+        assert_eq!(
+            capture.stdout(),
+            "3\n-4\n3.5\n1.5\n10\nint\nnumber\ntrue\ntrue\n"
+        );
+
+        for (source, message) in [
+            ("print 9223372036854775807 + 1;", "Integer overflow."),
+            ("print 1 div 0;", "Division by zero."),
+        ] {
+            let Err(LoxError::Runtime(error)) = lox.run(source) else {
+                panic!("expected a runtime error from {}", source);
+            };
+            assert_eq!(error.message, message);
+        }
+
+        let Err(LoxError::Compile(errors)) = lox.run("print 9223372036854775808;") else {
+            panic!("expected a compile error");
+        };
+        assert_eq!(
+            errors[0].to_string(),
+            "[line 1] Error: Integer literal is too large."
+        );
+    }
+
+    let (mut lox, capture) = capturing(Engine::default());
+    lox.run("var div = 6;\nprint div / 4;\nprint type(1);")
+        .unwrap();
+    assert_eq!(capture.stdout(), "1.5\nnumber\n");
+}
+
+#[test]
+fn division_by_zero_follows_the_configured_policy() {
+    let source =
+        "var zero = 0;\nprint 1 / zero;\nprint -1 / 0;\nprint 0 / 0 == 0 / 0;\nprint 1 / 2;";
+    for engine in bench::ENGINES {
+        let (mut lox, capture) = capturing(*engine);
+        lox.run(source).unwrap();
+        assert_eq!(capture.stdout(), "Infinity\n-Infinity\nfalse\n0.5\n");
+
+        let options = InterpreterOptions {
+            division_by_zero: DivisionByZero::Error,
+            ..InterpreterOptions::default()
+        };
+        let (mut lox, capture) = capturing_with(*engine, Dialect::default(), options);
+        lox.run("print 1 / 2;").unwrap();
+        assert_eq!(capture.stdout(), "0.5\n");
+        for source in ["print 1 / 0;", "var zero = 0;\nprint 1 / zero;"] {
+            let Err(LoxError::Runtime(error)) = lox.run(source) else {
+                panic!("expected a runtime error from {}", source);
+            };
+            assert_eq!(error.message, "Division by zero.");
+        }
+    }
+}
+
+#[test]
+fn comparison_operators_order_strings() {
+    for engine in bench::ENGINES {
+        let (mut lox, capture) = capturing(*engine);
+        lox.run("print \"apple\" < \"banana\";\nprint \"b\" >= \"a\";\nprint \"a\" < \"a\";\nprint \"a\" <= \"a\";\nprint \"Z\" > \"a\";\nprint \"ab\" > \"a\";")
+            .unwrap();
+        assert_eq!(capture.stdout(), "true\ntrue\nfalse\ntrue\nfalse\ntrue\n");
+
+        for source in ["print \"a\" < 1;", "print nil >= \"a\";"] {
+            let Err(LoxError::Runtime(error)) = lox.run(source) else {
+                panic!("expected a runtime error from {}", source);
+            };
+            assert_eq!(
+                error.message,
+                "Operands must be two numbers or two strings."
+            );
+        }
+        let Err(LoxError::Runtime(error)) = lox.run("print \"a\" - \"b\";") else {
+            panic!("expected a runtime error");
+        };
+        assert_eq!(error.message, "Operands must be numbers.");
+
+        let mut lox = Lox::builder()
+            .with_dialect(Dialect::book())
+            .engine(*engine)
+            .build();
+        let Err(LoxError::Runtime(error)) = lox.run("print \"a\" < \"b\";") else {
+            panic!("expected a runtime error");
+        };
+        assert_eq!(error.message, "Operands must be numbers.");
+    }
+}
+
+#[test]
+fn multiplication_repeats_strings_and_lists() {
+    for engine in bench::ENGINES {
+        let (mut lox, capture) = capturing(*engine);
+        lox.run("print \"ab\" * 3;\nprint 2 * \"-\";\nprint [0] * 5;\nprint [1, 2] * 0;\nprint \"x\" * 0 == \"\";\nvar row = [nil] * 2;\nprint row[1];\nprint [] * 1000000000000;\nprint \"\" * 1000000000000 == \"\";")
+            .unwrap();
+        assert_eq!(
+            capture.stdout(),
+            "ababab\n--\n[0, 0, 0, 0, 0]\n[]\ntrue\nnil\n[]\ntrue\n"
+        );
+
+        for (source, message) in [
+            (
+                "print \"ab\" * -1;",
+                "Repetition count must not be negative.",
+            ),
+            ("print [0] * 1.5;", "Repetition count must be an integer."),
+            ("print \"ab\" * \"c\";", "Operands must be numbers."),
+            (
+                "print \"ab\" * 100000000000000;",
+                "Repetition result is too large.",
+            ),
+            (
+                "print [0] * 1000000000000;",
+                "Repetition result is too large.",
+            ),
+        ] {
+            let Err(LoxError::Runtime(error)) = lox.run(source) else {
+                panic!("expected a runtime error from {}", source);
+            };
+            assert_eq!(error.message, message);
+        }
+
+        let mut lox = Lox::builder()
+            .with_dialect(Dialect::book())
+            .engine(*engine)
+            .build();
+        let Err(LoxError::Runtime(error)) = lox.run("print \"ab\" * 3;") else {
+            panic!("expected a runtime error");
+        };
+        assert_eq!(error.message, "Operands must be numbers.");
+    }
+}
@@ -0,0 +1,380 @@
+mod common;
+
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::rc::Rc;
+use std::thread;
+use std::time::Duration;
+
+use lox::bench;
+use lox::convert::{ConversionError, FromLox, IntoLox};
+use lox::error::{LoxError, RuntimeErrorKind};
+use lox::foreign::ForeignClass;
+use lox::interpreter::InterpreterOptions;
+use lox::io::{HostIo, IoResult};
+use lox::sync::{LoxHandle, SharedValue};
+use lox::value::Value;
+use lox::Lox;
+
+use common::capturing;
+
+#[test]
+fn restoring_a_snapshot_rolls_back_only_the_bindings() {
+    for engine in bench::ENGINES {
+        let (mut lox, capture) = capturing(*engine);
+        lox.run("class Box {}\nfun make() {\n  var kept = Box();\n  fun get() { return kept; }\n  return get;\n}\nvar get = make();\nvar boxed = get();")
+            .unwrap();
+
+        let snapshot = lox.snapshot();
+        assert!(lox
+            .run("boxed = Box();\nvar fresh = 3;\nmissing();")
+            .is_err());
+        lox.restore(&snapshot);
+        let names = snapshot.names();
+        assert!(names.iter().any(|name| name == "boxed"));
+        assert!(!names.iter().any(|name| name == "fresh"));
+
+        lox.run("print boxed == get();\nget().value = 1;\nprint boxed.value;")
+            .unwrap();
+        assert!(lox.run("print fresh;").is_err());
+        assert_eq!(capture.stdout(), "true\n1\n");
+    }
+}
+
+#[test]
+fn restoring_a_snapshot_keeps_changes_to_shared_objects() {
+    let mut lox = Lox::new();
+    lox.run("var items = [1];\nvar settings = {\"mode\": \"fast\"};\nclass Box {}\nvar box = Box();\nbox.value = 1;")
+        .unwrap();
+
+    let snapshot = lox.snapshot();
+    lox.run("push(items, 2);\nsettings[\"mode\"] = \"slow\";\nbox.value = 2;\nitems = nil;")
+        .unwrap();
+    lox.restore(&snapshot);
+
+    assert_eq!(lox.eval("items").unwrap().to_string(), "[1, 2]");
+    assert_eq!(
+        lox.eval("settings[\"mode\"]").unwrap(),
+        Value::String("slow".into())
+    );
+    assert_eq!(lox.eval("box.value").unwrap(), Value::Number(2.0));
+}
+
+#[test]
+fn eval_keeps_runtime_errors_apart_from_compile_errors() {
+    let options = InterpreterOptions {
+        max_steps: Some(10_000),
+        ..InterpreterOptions::default()
+    };
+    let mut lox = Lox::with_options(options);
+    lox.run("fun spin() { while (true) {} }\nfun add(a, b) { return a + b; }")
+        .unwrap();
+
+    assert_eq!(lox.eval("add(1, 2)").unwrap(), Value::Number(3.0));
+    assert!(matches!(lox.eval("add(1,"), Err(LoxError::Compile(_))));
+    let Err(LoxError::Runtime(error)) = lox.eval("spin()") else {
+        panic!("expected a runtime error");
+    };
+    assert_eq!(error.kind, RuntimeErrorKind::ResourceExceeded);
+
+    let handle = LoxHandle::spawn(move || Lox::with_options(options));
+    handle
+        .run("fun spin() { while (true) {} }\nfun pair(a, b) { return [a, {\"b\": b}]; }")
+        .unwrap();
+    assert_eq!(
+        handle
+            .call(
+                "pair",
+                vec![SharedValue::Int(1), SharedValue::String("two".to_string())]
+            )
+            .unwrap(),
+        SharedValue::List(vec![
+            SharedValue::Int(1),
+            SharedValue::Map(BTreeMap::from([(
+                "b".to_string(),
+                SharedValue::String("two".to_string())
+            )])),
+        ])
+    );
+    assert!(matches!(handle.eval("pair(1,"), Err(LoxError::Compile(_))));
+
+    let Err(LoxError::Runtime(error)) = handle.eval("spin()") else {
+        panic!("expected a runtime error");
+    };
+    assert_eq!(error.kind, RuntimeErrorKind::ResourceExceeded);
+    let Err(LoxError::Runtime(error)) = handle.eval("nil()") else {
+        panic!("expected a runtime error");
+    };
+    assert_eq!(error.kind, RuntimeErrorKind::Error);
+    assert_eq!(error.message, "Can only call functions and classes.");
+    let Err(LoxError::Runtime(error)) = handle.eval("spin") else {
+        panic!("expected a runtime error");
+    };
+    assert_eq!(error.message, "Can't share a function across threads.");
+}
+
+#[test]
+fn registered_natives_run_on_every_engine() {
+    for engine in bench::ENGINES {
+        let (mut lox, capture) = capturing(*engine);
+        let calls = Rc::new(Cell::new(0));
+        let counted = Rc::clone(&calls);
+        lox.register_native("hypot", 2, move |interpreter, arguments| {
+            counted.set(counted.get() + 1);
+            match (&arguments[0], &arguments[1]) {
+                (Value::Number(x), Value::Number(y)) => Ok(Value::Number(x.hypot(*y))),
+                _ => Err(interpreter.runtime_error("hypot() takes numbers.".to_string())),
+            }
+        });
+        lox.register_native("twice", 2, |interpreter, arguments| {
+            let once = interpreter.call(arguments[0].clone(), vec![arguments[1].clone()])?;
+            interpreter.call(arguments[0].clone(), vec![once])
+        });
+
+        lox.run(
+            "print hypot(3, 4);\nfun inc(n) { return n + 1; }\nprint twice(inc, 1);\nprint hypot;",
+        )
+        .unwrap();
+        assert_eq!(capture.stdout(), "5\n3\n<native fn>\n");
+        assert_eq!(calls.get(), 1);
+
+        let Err(LoxError::Runtime(error)) = lox.run("hypot(\"3\", 4);") else {
+            panic!("expected a runtime error on {:?}", engine);
+        };
+        assert_eq!(error.message, "hypot() takes numbers.");
+        let Err(LoxError::Runtime(error)) = lox.run("hypot(3);") else {
+            panic!("expected a runtime error on {:?}", engine);
+        };
+        assert_eq!(error.message, "Expected 2 arguments but got 1.");
+        assert_eq!(calls.get(), 2);
+    }
+}
+
+// Host streams where standard output has been closed.
+struct ClosedStdout;
+
+impl HostIo for ClosedStdout {
+    fn write_stdout(&mut self, _text: &str) -> IoResult<()> {
+        Err(io::Error::new(
+            io::ErrorKind::BrokenPipe,
+            "stdout is closed",
+        ))
+    }
+
+    fn write_stderr(&mut self, _text: &str) -> IoResult<()> {
+        Ok(())
+    }
+
+    fn read_line(&mut self) -> IoResult<Option<String>> {
+        Ok(None)
+    }
+
+    fn read_file(&mut self, path: &str) -> IoResult<String> {
+        Ok(format!("contents of {}", path))
+    }
+
+    fn write_file(&mut self, _path: &str, _contents: &str) -> IoResult<()> {
+        Ok(())
+    }
+
+    fn append_file(&mut self, _path: &str, _contents: &str) -> IoResult<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn output_goes_through_the_host_streams() {
+    for engine in bench::ENGINES {
+        let mut lox = Lox::builder()
+            .with_io(Box::new(ClosedStdout))
+            .engine(*engine)
+            .build();
+        assert_eq!(
+            lox.eval("readFile(\"notes\")").unwrap(),
+            Value::String("contents of notes".into())
+        );
+        let Err(LoxError::Runtime(error)) = lox.run("print 1;") else {
+            panic!("expected a runtime error on {:?}", engine);
+        };
+        assert_eq!(error.message, "Could not write output: stdout is closed.");
+
+        let (mut lox, capture) = capturing(*engine);
+        lox.interpreter().set_gc_log(true);
+        lox.run("print \"out\";").unwrap();
+        lox.interpreter().collect_garbage();
+        assert_eq!(capture.stdout(), "out\n");
+        assert!(capture.stderr().starts_with("-- gc: freed "));
+    }
+}
+
+#[test]
+fn values_convert_between_rust_and_lox() {
+    let mut lox = Lox::new();
+    let scores = HashMap::from([("ada".to_string(), vec![1.0, 2.5])]);
+    lox.define_global("scores", scores.clone().into_lox());
+    lox.define_global("missing", None::<String>.into_lox());
+    lox.run("push(scores[\"ada\"], 4);").unwrap();
+
+    let value = lox.eval("scores").unwrap();
+    assert_eq!(
+        HashMap::<String, Vec<f64>>::from_lox(&value),
+        Ok(HashMap::from([("ada".to_string(), vec![1.0, 2.5, 4.0])]))
+    );
+    assert_eq!(
+        Option::<String>::from_lox(&lox.eval("missing").unwrap()),
+        Ok(None)
+    );
+    assert_eq!(
+        String::try_from(lox.eval("\"a\" + \"b\"").unwrap()),
+        Ok("ab".to_string())
+    );
+    assert_eq!(bool::try_from(lox.eval("1 < 2").unwrap()), Ok(true));
+
+    let error = Vec::<f64>::from_lox(&lox.eval("[1, \"two\"]").unwrap()).unwrap_err();
+    assert_eq!(error.to_string(), "Expected number but got string.");
+    assert_eq!(
+        f64::from_lox(&Value::Nil),
+        Err(ConversionError {
+            expected: "number",
+            found: "nil",
+        })
+    );
+}
+
+#[test]
+fn hosts_call_lox_functions_on_every_engine() {
+    for engine in bench::ENGINES {
+        let (mut lox, capture) = capturing(*engine);
+        lox.run("fun counter() {\n  var count = 0;\n  fun next(by) { count = count + by; return count; }\n  return next;\n}\nvar next = counter();\nclass Greeter {\n  init(name) { this.name = name; }\n  greet(other) { print this.name + \" greets \" + other; }\n}\nfun fail() { return nil + 1; }")
+            .unwrap();
+
+        let next = lox.get_global("next").unwrap();
+        lox.call(&next, &[Value::Number(2.0)]).unwrap();
+        assert_eq!(
+            lox.call(&next, &[Value::Number(3.0)]).unwrap(),
+            Value::Number(5.0)
+        );
+
+        let class = lox.get_global("Greeter").unwrap();
+        let greeter = lox.call(&class, &["ada".into_lox()]).unwrap();
+        lox.define_global("greeter", greeter);
+        let greet = lox.eval("greeter.greet").unwrap();
+        lox.call(&greet, &["bob".into_lox()]).unwrap();
+        assert_eq!(capture.stdout(), "ada greets bob\n");
+
+        let fail = lox.get_global("fail").unwrap();
+        let error = lox.call(&fail, &[]).unwrap_err();
+        assert_eq!(
+            error.message,
+            "Operands must be two numbers or two strings."
+        );
+        let error = lox.call(&next, &[]).unwrap_err();
+        assert_eq!(error.message, "Expected 1 arguments but got 0.");
+        let error = lox.call(&Value::Nil, &[]).unwrap_err();
+        assert_eq!(error.message, "Can only call functions and classes.");
+        assert_eq!(
+            lox.call(&next, &[Value::Number(1.0)]).unwrap(),
+            Value::Number(6.0)
+        );
+    }
+}
+
+#[test]
+fn foreign_objects_behave_like_instances() {
+    let class = Rc::new(
+        ForeignClass::new("Account")
+            .getter("balance", |_, account| {
+                Ok(Value::Number(*account.borrow::<f64>().unwrap()))
+            })
+            .setter("balance", |interpreter, account, value| {
+                let balance = f64::from_lox(&value)
+                    .map_err(|error| interpreter.runtime_error(error.to_string()))?;
+                *account.borrow_mut::<f64>().unwrap() = balance;
+                Ok(())
+            })
+            .method("deposit", 1, |interpreter, account, arguments| {
+                let amount = f64::from_lox(&arguments[0])
+                    .map_err(|error| interpreter.runtime_error(error.to_string()))?;
+                let mut balance = account.borrow_mut::<f64>().unwrap();
+                *balance += amount;
+                Ok(Value::Number(*balance))
+            }),
+    );
+
+    for engine in bench::ENGINES {
+        let (mut lox, capture) = capturing(*engine);
+        let account = class.instance(10.0_f64);
+        lox.define_global("account", account.clone());
+        lox.run("var deposit = account.deposit;\nprint deposit(5);\naccount.balance = account.balance * 2;\nprint account.balance;\nprint account;\nprint type(account);")
+            .unwrap();
+        assert_eq!(capture.stdout(), "15\n30\nAccount instance\nforeign\n");
+        let Value::Foreign(foreign) = &account else {
+            panic!("expected a foreign value");
+        };
+        assert_eq!(*foreign.borrow::<f64>().unwrap(), 30.0);
+        assert!(foreign.borrow::<String>().is_none());
+
+        for (source, message) in [
+            ("account.owner;", "Undefined property 'owner'."),
+            (
+                "account.owner = 1;",
+                "Can't set property 'owner' on Account.",
+            ),
+            (
+                "account.balance = \"lots\";",
+                "Expected number but got string.",
+            ),
+            ("account.deposit();", "Expected 1 arguments but got 0."),
+        ] {
+            let Err(LoxError::Runtime(error)) = lox.run(source) else {
+                panic!("expected {} to fail on {:?}", source, engine);
+            };
+            assert_eq!(error.message, message);
+        }
+    }
+}
+
+#[test]
+fn eval_returns_the_value_of_an_expression() {
+    let mut lox = Lox::new();
+    lox.run("var base = 40;\nfun add(a, b) { return a + b; }")
+        .unwrap();
+    assert_eq!(lox.eval("add(base, 2)").unwrap(), Value::Number(42.0));
+    assert_eq!(lox.eval("base = 1").unwrap(), Value::Number(1.0));
+    assert_eq!(lox.get_global("base"), Some(Value::Number(1.0)));
+    assert_eq!(
+        lox.eval("[base, \"two\", nil]").unwrap().to_string(),
+        "[1, \"two\", nil]"
+    );
+    assert!(matches!(lox.eval("print base;"), Err(LoxError::Compile(_))));
+    assert!(matches!(lox.eval("add(1, 2) 3"), Err(LoxError::Compile(_))));
+
+    let Err(LoxError::Compile(errors)) = lox.eval("this") else {
+        panic!("expected the resolver to reject 'this'");
+    };
+    assert_eq!(errors[0].message, "Can't use 'this' outside of a class.");
+}
+
+#[test]
+fn handles_are_shared_and_cancelled_across_threads() {
+    let handle = LoxHandle::spawn(Lox::new);
+    let worker = handle.clone();
+    let running =
+        thread::spawn(move || worker.run("var spins = 0;\nwhile (true) spins = spins + 1;"));
+    thread::sleep(Duration::from_millis(50));
+    handle.cancel();
+    let Err(LoxError::Runtime(error)) = running.join().unwrap() else {
+        panic!("expected the loop to be interrupted");
+    };
+    assert_eq!(error.kind, RuntimeErrorKind::Interrupted);
+
+    assert!(matches!(
+        handle.eval("spins > 0"),
+        Ok(SharedValue::Boolean(true))
+    ));
+    let Err(LoxError::Runtime(error)) = handle.call("missing", Vec::new()) else {
+        panic!("expected a runtime error");
+    };
+    assert_eq!(error.message, "Undefined variable 'missing'.");
+}
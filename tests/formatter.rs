@@ -0,0 +1,99 @@
+mod common;
+
+use lox::bench;
+use lox::formatter::{self, BraceStyle, Style};
+
+use common::{run_walker, CASES};
+
+#[test]
+fn formatter_is_idempotent_and_keeps_behavior() {
+    let sources = CASES
+        .iter()
+        .map(|(name, source, _)| (*name, *source))
+        .chain(
+            bench::WORKLOADS
+                .iter()
+                .map(|workload| (workload.name, workload.source)),
+        );
+    for (name, source) in sources {
+        let formatted = formatter::format(source, &Style::default())
+            .unwrap_or_else(|errors| panic!("case '{}' failed to format: {:?}", name, errors));
+        assert_eq!(
+            formatter::format(&formatted, &Style::default()).unwrap(),
+            formatted,
+            "formatting case '{}' twice changes it",
+            name
+        );
+        assert_eq!(
+            run_walker(&formatted, false).0,
+            run_walker(source, false).0,
+            "formatted case '{}' prints differently",
+            name
+        );
+    }
+}
+
+#[test]
+fn formatter_keeps_comments_and_wraps_long_calls() {
+    let source = "// setup\nvar total=0;// running sum\n\n\nfor(var i=0;i<3;i=i+1){total=total+i;}\nif(total>2)print total;else{print \"small\";}\nprint describe(total, \"a long label\", [1,2,3]); // why\n";
+    assert_eq!(
+        formatter::format(
+            source,
+            &Style {
+                max_width: 30,
+                ..Style::default()
+            }
+        )
+        .unwrap(),
+        "// setup\n\
+         var total = 0; // running sum\n\
+         \n\
+         for (var i = 0; i < 3; i = i + 1) {\n\
+         \x20 total = total + i;\n\
+         }\n\
+         if (total > 2) print total;\n\
+         else {\n\
+         \x20 print \"small\";\n\
+         }\n\
+         print describe(\n\
+         \x20 total,\n\
+         \x20 \"a long label\",\n\
+         \x20 [1, 2, 3]\n\
+         ); // why\n"
+    );
+}
+
+#[test]
+fn formatter_follows_configured_style() {
+    let style = Style::parse(
+        "# team style\nindent_width = 4\nbrace_style = \"next-line\" # braces line up\nmax_width = 100\n",
+    )
+    .unwrap();
+    assert_eq!(
+        style,
+        Style {
+            indent_width: 4,
+            use_tabs: false,
+            max_width: 100,
+            brace_style: BraceStyle::NextLine,
+        }
+    );
+    assert!(Style::parse("indent = 4")
+        .unwrap_err()
+        .contains("Unknown option 'indent'"));
+    assert!(Style::parse("use_tabs = yes").is_err());
+
+    let source = "fun f(a){if(a){print a;}else{print 0;}}\n";
+    assert_eq!(
+        formatter::format(source, &style).unwrap(),
+        "fun f(a)\n{\n    if (a)\n    {\n        print a;\n    }\n    else\n    {\n        print 0;\n    }\n}\n"
+    );
+    let tabs = Style {
+        use_tabs: true,
+        ..Style::default()
+    };
+    assert_eq!(
+        formatter::format(source, &tabs).unwrap(),
+        "fun f(a) {\n\tif (a) {\n\t\tprint a;\n\t} else {\n\t\tprint 0;\n\t}\n}\n"
+    );
+}
@@ -0,0 +1,95 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use lox::bench;
+use lox::gc::{GcConfig, GcMode};
+use lox::io::CaptureIo;
+use lox::value::Value;
+use lox::Lox;
+
+#[test]
+fn finalizers_run_once_objects_are_freed() {
+    for engine in bench::ENGINES {
+        let mut lox = Lox::builder().engine(*engine).build();
+        lox.run("class Node {}\nvar a = Node();\nvar b = Node();\na.next = b;\nb.next = a;\nvar kept = [1];\nvar dropped = [2];")
+            .unwrap();
+
+        let finalized = Rc::new(RefCell::new(Vec::new()));
+        for name in ["a", "kept", "dropped"] {
+            let value = lox.get_global(name).unwrap();
+            let finalized = finalized.clone();
+            assert!(lox
+                .interpreter()
+                .add_finalizer(&value, move || finalized.borrow_mut().push(name)));
+        }
+        assert!(!lox.interpreter().add_finalizer(&Value::Number(1.0), || {}));
+        let weak = lox.get_global("a").unwrap().downgrade().unwrap();
+        assert!(weak.upgrade().is_some());
+
+        lox.run("a = nil;\nb = nil;\ndropped = nil;").unwrap();
+        assert!(finalized.borrow().is_empty());
+        lox.interpreter().collect_garbage();
+        assert!(weak.upgrade().is_none());
+        assert_eq!(*finalized.borrow(), ["a", "dropped"]);
+
+        drop(lox);
+        assert_eq!(*finalized.borrow(), ["a", "dropped", "kept"]);
+    }
+}
+
+#[test]
+fn generational_collections_free_the_nursery() {
+    let source = "class Node {}\nvar kept = Node();\nfor (var i = 0; i < 200; i = i + 1) {\n  var a = Node();\n  var b = Node();\n  a.next = b;\n  b.next = a;\n}\nkept.next = kept;\nprint \"done\";";
+    for engine in bench::ENGINES {
+        let capture = CaptureIo::new();
+        let gc = GcConfig {
+            mode: GcMode::Generational,
+            nursery_bytes: 1024,
+        };
+        let mut lox = Lox::builder()
+            .with_io(Box::new(capture.clone()))
+            .with_gc(gc)
+            .engine(*engine)
+            .build();
+        lox.interpreter().set_gc_log(true);
+        lox.run(source).unwrap();
+        assert_eq!(capture.stdout(), "done\n");
+
+        let log = capture.stderr();
+        assert!(log.contains("-- minor gc: freed "), "{}", log);
+        assert!(!log.contains("-- gc: "), "{}", log);
+        let live = lox.interpreter().heap().objects().len();
+        assert!(live < 50, "{}", lox.interpreter().heap().dump());
+
+        let kept = lox.get_global("kept").unwrap().downgrade().unwrap();
+        lox.run("kept = nil;").unwrap();
+        assert!(kept.is_alive());
+        let full = lox.interpreter().collect_garbage();
+        assert!(!full.minor);
+        assert!(!kept.is_alive());
+        assert_eq!(
+            lox.interpreter().heap().objects().len(),
+            live - full.objects_freed
+        );
+    }
+}
+
+#[test]
+fn empty_containers_and_their_growth_count_toward_collection() {
+    let source = "for (var i = 0; i < 50000; i = i + 1) {\n  var m = {};\n  m[\"self\"] = m;\n  var l = [];\n  push(l, l);\n}";
+    for engine in bench::ENGINES {
+        let mut lox = Lox::builder().engine(*engine).build();
+        lox.run(source).unwrap();
+        let live = lox.interpreter().heap().objects().len();
+        assert!(live < 50000, "{} objects left on {:?}", live, engine);
+
+        lox.run("var list = [];").unwrap();
+        let before = lox.interpreter().heap().bytes_allocated();
+        lox.run("for (var i = 0; i < 1000; i = i + 1) push(list, i);")
+            .unwrap();
+        assert!(
+            lox.interpreter().heap().bytes_allocated()
+                >= before + 1000 * std::mem::size_of::<Value>()
+        );
+    }
+}
@@ -0,0 +1,192 @@
+mod common;
+
+use lox::bench;
+use lox::{Engine, Lox};
+
+use common::capturing;
+
+#[cfg(feature = "trace")]
+#[test]
+fn tracing_sees_phases_calls_and_collections() {
+    use std::fmt::Debug;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Arc, Mutex};
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+
+    // Writes down each span as it is made, and each event's target.
+    #[derive(Clone, Default)]
+    struct Recorder {
+        records: Arc<Mutex<Vec<String>>>,
+        spans: Arc<AtomicU64>,
+    }
+
+    struct FunctionField(Option<String>);
+
+    impl Visit for FunctionField {
+        fn record_str(&mut self, field: &Field, value: &str) {
+            if field.name() == "function" {
+                self.0 = Some(value.to_string());
+            }
+        }
+
+        fn record_debug(&mut self, _: &Field, _: &dyn Debug) {}
+    }
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, metadata: &Metadata) -> bool {
+            metadata.target() == "lox"
+        }
+
+        fn new_span(&self, attributes: &Attributes) -> Id {
+            let mut function = FunctionField(None);
+            attributes.record(&mut function);
+            let name = attributes.metadata().name();
+            self.records.lock().unwrap().push(match function.0 {
+                Some(function) => format!("{} {}", name, function),
+                None => name.to_string(),
+            });
+            Id::from_u64(self.spans.fetch_add(1, Ordering::Relaxed) + 1)
+        }
+
+        fn record(&self, _: &Id, _: &Record) {}
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, event: &Event) {
+            let record = format!("event {}", event.metadata().target());
+            self.records.lock().unwrap().push(record);
+        }
+
+        fn enter(&self, _: &Id) {}
+
+        fn exit(&self, _: &Id) {}
+    }
+
+    let source = "fun add(a, b) { return a + b; }\nprint add(1, 2);";
+    let expected: &[(Engine, &[&str])] = &[
+        (
+            Engine::Walker,
+            &["run", "scan", "parse", "resolve", "resolve", "call add"],
+        ),
+        (
+            Engine::Vm,
+            &[
+                "run", "scan", "parse", "resolve", "compile", "optimize", "call add",
+            ],
+        ),
+    ];
+    for (engine, spans) in expected {
+        let recorder = Recorder::default();
+        let (mut lox, _) = capturing(*engine);
+        tracing::subscriber::with_default(recorder.clone(), || {
+            lox.run(source).unwrap();
+            lox.interpreter().collect_garbage();
+        });
+
+        let mut records = spans.to_vec();
+        records.extend(["collect", "event lox"]);
+        assert_eq!(*recorder.records.lock().unwrap(), records, "{:?}", engine);
+    }
+}
+
+#[test]
+fn metrics_count_a_run() {
+    let source =
+        "fun pair(n) { return [n, n]; }\nvar i = 0;\nwhile (i < 10) { pair(i); i = i + 1; }";
+    for engine in bench::ENGINES {
+        let (mut lox, _) = capturing(*engine);
+        lox.interpreter().set_gc_stress(true);
+        lox.run(source).unwrap();
+
+        let metrics = lox.metrics();
+        assert_eq!((metrics.tokens, metrics.nodes), (38, 21), "{:?}", engine);
+        assert!(metrics.instructions > 100, "{:?}", metrics);
+        assert!(metrics.allocations >= 10, "{:?}", metrics);
+        assert_eq!(metrics.collections, metrics.allocations, "{:?}", metrics);
+        assert!(metrics.peak_heap_bytes > 0, "{:?}", metrics);
+        assert!(metrics
+            .report()
+            .starts_with("tokens scanned             38\n"));
+    }
+
+    let mut lox = Lox::new();
+    assert!(lox.run("print 1 +;").is_err());
+    assert_eq!(lox.metrics().nodes, 0);
+    lox.eval("1 + 2 * 3").unwrap();
+    assert_eq!((lox.metrics().tokens, lox.metrics().nodes), (5, 5));
+}
+
+// heapDump() is only defined in debug builds.
+#[cfg(debug_assertions)]
+#[test]
+fn heap_dump_lists_objects_and_edges() {
+    let source = "class Node { init(next) { this.next = next; } }\nvar a = Node(nil);\nvar b = Node(a);\na.next = b;\nvar list = [a, 1];\nheapDump();";
+    for engine in bench::ENGINES {
+        let (mut lox, capture) = capturing(*engine);
+        lox.run(source).unwrap();
+
+        let heap = lox.interpreter().heap();
+        let objects = heap.objects();
+        let describe = |index: usize| objects[index].description.as_str();
+        let class = (0..objects.len())
+            .find(|&index| describe(index) == "class Node")
+            .unwrap();
+        let instances: Vec<usize> = (0..objects.len())
+            .filter(|&index| describe(index) == "Node instance")
+            .collect();
+        assert_eq!(instances.len(), 2, "{}", heap.dump());
+        assert_eq!(objects[instances[0]].references, [class, instances[1]]);
+        assert_eq!(objects[instances[1]].references, [class, instances[0]]);
+        let list = objects
+            .iter()
+            .find(|object| object.description == "list of 2")
+            .unwrap();
+        assert_eq!(list.references, [instances[0]]);
+
+        let dump = heap.dump();
+        assert!(dump.starts_with(&format!("{} live objects, ", objects.len())));
+        assert!(dump.contains(&format!("#{} class Node (", class)));
+        assert_eq!(capture.stderr(), dump, "{:?}", engine);
+    }
+}
+
+#[test]
+fn profiler_counts_calls() {
+    let source = "fun fib(n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2); } print fib(10); print len(\"ab\");";
+    for engine in [Engine::Walker, Engine::Vm] {
+        let (mut lox, _) = capturing(engine);
+        lox.interpreter().enable_profiler();
+        lox.run(source).expect("profiled run failed");
+
+        let profile = lox.interpreter().take_profile().expect("no profile");
+        let mut calls: Vec<(&str, u64)> = profile
+            .functions()
+            .into_iter()
+            .map(|function| (function.name.as_str(), function.calls))
+            .collect();
+        calls.sort();
+        assert_eq!(calls, [("fib", 177), ("len", 1)], "{:?}", engine);
+        assert!(profile.folded_stacks().contains("fib;fib;fib "));
+    }
+}
+
+#[test]
+fn coverage_marks_unexecuted_statements() {
+    let source = "fun f(n) {\n  if (n) {\n    print \"yes\";\n  } else {\n    print \"no\";\n  }\n}\nf(true);\nf(true);\n";
+    let (mut lox, _) = capturing(Engine::default());
+    lox.interpreter().enable_coverage();
+    lox.run(source).expect("covered run failed");
+
+    let coverage = lox.interpreter().take_coverage().expect("no coverage");
+    let lines: Vec<(usize, u64)> = coverage
+        .lines()
+        .iter()
+        .map(|(&line, &count)| (line, count))
+        .collect();
+    assert_eq!(lines, [(1, 1), (2, 2), (3, 2), (5, 0), (8, 1), (9, 1)]);
+    let lcov = coverage.lcov("f.lox");
+    assert!(lcov.starts_with("TN:\nSF:f.lox\nDA:1,1\n"));
+    assert!(lcov.ends_with("DA:9,1\nLF:6\nLH:5\nend_of_record\n"));
+}
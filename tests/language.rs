@@ -0,0 +1,419 @@
+mod common;
+
+use lox::bench;
+use lox::dialect::Dialect;
+use lox::error::LoxError;
+use lox::interpreter::InterpreterOptions;
+use lox::transpiler::Target;
+use lox::Lox;
+
+use common::{capturing, capturing_with, run_node};
+
+#[test]
+fn lists_concatenate_compare_and_have_a_length() {
+    for engine in bench::ENGINES {
+        let (mut lox, capture) = capturing(*engine);
+        lox.run(
+            "var a = [1, 2];\nvar b = a + [3];\nprint b;\nprint a;\nprint [] + [];\nprint [1, [2]] == [1, [2]];\nprint [1, 2] == [2, 1];\nprint [1] != [1, 1];\nprint len(\"héllo\");\nprint len(b);\nprint len({\"a\": 1});",
+        )
+        .unwrap();
+        assert_eq!(
+            capture.stdout(),
+            "[1, 2, 3]\n[1, 2]\n[]\ntrue\nfalse\ntrue\n5\n3\n1\n"
+        );
+
+        let (mut lox, capture) = capturing(*engine);
+        lox.run("var a = [];\npush(a, a);\nvar b = [];\npush(b, b);\nprint a == b;")
+            .unwrap();
+        assert_eq!(capture.stdout(), "true\n");
+
+        let Err(LoxError::Runtime(error)) = lox.run("print len(1);") else {
+            panic!("expected a runtime error");
+        };
+        assert_eq!(
+            error.message,
+            "Argument to 'len' must be a string, list or map."
+        );
+    }
+}
+
+#[test]
+fn lists_and_maps_that_contain_themselves_print() {
+    for engine in bench::ENGINES {
+        let (mut lox, capture) = capturing(*engine);
+        lox.run("var l = [1];\npush(l, l);\nprint l;\nprint string(l);\nvar m = {\"a\": \"b\"};\nm[\"self\"] = [m];\nprint m;\nvar shared = [1];\nprint [shared, shared];")
+            .unwrap();
+        lox.run(
+            "class P {\n  toString() { return \"P\"; }\n}\nvar p = [P()];\npush(p, p);\nprint p;",
+        )
+        .unwrap();
+        assert_eq!(
+            capture.stdout(),
+            "[1, [...]]\n[1, [...]]\n{\"a\": \"b\", \"self\": [{...}]}\n[[1], [1]]\n[P, [...]]\n"
+        );
+    }
+}
+
+#[test]
+fn for_in_follows_the_iteration_protocol() {
+    let source = "class Countdown {\n  init(from) { this.count = from; }\n  done() { return this.count == 0; }\n  next() { this.count = this.count - 1; return this.count + 1; }\n}\nclass Range {\n  init(end) { this.end = end; }\n  iterator() { return Countdown(this.end); }\n}\nclass Pairs {\n  iterator() { yield \"a\"; yield \"b\"; }\n}\nclass Wrapped {\n  iterator() { return [1, 2]; }\n}\nfor (var n in Range(3)) print n;\nfor (var n in Countdown(2)) print n;\nfor (var p in Pairs()) print p;\nfor (var w in Wrapped()) print w;";
+    let expected = "3\n2\n1\n2\n1\na\nb\n1\n2\n";
+
+    for engine in bench::ENGINES {
+        let (mut lox, capture) = capturing(*engine);
+        lox.run(source).unwrap();
+        assert_eq!(capture.stdout(), expected);
+
+        for (source, message) in [
+            (
+                "class A {}\nfor (var a in A()) print a;",
+                "Iterator must have 'next' and 'done' methods.",
+            ),
+            (
+                "for (var a in 1) print a;",
+                "Can only iterate over generators, lists and iterators.",
+            ),
+            (
+                "fun g() { for (var a in running) print a; yield 1; }\nvar running = g();\nfor (var a in running) print a;",
+                "Generator is already running.",
+            ),
+        ] {
+            let Err(LoxError::Runtime(error)) = lox.run(source) else {
+                panic!("expected a runtime error from {}", source);
+            };
+            assert_eq!(error.message, message);
+        }
+    }
+
+    let code = Lox::new().transpile(source, Target::JavaScript).unwrap();
+    if let Some(output) = run_node(&code) {
+        assert_eq!(output, expected);
+    }
+}
+
+#[test]
+fn print_uses_to_string_when_a_class_defines_it() {
+    let source = "class Point {\n  init(x, y) { this.x = x; this.y = y; }\n  toString() { return \"(\" + string(this.x) + \", \" + string(this.y) + \")\"; }\n}\nclass Loop {\n  toString() { return \"<\" + string(this) + \">\"; }\n}\nclass Plain {}\nprint Point(1, 2);\nprint [Point(3, 4), \"a\"];\nprint \"at \" + string(Point(5, 6));\nprint Loop();\nprint Plain();";
+    let expected = "(1, 2)\n[(3, 4), \"a\"]\nat (5, 6)\n<Loop instance>\nPlain instance\n";
+
+    for engine in bench::ENGINES {
+        let (mut lox, capture) = capturing(*engine);
+        lox.run(source).unwrap();
+        assert_eq!(capture.stdout(), expected);
+
+        let Err(LoxError::Runtime(error)) =
+            lox.run("class Bad {\n  toString() { return 1; }\n}\nprint Bad();")
+        else {
+            panic!("expected a runtime error");
+        };
+        assert_eq!(error.message, "'toString' must return a string.");
+        assert_eq!(error.line, 4);
+
+        let (mut lox, capture) =
+            capturing_with(*engine, Dialect::book(), InterpreterOptions::default());
+        lox.run("class A {\n  toString() { return \"a\"; }\n}\nprint A();")
+            .unwrap();
+        assert_eq!(capture.stdout(), "A instance\n");
+    }
+
+    let code = Lox::new().transpile(source, Target::JavaScript).unwrap();
+    if let Some(output) = run_node(&code) {
+        assert_eq!(output, expected);
+    }
+}
+
+#[test]
+fn initializers_return_the_instance() {
+    let source = "class A {\n  init(x) {\n    this.x = x;\n    if (x > 1) return;\n    this.y = x;\n  }\n}\nvar a = A(1);\nprint a.y;\nprint A(2).x;\nprint a.init(5) == a;\nprint a.x;\nvar init = a.init;\nprint init(7) == a;\nprint a.x;";
+    let expected = "1\n2\ntrue\n5\ntrue\n7\n";
+
+    for engine in bench::ENGINES {
+        let (mut lox, capture) = capturing(*engine);
+        lox.run(source).unwrap();
+        assert_eq!(capture.stdout(), expected);
+
+        let Err(LoxError::Compile(errors)) =
+            lox.run("class B {\n  init() {\n    return 1;\n  }\n}")
+        else {
+            panic!("expected a compile error");
+        };
+        assert_eq!(
+            errors[0].to_string(),
+            "[line 3] Error: Can't return a value from an initializer."
+        );
+    }
+
+    let lox = Lox::new();
+    let code = lox.transpile(source, Target::JavaScript).unwrap();
+    if let Some(output) = run_node(&code) {
+        assert_eq!(output, expected);
+    }
+}
+
+#[test]
+fn fields_and_keys_can_be_probed_and_deleted() {
+    let source = "class Config { init() { this.port = 80; } }\nvar config = Config();\nprint has(config, \"port\");\nprint has(config, \"host\");\nprint has(config, \"init\");\nprint delete(config, \"port\");\nprint delete(config, \"port\");\nprint has(config, \"port\");\nconfig.port = 8080;\nprint config.port;\nvar map = {\"a\": 1};\nprint has(map, \"a\");\nprint delete(map, \"a\");\nprint has(map, \"a\");";
+    let expected = "true\nfalse\nfalse\ntrue\nfalse\nfalse\n8080\ntrue\ntrue\nfalse\n";
+
+    for engine in bench::ENGINES {
+        let (mut lox, capture) = capturing(*engine);
+        lox.run(source).unwrap();
+        assert_eq!(capture.stdout(), expected);
+
+        let Err(LoxError::Runtime(error)) = lox.run("print has(1, \"a\");") else {
+            panic!("expected a runtime error");
+        };
+        assert_eq!(
+            error.message,
+            "Argument to 'has' must be an instance or a map."
+        );
+    }
+
+    let lox = Lox::new();
+    let code = lox.transpile(source, Target::JavaScript).unwrap();
+    if let Some(output) = run_node(&code) {
+        assert_eq!(output, expected);
+    }
+}
+
+#[test]
+fn methods_are_bound_values_that_compare_by_receiver() {
+    let source = "class Counter {\n  init(start) { this.count = start; }\n  add(by) { this.count = this.count + by; return this.count; }\n}\nvar a = Counter(1);\nvar b = Counter(10);\nvar add = a.add;\nprint add(2);\nprint a.count;\nprint a.add == a.add;\nprint add == a.add;\nprint a.add == b.add;\nfun f(x, y) { return x; }\nvar g = f;\nprint f == g;\nprint arity(f);\nprint arity(add);\nprint arity(Counter);\nprint arity(clock);\nprint name(f);\nprint name(add);\nprint name(Counter);\nprint name(clock);";
+    let expected = "3\n3\ntrue\ntrue\nfalse\ntrue\n2\n1\n1\n0\nf\nadd\nCounter\nclock\n";
+
+    for engine in bench::ENGINES {
+        let (mut lox, capture) = capturing(*engine);
+        lox.run(source).unwrap();
+        assert_eq!(capture.stdout(), expected, "{:?}", engine);
+
+        let Err(LoxError::Runtime(error)) = lox.run("print arity(1);") else {
+            panic!("expected a runtime error");
+        };
+        assert_eq!(
+            error.message,
+            "Argument to 'arity' must be a function or a class."
+        );
+    }
+
+    let lox = Lox::new();
+    let code = lox.transpile(source, Target::JavaScript).unwrap();
+    if let Some(output) = run_node(&code) {
+        assert_eq!(output, expected);
+    }
+}
+
+#[test]
+fn classes_can_be_walked_at_runtime() {
+    let source = "class Shape {}\nclass Circle < Shape {}\nvar circle = Circle();\nprint classOf(circle) == Circle;\nprint className(circle);\nprint className(Shape);\nprint superclassOf(Circle) == Shape;\nprint superclassOf(Shape);\nvar current = classOf(circle);\nwhile (current != nil) {\n  print className(current);\n  current = superclassOf(current);\n}";
+    let expected = "true\nCircle\nShape\ntrue\nnil\nCircle\nShape\n";
+
+    for engine in bench::ENGINES {
+        let (mut lox, capture) = capturing(*engine);
+        lox.run(source).unwrap();
+        assert_eq!(capture.stdout(), expected, "{:?}", engine);
+
+        let Err(LoxError::Runtime(error)) = lox.run("print classOf(Shape);") else {
+            panic!("expected a runtime error");
+        };
+        assert_eq!(error.message, "Argument to 'classOf' must be an instance.");
+    }
+
+    let lox = Lox::new();
+    let code = lox.transpile(source, Target::JavaScript).unwrap();
+    if let Some(output) = run_node(&code) {
+        assert_eq!(output, expected);
+    }
+}
+
+#[test]
+fn runtime_errors_are_caught_as_error_classes() {
+    let source = "fun fail(step) {\n  if (step == 0) return 1 + nil;\n  if (step == 1) return missing;\n  if (step == 2) return [1][3];\n  throw \"plain\";\n}\nfor (var step = 0; step < 4; step = step + 1) {\n  try {\n    fail(step);\n    print \"unreachable\";\n  } catch (error) {\n    if (type(error) == \"string\") print error;\n    else print className(error) + \": \" + error.message;\n  }\n}\nclass Timeout < Error {\n  init(message, seconds) {\n    super.init(message);\n    this.seconds = seconds;\n  }\n}\ntry {\n  try {\n    throw Timeout(\"slow\", 3);\n  } catch (error) {\n    print error.seconds;\n    throw error;\n  }\n} catch (error) {\n  print className(superclassOf(classOf(error))) + \": \" + error.message;\n}";
+    let expected = "TypeError: Operands must be two numbers or two strings.\nNameError: Undefined variable 'missing'.\nIndexError: List index out of range.\nplain\n3\nError: slow\n";
+
+    for engine in bench::ENGINES {
+        let (mut lox, capture) = capturing(*engine);
+        lox.run(source).unwrap();
+        assert_eq!(capture.stdout(), expected, "{:?}", engine);
+
+        lox.run("try {\n  nil.field;\n} catch (error) {\n  print error.line;\n}\nvar thrown = Error(\"late\");\ntry {\n  throw thrown;\n} catch (error) {\n  print error.line;\n}")
+            .unwrap();
+        assert_eq!(
+            capture.stdout(),
+            format!("{}2\n8\n", expected),
+            "{:?}",
+            engine
+        );
+
+        let Err(LoxError::Runtime(error)) = lox.run("\nthrow Error(\"boom\");") else {
+            panic!("expected a runtime error");
+        };
+        assert_eq!((error.message.as_str(), error.line), ("boom", 2));
+    }
+
+    let lox = Lox::new();
+    let code = lox.transpile(source, Target::JavaScript).unwrap();
+    if let Some(output) = run_node(&code) {
+        assert_eq!(output, expected);
+    }
+}
+
+#[test]
+fn errors_a_script_raises_are_caught_as_plain_errors() {
+    let source = "fun show(step) {\n  try {\n    if (step == 0) error(\"Undefined behaviour here\");\n    if (step == 1) error(\"Expected a widget\");\n    error(\"Index 3 out of range.\");\n  } catch (error) {\n    print className(error) + \": \" + error.message;\n  }\n}\nfor (var step = 0; step < 3; step = step + 1) show(step);";
+    let expected =
+        "Error: Undefined behaviour here\nError: Expected a widget\nError: Index 3 out of range.\n";
+
+    for engine in bench::ENGINES {
+        let (mut lox, capture) = capturing(*engine);
+        lox.run(source).unwrap();
+        assert_eq!(capture.stdout(), expected, "{:?}", engine);
+
+        lox.run("try {\n  assert(false, \"Operand went missing\");\n} catch (error) {\n  print className(error);\n}\ntry {\n  len(1);\n} catch (error) {\n  print className(error);\n}\ntry {\n  remove([], 0);\n} catch (error) {\n  print className(error);\n}")
+            .unwrap();
+        assert_eq!(
+            capture.stdout(),
+            format!("{}Error\nTypeError\nIndexError\n", expected),
+            "{:?}",
+            engine
+        );
+    }
+
+    let lox = Lox::new();
+    let code = lox.transpile(source, Target::JavaScript).unwrap();
+    if let Some(output) = run_node(&code) {
+        assert_eq!(output, expected);
+    }
+}
+
+#[test]
+fn stack_traces_list_the_calls_under_way() {
+    let source = "fun show(trace) {\n  for (var i = 0; i < len(trace); i = i + 1) {\n    print trace[i][\"function\"] + \" \" + string(trace[i][\"line\"]);\n  }\n}\nfun inner() {\n  return stackTrace();\n}\nfun outer() {\n  return inner();\n}\nshow(outer());\nclass Box {\n  open() {\n    return [][0];\n  }\n}\nfun fail() {\n  throw Error(\"bad\");\n}\ntry {\n  fail();\n} catch (error) {\n  show(error.trace);\n}\ntry {\n  Box().open();\n} catch (error) {\n  show(error.trace);\n}";
+    let expected = "inner 7\nouter 10\nscript 12\nfail 19\nscript 22\nopen 15\nscript 27\n";
+
+    for engine in bench::ENGINES {
+        let (mut lox, capture) = capturing(*engine);
+        lox.run(source).unwrap();
+        assert_eq!(capture.stdout(), expected, "{:?}", engine);
+    }
+}
+
+#[test]
+fn deferred_blocks_run_as_their_function_exits() {
+    let source = "fun work(step) {\n  var name = \"work\";\n  defer {\n    print \"close \" + name;\n  }\n  defer {\n    print \"flush\";\n  }\n  if (step == 0) return \"early\";\n  if (step == 1) return 1 + nil;\n  name = name + \"!\";\n  return \"done\";\n}\nfor (var step = 0; step < 3; step = step + 1) {\n  try {\n    print work(step);\n  } catch (error) {\n    print error.message;\n  }\n}\nfun failing() {\n  defer {\n    throw \"from defer\";\n  }\n  defer {\n    print \"still runs\";\n  }\n  return 1;\n}\ntry {\n  failing();\n} catch (error) {\n  print error;\n}\nclass File {\n  init(name) {\n    this.name = name;\n  }\n  use() {\n    defer {\n      print \"closed \" + this.name;\n    }\n    print \"using \" + this.name;\n  }\n}\nFile(\"a.txt\").use();";
+    let expected = "flush\nclose work\nearly\nflush\nclose work\nOperands must be two numbers or two strings.\nflush\nclose work!\ndone\nstill runs\nfrom defer\nusing a.txt\nclosed a.txt\n";
+
+    for engine in bench::ENGINES {
+        let (mut lox, capture) = capturing(*engine);
+        lox.run(source).unwrap();
+        assert_eq!(capture.stdout(), expected, "{:?}", engine);
+
+        let Err(LoxError::Compile(errors)) = lox.run("fun f() {\n  defer {\n    return;\n  }\n}")
+        else {
+            panic!("expected a compile error");
+        };
+        assert_eq!(errors[0].message, "Can't return from a deferred block.");
+    }
+
+    let lox = Lox::new();
+    let code = lox.transpile(source, Target::JavaScript).unwrap();
+    if let Some(output) = run_node(&code) {
+        assert_eq!(output, expected);
+    }
+}
+
+#[test]
+fn declarations_destructure_lists_maps_and_instances() {
+    let source = "var [a, b] = [1, 2];\nvar {x, y} = {\"x\": 3, \"y\": 4};\nprint a + b + x + y;\nclass Point {\n  init(x, y) {\n    this.x = x;\n    this.y = y;\n  }\n  norm() {\n    return this.x * this.x + this.y * this.y;\n  }\n}\nfun show(point) {\n  var {x, y, norm} = point;\n  var [first, second] = [\"(\", \")\"];\n  print first + string(x) + \", \" + string(y) + second;\n  return norm();\n}\nprint show(Point(3, 4));\ntry {\n  var [c, d] = [1];\n} catch (error) {\n  print className(error) + \": \" + error.message;\n}\ntry {\n  var {e} = [1];\n} catch (error) {\n  print className(error) + \": \" + error.message;\n}";
+    let expected = "10\n(3, 4)\n25\nIndexError: List index out of range.\nTypeError: Can only destructure instances and maps by name.\n";
+
+    for engine in bench::ENGINES {
+        let (mut lox, capture) = capturing(*engine);
+        lox.run(source).unwrap();
+        assert_eq!(capture.stdout(), expected, "{:?}", engine);
+
+        let Err(LoxError::Compile(errors)) = lox.run("var [a, b, a] = [1, 2, 3];") else {
+            panic!("expected a compile error");
+        };
+        assert_eq!(
+            errors[0].message,
+            "Duplicate name in destructuring pattern."
+        );
+    }
+
+    let lox = Lox::new();
+    let code = lox.transpile(source, Target::JavaScript).unwrap();
+    if let Some(output) = run_node(&code) {
+        assert_eq!(output, expected);
+    }
+}
+
+#[test]
+fn functions_return_several_values_as_a_list() {
+    let source = "fun divide(a, b) {\n  var quotient = 0;\n  while (a >= b) {\n    a = a - b;\n    quotient = quotient + 1;\n  }\n  return quotient, a;\n}\nvar quotient, remainder = divide(17, 5);\nprint quotient;\nprint remainder;\nprint divide(9, 4);\nfun bounds(list) {\n  var low, high = list[0], list[0];\n  for (var i = 1; i < len(list); i = i + 1) {\n    if (list[i] < low) low = list[i];\n    if (list[i] > high) high = list[i];\n  }\n  return low, high;\n}\nfun show() {\n  var low, high = bounds([3, 1, 4, 1, 5]);\n  print string(low) + \"..\" + string(high);\n}\nshow();";
+    let expected = "3\n2\n[2, 1]\n1..5\n";
+
+    for engine in bench::ENGINES {
+        let (mut lox, capture) = capturing(*engine);
+        lox.run(source).unwrap();
+        assert_eq!(capture.stdout(), expected, "{:?}", engine);
+    }
+
+    let lox = Lox::new();
+    let code = lox.transpile(source, Target::JavaScript).unwrap();
+    if let Some(output) = run_node(&code) {
+        assert_eq!(output, expected);
+    }
+}
+
+#[test]
+fn several_values_must_match_the_names_they_unpack_into() {
+    let cases = [
+        (
+            "fun pair() { return 1, 2; }\nvar a, b, c = pair();",
+            "Expected 3 values but got 2.",
+        ),
+        (
+            "fun f() {\n  var a, b = 1, 2, 3;\n  return a;\n}\nf();",
+            "Expected 2 values but got 3.",
+        ),
+        ("var a, b = 1;", "Can only destructure lists by position."),
+    ];
+
+    for engine in bench::ENGINES {
+        for (source, message) in cases {
+            let (mut lox, _) = capturing(*engine);
+            let Err(LoxError::Runtime(error)) = lox.run(source) else {
+                panic!("expected {} to fail on {:?}", source, engine);
+            };
+            assert_eq!(error.message, message, "{:?}", engine);
+        }
+    }
+}
+
+#[test]
+fn functions_take_default_and_rest_parameters() {
+    let source = "fun greet(name, greeting = \"hi\", mark = greeting + \"!\") {\n  print greeting + \", \" + name + mark;\n}\ngreet(\"ann\");\ngreet(\"bo\", \"yo\");\ngreet(\"cy\", \"hey\", \"?\");\nfun sum(first, ...rest) {\n  for (var i = 0; i < len(rest); i = i + 1) first = first + rest[i];\n  return first;\n}\nprint sum(1);\nprint sum(1, 2, 3);\nclass Point {\n  init(x = 0, y = x) {\n    this.x = x;\n    this.y = y;\n  }\n}\nprint Point(3).y;\nprint Point().x;";
+    let expected = "hi, annhi!\nyo, boyo!\nhey, cy?\n1\n6\n3\n0\n";
+
+    for engine in bench::ENGINES {
+        let (mut lox, capture) = capturing(*engine);
+        lox.run(source).unwrap();
+        assert_eq!(capture.stdout(), expected, "{:?}", engine);
+
+        for (call, message) in [
+            ("greet();", "Expected 1 to 3 arguments but got 0."),
+            ("sum();", "Expected at least 1 arguments but got 0."),
+        ] {
+            let Err(LoxError::Runtime(error)) = lox.run(call) else {
+                panic!("expected a runtime error from {}", call);
+            };
+            assert_eq!(error.message, message, "{:?}", engine);
+        }
+    }
+
+    let lox = Lox::new();
+    let code = lox.transpile(source, Target::JavaScript).unwrap();
+    if let Some(output) = run_node(&code) {
+        assert_eq!(output, expected);
+    }
+}
@@ -0,0 +1,160 @@
+mod common;
+
+use std::thread;
+use std::time::Duration;
+
+use lox::bench;
+use lox::check;
+use lox::dialect::Dialect;
+use lox::error::{LoxError, RuntimeErrorKind};
+use lox::interpreter::InterpreterOptions;
+use lox::syntax;
+
+use common::{capturing, capturing_with};
+
+#[test]
+fn deep_recursion_is_a_runtime_error() {
+    let source = "fun depth(n) {\n  if (n == 0) return 0;\n  return 1 + depth(n - 1);\n}";
+    for engine in bench::ENGINES {
+        let (mut lox, capture) = capturing(*engine);
+        lox.run(source).unwrap();
+        let Err(LoxError::Runtime(error)) = lox.run("depth(1000000);") else {
+            panic!("expected a runtime error on {:?}", engine);
+        };
+        assert_eq!(error.message, "Stack overflow.");
+        assert_eq!(error.kind, RuntimeErrorKind::ResourceExceeded);
+        lox.run("print depth(100);").unwrap();
+        assert_eq!(capture.stdout(), "100\n");
+
+        let options = InterpreterOptions {
+            max_call_depth: 10,
+            ..InterpreterOptions::default()
+        };
+        let (mut lox, capture) = capturing_with(*engine, Dialect::default(), options);
+        lox.run(source).unwrap();
+        lox.run("print depth(8);").unwrap();
+        assert_eq!(capture.stdout(), "8\n");
+        let Err(LoxError::Runtime(error)) = lox.run("depth(10);") else {
+            panic!("expected a runtime error on {:?}", engine);
+        };
+        assert_eq!(error.message, "Stack overflow.");
+    }
+}
+
+#[test]
+fn recursion_through_native_callbacks_is_a_catchable_runtime_error() {
+    let source = "fun f(n) {\n  fun g(x) {\n    return f(n - 1);\n  }\n  if (n == 0) return 0;\n  return map(g, [1])[0] + 1;\n}\ntry {\n  f(5000);\n} catch (error) {\n  print className(error) + \": \" + error.message;\n}\nprint f(40);";
+    for engine in bench::ENGINES {
+        let (mut lox, capture) = capturing(*engine);
+        lox.run(source).unwrap();
+        assert_eq!(
+            capture.stdout(),
+            "Error: Stack overflow.\n40\n",
+            "{:?}",
+            engine
+        );
+    }
+}
+
+#[test]
+fn long_chains_are_too_deeply_nested() {
+    let terms = vec!["1"; 200_000].join(" + ");
+    let calls = "()".repeat(100_000);
+    let fields = ".next".repeat(100_000);
+    let sources = [
+        format!("print {};", terms),
+        format!("fun f() {{ return f; }}\nf{};", calls),
+        format!("var list = nil;\nprint list{};", fields),
+    ];
+    for source in &sources {
+        for engine in bench::ENGINES {
+            let (mut lox, _) = capturing(*engine);
+            let Err(LoxError::Compile(errors)) = lox.run(source) else {
+                panic!("expected a compile error on {:?}", engine);
+            };
+            assert_eq!(errors[0].message, "Expression too deeply nested.");
+        }
+        let errors = check::check_source(source);
+        assert_eq!(errors[0].message, "Expression too deeply nested.");
+        let parse = syntax::parse(source);
+        assert_eq!(parse.errors()[0].message, "Expression too deeply nested.");
+    }
+
+    // A chain is flat however long it grows, so it only has to stay under
+    // its own limit, far above the nesting one.
+    let sum = format!("print {};", vec!["1"; 1000].join(" + "));
+    for engine in bench::ENGINES {
+        let (mut lox, capture) = capturing(*engine);
+        lox.run(&sum).unwrap();
+        assert_eq!(capture.stdout(), "1000\n");
+    }
+    assert!(check::check_source(&sum).is_empty());
+    assert!(syntax::parse(&sum).errors().is_empty());
+}
+
+#[test]
+fn step_and_heap_budgets_stop_runaway_scripts() {
+    for engine in bench::ENGINES {
+        let options = InterpreterOptions {
+            max_steps: Some(10_000),
+            max_heap_bytes: Some(1 << 20),
+            ..InterpreterOptions::default()
+        };
+        let (mut lox, capture) = capturing_with(*engine, Dialect::default(), options);
+        for (source, message) in [
+            ("while (true) {}", "Step limit exceeded."),
+            (
+                "var text = \"x\";\nwhile (true) text = text + text;",
+                "Heap limit exceeded.",
+            ),
+            (
+                "var items = [];\nfor (var i = 0; i < 5000; i = i + 1) push(items, [i] * 1000);",
+                "Heap limit exceeded.",
+            ),
+        ] {
+            let Err(LoxError::Runtime(error)) = lox.run(source) else {
+                panic!("expected {} to run out on {:?}", source, engine);
+            };
+            assert_eq!(error.message, message, "{} on {:?}", source, engine);
+            assert_eq!(error.kind, RuntimeErrorKind::ResourceExceeded);
+        }
+
+        // Each run gets a fresh step budget. The heap limit counts what is
+        // still alive, so garbage doesn't use it up but the globals do.
+        let source =
+            "var total = 0;\nfor (var i = 0; i < 100; i = i + 1) total = total + i;\nprint total;";
+        lox.run("text = nil;").unwrap();
+        lox.run(source).unwrap();
+        lox.run(source).unwrap();
+        assert_eq!(capture.stdout(), "4950\n4950\n");
+        lox.run("for (var i = 0; i < 300; i = i + 1) {\n  var garbage = [i] * 1000;\n  garbage = \"x\" * 1000;\n}")
+            .unwrap();
+    }
+}
+
+#[test]
+fn cancelling_interrupts_a_running_script() {
+    for engine in bench::ENGINES {
+        let (mut lox, capture) = capturing(*engine);
+        let cancel = lox.cancel_handle();
+        let canceller = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            cancel.cancel();
+        });
+        let Err(LoxError::Runtime(error)) = lox.run("while (true) {}") else {
+            panic!("expected an interruption on {:?}", engine);
+        };
+        canceller.join().unwrap();
+        assert_eq!(error.kind, RuntimeErrorKind::Interrupted);
+        assert_eq!(error.message, "Interrupted.");
+
+        // A cancel is used up by the run it stops.
+        lox.run("print \"again\";").unwrap();
+        assert_eq!(capture.stdout(), "again\n");
+        assert!(!lox.cancel_handle().is_cancelled());
+
+        lox.cancel_handle().cancel();
+        assert!(lox.run("print \"never\";").is_err());
+        assert_eq!(capture.stdout(), "again\n");
+    }
+}
@@ -0,0 +1,88 @@
+mod common;
+
+use lox::analysis;
+use lox::lint;
+
+use common::{run_walker, CASES};
+
+#[test]
+fn linter_reports_configured_rules() {
+    let source = "var count = 0;\nfun do_thing(a, _b) {\n  var unused = 1;\n  var count = a;\n  if (true) {}\n  for (var i = 0; i < 3; i = i + 1) {}\n  for (;;) { return count == nil; }\n}\nclass point {}\ndo_thing(1, 2);\n";
+    let findings = |config: &lint::Config| -> Vec<(&str, usize)> {
+        lint::lint(source, config)
+            .unwrap()
+            .iter()
+            .map(|warning| (warning.rule.code(), warning.position.line))
+            .collect()
+    };
+
+    assert_eq!(
+        findings(&lint::Config::default()),
+        vec![
+            ("L001", 1),
+            ("L006", 2),
+            ("L001", 3),
+            ("L002", 4),
+            ("L004", 5),
+            ("L003", 5),
+            ("L003", 6),
+            ("L006", 9),
+        ]
+    );
+
+    let config = lint::Config::parse(
+        "case = \"snake_case\"\nnil-comparison = true\nunused-variable = false\n",
+    )
+    .unwrap();
+    assert_eq!(
+        findings(&config),
+        vec![
+            ("L002", 4),
+            ("L004", 5),
+            ("L003", 5),
+            ("L003", 6),
+            ("L005", 7),
+            ("L006", 9),
+        ]
+    );
+    assert!(lint::Config::parse("shadowing = 1").is_err());
+    assert!(lint::lint("print x", &lint::Config::default()).is_err());
+}
+
+#[test]
+fn linter_fixes_keep_behavior() {
+    let config = lint::Config::default();
+    let source = "fun f(flag) {\n  var unused = 1;\n  var kept = clock();\n  var a = 1; print a;\n  print 1\n  print !flag == false;\n  print flag or a > 2 and false;\n  for (var i = 0; false; ) {}\n  var b = 2;\n  var c = b;\n}\nf(true);\n";
+    assert_eq!(
+        lint::fix(source, &config).unwrap(),
+        "fun f(flag) {\n  clock();\n  var a = 1; print a;\n  print 1;\n  print (!flag) == false;\n  print flag or (a > 2 and false);\n  for (; false; ) {}\n}\nf(true);\n"
+    );
+
+    let warnings = lint::lint("print !1 < 2 or 1 and 2;", &config).unwrap();
+    let fixes: Vec<&str> = warnings
+        .iter()
+        .filter(|warning| warning.rule == lint::Rule::Precedence)
+        .map(|warning| warning.fix.as_ref().unwrap().message.as_str())
+        .collect();
+    assert_eq!(fixes.len(), 2);
+
+    let analysis = analysis::Analysis::new("var a = 1\nprint a;");
+    assert_eq!(
+        analysis.fix_for(0).unwrap().edits[0].position.start,
+        "var a = 1".len()
+    );
+    assert!(analysis::Analysis::new("var a = ;").fix_for(0).is_none());
+    assert!(lint::fix("print x + ;", &config).is_err());
+
+    for (name, source, _) in CASES {
+        let Ok(fixed) = lint::fix(source, &config) else {
+            continue;
+        };
+        assert_eq!(
+            run_walker(&fixed, false),
+            run_walker(source, false),
+            "fixes changed the behavior of '{}'",
+            name
+        );
+    }
+}
@@ -0,0 +1,87 @@
+mod common;
+
+use lox::lsp;
+
+use common::SharedBuffer;
+
+fn lsp_frames(messages: &[String]) -> String {
+    messages
+        .iter()
+        .map(|message| format!("Content-Length: {}\r\n\r\n{}", message.len(), message))
+        .collect()
+}
+
+#[test]
+fn lsp_reports_diagnostics_hover_and_symbols() {
+    let uri = "file:///test.lox";
+    let text = "fun add(a, b) {\\n  return a + b;\\n}\\nclass Point {\\n  init(x) { this.x = x; }\\n}\\nprint add(1, 2);\\n";
+    let messages = [
+        r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{}}"#.to_string(),
+        format!(
+            r#"{{"jsonrpc":"2.0","method":"textDocument/didOpen","params":{{"textDocument":{{"uri":"{}","text":"{}"}}}}}}"#,
+            uri, text
+        ),
+        r#"{"jsonrpc":"2.0","method":"textDocument/didOpen","params":{"textDocument":{"uri":"file:///lint.lox","text":"{ var unused = 1; }"}}}"#.to_string(),
+        format!(
+            r#"{{"jsonrpc":"2.0","id":2,"method":"textDocument/hover","params":{{"textDocument":{{"uri":"{}"}},"position":{{"line":1,"character":9}}}}}}"#,
+            uri
+        ),
+        format!(
+            r#"{{"jsonrpc":"2.0","id":3,"method":"textDocument/documentSymbol","params":{{"textDocument":{{"uri":"{}"}}}}}}"#,
+            uri
+        ),
+        r#"{"jsonrpc":"2.0","id":5,"method":"workspace/symbol","params":{"query":"IN"}}"#
+            .to_string(),
+        format!(
+            r#"{{"jsonrpc":"2.0","id":6,"method":"textDocument/formatting","params":{{"textDocument":{{"uri":"{}"}},"options":{{"tabSize":4,"insertSpaces":true}}}}}}"#,
+            uri
+        ),
+        format!(
+            r#"{{"jsonrpc":"2.0","id":8,"method":"textDocument/completion","params":{{"textDocument":{{"uri":"{}"}},"position":{{"line":4,"character":17}}}}}}"#,
+            uri
+        ),
+        r#"{"jsonrpc":"2.0","id":7,"method":"textDocument/codeAction","params":{"textDocument":{"uri":"file:///lint.lox"},"range":{"start":{"line":0,"character":6},"end":{"line":0,"character":6}},"context":{"diagnostics":[]}}}"#.to_string(),
+        format!(
+            r#"{{"jsonrpc":"2.0","method":"textDocument/didChange","params":{{"textDocument":{{"uri":"{}"}},"contentChanges":[{{"text":"print this;\nvar = 1;\n"}}]}}}}"#,
+            uri
+        ),
+        format!(
+            r#"{{"jsonrpc":"2.0","method":"textDocument/didChange","params":{{"textDocument":{{"uri":"{}"}},"contentChanges":[{{"range":{{"start":{{"line":1,"character":4}},"end":{{"line":1,"character":4}}}},"text":"y "}},{{"range":{{"start":{{"line":0,"character":6}},"end":{{"line":0,"character":10}}}},"text":"y"}}]}}}}"#,
+            uri
+        ),
+        r#"{"jsonrpc":"2.0","id":4,"method":"shutdown"}"#.to_string(),
+        r#"{"jsonrpc":"2.0","method":"exit"}"#.to_string(),
+    ];
+
+    let output = SharedBuffer::default();
+    lsp::serve(std::io::Cursor::new(lsp_frames(&messages)), output.clone())
+        .expect("session failed");
+    let output = String::from_utf8(output.0.borrow().clone()).unwrap();
+
+    for expected in [
+        r#""diagnostics":[]"#,
+        r#""severity":2,"code":"L001","source":"lox","message":"Variable 'unused' is never used.""#,
+        r#""value":"```lox\nparameter a\n```\n\nParameter of `add`.""#,
+        r#"{"name":"add","detail":"fun add(a, b)","kind":12"#,
+        r#""children":[{"name":"init","detail":"Point.init(x)","kind":6"#,
+        r#"{"name":"init","kind":6,"location":{"uri":"file:///test.lox""#,
+        r#""containerName":"Point"}"#,
+        r#""newText":"fun add(a, b) {\n    return a + b;\n}\nclass Point {\n    init(x) {\n        this.x = x;"#,
+        r#""title":"Remove the unused variable 'unused'.","kind":"quickfix""#,
+        r#""changes":{"file:///lint.lox":[{"range":{"start":{"line":0,"character":2},"end":{"line":0,"character":18}},"newText":""}]}"#,
+        r#""id":8,"result":[{"label":"init","kind":2,"detail":"Point.init(x)"},{"label":"x","kind":5,"detail":"Point.x"}]"#,
+        r#""message":"Expect variable name.""#,
+        r#""id":4,"result":null"#,
+    ] {
+        assert!(
+            output.contains(expected),
+            "missing {} in {}",
+            expected,
+            output
+        );
+    }
+    assert!(!output.contains("outside of a class"));
+    // The last change edits ranges of the text rather than replacing it.
+    let last = &output[output.rfind("publishDiagnostics").unwrap()..];
+    assert!(last.contains(r#""diagnostics":[]"#), "{}", last);
+}
@@ -0,0 +1,162 @@
+use std::env;
+use std::fs;
+
+use lox::capability::Capabilities;
+use lox::error::{LoxError, RuntimeErrorKind};
+use lox::io::CaptureIo;
+use lox::value::Value;
+use lox::Lox;
+
+fn runtime_error(lox: &mut Lox, source: &str) -> String {
+    match lox.eval(source) {
+        Err(LoxError::Runtime(error)) => error.message,
+        other => panic!("expected a runtime error from {}, got {:?}", source, other),
+    }
+}
+
+#[test]
+fn console_natives_use_the_host_streams() {
+    let capture = CaptureIo::with_input(vec!["first".to_string(), "second".to_string()]);
+    let mut lox = Lox::builder().with_io(Box::new(capture.clone())).build();
+    lox.run("print readLine();\nprint readLine();\nprint readLine();")
+        .unwrap();
+    assert_eq!(capture.stdout(), "first\nsecond\nnil\n");
+}
+
+#[test]
+fn file_natives_round_trip_through_the_file_system() {
+    let dir = env::temp_dir().join(format!("lox-natives-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("notes.txt").to_str().unwrap().replace('\\', "/");
+
+    let mut lox = Lox::new();
+    lox.define_global("path", Value::String(path.as_str().into()));
+    lox.run("writeFile(path, \"one\n\");\nappendFile(path, \"two\n\");")
+        .unwrap();
+    assert_eq!(
+        lox.eval("readFile(path)").unwrap(),
+        Value::String("one\ntwo\n".into())
+    );
+    assert_eq!(fs::read_to_string(&path).unwrap(), "one\ntwo\n");
+    assert!(runtime_error(&mut lox, "readFile(path + \".missing\")")
+        .starts_with(&format!("Could not read file '{}.missing': ", path)));
+    fs::remove_dir_all(&dir).unwrap();
+
+    let capture = CaptureIo::new();
+    let mut lox = Lox::builder().with_io(Box::new(capture)).build();
+    lox.define_global("path", Value::String(path.as_str().into()));
+    assert_eq!(
+        runtime_error(&mut lox, "readFile(path)"),
+        format!("Could not read file '{}': I/O is disabled.", path)
+    );
+
+    let mut lox = Lox::builder().with_capabilities(Capabilities::NONE).build();
+    assert_eq!(
+        runtime_error(&mut lox, "writeFile(\"x\", \"y\")"),
+        "'writeFile' requires the 'fs' capability."
+    );
+}
+
+#[test]
+fn json_natives_decode_and_encode_values() {
+    let mut lox = Lox::new();
+    lox.define_global(
+        "text",
+        Value::String(r#"{"name": "lo\"xé", "tags": [1, 2.5e1, true, null], "nested": {}}"#.into()),
+    );
+    lox.run("var data = jsonParse(text);").unwrap();
+    assert_eq!(
+        lox.eval("data[\"name\"]").unwrap(),
+        Value::String("lo\"xé".into())
+    );
+    assert_eq!(lox.eval("data[\"tags\"][1]").unwrap(), Value::Number(25.0));
+    assert_eq!(
+        lox.eval("jsonStringify(data)").unwrap(),
+        Value::String(r#"{"name":"lo\"xé","nested":{},"tags":[1,25,true,null]}"#.into())
+    );
+    assert_eq!(
+        lox.eval("jsonParse(jsonStringify(data))[\"tags\"]")
+            .unwrap()
+            .to_string(),
+        "[1, 25, true, nil]"
+    );
+
+    assert!(runtime_error(&mut lox, "jsonParse(\"[1,\")").starts_with("Invalid JSON: "));
+    assert_eq!(
+        runtime_error(&mut lox, "jsonStringify([clock])"),
+        "Can't encode a function as JSON."
+    );
+    assert_eq!(
+        runtime_error(&mut lox, "jsonStringify(1 / 0)"),
+        "Can't encode a non-finite number as JSON."
+    );
+    lox.run("var cycle = [];\npush(cycle, cycle);").unwrap();
+    assert_eq!(
+        runtime_error(&mut lox, "jsonStringify(cycle)"),
+        "Value is nested too deeply to encode as JSON."
+    );
+}
+
+#[test]
+fn process_natives_need_the_process_capability() {
+    let mut lox = Lox::builder()
+        .with_capabilities(Capabilities::default() | Capabilities::PROCESS)
+        .build();
+    assert_eq!(
+        lox.eval("getenv(\"CARGO_MANIFEST_DIR\")").unwrap(),
+        Value::String(env!("CARGO_MANIFEST_DIR").into())
+    );
+    assert_eq!(
+        lox.eval("getenv(\"LOX_SURELY_UNSET_VARIABLE\")").unwrap(),
+        Value::Nil
+    );
+    #[cfg(unix)]
+    assert_eq!(
+        lox.eval("exec(\"echo hi\")").unwrap(),
+        Value::String("hi\n".into())
+    );
+
+    let Err(LoxError::Runtime(error)) = lox.run("exit(3);\nprint \"unreachable\";") else {
+        panic!("expected exit to stop the script");
+    };
+    assert_eq!(error.kind, RuntimeErrorKind::Exit(3));
+
+    let mut lox = Lox::new();
+    assert_eq!(
+        runtime_error(&mut lox, "getenv(\"HOME\")"),
+        "'getenv' requires the 'process' capability."
+    );
+    assert_eq!(
+        runtime_error(&mut lox, "exec(\"echo hi\")"),
+        "'exec' requires the 'process' capability."
+    );
+}
+
+#[test]
+fn seeded_random_numbers_repeat() {
+    let draws = "[random(), random(), randomInt(1, 6), randomInt(-3, 3)]";
+    let mut first = Lox::builder().with_seed(42).build();
+    let mut second = Lox::builder().with_seed(42).build();
+    let sequence = first.eval(draws).unwrap().to_string();
+    assert_eq!(second.eval(draws).unwrap().to_string(), sequence);
+
+    first.run("seedRandom(42);").unwrap();
+    assert_eq!(first.eval(draws).unwrap().to_string(), sequence);
+    first.run("seedRandom(7);").unwrap();
+    assert_ne!(first.eval(draws).unwrap().to_string(), sequence);
+
+    first
+        .run(
+            "for (var i = 0; i < 1000; i = i + 1) {\n  var n = random();\n  assert(n >= 0 and n < 1, n);\n  var roll = randomInt(1, 6);\n  assert(roll >= 1 and roll <= 6 and floor(roll) == roll, roll);\n}",
+        )
+        .unwrap();
+    assert_eq!(first.eval("randomInt(5, 5)").unwrap(), Value::Number(5.0));
+    assert_eq!(
+        runtime_error(&mut first, "randomInt(6, 1)"),
+        "Lower bound of 'randomInt' must not exceed the upper bound."
+    );
+    assert_eq!(
+        runtime_error(&mut first, "seedRandom(1.5)"),
+        "Argument to 'seedRandom' must be an integer."
+    );
+}
@@ -0,0 +1,158 @@
+use lox::formatter::{self, Style};
+use lox::scanner::Scanner;
+use lox::token::{Keyword, Kind, Token};
+use proptest::prelude::*;
+
+const PUNCTUATION: &[&str] = &[
+    "(", ")", "{", "}", "[", "]", ",", ":", ".", "-", "+", ";", "/", "*", "!", "!=", "=", "==",
+    ">", ">=", "<", "<=",
+];
+
+fn identifier() -> impl Strategy<Value = String> {
+    "[a-zA-Z_][a-zA-Z0-9_]{0,6}".prop_filter("keyword", |name| name.parse::<Keyword>().is_err())
+}
+
+// Written the way they print, so a lexeme is exactly its source text.
+fn number() -> impl Strategy<Value = String> {
+    "(0|[1-9][0-9]{0,3})(\\.[0-9]{0,2}[1-9])?"
+}
+
+fn string() -> impl Strategy<Value = String> {
+    "\"[a-z é\\n]{0,6}\""
+}
+
+fn comment() -> impl Strategy<Value = String> {
+    "//[a-z /é]{0,8}[a-z]"
+}
+
+// Any tokens at all, comments included, with whitespace between them so that
+// no two run together.
+fn token_soup() -> impl Strategy<Value = String> {
+    let keyword = prop::sample::select(vec![
+        "and", "class", "else", "false", "for", "fun", "if", "nil", "or", "print", "return",
+        "super", "this", "true", "var", "while",
+    ])
+    .prop_map(str::to_string);
+    let token = prop_oneof![
+        prop::sample::select(PUNCTUATION).prop_map(str::to_string),
+        keyword,
+        identifier(),
+        number(),
+        string(),
+        comment().prop_map(|comment| comment + "\n"),
+    ];
+    let separator = prop::sample::select(vec![" ", "\n", "\t", "  \n\t", "\r\n"]);
+    prop::collection::vec((token, separator), 0..24).prop_map(|pieces| {
+        pieces
+            .into_iter()
+            .map(|(token, separator)| token + separator)
+            .collect()
+    })
+}
+
+fn expression() -> impl Strategy<Value = String> {
+    let leaf = prop_oneof![
+        identifier(),
+        number(),
+        string(),
+        prop::sample::select(vec!["true", "false", "nil"]).prop_map(str::to_string),
+    ];
+    leaf.prop_recursive(4, 24, 3, |inner| {
+        let operator = prop::sample::select(vec![
+            "+", "-", "*", "/", "==", "!=", "<", "<=", ">", ">=", "and", "or",
+        ]);
+        prop_oneof![
+            (inner.clone(), operator, inner.clone())
+                .prop_map(|(left, operator, right)| format!("{} {} {}", left, operator, right)),
+            inner.clone().prop_map(|operand| format!("-{}", operand)),
+            inner.clone().prop_map(|operand| format!("!({})", operand)),
+            inner.clone().prop_map(|inner| format!("({})", inner)),
+            (identifier(), prop::collection::vec(inner.clone(), 0..3))
+                .prop_map(|(callee, arguments)| format!("{}({})", callee, arguments.join(", "))),
+            (inner, identifier()).prop_map(|(object, name)| format!("({}).{}", object, name)),
+        ]
+    })
+}
+
+// Programs that parse, with comments on lines of their own.
+fn program() -> impl Strategy<Value = String> {
+    let simple = prop_oneof![
+        expression().prop_map(|value| format!("print {};", value)),
+        (identifier(), expression()).prop_map(|(name, value)| format!("var {} = {};", name, value)),
+        expression().prop_map(|value| format!("{};", value)),
+        comment(),
+    ];
+    let statement = simple.prop_recursive(3, 16, 4, |inner| {
+        let block = prop::collection::vec(inner.clone(), 0..4)
+            .prop_map(|statements| format!("{{\n{}\n}}", statements.join("\n")));
+        prop_oneof![
+            block.clone(),
+            (expression(), block.clone(), block.clone()).prop_map(
+                |(condition, then, otherwise)| format!(
+                    "if ({}) {} else {}",
+                    condition, then, otherwise
+                )
+            ),
+            (expression(), block.clone())
+                .prop_map(|(condition, body)| format!("while ({}) {}", condition, body)),
+            (
+                identifier(),
+                prop::collection::vec(identifier(), 0..3),
+                block
+            )
+                .prop_map(|(name, parameters, body)| format!(
+                    "fun {}({}) {}",
+                    name,
+                    parameters.join(", "),
+                    body
+                )),
+        ]
+    });
+    prop::collection::vec(statement, 0..6).prop_map(|statements| statements.join("\n") + "\n")
+}
+
+fn trivia_tokens(source: &str) -> Vec<Token> {
+    let mut scanner = Scanner::new(source.to_string());
+    scanner.set_trivia(true);
+    scanner.scan_tokens().unwrap()
+}
+
+proptest! {
+    // Every token's lexeme is exactly the source it spans, on the line it
+    // ends on as with a string across lines, and nothing but whitespace lies between tokens, so the
+    // lexemes and the gaps between them give back the source.
+    #[test]
+    fn token_lexemes_rebuild_the_source(source in token_soup()) {
+        let characters: Vec<char> = source.chars().collect();
+        let mut rebuilt = String::new();
+        let mut last = 0;
+        for token in trivia_tokens(&source) {
+            let position = token.position;
+            let gap: String = characters[last..position.start].iter().collect();
+            prop_assert!(gap.chars().all(char::is_whitespace), "{:?} before {:?}", gap, token);
+            rebuilt.push_str(&gap);
+
+            let span: String = characters[position.start..position.current].iter().collect();
+            if token.kind != Kind::EndOfFile {
+                prop_assert_eq!(token.lexeme(), span);
+                rebuilt.push_str(&token.lexeme());
+            }
+
+            let line = 1 + characters[..position.current].iter().filter(|&&character| character == '\n').count();
+            prop_assert_eq!(position.line, line, "{:?}", token);
+            last = position.current;
+        }
+        rebuilt.extend(&characters[last..]);
+        prop_assert_eq!(rebuilt, source);
+    }
+
+    // The formatter moves tokens around but never changes them.
+    #[test]
+    fn formatting_keeps_the_tokens(source in program()) {
+        let formatted = formatter::format(&source, &Style::default()).unwrap();
+        let kinds = |source: &str| -> Vec<Kind> {
+            trivia_tokens(source).into_iter().map(|token| token.kind).collect()
+        };
+        prop_assert_eq!(kinds(&formatted), kinds(&source), "{}", formatted);
+    }
+}
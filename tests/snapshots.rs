@@ -0,0 +1,70 @@
+mod common;
+
+use lox::disassembler;
+use lox::syntax;
+use lox::Engine;
+
+use common::{compile, run_engine};
+
+// User-facing output is pinned in tests/snapshots; after an intended change,
+// review and accept the new output with `cargo insta review`.
+const SNAPSHOT_PROGRAM: &str = "// Points on a plane.
+class Point {
+  init(x, y) {
+    this.x = x;
+    this.y = y;
+  }
+
+  sum() { return this.x + this.y; }
+}
+
+fun counter() {
+  var count = 0;
+  fun increment() {
+    count = count + 1;
+    return count;
+  }
+  return increment;
+}
+
+var point = Point(1, 2);
+print point.sum() * -3;
+if (point.x < 2 and !nil) print \"near\"; else print \"far\";
+";
+
+#[test]
+fn syntax_tree_snapshot() {
+    insta::assert_snapshot!(
+        "syntax_tree",
+        syntax::parse(SNAPSHOT_PROGRAM).syntax().dump()
+    );
+
+    let broken = syntax::parse("var = 1;\nprint (2 +;\n");
+    let errors: Vec<String> = broken.errors().iter().map(ToString::to_string).collect();
+    insta::assert_snapshot!(
+        "syntax_tree_with_errors",
+        format!("{}\n{}", broken.syntax().dump(), errors.join("\n"))
+    );
+}
+
+#[test]
+fn diagnostics_snapshot() {
+    let diagnostics: Vec<String> = [
+        "var = 1;\nprint (2 +;\nfun f(a, b { }\n",
+        "fun f() {\n  print 1\n}\nprint f(;\n",
+        "var list = [1, 2];\nfun f(n) { return list[n]; }\nprint f(5);\n",
+        "print \"a\" + nil;\n",
+    ]
+    .iter()
+    .map(|source| run_engine(source, Engine::default()).1.unwrap())
+    .collect();
+    insta::assert_snapshot!("diagnostics", diagnostics.join("\n\n"));
+}
+
+#[test]
+fn disassembly_snapshot() {
+    insta::assert_snapshot!(
+        "disassembly",
+        disassembler::disassemble(&compile(SNAPSHOT_PROGRAM))
+    );
+}
@@ -0,0 +1,102 @@
+mod common;
+
+use std::env;
+use std::path::Path;
+
+use lox::bench;
+use lox::suite;
+use lox::Engine;
+
+use common::CASES;
+
+// What a file under tests/lox expects, written in its comments as Crafting
+// Interpreters does: `// expect: ` for each line of output, `// expect
+// runtime error: ` on the line that fails, and `// Error: ` on a line with a
+// compile error, or `// [line N] Error: ` for one reported on another line.
+#[test]
+fn lox_files_meet_their_expectations() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/lox");
+    let mut failures = Vec::new();
+    for &engine in bench::ENGINES {
+        let summary = suite::run(&root, engine).unwrap();
+        assert!(summary.passed > 0, "no .lox files under {}", root.display());
+        for (path, diff) in summary.failures {
+            failures.push(format!(
+                "{} on {}:\n{}",
+                path.display(),
+                engine.name(),
+                diff
+            ));
+        }
+    }
+    assert!(failures.is_empty(), "\n{}", failures.join("\n\n"));
+}
+
+#[test]
+fn suite_reads_reference_expectations() {
+    let source = "print a; // expect: 1\nprint \"//\"; // expect: //\nvar = 1; // Error at '=': Expect variable name.\n// [java line 4] Error at end: Expect ';'.\n// [c line 4] Error at end: Expect expression.\n";
+    assert_eq!(
+        suite::Expectations::parse(source),
+        suite::Expectations {
+            output: vec!["1".to_string(), "//".to_string()],
+            error: Some(
+                "[line 3] Error at '=': Expect variable name.\n[line 4] Error at end: Expect ';'."
+                    .to_string()
+            ),
+        }
+    );
+    assert_eq!(
+        suite::Expectations::parse(
+            "print 1;\nnil(); // expect runtime error: Can only call functions and classes."
+        )
+        .transcript(),
+        ["! Can only call functions and classes.", "! [line 2]"]
+    );
+
+    assert_eq!(
+        suite::tag(Path::new("scanning/numbers.lox")),
+        Some("scanning")
+    );
+    assert_eq!(suite::tag(Path::new("empty_file.lox")), None);
+    assert!(suite::check("print 1 + 2; // expect: 3", Engine::Vm).is_ok());
+    assert_eq!(
+        suite::check("print 1; // expect: 2", Engine::Walker),
+        Err("- 2\n+ 1".to_string())
+    );
+}
+
+#[test]
+fn backends_agree_on_the_corpus() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/lox");
+    let summary = suite::differential(&root).unwrap();
+    assert!(summary.failures.is_empty(), "{}", summary.report());
+
+    assert_eq!(
+        suite::outcome("print 1;\nexit(3);", Engine::Vm),
+        suite::Outcome {
+            stdout: "1\n".to_string(),
+            error: None,
+            exit_code: 3,
+        }
+    );
+    assert_eq!(
+        suite::outcome("print -\"a\";", Engine::Walker).error,
+        Some(suite::ErrorClass::Runtime)
+    );
+    for (_, source, _) in CASES {
+        assert_eq!(suite::compare(source), Ok(()));
+    }
+}
+
+// The craftinginterpreters suite isn't vendored; point LOX_TEST_SUITE at a
+// checkout's test directory to run it.
+#[test]
+fn reference_suite_passes() {
+    let Some(root) = env::var_os("LOX_TEST_SUITE") else {
+        return;
+    };
+    for &engine in bench::ENGINES {
+        let summary = suite::run(Path::new(&root), engine).unwrap();
+        assert!(summary.failures.is_empty(), "{}", summary.report());
+    }
+}
@@ -0,0 +1,33 @@
+use std::rc::Rc;
+
+use lox::symbol::{Interner, Symbol, SymbolTable};
+
+#[test]
+fn symbol_tables_find_names_by_text_or_symbol() {
+    let mut table = SymbolTable::new();
+    for index in 0..100 {
+        assert_eq!(
+            table.insert(Symbol::from(format!("name{}", index)), index),
+            None
+        );
+    }
+    assert_eq!(table.len(), 100);
+    assert_eq!(table.insert(Symbol::new("name7"), 700), Some(7));
+    assert_eq!(table.len(), 100);
+    assert_eq!(table.get("name7"), Some(&700));
+    assert_eq!(table.get_symbol(&Symbol::new("name42")), Some(&42));
+    assert_eq!(table.get("name100"), None);
+    *table.get_mut("name0").unwrap() = -1;
+    assert_eq!(table.get("name0"), Some(&-1));
+    let mut keys: Vec<String> = table.keys().map(ToString::to_string).collect();
+    keys.sort();
+    keys.dedup();
+    assert_eq!(keys.len(), 100);
+
+    let mut interner = Interner::new();
+    let first = interner.intern("field");
+    let second = interner.intern("field");
+    assert!(Rc::ptr_eq(first.text(), second.text()));
+    assert_eq!(first, Symbol::new("field"));
+    assert_ne!(first, interner.intern("other"));
+}
@@ -0,0 +1,288 @@
+mod common;
+
+use std::rc::Rc;
+
+use lox::analysis;
+use lox::bench;
+use lox::expression::{Expr, ExprId, ExpressionKind};
+use lox::lint;
+use lox::parser::Parser;
+use lox::scanner::Scanner;
+use lox::statement::Statement;
+use lox::syntax::{self, GreenNode, GreenToken, NodeKind, TokenKind};
+use lox::token::Position;
+
+use common::CASES;
+
+#[test]
+fn syntax_tree_keeps_every_character() {
+    let broken = [
+        "var s = \"unterminated",
+        "fun (a, { print ; } @ # class",
+        "print 1 +;\n}\n  // trailing",
+        "class A < { f( } x = ;",
+        "a + b = c; for (var i = 0; i < 1",
+        "  // nothing but a comment",
+        "\n\t x = 1;",
+    ];
+    let sources = CASES
+        .iter()
+        .map(|(_, source, _)| *source)
+        .chain(bench::WORKLOADS.iter().map(|workload| workload.source))
+        .chain(broken);
+    for source in sources {
+        let parse = syntax::parse(source);
+        assert_eq!(parse.syntax().text(), source);
+
+        let parses = Scanner::new(source.to_string())
+            .scan_tokens()
+            .is_ok_and(|tokens| Parser::new(tokens).parse().is_ok());
+        assert_eq!(
+            parse.errors().is_empty(),
+            parses,
+            "{:?} for {}",
+            parse.errors(),
+            source
+        );
+    }
+
+    let source = "var x = -a * (b + 1); // note\nfor (;;) x.y[0] = f(1, 2);\n";
+    let root = syntax::parse(source).syntax();
+    let dump = root.dump();
+    let lines: Vec<&str> = dump.lines().take(32).collect();
+    assert_eq!(
+        lines,
+        [
+            "Program@0..57",
+            "  VarDeclaration@0..21",
+            "    Code(Keyword(Var))@0..3 \"var\"",
+            "    Whitespace@3..4 \" \"",
+            "    Code(Identifier(\"x\"))@4..5 \"x\"",
+            "    Whitespace@5..6 \" \"",
+            "    Code(Equal)@6..7 \"=\"",
+            "    Whitespace@7..8 \" \"",
+            "    Binary@8..20",
+            "      Unary@8..10",
+            "        Code(Minus)@8..9 \"-\"",
+            "        Variable@9..10",
+            "          Code(Identifier(\"a\"))@9..10 \"a\"",
+            "      Whitespace@10..11 \" \"",
+            "      Code(Asterisk)@11..12 \"*\"",
+            "      Whitespace@12..13 \" \"",
+            "      Grouping@13..20",
+            "        Code(OpenParenthesis)@13..14 \"(\"",
+            "        Binary@14..19",
+            "          Variable@14..15",
+            "            Code(Identifier(\"b\"))@14..15 \"b\"",
+            "          Whitespace@15..16 \" \"",
+            "          Code(Plus)@16..17 \"+\"",
+            "          Whitespace@17..18 \" \"",
+            "          Literal@18..19",
+            "            Code(Number(1.0))@18..19 \"1\"",
+            "        Code(CloseParenthesis)@19..20 \")\"",
+            "    Code(Semicolon)@20..21 \";\"",
+            "  Whitespace@21..22 \" \"",
+            "  Comment@22..29 \"// note\"",
+            "  Whitespace@29..30 \"\\n\"",
+            "  ForStatement@30..56",
+        ]
+    );
+    let kinds: Vec<NodeKind> = root.descendants().iter().map(|node| node.kind()).collect();
+    assert_eq!(
+        kinds[kinds
+            .iter()
+            .position(|kind| *kind == NodeKind::ForStatement)
+            .unwrap()..],
+        [
+            NodeKind::ForStatement,
+            NodeKind::ExpressionStatement,
+            NodeKind::Assign,
+            NodeKind::Index,
+            NodeKind::Get,
+            NodeKind::Variable,
+            NodeKind::Literal,
+            NodeKind::Call,
+            NodeKind::Variable,
+            NodeKind::ArgumentList,
+            NodeKind::Literal,
+            NodeKind::Literal,
+        ]
+    );
+
+    let name = root.token_at(4).unwrap();
+    assert_eq!(name.text(), "x");
+    assert_eq!(name.parent().kind(), NodeKind::VarDeclaration);
+    assert_eq!(root.token_at(25).unwrap().kind(), &TokenKind::Comment);
+
+    let renamed = syntax::SyntaxNode::new_root(
+        name.replace_with(GreenToken::new(name.kind().clone(), "total".to_string())),
+    );
+    assert_eq!(renamed.text(), source.replacen("var x", "var total", 1));
+    let old_for = &root.green().children().last();
+    let new_for = &renamed.green().children().last();
+    assert_eq!(old_for, new_for);
+
+    let statement = root.children()[1].clone();
+    let emptied = statement.replace_with(std::rc::Rc::new(GreenNode::new(
+        NodeKind::Block,
+        Vec::new(),
+    )));
+    assert_eq!(
+        syntax::SyntaxNode::new_root(emptied).text(),
+        "var x = -a * (b + 1); // note\n\n"
+    );
+}
+
+#[test]
+fn incremental_edits_match_a_fresh_parse() {
+    let messages = |errors: &[lox::error::Error]| -> Vec<(String, usize)> {
+        errors
+            .iter()
+            .map(|error| (error.message.clone(), error.line))
+            .collect()
+    };
+    let sources = [
+        "var x = 1;\nfun f(a) {\n  // note\n  if (a) { print a + 1.5; }\n  return x;\n}\nprint f(2);\n",
+        "class A < B { m() { this.y = \"s\"; } }\n{ var i = 0; while (i < 2) i = i + 1; }\n",
+        "print 1 +;\n{ var = 2; }\nfun g( { }\n",
+    ];
+    let insertions = ["a", " ", "\n", "{", "}", ".", "5", "/", "\""];
+    for source in sources {
+        let length = source.chars().count();
+        let parse = syntax::parse(source);
+        let analysis = analysis::Analysis::new(source);
+        let edits = (0..=length)
+            .flat_map(|start| insertions.iter().map(move |text| (start, start, *text)))
+            .chain((0..length).map(|start| (start, start + 1, "")))
+            .chain((0..length.saturating_sub(3)).map(|start| (start, start + 3, "q")));
+        for (start, current, text) in edits {
+            let edit = analysis::TextEdit {
+                position: Position {
+                    start,
+                    current,
+                    line: 1,
+                },
+                text: text.to_string(),
+            };
+            let edited: String = source
+                .chars()
+                .take(start)
+                .chain(text.chars())
+                .chain(source.chars().skip(current))
+                .collect();
+
+            let expected = syntax::parse(&edited);
+            let reparsed = parse.reparse(&edit);
+            assert_eq!(
+                reparsed.syntax().dump(),
+                expected.syntax().dump(),
+                "{:?}",
+                edited
+            );
+            assert_eq!(
+                messages(reparsed.errors()),
+                messages(expected.errors()),
+                "{:?}",
+                edited
+            );
+
+            let expected = analysis::Analysis::new(&edited);
+            let relexed = analysis.edit(&edit);
+            assert_eq!(
+                messages(relexed.diagnostics()),
+                messages(expected.diagnostics()),
+                "{:?}",
+                edited
+            );
+            assert_eq!(relexed.semantic_tokens(), expected.semantic_tokens());
+        }
+    }
+
+    // Only the edited part of the tree is built again.
+    let source = sources[0];
+    let parse = syntax::parse(source);
+    for (start, current, text) in [(38, 39, "b"), (27, 31, "remark"), (43, 43, "print 3; ")] {
+        let edit = analysis::TextEdit {
+            position: Position {
+                start,
+                current,
+                line: 1,
+            },
+            text: text.to_string(),
+        };
+        let reparsed = parse.reparse(&edit);
+        let (old, new) = (parse.green().children(), reparsed.green().children());
+        assert!(matches!(
+            (&old[0], &new[0]),
+            (syntax::GreenElement::Node(old), syntax::GreenElement::Node(new)) if Rc::ptr_eq(old, new)
+        ));
+        assert!(!Rc::ptr_eq(parse.green(), reparsed.green()));
+    }
+}
+
+#[test]
+fn expression_ids_are_never_reused() {
+    fn ids(expression: &Expr, into: &mut Vec<ExprId>) {
+        into.push(expression.id);
+        assert_eq!(
+            expression.ast().nodes()[expression.id.index()].id,
+            expression.id
+        );
+        match &expression.kind {
+            ExpressionKind::Binary { left, right, .. } => {
+                ids(&expression.get(*left), into);
+                ids(&expression.get(*right), into);
+            }
+            ExpressionKind::Grouping(inner) => ids(&expression.get(*inner), into),
+            _ => {}
+        }
+    }
+
+    let mut seen = Vec::new();
+    for _ in 0..2 {
+        let tokens = Scanner::new("(a + b) * c - 1".to_string())
+            .scan_tokens()
+            .unwrap();
+        let expression = Parser::new(tokens).parse_expression().unwrap();
+        ids(&expression, &mut seen);
+    }
+    assert_eq!(seen.len(), 16);
+    seen.sort();
+    seen.dedup();
+    assert_eq!(seen.len(), 16);
+
+    let source = "var x = 1;\nprint !1 < 2 or x == nil and 2;\nwhile (false) {}";
+    let mut config = lint::Config::default();
+    config.rules.insert(lint::Rule::NilComparison);
+    let warnings = lint::lint(source, &config).unwrap();
+    let mut warned = Vec::new();
+    for warning in &warnings {
+        if warning.rule == lint::Rule::EmptyBlock {
+            assert_eq!(warning.expression, None);
+        } else {
+            warned.push(warning.expression.unwrap());
+        }
+    }
+    assert_eq!(warned.len(), 4);
+    warned.sort();
+    warned.dedup();
+    assert_eq!(warned.len(), 4);
+}
+
+#[test]
+fn deep_trees_drop_without_recursing() {
+    let terms = vec!["1"; 200_000].join(" + ");
+    let calls = "()".repeat(100_000);
+    for source in [format!("print {};", terms), format!("f{};", calls)] {
+        let tokens = Scanner::new(source).scan_tokens().unwrap();
+        let mut parser = Parser::new(tokens);
+        parser.set_max_nesting_depth(usize::MAX);
+        drop(parser.parse().unwrap());
+    }
+
+    let mut statement = Rc::new(Statement::Block(Vec::new()));
+    for _ in 0..1_000_000 {
+        statement = Rc::new(Statement::Block(vec![statement]));
+    }
+    drop(statement);
+}
@@ -0,0 +1,30 @@
+mod common;
+
+use lox::transpiler::Target;
+use lox::Lox;
+
+use common::{run_node, run_walker, CASES};
+
+#[test]
+fn transpiled_javascript_agrees() {
+    let lox = Lox::new();
+    for (name, source, _) in CASES {
+        let (stdout, error) = run_walker(source, false);
+        if error.is_some() {
+            continue;
+        }
+
+        let code = lox.transpile(source, Target::JavaScript).unwrap();
+        let Some(output) = run_node(&code) else {
+            return;
+        };
+        assert_eq!(
+            output, stdout,
+            "javascript and walker disagree on '{}'",
+            name
+        );
+    }
+
+    let errors = lox.transpile("return 1;", Target::JavaScript).unwrap_err();
+    assert_eq!(errors[0].message, "Can't return from top-level code.");
+}